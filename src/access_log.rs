@@ -0,0 +1,176 @@
+use log::{error, info};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Format of the access log lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessLogFormat {
+    /// Combined Log Format with the session duration appended, as written by Icecast
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+impl AccessLogFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "combined" | "clf" => Ok(Self::Combined),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unsupported access log format '{}'. Supported formats: combined, json",
+                format
+            )),
+        }
+    }
+}
+
+/// A finished listener session
+#[derive(Debug)]
+pub struct AccessLogEntry {
+    pub remote_addr: String,
+    pub connected_at: chrono::DateTime<chrono::Local>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub bytes: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub duration_seconds: u64,
+}
+
+/// Appends one line per listener session to the configured access log file
+#[derive(Clone)]
+pub struct AccessLog {
+    file: Arc<Mutex<File>>,
+    format: AccessLogFormat,
+}
+
+impl AccessLog {
+    pub fn open(
+        path: &Path,
+        format: AccessLogFormat,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        info!("Writing access log to {:?} ({:?})", path, format);
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            format,
+        })
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let line = match self.format {
+            AccessLogFormat::Combined => Self::format_combined(entry),
+            AccessLogFormat::Json => Self::format_json(entry),
+        };
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            error!("Failed to write access log: {}", e);
+        }
+    }
+
+    fn format_combined(entry: &AccessLogEntry) -> String {
+        format!(
+            "{} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\" {}",
+            entry.remote_addr,
+            entry.connected_at.format("%d/%b/%Y:%H:%M:%S %z"),
+            entry.method,
+            entry.path,
+            entry.status,
+            entry.bytes,
+            Self::quoted(&entry.referer),
+            Self::quoted(&entry.user_agent),
+            entry.duration_seconds
+        )
+    }
+
+    fn format_json(entry: &AccessLogEntry) -> String {
+        serde_json::json!({
+            "remote_addr": entry.remote_addr,
+            "timestamp": entry.connected_at.to_rfc3339(),
+            "method": entry.method,
+            "path": entry.path,
+            "status": entry.status,
+            "bytes": entry.bytes,
+            "referer": entry.referer,
+            "user_agent": entry.user_agent,
+            "duration_seconds": entry.duration_seconds,
+        })
+        .to_string()
+    }
+
+    /// CLF uses "-" for missing values, quotes within values are escaped
+    fn quoted(value: &Option<String>) -> String {
+        match value {
+            Some(value) if !value.is_empty() => value.replace('"', "\\\""),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn create_entry() -> AccessLogEntry {
+        AccessLogEntry {
+            remote_addr: "192.168.1.10".to_string(),
+            connected_at: chrono::Local
+                .with_ymd_and_hms(2025, 1, 15, 20, 14, 3)
+                .unwrap(),
+            method: "GET".to_string(),
+            path: "/high".to_string(),
+            status: 200,
+            bytes: 30160000,
+            referer: None,
+            user_agent: Some("VLC/3.0.20 \"LibVLC\"".to_string()),
+            duration_seconds: 754,
+        }
+    }
+
+    #[test]
+    fn given_finished_session_when_formatted_as_combined_then_matches_icecast_layout() {
+        let line = AccessLog::format_combined(&create_entry());
+
+        assert!(line.starts_with("192.168.1.10 - - [15/Jan/2025:20:14:03 "));
+        assert!(line.ends_with(
+            "] \"GET /high HTTP/1.1\" 200 30160000 \"-\" \"VLC/3.0.20 \\\"LibVLC\\\"\" 754"
+        ));
+    }
+
+    #[test]
+    fn given_json_format_when_written_then_appends_one_json_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("logs/access.log");
+        let access_log = AccessLog::open(&path, AccessLogFormat::Json).unwrap();
+
+        access_log.write(&create_entry());
+        access_log.write(&create_entry());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["bytes"], 30160000);
+        assert_eq!(json["path"], "/high");
+    }
+
+    #[test]
+    fn given_unknown_format_when_parsed_then_returns_error() {
+        assert_eq!(
+            AccessLogFormat::parse("combined"),
+            Ok(AccessLogFormat::Combined)
+        );
+        assert_eq!(AccessLogFormat::parse("JSON"), Ok(AccessLogFormat::Json));
+        assert!(AccessLogFormat::parse("xml").is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Ad insertion via an external ad decision server.
+//!
+//! At every break point of the configured cron schedule the ad decision URL is
+//! requested. The response is a VAST-lite document: any VAST XML containing
+//! `<Ad>` elements, of which only the first `<MediaFile>` and all `<Impression>`
+//! URLs of each ad are used. Creatives are downloaded into a directory per break
+//! and inserted into the playout, impressions are reported once a creative is
+//! handed to playout. Directories of breaks older than a day are removed.
+
+use crate::config::AdsConfig;
+use crate::http_client::HttpClientFactory;
+use crate::schedule_engine::{BreakItem, PlaylistCommand};
+use crate::xml_scan::{element_contents, text_value};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use cron::Schedule;
+use crossbeam_channel::Sender;
+use log::{debug, error, info, warn};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Directory with a subdirectory per break the creatives are downloaded to
+const AD_CACHE_DIR: &str = "./data/ads";
+/// Breaks can be queued behind long tracks, their creatives are kept for a day
+const AD_RETENTION_HOURS: i64 = 24;
+/// Larger creatives are not downloaded
+const MAX_CREATIVE_BYTES: u64 = 50 * 1024 * 1024;
+const BREAK_DIR_PREFIX: &str = "break-";
+const BREAK_DIR_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+const DEFAULT_MAX_ADS_PER_BREAK: usize = 3;
+const DEFAULT_AD_TIMEOUT_SECONDS: u64 = 10;
+
+/// A single ad returned by the ad decision server
+#[derive(Debug, PartialEq)]
+pub struct VastAd {
+    pub media_url: String,
+    pub impression_urls: Vec<String>,
+}
+
+pub struct AdBreakScheduler {
+    decision_url: String,
+    schedule: Schedule,
+    max_ads: usize,
+    client: reqwest::Client,
+    command_tx: Sender<PlaylistCommand>,
+}
+
+impl AdBreakScheduler {
+    pub fn new(
+        config: &AdsConfig,
+        command_tx: Sender<PlaylistCommand>,
+        http: &HttpClientFactory,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let schedule = Schedule::from_str(&config.break_cron)
+            .map_err(|e| format!("Invalid ad break cron '{}': {}", config.break_cron, e))?;
+
+        let client = http.client(std::time::Duration::from_secs(
+            config.timeout_seconds.unwrap_or(DEFAULT_AD_TIMEOUT_SECONDS),
+        ))?;
+
+        Ok(Self {
+            decision_url: config.decision_url.clone(),
+            schedule,
+            max_ads: config
+                .max_ads_per_break
+                .map(|max| max as usize)
+                .unwrap_or(DEFAULT_MAX_ADS_PER_BREAK),
+            client,
+            command_tx,
+        })
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Ad break scheduler started");
+
+            while let Some(next_break) = self.schedule.upcoming(Local).next() {
+                let wait = (next_break - Local::now()).to_std().unwrap_or_default();
+                debug!("Next ad break at {}", next_break.format("%H:%M:%S"));
+                tokio::time::sleep(wait).await;
+
+                match self.prepare_break().await {
+                    Ok(items) if items.is_empty() => info!("Ad break skipped, no ads returned"),
+                    Ok(items) => {
+                        info!("Inserting ad break with {} ad(s)", items.len());
+                        let command = PlaylistCommand::InsertBreak {
+                            name: "Ad break".to_string(),
+                            items,
+                        };
+                        if self.command_tx.send(command).is_err() {
+                            error!("Failed to send ad break - receiver dropped");
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Ad break skipped: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Requests ads from the decision server and downloads their creatives
+    async fn prepare_break(
+        &self,
+    ) -> Result<Vec<BreakItem>, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .client
+            .get(&self.decision_url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let ads = parse_vast(&response.text().await?);
+
+        let now = Local::now();
+        let cache_dir = Path::new(AD_CACHE_DIR);
+        prune_breaks(cache_dir, now).await;
+        let break_dir = break_dir(cache_dir, now);
+        tokio::fs::create_dir_all(&break_dir).await?;
+
+        let mut items = Vec::new();
+        for (index, ad) in ads.into_iter().take(self.max_ads).enumerate() {
+            let path = break_dir.join(format!(
+                "ad-{}.{}",
+                index,
+                creative_extension(&ad.media_url)
+            ));
+
+            match self.download(&ad.media_url, &path).await {
+                Ok(()) => items.push(BreakItem {
+                    path,
+                    impression_urls: ad.impression_urls,
+                }),
+                Err(e) => warn!("Failed to download ad creative {}: {}", ad.media_url, e),
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn download(
+        &self,
+        url: &str,
+        path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_CREATIVE_BYTES)
+        {
+            return Err(format!("creative exceeds {} bytes", MAX_CREATIVE_BYTES).into());
+        }
+
+        // The announced length may be missing or wrong, so the body is capped while reading
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (data.len() + chunk.len()) as u64 > MAX_CREATIVE_BYTES {
+                return Err(format!("creative exceeds {} bytes", MAX_CREATIVE_BYTES).into());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        tokio::fs::write(path, &data).await?;
+        Ok(())
+    }
+}
+
+/// Directory the creatives of the break at `time` are downloaded to
+fn break_dir(cache_dir: &Path, time: DateTime<Local>) -> PathBuf {
+    cache_dir.join(format!(
+        "{}{}",
+        BREAK_DIR_PREFIX,
+        time.format(BREAK_DIR_FORMAT)
+    ))
+}
+
+/// Time of the break a directory belongs to, `None` for other directories
+fn break_time(dir: &Path) -> Option<DateTime<Local>> {
+    let name = dir.file_name()?.to_str()?.strip_prefix(BREAK_DIR_PREFIX)?;
+    let time = NaiveDateTime::parse_from_str(name, BREAK_DIR_FORMAT).ok()?;
+    Local.from_local_datetime(&time).earliest()
+}
+
+/// Removes the creatives of breaks older than the retention, queued or playing breaks are
+/// newer and keep theirs
+async fn prune_breaks(cache_dir: &Path, now: DateTime<Local>) {
+    let Ok(mut entries) = tokio::fs::read_dir(cache_dir).await else {
+        return;
+    };
+    let cutoff = now - chrono::Duration::hours(AD_RETENTION_HOURS);
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let dir = entry.path();
+        if break_time(&dir).is_some_and(|time| time < cutoff) {
+            match tokio::fs::remove_dir_all(&dir).await {
+                Ok(()) => debug!("Removed creatives of past break {:?}", dir),
+                Err(e) => warn!("Failed to remove creatives of past break {:?}: {}", dir, e),
+            }
+        }
+    }
+}
+
+/// Requests all impression URLs of a played creative in the background
+pub fn report_impressions(http: &HttpClientFactory, urls: Vec<String>) {
+    if urls.is_empty() {
+        return;
+    }
+
+    let client = match http.client(std::time::Duration::from_secs(DEFAULT_AD_TIMEOUT_SECONDS)) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to report impressions: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        for url in urls {
+            match client.get(&url).send().await {
+                Ok(response) => debug!("Reported impression {}: {}", url, response.status()),
+                Err(e) => warn!("Failed to report impression {}: {}", url, e),
+            }
+        }
+    });
+}
+
+/// Extracts the ads of a VAST document, ads without a media file are ignored
+pub fn parse_vast(xml: &str) -> Vec<VastAd> {
+    element_contents(xml, "Ad")
+        .into_iter()
+        .filter_map(|ad| {
+            let media_url = element_contents(ad, "MediaFile")
+                .into_iter()
+                .map(text_value)
+                .find(|url| !url.is_empty())?;
+            let impression_urls = element_contents(ad, "Impression")
+                .into_iter()
+                .map(text_value)
+                .filter(|url| !url.is_empty())
+                .collect();
+
+            Some(VastAd {
+                media_url,
+                impression_urls,
+            })
+        })
+        .collect()
+}
+
+fn creative_extension(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| ext.len() <= 4)
+        .unwrap_or("mp3")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VAST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VAST version="3.0">
+  <Ad id="1">
+    <InLine>
+      <Impression><![CDATA[https://ads.example.com/imp?id=1]]></Impression>
+      <Impression>https://tracker.example.com/imp?id=1&amp;src=radio</Impression>
+      <Creatives>
+        <Creative>
+          <Linear>
+            <MediaFiles>
+              <MediaFile type="audio/mpeg" delivery="progressive">
+                <![CDATA[https://cdn.example.com/spot1.mp3]]>
+              </MediaFile>
+            </MediaFiles>
+          </Linear>
+        </Creative>
+      </Creatives>
+    </InLine>
+  </Ad>
+  <Ad id="2">
+    <InLine>
+      <Impression>https://ads.example.com/imp?id=2</Impression>
+    </InLine>
+  </Ad>
+</VAST>"#;
+
+    #[test]
+    fn given_vast_document_when_parsed_then_returns_ads_with_media_file() {
+        let ads = parse_vast(VAST);
+
+        assert_eq!(
+            ads,
+            vec![VastAd {
+                media_url: "https://cdn.example.com/spot1.mp3".to_string(),
+                impression_urls: vec![
+                    "https://ads.example.com/imp?id=1".to_string(),
+                    "https://tracker.example.com/imp?id=1&src=radio".to_string(),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn given_empty_vast_document_when_parsed_then_returns_no_ads() {
+        assert!(parse_vast(r#"<VAST version="3.0"></VAST>"#).is_empty());
+    }
+
+    #[test]
+    fn given_creative_url_when_getting_extension_then_ignores_query() {
+        assert_eq!(creative_extension("https://cdn/spot.ogg?x=1"), "ogg");
+        assert_eq!(creative_extension("https://cdn/spot"), "mp3");
+    }
+
+    #[tokio::test]
+    async fn given_break_directories_when_pruning_then_only_expired_breaks_are_removed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let now = Local::now();
+        let expired = break_dir(dir.path(), now - chrono::Duration::hours(25));
+        let queued = break_dir(dir.path(), now - chrono::Duration::hours(2));
+        let other = dir.path().join("keep");
+        for path in [&expired, &queued, &other] {
+            std::fs::create_dir_all(path).unwrap();
+            std::fs::write(path.join("ad-0.mp3"), b"ad").unwrap();
+        }
+
+        prune_breaks(dir.path(), now).await;
+
+        assert!(!expired.exists());
+        assert!(queued.join("ad-0.mp3").exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn given_break_directory_when_reading_its_time_then_matches_the_break() {
+        let time = Local.with_ymd_and_hms(2026, 3, 1, 14, 0, 0).unwrap();
+
+        let dir = break_dir(Path::new("/data/ads"), time);
+
+        assert_eq!(dir, Path::new("/data/ads/break-20260301-140000.000"));
+        assert_eq!(break_time(&dir), Some(time));
+        assert_eq!(break_time(Path::new("/data/ads/ad-0.mp3")), None);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::api_error::error_reply;
+use std::sync::Arc;
+use warp::http::header::WWW_AUTHENTICATE;
+use warp::http::{Method, StatusCode};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+/// Path prefixes only administrators may access, whatever the method
+const ADMIN_PATHS: [&str; 2] = ["/admin", "/api/admin"];
+
+/// Why a request to the admin API was refused
+#[derive(Debug, PartialEq)]
+enum AdminRejection {
+    /// No `admin_token` is configured
+    Disabled,
+    /// The request carries no or a wrong bearer token
+    Unauthorized,
+}
+
+impl warp::reject::Reject for AdminRejection {}
+
+/// Guards the admin API with the bearer token of `[server] admin_token`.
+///
+/// Every request changing the station (any method but `GET`, `HEAD` and `OPTIONS`), every
+/// request below `/admin` or `/api/admin` and the listener listings with client IPs and user
+/// agents need the token as `Authorization: Bearer` header.
+/// Without a configured token these requests are refused, read-only routes stay public.
+#[derive(Clone)]
+pub struct AdminAuth {
+    token: Option<Arc<str>>,
+}
+
+impl AdminAuth {
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            token: token.map(Arc::from),
+        }
+    }
+
+    /// Passes public requests and authorized admin requests, rejects the others
+    pub fn filter(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let auth = self.clone();
+        warp::method()
+            .and(warp::path::full())
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(
+                move |method: Method, path: FullPath, authorization: Option<String>| {
+                    let result = auth.authorize(&method, path.as_str(), authorization.as_deref());
+                    async move { result.map_err(warp::reject::custom) }
+                },
+            )
+            .untuple_one()
+    }
+
+    fn authorize(
+        &self,
+        method: &Method,
+        path: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), AdminRejection> {
+        if !Self::is_admin_request(method, path) {
+            return Ok(());
+        }
+
+        let Some(token) = &self.token else {
+            return Err(AdminRejection::Disabled);
+        };
+        let given = authorization
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        match given {
+            Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(AdminRejection::Unauthorized),
+        }
+    }
+
+    fn is_admin_request(method: &Method, path: &str) -> bool {
+        let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+        let admin_path = ADMIN_PATHS.iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+        !read_only || admin_path || Self::is_listener_listing(path)
+    }
+
+    /// `/status/streams/{name}/listeners`, admin-only like the Icecast `listclients` action
+    fn is_listener_listing(path: &str) -> bool {
+        let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        matches!(
+            segments[..],
+            ["", "status", "streams", name, "listeners"] if !name.is_empty()
+        )
+    }
+}
+
+/// Turns refused admin requests into JSON errors, other rejections pass on
+pub async fn recover(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    match rejection.find::<AdminRejection>() {
+        Some(AdminRejection::Disabled) => Ok(error_reply(
+            StatusCode::FORBIDDEN,
+            "Admin API disabled, set admin_token in the [server] config section",
+        )
+        .into_response()),
+        Some(AdminRejection::Unauthorized) => Ok(warp::reply::with_header(
+            error_reply(StatusCode::UNAUTHORIZED, "Missing or invalid admin token"),
+            WWW_AUTHENTICATE,
+            "Bearer",
+        )
+        .into_response()),
+        None => Err(rejection),
+    }
+}
+
+/// Compares the tokens without revealing the position of the first difference through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> AdminAuth {
+        AdminAuth::new(Some("s3cret".to_string()))
+    }
+
+    #[test]
+    fn given_read_only_public_request_when_authorizing_then_passes_without_token() {
+        for path in [
+            "/status",
+            "/api/schedule/export",
+            "/high",
+            "/administration",
+            "/status/streams",
+        ] {
+            assert_eq!(auth().authorize(&Method::GET, path, None), Ok(()));
+        }
+        assert_eq!(
+            AdminAuth::new(None).authorize(&Method::HEAD, "/high", None),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn given_changing_or_admin_request_when_authorizing_then_requires_the_token() {
+        let requests = [
+            (Method::POST, "/api/schedule/import"),
+            (Method::DELETE, "/api/library/tracks/1"),
+            (Method::PUT, "/api/playlists/1"),
+            (Method::GET, "/admin/webhooks"),
+            (Method::GET, "/api/admin/selftest"),
+            (Method::GET, "/status/streams/high/listeners"),
+        ];
+
+        for (method, path) in &requests {
+            assert_eq!(
+                auth().authorize(method, path, None),
+                Err(AdminRejection::Unauthorized)
+            );
+            assert_eq!(
+                auth().authorize(method, path, Some("Bearer wrong")),
+                Err(AdminRejection::Unauthorized)
+            );
+            assert_eq!(
+                auth().authorize(method, path, Some("s3cret")),
+                Err(AdminRejection::Unauthorized)
+            );
+            assert_eq!(
+                auth().authorize(method, path, Some("Bearer s3cret")),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn given_no_configured_token_when_authorizing_admin_request_then_disabled() {
+        let auth = AdminAuth::new(None);
+
+        assert_eq!(
+            auth.authorize(
+                &Method::POST,
+                "/api/library/upload",
+                Some("Bearer anything")
+            ),
+            Err(AdminRejection::Disabled)
+        );
+    }
+
+    #[tokio::test]
+    async fn given_unauthenticated_listener_listing_when_requested_then_refused() {
+        let route = auth()
+            .filter()
+            .and(warp::any().map(|| "listeners"))
+            .recover(recover);
+
+        let anonymous = warp::test::request()
+            .path("/status/streams/high/listeners")
+            .reply(&route)
+            .await;
+        let admin = warp::test::request()
+            .path("/status/streams/high/listeners")
+            .header("authorization", "Bearer s3cret")
+            .reply(&route)
+            .await;
+
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(admin.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::audio_metadata::TrackMetadata;
+use crate::config::AnnouncementsConfig;
+use log::{debug, error, warn};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+
+pub const ANNOUNCEMENT_CACHE_PATH: &str = "./data/announcements";
+
+const DEFAULT_TEMPLATE: &str = "You're listening to {station}, that was {artist} – {title}";
+pub const DEFAULT_EXTENSION: &str = "wav";
+
+/// Files the announcements are written to in turn, so a clip still playing is never overwritten
+const ANNOUNCEMENT_SLOTS: usize = 3;
+
+/// Spoken "that was" announcements between library tracks, synthesized by a TTS command.
+///
+/// The announcement of a track is synthesized in the background while the track plays and
+/// is played right after it. If the command has not finished by then, the announcement is
+/// dropped instead of delaying the next track.
+pub struct Announcements {
+    command: Vec<String>,
+    template: String,
+    station_name: String,
+    every_n_tracks: u32,
+    directory: PathBuf,
+    extension: String,
+    tracks_since: u32,
+    slot: usize,
+    synthesis: Option<JoinHandle<Result<PathBuf, String>>>,
+}
+
+impl Announcements {
+    pub fn new(config: &AnnouncementsConfig, station_name: &str, directory: PathBuf) -> Self {
+        Self {
+            command: config.command.clone(),
+            template: config
+                .template
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+            station_name: station_name.to_string(),
+            every_n_tracks: config.every_n_tracks,
+            directory,
+            extension: config
+                .extension
+                .clone()
+                .unwrap_or_else(|| DEFAULT_EXTENSION.to_string()),
+            tracks_since: 0,
+            slot: 0,
+            synthesis: None,
+        }
+    }
+
+    /// Counts a library track handed to playout and starts synthesizing its announcement
+    /// once the cadence is reached
+    pub fn track_played(&mut self, track: &Path) {
+        self.tracks_since += 1;
+        if self.tracks_since < self.every_n_tracks {
+            return;
+        }
+        self.tracks_since = 0;
+
+        let text = self.text(&TrackMetadata::from_file(track));
+        let output = self.next_output();
+        let command = self.command.clone();
+        debug!("Synthesizing announcement: {}", text);
+        self.synthesis = Some(std::thread::spawn(move || {
+            synthesize(&command, &text, &output)
+                .map(|()| output)
+                .map_err(|e| e.to_string())
+        }));
+    }
+
+    /// Announcement of the track played last, `None` if none is due or it is not ready
+    pub fn next_due(&mut self) -> Option<PathBuf> {
+        let synthesis = self.synthesis.take()?;
+        if !synthesis.is_finished() {
+            warn!("Announcement is not synthesized in time, skipping it");
+            return None;
+        }
+
+        match synthesis.join() {
+            Ok(Ok(clip)) => Some(clip),
+            Ok(Err(e)) => {
+                error!("Failed to synthesize announcement: {}", e);
+                None
+            }
+            Err(_) => {
+                error!("Announcement synthesis panicked");
+                None
+            }
+        }
+    }
+
+    fn text(&self, metadata: &TrackMetadata) -> String {
+        self.template
+            .replace("{station}", &self.station_name)
+            .replace("{artist}", &metadata.artist)
+            .replace("{title}", &metadata.title)
+    }
+
+    fn next_output(&mut self) -> PathBuf {
+        self.slot = (self.slot + 1) % ANNOUNCEMENT_SLOTS;
+        self.directory
+            .join(format!("announcement-{}.{}", self.slot, self.extension))
+    }
+}
+
+/// Runs the TTS command, which writes the spoken `text` to `output`
+pub fn synthesize(
+    command: &[String],
+    text: &str,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let output_arg = output.to_string_lossy();
+    let args: Vec<String> = command
+        .iter()
+        .map(|arg| arg.replace("{output}", &output_arg).replace("{text}", text))
+        .collect();
+    let (program, args) = args.split_first().ok_or("TTS command is empty")?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Cannot run {}: {}", program, e))?;
+    // Commands like piper read the text from stdin, others ignore it
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text.as_bytes());
+    }
+
+    let result = child.wait_with_output()?;
+    if !result.status.success() {
+        return Err(format!(
+            "{} failed with {}: {}",
+            program,
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )
+        .into());
+    }
+    if !output.is_file() {
+        return Err(format!("{} wrote no file to {:?}", program, output).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn announcements(directory: &TempDir, command: &[&str]) -> Announcements {
+        Announcements::new(
+            &AnnouncementsConfig {
+                command: command.iter().map(|arg| arg.to_string()).collect(),
+                every_n_tracks: 2,
+                template: None,
+                extension: Some("txt".to_string()),
+            },
+            "Funkstrom FM",
+            directory.path().to_path_buf(),
+        )
+    }
+
+    fn wait_until_synthesized(announcements: &Announcements) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while announcements
+            .synthesis
+            .as_ref()
+            .is_some_and(|s| !s.is_finished())
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn given_cadence_reached_when_track_played_then_announces_it_after_the_track() {
+        let directory = TempDir::new().unwrap();
+        let mut announcements = announcements(&directory, &["sh", "-c", "cat > {output}"]);
+
+        announcements.track_played(Path::new("/music/Artist - First.mp3"));
+        assert!(announcements.next_due().is_none());
+
+        announcements.track_played(Path::new("/music/Second Song.mp3"));
+        wait_until_synthesized(&announcements);
+        let clip = announcements.next_due().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(clip).unwrap(),
+            "You're listening to Funkstrom FM, that was Unknown Artist – Second Song"
+        );
+        assert!(announcements.next_due().is_none());
+    }
+
+    #[test]
+    fn given_failing_command_when_due_then_announcement_is_skipped() {
+        let directory = TempDir::new().unwrap();
+        let mut announcements = announcements(&directory, &["sh", "-c", "exit 1", "{output}"]);
+
+        announcements.track_played(Path::new("/music/First.mp3"));
+        announcements.track_played(Path::new("/music/Second.mp3"));
+        wait_until_synthesized(&announcements);
+
+        assert!(announcements.next_due().is_none());
+    }
+}
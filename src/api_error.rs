@@ -0,0 +1,20 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+
+/// Error body returned by the JSON API endpoints
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    #[schema(example = "Track 42 not found")]
+    pub error: String,
+}
+
+pub fn error_reply(status: StatusCode, message: &str) -> WithStatus<Json> {
+    warp::reply::with_status(
+        warp::reply::json(&ApiError {
+            error: message.to_string(),
+        }),
+        status,
+    )
+}
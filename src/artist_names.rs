@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+
+/// Artist the scanner stores for files without an artist tag
+pub const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+/// Start of the featured artists in an artist tag, matched ignoring case. The main artist ends
+/// before the first one.
+const FEATURING_MARKERS: &[&str] = &[
+    " feat. ",
+    " feat ",
+    " ft. ",
+    " ft ",
+    " featuring ",
+    "(feat.",
+    "(feat ",
+    "(ft.",
+    "(ft ",
+    "(featuring ",
+    "[feat.",
+    "[feat ",
+    "[ft.",
+    "[ft ",
+    "[featuring ",
+];
+
+/// The main artist of an artist tag without featured artists and surplus whitespace, e.g.
+/// `Daft Punk` for `Daft Punk feat. Pharrell Williams`
+pub fn main_artist(artist: &str) -> String {
+    // ASCII lowercase keeps the byte offsets of the tag
+    let lowercase = format!("{} ", artist.to_ascii_lowercase());
+    let end = FEATURING_MARKERS
+        .iter()
+        .filter_map(|marker| lowercase.find(marker))
+        .min()
+        .unwrap_or(artist.len())
+        .min(artist.len());
+    let main = artist[..end]
+        .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, ',' | '-' | '&' | '(' | '['));
+    let main = if main.trim().is_empty() { artist } else { main };
+
+    main.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Key under which spellings of the same artist are one artist: the main artist ignoring case.
+/// `None` for tracks without a known artist.
+pub fn artist_key(artist: &str) -> Option<String> {
+    let key = main_artist(artist).to_lowercase();
+    if key.is_empty() || key == UNKNOWN_ARTIST.to_lowercase() {
+        return None;
+    }
+    Some(key)
+}
+
+/// Remembers the artists of the last library tracks, so an artist does not play again within a
+/// number of tracks
+pub struct ArtistSeparation {
+    tracks: usize,
+    /// Keys of the last artists, `None` for tracks without a known artist
+    recent: VecDeque<Option<String>>,
+}
+
+impl ArtistSeparation {
+    pub fn new(tracks: u32) -> Self {
+        Self {
+            tracks: tracks as usize,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Whether the artist played within the last tracks, never for unknown artists
+    pub fn is_recent(&self, artist: &str) -> bool {
+        let key = artist_key(artist);
+        key.is_some() && self.recent.contains(&key)
+    }
+
+    pub fn track_played(&mut self, artist: Option<&str>) {
+        if self.recent.len() == self.tracks {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(artist.and_then(artist_key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_spellings_of_an_artist_when_normalized_then_share_the_key() {
+        for artist in [
+            "Daft Punk",
+            "daft punk",
+            "  Daft   Punk ",
+            "Daft Punk feat. Pharrell Williams",
+            "DAFT PUNK FT. Nile Rodgers",
+            "Daft Punk (feat. Julian Casablancas)",
+            "Daft Punk [Featuring Panda Bear]",
+        ] {
+            assert_eq!(
+                artist_key(artist).as_deref(),
+                Some("daft punk"),
+                "{}",
+                artist
+            );
+        }
+
+        assert_eq!(main_artist("Daft Punk Feat. Pharrell"), "Daft Punk");
+        // Names merely containing the letters of a marker are kept
+        assert_eq!(main_artist("Soft Cell"), "Soft Cell");
+        assert_eq!(main_artist("The Feathers"), "The Feathers");
+        assert_eq!(artist_key(UNKNOWN_ARTIST), None);
+        assert_eq!(artist_key(" "), None);
+    }
+
+    #[test]
+    fn given_separation_when_tracks_played_then_artist_is_recent_for_that_many_tracks() {
+        let mut separation = ArtistSeparation::new(2);
+        separation.track_played(Some("Daft Punk feat. Pharrell Williams"));
+        assert!(separation.is_recent("daft punk"));
+
+        separation.track_played(None);
+        assert!(separation.is_recent("Daft Punk"));
+        assert!(!separation.is_recent(UNKNOWN_ARTIST));
+
+        separation.track_played(Some("Air"));
+        assert!(!separation.is_recent("Daft Punk"));
+        assert!(separation.is_recent("Air"));
+    }
+}
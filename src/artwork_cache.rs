@@ -0,0 +1,125 @@
+use log::debug;
+use ring::digest;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Directory holding the cover art thumbnails
+pub const ARTWORK_CACHE_PATH: &str = "./data/artwork";
+
+/// Longest side of a thumbnail in pixels, smaller covers keep their size
+const THUMBNAIL_SIZE: u32 = 500;
+
+/// Thumbnails of the cover art embedded in library tracks, written by the library scan.
+///
+/// Thumbnails are named after the hash of the embedded image, so the tracks of an album
+/// share one thumbnail and it is resized only once.
+#[derive(Clone)]
+pub struct ArtworkCache {
+    directory: PathBuf,
+    ffmpeg_path: String,
+}
+
+impl ArtworkCache {
+    pub fn new(directory: PathBuf, ffmpeg_path: Option<String>) -> Self {
+        Self {
+            directory,
+            ffmpeg_path: ffmpeg_path.unwrap_or_else(|| "ffmpeg".to_string()),
+        }
+    }
+
+    /// Thumbnail of an embedded cover image, resized unless it is cached already
+    pub fn thumbnail(&self, image: &[u8]) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+        let key: String = digest::digest(&digest::SHA256, image).as_ref()[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let target = self.directory.join(format!("{}.jpg", key));
+        if target.is_file() {
+            return Ok(target);
+        }
+
+        fs::create_dir_all(&self.directory)?;
+        let partial = self.directory.join(format!("{}.part.jpg", key));
+        if let Err(e) = self.resize(image, &partial) {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, &target)?;
+
+        debug!("Cached cover art thumbnail {:?}", target);
+        Ok(target)
+    }
+
+    /// Scales the image down to a JPEG of at most the thumbnail size with ffmpeg
+    fn resize(&self, image: &[u8], output: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let scale = format!(
+            "scale='min({0},iw)':'min({0},ih)':force_original_aspect_ratio=decrease",
+            THUMBNAIL_SIZE
+        );
+        let mut child = Command::new(&self.ffmpeg_path)
+            .args(["-v", "error", "-i", "pipe:0", "-vf", &scale])
+            .args(["-frames:v", "1", "-update", "1", "-y"])
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Cannot run {}: {}", self.ffmpeg_path, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // ffmpeg may stop reading early for broken images, its exit status tells
+            let _ = stdin.write_all(image);
+        }
+
+        let result = child.wait_with_output()?;
+        if !result.status.success() {
+            return Err(format!(
+                "ffmpeg failed to resize the cover art with {}: {}",
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn given_same_cover_twice_when_cached_then_resizes_it_once() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new().unwrap();
+        let calls = directory.path().join("calls");
+        // Stands in for ffmpeg: counts its calls and copies stdin to the output file
+        let ffmpeg = directory.path().join("ffmpeg");
+        fs::write(
+            &ffmpeg,
+            format!(
+                "#!/bin/sh\necho call >> {}\nfor arg; do output=$arg; done\ncat > \"$output\"\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&ffmpeg, fs::Permissions::from_mode(0o755)).unwrap();
+        let cache = ArtworkCache::new(
+            directory.path().join("artwork"),
+            Some(ffmpeg.to_string_lossy().to_string()),
+        );
+
+        let first = cache.thumbnail(b"cover").unwrap();
+        let second = cache.thumbnail(b"cover").unwrap();
+        let other = cache.thumbnail(b"other cover").unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(fs::read(&first).unwrap(), b"cover");
+        assert_eq!(fs::read_to_string(calls).unwrap().lines().count(), 2);
+    }
+}
@@ -1,10 +1,12 @@
+use crate::audio_processor::AudioChunk;
 use bytes::Bytes;
 use crossbeam_channel::{bounded, Receiver, Sender};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub struct CircularBuffer {
-    buffer: VecDeque<Bytes>,
+    buffer: VecDeque<AudioChunk>,
     max_size: usize,
     total_bytes: usize,
     max_bytes: usize,
@@ -20,34 +22,43 @@ impl CircularBuffer {
         }
     }
 
-    pub fn push(&mut self, data: Bytes) {
-        while self.total_bytes + data.len() > self.max_bytes && !self.buffer.is_empty() {
+    pub fn push(&mut self, chunk: AudioChunk) {
+        let len = chunk.data.len();
+        while self.total_bytes + len > self.max_bytes && !self.buffer.is_empty() {
             if let Some(removed) = self.buffer.pop_front() {
-                self.total_bytes -= removed.len();
+                self.total_bytes -= removed.data.len();
             }
         }
 
-        if data.len() <= self.max_bytes {
-            self.buffer.push_back(data.clone());
-            self.total_bytes += data.len();
+        if len <= self.max_bytes {
+            self.buffer.push_back(chunk);
+            self.total_bytes += len;
 
             while self.buffer.len() > self.max_size {
                 if let Some(removed) = self.buffer.pop_front() {
-                    self.total_bytes -= removed.len();
+                    self.total_bytes -= removed.data.len();
                 }
             }
         }
     }
 
-    pub fn pop(&mut self) -> Option<Bytes> {
-        if let Some(data) = self.buffer.pop_front() {
-            self.total_bytes -= data.len();
-            Some(data)
+    pub fn pop(&mut self) -> Option<AudioChunk> {
+        if let Some(chunk) = self.buffer.pop_front() {
+            self.total_bytes -= chunk.data.len();
+            Some(chunk)
         } else {
             None
         }
     }
 
+    /// Playback time covered by the buffered chunks
+    pub fn time_span(&self) -> Duration {
+        match (self.buffer.front(), self.buffer.back()) {
+            (Some(oldest), Some(newest)) => newest.timestamp.saturating_sub(oldest.timestamp),
+            _ => Duration::ZERO,
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.buffer.len()
     }
@@ -63,9 +74,11 @@ impl CircularBuffer {
 
 pub struct StreamBuffer {
     buffer: Arc<Mutex<CircularBuffer>>,
-    input_sender: Sender<Bytes>,
-    input_receiver: Receiver<Bytes>,
+    input_sender: Sender<AudioChunk>,
+    input_receiver: Receiver<AudioChunk>,
     running: Arc<Mutex<bool>>,
+    on_air_since: Arc<Mutex<Option<chrono::DateTime<chrono::Local>>>>,
+    stream_header: Arc<Mutex<Option<Bytes>>>,
 }
 
 impl StreamBuffer {
@@ -80,10 +93,12 @@ impl StreamBuffer {
             input_sender,
             input_receiver,
             running: Arc::new(Mutex::new(false)),
+            on_air_since: Arc::new(Mutex::new(None)),
+            stream_header: Arc::new(Mutex::new(None)),
         }
     }
 
-    pub fn get_input_sender(&self) -> Sender<Bytes> {
+    pub fn get_input_sender(&self) -> Sender<AudioChunk> {
         self.input_sender.clone()
     }
 
@@ -91,6 +106,8 @@ impl StreamBuffer {
         let buffer = Arc::clone(&self.buffer);
         let receiver = self.input_receiver.clone();
         let running = Arc::clone(&self.running);
+        let on_air_since = Arc::clone(&self.on_air_since);
+        let stream_header = Arc::clone(&self.stream_header);
 
         {
             let mut running_guard = running.lock().unwrap();
@@ -106,9 +123,16 @@ impl StreamBuffer {
                 .await;
 
                 match data {
-                    Ok(Ok(bytes)) => {
+                    Ok(Ok(chunk)) if chunk.stream_header => {
+                        *stream_header.lock().unwrap() = Some(chunk.data);
+                    }
+                    Ok(Ok(chunk)) => {
+                        on_air_since
+                            .lock()
+                            .unwrap()
+                            .get_or_insert_with(chrono::Local::now);
                         let mut buffer_guard = buffer.lock().unwrap();
-                        buffer_guard.push(bytes);
+                        buffer_guard.push(chunk);
                     }
                     Ok(Err(_)) => break,
                     Err(_) => break,
@@ -120,7 +144,8 @@ impl StreamBuffer {
         });
     }
 
-    pub fn read_chunk(&self, max_size: usize) -> Option<Bytes> {
+    /// Reads up to about `max_size` bytes, stamped with the timestamp of the oldest chunk
+    pub fn read_chunk(&self, max_size: usize) -> Option<AudioChunk> {
         let mut buffer_guard = self.buffer.lock().unwrap();
 
         if buffer_guard.is_empty() {
@@ -131,7 +156,7 @@ impl StreamBuffer {
         let mut total_size = 0;
 
         while let Some(chunk) = buffer_guard.pop() {
-            let chunk_size = chunk.len();
+            let chunk_size = chunk.data.len();
             chunks.push(chunk);
             total_size += chunk_size;
 
@@ -140,16 +165,19 @@ impl StreamBuffer {
             }
         }
 
-        if chunks.is_empty() {
-            None
-        } else if chunks.len() == 1 {
-            Some(chunks.into_iter().next().unwrap())
+        if chunks.len() <= 1 {
+            chunks.into_iter().next()
         } else {
+            let timestamp = chunks[0].timestamp;
             let mut combined = Vec::with_capacity(total_size);
             for chunk in chunks {
-                combined.extend_from_slice(&chunk);
+                combined.extend_from_slice(&chunk.data);
             }
-            Some(Bytes::from(combined))
+            Some(AudioChunk {
+                data: Bytes::from(combined),
+                timestamp,
+                stream_header: false,
+            })
         }
     }
 
@@ -158,10 +186,25 @@ impl StreamBuffer {
         (buffer_guard.len(), buffer_guard.total_bytes())
     }
 
+    /// Playback time of the buffered audio, i.e. how far new listeners start behind live
+    pub fn buffered_duration(&self) -> Duration {
+        self.buffer.lock().unwrap().time_span()
+    }
+
     pub fn is_running(&self) -> bool {
         let running_guard = self.running.lock().unwrap();
         *running_guard
     }
+
+    /// Codec header of the running encoder, sent to listeners before the audio
+    pub fn stream_header(&self) -> Option<Bytes> {
+        self.stream_header.lock().unwrap().clone()
+    }
+
+    /// Time the first audio data of this stream arrived
+    pub fn on_air_since(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        *self.on_air_since.lock().unwrap()
+    }
 }
 
 impl Clone for StreamBuffer {
@@ -171,6 +214,48 @@ impl Clone for StreamBuffer {
             input_sender: self.input_sender.clone(),
             input_receiver: self.input_receiver.clone(),
             running: Arc::clone(&self.running),
+            on_air_since: Arc::clone(&self.on_air_since),
+            stream_header: Arc::clone(&self.stream_header),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &'static [u8], millis: u64) -> AudioChunk {
+        AudioChunk {
+            data: Bytes::from_static(data),
+            timestamp: Duration::from_millis(millis),
+            stream_header: false,
+        }
+    }
+
+    #[test]
+    fn given_timestamped_chunks_when_buffered_then_time_span_covers_oldest_to_newest() {
+        let mut buffer = CircularBuffer::new(10, 1024);
+        buffer.push(chunk(b"ab", 1_000));
+        buffer.push(chunk(b"cd", 1_500));
+        buffer.push(chunk(b"ef", 3_000));
+
+        assert_eq!(buffer.time_span(), Duration::from_secs(2));
+        assert_eq!(buffer.pop().unwrap().timestamp, Duration::from_secs(1));
+        assert_eq!(buffer.time_span(), Duration::from_millis(1_500));
+    }
+
+    #[test]
+    fn given_multiple_chunks_when_reading_then_combined_chunk_keeps_oldest_timestamp() {
+        let stream = StreamBuffer::new(10, 1024);
+        {
+            let mut buffer = stream.buffer.lock().unwrap();
+            buffer.push(chunk(b"ab", 1_000));
+            buffer.push(chunk(b"cd", 1_500));
+        }
+
+        let read = stream.read_chunk(8192).unwrap();
+
+        assert_eq!(read.data, Bytes::from_static(b"abcd"));
+        assert_eq!(read.timestamp, Duration::from_secs(1));
+    }
+}
@@ -1,6 +1,13 @@
+use crate::library_db::TrackRecord;
 use audiotags::Tag;
+use chrono::{DateTime, Local};
 use log::{debug, warn};
+use serde::Serialize;
 use std::path::Path;
+use utoipa::ToSchema;
+
+/// Path of the endpoint serving the cover art of the current track
+pub const ARTWORK_PATH: &str = "/current/artwork";
 
 #[derive(Debug, Clone)]
 pub struct TrackMetadata {
@@ -8,6 +15,44 @@ pub struct TrackMetadata {
     pub artist: String,
     pub album: String,
     pub file_path: String,
+    pub duration_seconds: Option<u64>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    /// Whether the file has an embedded cover image
+    pub has_artwork: bool,
+    /// Time the track became the current track
+    pub started_at: Option<DateTime<Local>>,
+}
+
+/// Now playing information as returned by the `/current` endpoint
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = CurrentTrack)]
+pub struct NowPlaying {
+    /// Track title (from tags or filename)
+    #[schema(example = "Bohemian Rhapsody")]
+    pub title: String,
+    /// Artist name (from tags or "Unknown Artist")
+    #[schema(example = "Queen")]
+    pub artist: String,
+    /// Album name (from tags or "Unknown Album")
+    #[schema(example = "A Night at the Opera")]
+    pub album: String,
+    /// Absolute path to the audio file
+    #[schema(example = "/music/queen/bohemian_rhapsody.mp3")]
+    pub file_path: String,
+    /// Track length, if known
+    #[schema(example = 355)]
+    pub duration_seconds: Option<u64>,
+    /// Seconds since the track started
+    #[schema(example = 42)]
+    pub elapsed_seconds: u64,
+    #[schema(example = 1975)]
+    pub year: Option<i32>,
+    #[schema(example = "Rock")]
+    pub genre: Option<String>,
+    /// URL of the embedded cover image, `null` if the track has none
+    #[schema(example = "/current/artwork")]
+    pub artwork_url: Option<String>,
 }
 
 impl TrackMetadata {
@@ -33,6 +78,11 @@ impl TrackMetadata {
                     .map(|a| a.title.to_string())
                     .unwrap_or_else(|| "Unknown Album".to_string());
 
+                let duration_seconds = tag
+                    .duration()
+                    .filter(|d| *d > 0.0)
+                    .map(|d| d.round() as u64);
+
                 debug!(
                     "Extracted metadata from {:?}: {} - {} ({})",
                     path, artist, title, album
@@ -43,6 +93,11 @@ impl TrackMetadata {
                     artist,
                     album,
                     file_path,
+                    duration_seconds,
+                    year: tag.year(),
+                    genre: tag.genre().map(|g| g.to_string()),
+                    has_artwork: tag.album_cover().is_some(),
+                    started_at: None,
                 }
             }
             Err(e) => {
@@ -52,6 +107,22 @@ impl TrackMetadata {
         }
     }
 
+    /// Metadata of a library track as stored by the scan, for tracks whose file holds more than
+    /// the track, like the tracks of single-file albums
+    pub fn from_track(track: &TrackRecord) -> Self {
+        Self {
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            file_path: track.file_path.clone(),
+            duration_seconds: track.duration_seconds.map(|d| d.max(0) as u64),
+            year: track.year,
+            genre: track.genre.clone(),
+            has_artwork: track.artwork_path.is_some(),
+            started_at: None,
+        }
+    }
+
     /// Create metadata from filename when tags are unavailable
     fn from_filename(path: &Path) -> Self {
         let title = Self::default_title(path);
@@ -62,6 +133,7 @@ impl TrackMetadata {
             artist: "Unknown Artist".to_string(),
             album: "Unknown Album".to_string(),
             file_path,
+            ..Self::default()
         }
     }
 
@@ -79,18 +151,41 @@ impl TrackMetadata {
         format!("{} - {}", self.artist, self.title)
     }
 
+    /// Now playing information at the given time, elapsed time is capped at the track duration
+    pub fn now_playing(&self, now: DateTime<Local>) -> NowPlaying {
+        let elapsed = self
+            .started_at
+            .map(|started| (now - started).num_seconds().max(0) as u64)
+            .unwrap_or(0);
+
+        NowPlaying {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            file_path: self.file_path.clone(),
+            duration_seconds: self.duration_seconds,
+            elapsed_seconds: self
+                .duration_seconds
+                .map_or(elapsed, |duration| elapsed.min(duration)),
+            year: self.year,
+            genre: self.genre.clone(),
+            artwork_url: self.has_artwork.then(|| ARTWORK_PATH.to_string()),
+        }
+    }
+
     /// Format metadata as JSON
     pub fn to_json(&self) -> String {
-        serde_json::json!({
-            "title": self.title,
-            "artist": self.artist,
-            "album": self.album,
-            "file_path": self.file_path,
-        })
-        .to_string()
+        serde_json::to_string(&self.now_playing(Local::now())).unwrap_or_default()
     }
 }
 
+/// Reads the embedded cover image of an audio file, returns its data and MIME type
+pub fn read_artwork(path: &Path) -> Option<(Vec<u8>, &'static str)> {
+    let tag = Tag::new().read_from_path(path).ok()?;
+    let cover = tag.album_cover()?;
+    Some((cover.data.to_vec(), cover.mime_type.into()))
+}
+
 impl Default for TrackMetadata {
     fn default() -> Self {
         Self {
@@ -98,6 +193,11 @@ impl Default for TrackMetadata {
             artist: "Unknown Artist".to_string(),
             album: "Unknown Album".to_string(),
             file_path: String::new(),
+            duration_seconds: None,
+            year: None,
+            genre: None,
+            has_artwork: false,
+            started_at: None,
         }
     }
 }
@@ -124,11 +224,36 @@ mod tests {
             artist: "Test Artist".to_string(),
             album: "Test Album".to_string(),
             file_path: "/music/test.mp3".to_string(),
+            ..TrackMetadata::default()
         };
 
         assert_eq!(metadata.to_icy_metadata(), "Test Artist - Test Song");
     }
 
+    #[test]
+    fn test_now_playing() {
+        let started = Local::now();
+        let metadata = TrackMetadata {
+            duration_seconds: Some(180),
+            year: Some(1975),
+            has_artwork: true,
+            started_at: Some(started),
+            ..TrackMetadata::default()
+        };
+
+        let now_playing = metadata.now_playing(started + chrono::Duration::seconds(42));
+        assert_eq!(now_playing.elapsed_seconds, 42);
+        assert_eq!(now_playing.year, Some(1975));
+        assert_eq!(now_playing.artwork_url.as_deref(), Some(ARTWORK_PATH));
+
+        let overrun = metadata.now_playing(started + chrono::Duration::seconds(200));
+        assert_eq!(overrun.elapsed_seconds, 180);
+        assert_eq!(
+            TrackMetadata::default().now_playing(started).artwork_url,
+            None
+        );
+    }
+
     #[test]
     fn test_default_title() {
         let path = PathBuf::from("/music/my song.flac");
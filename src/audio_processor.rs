@@ -1,20 +1,94 @@
+use crate::audio_reader::QueuedTrack;
+use crate::audio_stage::{AudioStages, StageChain};
+use crate::config::{AacProfile, DownmixConfig, EncodingMode, ProcessingPreset, Resampler};
+use crate::cue_sheet::TrackSection;
+use crate::playout_control::PlayoutControl;
+use crate::process_supervisor::{ProcessTimeouts, SupervisedProcess};
+use crate::simulcast::{Pacer, StationClock};
+use crate::track_quarantine::TrackQuarantine;
+use crate::transitions::{FadeOut, PcmFormat, TrackCue, Transition, VoiceOver, VoiceOverMixer};
 use bytes::Bytes;
-use crossbeam_channel::{unbounded, Receiver};
+use crossbeam_channel::{Receiver, Sender};
 use log::{debug, error, info, warn};
-use std::io::{BufReader, Read};
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 // Constants for audio processing configuration
 const AUDIO_CHUNK_SIZE: usize = 8192; // 8KB chunks for reading audio data
 const PROCESS_POLL_INTERVAL_MS: u64 = 10; // How often to poll FFmpeg process
+const ENCODER_RESTART_DELAY_MS: u64 = 1000; // Delay before restarting a failed encoder
 
+/// Encoder output searched for a stream header before it is passed on as audio
+const MAX_STREAM_HEADER_BYTES: usize = 1024 * 1024;
+
+/// Three band compressor with a brickwall limiter, each band is compressed on its own so a
+/// loud bass line does not pump the vocals
+const BROADCAST_FILTERS: &str = "acrossover=split=200 2000[low][mid][high];\
+[low]acompressor=threshold=-24dB:ratio=4:attack=30:release=300:makeup=2[low_out];\
+[mid]acompressor=threshold=-21dB:ratio=3:attack=15:release=200:makeup=2[mid_out];\
+[high]acompressor=threshold=-24dB:ratio=3:attack=5:release=100:makeup=2[high_out];\
+[low_out][mid_out][high_out]amix=inputs=3:normalize=0,\
+alimiter=limit=0.89:attack=5:release=50:level=disabled";
+
+/// Slow single band compressor evening out quiet and loud tracks, with a limiter catching peaks
+const GENTLE_FILTERS: &str =
+    "acompressor=threshold=-20dB:ratio=2:attack=50:release=500:makeup=1.5,\
+alimiter=limit=0.95:level=disabled";
+
+/// Default level of the channels folded into others by a configured downmix, -3 dB
+const DEFAULT_DOWNMIX_DB: f64 = -3.0;
+
+/// A decoder may take longer to start, e.g. while connecting to a liveset URL
+const DECODER_TIMEOUTS: ProcessTimeouts = ProcessTimeouts {
+    startup: Duration::from_secs(30),
+    read: Duration::from_secs(15),
+};
+
+/// An encoder has to answer the audio written to it quickly
+const ENCODER_TIMEOUTS: ProcessTimeouts = ProcessTimeouts {
+    startup: Duration::from_secs(10),
+    read: Duration::from_secs(10),
+};
+
+#[derive(Clone)]
 pub struct FFmpegProcessor {
     ffmpeg_path: String,
     sample_rate: u32,
     bitrate: u32,
     channels: u8,
     format: String,
+    /// Encoder found in the FFmpeg build by `check_ffmpeg_available`
+    codec: Option<String>,
+    transition: Transition,
+    /// Audio filter chain applied by the encoder
+    filters: Option<String>,
+    /// Bitrate control, the defaults of the FFmpeg encoder if not set
+    mode: Option<EncodingMode>,
+    /// VBR quality on the scale of the format
+    quality: Option<f64>,
+    aac_profile: Option<AacProfile>,
+    /// Built-in processing applied after `filters`
+    processing: Option<ProcessingPreset>,
+    /// Channel levels of the decoders mixing files down to the stream channels
+    downmix: Option<DownmixConfig>,
+    /// Sample rate converter of the decoders, FFmpeg's default if not set
+    resampler: Option<Resampler>,
+    /// Precision of the soxr resampler in bits
+    resampler_precision: Option<u8>,
+    /// Output gain in dB, applied last before encoding
+    gain_db: Option<f64>,
+    /// Custom stages applied to the mixed audio before it is written to the encoder
+    stages: AudioStages,
+    /// FFmpeg encoder used instead of the preferred one of the format, e.g. a hardware encoder
+    encoder: Option<String>,
+    /// Threads of the encoder, FFmpeg's default if not set
+    threads: Option<u32>,
 }
 
 impl FFmpegProcessor {
@@ -31,9 +105,29 @@ impl FFmpegProcessor {
             bitrate,
             channels,
             format,
+            codec: None,
+            transition: Transition::default(),
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: AudioStages::default(),
+            encoder: None,
+            threads: None,
         }
     }
 
+    /// Transition between tracks that have no program transition
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transition = transition;
+        self
+    }
+
     fn get_codec_for_format(&self, format: &str) -> &str {
         match format {
             "mp3" => "libmp3lame",
@@ -48,7 +142,282 @@ impl FFmpegProcessor {
         }
     }
 
-    pub fn check_ffmpeg_available(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// Constant or variable bitrate, `quality` is used in VBR mode
+    pub fn with_encoding_mode(mut self, mode: Option<EncodingMode>, quality: Option<f64>) -> Self {
+        self.mode = mode;
+        self.quality = quality;
+        self
+    }
+
+    /// Profile of AAC streams, the encoder default (AAC-LC) if not set
+    pub fn with_aac_profile(mut self, aac_profile: Option<AacProfile>) -> Self {
+        self.aac_profile = aac_profile;
+        self
+    }
+
+    /// Encoder option selecting the AAC profile
+    fn profile_args(&self, codec: &str) -> Vec<String> {
+        let profile = match (codec, self.aac_profile) {
+            ("libfdk_aac" | "aac" | "aac_at", Some(AacProfile::Lc)) => "aac_low",
+            ("libfdk_aac" | "aac_at", Some(AacProfile::HeAac)) => "aac_he",
+            ("libfdk_aac" | "aac_at", Some(AacProfile::HeAacV2)) => "aac_he_v2",
+            _ => return Vec::new(),
+        };
+        vec!["-profile:a".into(), profile.into()]
+    }
+
+    /// Encoder option limiting its threads
+    fn thread_args(&self) -> Vec<String> {
+        match self.threads {
+            Some(threads) => vec!["-threads".into(), threads.to_string()],
+            None => Vec::new(),
+        }
+    }
+
+    /// Encoder options controlling the bitrate
+    fn rate_control_args(&self, codec: &str, bitrate: u32) -> Vec<String> {
+        // Lossless, the bitrate follows from the audio
+        if codec == "flac" {
+            return Vec::new();
+        }
+
+        let bitrate = format!("{}k", bitrate);
+        let Some(quality) = self
+            .quality
+            .filter(|_| self.mode == Some(EncodingMode::Vbr))
+        else {
+            // Opus encodes VBR by default, an explicit mode switches it
+            return match (self.mode, codec) {
+                (Some(EncodingMode::Vbr), "libopus") => {
+                    vec!["-ab".into(), bitrate, "-vbr".into(), "on".into()]
+                }
+                (Some(EncodingMode::Cbr), "libopus") => {
+                    vec!["-ab".into(), bitrate, "-vbr".into(), "off".into()]
+                }
+                _ => vec!["-ab".into(), bitrate],
+            };
+        };
+
+        match codec {
+            // libfdk_aac has VBR modes 1 to 5, the native encoder a quality of 0.1 to 2
+            "libfdk_aac" => vec!["-vbr".into(), (quality.round() as u32).to_string()],
+            "aac" => vec!["-q:a".into(), format!("{:.1}", quality * 0.4)],
+            _ => vec!["-q:a".into(), quality.to_string()],
+        }
+    }
+
+    /// Audio filter chain the encoder applies before encoding
+    pub fn with_filters(mut self, filters: Option<String>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Processing preset the encoder applies after the filter chain
+    pub fn with_processing(mut self, processing: Option<ProcessingPreset>) -> Self {
+        self.processing = processing;
+        self
+    }
+
+    /// Channel levels of the downmix to the stream channels, FFmpeg defaults if not set
+    pub fn with_downmix(mut self, downmix: Option<DownmixConfig>) -> Self {
+        self.downmix = downmix;
+        self
+    }
+
+    /// Sample rate converter of the decoders and the precision of soxr
+    pub fn with_resampler(mut self, resampler: Option<Resampler>, precision: Option<u8>) -> Self {
+        self.resampler = resampler;
+        self.resampler_precision = precision;
+        self
+    }
+
+    /// Decoder filter converting files to the stream format with the configured resampler
+    /// and downmix, `None` to leave the conversion to the FFmpeg defaults
+    fn decoder_filter(&self) -> Option<String> {
+        let mut options = Vec::new();
+        // A single downmix matrix covers mono, stereo and surround files brought to 5.1 first
+        if self.downmix.is_some() {
+            options.push("ocl=5.1".to_string());
+        }
+        if let Some(resampler) = self.resampler {
+            options.push(match resampler {
+                Resampler::Swr => "resampler=swr".to_string(),
+                Resampler::Soxr => "resampler=soxr".to_string(),
+            });
+        }
+        if let Some(precision) = self.resampler_precision {
+            options.push(format!("precision={}", precision));
+        }
+        if options.is_empty() {
+            return None;
+        }
+
+        let resample = format!("aresample={}", options.join(":"));
+        Some(match self.downmix_matrix() {
+            Some(matrix) => format!("{},pan={}", resample, matrix),
+            None => resample,
+        })
+    }
+
+    /// Decoder filter of a track, the loudness correction follows the format conversion
+    fn track_filter(&self, gain_db: Option<f64>) -> Option<String> {
+        let gain = gain_db.map(|db| format!("volume={:.2}dB", db));
+        match (self.decoder_filter(), gain) {
+            (Some(filter), Some(gain)) => Some(format!("{},{}", filter, gain)),
+            (filter, gain) => filter.or(gain),
+        }
+    }
+
+    /// Pan matrix mixing the 5.1 layout down to the stream channels
+    fn downmix_matrix(&self) -> Option<String> {
+        let downmix = self.downmix.as_ref()?;
+        let gain = |db: Option<f64>, default: f64| 10f64.powf(db.unwrap_or(default) / 20.0);
+        let pan = gain(downmix.pan_law_db, DEFAULT_DOWNMIX_DB);
+        let center = gain(downmix.center_db, DEFAULT_DOWNMIX_DB);
+        let surround = gain(downmix.surround_db, DEFAULT_DOWNMIX_DB);
+        let lfe = downmix.lfe_db.map_or(0.0, |db| gain(Some(db), 0.0));
+
+        let matrix = if self.channels == 1 {
+            format!(
+                "mono|c0={:.4}*FL+{:.4}*FR+FC+{:.4}*BL+{:.4}*BR+{:.4}*LFE",
+                pan,
+                pan,
+                pan * surround,
+                pan * surround,
+                lfe
+            )
+        } else {
+            format!(
+                "stereo|FL=FL+{c:.4}*FC+{s:.4}*BL+{l:.4}*LFE|FR=FR+{c:.4}*FC+{s:.4}*BR+{l:.4}*LFE",
+                c = center,
+                s = surround,
+                l = lfe
+            )
+        };
+        Some(matrix)
+    }
+
+    /// Output gain of the encoder in dB
+    pub fn with_gain(mut self, gain_db: Option<f64>) -> Self {
+        self.gain_db = gain_db;
+        self
+    }
+
+    /// Encoder to use instead of the preferred one of the format, and its number of threads
+    pub fn with_encoder(mut self, encoder: Option<String>, threads: Option<u32>) -> Self {
+        self.encoder = encoder;
+        self.threads = threads;
+        self
+    }
+
+    /// Custom audio stages of the stream, applied before the encoder filters
+    pub fn with_stages(mut self, stages: AudioStages) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Filter chain of the encoder: the custom filters, the processing preset and the gain
+    fn filter_chain(&self) -> Option<String> {
+        let preset = self.processing.map(|preset| match preset {
+            ProcessingPreset::Broadcast => BROADCAST_FILTERS.to_string(),
+            ProcessingPreset::Gentle => GENTLE_FILTERS.to_string(),
+        });
+        let gain = self.gain_db.map(|db| format!("volume={}dB", db));
+        let chain: Vec<String> = [self.filters.clone(), preset, gain]
+            .into_iter()
+            .flatten()
+            .collect();
+        (!chain.is_empty()).then(|| chain.join(","))
+    }
+
+    /// Verifies that FFmpeg runs and has an encoder for the output format, preferring
+    /// libfdk_aac over the native AAC encoder if it is compiled in
+    pub fn check_ffmpeg_available(
+        &mut self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("FFmpeg available: {}", self.ffmpeg_version()?);
+
+        let encoders = self.available_encoders()?;
+        let codec = match &self.encoder {
+            Some(encoder) if encoders.contains(encoder) => encoder.clone(),
+            Some(encoder) => {
+                return Err(format!(
+                    "FFmpeg at {}: encoder '{}' is not compiled in",
+                    self.ffmpeg_path, encoder
+                )
+                .into())
+            }
+            None => select_codec(&self.format, &encoders)
+                .map_err(|e| format!("FFmpeg at {}: {}", self.ffmpeg_path, e))?
+                .to_string(),
+        };
+        debug!("Format '{}' is encoded with {}", self.format, codec);
+
+        // Only libfdk_aac and AudioToolbox encode the HE profiles
+        if let Some(profile) = self
+            .aac_profile
+            .filter(|p| *p != AacProfile::Lc && !HE_AAC_ENCODERS.contains(&codec.as_str()))
+        {
+            warn!(
+                "AAC profile {:?} needs libfdk_aac, which FFmpeg at {} lacks, falling back to AAC-LC",
+                profile, self.ffmpeg_path
+            );
+            self.aac_profile = Some(AacProfile::Lc);
+        }
+        self.codec = Some(codec);
+
+        if let Some(filters) = self.filter_chain() {
+            self.check_filters(&filters)?;
+        }
+        // Also fails if the resampler is missing in the FFmpeg build
+        if let Some(filter) = self.decoder_filter() {
+            self.check_filters(&filter)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the filter chain on a moment of silence, so a broken chain fails at startup
+    /// instead of killing the encoder
+    fn check_filters(&self, filters: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args([
+                "-hide_banner",
+                "-loglevel",
+                "error",
+                "-f",
+                "lavfi",
+                "-i",
+                &format!(
+                    "anullsrc=r={}:cl={}",
+                    self.sample_rate,
+                    if self.channels == 1 { "mono" } else { "stereo" }
+                ),
+                "-t",
+                "0.1",
+                "-af",
+                filters,
+                "-f",
+                "null",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "Invalid filter chain '{}': {}",
+                filters,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// First line of `ffmpeg -version`
+    pub fn ffmpeg_version(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         debug!("Checking FFmpeg availability at: {}", self.ffmpeg_path);
 
         let output = Command::new(&self.ffmpeg_path)
@@ -60,55 +429,68 @@ impl FFmpegProcessor {
         }
 
         let version_info = String::from_utf8_lossy(&output.stdout);
-        info!(
-            "FFmpeg available: {}",
-            version_info.lines().next().unwrap_or("Unknown version")
-        );
-
-        Ok(())
+        Ok(version_info
+            .lines()
+            .next()
+            .unwrap_or("Unknown version")
+            .to_string())
     }
 
-    pub fn start_conversion_process(
-        &self,
-        input_path: &Path,
-    ) -> Result<AudioProcess, Box<dyn std::error::Error + Send + Sync>> {
-        let input_str = input_path.to_str().unwrap();
-        self.start_conversion(input_str)
+    /// Encoder of the output format, the one found in the FFmpeg build once checked
+    fn codec(&self) -> &str {
+        match self.codec.as_ref().or(self.encoder.as_ref()) {
+            Some(codec) => codec,
+            None => self.get_codec_for_format(&self.format),
+        }
     }
 
-    pub fn start_conversion_from_url(
+    /// Encoders compiled into the FFmpeg build, as listed by `ffmpeg -encoders`
+    pub fn available_encoders(
         &self,
-        url: &str,
-    ) -> Result<AudioProcess, Box<dyn std::error::Error + Send + Sync>> {
-        self.start_conversion(url)
+    ) -> Result<HashSet<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-hide_banner", "-encoders"])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(format!("Failed to list FFmpeg encoders of {}", self.ffmpeg_path).into());
+        }
+
+        Ok(parse_encoders(&String::from_utf8_lossy(&output.stdout)))
     }
 
-    fn start_conversion(
+    /// Starts decoding a local file or URL to raw PCM in the sample format of the stream,
+    /// `gain_db` is the loudness correction of the track. With a `section` only that part of
+    /// the file is decoded.
+    fn start_decoder(
         &self,
         input: &str,
+        section: Option<&TrackSection>,
+        gain_db: Option<f64>,
     ) -> Result<AudioProcess, Box<dyn std::error::Error + Send + Sync>> {
-        info!("Starting FFmpeg conversion for: {}", input);
+        info!("Starting FFmpeg decoder for: {}", input);
 
         // Only check file existence for local files (not URLs)
-        if !input.starts_with("http://") && !input.starts_with("https://") {
+        if !is_stream_url(input) {
             let path = Path::new(input);
             if !path.exists() {
                 return Err(format!("Input file does not exist: {}", input).into());
             }
         }
 
-        let codec = self.get_codec_for_format(&self.format);
-
         let mut cmd = Command::new(&self.ffmpeg_path);
+        if let Some(section) = section {
+            cmd.args(section.input_args());
+        }
+        cmd.args(["-i", input]);
+        if let Some(filter) = self.track_filter(gain_db) {
+            cmd.args(["-af", &filter]);
+        }
         cmd.args([
-            "-i",
-            input,
             "-f",
-            &self.format,
+            "s16le",
             "-acodec",
-            codec,
-            "-ab",
-            &format!("{}k", self.bitrate),
+            "pcm_s16le",
             "-ar",
             &self.sample_rate.to_string(),
             "-ac",
@@ -117,55 +499,466 @@ impl FFmpegProcessor {
             "error",
             "-",
         ])
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stdin(Stdio::null());
 
-        debug!("FFmpeg command: {:?}", cmd);
+        debug!("FFmpeg decoder command: {:?}", cmd);
 
-        let child = cmd.spawn()?;
+        let process =
+            SupervisedProcess::spawn(&mut cmd, &format!("decoder of {}", input), DECODER_TIMEOUTS)?;
 
-        Ok(AudioProcess::new(child))
+        Ok(AudioProcess::new(process))
     }
 
+    /// Starts decoding a track and reads the audio needed for the transition into it
+    fn start_track(
+        &self,
+        queued: QueuedTrack,
+        format: PcmFormat,
+    ) -> Result<StartedTrack, Box<dyn std::error::Error + Send + Sync>> {
+        let input = match &queued.section {
+            Some(section) => section.file.to_str(),
+            None => queued.path.to_str(),
+        };
+        let mut process =
+            self.start_decoder(input.unwrap_or(""), queued.section.as_ref(), queued.gain_db)?;
+        let transition = queued.transition.unwrap_or(self.transition);
+        // At least one chunk, so a prestarted track has audio ready even without a transition
+        let (head, result) = process.read_at_least(transition.head_bytes(format).max(1));
+        Ok(StartedTrack {
+            transition,
+            process,
+            head,
+            result,
+            voice_over: queued.voice_over,
+            cue: queued.cue,
+        })
+    }
+
+    /// Starts decoding a voice to be played over the current track, the track plays without
+    /// it if the voice cannot be decoded
+    fn start_voice_over(
+        &self,
+        voice_over: VoiceOver,
+        format: PcmFormat,
+    ) -> Option<ActiveVoiceOver> {
+        match self.start_decoder(voice_over.path.to_str().unwrap_or(""), None, None) {
+            Ok(process) => Some(ActiveVoiceOver {
+                process,
+                mixer: VoiceOverMixer::new(voice_over.ducking, format),
+                voice: Vec::new(),
+                ended: false,
+            }),
+            Err(e) => {
+                error!("Failed to start voice over {:?}: {}", voice_over.path, e);
+                None
+            }
+        }
+    }
+
+    /// Starts the next track in the background while the current one plays. Streams from
+    /// URLs are not prestarted, their connection could time out before they are due.
+    fn prestart_track(&self, queued: QueuedTrack, format: PcmFormat) -> NextTrack {
+        if is_stream_url(queued.path.to_str().unwrap_or("")) {
+            return NextTrack::Queued(queued);
+        }
+
+        debug!("Prestarting decoder for {:?}", queued.path);
+        let path = queued.path.clone();
+        let playout = queued.playout;
+        let program = queued.program.clone();
+        let processor = self.clone();
+        let decoder = std::thread::spawn(move || processor.start_track(queued, format));
+        NextTrack::Prestarted(PrestartedTrack {
+            path,
+            playout,
+            program,
+            decoder,
+        })
+    }
+
+    /// Starts an encoder of the stream, which reads the mixed PCM audio from stdin.
+    /// A reader thread forwards the encoded audio to the output.
+    fn start_encoder(
+        &self,
+        output: &EncoderOutput,
+    ) -> Result<PcmEncoder, Box<dyn std::error::Error + Send + Sync>> {
+        let audio_tx = output.audio_tx.clone();
+        let codec = self.codec();
+        let sample_rate = self.sample_rate.to_string();
+        let channels = self.channels.to_string();
+
+        let mut cmd = Command::new(&self.ffmpeg_path);
+        cmd.args([
+            "-f",
+            "s16le",
+            "-ar",
+            &sample_rate,
+            "-ac",
+            &channels,
+            "-i",
+            "-",
+        ]);
+        if let Some(filters) = self.filter_chain() {
+            cmd.args(["-af", &filters]);
+        }
+        cmd.args(["-f", &self.format, "-acodec", codec])
+            .args(self.profile_args(codec))
+            .args(self.rate_control_args(codec, output.bitrate))
+            .args(self.thread_args());
+        cmd.args([
+            "-ar",
+            &sample_rate,
+            "-ac",
+            &channels,
+            "-loglevel",
+            "error",
+            "-",
+        ])
+        .stdin(Stdio::piped());
+
+        debug!("FFmpeg encoder command: {:?}", cmd);
+
+        let process = SupervisedProcess::spawn(
+            &mut cmd,
+            &format!("{} encoder", self.format),
+            ENCODER_TIMEOUTS,
+        )?;
+        let stdin = process.take_stdin().ok_or("No stdin for FFmpeg encoder")?;
+        let mut stdout = process
+            .take_stdout()
+            .ok_or("No stdout for FFmpeg encoder")?;
+        let monitor = process.monitor();
+        let written = Arc::new(AtomicU64::new(0));
+        let position = Arc::clone(&written);
+        let mut splitter = HeaderSplitter::new(&self.format);
+
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; AUDIO_CHUNK_SIZE];
+            'read: loop {
+                match stdout.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(bytes_read) => {
+                        monitor.output_received();
+                        let timestamp = Duration::from_micros(position.load(Ordering::Relaxed));
+                        let (header, audio) = splitter.split(&buffer[..bytes_read]);
+                        let chunks = header
+                            .map(|data| (data, true))
+                            .into_iter()
+                            .chain(Some((audio, false)).filter(|(data, _)| !data.is_empty()));
+                        for (data, stream_header) in chunks {
+                            let audio_chunk = AudioChunk {
+                                data,
+                                timestamp,
+                                stream_header,
+                            };
+                            if audio_tx.send(audio_chunk).is_err() {
+                                warn!("Failed to send audio chunk - receiver dropped");
+                                break 'read;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error reading from FFmpeg encoder: {}", e);
+                        break;
+                    }
+                }
+            }
+            debug!("FFmpeg encoder output closed");
+        });
+
+        Ok(PcmEncoder {
+            process,
+            stdin,
+            written,
+        })
+    }
+
+    /// Transcodes a whole file into `output` with the encoder settings of this processor,
+    /// blocks until FFmpeg finished
+    pub fn transcode_file(
+        &self,
+        input: &Path,
+        output: &Path,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Transcoding {:?} to {}", input, self.format);
+
+        let codec = self.codec();
+        let mut cmd = Command::new(&self.ffmpeg_path);
+        cmd.arg("-i")
+            .arg(input)
+            .args(["-vn", "-f", &self.format, "-acodec", codec])
+            .args(self.profile_args(codec))
+            .args(self.rate_control_args(codec, self.bitrate))
+            .args(self.thread_args())
+            .args([
+                "-ar",
+                &self.sample_rate.to_string(),
+                "-ac",
+                &self.channels.to_string(),
+                "-loglevel",
+                "error",
+                "-y",
+            ])
+            .arg(output)
+            .stdin(Stdio::null());
+
+        debug!("FFmpeg transcode command: {:?}", cmd);
+
+        let result = cmd.output()?;
+        if !result.status.success() {
+            return Err(format!(
+                "FFmpeg failed to transcode {:?}: {}",
+                input,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Decodes tracks from `track_rx`, mixes the transitions between them and encodes the
+    /// result into each output in real time on `clock` until the returned handle is stopped.
+    /// The outputs share the decoding and mixing, only the encoders run once per output.
     pub fn start_streaming_service(
         self,
-        track_rx: Receiver<std::path::PathBuf>,
-    ) -> Receiver<AudioChunk> {
-        let (audio_tx, audio_rx) = unbounded::<AudioChunk>();
+        outputs: Vec<EncoderOutput>,
+        track_rx: Receiver<QueuedTrack>,
+        clock: StationClock,
+        quarantine: TrackQuarantine,
+        playout_control: PlayoutControl,
+    ) -> EncoderHandle {
+        let handle = EncoderHandle::default();
+        let stopped = Arc::clone(&handle.stopped);
+        let format = PcmFormat {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+        };
 
         tokio::spawn(async move {
+            let mut pacer = Pacer::new(clock, format);
+            let mut stages = self.stages.build(format);
+            let mut encoders: Vec<Option<PcmEncoder>> = outputs.iter().map(|_| None).collect();
             let mut current_process: Option<AudioProcess> = None;
             let mut current_track: Option<std::path::PathBuf> = None;
+            let mut current_playout = 0;
+            // Scheduled program of the current track
+            let mut current_program: Option<String> = None;
+            // Cue points of the current track and the audio decoded of it so far
+            let mut current_cue: Option<TrackCue> = None;
+            let mut decoded = 0;
+            let mut next_track: Option<NextTrack> = None;
+            // End of the current track, held back to be mixed with the start of the next one
+            let mut tail: VecDeque<u8> = VecDeque::new();
+            let mut tail_bytes = 0;
+            // Voice played over the current track with the music ducked
+            let mut voice_over: Option<ActiveVoiceOver> = None;
+            // Fade out of the current track, whose program ended
+            let mut fade_out: Option<FadeOut> = None;
 
             loop {
+                if stopped.load(Ordering::Relaxed) {
+                    if let Some(mut process) = current_process.take() {
+                        process.stop();
+                    }
+                    if let Some(active) = voice_over.take() {
+                        active.stop();
+                    }
+                    if let Some(track) = current_track.take() {
+                        info!("Encoder stopped while playing {:?}", track);
+                        playout_control.track_finished(&track);
+                    }
+                    for encoder in encoders.iter_mut().filter_map(Option::take) {
+                        encoder.stop();
+                    }
+                    break;
+                }
+
+                let mut failed = false;
+                for (encoder, output) in encoders.iter_mut().zip(&outputs) {
+                    if encoder.is_none() {
+                        match self.start_encoder(output) {
+                            Ok(started) => *encoder = Some(started),
+                            Err(e) => {
+                                error!(
+                                    "Failed to start FFmpeg encoder at {}kbps: {}",
+                                    output.bitrate, e
+                                );
+                                failed = true;
+                            }
+                        }
+                    }
+                }
+                if failed {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(
+                        ENCODER_RESTART_DELAY_MS,
+                    ))
+                    .await;
+                    continue;
+                }
+
                 // Start new process if needed
                 if current_process.is_none() {
-                    // Try to get next track
-                    if let Ok(track) = track_rx.try_recv() {
-                        current_track = Some(track.clone());
-
-                        // Check if track is a URL or local file
-                        let track_str = track.to_str().unwrap_or("");
-                        let result = if track_str.starts_with("http://")
-                            || track_str.starts_with("https://")
-                        {
-                            info!("Starting stream from URL: {}", track_str);
-                            self.start_conversion_from_url(track_str)
-                        } else {
-                            self.start_conversion_process(&track)
-                        };
-
-                        match result {
-                            Ok(process) => {
-                                info!("Started processing track: {:?}", track);
-                                current_process = Some(process);
+                    let upcoming = next_track
+                        .take()
+                        .or_else(|| track_rx.try_recv().ok().map(NextTrack::Queued));
+                    match upcoming {
+                        Some(next) => {
+                            let track = next.path().to_path_buf();
+                            let playout = next.playout();
+                            if playout_control.is_skipped(&track) {
+                                info!("Dropping skipped track: {:?}", track);
+                                continue;
                             }
-                            Err(e) => {
-                                error!("Failed to start FFmpeg process for {:?}: {}", track, e);
+                            let program = next.program().map(str::to_string);
+                            if let Some(name) = program
+                                .as_deref()
+                                .filter(|name| playout_control.program_end(name).is_some())
+                            {
+                                info!("Dropping track of ended program '{}': {:?}", name, track);
                                 continue;
                             }
+
+                            let started = match next {
+                                NextTrack::Queued(queued) => {
+                                    let track_str = track.to_str().unwrap_or("");
+                                    if is_stream_url(track_str) {
+                                        info!("Starting stream from URL: {}", track_str);
+                                    }
+                                    self.start_track(queued, format)
+                                }
+                                NextTrack::Prestarted(prestarted) => prestarted.join(),
+                            };
+                            let StartedTrack {
+                                transition,
+                                mut process,
+                                mut head,
+                                mut result,
+                                voice_over: next_voice_over,
+                                cue,
+                            } = match started {
+                                Ok(started) => started,
+                                Err(e) => {
+                                    error!("Failed to start FFmpeg process for {:?}: {}", track, e);
+                                    quarantine.report_failure(playout, &track, &e.to_string());
+                                    continue;
+                                }
+                            };
+                            info!("Started processing track: {:?}", track);
+                            playout_control.track_started(&track);
+
+                            // Mix the held back end of the previous track with the start of this one,
+                            // starting over its outro if it has cue points
+                            let previous: Vec<u8> = tail.drain(..).collect();
+                            let segue = current_cue
+                                .take()
+                                .filter(|_| !previous.is_empty())
+                                .and_then(|previous_cue| {
+                                    previous_cue.segue(format.duration_of(decoded), cue.as_ref())
+                                });
+                            let transition = segue.unwrap_or(transition);
+                            let needed = transition.head_bytes(format);
+                            if segue.is_some() && head.len() < needed && matches!(result, Ok(true))
+                            {
+                                let (rest, rest_result) =
+                                    process.read_at_least(needed - head.len());
+                                head.extend_from_slice(&rest);
+                                result = rest_result;
+                            }
+                            decoded = head.len();
+                            current_cue = cue;
+                            let mixed = transition.mix(&previous, &head, format);
+                            if let Some(next) = next_voice_over {
+                                if let Some(active) = voice_over.take() {
+                                    active.stop();
+                                }
+                                voice_over = self.start_voice_over(next, format);
+                            }
+                            let mixed = mix_voice_over(&mut voice_over, mixed);
+                            write_pcm(&mut encoders, &mut pacer, &mut stages, &mixed).await;
+                            tail_bytes = transition
+                                .tail_bytes(format)
+                                .max(self.transition.tail_bytes(format))
+                                .max(
+                                    current_cue
+                                        .and_then(|c| c.outro_hold())
+                                        .map_or(0, |hold| format.bytes_for(hold)),
+                                );
+
+                            match result {
+                                Ok(true) => {
+                                    current_track = Some(track);
+                                    current_playout = playout;
+                                    current_program = program;
+                                    current_process = Some(process);
+                                }
+                                Ok(false) => {
+                                    info!("Track processing completed: {:?}", track);
+                                    quarantine.report_success(playout, &track);
+                                    playout_control.track_finished(&track);
+                                }
+                                Err(e) => {
+                                    error!("Error reading from FFmpeg process: {}", e);
+                                    process.stop();
+                                    quarantine.report_failure(playout, &track, &e.to_string());
+                                    playout_control.track_finished(&track);
+                                    // Skip to the next track right away instead of waiting a poll cycle
+                                    continue;
+                                }
+                            }
+                        }
+                        None if !tail.is_empty() => {
+                            // Nothing queued, play out the held back audio instead of waiting
+                            let rest: Vec<u8> = tail.drain(..).collect();
+                            let rest = mix_voice_over(&mut voice_over, rest);
+                            write_pcm(&mut encoders, &mut pacer, &mut stages, &rest).await;
                         }
+                        None => {}
+                    }
+                }
+
+                // Stop the current track if it was skipped while playing
+                if let (Some(process), Some(track)) = (current_process.as_mut(), &current_track) {
+                    if playout_control.is_skipped(track) {
+                        info!("Stopping skipped track: {:?}", track);
+                        process.stop();
+                        playout_control.track_finished(track);
+                        tail.clear();
+                        fade_out = None;
+                        if let Some(active) = voice_over.take() {
+                            active.stop();
+                        }
+                        current_track = None;
+                        current_process = None;
+                    }
+                }
+
+                // Fade out or stop the current track once its program ended
+                if let (Some(process), Some(track), None) =
+                    (current_process.as_mut(), &current_track, &fade_out)
+                {
+                    let end = current_program
+                        .as_deref()
+                        .and_then(|name| playout_control.program_end(name));
+                    match end {
+                        Some(fade) if fade.is_zero() => {
+                            info!("Program ended, stopping track: {:?}", track);
+                            process.stop();
+                            playout_control.track_finished(track);
+                            tail.clear();
+                            if let Some(active) = voice_over.take() {
+                                active.stop();
+                            }
+                            current_track = None;
+                            current_process = None;
+                        }
+                        Some(fade) => {
+                            info!("Program ended, fading out track: {:?}", track);
+                            fade_out = Some(FadeOut::new(fade, format));
+                            // Nothing is mixed into the next track, play the held back end
+                            tail_bytes = 0;
+                        }
+                        None => {}
                     }
                 }
 
@@ -173,46 +966,295 @@ impl FFmpegProcessor {
                 if let Some(ref mut process) = current_process {
                     match process.read_chunk() {
                         Ok(Some(chunk)) => {
-                            let audio_chunk = AudioChunk { data: chunk };
-
-                            if audio_tx.send(audio_chunk).is_err() {
-                                warn!("Failed to send audio chunk - receiver dropped");
-                                break;
+                            decoded += chunk.len();
+                            tail.extend(chunk.iter());
+                            if tail.len() > tail_bytes {
+                                let ready = tail.len() - tail_bytes;
+                                let ready = ready - ready % format.frame_bytes();
+                                let pcm: Vec<u8> = tail.drain(..ready).collect();
+                                let pcm = mix_voice_over(&mut voice_over, pcm);
+                                match fade_out.as_mut() {
+                                    Some(fade) => {
+                                        write_pcm(
+                                            &mut encoders,
+                                            &mut pacer,
+                                            &mut stages,
+                                            &fade.apply(&pcm),
+                                        )
+                                        .await
+                                    }
+                                    None => {
+                                        write_pcm(&mut encoders, &mut pacer, &mut stages, &pcm)
+                                            .await
+                                    }
+                                }
+                            }
+                            if fade_out.as_ref().is_some_and(FadeOut::is_finished) {
+                                info!("Track faded out: {:?}", current_track);
+                                process.stop();
+                                if let Some(track) = current_track.take() {
+                                    playout_control.track_finished(&track);
+                                }
+                                if let Some(active) = voice_over.take() {
+                                    active.stop();
+                                }
+                                tail.clear();
+                                fade_out = None;
+                                current_process = None;
                             }
                         }
                         Ok(None) => {
                             // Process finished
                             info!("Track processing completed: {:?}", current_track);
+                            if let Some(track) = current_track.take() {
+                                quarantine.report_success(current_playout, &track);
+                                playout_control.track_finished(&track);
+                            }
+                            if fade_out.take().is_some() {
+                                tail.clear();
+                            }
                             current_process = None;
-                            current_track = None;
                         }
                         Err(e) => {
                             error!("Error reading from FFmpeg process: {}", e);
+                            if let Some(track) = current_track.take() {
+                                quarantine.report_failure(current_playout, &track, &e.to_string());
+                                playout_control.track_finished(&track);
+                            }
+                            fade_out = None;
                             current_process = None;
-                            current_track = None;
+                            continue;
                         }
                     }
                 }
 
+                // Decode the start of the next track while this one plays, so it starts without delay
+                if current_process.is_some() && next_track.is_none() {
+                    if let Ok(queued) = track_rx.try_recv() {
+                        next_track = Some(self.prestart_track(queued, format));
+                    }
+                }
+
                 // Small delay to avoid busy waiting
                 tokio::time::sleep(tokio::time::Duration::from_millis(PROCESS_POLL_INTERVAL_MS))
                     .await;
             }
         });
 
-        audio_rx
+        handle
+    }
+}
+
+/// Writes mixed audio to the encoders in real time, dropping an encoder if it died so it
+/// gets restarted
+async fn write_pcm(
+    encoders: &mut [Option<PcmEncoder>],
+    pacer: &mut Pacer,
+    stages: &mut StageChain,
+    pcm: &[u8],
+) {
+    let pcm = stages.apply(pcm);
+    let start = pacer.pace(pcm.len()).await;
+    for encoder in encoders {
+        let Some(running) = encoder.as_mut() else {
+            continue;
+        };
+        running
+            .written
+            .store(start.as_micros() as u64, Ordering::Relaxed);
+        match running.stdin.write_all(&pcm) {
+            Ok(()) => running.process.expect_output(),
+            Err(e) => {
+                let reason = if running.process.timed_out() {
+                    "it stopped producing audio".to_string()
+                } else {
+                    e.to_string()
+                };
+                error!(
+                    "Failed to write to FFmpeg encoder, restarting it: {}",
+                    reason
+                );
+                if let Some(failed) = encoder.take() {
+                    failed.stop();
+                }
+            }
+        }
+    }
+}
+
+/// Mixes the active voice over into the audio, ending it once the music is back at full level
+fn mix_voice_over(voice_over: &mut Option<ActiveVoiceOver>, pcm: Vec<u8>) -> Vec<u8> {
+    let Some(active) = voice_over.as_mut() else {
+        return pcm;
+    };
+    let mut mixed = active.mix(&pcm);
+    if active.mixer.is_finished() {
+        if let Some(finished) = voice_over.take() {
+            mixed.extend(finished.into_rest());
+        }
+    }
+    mixed
+}
+
+fn is_stream_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Decoder of a track with the audio at its start, mixed with the end of the previous track
+struct StartedTrack {
+    transition: Transition,
+    process: AudioProcess,
+    head: Vec<u8>,
+    /// Result of reading the head, `false` if the track already ended
+    result: Result<bool, Box<dyn std::error::Error + Send + Sync>>,
+    voice_over: Option<VoiceOver>,
+    cue: Option<TrackCue>,
+}
+
+/// Voice decoded alongside the current track and mixed over the ducked music
+struct ActiveVoiceOver {
+    process: AudioProcess,
+    mixer: VoiceOverMixer,
+    /// Decoded voice not mixed yet
+    voice: Vec<u8>,
+    /// Whether the voice decoder finished
+    ended: bool,
+}
+
+impl ActiveVoiceOver {
+    fn mix(&mut self, music: &[u8]) -> Vec<u8> {
+        let needed = self.mixer.voice_bytes_for(music.len());
+        if !self.ended && self.voice.len() < needed {
+            let (data, result) = self.process.read_at_least(needed - self.voice.len());
+            self.voice.extend_from_slice(&data);
+            match result {
+                Ok(true) => {}
+                Ok(false) => self.ended = true,
+                Err(e) => {
+                    error!("Error reading voice over, playing the music alone: {}", e);
+                    self.process.stop();
+                    self.ended = true;
+                }
+            }
+        }
+        let voice: Vec<u8> = self.voice.drain(..needed.min(self.voice.len())).collect();
+        self.mixer.mix(music, &voice)
+    }
+
+    /// Stops the voice decoder, returns the audio the mixer held back
+    fn into_rest(mut self) -> Vec<u8> {
+        self.process.stop();
+        self.mixer.into_rest()
+    }
+
+    /// Cuts the voice off, e.g. when its track was skipped
+    fn stop(mut self) {
+        self.process.stop();
+    }
+}
+
+/// Decoder started in the background, its process is stopped when dropped before it plays
+struct PrestartedTrack {
+    path: PathBuf,
+    playout: u64,
+    program: Option<String>,
+    decoder: JoinHandle<Result<StartedTrack, Box<dyn std::error::Error + Send + Sync>>>,
+}
+
+impl PrestartedTrack {
+    /// Waits until the start of the track is decoded
+    fn join(self) -> Result<StartedTrack, Box<dyn std::error::Error + Send + Sync>> {
+        self.decoder
+            .join()
+            .unwrap_or_else(|_| Err("FFmpeg decoder thread panicked".into()))
+    }
+}
+
+/// Track to play after the current one
+enum NextTrack {
+    Queued(QueuedTrack),
+    Prestarted(PrestartedTrack),
+}
+
+impl NextTrack {
+    fn path(&self) -> &Path {
+        match self {
+            NextTrack::Queued(queued) => &queued.path,
+            NextTrack::Prestarted(prestarted) => &prestarted.path,
+        }
+    }
+
+    fn playout(&self) -> u64 {
+        match self {
+            NextTrack::Queued(queued) => queued.playout,
+            NextTrack::Prestarted(prestarted) => prestarted.playout,
+        }
+    }
+
+    fn program(&self) -> Option<&str> {
+        match self {
+            NextTrack::Queued(queued) => queued.program.as_deref(),
+            NextTrack::Prestarted(prestarted) => prestarted.program.as_deref(),
+        }
+    }
+}
+
+/// Long-running FFmpeg process encoding the mixed PCM audio of a stream, killed by its
+/// supervisor if it stops producing audio so that writing fails and it gets restarted
+struct PcmEncoder {
+    process: SupervisedProcess,
+    stdin: ChildStdin,
+    /// Station time of the audio last written, used to stamp the encoded chunks
+    written: Arc<AtomicU64>,
+}
+
+impl PcmEncoder {
+    fn stop(self) {
+        self.process.stop();
+    }
+}
+
+/// Stops a running encoder, e.g. to replace it with one using new settings
+#[derive(Clone, Default)]
+pub struct EncoderHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl EncoderHandle {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
     }
 }
 
+/// FFmpeg decoder of a single track, killed by its supervisor if it hangs
 pub struct AudioProcess {
-    child: Child,
+    process: SupervisedProcess,
     reader: Option<BufReader<std::process::ChildStdout>>,
 }
 
 impl AudioProcess {
-    fn new(mut child: Child) -> Self {
-        let reader = child.stdout.take().map(BufReader::new);
-        Self { child, reader }
+    fn new(process: SupervisedProcess) -> Self {
+        let reader = process.take_stdout().map(BufReader::new);
+        Self { process, reader }
+    }
+
+    /// Reads until at least `bytes` are available, returns `false` if the process finished
+    pub fn read_at_least(
+        &mut self,
+        bytes: usize,
+    ) -> (
+        Vec<u8>,
+        Result<bool, Box<dyn std::error::Error + Send + Sync>>,
+    ) {
+        let mut data = Vec::with_capacity(bytes);
+        while data.len() < bytes {
+            match self.read_chunk() {
+                Ok(Some(chunk)) => data.extend_from_slice(&chunk),
+                Ok(None) => return (data, Ok(false)),
+                Err(e) => return (data, Err(e)),
+            }
+        }
+        (data, Ok(true))
     }
 
     pub fn read_chunk(
@@ -221,13 +1263,17 @@ impl AudioProcess {
         if let Some(ref mut reader) = self.reader {
             let mut buffer = [0u8; AUDIO_CHUNK_SIZE];
 
+            self.process.expect_output();
             match reader.read(&mut buffer) {
                 Ok(0) => {
-                    // EOF reached
+                    // EOF reached, also when the supervisor killed a hung process
                     self.wait_for_completion()?;
                     Ok(None)
                 }
-                Ok(bytes_read) => Ok(Some(Bytes::copy_from_slice(&buffer[..bytes_read]))),
+                Ok(bytes_read) => {
+                    self.process.output_received();
+                    Ok(Some(Bytes::copy_from_slice(&buffer[..bytes_read])))
+                }
                 Err(e) => {
                     error!("Error reading from FFmpeg stdout: {}", e);
                     Err(e.into())
@@ -238,27 +1284,161 @@ impl AudioProcess {
         }
     }
 
+    /// Terminates FFmpeg without waiting for the track to end
+    pub fn stop(&mut self) {
+        self.process.stop();
+    }
+
     fn wait_for_completion(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        match self.child.wait() {
-            Ok(status) => {
-                if status.success() {
-                    debug!("FFmpeg process completed successfully");
-                } else {
-                    warn!("FFmpeg process exited with status: {}", status);
-                }
-                Ok(())
+        let status = self.process.wait()?;
+        if self.process.timed_out() {
+            return Err(format!(
+                "FFmpeg produced no audio in time and was killed: {}",
+                self.process.stderr_tail()
+            )
+            .into());
+        }
+
+        if status.success() {
+            debug!("FFmpeg process completed successfully");
+            Ok(())
+        } else {
+            warn!("FFmpeg process exited with status: {}", status);
+            Err(format!(
+                "FFmpeg exited with status {}: {}",
+                status,
+                self.process.stderr_tail()
+            )
+            .into())
+        }
+    }
+}
+
+/// AAC encoders supporting the HE-AAC profiles, AudioToolbox is the hardware encoder on macOS
+const HE_AAC_ENCODERS: [&str; 2] = ["libfdk_aac", "aac_at"];
+
+/// Encoders able to produce a format, in order of preference
+fn codec_candidates(format: &str) -> &'static [&'static str] {
+    match format {
+        "mp3" => &["libmp3lame"],
+        "opus" => &["libopus"],
+        "aac" => &["libfdk_aac", "aac"],
+        "vorbis" | "ogg" => &["libvorbis"],
+        "flac" => &["flac"],
+        _ => &["libmp3lame"],
+    }
+}
+
+/// Preferred encoder of a format among the encoders of an FFmpeg build
+pub fn select_codec(format: &str, encoders: &HashSet<String>) -> Result<&'static str, String> {
+    let candidates = codec_candidates(format);
+    candidates
+        .iter()
+        .find(|codec| encoders.contains(**codec))
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "no encoder for format '{}' compiled in, it needs to be built with {}",
+                format,
+                candidates.join(" or ")
+            )
+        })
+}
+
+/// Names of the encoders in the output of `ffmpeg -encoders`, listed below a `------` line
+fn parse_encoders(list: &str) -> HashSet<String> {
+    list.lines()
+        .skip_while(|line| !line.trim_start().starts_with("------"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+/// HTTP content type of a stream format
+pub fn content_type(format: &str) -> &'static str {
+    match format {
+        "mp3" => "audio/mpeg",
+        "aac" => "audio/aac",
+        "opus" | "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Separates the stream header from the start of the encoder output. Listeners joining
+/// later need it before any audio, e.g. the FLAC stream info with the sample format.
+struct HeaderSplitter {
+    /// Output collected until the header is complete, `None` once split or without header
+    pending: Option<Vec<u8>>,
+}
+
+impl HeaderSplitter {
+    fn new(format: &str) -> Self {
+        Self {
+            pending: (format == "flac").then(Vec::new),
+        }
+    }
+
+    /// Returns the header once it is complete and the audio following it
+    fn split(&mut self, data: &[u8]) -> (Option<Bytes>, Bytes) {
+        let Some(mut pending) = self.pending.take() else {
+            return (None, Bytes::copy_from_slice(data));
+        };
+
+        pending.extend_from_slice(data);
+        match flac_header_len(&pending) {
+            None if pending.len() < MAX_STREAM_HEADER_BYTES => {
+                self.pending = Some(pending);
+                (None, Bytes::new())
             }
-            Err(e) => {
-                error!("Error waiting for FFmpeg process: {}", e);
-                Err(e.into())
+            None => {
+                warn!("No complete stream header in encoder output, passing it on as audio");
+                (None, Bytes::from(pending))
+            }
+            Some(len) => {
+                let mut audio = Bytes::from(pending);
+                let header = audio.split_to(len);
+                (Some(header).filter(|h| !h.is_empty()), audio)
             }
         }
     }
 }
 
+/// Length of the FLAC header at the start of `data`: the `fLaC` marker and the metadata
+/// blocks up to the last one. `None` while incomplete, `Some(0)` if the data has no header.
+fn flac_header_len(data: &[u8]) -> Option<usize> {
+    const MARKER: &[u8] = b"fLaC";
+    if !MARKER.starts_with(&data[..data.len().min(MARKER.len())]) {
+        return Some(0);
+    }
+
+    // Each block starts with a last-block flag and type byte and a 24 bit length
+    let mut pos = MARKER.len();
+    loop {
+        let block = data.get(pos..pos + 4)?;
+        let length = u32::from_be_bytes([0, block[1], block[2], block[3]]) as usize;
+        pos += 4 + length;
+        if block[0] & 0x80 != 0 {
+            return (pos <= data.len()).then_some(pos);
+        }
+    }
+}
+
+/// Encoder of the streaming service, feeding the buffer of one mount
+pub struct EncoderOutput {
+    /// Bitrate in kbps
+    pub bitrate: u32,
+    pub audio_tx: Sender<AudioChunk>,
+}
+
 #[derive(Debug, Clone)]
 pub struct AudioChunk {
     pub data: Bytes,
+    /// Station time at which this audio plays, taken from the encoder input
+    pub timestamp: Duration,
+    /// Codec header sent to each listener before the audio instead of being streamed
+    pub stream_header: bool,
 }
 
 #[cfg(test)]
@@ -306,4 +1486,274 @@ mod tests {
         let processor = FFmpegProcessor::new(None, 48000, 192, 2, "unknown".to_string());
         assert_eq!(processor.get_codec_for_format("unknown"), "libmp3lame");
     }
+
+    #[test]
+    fn given_encoder_list_when_parsing_then_returns_encoder_names() {
+        let list = "Encoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D libmp3lame           libmp3lame MP3 (MPEG audio layer 3)
+";
+
+        let encoders = parse_encoders(list);
+
+        assert_eq!(encoders.len(), 3);
+        assert!(encoders.contains("libmp3lame"));
+        assert!(!encoders.contains("V....."));
+    }
+
+    #[test]
+    fn given_encoder_list_when_selecting_codec_then_prefers_compiled_in_encoder() {
+        let native: HashSet<String> = ["aac", "libmp3lame"].map(String::from).into();
+        let fdk: HashSet<String> = ["aac", "libfdk_aac"].map(String::from).into();
+
+        assert_eq!(select_codec("aac", &native), Ok("aac"));
+        assert_eq!(select_codec("aac", &fdk), Ok("libfdk_aac"));
+        assert_eq!(select_codec("mp3", &native), Ok("libmp3lame"));
+        assert!(select_codec("opus", &native)
+            .unwrap_err()
+            .contains("libopus"));
+    }
+
+    #[test]
+    fn given_encoding_mode_when_building_encoder_args_then_controls_bitrate() {
+        let processor = |format: &str, mode, quality| {
+            FFmpegProcessor::new(None, 44100, 128, 2, format.to_string())
+                .with_encoding_mode(Some(mode), quality)
+        };
+
+        assert_eq!(
+            processor("mp3", EncodingMode::Cbr, None).rate_control_args("libmp3lame", 128),
+            ["-ab", "128k"]
+        );
+        assert_eq!(
+            processor("mp3", EncodingMode::Vbr, Some(2.0)).rate_control_args("libmp3lame", 128),
+            ["-q:a", "2"]
+        );
+        assert_eq!(
+            processor("opus", EncodingMode::Vbr, None).rate_control_args("libopus", 128),
+            ["-ab", "128k", "-vbr", "on"]
+        );
+        assert_eq!(
+            processor("opus", EncodingMode::Cbr, None).rate_control_args("libopus", 128),
+            ["-ab", "128k", "-vbr", "off"]
+        );
+        assert_eq!(
+            processor("aac", EncodingMode::Vbr, Some(4.0)).rate_control_args("libfdk_aac", 128),
+            ["-vbr", "4"]
+        );
+        assert_eq!(
+            processor("aac", EncodingMode::Vbr, Some(4.0)).rate_control_args("aac", 128),
+            ["-q:a", "1.6"]
+        );
+    }
+
+    #[test]
+    fn given_encoder_override_when_building_encoder_args_then_uses_it_with_threads() {
+        let processor = FFmpegProcessor::new(None, 44100, 128, 2, "aac".to_string())
+            .with_encoder(Some("aac_at".to_string()), Some(2));
+
+        assert_eq!(processor.codec(), "aac_at");
+        assert_eq!(processor.thread_args(), ["-threads", "2"]);
+        assert!(FFmpegProcessor::new(None, 44100, 128, 2, "mp3".to_string())
+            .thread_args()
+            .is_empty());
+    }
+
+    #[test]
+    fn given_flac_output_in_pieces_when_splitting_then_header_is_separated() {
+        // Marker, a stream info block and the last, a padding block, then the first frame
+        let mut output = b"fLaC".to_vec();
+        output.extend_from_slice(&[0x00, 0, 0, 34]);
+        output.extend_from_slice(&[1; 34]);
+        output.extend_from_slice(&[0x81, 0, 0, 8]);
+        output.extend_from_slice(&[0; 8]);
+        let header_len = output.len();
+        output.extend_from_slice(&[0xff, 0xf8, 7, 7]);
+
+        let mut splitter = HeaderSplitter::new("flac");
+        assert_eq!(splitter.split(&output[..20]), (None, Bytes::new()));
+        let (header, audio) = splitter.split(&output[20..]);
+
+        assert_eq!(header.unwrap(), output[..header_len]);
+        assert_eq!(audio, output[header_len..]);
+        assert_eq!(
+            splitter.split(b"frame"),
+            (None, Bytes::from_static(b"frame"))
+        );
+        assert_eq!(
+            HeaderSplitter::new("mp3").split(b"fLaC"),
+            (None, Bytes::from_static(b"fLaC"))
+        );
+    }
+
+    #[test]
+    fn given_he_aac_profile_when_building_encoder_args_then_only_fdk_gets_it() {
+        let processor = |profile| {
+            FFmpegProcessor::new(None, 44100, 32, 2, "aac".to_string())
+                .with_aac_profile(Some(profile))
+        };
+
+        assert_eq!(
+            processor(AacProfile::HeAacV2).profile_args("libfdk_aac"),
+            ["-profile:a", "aac_he_v2"]
+        );
+        assert_eq!(
+            processor(AacProfile::HeAac).profile_args("libfdk_aac"),
+            ["-profile:a", "aac_he"]
+        );
+        assert!(processor(AacProfile::HeAac).profile_args("aac").is_empty());
+        assert_eq!(
+            processor(AacProfile::HeAac).profile_args("aac_at"),
+            ["-profile:a", "aac_he"]
+        );
+        assert_eq!(
+            processor(AacProfile::Lc).profile_args("aac"),
+            ["-profile:a", "aac_low"]
+        );
+    }
+
+    #[test]
+    fn given_processing_preset_when_building_filter_chain_then_runs_after_custom_filters() {
+        let processor = FFmpegProcessor::new(None, 44100, 128, 2, "mp3".to_string())
+            .with_processing(Some(ProcessingPreset::Gentle));
+        assert_eq!(processor.filter_chain().as_deref(), Some(GENTLE_FILTERS));
+
+        let processor = processor.with_filters(Some("highpass=f=40".to_string()));
+        assert_eq!(
+            processor.filter_chain(),
+            Some(format!("highpass=f=40,{}", GENTLE_FILTERS))
+        );
+
+        let processor = processor.with_processing(None);
+        assert_eq!(processor.filter_chain().as_deref(), Some("highpass=f=40"));
+
+        let processor = processor.with_gain(Some(-1.5));
+        assert_eq!(
+            processor.filter_chain().as_deref(),
+            Some("highpass=f=40,volume=-1.5dB")
+        );
+    }
+
+    #[test]
+    fn given_downmix_when_building_decoder_filter_then_folds_surround_into_stream_channels() {
+        let downmix = DownmixConfig {
+            pan_law_db: None,
+            center_db: None,
+            surround_db: Some(-6.0),
+            lfe_db: None,
+        };
+
+        let stereo = FFmpegProcessor::new(None, 44100, 128, 2, "mp3".to_string())
+            .with_downmix(Some(downmix.clone()));
+        assert_eq!(
+            stereo.decoder_filter().unwrap(),
+            "aresample=ocl=5.1,pan=stereo|FL=FL+0.7079*FC+0.5012*BL+0.0000*LFE\
+             |FR=FR+0.7079*FC+0.5012*BR+0.0000*LFE"
+        );
+
+        let mono =
+            FFmpegProcessor::new(None, 44100, 64, 1, "mp3".to_string()).with_downmix(Some(downmix));
+        assert_eq!(
+            mono.decoder_filter().unwrap(),
+            "aresample=ocl=5.1,pan=mono|c0=0.7079*FL+0.7079*FR+FC+0.3548*BL+0.3548*BR+0.0000*LFE"
+        );
+
+        let default = FFmpegProcessor::new(None, 44100, 64, 1, "mp3".to_string());
+        assert!(default.decoder_filter().is_none());
+    }
+
+    #[test]
+    fn given_soxr_resampler_when_building_decoder_filter_then_selects_it_with_precision() {
+        let processor = FFmpegProcessor::new(None, 44100, 128, 2, "mp3".to_string())
+            .with_resampler(Some(Resampler::Soxr), Some(28));
+        assert_eq!(
+            processor.decoder_filter().unwrap(),
+            "aresample=resampler=soxr:precision=28"
+        );
+
+        let processor = processor.with_downmix(Some(DownmixConfig {
+            pan_law_db: None,
+            center_db: None,
+            surround_db: None,
+            lfe_db: None,
+        }));
+        assert!(processor
+            .decoder_filter()
+            .unwrap()
+            .starts_with("aresample=ocl=5.1:resampler=soxr:precision=28,pan=stereo|"));
+    }
+
+    #[test]
+    fn given_loudness_correction_when_building_track_filter_then_applies_it_after_resampling() {
+        let processor = FFmpegProcessor::new(None, 44100, 128, 2, "mp3".to_string());
+        assert_eq!(
+            processor.track_filter(Some(-4.5)).unwrap(),
+            "volume=-4.50dB"
+        );
+        assert!(processor.track_filter(None).is_none());
+
+        let processor = processor.with_resampler(Some(Resampler::Soxr), None);
+        assert_eq!(
+            processor.track_filter(Some(3.0)).unwrap(),
+            "aresample=resampler=soxr,volume=3.00dB"
+        );
+        assert_eq!(
+            processor.track_filter(None).unwrap(),
+            "aresample=resampler=soxr"
+        );
+    }
+
+    #[test]
+    fn given_flac_format_when_building_encoder_args_then_has_no_bitrate() {
+        let processor = FFmpegProcessor::new(None, 48000, 1000, 2, "flac".to_string());
+        assert!(processor.rate_control_args("flac", 1000).is_empty());
+    }
+
+    #[test]
+    fn given_local_next_track_when_prestarted_then_start_is_decoded_in_background() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stands in for FFmpeg, writes the same audio for every input
+        let dir = tempfile::TempDir::new().unwrap();
+        let ffmpeg = dir.path().join("ffmpeg");
+        std::fs::write(&ffmpeg, "#!/bin/sh\nprintf pcm-audio\n").unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let processor = FFmpegProcessor::new(
+            Some(ffmpeg.to_string_lossy().to_string()),
+            44100,
+            128,
+            2,
+            "mp3".to_string(),
+        );
+        let format = PcmFormat {
+            sample_rate: 44100,
+            channels: 2,
+        };
+        let queued = |path: PathBuf| QueuedTrack {
+            path,
+            playout: 1,
+            transition: None,
+            voice_over: None,
+            program: None,
+            cue: None,
+            gain_db: None,
+            section: None,
+        };
+
+        let next = processor.prestart_track(queued(ffmpeg.clone()), format);
+        let NextTrack::Prestarted(prestarted) = next else {
+            panic!("local track was not prestarted");
+        };
+        let started = prestarted.join().unwrap();
+        assert_eq!(started.head, b"pcm-audio");
+        assert!(started.result.unwrap());
+
+        let url = PathBuf::from("https://example.com/liveset.mp3");
+        let next = processor.prestart_track(queued(url), format);
+        assert!(matches!(next, NextTrack::Queued(_)));
+    }
 }
@@ -1,12 +1,38 @@
+use crate::ad_breaks;
+use crate::announcements::Announcements;
+use crate::artist_names::ArtistSeparation;
 use crate::audio_metadata::TrackMetadata;
+use crate::clock_alignment::ClockAligner;
+use crate::config::PodcastEpisodeSelection;
+use crate::cue_sheet::TrackSection;
+use crate::dayparting::{self, Dayparting, GenreTags};
+use crate::genre_rotation::GenreRotation;
 use crate::hearthis_client::{HearthisClient, HearthisTrack};
-use crate::library_db::LibraryDatabase;
-use crate::schedule_engine::PlaylistCommand;
+use crate::http_client::HttpClientFactory;
+use crate::jingles::Jingles;
+use crate::library_db::{LibraryDatabase, PlaySource, TrackRecord};
+use crate::loudness_analysis::normalization_gain;
+use crate::notifier::{Notifier, WebhookEvent};
+use crate::play_history::PlayHistory;
+use crate::playout_control::PlayoutControl;
+use crate::podcast_feed::{PodcastClient, PodcastEpisode};
+use crate::program_intros::ProgramIntros;
+use crate::program_stats::ProgramStats;
+use crate::rotation_weights::weighted_rotation;
+use crate::schedule_engine::{
+    BreakItem, PlaylistCommand, SourceRecovery, VoiceBreak, VoiceBreakTrigger,
+};
+use crate::shuffle_memory::ShuffleMemory;
+use crate::station_id::StationId;
+use crate::station_metadata::StationMetadata;
+use crate::tempo_analysis::{closest_tempo, TrackTempo, TEMPO_CANDIDATES};
+use crate::track_cache::TrackCache;
+use crate::transitions::{ProgramTransitions, TrackCue, Transition, VoiceOver};
 use chrono::Duration;
-use crossbeam_channel::{bounded, Receiver};
-use log::{error, info};
-use std::collections::VecDeque;
-use std::path::PathBuf;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 // Constants for audio reader configuration
@@ -16,17 +42,76 @@ const SCHEDULE_CHECK_INTERVAL_MS: u64 = 100; // How often to check for schedule
 #[derive(Debug, Clone)]
 enum PlaylistSource {
     Library,
-    Scheduled { end_time: std::time::Instant },
+    Scheduled {
+        name: String,
+        started: std::time::Instant,
+        end_time: std::time::Instant,
+    },
 }
 
-// Struct to track pending liveset fetch requests
+// Struct to track pending liveset and podcast fetch requests
 #[derive(Debug)]
-struct PendingLiveset {
+struct PendingProgram {
     name: String,
     duration: Duration,
+    transitions: ProgramTransitions,
+    source: ProgramSource,
+    recovery: SourceRecovery,
+    /// Retries of the fetch so far
+    attempt: u32,
+    requested: std::time::Instant,
+}
+
+impl PendingProgram {
+    /// Time left of the program, shorter than its duration if the fetch took a while
+    fn remaining(&self) -> Duration {
+        self.duration - Duration::from_std(self.requested.elapsed()).unwrap_or(Duration::MAX)
+    }
+}
+
+/// Where a liveset or podcast program gets its track from
+#[derive(Debug, Clone)]
+enum ProgramSource {
+    /// Genres of a random liveset, in the order of the genre rotation
+    Liveset(Vec<String>),
+    Podcast {
+        feed_url: String,
+        episode: PodcastEpisodeSelection,
+    },
+}
+
+/// Track of a liveset or podcast program fetched by an async task
+#[derive(Debug)]
+enum FetchedTrack {
+    /// Liveset and the genre it was found for
+    Liveset(HearthisTrack, Option<String>),
+    Podcast(PodcastEpisode),
+}
+
+type FetchResult = (PendingProgram, Result<FetchedTrack, String>);
+
+/// A track handed to the stream encoders
+#[derive(Debug, Clone)]
+pub struct QueuedTrack {
+    pub path: PathBuf,
+    /// Number of the playout, the same in every stream playing the track
+    pub playout: u64,
+    /// Transition into this track, the stream transition is used if not set
+    pub transition: Option<Transition>,
+    /// Voice played over the start of this track
+    pub voice_over: Option<VoiceOver>,
+    /// Scheduled program the track belongs to, `None` for library tracks
+    pub program: Option<String>,
+    /// Intro and outro of the track, for segues into and out of it
+    pub cue: Option<TrackCue>,
+    /// Loudness correction in dB applied by the decoder of the track
+    pub gain_db: Option<f64>,
+    /// Part of the album file played for a track of a single-file album, `path` is the library
+    /// path of the track then
+    pub section: Option<TrackSection>,
 }
 
-fn shuffle_playlist(playlist: &mut VecDeque<PathBuf>) {
+pub fn shuffle_playlist(playlist: &mut VecDeque<PathBuf>) {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
 
@@ -42,6 +127,61 @@ fn shuffle_playlist(playlist: &mut VecDeque<PathBuf>) {
     *playlist = playlist_vec.into_iter().collect();
 }
 
+/// A pass of the library rotation: shuffled and weighted by the track ratings, or in library
+/// order without shuffle
+fn library_rotation(
+    library: &[PathBuf],
+    shuffle: bool,
+    track_cache: &TrackCache,
+) -> VecDeque<PathBuf> {
+    if shuffle {
+        weighted_rotation(library, |track| track_cache.rating(track))
+    } else {
+        library.iter().cloned().collect()
+    }
+}
+
+/// Library rotation interrupted at `index` updated to the current library: removed tracks are
+/// dropped and new ones added after the last track of the rotation. A rotation interrupted at
+/// its end starts over.
+fn resume_rotation(
+    rotation: VecDeque<PathBuf>,
+    index: usize,
+    library: &[PathBuf],
+    shuffle: bool,
+) -> (VecDeque<PathBuf>, usize) {
+    let available: HashSet<&PathBuf> = library.iter().collect();
+    let mut resumed_index = 0;
+    let mut playlist = VecDeque::with_capacity(library.len());
+    for (position, track) in rotation.into_iter().enumerate() {
+        if available.contains(&track) {
+            if position < index {
+                resumed_index += 1;
+            }
+            playlist.push_back(track);
+        }
+    }
+
+    let known: HashSet<&PathBuf> = playlist.iter().collect();
+    let mut added: VecDeque<PathBuf> = library
+        .iter()
+        .filter(|track| !known.contains(track))
+        .cloned()
+        .collect();
+    if shuffle {
+        shuffle_playlist(&mut added);
+    }
+    playlist.extend(added);
+
+    if resumed_index >= playlist.len() {
+        resumed_index = 0;
+        if shuffle {
+            shuffle_playlist(&mut playlist);
+        }
+    }
+    (playlist, resumed_index)
+}
+
 pub struct AudioReader {
     library_shuffle: bool,
     library_repeat: bool,
@@ -49,7 +189,55 @@ pub struct AudioReader {
     current_index: usize,
     current_metadata: Arc<Mutex<TrackMetadata>>,
     playlist_source: PlaylistSource,
+    pending_break: VecDeque<BreakItem>,
+    /// Voice breaks of the running program that are not yet due
+    voice_breaks: Vec<VoiceBreak>,
+    /// Due voice breaks waiting for the next program track to be played over
+    voice_overs: VecDeque<VoiceOver>,
+    /// Voice over of the track selected last
+    next_voice_over: Option<VoiceOver>,
+    /// Tracks of the running program handed out so far
+    program_tracks: u32,
+    /// Transition of the running program
+    program_transition: Option<Transition>,
+    /// Crossfade of the running program back into the library
+    program_fade_out: Option<Transition>,
+    /// Transition into the next track handed out, set when switching between library and
+    /// program
+    switch_transition: Option<Transition>,
+    durations: HashMap<PathBuf, u64>,
+    /// Library loaded last, played while the database is unavailable
+    library_tracks: Vec<PathBuf>,
+    /// Library rotation and position interrupted by a program, resumed when it ends
+    paused_library: Option<(VecDeque<PathBuf>, usize)>,
+    aligner: Option<ClockAligner>,
     db: LibraryDatabase,
+    track_cache: TrackCache,
+    playout_control: PlayoutControl,
+    notifier: Option<Notifier>,
+    http: HttpClientFactory,
+    genre_rotation: GenreRotation,
+    jingles: Option<Jingles>,
+    program_stats: Option<ProgramStats>,
+    station_metadata: Option<StationMetadata>,
+    program_intros: Option<ProgramIntros>,
+    station_id: Option<StationId>,
+    announcements: Option<Announcements>,
+    shuffle_memory: Option<ShuffleMemory>,
+    artist_separation: Option<ArtistSeparation>,
+    play_history: Option<PlayHistory>,
+    dayparting: Option<Dayparting>,
+    /// Integrated loudness in LUFS measured tracks are normalized to
+    loudness_target: Option<f64>,
+    /// Orders the library rotation by tempo, with the tempo of the library track played last
+    tempo_matching: Option<TrackTempo>,
+    /// Genres of library tracks, for programs falling back to library tracks of genres
+    genre_tags: GenreTags,
+    /// Liveset or podcast program whose track is being fetched, fetches of other programs
+    /// are outdated
+    awaited_program: Option<String>,
+    /// Tracks queued so far, numbers their playouts
+    queued_tracks: u64,
 }
 
 impl AudioReader {
@@ -58,8 +246,11 @@ impl AudioReader {
         shuffle: bool,
         repeat: bool,
         db: LibraryDatabase,
+        track_cache: TrackCache,
+        playout_control: PlayoutControl,
+        http: HttpClientFactory,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let tracks = db.get_all_tracks()?;
+        let tracks = db.get_playable_tracks()?;
 
         if tracks.is_empty() {
             return Err("No tracks found in library database".into());
@@ -67,14 +258,12 @@ impl AudioReader {
 
         info!("Loaded {} tracks from database", tracks.len());
 
-        let mut playlist: VecDeque<PathBuf> = tracks
+        let durations = Self::track_durations(&tracks);
+        let library_tracks: Vec<PathBuf> = tracks
             .into_iter()
             .map(|t| PathBuf::from(t.file_path))
             .collect();
-
-        if shuffle {
-            shuffle_playlist(&mut playlist);
-        }
+        let playlist = library_rotation(&library_tracks, shuffle, &track_cache);
 
         Ok(Self {
             library_shuffle: shuffle,
@@ -83,28 +272,507 @@ impl AudioReader {
             current_index: 0,
             current_metadata: Arc::new(Mutex::new(TrackMetadata::default())),
             playlist_source: PlaylistSource::Library,
+            pending_break: VecDeque::new(),
+            voice_breaks: Vec::new(),
+            voice_overs: VecDeque::new(),
+            next_voice_over: None,
+            program_tracks: 0,
+            program_transition: None,
+            program_fade_out: None,
+            switch_transition: None,
+            durations,
+            library_tracks,
+            paused_library: None,
+            aligner: None,
+            genre_rotation: GenreRotation::new(db.clone()),
             db,
+            track_cache,
+            playout_control,
+            notifier: None,
+            http,
+            jingles: None,
+            program_stats: None,
+            station_metadata: None,
+            program_intros: None,
+            station_id: None,
+            announcements: None,
+            shuffle_memory: None,
+            artist_separation: None,
+            play_history: None,
+            dayparting: None,
+            loudness_target: None,
+            tempo_matching: None,
+            genre_tags: GenreTags::new(),
+            awaited_program: None,
+            queued_tracks: 0,
         })
     }
 
-    pub fn get_current_metadata(&self) -> Arc<Mutex<TrackMetadata>> {
-        Arc::clone(&self.current_metadata)
+    /// Selects library tracks by duration so announced programs start on time
+    pub fn enable_clock_alignment(&mut self, tolerance_seconds: u64) {
+        self.aligner = Some(ClockAligner::new(tolerance_seconds));
+    }
+
+    /// Plays jingles between library tracks
+    pub fn enable_jingles(&mut self, jingles: Jingles) {
+        self.jingles = Some(jingles);
+    }
+
+    /// Plays the station identification at the track boundary closest to each full hour
+    pub fn enable_station_id(&mut self, station_id: StationId) {
+        self.station_id = Some(station_id);
+    }
+
+    /// Plays spoken announcements of library tracks after them
+    pub fn enable_announcements(&mut self, announcements: Announcements) {
+        self.announcements = Some(announcements);
+    }
+
+    /// Skips library tracks played within the memory window, plays of programs count as well
+    pub fn enable_shuffle_memory(&mut self, shuffle_memory: ShuffleMemory) {
+        self.shuffle_memory = Some(shuffle_memory);
+    }
+
+    /// Stores every item going on air in the play history
+    /// Skips library tracks by an artist of the last `tracks` library tracks
+    pub fn enable_artist_separation(&mut self, tracks: u32) {
+        self.artist_separation = Some(ArtistSeparation::new(tracks));
+    }
+
+    pub fn enable_play_history(&mut self, play_history: PlayHistory) {
+        self.play_history = Some(play_history);
+    }
+
+    /// Limits the library rotation to the genres of the dayparts
+    pub fn enable_dayparting(&mut self, dayparting: Dayparting) {
+        self.dayparting = Some(dayparting);
+    }
+
+    /// Plays the library track closest in tempo and energy to the previous one among the next
+    /// few of the rotation, for smoother transitions
+    pub fn enable_tempo_matching(&mut self) {
+        self.tempo_matching = Some(TrackTempo::default());
+    }
+
+    /// Corrects the level of tracks measured by the loudness analysis to the target loudness
+    pub fn enable_loudness_normalization(&mut self, target_lufs: f64) {
+        self.loudness_target = Some(target_lufs);
+    }
+
+    /// Sends track change and program start/end webhook events
+    pub fn enable_webhooks(&mut self, notifier: Notifier) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Collects the listener audience of each program
+    pub fn enable_program_stats(&mut self, program_stats: ProgramStats) {
+        self.program_stats = Some(program_stats);
+    }
+
+    /// Applies the station metadata overrides of each program while it plays
+    pub fn enable_station_metadata(&mut self, station_metadata: StationMetadata) {
+        self.station_metadata = Some(station_metadata);
+    }
+
+    /// Plays the intro of each program right before its first track
+    pub fn enable_program_intros(&mut self, program_intros: ProgramIntros) {
+        self.program_intros = Some(program_intros);
+    }
+
+    fn notify(&self, event: WebhookEvent) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(event);
+        }
+    }
+
+    /// Sends the end event of the running program and stores its audience, if any
+    fn end_program(&self) {
+        if let PlaylistSource::Scheduled { name, .. } = &self.playlist_source {
+            self.notify(WebhookEvent::ProgramEnd { name: name.clone() });
+            if let Some(program_stats) = &self.program_stats {
+                program_stats.program_ended();
+            }
+            if let Some(station_metadata) = &self.station_metadata {
+                station_metadata.program_ended();
+            }
+        }
+    }
+
+    fn program_name(&self) -> Option<String> {
+        match &self.playlist_source {
+            PlaylistSource::Scheduled { name, .. } => Some(name.clone()),
+            PlaylistSource::Library => None,
+        }
+    }
+
+    fn track_durations(tracks: &[TrackRecord]) -> HashMap<PathBuf, u64> {
+        tracks
+            .iter()
+            .filter_map(|t| {
+                let duration = u64::try_from(t.duration_seconds?).ok()?;
+                Some((PathBuf::from(&t.file_path), duration))
+            })
+            .collect()
+    }
+
+    /// Writes the metadata of the playing track into `current_metadata`, e.g. the one the
+    /// server was started with
+    pub fn publish_metadata_to(&mut self, current_metadata: Arc<Mutex<TrackMetadata>>) {
+        self.current_metadata = current_metadata;
     }
 
     pub fn next_track(&mut self) -> Option<PathBuf> {
-        if self.playlist.is_empty() {
+        let now = chrono::Local::now();
+        if let Some(station_id) = self.station_id.as_mut().and_then(|s| s.next_due(now)) {
+            self.set_current_metadata(&station_id, PlaySource::StationId);
+            return Some(station_id);
+        }
+
+        if let Some(announcement) = self.due_announcement() {
+            info!("Playing announcement {:?}", announcement);
+            self.set_current_metadata(&announcement, PlaySource::Announcement);
+            return Some(announcement);
+        }
+
+        self.queue_due_voice_breaks();
+
+        if let Some(item) = self.pending_break.pop_front() {
+            self.set_current_metadata(&item.path, PlaySource::Break);
+            ad_breaks::report_impressions(&self.http, item.impression_urls);
+            return Some(item.path);
+        }
+
+        if let Some(jingle) = self.due_jingle() {
+            info!("Playing jingle {:?}", jingle);
+            self.set_current_metadata(&jingle, PlaySource::Jingle);
+            return Some(jingle);
+        }
+
+        // Bound the attempts so a playlist consisting only of quarantined tracks cannot loop forever
+        let max_attempts = self.playlist.len();
+        // Played recently, by a recent artist or outside of the daypart, but better than nothing
+        // if all other tracks are skipped too
+        let mut fallback = None;
+
+        for _ in 0..max_attempts {
+            self.match_next_tempo();
+            self.align_next_track();
+            let track = self.advance()?;
+
+            if self.track_cache.is_quarantined(&track) {
+                info!("Skipping quarantined track: {:?}", track);
+                continue;
+            }
+
+            if self.playout_control.is_skipped(&track) {
+                info!("Skipping removed track: {:?}", track);
+                continue;
+            }
+
+            if matches!(self.playlist_source, PlaylistSource::Library)
+                && self
+                    .shuffle_memory
+                    .as_ref()
+                    .is_some_and(|memory| memory.is_recent(&track))
+            {
+                debug!("Skipping recently played track: {:?}", track);
+                fallback.get_or_insert(track);
+                continue;
+            }
+
+            if matches!(self.playlist_source, PlaylistSource::Library)
+                && self.artist_separation.as_ref().is_some_and(|separation| {
+                    self.track_cache
+                        .artist(&track)
+                        .is_some_and(|artist| separation.is_recent(&artist))
+                })
+            {
+                debug!("Skipping track of a recently played artist: {:?}", track);
+                fallback.get_or_insert(track);
+                continue;
+            }
+
+            if matches!(self.playlist_source, PlaylistSource::Library)
+                && self.dayparting.as_mut().is_some_and(|d| {
+                    !d.allows(
+                        &track,
+                        &self.track_cache.tempo(&track),
+                        chrono::Local::now().time(),
+                    )
+                })
+            {
+                debug!("Skipping track outside of the daypart: {:?}", track);
+                fallback.get_or_insert(track);
+                continue;
+            }
+
+            return Some(self.track_selected(track));
+        }
+
+        fallback.map(|track| {
+            info!(
+                "All tracks were played recently, are by a recent artist or outside of the daypart, playing {:?}",
+                track
+            );
+            self.track_selected(track)
+        })
+    }
+
+    /// Counts the track as played and makes it the current track
+    fn track_selected(&mut self, track: PathBuf) -> PathBuf {
+        let source = match self.playlist_source {
+            PlaylistSource::Scheduled { .. } => {
+                self.program_tracks += 1;
+                self.next_voice_over = self.voice_overs.pop_front();
+                PlaySource::Program
+            }
+            PlaylistSource::Library => {
+                if let Some(jingles) = self.jingles.as_mut() {
+                    jingles.track_played();
+                }
+                if let Some(announcements) = self.announcements.as_mut() {
+                    announcements.track_played(&track);
+                }
+                if let Some(previous) = self.tempo_matching.as_mut() {
+                    *previous = self.track_cache.tempo(&track);
+                }
+                if let Some(separation) = self.artist_separation.as_mut() {
+                    separation.track_played(self.track_cache.artist(&track).as_deref());
+                }
+                PlaySource::Library
+            }
+        };
+        if let Some(memory) = self.shuffle_memory.as_mut() {
+            memory.track_played(&track);
+        }
+
+        self.set_current_metadata(&track, source);
+        track
+    }
+
+    /// Jingle due before the next library track, programs are never interrupted by jingles
+    fn due_jingle(&mut self) -> Option<PathBuf> {
+        if !matches!(self.playlist_source, PlaylistSource::Library) {
             return None;
         }
+        self.jingles.as_mut()?.next_due(std::time::Instant::now())
+    }
 
-        let track = self.playlist.get(self.current_index).cloned();
+    /// Announcement of the library track played last, dropped if a program started since
+    fn due_announcement(&mut self) -> Option<PathBuf> {
+        let announcement = self.announcements.as_mut()?.next_due();
+        announcement.filter(|_| matches!(self.playlist_source, PlaylistSource::Library))
+    }
+
+    /// Moves voice breaks of the running program whose trigger is reached into the pending break
+    fn queue_due_voice_breaks(&mut self) {
+        let PlaylistSource::Scheduled { started, .. } = self.playlist_source else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        let program_tracks = self.program_tracks;
+
+        let (due, pending): (Vec<VoiceBreak>, Vec<VoiceBreak>) = self
+            .voice_breaks
+            .drain(..)
+            .partition(|voice_break| match voice_break.trigger {
+                VoiceBreakTrigger::AfterTrack(tracks) => program_tracks >= tracks,
+                VoiceBreakTrigger::Offset(offset) => {
+                    offset.to_std().is_ok_and(|offset| elapsed >= offset)
+                }
+            });
+        self.voice_breaks = pending;
+
+        for voice_break in due {
+            let Some(ducking) = voice_break.ducking else {
+                info!("Playing voice break {:?}", voice_break.path);
+                self.pending_break.push_back(BreakItem {
+                    path: voice_break.path,
+                    impression_urls: Vec::new(),
+                });
+                continue;
+            };
+            info!(
+                "Playing voice break {:?} over the next track",
+                voice_break.path
+            );
+            self.voice_overs.push_back(VoiceOver {
+                path: voice_break.path,
+                ducking,
+            });
+        }
+    }
+
+    /// Moves the track that best fills the time until the next program to the front of the rotation
+    /// Moves the track closest in tempo to the library track played last among the next ones of
+    /// the rotation to the front, nothing moves after an unanalyzed track
+    fn match_next_tempo(&mut self) {
+        if !matches!(self.playlist_source, PlaylistSource::Library) {
+            return;
+        }
+        let Some(previous) = self.tempo_matching else {
+            return;
+        };
+
+        let candidates: Vec<TrackTempo> = self
+            .playlist
+            .iter()
+            .skip(self.current_index)
+            .take(TEMPO_CANDIDATES)
+            .map(|track| self.track_cache.tempo(track))
+            .collect();
+        if let Some(offset) = closest_tempo(&previous, &candidates).filter(|&o| o > 0) {
+            self.playlist
+                .swap(self.current_index, self.current_index + offset);
+            debug!(
+                "Selected {:?} as closest in tempo to the previous track",
+                self.playlist[self.current_index]
+            );
+        }
+    }
 
-        // Extract and store metadata for current track
-        if let Some(ref track_path) = track {
-            let metadata = TrackMetadata::from_file(track_path);
-            if let Ok(mut current) = self.current_metadata.lock() {
-                *current = metadata;
+    fn align_next_track(&mut self) {
+        if !matches!(self.playlist_source, PlaylistSource::Library) {
+            return;
+        }
+        let Some(aligner) = self.aligner.as_mut() else {
+            return;
+        };
+        let Some(remaining) = aligner.remaining_seconds(chrono::Local::now()) else {
+            return;
+        };
+
+        let candidates: Vec<Option<u64>> = self
+            .playlist
+            .iter()
+            .skip(self.current_index)
+            .map(|track| self.durations.get(track).copied())
+            .collect();
+
+        let selected = match aligner.pick(remaining, &candidates) {
+            Some(offset) => self.current_index + offset,
+            None => {
+                // Nothing left in this pass of the rotation fits, search the whole library
+                let max_fill = aligner.max_fill(remaining);
+                let Some(record) = self.select_track_for_window(max_fill) else {
+                    debug!("No track fits the {}s until the next program", remaining);
+                    return;
+                };
+                let track = PathBuf::from(&record.file_path);
+                self.durations.extend(Self::track_durations(&[record]));
+                match self.playlist.iter().position(|t| *t == track) {
+                    Some(index) => index,
+                    None => {
+                        self.playlist.push_back(track);
+                        self.playlist.len() - 1
+                    }
+                }
+            }
+        };
+
+        if selected != self.current_index {
+            if selected < self.current_index {
+                // Already played in this pass, play it again instead of taking it from history
+                let track = self.playlist[selected].clone();
+                self.playlist.insert(self.current_index, track);
+            } else {
+                self.playlist.swap(self.current_index, selected);
+            }
+            debug!(
+                "Selected {:?} to fill {}s until the next program",
+                self.playlist[self.current_index], remaining
+            );
+        }
+    }
+
+    /// Picks the playable library track that fills a window of `max_seconds` best, i.e. the
+    /// longest one not exceeding it. Tracks that are currently playing or removed are skipped.
+    pub fn select_track_for_window(&self, max_seconds: i64) -> Option<TrackRecord> {
+        const WINDOW_CANDIDATES: usize = 10;
+
+        self.track_cache
+            .tracks_fitting(max_seconds, WINDOW_CANDIDATES)
+            .into_iter()
+            .find(|t| {
+                let track = Path::new(&t.file_path);
+                !self.playout_control.is_playing(track) && !self.playout_control.is_skipped(track)
+            })
+    }
+
+    fn track_queued(&mut self, track: &Path) {
+        let duration = self.durations.get(track).copied();
+        let now = chrono::Local::now();
+        if let Some(aligner) = self.aligner.as_mut() {
+            aligner.track_queued(duration, now);
+        }
+        if let Some(station_id) = self.station_id.as_mut() {
+            station_id.track_queued(duration, now);
+        }
+    }
+
+    fn track_cue(&self, track: &Path) -> Option<TrackCue> {
+        let cue = match self.db.get_cue_points(&track.to_string_lossy()) {
+            Ok(cue) => cue?,
+            Err(e) => {
+                debug!("Failed to load cue points of {:?}: {}", track, e);
+                return None;
             }
+        };
+        let seconds =
+            |value: Option<f64>| value.map(|s| std::time::Duration::from_secs_f64(s.max(0.0)));
+        Some(TrackCue {
+            intro: seconds(cue.intro_seconds),
+            outro: seconds(cue.outro_seconds),
+            length: self
+                .durations
+                .get(track)
+                .map(|&secs| std::time::Duration::from_secs(secs)),
+        })
+    }
+
+    /// Gain bringing the track to the target loudness, `None` for unmeasured tracks
+    fn track_gain(&self, track: &Path) -> Option<f64> {
+        let target = self.loudness_target?;
+        normalization_gain(self.track_cache.loudness(track)?, target)
+    }
+
+    fn set_current_metadata(&self, track: &Path, source: PlaySource) {
+        // The tags of a single-file album describe the album, the scan read its tracks from
+        // the cue sheet
+        let mut metadata = match self.track_cache.track(track) {
+            Some(record) if record.start_seconds.is_some() => TrackMetadata::from_track(&record),
+            _ => TrackMetadata::from_file(track),
+        };
+        metadata.duration_seconds = metadata
+            .duration_seconds
+            .or_else(|| self.durations.get(track).copied());
+        metadata.started_at = Some(chrono::Local::now());
+        self.notify(WebhookEvent::TrackChange {
+            title: metadata.title.clone(),
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            duration_seconds: metadata.duration_seconds,
+        });
+        if let Some(play_history) = &self.play_history {
+            play_history.item_played(source, self.program_name(), &metadata);
+        }
+        if let Ok(mut current) = self.current_metadata.lock() {
+            *current = metadata;
         }
+    }
+
+    /// Queues break items to be played before the next playlist track
+    pub fn insert_break(&mut self, name: &str, items: Vec<BreakItem>) {
+        info!("Inserting break '{}' with {} item(s)", name, items.len());
+        self.pending_break.extend(items);
+    }
+
+    fn advance(&mut self) -> Option<PathBuf> {
+        if self.playlist.is_empty() {
+            return None;
+        }
+
+        let track = self.playlist.get(self.current_index).cloned();
 
         self.current_index += 1;
 
@@ -113,18 +781,20 @@ impl AudioReader {
                 PlaylistSource::Library => {
                     if self.library_repeat {
                         self.current_index = 0;
-                        if self.library_shuffle {
-                            shuffle_playlist(&mut self.playlist);
-                        }
+                        self.playlist = library_rotation(
+                            &self.library_tracks,
+                            self.library_shuffle,
+                            &self.track_cache,
+                        );
                     } else {
                         return None;
                     }
                 }
-                PlaylistSource::Scheduled { end_time } => {
+                PlaylistSource::Scheduled { end_time, .. } => {
                     if std::time::Instant::now() >= *end_time {
                         info!("Scheduled program ended, returning to library");
                         self.return_to_library();
-                        return self.next_track();
+                        return self.advance();
                     } else {
                         self.current_index = 0;
                     }
@@ -140,6 +810,8 @@ impl AudioReader {
         name: String,
         tracks: Vec<PathBuf>,
         duration: Duration,
+        voice_breaks: Vec<VoiceBreak>,
+        transitions: ProgramTransitions,
     ) {
         info!(
             "Switching to scheduled playlist '{}' with {} tracks",
@@ -147,144 +819,394 @@ impl AudioReader {
             tracks.len()
         );
 
+        self.end_program();
+        if matches!(self.playlist_source, PlaylistSource::Library) {
+            self.paused_library = Some((std::mem::take(&mut self.playlist), self.current_index));
+        }
+        self.playout_control.program_started(&name);
+        self.notify(WebhookEvent::ProgramStart { name: name.clone() });
+        if let Some(program_stats) = &self.program_stats {
+            program_stats.program_started(&name);
+        }
+        if let Some(station_metadata) = &self.station_metadata {
+            station_metadata.program_started(&name);
+        }
+        if let Some(intro) = self
+            .program_intros
+            .as_mut()
+            .and_then(|intros| intros.program_started(&name))
+        {
+            info!("Playing intro of program '{}': {:?}", name, intro);
+            self.pending_break.push_front(BreakItem {
+                path: intro,
+                impression_urls: Vec::new(),
+            });
+        }
+
         self.playlist = tracks.into_iter().collect();
         self.current_index = 0;
 
+        self.voice_breaks = voice_breaks;
+        self.voice_overs.clear();
+        self.program_tracks = 0;
+        self.program_transition = transitions.tracks;
+        self.program_fade_out = transitions.fade_out;
+        self.switch_transition = transitions.fade_in;
+
         let duration_std = std::time::Duration::from_secs(duration.num_seconds() as u64);
-        let end_time = std::time::Instant::now() + duration_std;
+        let started = std::time::Instant::now();
+        let end_time = started + duration_std;
 
-        self.playlist_source = PlaylistSource::Scheduled { end_time };
+        self.playlist_source = PlaylistSource::Scheduled {
+            name,
+            started,
+            end_time,
+        };
     }
 
-    pub fn return_to_library(&mut self) {
-        info!("Returning to library playlist");
-        self.playlist.clear();
+    /// Plays shuffled library tracks of the genres as the program, the fallback of a program
+    /// whose source is unavailable. The library rotation continues if no track matches.
+    pub fn play_genres(
+        &mut self,
+        name: String,
+        genres: &[String],
+        duration: Duration,
+        transitions: ProgramTransitions,
+    ) {
+        let wanted = dayparting::split_all(genres);
+        let mut tracks: VecDeque<PathBuf> = self
+            .library_tracks
+            .iter()
+            .filter(|track| self.genre_tags.matches(track, &wanted))
+            .cloned()
+            .collect();
+
+        if tracks.is_empty() {
+            warn!(
+                "No library tracks of {} for program '{}', continuing with library",
+                genres.join(", "),
+                name
+            );
+            return;
+        }
+
+        shuffle_playlist(&mut tracks);
+        self.switch_to_scheduled_playlist(name, tracks.into(), duration, Vec::new(), transitions);
+    }
+
+    /// Fetches the track of a liveset or podcast program after `delay` in an async task,
+    /// the result is sent to `tx`
+    fn fetch_program_track(
+        &self,
+        pending: PendingProgram,
+        delay: std::time::Duration,
+        tx: Sender<FetchResult>,
+    ) {
+        let http = self.http.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+
+            let result = match &pending.source {
+                ProgramSource::Liveset(genres) => match HearthisClient::new(&http) {
+                    Ok(client) => match client.get_random_liveset(genres).await {
+                        Ok((track, genre)) => {
+                            info!(
+                                "Fetched liveset: '{}' by {} ({})",
+                                track.title, track.user.username, track.genre
+                            );
+                            Ok(FetchedTrack::Liveset(track, genre))
+                        }
+                        Err(e) => Err(format!("API error: {}", e)),
+                    },
+                    Err(e) => Err(format!("Client error: {}", e)),
+                },
+                ProgramSource::Podcast { feed_url, episode } => match PodcastClient::new(&http) {
+                    Ok(client) => client
+                        .fetch_episode(feed_url, *episode)
+                        .await
+                        .map(FetchedTrack::Podcast)
+                        .map_err(|e| format!("Feed error: {}", e)),
+                    Err(e) => Err(format!("Client error: {}", e)),
+                },
+            };
+
+            // Send result back to main loop
+            if tx.send((pending, result)).is_err() {
+                error!("Failed to send program fetch result - receiver dropped");
+            }
+        });
+    }
 
-        match self.db.get_all_tracks() {
-            Ok(tracks) => {
-                if !tracks.is_empty() {
-                    self.playlist = tracks
-                        .into_iter()
-                        .map(|t| PathBuf::from(t.file_path))
-                        .collect();
+    /// Switches to the fetched track of a program, or retries the fetch and finally plays
+    /// the fallback of the program if it failed
+    fn program_track_fetched(
+        &mut self,
+        mut pending: PendingProgram,
+        result: Result<FetchedTrack, String>,
+        tx: &Sender<FetchResult>,
+    ) {
+        if self.awaited_program.as_deref() != Some(pending.name.as_str()) {
+            debug!(
+                "Dropping fetched track of program '{}', it is no longer due",
+                pending.name
+            );
+            return;
+        }
+        let remaining = pending.remaining();
+        if remaining <= Duration::zero() {
+            info!(
+                "Program '{}' ended while its track was fetched",
+                pending.name
+            );
+            self.awaited_program = None;
+            return;
+        }
 
-                    if self.library_shuffle {
-                        shuffle_playlist(&mut self.playlist);
+        let e = match result {
+            Ok(fetched) => {
+                self.awaited_program = None;
+                let track = match fetched {
+                    FetchedTrack::Liveset(track, genre) => {
+                        info!(
+                            "Liveset fetched successfully for program '{}': '{}' by {}",
+                            pending.name, track.title, track.user.username
+                        );
+                        if let Some(genre) = genre {
+                            self.genre_rotation.record(&pending.name, &genre);
+                        }
+                        // The stream URL is played as a track
+                        track.stream_url
                     }
+                    FetchedTrack::Podcast(episode) => {
+                        info!(
+                            "Podcast episode fetched for program '{}': '{}'",
+                            pending.name, episode.title
+                        );
+                        episode.url
+                    }
+                };
+                self.switch_to_scheduled_playlist(
+                    pending.name,
+                    vec![PathBuf::from(track)],
+                    remaining,
+                    Vec::new(),
+                    pending.transitions,
+                );
+                return;
+            }
+            Err(e) => e,
+        };
 
-                    self.current_index = 0;
-                    self.playlist_source = PlaylistSource::Library;
-                } else {
-                    error!("No tracks found in database when returning to library");
-                }
+        if pending.attempt < pending.recovery.retry_attempts {
+            pending.attempt += 1;
+            let delay = pending.recovery.delay(pending.attempt);
+            warn!(
+                "Failed to fetch the track of program '{}': {}. Retry {} of {} in {}s",
+                pending.name,
+                e,
+                pending.attempt,
+                pending.recovery.retry_attempts,
+                delay.as_secs()
+            );
+            self.fetch_program_track(pending, delay, tx.clone());
+            return;
+        }
+
+        self.awaited_program = None;
+        match pending.recovery.fallback_genres.clone() {
+            Some(genres) => {
+                error!(
+                    "Failed to fetch the track of program '{}': {}. Playing library tracks of {} instead.",
+                    pending.name,
+                    e,
+                    genres.join(", ")
+                );
+                self.play_genres(pending.name, &genres, remaining, pending.transitions);
+            }
+            None => error!(
+                "Failed to fetch the track of program '{}': {}. Continuing with library.",
+                pending.name, e
+            ),
+        }
+    }
+
+    pub fn return_to_library(&mut self) {
+        info!("Returning to library playlist");
+        self.end_program();
+        self.playlist.clear();
+        self.voice_breaks.clear();
+        self.voice_overs.clear();
+        self.program_transition = None;
+        self.switch_transition = self.program_fade_out.take();
+
+        match self.db.get_playable_tracks() {
+            Ok(tracks) if !tracks.is_empty() => {
+                self.durations = Self::track_durations(&tracks);
+                self.library_tracks = tracks
+                    .into_iter()
+                    .map(|t| PathBuf::from(t.file_path))
+                    .collect();
+            }
+            Ok(_) => {
+                warn!(
+                    "No tracks found in database when returning to library, playing the {} tracks loaded last",
+                    self.library_tracks.len()
+                );
             }
             Err(e) => {
-                error!("Failed to load tracks from database: {}", e);
+                warn!(
+                    "Failed to load tracks from database, playing the {} tracks loaded last: {}",
+                    self.library_tracks.len(),
+                    e
+                );
+            }
+        }
+
+        match self.paused_library.take() {
+            Some((rotation, index)) => {
+                let (playlist, index) =
+                    resume_rotation(rotation, index, &self.library_tracks, self.library_shuffle);
+                debug!(
+                    "Resuming library rotation at track {} of {}",
+                    index + 1,
+                    playlist.len()
+                );
+                self.playlist = playlist;
+                self.current_index = index;
+            }
+            None => {
+                self.playlist = library_rotation(
+                    &self.library_tracks,
+                    self.library_shuffle,
+                    &self.track_cache,
+                );
+                self.current_index = 0;
             }
         }
+        self.playlist_source = PlaylistSource::Library;
     }
 
     pub fn start_playlist_service(
         mut self,
-        schedule_command_rx: Option<Receiver<PlaylistCommand>>,
-    ) -> Receiver<PathBuf> {
+        command_rx: Receiver<PlaylistCommand>,
+    ) -> Receiver<QueuedTrack> {
         // Use bounded channel to keep tracks buffered ahead
         // This provides backpressure and prevents flooding the channel
-        let (track_tx, track_rx) = bounded::<PathBuf>(TRACK_BUFFER_SIZE);
+        let (track_tx, track_rx) = bounded::<QueuedTrack>(TRACK_BUFFER_SIZE);
 
-        // Channel for receiving fetched livesets from async tasks
-        let (liveset_tx, liveset_rx) =
-            bounded::<(PendingLiveset, Result<HearthisTrack, String>)>(1);
+        // Channel for receiving fetched livesets and podcast episodes from async tasks
+        let (fetch_tx, fetch_rx) = bounded::<FetchResult>(1);
 
         tokio::spawn(async move {
             loop {
                 // Check for schedule commands
-                if let Some(ref cmd_rx) = schedule_command_rx {
-                    match cmd_rx.try_recv() {
-                        Ok(PlaylistCommand::SwitchToPlaylist {
+                match command_rx.try_recv() {
+                    Ok(PlaylistCommand::SwitchToPlaylist {
+                        name,
+                        tracks,
+                        duration,
+                        voice_breaks,
+                        transitions,
+                    }) => {
+                        self.awaited_program = None;
+                        self.switch_to_scheduled_playlist(
                             name,
                             tracks,
                             duration,
-                        }) => {
-                            self.switch_to_scheduled_playlist(name, tracks, duration);
-                        }
-                        Ok(PlaylistCommand::SwitchToLiveset {
+                            voice_breaks,
+                            transitions,
+                        );
+                    }
+                    Ok(PlaylistCommand::SwitchToLiveset {
+                        name,
+                        genres,
+                        duration,
+                        transitions,
+                        recovery,
+                    }) => {
+                        // Fetch liveset from hearthis.at API asynchronously
+                        info!(
+                            "Fetching liveset for program '{}' (genres: {:?})",
+                            name, genres
+                        );
+
+                        let genres = self.genre_rotation.next_order(&name, &genres);
+                        self.awaited_program = Some(name.clone());
+                        let pending = PendingProgram {
                             name,
-                            genres,
                             duration,
-                        }) => {
-                            // Fetch liveset from hearthis.at API asynchronously
-                            info!(
-                                "Fetching liveset for program '{}' (genres: {:?})",
-                                name, genres
-                            );
+                            transitions,
+                            source: ProgramSource::Liveset(genres),
+                            recovery,
+                            attempt: 0,
+                            requested: std::time::Instant::now(),
+                        };
+                        self.fetch_program_track(
+                            pending,
+                            std::time::Duration::ZERO,
+                            fetch_tx.clone(),
+                        );
+                    }
+                    Ok(PlaylistCommand::SwitchToPodcast {
+                        name,
+                        feed_url,
+                        episode,
+                        duration,
+                        transitions,
+                        recovery,
+                    }) => {
+                        info!(
+                            "Fetching podcast episode for program '{}' from {}",
+                            name, feed_url
+                        );
 
-                            // Spawn async task to fetch liveset and send result back via channel
-                            let tx = liveset_tx.clone();
-                            let pending = PendingLiveset {
-                                name: name.clone(),
-                                duration,
-                            };
-
-                            tokio::spawn(async move {
-                                let result = match HearthisClient::new() {
-                                    Ok(client) => match client.get_random_liveset(&genres).await {
-                                        Ok(track) => {
-                                            info!(
-                                                "Fetched liveset: '{}' by {} ({})",
-                                                track.title, track.user.username, track.genre
-                                            );
-                                            Ok(track)
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to fetch liveset: {}", e);
-                                            Err(format!("API error: {}", e))
-                                        }
-                                    },
-                                    Err(e) => {
-                                        error!("Failed to create hearthis client: {}", e);
-                                        Err(format!("Client error: {}", e))
-                                    }
-                                };
-
-                                // Send result back to main loop
-                                if tx.send((pending, result)).is_err() {
-                                    error!("Failed to send liveset result - receiver dropped");
-                                }
-                            });
+                        self.awaited_program = Some(name.clone());
+                        let pending = PendingProgram {
+                            name,
+                            duration,
+                            transitions,
+                            source: ProgramSource::Podcast { feed_url, episode },
+                            recovery,
+                            attempt: 0,
+                            requested: std::time::Instant::now(),
+                        };
+                        self.fetch_program_track(
+                            pending,
+                            std::time::Duration::ZERO,
+                            fetch_tx.clone(),
+                        );
+                    }
+                    Ok(PlaylistCommand::SwitchToGenres {
+                        name,
+                        genres,
+                        duration,
+                        transitions,
+                    }) => {
+                        self.awaited_program = None;
+                        self.play_genres(name, &genres, duration, transitions);
+                    }
+                    Ok(PlaylistCommand::ReturnToLibrary) => {
+                        self.awaited_program = None;
+                        self.return_to_library();
+                    }
+                    Ok(PlaylistCommand::InsertBreak { name, items }) => {
+                        self.insert_break(&name, items);
+                    }
+                    Ok(PlaylistCommand::UpcomingProgram { name, start_time }) => {
+                        if let Some(intros) = self.program_intros.as_mut() {
+                            intros.program_announced(&name);
                         }
-                        Ok(PlaylistCommand::ReturnToLibrary) => {
-                            self.return_to_library();
+                        if let Some(aligner) = self.aligner.as_mut() {
+                            aligner.set_target(name, start_time);
                         }
-                        Err(_) => {}
                     }
+                    Err(_) => {}
                 }
 
-                // Check for liveset fetch results
-                if let Ok((pending, result)) = liveset_rx.try_recv() {
-                    match result {
-                        Ok(track) => {
-                            info!(
-                                "Liveset fetched successfully for program '{}': '{}' by {}",
-                                pending.name, track.title, track.user.username
-                            );
-
-                            // Switch to the liveset by treating the stream URL as a track
-                            let liveset_url = PathBuf::from(track.stream_url);
-                            self.switch_to_scheduled_playlist(
-                                pending.name,
-                                vec![liveset_url],
-                                pending.duration,
-                            );
-                        }
-                        Err(e) => {
-                            error!(
-                                "Failed to fetch liveset for program '{}': {}. Continuing with library.",
-                                pending.name, e
-                            );
-                            // Continue with library playback on error
-                        }
-                    }
+                // Check for liveset and podcast fetch results
+                if let Ok((pending, result)) = fetch_rx.try_recv() {
+                    self.program_track_fetched(pending, result, &fetch_tx);
                 }
 
                 // Get next track
@@ -295,14 +1217,29 @@ impl AudioReader {
                     // Blocking is moved to tokio blocking thread to avoid blocking async runtime
                     let result = tokio::task::spawn_blocking({
                         let track_tx = track_tx.clone();
-                        let track = track.clone();
-                        move || track_tx.send(track)
+                        self.queued_tracks += 1;
+                        let queued = QueuedTrack {
+                            path: track.clone(),
+                            playout: self.queued_tracks,
+                            transition: self.switch_transition.take().or(self.program_transition),
+                            voice_over: self.next_voice_over.take(),
+                            program: self.program_name(),
+                            cue: self.track_cue(&track),
+                            gain_db: self.track_gain(&track),
+                            section: self
+                                .track_cache
+                                .track(&track)
+                                .as_ref()
+                                .and_then(TrackSection::of),
+                        };
+                        // The unsent track is not needed, only whether the receiver is gone
+                        move || track_tx.send(queued).map_err(|_| ())
                     })
                     .await;
 
                     match result {
                         Ok(Ok(())) => {
-                            // Track sent successfully
+                            self.track_queued(&track);
                         }
                         Ok(Err(_)) => {
                             error!("Failed to send track to channel - receiver dropped");
@@ -333,3 +1270,30 @@ impl AudioReader {
         track_rx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn given_interrupted_rotation_when_resumed_then_continues_with_the_current_library() {
+        let rotation: VecDeque<PathBuf> = tracks(&["c", "a", "gone", "d", "b"]).into();
+        let library = tracks(&["a", "b", "c", "d", "new"]);
+
+        let (playlist, index) = resume_rotation(rotation.clone(), 3, &library, false);
+        assert_eq!(playlist, tracks(&["c", "a", "d", "b", "new"]));
+        assert_eq!(playlist[index], PathBuf::from("d"));
+
+        // New tracks play before the rotation starts over
+        let (playlist, index) = resume_rotation(rotation.clone(), 5, &library, false);
+        assert_eq!(playlist[index], PathBuf::from("new"));
+
+        let (playlist, index) = resume_rotation(rotation, 5, &library[..4], false);
+        assert_eq!(playlist.len(), 4);
+        assert_eq!(index, 0);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::transitions::{PcmFormat, PCM_SAMPLE_BYTES};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Custom processing of the mixed PCM audio of a stream, e.g. watermarking or an EQ.
+///
+/// Each stream encoder creates its own instances of the stages it uses, so a stage may keep
+/// state between the pieces of audio it is handed.
+pub trait AudioStage: Send {
+    /// Processes a piece of interleaved signed 16-bit samples in place
+    fn process(&mut self, samples: &mut [i16], format: PcmFormat);
+}
+
+/// Creates a stage for a stream with the given PCM format
+pub type StageFactory = Arc<dyn Fn(PcmFormat) -> Box<dyn AudioStage> + Send + Sync>;
+
+/// Named audio stages, streams pick theirs with the `stages` option
+#[derive(Clone, Default)]
+pub struct AudioStages {
+    factories: Vec<(String, StageFactory)>,
+}
+
+impl AudioStages {
+    /// Built-in stages available to the streams. Custom stages are added with
+    /// [`register`](Self::register) and handed to `station::run_with_stages`, they can then
+    /// be enabled per stream without changes to the audio processor.
+    pub fn registered() -> Self {
+        let mut stages = Self::default();
+        stages.register("dc_block", |format| Box::new(DcBlock::new(format)));
+        stages
+    }
+
+    /// Adds a stage under `name`, replacing a stage registered under the same name
+    ///
+    /// ```
+    /// use funkstrom::audio_stage::{AudioStage, AudioStages};
+    /// use funkstrom::transitions::PcmFormat;
+    ///
+    /// struct Invert;
+    ///
+    /// impl AudioStage for Invert {
+    ///     fn process(&mut self, samples: &mut [i16], _format: PcmFormat) {
+    ///         samples.iter_mut().for_each(|sample| *sample = -*sample);
+    ///     }
+    /// }
+    ///
+    /// let mut stages = AudioStages::registered();
+    /// stages.register("invert", |_| Box::new(Invert));
+    ///
+    /// let format = PcmFormat { sample_rate: 44100, channels: 1 };
+    /// let mut chain = stages.select(&["invert".to_string()]).unwrap().build(format);
+    /// let pcm: Vec<u8> = [1000i16, -5].iter().flat_map(|s| s.to_le_bytes()).collect();
+    /// let inverted: Vec<u8> = [-1000i16, 5].iter().flat_map(|s| s.to_le_bytes()).collect();
+    /// assert_eq!(chain.apply(&pcm).into_owned(), inverted);
+    /// ```
+    pub fn register(
+        &mut self,
+        name: &str,
+        factory: impl Fn(PcmFormat) -> Box<dyn AudioStage> + Send + Sync + 'static,
+    ) {
+        self.factories.retain(|(registered, _)| registered != name);
+        self.factories.push((name.to_string(), Arc::new(factory)));
+    }
+
+    /// The stages with the given names in the given order
+    pub fn select(&self, names: &[String]) -> Result<Self, String> {
+        let factories = names
+            .iter()
+            .map(|name| {
+                self.factories
+                    .iter()
+                    .find(|(registered, _)| registered == name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        let available: Vec<&str> = self
+                            .factories
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect();
+                        format!(
+                            "Unknown audio stage '{}'. Available: {}",
+                            name,
+                            available.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { factories })
+    }
+
+    /// Creates the stages for a stream
+    pub fn build(&self, format: PcmFormat) -> StageChain {
+        StageChain {
+            stages: self
+                .factories
+                .iter()
+                .map(|(_, factory)| factory(format))
+                .collect(),
+            format,
+        }
+    }
+}
+
+/// Stages of a running stream, applied in order
+pub struct StageChain {
+    stages: Vec<Box<dyn AudioStage>>,
+    format: PcmFormat,
+}
+
+impl StageChain {
+    pub fn apply<'a>(&mut self, pcm: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.stages.is_empty() {
+            return Cow::Borrowed(pcm);
+        }
+
+        let mut samples: Vec<i16> = pcm
+            .chunks_exact(PCM_SAMPLE_BYTES)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        for stage in &mut self.stages {
+            stage.process(&mut samples, self.format);
+        }
+        Cow::Owned(samples.iter().flat_map(|s| s.to_le_bytes()).collect())
+    }
+}
+
+/// Removes a DC offset with a one-pole high-pass filter below the audible range
+struct DcBlock {
+    /// Previous input and output sample of each channel
+    state: Vec<(f32, f32)>,
+    channel: usize,
+}
+
+impl DcBlock {
+    /// Pole of the filter, a cutoff of about 5 Hz at 44.1 kHz
+    const POLE: f32 = 0.9993;
+
+    fn new(format: PcmFormat) -> Self {
+        Self {
+            state: vec![(0.0, 0.0); format.channels.max(1) as usize],
+            channel: 0,
+        }
+    }
+}
+
+impl AudioStage for DcBlock {
+    fn process(&mut self, samples: &mut [i16], _format: PcmFormat) {
+        for sample in samples {
+            let (previous_in, previous_out) = &mut self.state[self.channel];
+            let input = *sample as f32;
+            let output = input - *previous_in + Self::POLE * *previous_out;
+            *previous_in = input;
+            *previous_out = output;
+            *sample = output.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            self.channel = (self.channel + 1) % self.state.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: PcmFormat = PcmFormat {
+        sample_rate: 44100,
+        channels: 2,
+    };
+
+    /// Adds a fixed offset to every sample
+    struct Offset(i16);
+
+    impl AudioStage for Offset {
+        fn process(&mut self, samples: &mut [i16], _format: PcmFormat) {
+            for sample in samples {
+                *sample += self.0;
+            }
+        }
+    }
+
+    fn pcm(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn given_selected_stages_when_applied_then_run_in_the_selected_order() {
+        let mut stages = AudioStages::default();
+        stages.register("plus_one", |_| Box::new(Offset(1)));
+        stages.register("plus_ten", |_| Box::new(Offset(10)));
+
+        let selected = stages
+            .select(&["plus_ten".to_string(), "plus_one".to_string()])
+            .unwrap();
+        let mut chain = selected.build(FORMAT);
+
+        assert_eq!(chain.apply(&pcm(&[0, 5])).into_owned(), pcm(&[11, 16]));
+    }
+
+    #[test]
+    fn given_unknown_stage_when_selected_then_lists_available_stages() {
+        let error = AudioStages::registered()
+            .select(&["watermark".to_string()])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error,
+            "Unknown audio stage 'watermark'. Available: dc_block"
+        );
+    }
+
+    #[test]
+    fn given_no_stages_when_applied_then_audio_passes_unchanged() {
+        let mut chain = AudioStages::default().build(FORMAT);
+        let audio = pcm(&[100, -100]);
+
+        assert!(matches!(chain.apply(&audio), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn given_dc_offset_when_dc_block_applied_then_offset_decays() {
+        let mut chain = AudioStages::registered()
+            .select(&["dc_block".to_string()])
+            .unwrap()
+            .build(FORMAT);
+
+        let output = chain.apply(&pcm(&[1000; 88200])).into_owned();
+        let last = i16::from_le_bytes([output[output.len() - 2], output[output.len() - 1]]);
+
+        assert_eq!(&output[..4], pcm(&[1000, 1000]).as_slice());
+        assert!(last.abs() < 10, "Offset left: {}", last);
+    }
+}
@@ -1,6 +1,19 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use std::path::PathBuf;
 
+pub enum CliCommand {
+    Serve,
+    ScheduleExport { output: Option<PathBuf> },
+    ScheduleImport { file: PathBuf, dry_run: bool },
+    SchedulePreview { days: u32 },
+    LibraryDuplicates,
+}
+
+pub struct CliArgs {
+    pub config_path: PathBuf,
+    pub command: CliCommand,
+}
+
 pub fn build_cli() -> Command {
     Command::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
@@ -11,11 +24,89 @@ pub fn build_cli() -> Command {
                 .long("config")
                 .value_name("FILE")
                 .help("Sets a custom config file")
-                .default_value("./data/config.toml"),
+                .default_value("./data/config.toml")
+                .global(true),
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("Manage the program schedule")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Export the schedule as JSON")
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .value_name("FILE")
+                                .help("Write to a file instead of stdout"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Validate and import a JSON schedule")
+                        .arg(Arg::new("file").value_name("FILE").required(true))
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .action(ArgAction::SetTrue)
+                                .help("Only validate and show the changes"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("preview")
+                        .about("Validate the programs and print their upcoming occurrences")
+                        .arg(
+                            Arg::new("days")
+                                .long("days")
+                                .value_name("DAYS")
+                                .value_parser(clap::value_parser!(u32).range(1..=366))
+                                .default_value("7")
+                                .help("Number of days to preview"),
+                        ),
+                ),
         )
+        .subcommand(
+            Command::new("library")
+                .about("Inspect the music library")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("duplicates")
+                        .about("List duplicate tracks found by the library scan"),
+                ),
+        )
+}
+
+pub fn parse_args() -> CliArgs {
+    args_from_matches(&build_cli().get_matches())
 }
 
-pub fn get_config_path() -> PathBuf {
-    let matches = build_cli().get_matches();
-    PathBuf::from(matches.get_one::<String>("config").unwrap())
+fn args_from_matches(matches: &ArgMatches) -> CliArgs {
+    let config_path = PathBuf::from(matches.get_one::<String>("config").unwrap());
+
+    let command = match matches.subcommand() {
+        Some(("schedule", schedule)) => match schedule.subcommand() {
+            Some(("export", export)) => CliCommand::ScheduleExport {
+                output: export.get_one::<String>("output").map(PathBuf::from),
+            },
+            Some(("import", import)) => CliCommand::ScheduleImport {
+                file: PathBuf::from(import.get_one::<String>("file").unwrap()),
+                dry_run: import.get_flag("dry-run"),
+            },
+            Some(("preview", preview)) => CliCommand::SchedulePreview {
+                days: *preview.get_one::<u32>("days").unwrap(),
+            },
+            _ => unreachable!("schedule subcommand is required"),
+        },
+        Some(("library", library)) => match library.subcommand() {
+            Some(("duplicates", _)) => CliCommand::LibraryDuplicates,
+            _ => unreachable!("library subcommand is required"),
+        },
+        _ => CliCommand::Serve,
+    };
+
+    CliArgs {
+        config_path,
+        command,
+    }
 }
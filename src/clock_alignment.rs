@@ -0,0 +1,146 @@
+use chrono::{DateTime, Local};
+use log::{debug, info};
+
+/// Default deviation from the program start that is accepted as aligned
+pub const DEFAULT_ALIGN_TOLERANCE_SECONDS: u64 = 10;
+
+/// Soft-aligns library rotation to the start of the next scheduled program,
+/// by selecting tracks whose durations fill the remaining time.
+pub struct ClockAligner {
+    tolerance: i64,
+    target: Option<(String, DateTime<Local>)>,
+    /// Estimated end of all audio already handed to playout
+    queued_until: Option<DateTime<Local>>,
+}
+
+impl ClockAligner {
+    pub fn new(tolerance_seconds: u64) -> Self {
+        Self {
+            tolerance: tolerance_seconds as i64,
+            target: None,
+            queued_until: None,
+        }
+    }
+
+    pub fn set_target(&mut self, name: String, start_time: DateTime<Local>) {
+        info!(
+            "Aligning library rotation to program '{}' at {}",
+            name,
+            start_time.format("%H:%M:%S")
+        );
+        self.target = Some((name, start_time));
+    }
+
+    /// Records a track handed to playout, tracks of unknown duration reset the estimate
+    pub fn track_queued(&mut self, duration_seconds: Option<u64>, now: DateTime<Local>) {
+        self.queued_until = duration_seconds.map(|duration| {
+            let start = self
+                .queued_until
+                .filter(|until| *until > now)
+                .unwrap_or(now);
+            start + chrono::Duration::seconds(duration as i64)
+        });
+    }
+
+    /// Seconds left to fill until the target program starts, `None` if not aligning
+    pub fn remaining_seconds(&mut self, now: DateTime<Local>) -> Option<i64> {
+        let (name, start_time) = self.target.as_ref()?;
+        let queued_until = self
+            .queued_until
+            .filter(|until| *until > now)
+            .unwrap_or(now);
+        let remaining = (*start_time - queued_until).num_seconds();
+
+        if remaining <= self.tolerance {
+            debug!("Alignment to '{}' finished, {}s remaining", name, remaining);
+            self.target = None;
+            return None;
+        }
+
+        Some(remaining)
+    }
+
+    /// Longest track duration that still ends within the tolerance of the program start
+    pub fn max_fill(&self, remaining: i64) -> i64 {
+        remaining + self.tolerance
+    }
+
+    /// Picks the index of the track to play next from the candidates in rotation order.
+    ///
+    /// The first candidate is kept if it ends the window within the tolerance or leaves
+    /// room for at least one more track. Otherwise the first candidate that does is
+    /// chosen, `None` if no candidate fits.
+    pub fn pick(&self, remaining: i64, candidates: &[Option<u64>]) -> Option<usize> {
+        let shortest = candidates.iter().flatten().min().copied().unwrap_or(0) as i64;
+
+        candidates.iter().position(|duration| match duration {
+            Some(duration) => {
+                let gap = remaining - *duration as i64;
+                gap.abs() <= self.tolerance || gap >= shortest
+            }
+            None => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        Local::now()
+            .date_naive()
+            .and_hms_opt(hour, minute, second)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    #[test]
+    fn given_queued_tracks_when_computing_remaining_then_subtracts_queued_audio() {
+        let mut aligner = ClockAligner::new(10);
+        aligner.set_target("News".to_string(), at(13, 0, 0));
+
+        aligner.track_queued(Some(240), at(12, 50, 0));
+
+        assert_eq!(aligner.remaining_seconds(at(12, 50, 0)), Some(360));
+    }
+
+    #[test]
+    fn given_remaining_within_tolerance_when_computing_remaining_then_alignment_ends() {
+        let mut aligner = ClockAligner::new(10);
+        aligner.set_target("News".to_string(), at(13, 0, 0));
+
+        assert_eq!(aligner.remaining_seconds(at(12, 59, 55)), None);
+        assert_eq!(aligner.remaining_seconds(at(12, 0, 0)), None);
+    }
+
+    #[test]
+    fn given_next_track_leaving_room_when_picking_then_keeps_rotation() {
+        let aligner = ClockAligner::new(10);
+
+        assert_eq!(
+            aligner.pick(600, &[Some(200), Some(180), Some(400)]),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn given_next_track_overrunning_when_picking_then_chooses_track_ending_on_time() {
+        let aligner = ClockAligner::new(10);
+
+        // 230s left: 300s overruns, 190s leaves a gap shorter than any track, 225s ends on time
+        assert_eq!(
+            aligner.pick(230, &[Some(300), Some(190), Some(225)]),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn given_no_track_fitting_when_picking_then_returns_none() {
+        let aligner = ClockAligner::new(10);
+
+        assert_eq!(aligner.pick(100, &[Some(300), Some(150), None]), None);
+        assert_eq!(aligner.max_fill(100), 110);
+    }
+}
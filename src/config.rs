@@ -1,7 +1,17 @@
+use crate::access_log::AccessLogFormat;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Longest configurable fade of a track transition
+const MAX_FADE_SECONDS: f64 = 30.0;
+/// Most attempts to load an unavailable program source again
+const MAX_RETRY_ATTEMPTS: u32 = 10;
+/// Longest wait before the first retry of an unavailable program source
+const MAX_RETRY_DELAY_SECONDS: u64 = 3600;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
@@ -10,6 +20,14 @@ pub struct Config {
     pub station: StationConfig,
     pub stream: HashMap<String, StreamConfig>,
     pub schedule: Option<ScheduleConfig>,
+    pub ads: Option<AdsConfig>,
+    pub ondemand: Option<OnDemandConfig>,
+    pub http: Option<HttpConfig>,
+    pub access: Option<AccessConfig>,
+    pub jingles: Option<JinglesConfig>,
+    pub station_id: Option<StationIdConfig>,
+    pub announcements: Option<AnnouncementsConfig>,
+    pub dayparts: Option<Vec<DaypartConfig>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -17,6 +35,18 @@ pub struct ServerConfig {
     pub port: u16,
     pub bind_address: String,
     pub ffmpeg_path: Option<String>,
+    pub access_log: Option<String>,
+    pub access_log_format: Option<String>,
+    pub anonymize_listener_ips: Option<bool>,
+    pub templates_dir: Option<String>,
+    pub standby: Option<bool>,
+    /// Accept HTTP/2 for API and UI requests, streams stay on HTTP/1.1
+    pub http2: Option<bool>,
+    /// Interval of the HTTP/2 pings detecting dead connections, disabled if unset
+    pub http2_keep_alive_seconds: Option<u64>,
+    /// Bearer token required by the admin API, i.e. every request changing the station and
+    /// everything below `/admin` and `/api/admin`. The admin API is disabled if unset
+    pub admin_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -24,6 +54,30 @@ pub struct LibraryConfig {
     pub music_directory: String,
     pub shuffle: bool,
     pub repeat: bool,
+    pub max_track_failures: Option<u32>,
+    pub inbox_directory: Option<String>,
+    /// Hours before a played track may be played again, disabled if unset
+    pub no_repeat_hours: Option<u32>,
+    /// Library tracks played before an artist may play again, disabled if unset
+    pub artist_separation: Option<u32>,
+    /// Days the play history is kept, 90 if unset
+    pub history_retention_days: Option<u32>,
+    /// Hash the decoded audio of scanned tracks to find copies differing only in their tags
+    pub audio_fingerprints: Option<bool>,
+    /// Decode the first second of new and changed files and quarantine unreadable ones
+    pub validate_files: Option<bool>,
+    /// Integrated loudness in LUFS tracks are normalized to, -40 to -5, e.g. -16. Measures the
+    /// loudness of all tracks in the background, tracks play unchanged if unset
+    pub loudness_target_lufs: Option<f64>,
+    /// Estimate tempo and energy of new and changed files, for dayparts and tempo matching
+    pub tempo_analysis: Option<bool>,
+    /// Play the library track closest in tempo and energy among the next ones of the rotation
+    pub tempo_matching: Option<bool>,
+    /// Incremental scans skip the files of directories whose modification time is unchanged
+    pub skip_unchanged_directories: Option<bool>,
+    /// Hours between incremental scans looking at every file with `skip_unchanged_directories`,
+    /// 24 if unset
+    pub reconcile_hours: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -32,19 +86,182 @@ pub struct StationConfig {
     pub description: String,
     pub genre: String,
     pub url: String,
+    /// Contact e-mail of the operator, identifies the station in outgoing requests
+    pub contact: Option<String>,
 }
 
 /// Configuration for an individual audio stream.
 ///
-/// Supported formats: mp3, aac, opus, ogg
+/// Supported formats: mp3, aac, opus, ogg, flac
 /// Stream names must contain only alphanumeric characters, underscores, or hyphens
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct StreamConfig {
+    /// Bitrate in kbps, not set if the stream has a `bitrates` ladder
+    #[serde(default)]
     pub bitrate: u32,
     pub format: String,
     pub sample_rate: u32,
     pub channels: u8,
     pub enabled: bool,
+    /// Transition between consecutive tracks of this stream, hard cut if not set
+    pub transition: Option<TransitionConfig>,
+    /// FFmpeg audio filter chain applied before encoding, passed as `-af`,
+    /// e.g. `"acompressor=threshold=-18dB:ratio=3,alimiter=limit=0.9"`
+    pub filters: Option<String>,
+    /// Constant or variable bitrate, the encoder defaults apply to `bitrate` if not set
+    pub mode: Option<EncodingMode>,
+    /// VBR quality of the encoder: mp3 0 (best) to 9, ogg -1 to 10 (best), aac 1 to 5 (best)
+    pub quality: Option<f64>,
+    /// Profile of `aac` streams, AAC-LC if not set
+    pub aac_profile: Option<AacProfile>,
+    /// Built-in processing preset applied after `filters`, none if not set
+    pub processing: Option<ProcessingPreset>,
+    /// Levels of the channels mixed down to the stream channels, FFmpeg defaults if not set
+    pub downmix: Option<DownmixConfig>,
+    /// Sample rate converter of the decoders, FFmpeg's swresample if not set
+    pub resampler: Option<Resampler>,
+    /// Precision of the soxr resampler in bits, 15 to 33
+    pub resampler_precision: Option<u8>,
+    /// Output gain in dB applied last before encoding, -30 to 12
+    pub gain_db: Option<f64>,
+    /// Registered custom audio stages applied in order to the mixed audio before encoding
+    pub stages: Option<Vec<String>>,
+    /// FFmpeg encoder to use instead of the preferred one of the format, e.g. `aac_at`
+    pub encoder: Option<String>,
+    /// Threads of the encoder, 1 to 64, FFmpeg's default if not set
+    pub threads: Option<u32>,
+    /// Bitrate ladder, one mount `<name>_<bitrate>` per bitrate sharing a single decode
+    pub bitrates: Option<Vec<u32>>,
+}
+
+/// Sample rate converter used when files do not match the stream sample rate
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Resampler {
+    /// FFmpeg's built-in swresample
+    Swr,
+    /// SoX resampler, higher quality, needs FFmpeg built with libsoxr
+    Soxr,
+}
+
+/// Levels at which the channels of stereo and surround files are mixed into the stream
+/// channels, in dB
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DownmixConfig {
+    /// Level of left and right in a mono mix, default -3 (equal power pan law)
+    pub pan_law_db: Option<f64>,
+    /// Level of the center channel in the left and right channels, default -3
+    pub center_db: Option<f64>,
+    /// Level of the surround channels in the front channels, default -3
+    pub surround_db: Option<f64>,
+    /// Level of the LFE channel, dropped if not set
+    pub lfe_db: Option<f64>,
+}
+
+impl DownmixConfig {
+    fn validate(&self) -> Result<(), String> {
+        for (name, db) in [
+            ("pan_law_db", self.pan_law_db),
+            ("center_db", self.center_db),
+            ("surround_db", self.surround_db),
+            ("lfe_db", self.lfe_db),
+        ] {
+            if let Some(db) = db.filter(|db| !(-60.0..=0.0).contains(db)) {
+                return Err(format!(
+                    "Downmix {} {} is out of range. Valid range: -60 to 0",
+                    name, db
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Built-in dynamics processing for a consistent loudness across tracks
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessingPreset {
+    /// Multiband compressor and limiter, a dense and loud "radio sound"
+    Broadcast,
+    /// Light single-band compression and a limiter, keeps most of the dynamics
+    Gentle,
+}
+
+/// AAC profile, the HE profiles need libfdk_aac and fall back to AAC-LC without it
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AacProfile {
+    /// Low complexity, for 96 kbps and more
+    #[default]
+    Lc,
+    /// High efficiency with spectral band replication, for 48 to 64 kbps
+    HeAac,
+    /// High efficiency with parametric stereo, for stereo at 32 to 48 kbps
+    HeAacV2,
+}
+
+/// Bitrate control of a stream encoder
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingMode {
+    /// Constant bitrate of `bitrate` kbps
+    #[default]
+    Cbr,
+    /// Variable bitrate by `quality`, opus targets `bitrate` on average
+    Vbr,
+}
+
+/// Transition between consecutive tracks, applied when mixing the decoded audio
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct TransitionConfig {
+    pub style: TransitionStyle,
+    /// Length of the fade-out of the ending track in seconds, default 3
+    #[schema(example = 3.0)]
+    pub fade_out_seconds: Option<f64>,
+    /// Length of the fade-in of the next track in seconds, default 3
+    #[schema(example = 1.5)]
+    pub fade_in_seconds: Option<f64>,
+    /// Level the ending track is ducked to under the next one (`duck` style), default -12
+    #[schema(example = -12.0)]
+    pub duck_db: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionStyle {
+    /// Next track starts right after the previous one
+    Cut,
+    /// Previous track fades out, then the next one fades in
+    Fade,
+    /// Both tracks overlap while one fades out and the other fades in
+    Crossfade,
+    /// Next track starts over the ducked, fading out end of the previous one
+    Duck,
+}
+
+impl TransitionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, seconds) in [
+            ("fade_out_seconds", self.fade_out_seconds),
+            ("fade_in_seconds", self.fade_in_seconds),
+        ] {
+            if let Some(seconds) = seconds.filter(|s| !(0.0..=MAX_FADE_SECONDS).contains(s)) {
+                return Err(format!(
+                    "Transition {} {} is out of range. Valid range: 0-{}",
+                    name, seconds, MAX_FADE_SECONDS
+                ));
+            }
+        }
+
+        if let Some(duck_db) = self.duck_db.filter(|db| !(-60.0..=0.0).contains(db)) {
+            return Err(format!(
+                "Transition duck_db {} is out of range. Valid range: -60 to 0",
+                duck_db
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 impl StreamConfig {
@@ -52,20 +269,28 @@ impl StreamConfig {
     pub fn validate(&self) -> Result<(), String> {
         // Validate format
         match self.format.to_lowercase().as_str() {
-            "mp3" | "aac" | "opus" | "ogg" => {}
+            "mp3" | "aac" | "opus" | "ogg" | "flac" => {}
             _ => {
                 return Err(format!(
-                    "Unsupported audio format '{}'. Supported formats: mp3, aac, opus, ogg",
+                    "Unsupported audio format '{}'. Supported formats: mp3, aac, opus, ogg, flac",
                     self.format
                 ))
             }
         }
 
-        // Validate bitrate
-        if self.bitrate < 32 || self.bitrate > 320 {
+        // Validate bitrate, FLAC is lossless and only announces it
+        let lossless = self.format.eq_ignore_ascii_case("flac");
+        if let Some(bitrates) = &self.bitrates {
+            self.validate_ladder(bitrates, lossless)?;
+        }
+        if let Some(bitrate) = self
+            .ladder()
+            .into_iter()
+            .find(|b| !lossless && !(32..=320).contains(b))
+        {
             return Err(format!(
                 "Bitrate {} is out of range. Valid range: 32-320 kbps",
-                self.bitrate
+                bitrate
             ));
         }
 
@@ -83,25 +308,560 @@ impl StreamConfig {
             ));
         }
 
+        if let Some(transition) = &self.transition {
+            transition.validate()?;
+        }
+
+        if let Some(filters) = &self.filters {
+            if filters.trim().is_empty() {
+                return Err("Filter chain must not be empty, remove 'filters' instead".to_string());
+            }
+        }
+
+        if let Some(downmix) = &self.downmix {
+            downmix.validate()?;
+        }
+
+        if let Some(gain_db) = self.gain_db.filter(|db| !(-30.0..=12.0).contains(db)) {
+            return Err(format!(
+                "Gain {} dB is out of range. Valid range: -30 to 12",
+                gain_db
+            ));
+        }
+
+        if let Some(encoder) = &self.encoder {
+            if encoder.is_empty() || encoder.contains(char::is_whitespace) {
+                return Err(format!("Invalid encoder name '{}'", encoder));
+            }
+        }
+
+        if let Some(threads) = self.threads.filter(|t| !(1..=64).contains(t)) {
+            return Err(format!(
+                "Encoder threads {} is out of range. Valid range: 1 to 64",
+                threads
+            ));
+        }
+
+        self.validate_aac_profile()?;
+
+        self.validate_resampler()?;
+
+        self.validate_quality()
+    }
+
+    fn validate_ladder(&self, bitrates: &[u32], lossless: bool) -> Result<(), String> {
+        if lossless {
+            return Err("bitrates cannot be used with the lossless format flac".to_string());
+        }
+        if self.bitrate != 0 {
+            return Err("Set either bitrate or bitrates, not both".to_string());
+        }
+        if bitrates.is_empty() {
+            return Err("bitrates must contain at least one bitrate".to_string());
+        }
+        if self.quality.is_some() {
+            return Err(
+                "quality sets the same VBR quality for every bitrate, remove it with bitrates"
+                    .to_string(),
+            );
+        }
+        let mut distinct = bitrates.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() != bitrates.len() {
+            return Err("bitrates must not contain a bitrate twice".to_string());
+        }
         Ok(())
     }
+
+    /// Bitrates of the encoders of the stream, one unless it has a ladder
+    pub fn ladder(&self) -> Vec<u32> {
+        self.bitrates.clone().unwrap_or_else(|| vec![self.bitrate])
+    }
+
+    /// Mounts of the stream with their settings: the stream itself, or one mount named
+    /// `<name>_<bitrate>` per bitrate of its ladder
+    pub fn mounts(&self, name: &str) -> Vec<(String, StreamConfig)> {
+        match &self.bitrates {
+            Some(bitrates) => bitrates
+                .iter()
+                .map(|&bitrate| {
+                    let settings = StreamConfig {
+                        bitrate,
+                        bitrates: None,
+                        ..self.clone()
+                    };
+                    (format!("{}_{}", name, bitrate), settings)
+                })
+                .collect(),
+            None => vec![(name.to_string(), self.clone())],
+        }
+    }
+
+    fn validate_aac_profile(&self) -> Result<(), String> {
+        match self.aac_profile {
+            Some(_) if !self.format.eq_ignore_ascii_case("aac") => Err(format!(
+                "aac_profile is only used with format \"aac\", not '{}'",
+                self.format
+            )),
+            Some(AacProfile::HeAacV2) if self.channels != 2 => {
+                Err("aac_profile \"he-aac-v2\" requires channels = 2".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_resampler(&self) -> Result<(), String> {
+        match (self.resampler, self.resampler_precision) {
+            (Some(Resampler::Soxr), Some(precision)) if !(15..=33).contains(&precision) => {
+                Err(format!(
+                    "Resampler precision {} is out of range. Valid range: 15-33 bits",
+                    precision
+                ))
+            }
+            (Some(Resampler::Soxr), _) | (_, None) => Ok(()),
+            (_, Some(_)) => {
+                Err("resampler_precision is only used with resampler = \"soxr\"".to_string())
+            }
+        }
+    }
+
+    fn validate_quality(&self) -> Result<(), String> {
+        let format = self.format.to_lowercase();
+        match (self.mode.unwrap_or_default(), self.quality) {
+            (EncodingMode::Cbr, None) => Ok(()),
+            (EncodingMode::Cbr, Some(_)) => {
+                Err("Quality is only used with mode = \"vbr\"".to_string())
+            }
+            (EncodingMode::Vbr, _) if format == "flac" => {
+                Err("FLAC is lossless and has no VBR mode, remove 'mode'".to_string())
+            }
+            (EncodingMode::Vbr, quality) if format == "opus" => match quality {
+                None => Ok(()),
+                Some(_) => {
+                    Err("Opus VBR targets the bitrate on average, remove 'quality'".to_string())
+                }
+            },
+            (EncodingMode::Vbr, None) => Err(format!(
+                "VBR for format '{}' requires a quality",
+                self.format
+            )),
+            (EncodingMode::Vbr, Some(quality)) => {
+                let (min, max) = match format.as_str() {
+                    "mp3" => (0.0, 9.0),
+                    "ogg" => (-1.0, 10.0),
+                    _ => (1.0, 5.0),
+                };
+                if (min..=max).contains(&quality) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Quality {} is out of range for format '{}'. Valid range: {} to {}",
+                        quality, self.format, min, max
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Ad insertion through an external ad decision server returning VAST
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AdsConfig {
+    pub decision_url: String,
+    pub break_cron: String,
+    pub max_ads_per_break: Option<u32>,
+    pub timeout_seconds: Option<u64>,
+}
+
+/// Settings of outgoing HTTP requests (hearthis.at, ads, webhooks)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HttpConfig {
+    /// Proxy URL for all requests, e.g. `http://proxy:3128`; the proxy environment variables
+    /// apply if not set
+    pub proxy: Option<String>,
+    /// Comma separated hosts that bypass the configured proxy
+    pub no_proxy: Option<String>,
+    /// PEM files of additional trusted CA certificates
+    pub ca_certificates: Option<Vec<String>>,
+    pub user_agent: Option<String>,
+}
+
+/// Listener access to restricted programs
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AccessConfig {
+    /// Tokens unlocking restricted programs, sent as `access_token` query parameter or
+    /// `Authorization: Bearer` header
+    pub tokens: Vec<String>,
+    /// Audio file looped for listeners without a valid token while a restricted program runs
+    pub fallback: String,
+}
+
+/// Station IDs and sweepers played between library tracks
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JinglesConfig {
+    pub directory: String,
+    /// Play a jingle after this many library tracks
+    pub every_n_tracks: Option<u32>,
+    /// Play a jingle before the next library track once this many minutes have passed
+    pub every_minutes: Option<u64>,
+}
+
+/// Station identification played at the top of each hour
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StationIdConfig {
+    pub file: String,
+    /// Seconds a track boundary may deviate from the full hour to play the ID there
+    pub tolerance_seconds: Option<u64>,
+}
+
+/// Spoken announcements synthesized by a text-to-speech command, played between library tracks
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnnouncementsConfig {
+    /// Program and arguments of the TTS command, `{output}` is replaced by the file to write
+    /// and `{text}` by the text, which is also passed on stdin
+    pub command: Vec<String>,
+    /// Announce after this many library tracks
+    pub every_n_tracks: u32,
+    /// Text of the announcement with `{station}`, `{artist}` and `{title}` placeholders
+    pub template: Option<String>,
+    /// Extension of the files the command writes, default wav
+    pub extension: Option<String>,
+}
+
+/// Time of day whose library rotation only plays tracks of some genres, tempo or energy
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DaypartConfig {
+    pub name: String,
+    /// Start time as `HH:MM`
+    pub start: String,
+    /// End time as `HH:MM`, before `start` for dayparts past midnight
+    pub end: String,
+    /// Genres played, compared with the genre tags of the tracks ignoring case, all if empty
+    #[serde(default)]
+    pub genres: Vec<String>,
+    /// Slowest tempo played in beats per minute
+    pub min_bpm: Option<f64>,
+    /// Fastest tempo played in beats per minute
+    pub max_bpm: Option<f64>,
+    /// Lowest energy played, 0 (calm) to 1 (energetic)
+    pub min_energy: Option<f64>,
+    /// Highest energy played, 0 (calm) to 1 (energetic)
+    pub max_energy: Option<f64>,
+}
+
+impl DaypartConfig {
+    /// Whether the daypart selects tracks by the tempo analysis
+    pub fn limits_tempo(&self) -> bool {
+        self.min_bpm.is_some()
+            || self.max_bpm.is_some()
+            || self.min_energy.is_some()
+            || self.max_energy.is_some()
+    }
+
+    /// Start and end time of the daypart
+    pub fn times(&self) -> Result<(chrono::NaiveTime, chrono::NaiveTime), String> {
+        let parse = |time: &str| {
+            chrono::NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| {
+                format!(
+                    "Daypart '{}': invalid time '{}', expected HH:MM",
+                    self.name, time
+                )
+            })
+        };
+        Ok((parse(&self.start)?, parse(&self.end)?))
+    }
+}
+
+/// Files of a directory served transcoded with seek support at `/ondemand/...`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OnDemandConfig {
+    pub directory: String,
+    /// Output format of the transcoded files, default mp3
+    pub format: Option<String>,
+    /// Output bitrate in kbps, default 192
+    pub bitrate: Option<u32>,
+}
+
+impl OnDemandConfig {
+    /// Encoder settings of the transcoded files
+    pub fn stream_settings(&self) -> StreamConfig {
+        StreamConfig {
+            bitrate: self.bitrate.unwrap_or(192),
+            format: self.format.clone().unwrap_or_else(|| "mp3".to_string()),
+            sample_rate: 44100,
+            channels: 2,
+            enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ScheduleConfig {
+    #[serde(default)]
     pub programs: Vec<ScheduleProgram>,
+    pub align_to_programs: Option<bool>,
+    pub align_tolerance_seconds: Option<u64>,
+    /// Whether overlapping programs are logged or refused, default `warn`
+    pub on_overlap: Option<OverlapPolicy>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Handling of programs whose time slots overlap
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// Logs the overlaps, the program priority decides on air
+    #[default]
+    Warn,
+    /// Refuses to start with or reload a schedule that has overlapping programs
+    Error,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
 pub struct ScheduleProgram {
+    #[schema(example = "Techno Night")]
     pub name: String,
     pub active: bool,
+    /// Start times of the program, empty for programs that only start after another one
+    /// via `followed_by`
+    #[schema(example = "0 0 22 * * 5,6")]
+    #[serde(default)]
     pub cron: String,
+    /// Interval of the starts instead of `cron`, e.g. `3h`, counted from midnight
+    #[schema(example = "3h")]
+    pub every: Option<String>,
+    /// Dates the program does not run on, as `YYYY-MM-DD`
+    #[schema(example = json!(["2025-12-24", "2025-12-31"]))]
+    pub except_dates: Option<Vec<String>>,
+    /// Dates the program only runs on, as `YYYY-MM-DD`
+    #[schema(example = json!(["2025-12-24"]))]
+    pub only_dates: Option<Vec<String>>,
+    /// Length of the time slot, e.g. `2h` or `90m`, or `until_next` to run until the next
+    /// program starts
+    #[schema(example = "4h")]
     pub duration: String,
+    /// Program type: `playlist` (default), `liveset`, `local_liveset` or `podcast`
     #[serde(rename = "type")]
     pub program_type: Option<String>,
+    /// Path of an M3U playlist file
     pub playlist: Option<String>,
+    /// Name of a server-managed playlist (`/api/playlists`), alternative to `playlist`
+    #[schema(example = "Friday Warmup")]
+    pub stored_playlist: Option<String>,
+    /// Name of a smart playlist (`/api/smart-playlists`) whose query selects library tracks
+    /// when the program starts, alternative to `playlist`
+    #[schema(example = "Jazz Classics")]
+    pub smart_playlist: Option<String>,
+    /// Directory whose audio files are played, alternative to `playlist`
+    #[schema(example = "/shows/jazz_hour")]
+    pub source_dir: Option<String>,
+    /// Plays the files of `source_dir` or the tracks of `smart_playlist` shuffled instead of
+    /// sorted
+    pub shuffle: Option<bool>,
     pub genres: Option<Vec<String>>,
+    /// Shortest library track a local liveset program plays, e.g. `20m`, default 20m
+    #[schema(example = "30m")]
+    pub min_track_duration: Option<String>,
+    /// RSS feed of a podcast program
+    #[schema(example = "https://example.com/podcast/feed.xml")]
+    pub feed_url: Option<String>,
+    /// Episode of the feed a podcast program plays, default `newest`
+    pub episode: Option<PodcastEpisodeSelection>,
+    /// Pre-recorded voice breaks played within a playlist program
+    pub voice_breaks: Option<Vec<ProgramVoiceBreak>>,
+    /// Transition into the tracks of this program, overrides the stream transition
+    pub transition: Option<TransitionConfig>,
+    /// Only listeners with an access token hear the program, others hear the `[access]`
+    /// fallback loop
+    pub restricted: Option<bool>,
+    /// What happens to the track playing when the program ends, default `finish_track`,
+    /// also accepted as `on_end`
+    #[serde(alias = "on_end")]
+    pub end_behavior: Option<ProgramEndBehavior>,
+    /// Length of the fade out when `end_behavior = "fade"` in seconds, default 3
+    #[schema(example = 3.0)]
+    pub end_fade_seconds: Option<f64>,
+    /// Crossfade from the library into the program in seconds, overrides the transition into
+    /// the first program track
+    #[schema(example = 4.0)]
+    pub fade_in_seconds: Option<f64>,
+    /// Crossfade from the program back into the library in seconds
+    #[schema(example = 4.0)]
+    pub fade_out_seconds: Option<f64>,
+    /// Priority over overlapping programs, higher wins, default 0
+    #[schema(example = 10)]
+    pub priority: Option<i32>,
+    /// Program started right when this program ends
+    #[schema(example = "Music Special")]
+    pub followed_by: Option<String>,
+    /// Station metadata announced to listeners while the program runs
+    pub metadata: Option<ProgramMetadata>,
+    /// Intro played right before the first track of the program
+    pub intro: Option<ProgramIntro>,
+    /// Program and arguments of a command run when the program starts, only accepted from the
+    /// config file and never exported
+    #[serde(skip_serializing)]
+    #[schema(example = json!(["/usr/local/bin/studio-lights", "on"]))]
+    pub on_start_cmd: Option<Vec<String>>,
+    /// Program and arguments of a command run when the program ends, only accepted from the
+    /// config file and never exported
+    #[serde(skip_serializing)]
+    #[schema(example = json!(["/usr/local/bin/studio-lights", "off"]))]
+    pub on_end_cmd: Option<Vec<String>>,
+    /// Further attempts to load the program source if it is unavailable at the start,
+    /// default 0
+    #[schema(example = 3)]
+    pub retry_attempts: Option<u32>,
+    /// Wait before the first retry in seconds, doubled for each further one, default 30
+    #[schema(example = 30)]
+    pub retry_delay_seconds: Option<u64>,
+    /// What fills the time slot once all attempts failed, default `library`
+    pub fallback: Option<ProgramFallback>,
+    /// Genres of the library tracks played by `fallback = "genres"`, default the `genres` of
+    /// a liveset program
+    #[schema(example = json!(["techno", "house"]))]
+    pub fallback_genres: Option<Vec<String>>,
+}
+
+/// What plays in the time slot of a program whose source stays unavailable
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgramFallback {
+    /// The library rotation continues
+    Library,
+    /// Library tracks tagged with one of the fallback genres fill the time slot
+    Genres,
+}
+
+/// Overrides of the station metadata while a program runs, unset fields keep the station
+/// defaults
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct ProgramMetadata {
+    #[schema(example = "Techno Tuesdays w/ DJ X")]
+    pub station_name: Option<String>,
+    pub description: Option<String>,
+    #[schema(example = "Techno")]
+    pub genre: Option<String>,
+}
+
+/// Intro of a program, either a pre-recorded file or a text spoken by the TTS command of the
+/// announcements
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct ProgramIntro {
+    /// Pre-recorded intro
+    #[schema(example = "/shows/intros/jazz_hour.mp3")]
+    pub file: Option<String>,
+    /// Text of a spoken intro with `{program}` and `{station}` placeholders
+    #[schema(example = "Up next on {station}: {program}")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgramEndBehavior {
+    /// The program track fades out, then the library starts
+    Fade,
+    /// The program track stops immediately and the library starts
+    Cut,
+    /// The program track plays to its end before the library starts
+    FinishTrack,
+    /// The program playlist plays to its last track before the library starts
+    FinishPlaylist,
+}
+
+/// Episode of its feed a podcast program plays
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PodcastEpisodeSelection {
+    /// The episode with the latest publication date
+    Newest,
+    /// A random episode of the feed
+    Random,
+}
+
+/// A pre-recorded voice break, triggered either after a number of program tracks
+/// or once an offset from the program start has passed
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct ProgramVoiceBreak {
+    #[schema(example = "voice/station-id.mp3")]
+    pub file: String,
+    /// Play after this many tracks of the program
+    #[schema(example = 3)]
+    pub after_track: Option<u32>,
+    /// Play at the first track change after this time into the program, e.g. `15m`
+    #[schema(example = "15m")]
+    pub offset: Option<String>,
+    /// Play over the start of the next track with the music ducked to this level in dB,
+    /// between tracks if not set
+    #[schema(example = -12.0)]
+    pub duck_db: Option<f64>,
+    /// Length of the fade down to and back up from the ducked level in seconds, default 0.5
+    #[schema(example = 0.5)]
+    pub duck_fade_seconds: Option<f64>,
+}
+
+impl ProgramVoiceBreak {
+    fn validate_ducking(&self) -> Result<(), String> {
+        if let Some(duck_db) = self.duck_db.filter(|db| !(-60.0..=0.0).contains(db)) {
+            return Err(format!(
+                "Voice break '{}': duck_db {} is out of range. Valid range: -60 to 0",
+                self.file, duck_db
+            ));
+        }
+        match self.duck_fade_seconds {
+            Some(_) if self.duck_db.is_none() => Err(format!(
+                "Voice break '{}': duck_fade_seconds requires duck_db",
+                self.file
+            )),
+            Some(seconds) if !(0.0..=MAX_FADE_SECONDS).contains(&seconds) => Err(format!(
+                "Voice break '{}': duck_fade_seconds {} is out of range. Valid range: 0-{}",
+                self.file, seconds, MAX_FADE_SECONDS
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Expands an interval like `15m` or `3h` to a cron expression starting at midnight. The
+/// interval has to divide an hour or a day, so the starts are the same every day.
+fn interval_cron(every: &str) -> Result<String, String> {
+    let every = every.trim();
+    let invalid = || {
+        format!(
+            "Invalid every '{}'. Use minutes dividing an hour, e.g. '15m', or hours dividing a day, e.g. '3h'",
+            every
+        )
+    };
+
+    if let Some(minutes) = every.strip_suffix('m') {
+        match minutes.parse::<u32>().map_err(|_| invalid())? {
+            60 => Ok("0 0 * * * *".to_string()),
+            minutes if minutes > 0 && 60 % minutes == 0 => Ok(format!("0 */{} * * * *", minutes)),
+            _ => Err(invalid()),
+        }
+    } else if let Some(hours) = every.strip_suffix('h') {
+        match hours.parse::<u32>().map_err(|_| invalid())? {
+            24 => Ok("0 0 0 * * *".to_string()),
+            hours if hours > 0 && 24 % hours == 0 => Ok(format!("0 0 */{} * * *", hours)),
+            _ => Err(invalid()),
+        }
+    } else {
+        Err(invalid())
+    }
 }
 
 impl ScheduleProgram {
@@ -109,6 +869,8 @@ impl ScheduleProgram {
     pub fn get_type(&self) -> ProgramType {
         match self.program_type.as_deref() {
             Some("liveset") => ProgramType::Liveset,
+            Some("local_liveset") => ProgramType::LocalLiveset,
+            Some("podcast") => ProgramType::Podcast,
             _ => {
                 // Default to playlist if type is not specified or is "playlist"
                 ProgramType::Playlist
@@ -116,24 +878,259 @@ impl ScheduleProgram {
         }
     }
 
+    /// Cron expression of the program starts, expanded from `every` if set. `None` for
+    /// programs that only start after another one via `followed_by`.
+    pub fn cron_expression(&self) -> Result<Option<String>, String> {
+        match (self.cron.trim().is_empty(), &self.every) {
+            (false, Some(_)) => {
+                Err("Programs must specify only one of 'cron' and 'every'".to_string())
+            }
+            (false, None) => Ok(Some(self.cron.clone())),
+            (true, Some(every)) => interval_cron(every).map(Some),
+            (true, None) => Ok(None),
+        }
+    }
+
+    /// Dates of `except_dates` and `only_dates`
+    pub fn calendar_dates(
+        &self,
+    ) -> Result<(Vec<chrono::NaiveDate>, Vec<chrono::NaiveDate>), String> {
+        let parse = |field: &str, dates: &Option<Vec<String>>| {
+            dates
+                .iter()
+                .flatten()
+                .map(|date| {
+                    chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| {
+                        format!("Invalid date '{}' in {}, expected YYYY-MM-DD", date, field)
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok((
+            parse("except_dates", &self.except_dates)?,
+            parse("only_dates", &self.only_dates)?,
+        ))
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.restricted.unwrap_or(false)
+    }
+
+    /// Whether the program runs until the next program starts instead of a fixed duration
+    pub fn runs_until_next(&self) -> bool {
+        self.duration.trim() == "until_next"
+    }
+
+    /// Genres of the library tracks filling the time slot if the source stays unavailable,
+    /// `None` if the library rotation continues
+    pub fn fallback_genres(&self) -> Option<Vec<String>> {
+        if self.fallback != Some(ProgramFallback::Genres) {
+            return None;
+        }
+        self.fallback_genres
+            .clone()
+            .or_else(|| self.genres.clone())
+            .filter(|genres| !genres.is_empty())
+    }
+
     /// Validates the program configuration
     pub fn validate(&self) -> Result<(), String> {
         match self.get_type() {
             ProgramType::Playlist => {
-                if self.playlist.is_none() {
-                    return Err("Playlist programs must specify a 'playlist' field".to_string());
+                let sources = [
+                    self.playlist.is_some(),
+                    self.stored_playlist.is_some(),
+                    self.smart_playlist.is_some(),
+                    self.source_dir.is_some(),
+                ];
+                match sources.iter().filter(|set| **set).count() {
+                    1 => {}
+                    0 => return Err(
+                        "Playlist programs must specify a 'playlist', 'stored_playlist', 'smart_playlist' or 'source_dir' field"
+                            .to_string(),
+                    ),
+                    _ => return Err(
+                        "Playlist programs must specify only one of 'playlist', 'stored_playlist', 'smart_playlist' and 'source_dir'"
+                            .to_string(),
+                    ),
                 }
             }
-            ProgramType::Liveset => {
+            ProgramType::Liveset | ProgramType::LocalLiveset => {
                 if self.genres.is_none() {
                     return Err(
                         "Liveset programs must specify a 'genres' field (use empty array [] for all genres)"
                             .to_string(),
                     );
                 }
+                if self.voice_breaks.is_some() {
+                    return Err("Voice breaks are only supported for playlist programs".to_string());
+                }
+            }
+            ProgramType::Podcast => {
+                match &self.feed_url {
+                    Some(url) if url.starts_with("http://") || url.starts_with("https://") => {}
+                    Some(url) => return Err(format!("Invalid feed_url '{}'", url)),
+                    None => {
+                        return Err("Podcast programs must specify a 'feed_url' field".to_string())
+                    }
+                }
+                if self.voice_breaks.is_some() {
+                    return Err("Voice breaks are only supported for playlist programs".to_string());
+                }
             }
         }
-        Ok(())
+
+        if self.get_type() != ProgramType::Podcast
+            && (self.feed_url.is_some() || self.episode.is_some())
+        {
+            return Err("feed_url and episode are only used by podcast programs".to_string());
+        }
+
+        if self.min_track_duration.is_some() && self.get_type() != ProgramType::LocalLiveset {
+            return Err("min_track_duration is only used by local_liveset programs".to_string());
+        }
+
+        if self.shuffle.is_some() && self.source_dir.is_none() && self.smart_playlist.is_none() {
+            return Err("shuffle requires a 'source_dir' or 'smart_playlist'".to_string());
+        }
+
+        if self.followed_by.as_deref() == Some(self.name.as_str()) {
+            return Err("A program cannot be followed by itself".to_string());
+        }
+
+        if self.cron_expression()?.is_none() && self.is_restricted() {
+            return Err("Restricted programs need a cron expression".to_string());
+        }
+
+        self.calendar_dates()?;
+        if self.cron_expression()?.is_none()
+            && (self.except_dates.is_some() || self.only_dates.is_some())
+        {
+            return Err("except_dates and only_dates need a cron expression".to_string());
+        }
+
+        if self.runs_until_next() && self.is_restricted() {
+            return Err("Restricted programs need a fixed duration".to_string());
+        }
+
+        if let Some(metadata) = &self.metadata {
+            let fields = [
+                ("station_name", &metadata.station_name),
+                ("description", &metadata.description),
+                ("genre", &metadata.genre),
+            ];
+            for (field, value) in fields {
+                match value {
+                    Some(value) if value.trim().is_empty() => {
+                        return Err(format!("metadata.{} must not be empty", field));
+                    }
+                    Some(value) if value.chars().any(char::is_control) => {
+                        return Err(format!(
+                            "metadata.{} must not contain control characters",
+                            field
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(intro) = &self.intro {
+            match (&intro.file, &intro.text) {
+                (Some(_), None) => {}
+                (None, Some(text)) if !text.trim().is_empty() => {}
+                (None, Some(_)) => return Err("intro.text must not be empty".to_string()),
+                _ => {
+                    return Err("intro must specify either 'file' or 'text'".to_string());
+                }
+            }
+        }
+
+        for (field, command) in [
+            ("on_start_cmd", &self.on_start_cmd),
+            ("on_end_cmd", &self.on_end_cmd),
+        ] {
+            if command
+                .as_ref()
+                .is_some_and(|c| c.first().is_none_or(|program| program.trim().is_empty()))
+            {
+                return Err(format!("{} must start with the program to run", field));
+            }
+        }
+
+        for voice_break in self.voice_breaks.iter().flatten() {
+            match (voice_break.after_track, &voice_break.offset) {
+                (Some(0), None) => {
+                    return Err(format!(
+                        "Voice break '{}': after_track must be at least 1",
+                        voice_break.file
+                    ))
+                }
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => {
+                    return Err(format!(
+                        "Voice break '{}' must specify either 'after_track' or 'offset'",
+                        voice_break.file
+                    ))
+                }
+            }
+            voice_break.validate_ducking()?;
+        }
+
+        if let Some(transition) = &self.transition {
+            transition.validate()?;
+        }
+
+        for (name, seconds) in [
+            ("fade_in_seconds", self.fade_in_seconds),
+            ("fade_out_seconds", self.fade_out_seconds),
+        ] {
+            if let Some(seconds) = seconds.filter(|s| !(0.0..=MAX_FADE_SECONDS).contains(s)) {
+                return Err(format!(
+                    "{} {} is out of range. Valid range: 0-{}",
+                    name, seconds, MAX_FADE_SECONDS
+                ));
+            }
+        }
+
+        if self.retry_attempts.is_some_and(|a| a > MAX_RETRY_ATTEMPTS) {
+            return Err(format!(
+                "retry_attempts must be at most {}",
+                MAX_RETRY_ATTEMPTS
+            ));
+        }
+        match self.retry_delay_seconds {
+            Some(_) if self.retry_attempts.unwrap_or(0) == 0 => {
+                return Err("retry_delay_seconds requires retry_attempts".to_string());
+            }
+            Some(seconds) if !(1..=MAX_RETRY_DELAY_SECONDS).contains(&seconds) => {
+                return Err(format!(
+                    "retry_delay_seconds {} is out of range. Valid range: 1-{}",
+                    seconds, MAX_RETRY_DELAY_SECONDS
+                ));
+            }
+            _ => {}
+        }
+        if self.fallback_genres.is_some() && self.fallback != Some(ProgramFallback::Genres) {
+            return Err("fallback_genres requires fallback = \"genres\"".to_string());
+        }
+        if self.fallback == Some(ProgramFallback::Genres) && self.fallback_genres().is_none() {
+            return Err(
+                "fallback = \"genres\" needs 'fallback_genres' or the 'genres' of a liveset"
+                    .to_string(),
+            );
+        }
+
+        match self.end_fade_seconds {
+            Some(_) if self.end_behavior != Some(ProgramEndBehavior::Fade) => {
+                Err("end_fade_seconds requires end_behavior = \"fade\"".to_string())
+            }
+            Some(seconds) if !(0.0..=MAX_FADE_SECONDS).contains(&seconds) => Err(format!(
+                "end_fade_seconds {} is out of range. Valid range: 0-{}",
+                seconds, MAX_FADE_SECONDS
+            )),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -141,6 +1138,9 @@ impl ScheduleProgram {
 pub enum ProgramType {
     Playlist,
     Liveset,
+    /// Long tracks of the library, e.g. DJ mixes, instead of a liveset from hearthis.at
+    LocalLiveset,
+    Podcast,
 }
 
 impl Config {
@@ -154,6 +1154,10 @@ impl Config {
         Ok(config)
     }
 
+    pub fn has_enabled_streams(&self) -> bool {
+        self.stream.values().any(|s| s.enabled)
+    }
+
     /// Validates the entire configuration
     fn validate(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Check for empty stream configuration
@@ -184,48 +1188,359 @@ impl Config {
                 .map_err(|e| format!("Stream '{}': {}", name, e))?;
         }
 
-        // Check that at least one stream is enabled
-        if !self.stream.values().any(|s| s.enabled) {
-            return Err("At least one stream must be enabled".into());
+        // Mounts of bitrate ladders must not take the name of another stream
+        let mut mounts = HashSet::new();
+        for (name, stream_config) in &self.stream {
+            for (mount, _) in stream_config.mounts(name) {
+                if !mounts.insert(mount.clone()) {
+                    return Err(format!(
+                        "Stream '{}': mount '{}' is defined twice, rename the stream",
+                        name, mount
+                    )
+                    .into());
+                }
+            }
         }
 
-        Ok(())
-    }
-}
+        // Check that at least one stream is enabled, unless the server may run in standby
+        if !self.has_enabled_streams() && !self.server.standby.unwrap_or(false) {
+            return Err(
+                "At least one stream must be enabled (or set server.standby = true to start without streams)"
+                    .into(),
+            );
+        }
 
-impl Default for Config {
-    fn default() -> Self {
-        let mut streams = HashMap::new();
-        streams.insert(
-            "default".to_string(),
-            StreamConfig {
-                bitrate: 128,
-                format: "mp3".to_string(),
-                sample_rate: 44100,
-                channels: 2,
-                enabled: true,
-            },
-        );
+        if let Some(format) = &self.server.access_log_format {
+            AccessLogFormat::parse(format)?;
+        }
 
-        Self {
-            server: ServerConfig {
-                port: 8284,
+        if let Some(ads) = &self.ads {
+            cron::Schedule::from_str(&ads.break_cron)
+                .map_err(|e| format!("Invalid ads.break_cron '{}': {}", ads.break_cron, e))?;
+        }
+
+        if let Some(ondemand) = &self.ondemand {
+            ondemand
+                .stream_settings()
+                .validate()
+                .map_err(|e| format!("ondemand: {}", e))?;
+        }
+
+        if let Some(access) = &self.access {
+            if access.tokens.is_empty() || access.tokens.iter().any(|t| t.trim().is_empty()) {
+                return Err("access.tokens must contain at least one non-empty token".into());
+            }
+            if !std::path::Path::new(&access.fallback).is_file() {
+                return Err(format!("access.fallback '{}' is not a file", access.fallback).into());
+            }
+        } else if let Some(program) = self
+            .schedule
+            .iter()
+            .flat_map(|s| &s.programs)
+            .find(|p| p.is_restricted())
+        {
+            return Err(format!(
+                "Program '{}' is restricted, which requires an [access] section",
+                program.name
+            )
+            .into());
+        }
+
+        if let Some(jingles) = &self.jingles {
+            match (jingles.every_n_tracks, jingles.every_minutes) {
+                (Some(0), _) | (_, Some(0)) => {
+                    return Err("jingles.every_n_tracks and every_minutes must be positive".into());
+                }
+                (Some(_), Some(_)) | (None, None) => {
+                    return Err(
+                        "jingles requires exactly one of every_n_tracks or every_minutes".into(),
+                    );
+                }
+                _ => {}
+            }
+            if !std::path::Path::new(&jingles.directory).is_dir() {
+                return Err(format!(
+                    "jingles.directory '{}' is not a directory",
+                    jingles.directory
+                )
+                .into());
+            }
+        }
+
+        if let Some(station_id) = &self.station_id {
+            if !std::path::Path::new(&station_id.file).is_file() {
+                return Err(format!("station_id.file '{}' is not a file", station_id.file).into());
+            }
+            if station_id.tolerance_seconds.is_some_and(|t| t >= 1800) {
+                return Err("station_id.tolerance_seconds must be less than 1800".into());
+            }
+        }
+
+        if let Some(announcements) = &self.announcements {
+            if !announcements
+                .command
+                .iter()
+                .any(|arg| arg.contains("{output}"))
+            {
+                return Err(
+                    "announcements.command needs an {output} placeholder for the file to write"
+                        .into(),
+                );
+            }
+            if announcements.every_n_tracks == 0 {
+                return Err("announcements.every_n_tracks must be positive".into());
+            }
+        }
+
+        if self
+            .server
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return Err("admin_token must not be empty".into());
+        }
+
+        if self.server.http2_keep_alive_seconds == Some(0) {
+            return Err("http2_keep_alive_seconds must be greater than 0".into());
+        }
+
+        if let Some(templates_dir) = &self.server.templates_dir {
+            if !std::path::Path::new(templates_dir).is_dir() {
+                return Err(format!("templates_dir '{}' is not a directory", templates_dir).into());
+            }
+        }
+
+        // Uploads must land inside the music directory to be picked up by scans
+        if let Some(inbox) = &self.library.inbox_directory {
+            let inbox = std::path::Path::new(inbox);
+            if inbox.is_absolute()
+                || inbox
+                    .components()
+                    .any(|c| matches!(c, std::path::Component::ParentDir))
+            {
+                return Err(format!(
+                    "Invalid inbox_directory {:?}. It must be a relative path inside music_directory",
+                    inbox
+                )
+                .into());
+            }
+        }
+
+        if self.library.no_repeat_hours == Some(0) {
+            return Err("library.no_repeat_hours must be positive".into());
+        }
+
+        if self.library.artist_separation == Some(0) {
+            return Err("library.artist_separation must be positive".into());
+        }
+
+        if self.library.history_retention_days == Some(0) {
+            return Err("library.history_retention_days must be positive".into());
+        }
+
+        if self.library.reconcile_hours == Some(0) {
+            return Err("library.reconcile_hours must be positive".into());
+        }
+
+        if let Some(target) = self
+            .library
+            .loudness_target_lufs
+            .filter(|lufs| !(-40.0..=-5.0).contains(lufs))
+        {
+            return Err(format!(
+                "library.loudness_target_lufs must be between -40 and -5, got {}",
+                target
+            )
+            .into());
+        }
+
+        let tempo_analysis = self.library.tempo_analysis.unwrap_or(false);
+        if self.library.tempo_matching.unwrap_or(false) && !tempo_analysis {
+            return Err("library.tempo_matching needs library.tempo_analysis".into());
+        }
+
+        if let Some(dayparts) = &self.dayparts {
+            Self::validate_dayparts(dayparts)?;
+            if let Some(daypart) = dayparts
+                .iter()
+                .find(|d| d.limits_tempo() && !tempo_analysis)
+            {
+                return Err(format!(
+                    "Daypart '{}' selects by tempo or energy, which needs library.tempo_analysis",
+                    daypart.name
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the limits of a daypart lie in `valid` and do not cross
+    fn validate_daypart_range(
+        name: &str,
+        quantity: &str,
+        min: Option<f64>,
+        max: Option<f64>,
+        valid: std::ops::RangeInclusive<f64>,
+    ) -> Result<(), String> {
+        if let Some(limit) = [min, max]
+            .into_iter()
+            .flatten()
+            .find(|l| !valid.contains(l))
+        {
+            return Err(format!(
+                "Daypart '{}': {} {} is out of range. Valid range: {} to {}",
+                name,
+                quantity,
+                limit,
+                valid.start(),
+                valid.end()
+            ));
+        }
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!(
+                    "Daypart '{}': min_{} must not exceed max_{}",
+                    name, quantity, quantity
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_dayparts(dayparts: &[DaypartConfig]) -> Result<(), String> {
+        use chrono::Timelike;
+        const MINUTES_PER_DAY: u32 = 24 * 60;
+        let mut covered: Vec<Option<&str>> = vec![None; MINUTES_PER_DAY as usize];
+
+        for daypart in dayparts {
+            if daypart.name.trim().is_empty() {
+                return Err("Daypart name must not be empty".to_string());
+            }
+            if daypart.genres.iter().all(|g| g.trim().is_empty()) && !daypart.limits_tempo() {
+                return Err(format!(
+                    "Daypart '{}' needs at least one genre or a tempo or energy range",
+                    daypart.name
+                ));
+            }
+            Self::validate_daypart_range(
+                &daypart.name,
+                "bpm",
+                daypart.min_bpm,
+                daypart.max_bpm,
+                1.0..=400.0,
+            )?;
+            Self::validate_daypart_range(
+                &daypart.name,
+                "energy",
+                daypart.min_energy,
+                daypart.max_energy,
+                0.0..=1.0,
+            )?;
+            let (start, end) = daypart.times()?;
+            if start == end {
+                return Err(format!(
+                    "Daypart '{}' must end at a different time than it starts",
+                    daypart.name
+                ));
+            }
+
+            let minute = |time: chrono::NaiveTime| time.num_seconds_from_midnight() / 60;
+            let mut at = minute(start);
+            while at != minute(end) {
+                let slot = &mut covered[at as usize];
+                if let Some(other) = slot {
+                    return Err(format!(
+                        "Dayparts '{}' and '{}' overlap",
+                        other, daypart.name
+                    ));
+                }
+                *slot = Some(&daypart.name);
+                at = (at + 1) % MINUTES_PER_DAY;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut streams = HashMap::new();
+        streams.insert(
+            "default".to_string(),
+            StreamConfig {
+                bitrate: 128,
+                format: "mp3".to_string(),
+                sample_rate: 44100,
+                channels: 2,
+                enabled: true,
+                transition: None,
+                filters: None,
+                mode: None,
+                quality: None,
+                aac_profile: None,
+                processing: None,
+                downmix: None,
+                resampler: None,
+                resampler_precision: None,
+                gain_db: None,
+                stages: None,
+                encoder: None,
+                threads: None,
+                bitrates: None,
+            },
+        );
+
+        Self {
+            server: ServerConfig {
+                port: 8284,
                 bind_address: "127.0.0.1".to_string(),
                 ffmpeg_path: None,
+                access_log: None,
+                access_log_format: None,
+                anonymize_listener_ips: None,
+                templates_dir: None,
+                standby: None,
+                http2: None,
+                http2_keep_alive_seconds: None,
+                admin_token: None,
             },
             library: LibraryConfig {
                 music_directory: "/path/to/music".to_string(),
                 shuffle: true,
                 repeat: true,
+                max_track_failures: None,
+                inbox_directory: None,
+                no_repeat_hours: None,
+                artist_separation: None,
+                history_retention_days: None,
+                audio_fingerprints: None,
+                validate_files: None,
+                loudness_target_lufs: None,
+                tempo_analysis: None,
+                tempo_matching: None,
+                skip_unchanged_directories: None,
+                reconcile_hours: None,
             },
             station: StationConfig {
                 station_name: "My Radio Station".to_string(),
                 description: "Great music 24/7".to_string(),
                 genre: "Various".to_string(),
                 url: "http://localhost:8000".to_string(),
+                contact: None,
             },
             stream: streams,
             schedule: None,
+            ads: None,
+            ondemand: None,
+            http: None,
+            access: None,
+            jingles: None,
+            station_id: None,
+            announcements: None,
+            dayparts: None,
         }
     }
 }
@@ -244,19 +1559,317 @@ mod tests {
             sample_rate: 44100,
             channels: 2,
             enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        };
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_stream_config_transition_validation() {
+        let transition: TransitionConfig =
+            toml::from_str("style = \"crossfade\"\nfade_out_seconds = 4.5").unwrap();
+        assert_eq!(transition.style, TransitionStyle::Crossfade);
+
+        let mut config = StreamConfig {
+            bitrate: 128,
+            format: "mp3".to_string(),
+            sample_rate: 44100,
+            channels: 2,
+            enabled: true,
+            transition: Some(transition),
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.transition.as_mut().unwrap().fade_in_seconds = Some(45.0);
+        assert!(config.validate().is_err());
+
+        config.transition.as_mut().unwrap().fade_in_seconds = None;
+        config.transition.as_mut().unwrap().duck_db = Some(6.0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_stream_config_filters_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+filters = "acompressor=threshold=-18dB:ratio=3,alimiter=limit=0.9"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.filters.as_deref(),
+            Some("acompressor=threshold=-18dB:ratio=3,alimiter=limit=0.9")
+        );
+        assert!(config.validate().is_ok());
+
+        config.filters = Some("  ".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_stream_config_downmix_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 64
+format = "mp3"
+sample_rate = 44100
+channels = 1
+enabled = true
+
+[downmix]
+pan_law_db = -3.0
+lfe_db = -10.0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.downmix.as_ref().unwrap().pan_law_db, Some(-3.0));
+        assert!(config.validate().is_ok());
+
+        config.downmix.as_mut().unwrap().center_db = Some(3.0);
+        assert!(config.validate().unwrap_err().contains("center_db"));
+    }
+
+    #[test]
+    fn test_stream_config_resampler_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+resampler = "soxr"
+resampler_precision = 28
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.resampler, Some(Resampler::Soxr));
+        assert!(config.validate().is_ok());
+
+        config.resampler_precision = Some(40);
+        assert!(config.validate().unwrap_err().contains("15-33"));
+
+        config.resampler = Some(Resampler::Swr);
+        config.resampler_precision = Some(28);
+        assert!(config.validate().unwrap_err().contains("soxr"));
+
+        config.resampler = None;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_stream_config_gain_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+gain_db = -3.0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.gain_db, Some(-3.0));
+        assert!(config.validate().is_ok());
+
+        config.gain_db = Some(20.0);
+        assert!(config.validate().unwrap_err().contains("-30 to 12"));
+    }
+
+    #[test]
+    fn test_stream_config_encoder_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 128
+format = "aac"
+sample_rate = 44100
+channels = 2
+enabled = true
+encoder = "aac_at"
+threads = 2
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.encoder.as_deref(), Some("aac_at"));
+        assert!(config.validate().is_ok());
+
+        config.threads = Some(0);
+        assert!(config.validate().unwrap_err().contains("1 to 64"));
+
+        config.threads = None;
+        config.encoder = Some("aac at".to_string());
+        assert!(config.validate().unwrap_err().contains("Invalid encoder"));
+    }
+
+    #[test]
+    fn test_stream_config_bitrate_ladder() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+bitrates = [64, 128]
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let mounts = config.mounts("main");
+        assert_eq!(mounts[0].0, "main_64");
+        assert_eq!(mounts[1].0, "main_128");
+        assert_eq!(mounts[1].1.bitrate, 128);
+        assert_eq!(mounts[1].1.bitrates, None);
+
+        config.bitrate = 128;
+        assert!(config.validate().unwrap_err().contains("either"));
+
+        config.bitrate = 0;
+        config.bitrates = Some(vec![64, 512]);
+        assert!(config.validate().unwrap_err().contains("out of range"));
+
+        config.bitrates = Some(vec![64, 64]);
+        assert!(config.validate().unwrap_err().contains("twice"));
+    }
+
+    #[test]
+    fn test_config_validation_ladder_mount_collision() {
+        let mut config = Config::default();
+        let single = config.stream.values().next().unwrap().clone();
+        let ladder = StreamConfig {
+            bitrate: 0,
+            bitrates: Some(vec![64, 128]),
+            ..single.clone()
         };
+        config.stream.clear();
+        config.stream.insert("main".to_string(), ladder);
+        config.stream.insert("main_128".to_string(), single);
+
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("defined twice"));
+    }
+
+    #[test]
+    fn test_stream_config_processing_preset() {
+        let config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+processing = "broadcast"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.processing, Some(ProcessingPreset::Broadcast));
+        assert!(config.validate().is_ok());
+
+        let invalid: Result<StreamConfig, _> = toml::from_str(
+            r#"
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+processing = "loud"
+"#,
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_stream_config_vbr_validation() {
+        let mut config: StreamConfig = toml::from_str(
+            r#"
+bitrate = 192
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+mode = "vbr"
+quality = 2
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, Some(EncodingMode::Vbr));
+        assert!(config.validate().is_ok());
 
+        config.quality = Some(12.0);
+        assert!(config.validate().unwrap_err().contains("out of range"));
+
+        config.quality = None;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("requires a quality"));
+
+        config.format = "opus".to_string();
         assert!(config.validate().is_ok());
+
+        config.mode = Some(EncodingMode::Cbr);
+        config.quality = Some(2.0);
+        assert!(config.validate().is_err());
     }
 
     #[test]
     fn test_stream_config_validation_invalid_format() {
         let config = StreamConfig {
             bitrate: 128,
-            format: "flac".to_string(),
+            format: "wma".to_string(),
             sample_rate: 44100,
             channels: 2,
             enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
         };
 
         let result = config.validate();
@@ -264,6 +1877,73 @@ mod tests {
         assert!(result.unwrap_err().contains("Unsupported audio format"));
     }
 
+    #[test]
+    fn test_stream_config_aac_profile_validation() {
+        let mut config = StreamConfig {
+            bitrate: 32,
+            format: "aac".to_string(),
+            sample_rate: 44100,
+            channels: 2,
+            enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: Some(AacProfile::HeAacV2),
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.channels = 1;
+        assert!(config.validate().unwrap_err().contains("channels = 2"));
+
+        config.aac_profile = Some(AacProfile::HeAac);
+        assert!(config.validate().is_ok());
+
+        config.format = "mp3".to_string();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("only used with format"));
+    }
+
+    #[test]
+    fn test_stream_config_flac_validation() {
+        let mut config = StreamConfig {
+            bitrate: 1000,
+            format: "flac".to_string(),
+            sample_rate: 48000,
+            channels: 2,
+            enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        };
+        assert!(config.validate().is_ok());
+
+        config.mode = Some(EncodingMode::Vbr);
+        assert!(config.validate().unwrap_err().contains("lossless"));
+    }
+
     #[test]
     fn test_stream_config_validation_invalid_bitrate() {
         let config = StreamConfig {
@@ -272,6 +1952,20 @@ mod tests {
             sample_rate: 44100,
             channels: 2,
             enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
         };
 
         let result = config.validate();
@@ -287,6 +1981,20 @@ mod tests {
             sample_rate: 99999,
             channels: 2,
             enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
         };
 
         let result = config.validate();
@@ -302,6 +2010,20 @@ mod tests {
             sample_rate: 44100,
             channels: 5,
             enabled: true,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
         };
 
         let result = config.validate();
@@ -334,6 +2056,20 @@ mod tests {
                 sample_rate: 44100,
                 channels: 2,
                 enabled: true,
+                transition: None,
+                filters: None,
+                mode: None,
+                quality: None,
+                aac_profile: None,
+                processing: None,
+                downmix: None,
+                resampler: None,
+                resampler_precision: None,
+                gain_db: None,
+                stages: None,
+                encoder: None,
+                threads: None,
+                bitrates: None,
             },
         );
 
@@ -360,6 +2096,18 @@ mod tests {
             .contains("At least one stream must be enabled"));
     }
 
+    #[test]
+    fn test_config_validation_all_streams_disabled_in_standby() {
+        let mut config = Config::default();
+        for stream in config.stream.values_mut() {
+            stream.enabled = false;
+        }
+        config.server.standby = Some(true);
+
+        assert!(config.validate().is_ok());
+        assert!(!config.has_enabled_streams());
+    }
+
     #[test]
     fn test_config_from_file_valid() {
         let toml_content = r#"
@@ -449,6 +2197,20 @@ enabled = true
                 sample_rate: 44100,
                 channels: 2,
                 enabled: true,
+                transition: None,
+                filters: None,
+                mode: None,
+                quality: None,
+                aac_profile: None,
+                processing: None,
+                downmix: None,
+                resampler: None,
+                resampler_precision: None,
+                gain_db: None,
+                stages: None,
+                encoder: None,
+                threads: None,
+                bitrates: None,
             };
             assert!(
                 config.validate().is_ok(),
@@ -473,6 +2235,20 @@ enabled = true
                     sample_rate: 44100,
                     channels: 2,
                     enabled: true,
+                    transition: None,
+                    filters: None,
+                    mode: None,
+                    quality: None,
+                    aac_profile: None,
+                    processing: None,
+                    downmix: None,
+                    resampler: None,
+                    resampler_precision: None,
+                    gain_db: None,
+                    stages: None,
+                    encoder: None,
+                    threads: None,
+                    bitrates: None,
                 },
             );
             assert!(
@@ -489,10 +2265,37 @@ enabled = true
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         assert!(program.validate().is_ok());
@@ -504,29 +2307,228 @@ enabled = true
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         let result = program.validate();
         assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .contains("must specify a 'playlist' field"));
+        assert!(result.unwrap_err().contains(
+            "must specify a 'playlist', 'stored_playlist', 'smart_playlist' or 'source_dir' field"
+        ));
     }
 
     #[test]
-    fn test_liveset_program_validation_success() {
-        let program = ScheduleProgram {
+    fn test_playlist_program_validation_stored_playlist() {
+        let mut program = ScheduleProgram {
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "30m".to_string(),
+            program_type: None,
+            playlist: None,
+            stored_playlist: Some("Friday Warmup".to_string()),
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        };
+        assert!(program.validate().is_ok());
+
+        program.playlist = Some("test.m3u".to_string());
+        let result = program.validate();
+        assert!(result.unwrap_err().contains("only one of"));
+    }
+
+    #[test]
+    fn test_playlist_program_validation_source_dir() {
+        let mut program = ScheduleProgram {
+            name: "test".to_string(),
+            active: true,
+            cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "30m".to_string(),
+            program_type: None,
+            playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: Some("/shows/jazz_hour".to_string()),
+            shuffle: Some(true),
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        };
+        assert!(program.validate().is_ok());
+
+        program.stored_playlist = Some("Friday Warmup".to_string());
+        assert!(program.validate().unwrap_err().contains("only one of"));
+
+        program.stored_playlist = None;
+        program.source_dir = None;
+        program.playlist = Some("test.m3u".to_string());
+        assert!(program.validate().unwrap_err().contains("shuffle"));
+    }
+
+    #[test]
+    fn test_playlist_program_validation_smart_playlist() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Jazz Hour"
+active = true
+cron = "0 0 20 * * *"
+duration = "1h"
+smart_playlist = "Jazz Classics"
+shuffle = true
+"#,
+        )
+        .unwrap();
+        assert!(program.validate().is_ok());
+
+        program.stored_playlist = Some("Friday Warmup".to_string());
+        assert!(program.validate().unwrap_err().contains("only one of"));
+    }
+
+    #[test]
+    fn test_podcast_program_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Morning Podcast"
+active = true
+cron = "0 0 7 * * *"
+duration = "1h"
+type = "podcast"
+feed_url = "https://example.com/feed.xml"
+episode = "random"
+"#,
+        )
+        .unwrap();
+        assert_eq!(program.get_type(), ProgramType::Podcast);
+        assert_eq!(program.episode, Some(PodcastEpisodeSelection::Random));
+        assert!(program.validate().is_ok());
+
+        program.feed_url = Some("feed.xml".to_string());
+        assert!(program.validate().unwrap_err().contains("Invalid feed_url"));
+
+        program.feed_url = None;
+        assert!(program.validate().unwrap_err().contains("feed_url"));
+
+        program.program_type = None;
+        program.playlist = Some("test.m3u".to_string());
+        assert!(program
+            .validate()
+            .unwrap_err()
+            .contains("only used by podcast"));
+    }
+
+    #[test]
+    fn test_liveset_program_validation_success() {
+        let program = ScheduleProgram {
+            name: "test".to_string(),
+            active: true,
+            cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("liveset".to_string()),
             playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: Some(vec!["techno".to_string(), "house".to_string()]),
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         assert!(program.validate().is_ok());
@@ -538,10 +2540,37 @@ enabled = true
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("liveset".to_string()),
             playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: Some(vec![]),
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         assert!(program.validate().is_ok());
@@ -553,10 +2582,37 @@ enabled = true
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("liveset".to_string()),
             playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         let result = program.validate();
@@ -566,16 +2622,452 @@ enabled = true
             .contains("must specify a 'genres' field"));
     }
 
+    #[test]
+    fn test_voice_break_validation_requires_single_trigger() {
+        let voice_break = |after_track: Option<u32>, offset: Option<&str>| ProgramVoiceBreak {
+            file: "voice/id.mp3".to_string(),
+            after_track,
+            offset: offset.map(|o| o.to_string()),
+            duck_db: None,
+            duck_fade_seconds: None,
+        };
+        let program = |voice_breaks: Vec<ProgramVoiceBreak>| ScheduleProgram {
+            name: "test".to_string(),
+            active: true,
+            cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "30m".to_string(),
+            program_type: None,
+            playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: Some(voice_breaks),
+            transition: None,
+        };
+
+        assert!(program(vec![
+            voice_break(Some(3), None),
+            voice_break(None, Some("15m"))
+        ])
+        .validate()
+        .is_ok());
+        assert!(program(vec![voice_break(Some(3), Some("15m"))])
+            .validate()
+            .is_err());
+        assert!(program(vec![voice_break(None, None)]).validate().is_err());
+        assert!(program(vec![voice_break(Some(0), None)])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_program_end_fade_validation() {
+        let program = |end_behavior: Option<ProgramEndBehavior>, end_fade_seconds: Option<f64>| {
+            ScheduleProgram {
+                name: "test".to_string(),
+                active: true,
+                cron: "0 0 * * * *".to_string(),
+                every: None,
+                except_dates: None,
+                only_dates: None,
+                duration: "30m".to_string(),
+                program_type: None,
+                playlist: Some("test.m3u".to_string()),
+                stored_playlist: None,
+                smart_playlist: None,
+                source_dir: None,
+                shuffle: None,
+                restricted: None,
+                end_behavior,
+                end_fade_seconds,
+                fade_in_seconds: None,
+                fade_out_seconds: None,
+                priority: None,
+                followed_by: None,
+                metadata: None,
+                intro: None,
+                on_start_cmd: None,
+                on_end_cmd: None,
+                retry_attempts: None,
+                retry_delay_seconds: None,
+                fallback: None,
+                fallback_genres: None,
+                genres: None,
+                min_track_duration: None,
+                feed_url: None,
+                episode: None,
+                voice_breaks: None,
+                transition: None,
+            }
+        };
+
+        assert!(program(Some(ProgramEndBehavior::Fade), Some(5.0))
+            .validate()
+            .is_ok());
+        assert!(program(Some(ProgramEndBehavior::Cut), None)
+            .validate()
+            .is_ok());
+        assert!(program(Some(ProgramEndBehavior::Cut), Some(5.0))
+            .validate()
+            .is_err());
+        assert!(program(Some(ProgramEndBehavior::Fade), Some(60.0))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_program_fade_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 * * * *"
+duration = "30m"
+playlist = "test.m3u"
+fade_in_seconds = 4.0
+fade_out_seconds = 0.0
+"#,
+        )
+        .unwrap();
+        assert!(program.validate().is_ok());
+
+        program.fade_out_seconds = Some(45.0);
+        assert!(program
+            .validate()
+            .unwrap_err()
+            .contains("fade_out_seconds 45 is out of range"));
+        program.fade_out_seconds = None;
+        program.fade_in_seconds = Some(-1.0);
+        assert!(program.validate().is_err());
+    }
+
+    #[test]
+    fn test_program_until_next_duration() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 20 * * *"
+duration = "until_next"
+playlist = "test.m3u"
+"#,
+        )
+        .unwrap();
+        assert!(program.runs_until_next());
+        assert!(program.validate().is_ok());
+
+        program.restricted = Some(true);
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "Restricted programs need a fixed duration"
+        );
+    }
+
+    #[test]
+    fn test_local_liveset_program_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 22 * * *"
+duration = "2h"
+type = "local_liveset"
+genres = ["techno"]
+min_track_duration = "45m"
+"#,
+        )
+        .unwrap();
+        assert_eq!(program.get_type(), ProgramType::LocalLiveset);
+        assert!(program.validate().is_ok());
+
+        program.genres = None;
+        assert!(program
+            .validate()
+            .unwrap_err()
+            .starts_with("Liveset programs must specify a 'genres' field"));
+
+        program.genres = Some(Vec::new());
+        program.program_type = Some("liveset".to_string());
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "min_track_duration is only used by local_liveset programs"
+        );
+    }
+
+    #[test]
+    fn test_program_intro_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Jazz Hour"
+active = true
+cron = "0 0 21 * * *"
+duration = "1h"
+playlist = "jazz.m3u"
+intro = { text = "Up next: {program}" }
+"#,
+        )
+        .unwrap();
+        assert!(program.validate().is_ok());
+
+        program.intro = Some(ProgramIntro {
+            file: Some("/shows/intros/jazz_hour.mp3".to_string()),
+            text: Some("Up next: {program}".to_string()),
+        });
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "intro must specify either 'file' or 'text'"
+        );
+
+        program.intro = Some(ProgramIntro {
+            file: None,
+            text: Some(" ".to_string()),
+        });
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "intro.text must not be empty"
+        );
+    }
+
+    #[test]
+    fn test_program_hook_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 * * * *"
+duration = "30m"
+playlist = "test.m3u"
+on_start_cmd = ["/usr/local/bin/studio-lights", "on"]
+"#,
+        )
+        .unwrap();
+        assert!(program.validate().is_ok());
+
+        program.on_end_cmd = Some(Vec::new());
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "on_end_cmd must start with the program to run"
+        );
+    }
+
+    #[test]
+    fn test_program_retry_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 * * * *"
+duration = "30m"
+type = "liveset"
+genres = ["techno"]
+retry_attempts = 3
+retry_delay_seconds = 10
+fallback = "genres"
+"#,
+        )
+        .unwrap();
+        assert!(program.validate().is_ok());
+        assert_eq!(program.fallback_genres(), Some(vec!["techno".to_string()]));
+
+        program.retry_delay_seconds = Some(0);
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "retry_delay_seconds 0 is out of range. Valid range: 1-3600"
+        );
+
+        program.retry_delay_seconds = None;
+        program.genres = Some(Vec::new());
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "fallback = \"genres\" needs 'fallback_genres' or the 'genres' of a liveset"
+        );
+
+        program.fallback = None;
+        program.fallback_genres = Some(vec!["house".to_string()]);
+        assert_eq!(
+            program.validate().unwrap_err(),
+            "fallback_genres requires fallback = \"genres\""
+        );
+    }
+
+    #[test]
+    fn test_program_on_end_alias() {
+        let program: ScheduleProgram = toml::from_str(
+            r#"
+name = "test"
+active = true
+cron = "0 0 * * * *"
+duration = "30m"
+playlist = "test.m3u"
+on_end = "finish_playlist"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.end_behavior,
+            Some(ProgramEndBehavior::FinishPlaylist)
+        );
+        assert!(program.validate().is_ok());
+    }
+
+    #[test]
+    fn test_program_followed_by_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Music Special"
+active = true
+duration = "1h"
+playlist = "test.m3u"
+followed_by = "Late Night"
+"#,
+        )
+        .unwrap();
+        assert_eq!(program.cron, "");
+        assert!(program.validate().is_ok());
+
+        program.followed_by = Some("Music Special".to_string());
+        assert!(program
+            .validate()
+            .unwrap_err()
+            .contains("followed by itself"));
+
+        program.followed_by = None;
+        program.restricted = Some(true);
+        assert!(program.validate().unwrap_err().contains("cron expression"));
+    }
+
+    #[test]
+    fn test_program_metadata_validation() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Techno Tuesdays"
+active = true
+cron = "0 0 20 * * 3"
+duration = "2h"
+playlist = "techno.m3u"
+
+[metadata]
+station_name = "Techno Tuesdays w/ DJ X"
+genre = "Techno"
+"#,
+        )
+        .unwrap();
+        let metadata = program.metadata.clone().unwrap();
+        assert_eq!(
+            metadata.station_name.as_deref(),
+            Some("Techno Tuesdays w/ DJ X")
+        );
+        assert_eq!(metadata.description, None);
+        assert!(program.validate().is_ok());
+
+        program.metadata.as_mut().unwrap().genre = Some(" ".to_string());
+        assert!(program.validate().unwrap_err().contains("metadata.genre"));
+
+        program.metadata.as_mut().unwrap().genre = Some("Techno\r\nX-Injected: 1".to_string());
+        assert!(program
+            .validate()
+            .unwrap_err()
+            .contains("control characters"));
+    }
+
+    #[test]
+    fn test_program_every_expands_to_cron() {
+        let mut program: ScheduleProgram = toml::from_str(
+            r#"
+name = "News"
+active = true
+every = "3h"
+duration = "5m"
+playlist = "news.m3u"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            program.cron_expression().unwrap().as_deref(),
+            Some("0 0 */3 * * *")
+        );
+        assert!(program.validate().is_ok());
+
+        for (every, cron) in [
+            ("15m", "0 */15 * * * *"),
+            ("60m", "0 0 * * * *"),
+            ("24h", "0 0 0 * * *"),
+        ] {
+            program.every = Some(every.to_string());
+            assert_eq!(program.cron_expression().unwrap().as_deref(), Some(cron));
+        }
+
+        for every in ["45m", "5h", "0h", "2d", "h"] {
+            program.every = Some(every.to_string());
+            assert!(program.validate().unwrap_err().contains("Invalid every"));
+        }
+
+        program.every = Some("3h".to_string());
+        program.cron = "0 0 * * * *".to_string();
+        assert!(program.validate().unwrap_err().contains("only one of"));
+    }
+
     #[test]
     fn test_program_type_defaults_to_playlist() {
         let program = ScheduleProgram {
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: None,
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         assert_eq!(program.get_type(), ProgramType::Playlist);
@@ -587,10 +3079,37 @@ enabled = true
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("liveset".to_string()),
             playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: Some(vec![]),
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         assert_eq!(program.get_type(), ProgramType::Liveset);
@@ -626,7 +3145,7 @@ enabled = true
 
                         // Track program types found
                         match program.get_type() {
-                            ProgramType::Liveset => {
+                            ProgramType::Liveset | ProgramType::LocalLiveset => {
                                 has_liveset_program = true;
                                 assert!(
                                     program.genres.is_some(),
@@ -634,6 +3153,13 @@ enabled = true
                                     program.name
                                 );
                             }
+                            ProgramType::Podcast => {
+                                assert!(
+                                    program.feed_url.is_some(),
+                                    "Podcast program '{}' should have feed_url field",
+                                    program.name
+                                );
+                            }
                             ProgramType::Playlist => {
                                 has_playlist_program = true;
                                 assert!(
@@ -661,4 +3187,356 @@ enabled = true
             }
         }
     }
+
+    #[test]
+    fn test_config_validate_inbox_outside_music_directory() {
+        let mut config = Config::default();
+        config.library.inbox_directory = Some("../uploads".to_string());
+        assert!(config.validate().is_err());
+
+        config.library.inbox_directory = Some("/tmp/uploads".to_string());
+        assert!(config.validate().is_err());
+
+        config.library.inbox_directory = Some("inbox".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_no_repeat_hours() {
+        let mut config = Config::default();
+        config.library.no_repeat_hours = Some(0);
+        assert!(config.validate().is_err());
+
+        config.library.no_repeat_hours = Some(24);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_artist_separation() {
+        let mut config = Config::default();
+        config.library.artist_separation = Some(0);
+        assert!(config.validate().is_err());
+
+        config.library.artist_separation = Some(3);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_history_retention_days() {
+        let mut config = Config::default();
+        config.library.history_retention_days = Some(0);
+        assert!(config.validate().is_err());
+
+        config.library.history_retention_days = Some(30);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_reconcile_hours() {
+        let mut config = Config::default();
+        config.library.reconcile_hours = Some(0);
+        assert!(config.validate().is_err());
+
+        config.library.reconcile_hours = Some(6);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_loudness_target_lufs() {
+        let mut config = Config::default();
+        config.library.loudness_target_lufs = Some(-2.0);
+        assert!(config.validate().is_err());
+
+        config.library.loudness_target_lufs = Some(-16.0);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_http2_keep_alive() {
+        let mut config = Config::default();
+        config.server.http2 = Some(true);
+        config.server.http2_keep_alive_seconds = Some(0);
+        assert!(config.validate().is_err());
+
+        config.server.http2_keep_alive_seconds = Some(30);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_admin_token() {
+        let mut config = Config::default();
+        config.server.admin_token = Some(" ".to_string());
+        assert!(config.validate().is_err());
+
+        config.server.admin_token = Some("s3cret".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_templates_dir() {
+        let mut config = Config::default();
+
+        config.server.templates_dir = Some("/nonexistent/templates".to_string());
+        assert!(config.validate().is_err());
+
+        config.server.templates_dir = Some("templates".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_access_log_format() {
+        let mut config = Config::default();
+        config.server.access_log_format = Some("xml".to_string());
+        assert!(config.validate().is_err());
+
+        config.server.access_log_format = Some("json".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_ads_break_cron() {
+        let mut config = Config {
+            ads: Some(AdsConfig {
+                decision_url: "https://ads.example.com/vast".to_string(),
+                break_cron: "not a cron".to_string(),
+                max_ads_per_break: None,
+                timeout_seconds: None,
+            }),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.ads.as_mut().unwrap().break_cron = "0 0 * * * *".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_ondemand_settings() {
+        let mut config = Config {
+            ondemand: Some(OnDemandConfig {
+                directory: "/srv/archive".to_string(),
+                format: Some("wav".to_string()),
+                bitrate: None,
+            }),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.ondemand.as_mut().unwrap().format = None;
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.ondemand.unwrap().stream_settings().format,
+            "mp3".to_string()
+        );
+    }
+
+    #[test]
+    fn test_config_validate_restricted_program_requires_access() {
+        let fallback = tempfile::NamedTempFile::new().unwrap();
+        let program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Members Hour"
+active = true
+cron = "0 0 20 * * *"
+duration = "1h"
+playlist = "/playlists/members.m3u"
+restricted = true
+"#,
+        )
+        .unwrap();
+        let mut config = Config {
+            schedule: Some(ScheduleConfig {
+                programs: vec![program],
+                align_to_programs: None,
+                align_tolerance_seconds: None,
+                on_overlap: None,
+            }),
+            ..Default::default()
+        };
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("requires an [access] section"));
+
+        config.access = Some(AccessConfig {
+            tokens: vec!["".to_string()],
+            fallback: fallback.path().to_string_lossy().to_string(),
+        });
+        assert!(config.validate().is_err());
+
+        config.access.as_mut().unwrap().tokens = vec!["member-token".to_string()];
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_dayparts() {
+        let config: Config = toml::from_str(
+            r#"
+[server]
+port = 8000
+bind_address = "0.0.0.0"
+
+[library]
+music_directory = "/music"
+shuffle = true
+repeat = true
+
+[station]
+station_name = "Test"
+description = "Test"
+genre = "Various"
+url = "http://localhost"
+
+[stream.default]
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+
+[[dayparts]]
+name = "Mornings"
+start = "06:00"
+end = "10:00"
+genres = ["chillout", "jazz"]
+
+[[dayparts]]
+name = "Nights"
+start = "22:00"
+end = "04:00"
+genres = ["techno"]
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let mut overlapping = config.clone();
+        overlapping.dayparts.as_mut().unwrap()[0].start = "03:00".to_string();
+        assert_eq!(
+            overlapping.validate().unwrap_err().to_string(),
+            "Dayparts 'Mornings' and 'Nights' overlap"
+        );
+
+        let mut invalid = config.clone();
+        invalid.dayparts.as_mut().unwrap()[1].end = "4 am".to_string();
+        assert!(invalid
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("invalid time '4 am'"));
+
+        let mut empty = config.clone();
+        empty.dayparts.as_mut().unwrap()[0].genres.clear();
+        assert!(empty.validate().is_err());
+
+        // Tempo and energy ranges replace genres, but need the tempo analysis
+        let mut tempo = empty;
+        tempo.dayparts.as_mut().unwrap()[0].max_energy = Some(0.4);
+        assert!(tempo
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("needs library.tempo_analysis"));
+        tempo.library.tempo_analysis = Some(true);
+        assert!(tempo.validate().is_ok());
+        tempo.dayparts.as_mut().unwrap()[0].min_energy = Some(0.6);
+        assert_eq!(
+            tempo.validate().unwrap_err().to_string(),
+            "Daypart 'Mornings': min_energy must not exceed max_energy"
+        );
+        tempo.dayparts.as_mut().unwrap()[0].min_energy = None;
+        tempo.dayparts.as_mut().unwrap()[1].min_bpm = Some(0.0);
+        assert!(tempo.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_validate_tempo_matching() {
+        let mut config = Config::default();
+        config.library.tempo_matching = Some(true);
+        assert!(config.validate().is_err());
+
+        config.library.tempo_analysis = Some(true);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validate_jingles() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            jingles: Some(JinglesConfig {
+                directory: directory.path().to_string_lossy().to_string(),
+                every_n_tracks: Some(4),
+                every_minutes: None,
+            }),
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.jingles.as_mut().unwrap().every_minutes = Some(15);
+        assert!(config.validate().is_err());
+
+        config.jingles.as_mut().unwrap().every_n_tracks = None;
+        assert!(config.validate().is_ok());
+
+        config.jingles.as_mut().unwrap().every_minutes = Some(0);
+        assert!(config.validate().is_err());
+
+        config.jingles.as_mut().unwrap().every_minutes = None;
+        assert!(config.validate().is_err());
+
+        config.jingles = Some(JinglesConfig {
+            directory: "/nonexistent/jingles".to_string(),
+            every_n_tracks: Some(4),
+            every_minutes: None,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_validate_station_id() {
+        let file = NamedTempFile::new().unwrap();
+        let mut config = Config {
+            station_id: Some(StationIdConfig {
+                file: file.path().to_string_lossy().to_string(),
+                tolerance_seconds: Some(90),
+            }),
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.station_id.as_mut().unwrap().tolerance_seconds = Some(1800);
+        assert!(config.validate().is_err());
+
+        config.station_id = Some(StationIdConfig {
+            file: "/nonexistent/station-id.mp3".to_string(),
+            tolerance_seconds: None,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_validate_announcements() {
+        let mut config = Config {
+            announcements: Some(AnnouncementsConfig {
+                command: vec![
+                    "espeak-ng".to_string(),
+                    "-w".to_string(),
+                    "{output}".to_string(),
+                ],
+                every_n_tracks: 3,
+                template: None,
+                extension: None,
+            }),
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.announcements.as_mut().unwrap().every_n_tracks = 0;
+        assert!(config.validate().is_err());
+
+        config.announcements.as_mut().unwrap().every_n_tracks = 3;
+        config.announcements.as_mut().unwrap().command = vec!["espeak-ng".to_string()];
+        assert!(config.validate().is_err());
+    }
 }
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+use warp::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use warp::reply::Response;
+use warp::Reply;
+
+/// Response format of the statistics endpoints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    /// Spreadsheet friendly CSV download
+    Csv,
+}
+
+/// A record exported as one CSV row
+pub trait CsvRecord {
+    const HEADER: &'static [&'static str];
+
+    /// Fields in the order of `HEADER`
+    fn fields(&self) -> Vec<String>;
+}
+
+/// RFC 4180 CSV of the records, with a header row and CRLF line breaks
+pub fn to_csv<T: CsvRecord>(records: &[T]) -> String {
+    let mut csv = String::new();
+    push_row(&mut csv, T::HEADER.iter().map(|h| h.to_string()));
+    for record in records {
+        push_row(&mut csv, record.fields());
+    }
+    csv
+}
+
+/// CSV download of the records, saved as `filename` by browsers
+pub fn csv_reply<T: CsvRecord>(records: &[T], filename: &str) -> Response {
+    let reply = warp::reply::with_header(to_csv(records), CONTENT_TYPE, "text/csv; charset=utf-8");
+    warp::reply::with_header(
+        reply,
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}\"", sanitize_filename(filename)),
+    )
+    .into_response()
+}
+
+fn push_row(csv: &mut String, fields: impl IntoIterator<Item = String>) {
+    let row: Vec<String> = fields.into_iter().map(|f| escape(&f)).collect();
+    csv.push_str(&row.join(","));
+    csv.push_str("\r\n");
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keeps the filename safe inside the quoted header value
+fn sanitize_filename(filename: &str) -> String {
+    filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Row(&'static str, u32);
+
+    impl CsvRecord for Row {
+        const HEADER: &'static [&'static str] = &["name", "count"];
+
+        fn fields(&self) -> Vec<String> {
+            vec![self.0.to_string(), self.1.to_string()]
+        }
+    }
+
+    #[test]
+    fn given_fields_with_separators_when_exporting_then_quotes_them() {
+        let csv = to_csv(&[Row("Morning Show", 3), Row("Rock, \"Live\"", 7)]);
+
+        assert_eq!(
+            csv,
+            "name,count\r\nMorning Show,3\r\n\"Rock, \"\"Live\"\"\",7\r\n"
+        );
+        assert_eq!(to_csv::<Row>(&[]), "name,count\r\n");
+    }
+
+    #[test]
+    fn given_filename_with_unsafe_characters_when_replying_then_sanitizes_it() {
+        let response = csv_reply(&[Row("a", 1)], "listeners-\"high\".csv");
+
+        assert_eq!(
+            response.headers()[CONTENT_DISPOSITION],
+            "attachment; filename=\"listeners-_high_.csv\""
+        );
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/csv; charset=utf-8");
+    }
+}
@@ -0,0 +1,311 @@
+use crate::library_db::TrackRecord;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Frames per second of the `mm:ss:ff` positions of a cue sheet
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+/// Separates the album file and the track number in the library path of a cue track
+const TRACK_SEPARATOR: &str = "#track";
+
+/// An audio track of a cue sheet
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Position of `INDEX 01` in the album file
+    pub start_seconds: f64,
+}
+
+/// Cue sheet describing the tracks of an album ripped to a single audio file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSheet {
+    pub performer: Option<String>,
+    /// Album title
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    /// Audio file as named in the sheet, relative to the sheet
+    pub file: String,
+    /// Audio tracks in playout order
+    pub tracks: Vec<CueTrack>,
+}
+
+impl CueSheet {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        // Sheets written by older rippers are often Latin-1
+        let content = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut sheet = CueSheet {
+            performer: None,
+            title: None,
+            genre: None,
+            year: None,
+            file: String::new(),
+            tracks: Vec::new(),
+        };
+        // Track being read, `None` before the first and for data tracks, and its `INDEX 01`
+        let mut track: Option<CueTrack> = None;
+        let mut start: Option<f64> = None;
+        let mut in_track = false;
+
+        for line in content.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            let (command, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    if !sheet.file.is_empty() {
+                        return Err("Cue sheets of several audio files are not supported".into());
+                    }
+                    sheet.file = file_name(value);
+                }
+                "TRACK" => {
+                    sheet.push_track(track.take(), start.take());
+                    in_track = true;
+                    let (number, kind) = value.split_once(' ').unwrap_or((value, ""));
+                    if kind.trim().eq_ignore_ascii_case("AUDIO") {
+                        let number = number
+                            .parse()
+                            .map_err(|_| format!("Invalid track number: {}", number))?;
+                        track = Some(CueTrack {
+                            number,
+                            title: None,
+                            performer: None,
+                            start_seconds: 0.0,
+                        });
+                    }
+                }
+                "TITLE" if in_track => {
+                    if let Some(track) = track.as_mut() {
+                        track.title = Some(unquote(value));
+                    }
+                }
+                "PERFORMER" if in_track => {
+                    if let Some(track) = track.as_mut() {
+                        track.performer = Some(unquote(value));
+                    }
+                }
+                "INDEX" => {
+                    let (index, position) = value.split_once(' ').unwrap_or((value, ""));
+                    if let (Ok(1), Some(_)) = (index.parse::<u32>(), &track) {
+                        start = Some(
+                            position_seconds(position.trim())
+                                .ok_or_else(|| format!("Invalid index position: {}", position))?,
+                        );
+                    }
+                }
+                "TITLE" => sheet.title = Some(unquote(value)),
+                "PERFORMER" => sheet.performer = Some(unquote(value)),
+                "REM" => {
+                    let (key, value) = value.split_once(' ').unwrap_or((value, ""));
+                    match key.to_ascii_uppercase().as_str() {
+                        "GENRE" => sheet.genre = Some(unquote(value.trim())),
+                        "DATE" => sheet.year = value.trim().get(..4).and_then(|y| y.parse().ok()),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        sheet.push_track(track, start);
+
+        if sheet.file.is_empty() {
+            return Err("The cue sheet names no audio file".into());
+        }
+        if sheet.tracks.is_empty() {
+            return Err("The cue sheet has no audio tracks".into());
+        }
+        Ok(sheet)
+    }
+
+    fn push_track(&mut self, track: Option<CueTrack>, start_seconds: Option<f64>) {
+        // Tracks without an index are not in the file
+        if let (Some(track), Some(start_seconds)) = (track, start_seconds) {
+            self.tracks.push(CueTrack {
+                start_seconds,
+                ..track
+            });
+        }
+    }
+
+    /// Audio file of the album, the sheet names it relative to its own directory
+    pub fn audio_file(&self, sheet_path: &Path) -> PathBuf {
+        sheet_path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&self.file)
+    }
+
+    /// Where each track ends: the start of the next one, `None` for the last track which
+    /// plays to the end of the file
+    pub fn track_ends(&self) -> Vec<Option<f64>> {
+        self.tracks
+            .iter()
+            .skip(1)
+            .map(|next| Some(next.start_seconds))
+            .chain([None])
+            .collect()
+    }
+}
+
+/// `FILE "Album.flac" WAVE` names the file in quotes, followed by its type
+fn file_name(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|rest| rest.split_once('"'))
+    {
+        Some((name, _)) => name.to_string(),
+        None => value
+            .rsplit_once(' ')
+            .map_or(value, |(name, _)| name)
+            .to_string(),
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Seconds of a `mm:ss:ff` position, frames are 1/75 s
+fn position_seconds(position: &str) -> Option<f64> {
+    let mut parts = position.split(':').map(|part| part.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= 75 {
+        return None;
+    }
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+pub fn is_cue_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"))
+}
+
+/// Library path of a track of a single-file album, e.g. `/music/Album.flac#track03`
+pub fn track_path(audio_file: &Path, number: u32) -> PathBuf {
+    PathBuf::from(format!(
+        "{}{}{:02}",
+        audio_file.to_string_lossy(),
+        TRACK_SEPARATOR,
+        number
+    ))
+}
+
+/// File a library track is played from: the album file of a cue track, the path itself for
+/// all other tracks
+pub fn audio_file(track: &Path) -> PathBuf {
+    let path = track.to_string_lossy();
+    match path.rsplit_once(TRACK_SEPARATOR) {
+        Some((file, number))
+            if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            PathBuf::from(file)
+        }
+        _ => track.to_path_buf(),
+    }
+}
+
+/// Part of an album file a cue track plays
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSection {
+    pub file: PathBuf,
+    pub start_seconds: f64,
+    /// `None` plays to the end of the file
+    pub end_seconds: Option<f64>,
+}
+
+impl TrackSection {
+    /// Section of a library track, `None` for tracks that are a whole file
+    pub fn of(track: &TrackRecord) -> Option<Self> {
+        Some(Self {
+            file: audio_file(Path::new(&track.file_path)),
+            start_seconds: track.start_seconds?,
+            end_seconds: track.end_seconds,
+        })
+    }
+
+    /// FFmpeg input options decoding only the section, they go before `-i`
+    pub fn input_args(&self) -> Vec<String> {
+        let mut args = vec!["-ss".to_string(), format!("{:.3}", self.start_seconds)];
+        if let Some(end) = self.end_seconds {
+            args.extend(["-to".to_string(), format!("{:.3}", end)]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE \"Electronic\"
+REM DATE 2001
+PERFORMER \"Daft Punk\"
+TITLE \"Discovery\"
+FILE \"Daft Punk - Discovery.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"One More Time\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Aerodynamic\"
+    PERFORMER \"Daft Punk feat. Romanthony\"
+    INDEX 00 05:18:50
+    INDEX 01 05:20:37
+  TRACK 03 MODE1/2352
+    INDEX 01 08:52:00
+";
+
+    #[test]
+    fn given_cue_sheet_when_parsed_then_reads_album_and_audio_tracks() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+
+        assert_eq!(sheet.performer.as_deref(), Some("Daft Punk"));
+        assert_eq!(sheet.title.as_deref(), Some("Discovery"));
+        assert_eq!(sheet.genre.as_deref(), Some("Electronic"));
+        assert_eq!(sheet.year, Some(2001));
+        assert_eq!(
+            sheet.audio_file(Path::new("/music/discovery.cue")),
+            PathBuf::from("/music/Daft Punk - Discovery.flac")
+        );
+        assert_eq!(sheet.tracks.len(), 2);
+        assert_eq!(sheet.tracks[1].title.as_deref(), Some("Aerodynamic"));
+        assert_eq!(
+            sheet.tracks[1].performer.as_deref(),
+            Some("Daft Punk feat. Romanthony")
+        );
+        assert!((sheet.tracks[1].start_seconds - 320.4933).abs() < 0.001);
+        assert_eq!(
+            sheet.track_ends(),
+            [Some(sheet.tracks[1].start_seconds), None]
+        );
+
+        assert!(CueSheet::parse("TRACK 01 AUDIO\nINDEX 01 00:00:00").is_err());
+        assert!(CueSheet::parse("FILE \"a.flac\" WAVE\nFILE \"b.flac\" WAVE").is_err());
+    }
+
+    #[test]
+    fn given_cue_track_when_resolving_then_plays_its_section_of_the_album_file() {
+        let album = Path::new("/music/Discovery.flac");
+        let path = track_path(album, 3);
+        assert_eq!(path, PathBuf::from("/music/Discovery.flac#track03"));
+        assert_eq!(audio_file(&path), album);
+        assert_eq!(audio_file(album), album);
+
+        let section = TrackSection {
+            file: album.to_path_buf(),
+            start_seconds: 320.5,
+            end_seconds: Some(532.0),
+        };
+        assert_eq!(section.input_args(), ["-ss", "320.500", "-to", "532.000"]);
+    }
+}
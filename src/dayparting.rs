@@ -0,0 +1,252 @@
+use crate::audio_metadata::TrackMetadata;
+use crate::config::DaypartConfig;
+use crate::tempo_analysis::TrackTempo;
+use chrono::NaiveTime;
+use log::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Limits the library rotation to tracks of some genres, tempo or energy at certain times of
+/// the day, e.g. calm chillout in the morning and energetic techno at night. Outside of the
+/// dayparts all tracks play.
+///
+/// The genres come from the tags of the tracks, see [`GenreTags`], tempo and energy from the
+/// tempo analysis of the scan.
+pub struct Dayparting {
+    dayparts: Vec<Daypart>,
+    tags: GenreTags,
+    /// Daypart of the last selection, to log when it changes
+    current: Option<String>,
+}
+
+/// Genres of library tracks, read from their tags when a track is first looked at and
+/// remembered afterwards
+pub struct GenreTags {
+    /// Lowercase genres of the tracks looked at so far
+    genres: HashMap<PathBuf, Vec<String>>,
+    read_genre: fn(&Path) -> Option<String>,
+}
+
+struct Daypart {
+    name: String,
+    start: NaiveTime,
+    end: NaiveTime,
+    genres: Vec<String>,
+    bpm: (Option<f64>, Option<f64>),
+    energy: (Option<f64>, Option<f64>),
+}
+
+impl Daypart {
+    /// Whether the tempo lies in the ranges of the daypart, unknown values are outside of a range
+    fn fits_tempo(&self, tempo: &TrackTempo) -> bool {
+        let within = |value: Option<f64>, (min, max): (Option<f64>, Option<f64>)| {
+            if min.is_none() && max.is_none() {
+                return true;
+            }
+            value.is_some_and(|v| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max))
+        };
+        within(tempo.bpm, self.bpm) && within(tempo.energy, self.energy)
+    }
+
+    /// What the daypart plays, for the log
+    fn describe(&self) -> String {
+        let range = |name: &str, (min, max): (Option<f64>, Option<f64>)| match (min, max) {
+            (Some(min), Some(max)) => Some(format!("{} {} to {}", name, min, max)),
+            (Some(min), None) => Some(format!("{} from {}", name, min)),
+            (None, Some(max)) => Some(format!("{} up to {}", name, max)),
+            (None, None) => None,
+        };
+        let mut parts = Vec::new();
+        if !self.genres.is_empty() {
+            parts.push(self.genres.join(", "));
+        }
+        parts.extend(range("bpm", self.bpm));
+        parts.extend(range("energy", self.energy));
+        parts.join(", ")
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            // Past midnight
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl Dayparting {
+    pub fn new(config: &[DaypartConfig]) -> Self {
+        let dayparts = config
+            .iter()
+            .map(|daypart| {
+                let (start, end) = daypart
+                    .times()
+                    .expect("Daypart times should be valid after validation");
+                Daypart {
+                    name: daypart.name.clone(),
+                    start,
+                    end,
+                    genres: split_all(&daypart.genres),
+                    bpm: (daypart.min_bpm, daypart.max_bpm),
+                    energy: (daypart.min_energy, daypart.max_energy),
+                }
+            })
+            .collect();
+
+        Self {
+            dayparts,
+            tags: GenreTags::new(),
+            current: None,
+        }
+    }
+
+    /// Whether the library track of the tempo may play at `time`
+    pub fn allows(&mut self, track: &Path, tempo: &TrackTempo, time: NaiveTime) -> bool {
+        let Some(index) = self.dayparts.iter().position(|d| d.contains(time)) else {
+            if let Some(name) = self.current.take() {
+                info!("Daypart '{}' ended, playing the whole library", name);
+            }
+            return true;
+        };
+
+        let daypart = &self.dayparts[index];
+        if self.current.as_deref() != Some(daypart.name.as_str()) {
+            info!(
+                "Daypart '{}' started, playing {} from the library",
+                daypart.name,
+                daypart.describe()
+            );
+            self.current = Some(daypart.name.clone());
+        }
+
+        daypart.fits_tempo(tempo)
+            && (daypart.genres.is_empty() || self.tags.matches(track, &daypart.genres))
+    }
+}
+
+impl Default for GenreTags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GenreTags {
+    pub fn new() -> Self {
+        Self {
+            genres: HashMap::new(),
+            read_genre: |path| TrackMetadata::from_file(path).genre,
+        }
+    }
+
+    /// Whether the track is tagged with one of the genres, given lowercase as returned by
+    /// [`split_all`]
+    pub fn matches(&mut self, track: &Path, genres: &[String]) -> bool {
+        let read_genre = self.read_genre;
+        self.genres
+            .entry(track.to_path_buf())
+            .or_insert_with(|| {
+                read_genre(track)
+                    .map(|g| split_genres(&g))
+                    .unwrap_or_default()
+            })
+            .iter()
+            .any(|genre| genres.contains(genre))
+    }
+}
+
+/// Lowercase genres of configured genre names, each of which may list several
+pub fn split_all(genres: &[String]) -> Vec<String> {
+    genres.iter().flat_map(|g| split_genres(g)).collect()
+}
+
+/// Genres of a tag, which may list several, e.g. `Jazz; Chillout`
+pub fn split_genres(tag: &str) -> Vec<String> {
+    tag.split([';', ',', '/'])
+        .map(|genre| genre.trim().to_lowercase())
+        .filter(|genre| !genre.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn daypart(name: &str, start: &str, end: &str, genres: &[&str]) -> DaypartConfig {
+        DaypartConfig {
+            name: name.to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            genres: genres.iter().map(|g| g.to_string()).collect(),
+            min_bpm: None,
+            max_bpm: None,
+            min_energy: None,
+            max_energy: None,
+        }
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn given_dayparts_when_selecting_tracks_then_only_their_genres_play() {
+        let mut dayparting = Dayparting {
+            tags: GenreTags {
+                genres: HashMap::new(),
+                read_genre: |path| match path.to_str() {
+                    Some("/music/calm.mp3") => Some("Jazz; Chillout".to_string()),
+                    Some("/music/loud.mp3") => Some("Techno".to_string()),
+                    _ => None,
+                },
+            },
+            ..Dayparting::new(&[
+                daypart("Mornings", "06:00", "10:00", &["chillout", "Jazz"]),
+                daypart("Nights", "22:00", "04:00", &["techno"]),
+            ])
+        };
+        let (calm, loud, untagged) = (
+            Path::new("/music/calm.mp3"),
+            Path::new("/music/loud.mp3"),
+            Path::new("/music/untagged.mp3"),
+        );
+        let unknown = TrackTempo::default();
+
+        assert!(dayparting.allows(calm, &unknown, time(6, 0)));
+        assert!(!dayparting.allows(loud, &unknown, time(9, 59)));
+        assert!(!dayparting.allows(untagged, &unknown, time(7, 0)));
+
+        assert!(dayparting.allows(loud, &unknown, time(1, 30)));
+        assert!(!dayparting.allows(calm, &unknown, time(23, 0)));
+
+        assert!(dayparting.allows(untagged, &unknown, time(12, 0)));
+        assert!(dayparting.allows(loud, &unknown, time(10, 0)));
+    }
+
+    #[test]
+    fn given_tempo_dayparts_when_selecting_tracks_then_only_fitting_tempos_play() {
+        let mut mornings = daypart("Mornings", "06:00", "10:00", &[]);
+        mornings.max_energy = Some(0.4);
+        let mut nights = daypart("Nights", "22:00", "04:00", &["techno"]);
+        nights.min_bpm = Some(120.0);
+        let mut dayparting = Dayparting {
+            tags: GenreTags {
+                genres: HashMap::new(),
+                read_genre: |_| Some("Techno".to_string()),
+            },
+            ..Dayparting::new(&[mornings, nights])
+        };
+        let track = Path::new("/music/track.mp3");
+        let tempo = |bpm: f64, energy: f64| TrackTempo {
+            bpm: Some(bpm),
+            energy: Some(energy),
+        };
+
+        assert!(dayparting.allows(track, &tempo(90.0, 0.3), time(7, 0)));
+        assert!(!dayparting.allows(track, &tempo(90.0, 0.8), time(7, 0)));
+        assert!(!dayparting.allows(track, &TrackTempo::default(), time(7, 0)));
+
+        assert!(dayparting.allows(track, &tempo(128.0, 0.8), time(23, 0)));
+        assert!(!dayparting.allows(track, &tempo(100.0, 0.8), time(23, 0)));
+    }
+}
@@ -0,0 +1,515 @@
+use crate::artist_names::{artist_key, main_artist};
+use log::info;
+use rusqlite::{Connection, Transaction};
+use std::error::Error;
+
+type MigrationResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// A change of the library database schema
+struct Migration {
+    description: &'static str,
+    apply: fn(&Transaction) -> MigrationResult,
+}
+
+/// Schema changes in the order they are applied. The schema version, stored as SQLite
+/// `user_version`, is the number of applied migrations.
+///
+/// Append new migrations and never change released ones: a database of an older version applies
+/// the migrations after its version on the next start and keeps its data.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "Library schema",
+        apply: baseline,
+    },
+    Migration {
+        description: "Track loudness",
+        apply: track_loudness,
+    },
+    Migration {
+        description: "Track tempo and energy",
+        apply: track_tempo,
+    },
+    Migration {
+        description: "Directory modification times",
+        apply: scanned_directories,
+    },
+    Migration {
+        description: "Playlist files of the library",
+        apply: library_playlists,
+    },
+    Migration {
+        description: "Normalized artists",
+        apply: artists,
+    },
+    Migration {
+        description: "Tracks of single-file albums",
+        apply: track_sections,
+    },
+];
+
+/// Brings the database to the latest schema version, each migration in its own transaction
+pub fn migrate(conn: &mut Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let version = schema_version(conn)?;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "The database has schema version {}, this release supports up to version {}",
+            version,
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx).map_err(|e| {
+            format!(
+                "Database migration to version {} ({}) failed: {}",
+                index + 1,
+                migration.description,
+                e
+            )
+        })?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+        info!(
+            "Migrated the database to schema version {}: {}",
+            index + 1,
+            migration.description
+        );
+    }
+
+    Ok(())
+}
+
+/// Number of applied migrations, 0 for new databases and those of releases before versioning
+pub fn schema_version(conn: &Connection) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(usize::try_from(version)?)
+}
+
+/// Adds a column unless the table has it, returns whether it was added
+fn add_column(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let exists = tx
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists([table, column])?;
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(!exists)
+}
+
+/// Version 1: the schema of the releases before versioned migrations. Their databases have
+/// version 0 and any subset of these tables and columns, so only missing ones are created.
+fn baseline(tx: &Transaction) -> MigrationResult {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tracks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_path TEXT NOT NULL UNIQUE,
+            title TEXT NOT NULL,
+            artist TEXT NOT NULL,
+            album TEXT NOT NULL,
+            duration_seconds INTEGER,
+            file_size INTEGER NOT NULL,
+            last_modified INTEGER NOT NULL,
+            file_extension TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            failure_count INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            quarantined INTEGER NOT NULL DEFAULT 0,
+            genre TEXT,
+            artwork_path TEXT,
+            rating INTEGER,
+            year INTEGER,
+            content_hash TEXT,
+            audio_fingerprint TEXT,
+            validation_error TEXT
+        )",
+        [],
+    )?;
+
+    // Columns added to libraries before versioned migrations, filled by reading all tracks
+    // again
+    for (column, definition) in [
+        ("genre", "TEXT"),
+        ("artwork_path", "TEXT"),
+        ("year", "INTEGER"),
+        ("content_hash", "TEXT"),
+    ] {
+        if add_column(tx, "tracks", column, definition)? {
+            info!("Added the {} column, all tracks are read again", column);
+            // The next incremental scan reads every track
+            tx.execute("UPDATE tracks SET last_modified = 0", [])?;
+        }
+    }
+
+    // Columns added to libraries before versioned migrations, not read by a regular scan
+    for (column, definition) in [
+        ("rating", "INTEGER"),
+        ("audio_fingerprint", "TEXT"),
+        ("validation_error", "TEXT"),
+    ] {
+        add_column(tx, "tracks", column, definition)?;
+    }
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_file_path ON tracks(file_path)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_artist ON tracks(artist)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_album ON tracks(album)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_last_modified ON tracks(last_modified)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_content_hash ON tracks(content_hash)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_audio_fingerprint ON tracks(audio_fingerprint)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS library_metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS playlists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS playlist_tracks (
+            playlist_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            PRIMARY KEY (playlist_id, position)
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS smart_playlists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            query TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS pending_deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            webhook_id TEXT NOT NULL,
+            url TEXT NOT NULL,
+            event TEXT NOT NULL,
+            body TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER NOT NULL,
+            last_error TEXT,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS program_audiences (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            program TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER NOT NULL,
+            average_listeners REAL NOT NULL,
+            peak_listeners INTEGER NOT NULL,
+            joins INTEGER NOT NULL,
+            leaves INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_program_audiences_program
+         ON program_audiences(program, started_at)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS recent_plays (
+            track_id INTEGER PRIMARY KEY,
+            played_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS cue_points (
+            track_id INTEGER PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+            intro_seconds REAL,
+            outro_seconds REAL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS play_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            played_at INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            program TEXT,
+            streams TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            track_id INTEGER,
+            title TEXT NOT NULL,
+            artist TEXT NOT NULL,
+            album TEXT NOT NULL,
+            duration_seconds INTEGER
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_play_history_played_at ON play_history(played_at)",
+        [],
+    )?;
+
+    // Full-text index over title, artist and album, kept in sync by the triggers below
+    let search_exists = tx
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'tracks_fts'")?
+        .exists([])?;
+    tx.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS tracks_fts USING fts5(
+            title, artist, album,
+            content = 'tracks', content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        )",
+        [],
+    )?;
+    if !search_exists {
+        info!("Building the full-text search index of the library");
+        tx.execute("INSERT INTO tracks_fts(tracks_fts) VALUES ('rebuild')", [])?;
+    }
+
+    tx.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS tracks_fts_insert AFTER INSERT ON tracks BEGIN
+            INSERT INTO tracks_fts(rowid, title, artist, album)
+            VALUES (new.id, new.title, new.artist, new.album);
+         END;
+         CREATE TRIGGER IF NOT EXISTS tracks_fts_delete AFTER DELETE ON tracks BEGIN
+            INSERT INTO tracks_fts(tracks_fts, rowid, title, artist, album)
+            VALUES ('delete', old.id, old.title, old.artist, old.album);
+         END;
+         CREATE TRIGGER IF NOT EXISTS tracks_fts_update
+         AFTER UPDATE OF title, artist, album ON tracks BEGIN
+            INSERT INTO tracks_fts(tracks_fts, rowid, title, artist, album)
+            VALUES ('delete', old.id, old.title, old.artist, old.album);
+            INSERT INTO tracks_fts(rowid, title, artist, album)
+            VALUES (new.id, new.title, new.artist, new.album);
+         END;",
+    )?;
+
+    Ok(())
+}
+
+/// Version 2: integrated loudness measured by the background loudness analysis
+fn track_loudness(tx: &Transaction) -> MigrationResult {
+    add_column(tx, "tracks", "loudness_lufs", "REAL")?;
+    add_column(tx, "tracks", "loudness_analyzed_at", "INTEGER")?;
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_loudness_analyzed_at
+         ON tracks(loudness_analyzed_at)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 3: tempo and energy estimated by the tempo analysis of the scan
+fn track_tempo(tx: &Transaction) -> MigrationResult {
+    add_column(tx, "tracks", "bpm", "REAL")?;
+    add_column(tx, "tracks", "energy", "REAL")?;
+    Ok(())
+}
+
+/// Version 4: modification times of the directories seen by the last scan
+fn scanned_directories(tx: &Transaction) -> MigrationResult {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS scanned_directories (
+            path TEXT PRIMARY KEY,
+            modified_nanos INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 5: playlists indexed from M3U and PLS files of the music directory
+fn library_playlists(tx: &Transaction) -> MigrationResult {
+    add_column(tx, "playlists", "source_path", "TEXT")?;
+    add_column(
+        tx,
+        "playlists",
+        "unresolved_entries",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    tx.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_playlists_source_path ON playlists(source_path)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 6: artists of the tracks, spellings differing in case and featured artists are one
+/// artist. Links the existing tracks.
+fn artists(tx: &Transaction) -> MigrationResult {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS artists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            normalized_name TEXT NOT NULL UNIQUE
+        )",
+        [],
+    )?;
+    add_column(tx, "tracks", "artist_id", "INTEGER REFERENCES artists(id)")?;
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tracks_artist_id ON tracks(artist_id)",
+        [],
+    )?;
+
+    let tracks: Vec<(i64, String)> = tx
+        .prepare("SELECT id, artist FROM tracks")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (id, artist) in tracks {
+        let Some(key) = artist_key(&artist) else {
+            continue;
+        };
+        tx.execute(
+            "INSERT OR IGNORE INTO artists (name, normalized_name) VALUES (?1, ?2)",
+            [main_artist(&artist), key.clone()],
+        )?;
+        tx.execute(
+            "UPDATE tracks SET artist_id = (SELECT id FROM artists WHERE normalized_name = ?1)
+             WHERE id = ?2",
+            rusqlite::params![key, id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Version 7: where the tracks of albums ripped to a single file with a cue sheet start and end
+/// in that file
+fn track_sections(tx: &Transaction) -> MigrationResult {
+    add_column(tx, "tracks", "start_seconds", "REAL")?;
+    add_column(tx, "tracks", "end_seconds", "REAL")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn given_new_database_when_migrated_twice_then_reaches_latest_version_once() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO library_metadata (key, value, updated_at) VALUES ('kept', 'yes', 0)",
+            [],
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        let kept: String = conn
+            .query_row(
+                "SELECT value FROM library_metadata WHERE key = 'kept'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kept, "yes");
+    }
+
+    #[test]
+    fn given_database_of_newer_release_when_migrating_then_refuses_it() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        let error = migrate(&mut conn).unwrap_err().to_string();
+
+        assert!(error.contains("supports up to version"));
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len() + 1);
+    }
+
+    #[test]
+    fn given_tracks_of_an_earlier_version_when_migrating_then_links_them_to_their_artists() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+        migrate(&mut conn).unwrap();
+        for (path, artist) in [
+            ("/a.mp3", "Daft Punk"),
+            ("/b.mp3", "daft punk feat. Pharrell"),
+            ("/c.mp3", "Unknown Artist"),
+        ] {
+            conn.execute(
+                "INSERT INTO tracks (file_path, title, artist, album, file_size, last_modified,
+                    file_extension, created_at, updated_at)
+                 VALUES (?1, 'Song', ?2, 'Album', 0, 0, 'mp3', 0, 0)",
+                [path, artist],
+            )
+            .unwrap();
+        }
+
+        // Version 5 is the last one without artists
+        conn.pragma_update(None, "user_version", 5).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let artists: Vec<(String, i64)> = conn
+            .prepare(
+                "SELECT a.name, COUNT(t.id) FROM artists a JOIN tracks t ON t.artist_id = a.id
+                 GROUP BY a.id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(artists, [("Daft Punk".to_string(), 2)]);
+    }
+}
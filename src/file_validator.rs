@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Seconds of audio decoded to check a file
+const VALIDATION_SECONDS: &str = "1";
+
+/// Checks that scanned files can be decoded, by decoding their first second with ffmpeg, so
+/// broken files are flagged in the library instead of being discovered on air.
+#[derive(Clone)]
+pub struct FileValidator {
+    ffmpeg_path: String,
+}
+
+impl FileValidator {
+    pub fn new(ffmpeg_path: Option<String>) -> Self {
+        Self {
+            ffmpeg_path: ffmpeg_path.unwrap_or_else(|| "ffmpeg".to_string()),
+        }
+    }
+
+    /// Why the file cannot be played, `None` if it decodes
+    pub fn validate(&self, path: &Path) -> Option<String> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-v", "error", "-xerror", "-i"])
+            .arg(path)
+            .args(["-map", "0:a:0", "-t", VALIDATION_SECONDS, "-f", "null", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = stderr.lines().map(str::trim).find(|l| !l.is_empty());
+                Some(match message {
+                    Some(line) => format!("Cannot decode the file: {}", line),
+                    None => format!(
+                        "Cannot decode the file, ffmpeg exited with {}",
+                        output.status
+                    ),
+                })
+            }
+            // Without ffmpeg nothing is validated, playout reports missing ffmpeg anyway
+            Err(e) => {
+                log::warn!(
+                    "Cannot run {} to validate {:?}: {}",
+                    self.ffmpeg_path,
+                    path,
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn given_undecodable_file_when_validating_then_reports_the_ffmpeg_error() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new().unwrap();
+        // Stands in for ffmpeg and fails for files named broken
+        let ffmpeg = directory.path().join("ffmpeg");
+        std::fs::write(
+            &ffmpeg,
+            "#!/bin/sh\ncase \"$*\" in *broken*) echo 'Invalid data found when processing input' >&2; exit 1;; esac\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let validator = FileValidator::new(Some(ffmpeg.to_string_lossy().to_string()));
+
+        assert_eq!(validator.validate(Path::new("/music/fine.mp3")), None);
+        assert_eq!(
+            validator
+                .validate(Path::new("/music/broken.mp3"))
+                .as_deref(),
+            Some("Cannot decode the file: Invalid data found when processing input")
+        );
+        assert_eq!(
+            FileValidator::new(Some("/nonexistent/ffmpeg".to_string()))
+                .validate(Path::new("/music/broken.mp3")),
+            None
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::library_db::LibraryDatabase;
+use log::{debug, error};
+
+/// Prefix of the library metadata keys holding the genre a program aired last
+const LAST_GENRE_KEY_PREFIX: &str = "liveset_last_genre:";
+
+/// Rotates through the genres of recurring liveset programs, so each occurrence starts with
+/// the genre after the one that aired last instead of always trying the first genre.
+/// The last aired genre is stored per program and survives restarts.
+#[derive(Clone)]
+pub struct GenreRotation {
+    db: LibraryDatabase,
+}
+
+impl GenreRotation {
+    pub fn new(db: LibraryDatabase) -> Self {
+        Self { db }
+    }
+
+    /// Genres of the program in the order to try for its next occurrence
+    pub fn next_order(&self, program: &str, genres: &[String]) -> Vec<String> {
+        let last = match self.db.get_metadata(&Self::key(program)) {
+            Ok(last) => last,
+            Err(e) => {
+                error!("Failed to read last genre of program '{}': {}", program, e);
+                None
+            }
+        };
+
+        let order = rotate(genres, last.as_deref());
+        debug!("Genre order of program '{}': {:?}", program, order);
+        order
+    }
+
+    /// Remembers the genre a program aired
+    pub fn record(&self, program: &str, genre: &str) {
+        if let Err(e) = self.db.set_metadata(&Self::key(program), genre) {
+            error!("Failed to store last genre of program '{}': {}", program, e);
+        }
+    }
+
+    fn key(program: &str) -> String {
+        format!("{}{}", LAST_GENRE_KEY_PREFIX, program)
+    }
+}
+
+/// Starts with the genre after `last`, keeps the configured order if `last` is unknown
+fn rotate(genres: &[String], last: Option<&str>) -> Vec<String> {
+    let start = last
+        .and_then(|last| genres.iter().position(|g| g.eq_ignore_ascii_case(last)))
+        .map_or(0, |index| index + 1);
+
+    let mut order = genres.to_vec();
+    if !order.is_empty() {
+        order.rotate_left(start % genres.len());
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn genres() -> Vec<String> {
+        ["techno", "house", "trance"]
+            .iter()
+            .map(|g| g.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn given_last_aired_genre_when_rotating_then_starts_with_next_genre() {
+        assert_eq!(rotate(&genres(), None), genres());
+        assert_eq!(
+            rotate(&genres(), Some("House")),
+            vec!["trance", "techno", "house"]
+        );
+        assert_eq!(rotate(&genres(), Some("trance")), genres());
+        assert_eq!(rotate(&genres(), Some("removed")), genres());
+        assert!(rotate(&[], Some("techno")).is_empty());
+    }
+
+    #[test]
+    fn given_recorded_genre_when_next_occurrence_then_order_continues_per_program() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let rotation = GenreRotation::new(db);
+
+        rotation.record("Friday Night", "techno");
+
+        assert_eq!(rotation.next_order("Friday Night", &genres())[0], "house");
+        assert_eq!(rotation.next_order("Sunday Chill", &genres())[0], "techno");
+    }
+}
@@ -27,22 +27,24 @@
 //!
 //! ```no_run
 //! use funkstrom::hearthis_client::HearthisClient;
+//! use funkstrom::http_client::HttpClientFactory;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-//! let client = HearthisClient::new()?;
+//! let client = HearthisClient::new(&HttpClientFactory::default())?;
 //! let genres = vec!["techno".to_string(), "house".to_string()];
-//! let track = client.get_random_liveset(&genres).await?;
+//! let (track, _genre) = client.get_random_liveset(&genres).await?;
 //! println!("Playing: {} by {}", track.title, track.user.username);
 //! # Ok(())
 //! # }
 //! ```
 
+use crate::http_client::HttpClientFactory;
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
-const HEARTHIS_API_BASE: &str = "https://api-v2.hearthis.at";
+pub const HEARTHIS_API_BASE: &str = "https://api-v2.hearthis.at";
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct HearthisTrack {
@@ -67,10 +69,8 @@ pub struct HearthisClient {
 }
 
 impl HearthisClient {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
+    pub fn new(http: &HttpClientFactory) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let client = http.client(std::time::Duration::from_secs(30))?;
 
         Ok(Self { client })
     }
@@ -90,14 +90,15 @@ impl HearthisClient {
     ///
     /// # Returns
     ///
-    /// A random track selected from the available results (up to 20 tracks per query).
+    /// A random track selected from the available results (up to 20 tracks per query) and the
+    /// genre of `genres` it was found in, `None` if it came from the general feed.
     pub async fn get_random_liveset(
         &self,
         genres: &[String],
-    ) -> Result<HearthisTrack, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(HearthisTrack, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
         if genres.is_empty() {
             // Fetch from general feed (popular/recent tracks across all genres)
-            self.fetch_random_from_feed().await
+            Ok((self.fetch_random_from_feed().await?, None))
         } else {
             // Try each genre until we find one with tracks
             self.fetch_random_from_genres(genres).await
@@ -138,7 +139,7 @@ impl HearthisClient {
     async fn fetch_random_from_genres(
         &self,
         genres: &[String],
-    ) -> Result<HearthisTrack, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(HearthisTrack, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
         // Try each genre in the list
         for genre in genres {
             match self.fetch_from_genre(genre).await {
@@ -147,7 +148,7 @@ impl HearthisClient {
                         "Selected random '{}' track: '{}' by {}",
                         genre, track.title, track.user.username
                     );
-                    return Ok(track);
+                    return Ok((track, Some(genre.clone())));
                 }
                 Err(e) => {
                     error!("Failed to fetch from genre '{}': {}", genre, e);
@@ -161,7 +162,7 @@ impl HearthisClient {
             "All specified genres failed, falling back to general feed: {:?}",
             genres
         );
-        self.fetch_random_from_feed().await
+        Ok((self.fetch_random_from_feed().await?, None))
     }
 
     async fn fetch_from_genre(
@@ -244,7 +245,7 @@ mod tests {
 
     #[tokio::test]
     async fn given_api_available_when_fetching_from_feed_then_returns_track() {
-        let client = HearthisClient::new().unwrap();
+        let client = HearthisClient::new(&HttpClientFactory::default()).unwrap();
 
         let result = client.fetch_random_from_feed().await;
 
@@ -263,7 +264,7 @@ mod tests {
 
     #[tokio::test]
     async fn given_techno_genre_when_fetching_then_returns_techno_track() {
-        let client = HearthisClient::new().unwrap();
+        let client = HearthisClient::new(&HttpClientFactory::default()).unwrap();
 
         let result = client.fetch_from_genre("techno").await;
 
@@ -284,13 +285,13 @@ mod tests {
 
     #[tokio::test]
     async fn given_multiple_genres_when_getting_random_liveset_then_returns_matching_track() {
-        let client = HearthisClient::new().unwrap();
+        let client = HearthisClient::new(&HttpClientFactory::default()).unwrap();
         let genres = vec!["techno".to_string(), "house".to_string()];
 
         let result = client.get_random_liveset(&genres).await;
 
         match result {
-            Ok(track) => {
+            Ok((track, _)) => {
                 assert!(!track.id.is_empty());
                 assert!(!track.stream_url.is_empty());
                 println!(
@@ -306,13 +307,13 @@ mod tests {
 
     #[tokio::test]
     async fn given_empty_genres_when_getting_random_liveset_then_returns_from_feed() {
-        let client = HearthisClient::new().unwrap();
+        let client = HearthisClient::new(&HttpClientFactory::default()).unwrap();
         let genres: Vec<String> = vec![];
 
         let result = client.get_random_liveset(&genres).await;
 
         match result {
-            Ok(track) => {
+            Ok((track, _)) => {
                 assert!(!track.id.is_empty());
                 assert!(!track.stream_url.is_empty());
                 println!(
@@ -0,0 +1,210 @@
+use crate::config::{HttpConfig, StationConfig};
+use log::info;
+use reqwest::header::{HeaderMap, HeaderValue, FROM};
+use reqwest::{Certificate, NoProxy, Proxy};
+use std::fs;
+use std::time::Duration;
+
+/// Product part of the User-Agent of outgoing requests
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the clients of all outgoing HTTP requests (hearthis.at, ads, webhooks) with the
+/// proxy, CA certificates and user agent of the `[http]` section.
+///
+/// Requests identify the station, as several public APIs require: the User-Agent carries
+/// the station URL and contact, the contact is also sent as `From` header.
+///
+/// Without a configured proxy the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment
+/// variables apply.
+#[derive(Clone)]
+pub struct HttpClientFactory {
+    proxy: Option<Proxy>,
+    ca_certificates: Vec<Certificate>,
+    user_agent: HeaderValue,
+    contact: Option<HeaderValue>,
+}
+
+impl Default for HttpClientFactory {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            ca_certificates: Vec::new(),
+            user_agent: HeaderValue::from_static(DEFAULT_USER_AGENT),
+            contact: None,
+        }
+    }
+}
+
+impl HttpClientFactory {
+    /// Reads the proxy and CA certificates of the configuration, fails on invalid settings so
+    /// they are reported at startup instead of on the first request
+    pub fn new(
+        config: Option<&HttpConfig>,
+        station: &StationConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contact = station
+            .contact
+            .as_deref()
+            .map(HeaderValue::from_str)
+            .transpose()
+            .map_err(|e| format!("Invalid station.contact: {}", e))?;
+        let user_agent = match config.and_then(|c| c.user_agent.clone()) {
+            Some(user_agent) => user_agent,
+            None => station_user_agent(station),
+        };
+        let user_agent = HeaderValue::from_str(&user_agent)
+            .map_err(|e| format!("Invalid user agent '{}': {}", user_agent, e))?;
+
+        let Some(config) = config else {
+            return Ok(Self {
+                user_agent,
+                contact,
+                ..Self::default()
+            });
+        };
+
+        let proxy = match &config.proxy {
+            Some(url) => {
+                let no_proxy = config.no_proxy.as_deref().and_then(NoProxy::from_string);
+                let proxy = Proxy::all(url)
+                    .map_err(|e| format!("Invalid http.proxy '{}': {}", url, e))?
+                    .no_proxy(no_proxy);
+                info!("Outgoing HTTP requests use proxy {}", url);
+                Some(proxy)
+            }
+            None => None,
+        };
+
+        let mut ca_certificates = Vec::new();
+        for path in config.ca_certificates.iter().flatten() {
+            let pem = fs::read(path)
+                .map_err(|e| format!("Cannot read CA certificate {}: {}", path, e))?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Invalid CA certificate {}: {}", path, e))?;
+            if certificates.is_empty() {
+                return Err(format!("No certificate found in {}", path).into());
+            }
+            ca_certificates.extend(certificates);
+        }
+
+        Ok(Self {
+            proxy,
+            ca_certificates,
+            user_agent,
+            contact,
+        })
+    }
+
+    /// Client with the configured settings and the given request timeout
+    pub fn client(
+        &self,
+        timeout: Duration,
+    ) -> Result<reqwest::Client, Box<dyn std::error::Error + Send + Sync>> {
+        let mut headers = HeaderMap::new();
+        if let Some(contact) = &self.contact {
+            headers.insert(FROM, contact.clone());
+        }
+        let mut builder = reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(self.user_agent.clone())
+            .default_headers(headers);
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &self.ca_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// User-Agent identifying the station, e.g. `funkstrom/0.1.0 (+https://radio.example.com)`
+fn station_user_agent(station: &StationConfig) -> String {
+    let mut details = vec![format!("+{}", station.url)];
+    details.extend(station.contact.clone());
+    format!("{} ({})", DEFAULT_USER_AGENT, details.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn station() -> StationConfig {
+        StationConfig {
+            station_name: "Deep Sea Radio".to_string(),
+            description: "Oceanic beats".to_string(),
+            genre: "Electronic".to_string(),
+            url: "https://radio.example.com".to_string(),
+            contact: Some("ops@example.com".to_string()),
+        }
+    }
+
+    fn config() -> HttpConfig {
+        HttpConfig {
+            proxy: None,
+            no_proxy: None,
+            ca_certificates: None,
+            user_agent: None,
+        }
+    }
+
+    #[test]
+    fn given_no_config_when_creating_then_user_agent_identifies_station() {
+        let factory = HttpClientFactory::new(None, &station()).unwrap();
+
+        assert_eq!(
+            factory.user_agent,
+            format!(
+                "{} (+https://radio.example.com; ops@example.com)",
+                DEFAULT_USER_AGENT
+            )
+        );
+        assert_eq!(factory.contact.unwrap(), "ops@example.com");
+    }
+
+    #[test]
+    fn given_proxy_and_user_agent_when_creating_then_builds_client() {
+        let factory = HttpClientFactory::new(
+            Some(&HttpConfig {
+                proxy: Some("http://proxy.example.com:3128".to_string()),
+                no_proxy: Some("localhost,127.0.0.1".to_string()),
+                user_agent: Some("MyRadio/1.0".to_string()),
+                ..config()
+            }),
+            &station(),
+        )
+        .unwrap();
+
+        assert!(factory.proxy.is_some());
+        assert_eq!(factory.user_agent, "MyRadio/1.0");
+        assert!(factory.client(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn given_invalid_ca_certificate_when_creating_then_fails() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "not a certificate").unwrap();
+
+        let missing = HttpClientFactory::new(
+            Some(&HttpConfig {
+                ca_certificates: Some(vec!["/nonexistent/ca.pem".to_string()]),
+                ..config()
+            }),
+            &station(),
+        );
+        let invalid = HttpClientFactory::new(
+            Some(&HttpConfig {
+                ca_certificates: Some(vec![file.path().to_string_lossy().to_string()]),
+                ..config()
+            }),
+            &station(),
+        );
+
+        assert!(missing.is_err());
+        assert!(invalid.is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::audio_reader::shuffle_playlist;
+use crate::config::JinglesConfig;
+use crate::library_scanner::LibraryScanner;
+use log::{error, warn};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// When a jingle is due
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JingleTrigger {
+    /// After this many library tracks
+    Tracks(u32),
+    /// Before the first library track once this much time has passed
+    Interval(Duration),
+}
+
+/// Station IDs and sweepers played between library tracks.
+///
+/// The jingles of the directory are played in shuffled rotation, so none repeats before all
+/// others have aired. The directory is read again once the rotation is used up, so added or
+/// removed files apply without a restart.
+pub struct Jingles {
+    directory: PathBuf,
+    trigger: JingleTrigger,
+    rotation: VecDeque<PathBuf>,
+    tracks_since: u32,
+    last_played: Instant,
+}
+
+impl Jingles {
+    pub fn new(config: &JinglesConfig) -> Self {
+        let trigger = match (config.every_n_tracks, config.every_minutes) {
+            (Some(tracks), _) => JingleTrigger::Tracks(tracks),
+            (None, Some(minutes)) => JingleTrigger::Interval(Duration::from_secs(minutes * 60)),
+            (None, None) => JingleTrigger::Tracks(1),
+        };
+
+        Self {
+            directory: PathBuf::from(&config.directory),
+            trigger,
+            rotation: VecDeque::new(),
+            tracks_since: 0,
+            last_played: Instant::now(),
+        }
+    }
+
+    /// Counts a library track handed to playout
+    pub fn track_played(&mut self) {
+        self.tracks_since += 1;
+    }
+
+    /// Jingle to play before the next library track, `None` if none is due
+    pub fn next_due(&mut self, now: Instant) -> Option<PathBuf> {
+        let due = match self.trigger {
+            JingleTrigger::Tracks(tracks) => self.tracks_since >= tracks,
+            JingleTrigger::Interval(interval) => {
+                self.tracks_since > 0 && now.duration_since(self.last_played) >= interval
+            }
+        };
+        if !due {
+            return None;
+        }
+
+        self.tracks_since = 0;
+        self.last_played = now;
+        self.next_jingle()
+    }
+
+    fn next_jingle(&mut self) -> Option<PathBuf> {
+        if self.rotation.is_empty() {
+            self.rotation = self.read_directory();
+            shuffle_playlist(&mut self.rotation);
+        }
+        self.rotation.pop_front()
+    }
+
+    fn read_directory(&self) -> VecDeque<PathBuf> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Cannot read jingles directory {:?}: {}", self.directory, e);
+                return VecDeque::new();
+            }
+        };
+
+        let mut jingles: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && LibraryScanner::is_audio_file(path))
+            .collect();
+        jingles.sort();
+
+        if jingles.is_empty() {
+            warn!("No jingles found in {:?}", self.directory);
+        }
+        jingles.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    fn jingles(
+        directory: &TempDir,
+        every_n_tracks: Option<u32>,
+        every_minutes: Option<u64>,
+    ) -> Jingles {
+        for name in ["id-1.mp3", "id-2.mp3", "notes.txt"] {
+            std::fs::write(directory.path().join(name), b"audio").unwrap();
+        }
+        Jingles::new(&JinglesConfig {
+            directory: directory.path().to_string_lossy().to_string(),
+            every_n_tracks,
+            every_minutes,
+        })
+    }
+
+    #[test]
+    fn given_track_trigger_when_tracks_played_then_jingles_rotate_every_n_tracks() {
+        let directory = TempDir::new().unwrap();
+        let mut jingles = jingles(&directory, Some(2), None);
+        let now = Instant::now();
+
+        assert!(jingles.next_due(now).is_none());
+        jingles.track_played();
+        assert!(jingles.next_due(now).is_none());
+        jingles.track_played();
+        let first = jingles.next_due(now).unwrap();
+        assert!(jingles.next_due(now).is_none());
+
+        jingles.track_played();
+        jingles.track_played();
+        let second = jingles.next_due(now).unwrap();
+
+        let played: HashSet<_> = [first, second].into_iter().collect();
+        assert_eq!(played.len(), 2);
+        assert!(played.iter().all(|p| p.extension().unwrap() == "mp3"));
+    }
+
+    #[test]
+    fn given_interval_trigger_when_time_passes_then_jingle_due_before_next_track() {
+        let directory = TempDir::new().unwrap();
+        let mut jingles = jingles(&directory, None, Some(15));
+        let start = Instant::now();
+
+        jingles.track_played();
+        assert!(jingles
+            .next_due(start + Duration::from_secs(14 * 60))
+            .is_none());
+        assert!(jingles
+            .next_due(start + Duration::from_secs(15 * 60))
+            .is_some());
+
+        // The interval restarts with the jingle and needs a track in between
+        assert!(jingles
+            .next_due(start + Duration::from_secs(45 * 60))
+            .is_none());
+    }
+}
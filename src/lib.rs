@@ -0,0 +1,102 @@
+//! Funkstrom, an Icecast-compatible internet radio server, as a library.
+//!
+//! The `funkstrom` binary is a thin wrapper around [`station::run`], which wires the components
+//! below into a running station. Other applications can embed the whole station the same way,
+//! with their own audio stages through [`station::run_with_stages`], or use its parts on their
+//! own:
+//!
+//! - **Library**: [`library_scanner::LibraryScanner`] indexes a music directory into the SQLite
+//!   [`library_db::LibraryDatabase`], [`track_cache::TrackCache`] keeps it in memory for playout
+//! - **Schedule**: [`schedule_engine::ScheduleEngine`] switches between the programs of a
+//!   [`schedule_store::ScheduleStore`] and the library rotation
+//! - **Streaming**: [`audio_reader::AudioReader`] selects the tracks,
+//!   [`stream_encoders::StreamEncoders`] decodes and encodes them for every stream and
+//!   [`server_icecast::IcecastServer`] serves the streams and the HTTP API
+//!
+//! Configuration is read into [`config::Config`], the types of `config.toml`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use funkstrom::library_db::LibraryDatabase;
+//! use funkstrom::library_scanner::LibraryScanner;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let db = LibraryDatabase::new("library.db")?;
+//! db.initialize_schema()?;
+//! let result = LibraryScanner::new("/home/radio/music".into(), db.clone()).full_scan()?;
+//! println!("Indexed {} tracks", result.added);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod access_log;
+pub mod ad_breaks;
+pub mod admin_auth;
+pub mod announcements;
+mod api_error;
+pub mod artist_names;
+pub mod artwork_cache;
+pub mod audio_buffer;
+pub mod audio_metadata;
+pub mod audio_processor;
+pub mod audio_reader;
+pub mod audio_stage;
+pub mod cli;
+pub mod clock_alignment;
+pub mod config;
+pub mod csv_export;
+pub mod cue_sheet;
+pub mod dayparting;
+mod db_migrations;
+pub mod file_validator;
+pub mod genre_rotation;
+pub mod hearthis_client;
+pub mod http_client;
+pub mod jingles;
+pub mod library_db;
+pub mod library_scanner;
+pub mod listener_registry;
+pub mod loudness_analysis;
+pub mod m3u_parser;
+pub mod notifier;
+pub mod ondemand;
+mod page_templates;
+pub mod play_history;
+pub mod playout_control;
+pub mod podcast_feed;
+pub mod process_supervisor;
+pub mod program_access;
+pub mod program_hooks;
+pub mod program_intros;
+pub mod program_schedule;
+pub mod program_stats;
+pub mod rotation_weights;
+pub mod schedule_engine;
+pub mod schedule_store;
+pub mod selftest;
+pub mod server_icecast;
+pub mod server_library;
+pub mod server_listeners;
+pub mod server_ondemand;
+pub mod server_playlists;
+pub mod server_schedule;
+pub mod server_selftest;
+pub mod server_smart_playlists;
+pub mod server_stats;
+pub mod server_swagger;
+pub mod server_webhooks;
+pub mod shuffle_memory;
+pub mod simulcast;
+pub mod smart_playlist;
+pub mod station;
+pub mod station_id;
+pub mod station_metadata;
+pub mod stream_encoders;
+pub mod stream_rejection;
+pub mod tempo_analysis;
+pub mod track_cache;
+pub mod track_hashes;
+pub mod track_quarantine;
+pub mod transitions;
+mod xml_scan;
@@ -1,14 +1,22 @@
-use log::info;
+use crate::artist_names::{artist_key, main_artist};
+use crate::db_migrations;
+use log::{info, warn};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utoipa::ToSchema;
 
 type TrackKey = (i64, String, i64);
 
-#[derive(Debug, Clone)]
+/// A track of the library
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(as = Track)]
 pub struct TrackRecord {
-    #[allow(dead_code)] // Field populated from database, used for internal tracking
     pub id: Option<i64>,
     pub file_path: String,
     pub title: String,
@@ -16,27 +24,370 @@ pub struct TrackRecord {
     pub album: String,
     pub duration_seconds: Option<i64>,
     pub file_size: i64,
+    /// File modification time (unix seconds)
     pub last_modified: i64,
     pub file_extension: String,
     pub created_at: i64,
     pub updated_at: i64,
+    /// Genre tag, which may list several genres, e.g. `Jazz; Chillout`
+    pub genre: Option<String>,
+    /// Release year tag
+    pub year: Option<i32>,
+    /// Cached thumbnail of the embedded cover art, served by the artwork endpoints
+    #[serde(skip)]
+    pub artwork_path: Option<String>,
+    /// Rating of 1 to 5 stars given by a curator, `null` if unrated
+    #[schema(example = 4)]
+    pub rating: Option<u8>,
+    /// SHA-256 of the file, shared by identical copies
+    #[serde(skip)]
+    pub content_hash: Option<String>,
+    /// SHA-256 of the decoded audio, shared by copies differing only in their tags
+    #[serde(skip)]
+    pub audio_fingerprint: Option<String>,
+    /// Why the file failed the decode validation of the scan, such tracks are quarantined
+    #[serde(skip)]
+    pub validation_error: Option<String>,
+    /// Integrated loudness in LUFS measured by the loudness analysis, `null` until analyzed
+    #[schema(example = -11.5)]
+    pub loudness_lufs: Option<f64>,
+    /// Tempo in beats per minute estimated by the tempo analysis of the scan
+    #[schema(example = 124.0)]
+    pub bpm: Option<f64>,
+    /// Energy from 0 (calm) to 1 (energetic) estimated by the tempo analysis of the scan
+    #[schema(example = 0.72)]
+    pub energy: Option<f64>,
+    /// Start in the album file of a track of a single-file album with a cue sheet, `null` for
+    /// tracks that are a whole file
+    #[schema(example = 320.49)]
+    pub start_seconds: Option<f64>,
+    /// End in the album file of a track of a single-file album, `null` if it plays to the end
+    #[schema(example = 532.0)]
+    pub end_seconds: Option<f64>,
+}
+
+/// Intro and outro cue points of a track, in seconds from its start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CuePoints {
+    /// End of the intro, the outro of the previous track may play over the intro
+    #[schema(example = 8.5)]
+    pub intro_seconds: Option<f64>,
+    /// Start of the outro, the next track starts here
+    #[schema(example = 201.0)]
+    pub outro_seconds: Option<f64>,
+}
+
+/// A track that failed to play at least once
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProblemTrack {
+    pub id: i64,
+    pub file_path: String,
+    pub title: String,
+    pub artist: String,
+    /// Consecutive failed playout attempts
+    pub failure_count: i64,
+    /// Error of the most recent failed attempt
+    pub last_error: Option<String>,
+    /// Whether the track is excluded from track selection
+    pub quarantined: bool,
+}
+
+/// A file that failed the decode validation of the library scan
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BrokenTrack {
+    pub id: i64,
+    pub file_path: String,
+    pub title: String,
+    pub artist: String,
+    #[schema(example = "Cannot decode the file: Invalid data found when processing input")]
+    pub error: String,
+    /// When the file was scanned (unix seconds)
+    pub scanned_at: i64,
+}
+
+/// An artist of the library, spellings differing in case and featured artists included
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Artist {
+    pub id: i64,
+    /// Spelling of the first track naming the artist, without featured artists
+    #[schema(example = "Daft Punk")]
+    pub name: String,
+    pub track_count: usize,
+}
+
+/// A playlist without its tracks
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlaylistSummary {
+    pub id: i64,
+    #[schema(example = "Friday Warmup")]
+    pub name: String,
+    pub track_count: usize,
+    /// Sum of the known track durations
+    pub duration_seconds: i64,
+    /// Playlist file of the music directory it is indexed from, `None` if server-managed
+    pub source_path: Option<String>,
+    /// Entries of the playlist file that are no library track
+    pub unresolved_entries: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A playlist with its tracks in playout order
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Playlist {
+    pub id: i64,
+    #[schema(example = "Friday Warmup")]
+    pub name: String,
+    pub tracks: Vec<TrackRecord>,
+    /// Playlist file of the music directory it is indexed from, `None` if server-managed
+    pub source_path: Option<String>,
+    /// Entries of the playlist file that are no library track
+    pub unresolved_entries: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A saved query selecting library tracks when a program starts
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SmartPlaylist {
+    pub id: i64,
+    #[schema(example = "Jazz Classics")]
+    pub name: String,
+    #[schema(example = "genre = \"jazz\" AND year >= 1990 AND rating >= 3")]
+    pub query: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A webhook delivery that failed and waits for its next attempt
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PendingDelivery {
+    pub id: i64,
+    #[schema(example = "3f2a9c0d5e7b4a1c8d6e0f1a2b3c4d5e")]
+    pub webhook_id: String,
+    #[schema(example = "https://example.com/hooks/radio")]
+    pub url: String,
+    #[schema(example = "track_change")]
+    pub event: String,
+    /// Signed JSON payload
+    #[serde(skip)]
+    pub body: String,
+    /// Failed attempts from the queue
+    pub attempts: i64,
+    /// Next attempt (unix seconds)
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+}
+
+/// Audience of a single program broadcast
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ProgramAudience {
+    #[schema(example = "Morning Show")]
+    pub program: String,
+    /// Start of the broadcast (unix seconds)
+    pub started_at: i64,
+    /// End of the broadcast (unix seconds), `null` while it is on air
+    pub ended_at: Option<i64>,
+    /// Time-weighted average of the connected listeners during the broadcast
+    #[schema(example = 12.4)]
+    pub average_listeners: f64,
+    pub peak_listeners: i64,
+    /// Listeners that connected during the broadcast
+    pub joins: i64,
+    /// Listeners that disconnected during the broadcast
+    pub leaves: i64,
+}
+
+/// What kind of item went on air
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaySource {
+    /// Track of the library rotation
+    Library,
+    /// Track of a scheduled program
+    Program,
+    Jingle,
+    StationId,
+    Announcement,
+    /// Ad, voice break or program intro
+    Break,
+}
+
+impl PlaySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlaySource::Library => "library",
+            PlaySource::Program => "program",
+            PlaySource::Jingle => "jingle",
+            PlaySource::StationId => "station_id",
+            PlaySource::Announcement => "announcement",
+            PlaySource::Break => "break",
+        }
+    }
+
+    fn from_column(value: &str) -> Self {
+        match value {
+            "program" => PlaySource::Program,
+            "jingle" => PlaySource::Jingle,
+            "station_id" => PlaySource::StationId,
+            "announcement" => PlaySource::Announcement,
+            "break" => PlaySource::Break,
+            _ => PlaySource::Library,
+        }
+    }
+}
+
+/// An item that went on air, as stored in the play history
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PlayedItem {
+    /// Start of the play (unix seconds)
+    pub played_at: i64,
+    pub source: PlaySource,
+    /// Scheduled program on air, `null` for the library rotation
+    #[schema(example = "Morning Show")]
+    pub program: Option<String>,
+    /// Streams the item went out on
+    #[schema(example = json!(["high", "standard"]))]
+    pub streams: Vec<String>,
+    pub file_path: String,
+    /// Library id of the track, `null` for files outside the library
+    pub track_id: Option<i64>,
+    #[schema(example = "Bohemian Rhapsody")]
+    pub title: String,
+    #[schema(example = "Queen")]
+    pub artist: String,
+    pub album: String,
+    pub duration_seconds: Option<i64>,
+}
+
+/// How the tracks of a duplicate group match
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMatch {
+    /// Identical files
+    Content,
+    /// Identical decoded audio, the files differ in their tags or cover art
+    Audio,
+}
+
+/// Library tracks that are copies of the same song
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DuplicateGroup {
+    pub matched_by: DuplicateMatch,
+    /// Content hash or audio fingerprint shared by the tracks
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub hash: String,
+    /// Tracks in the order they were added, only the first one plays in the library rotation
+    pub tracks: Vec<TrackRecord>,
+}
+
+/// How long a statement retries while another process, e.g. a backup, locks the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a pooled connection before failing
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the availability of the database is checked
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Availability of the library database
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DatabaseHealth {
+    /// `false` while the database is locked or unavailable, playout then continues with the
+    /// library loaded last
+    pub available: bool,
+    /// RFC 3339 time the database became unavailable
+    #[schema(example = "2025-01-15T03:00:12+01:00")]
+    pub unavailable_since: Option<String>,
+    /// Error of the last failed check
+    #[schema(example = "database is locked")]
+    pub last_error: Option<String>,
+}
+
+impl Default for DatabaseHealth {
+    fn default() -> Self {
+        Self {
+            available: true,
+            unavailable_since: None,
+            last_error: None,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct LibraryDatabase {
     pool: Pool<SqliteConnectionManager>,
+    health: Arc<Mutex<DatabaseHealth>>,
 }
 
 impl LibraryDatabase {
     pub fn new(db_path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let manager = SqliteConnectionManager::file(db_path);
-        let pool = Pool::builder().max_size(5).build(manager)?;
+        // SQLite retries busy statements with a backoff up to the busy timeout
+        let manager = SqliteConnectionManager::file(db_path)
+            .with_init(|conn| conn.busy_timeout(BUSY_TIMEOUT));
+        let pool = Pool::builder()
+            .max_size(5)
+            .connection_timeout(CONNECTION_TIMEOUT)
+            .build(manager)?;
 
         info!("Database initialized at: {}", db_path);
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            health: Arc::default(),
+        })
+    }
+
+    /// Availability as seen by the last check
+    pub fn health(&self) -> DatabaseHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Checks that the database can be read and updates the health
+    pub fn check_health(&self) -> DatabaseHealth {
+        let result = self.pool.get().map_err(|e| e.to_string()).and_then(|conn| {
+            conn.query_row("SELECT COUNT(*) FROM tracks", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| e.to_string())
+        });
+
+        let mut health = self.health.lock().unwrap();
+        match result {
+            Ok(_) if !health.available => {
+                info!("Library database is available again");
+                *health = DatabaseHealth::default();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if health.available {
+                    warn!(
+                        "Library database unavailable, playout continues with the library loaded last: {}",
+                        e
+                    );
+                    health.available = false;
+                    health.unavailable_since = Some(chrono::Local::now().to_rfc3339());
+                }
+                health.last_error = Some(e);
+            }
+        }
+        health.clone()
+    }
+
+    /// Checks the availability of the database in the background
+    pub fn start_health_check(&self) {
+        let db = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+                let db = db.clone();
+                let _ = tokio::task::spawn_blocking(move || db.check_health()).await;
+            }
+        });
     }
 
+    /// Creates the schema or migrates it to the latest version, see `db_migrations`
     pub fn initialize_schema(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let mut conn = self.pool.get()?;
 
@@ -45,68 +396,111 @@ impl LibraryDatabase {
         conn.pragma_update(None, "foreign_keys", true)?;
         conn.pragma_update(None, "temp_store", "MEMORY")?;
 
-        let tx = conn.transaction()?;
+        db_migrations::migrate(&mut conn)
+    }
 
-        tx.execute(
-            "CREATE TABLE IF NOT EXISTS tracks (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                file_path TEXT NOT NULL UNIQUE,
-                title TEXT NOT NULL,
-                artist TEXT NOT NULL,
-                album TEXT NOT NULL,
-                duration_seconds INTEGER,
-                file_size INTEGER NOT NULL,
-                last_modified INTEGER NOT NULL,
-                file_extension TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            )",
-            [],
-        )?;
+    pub fn insert_track(&self, track: &TrackRecord) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
 
-        tx.execute(
-            "CREATE INDEX IF NOT EXISTS idx_tracks_file_path ON tracks(file_path)",
-            [],
+        conn.execute(
+            "INSERT INTO tracks (file_path, title, artist, album, duration_seconds, 
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, year, content_hash, audio_fingerprint, validation_error, last_error,
+                quarantined, bpm, energy, start_seconds, end_seconds)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?16,
+                ?17, ?18, ?19, ?20, ?21)",
+            params![
+                track.file_path,
+                track.title,
+                track.artist,
+                track.album,
+                track.duration_seconds,
+                track.file_size,
+                track.last_modified,
+                track.file_extension,
+                track.created_at,
+                track.updated_at,
+                track.genre,
+                track.artwork_path,
+                track.year,
+                track.content_hash,
+                track.audio_fingerprint,
+                track.validation_error,
+                track.validation_error.is_some(),
+                track.bpm,
+                track.energy,
+                track.start_seconds,
+                track.end_seconds,
+            ],
         )?;
+        let id = conn.last_insert_rowid();
+        Self::link_artist(&conn, &track.file_path, &track.artist)?;
 
-        tx.execute(
-            "CREATE INDEX IF NOT EXISTS idx_tracks_artist ON tracks(artist)",
-            [],
-        )?;
+        Ok(id)
+    }
 
-        tx.execute(
-            "CREATE INDEX IF NOT EXISTS idx_tracks_album ON tracks(album)",
-            [],
-        )?;
+    pub fn insert_tracks_batch(
+        &self,
+        tracks: &[TrackRecord],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
 
-        tx.execute(
-            "CREATE INDEX IF NOT EXISTS idx_tracks_last_modified ON tracks(last_modified)",
-            [],
+        let mut stmt = tx.prepare(
+            "INSERT INTO tracks (file_path, title, artist, album, duration_seconds, 
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, year, content_hash, audio_fingerprint, validation_error, last_error,
+                quarantined, bpm, energy, start_seconds, end_seconds)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?16,
+                ?17, ?18, ?19, ?20, ?21)",
         )?;
 
-        tx.execute(
-            "CREATE TABLE IF NOT EXISTS library_metadata (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL,
-                updated_at INTEGER NOT NULL
-            )",
-            [],
-        )?;
+        for track in tracks {
+            stmt.execute(params![
+                track.file_path,
+                track.title,
+                track.artist,
+                track.album,
+                track.duration_seconds,
+                track.file_size,
+                track.last_modified,
+                track.file_extension,
+                track.created_at,
+                track.updated_at,
+                track.genre,
+                track.artwork_path,
+                track.year,
+                track.content_hash,
+                track.audio_fingerprint,
+                track.validation_error,
+                track.validation_error.is_some(),
+                track.bpm,
+                track.energy,
+                track.start_seconds,
+                track.end_seconds,
+            ])?;
+            Self::link_artist(&tx, &track.file_path, &track.artist)?;
+        }
 
+        drop(stmt);
         tx.commit()?;
 
         Ok(())
     }
 
-    pub fn insert_track(&self, track: &TrackRecord) -> Result<i64, Box<dyn Error + Send + Sync>> {
+    pub fn update_track(&self, track: &TrackRecord) -> Result<(), Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
 
         conn.execute(
-            "INSERT INTO tracks (file_path, title, artist, album, duration_seconds, 
-                file_size, last_modified, file_extension, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "UPDATE tracks SET title = ?1, artist = ?2, album = ?3, duration_seconds = ?4,
+                file_size = ?5, last_modified = ?6, file_extension = ?7, updated_at = ?8,
+                genre = ?9, artwork_path = ?10, year = ?11, content_hash = ?12,
+                audio_fingerprint = ?13, validation_error = ?14, failure_count = 0,
+                last_error = ?14, quarantined = ?15, loudness_lufs = NULL,
+                loudness_analyzed_at = NULL, bpm = ?16, energy = ?17, start_seconds = ?18,
+                end_seconds = ?19
+             WHERE file_path = ?20",
             params![
-                track.file_path,
                 track.title,
                 track.artist,
                 track.album,
@@ -114,15 +508,28 @@ impl LibraryDatabase {
                 track.file_size,
                 track.last_modified,
                 track.file_extension,
-                track.created_at,
                 track.updated_at,
+                track.genre,
+                track.artwork_path,
+                track.year,
+                track.content_hash,
+                track.audio_fingerprint,
+                track.validation_error,
+                track.validation_error.is_some(),
+                track.bpm,
+                track.energy,
+                track.start_seconds,
+                track.end_seconds,
+                track.file_path,
             ],
         )?;
+        Self::link_artist(&conn, &track.file_path, &track.artist)?;
+        Self::prune_artists(&conn)?;
 
-        Ok(conn.last_insert_rowid())
+        Ok(())
     }
 
-    pub fn insert_tracks_batch(
+    pub fn update_tracks_batch(
         &self,
         tracks: &[TrackRecord],
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -130,14 +537,18 @@ impl LibraryDatabase {
         let tx = conn.transaction()?;
 
         let mut stmt = tx.prepare(
-            "INSERT INTO tracks (file_path, title, artist, album, duration_seconds, 
-                file_size, last_modified, file_extension, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "UPDATE tracks SET title = ?1, artist = ?2, album = ?3, duration_seconds = ?4,
+                file_size = ?5, last_modified = ?6, file_extension = ?7, updated_at = ?8,
+                genre = ?9, artwork_path = ?10, year = ?11, content_hash = ?12,
+                audio_fingerprint = ?13, validation_error = ?14, failure_count = 0,
+                last_error = ?14, quarantined = ?15, loudness_lufs = NULL,
+                loudness_analyzed_at = NULL, bpm = ?16, energy = ?17, start_seconds = ?18,
+                end_seconds = ?19
+             WHERE file_path = ?20",
         )?;
 
         for track in tracks {
             stmt.execute(params![
-                track.file_path,
                 track.title,
                 track.artist,
                 track.album,
@@ -145,173 +556,1381 @@ impl LibraryDatabase {
                 track.file_size,
                 track.last_modified,
                 track.file_extension,
-                track.created_at,
                 track.updated_at,
+                track.genre,
+                track.artwork_path,
+                track.year,
+                track.content_hash,
+                track.audio_fingerprint,
+                track.validation_error,
+                track.validation_error.is_some(),
+                track.bpm,
+                track.energy,
+                track.start_seconds,
+                track.end_seconds,
+                track.file_path,
             ])?;
+            Self::link_artist(&tx, &track.file_path, &track.artist)?;
         }
 
         drop(stmt);
+        Self::prune_artists(&tx)?;
         tx.commit()?;
 
         Ok(())
     }
 
-    pub fn update_track(&self, track: &TrackRecord) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pub fn delete_track(&self, file_path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM tracks WHERE file_path = ?1",
+            params![file_path],
+        )?;
+        Self::prune_artists(&conn)?;
+        Ok(())
+    }
+
+    pub fn delete_tracks_batch(
+        &self,
+        file_paths: &[String],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+
+        let mut stmt = tx.prepare("DELETE FROM tracks WHERE file_path = ?1")?;
+
+        for file_path in file_paths {
+            stmt.execute(params![file_path])?;
+        }
+
+        drop(stmt);
+        Self::prune_artists(&tx)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns all tracks including quarantined ones, playout uses `get_playable_tracks`
+    pub fn get_all_tracks(&self) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks",
+        )?;
+
+        let tracks = stmt
+            .query_map([], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// Returns all tracks that are eligible for playout, i.e. not quarantined. Of duplicate
+    /// tracks only the one added first is returned.
+    pub fn get_playable_tracks(&self) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks
+             WHERE quarantined = 0
+                AND NOT EXISTS (
+                    SELECT 1 FROM tracks original
+                    WHERE original.quarantined = 0 AND original.id < tracks.id
+                        AND (original.content_hash = tracks.content_hash
+                            OR original.audio_fingerprint = tracks.audio_fingerprint)
+                )",
+        )?;
+
+        let tracks = stmt
+            .query_map([], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// Returns playable tracks of at least `min_seconds`, e.g. DJ mixes
+    pub fn get_tracks_longer_than(
+        &self,
+        min_seconds: i64,
+    ) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks
+             WHERE quarantined = 0 AND duration_seconds >= ?1
+             ORDER BY file_path",
+        )?;
+
+        let tracks = stmt
+            .query_map(params![min_seconds], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    pub fn get_track(&self, id: i64) -> Result<Option<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let track = conn
+            .query_row(
+                "SELECT id, file_path, title, artist, album, duration_seconds,
+                    file_size, last_modified, file_extension, created_at, updated_at, genre,
+                    artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                    loudness_lufs, bpm, energy, start_seconds, end_seconds
+                 FROM tracks WHERE id = ?1",
+                params![id],
+                Self::track_from_row,
+            )
+            .optional()?;
+
+        Ok(track)
+    }
+
+    pub fn get_track_by_path(
+        &self,
+        file_path: &str,
+    ) -> Result<Option<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let track = conn
+            .query_row(
+                "SELECT id, file_path, title, artist, album, duration_seconds,
+                    file_size, last_modified, file_extension, created_at, updated_at, genre,
+                    artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                    loudness_lufs, bpm, energy, start_seconds, end_seconds
+                 FROM tracks WHERE file_path = ?1",
+                params![file_path],
+                Self::track_from_row,
+            )
+            .optional()?;
+
+        Ok(track)
+    }
+
+    /// Sets or clears the rating of a track, `false` if there is no track with the id
+    pub fn set_track_rating(
+        &self,
+        id: i64,
+        rating: Option<u8>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let updated = conn.execute(
+            "UPDATE tracks SET rating = ?2 WHERE id = ?1",
+            params![id, rating],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Playable tracks the loudness analysis has not measured yet, oldest first. New and
+    /// changed files are measured again.
+    pub fn get_tracks_without_loudness(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks
+             WHERE loudness_analyzed_at IS NULL AND quarantined = 0
+             ORDER BY id
+             LIMIT ?1",
+        )?;
+
+        let tracks = stmt
+            .query_map(params![limit as i64], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// Stores the measured loudness of a track, `None` if it could not be measured so the
+    /// analysis does not retry it until the file changes
+    pub fn set_track_loudness(
+        &self,
+        id: i64,
+        loudness_lufs: Option<f64>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let updated = conn.execute(
+            "UPDATE tracks SET loudness_lufs = ?2, loudness_analyzed_at = ?3 WHERE id = ?1",
+            params![id, loudness_lufs, now],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Searches title, artist and album, best matches first.
+    ///
+    /// Every word of the query has to match the start of a word, so "beat liv" finds
+    /// "Beatles - Live at the BBC". Case and diacritics are ignored.
+    pub fn search_tracks(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let Some(pattern) = Self::search_pattern(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.file_path, t.title, t.artist, t.album, t.duration_seconds,
+                t.file_size, t.last_modified, t.file_extension, t.created_at, t.updated_at,
+                t.genre, t.artwork_path, t.rating, t.year, t.content_hash, t.audio_fingerprint,
+                t.validation_error, t.loudness_lufs, t.bpm, t.energy,
+                t.start_seconds, t.end_seconds
+             FROM tracks_fts
+             JOIN tracks t ON t.id = tracks_fts.rowid
+             WHERE tracks_fts MATCH ?1
+             ORDER BY tracks_fts.rank
+             LIMIT ?2",
+        )?;
+
+        let tracks = stmt
+            .query_map(params![pattern, limit as i64], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// FTS5 query matching every word of `query` as a prefix, `None` without any words.
+    /// The words are quoted so FTS5 operators in user input are matched literally.
+    fn search_pattern(query: &str) -> Option<String> {
+        let terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|term| !term.is_empty())
+            .map(|term| format!("\"{}\"*", term))
+            .collect();
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+
+    /// Artists with their number of tracks, ordered by name ignoring case
+    pub fn get_artists(&self) -> Result<Vec<Artist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT a.id, a.name, COUNT(t.id)
+             FROM artists a
+             JOIN tracks t ON t.artist_id = a.id
+             GROUP BY a.id
+             ORDER BY a.normalized_name",
+        )?;
+        let artists = stmt
+            .query_map([], |row| {
+                Ok(Artist {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    track_count: row.get::<_, i64>(2)? as usize,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(artists)
+    }
+
+    /// Tracks of an artist ordered by album and title, `None` if there is no such artist
+    pub fn get_artist_tracks(
+        &self,
+        artist_id: i64,
+    ) -> Result<Option<Vec<TrackRecord>>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let exists = conn
+            .prepare("SELECT 1 FROM artists WHERE id = ?1")?
+            .exists(params![artist_id])?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks
+             WHERE artist_id = ?1
+             ORDER BY album COLLATE NOCASE, title COLLATE NOCASE",
+        )?;
+        let tracks = stmt
+            .query_map(params![artist_id], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(Some(tracks))
+    }
+
+    fn track_from_row(row: &rusqlite::Row) -> SqliteResult<TrackRecord> {
+        Ok(TrackRecord {
+            id: row.get(0)?,
+            file_path: row.get(1)?,
+            title: row.get(2)?,
+            artist: row.get(3)?,
+            album: row.get(4)?,
+            duration_seconds: row.get(5)?,
+            file_size: row.get(6)?,
+            last_modified: row.get(7)?,
+            file_extension: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+            genre: row.get(11)?,
+            artwork_path: row.get(12)?,
+            rating: row.get(13)?,
+            year: row.get(14)?,
+            content_hash: row.get(15)?,
+            audio_fingerprint: row.get(16)?,
+            validation_error: row.get(17)?,
+            loudness_lufs: row.get(18)?,
+            bpm: row.get(19)?,
+            energy: row.get(20)?,
+            start_seconds: row.get(21)?,
+            end_seconds: row.get(22)?,
+        })
+    }
+
+    pub fn get_track_keys(&self) -> Result<Vec<TrackKey>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare("SELECT id, file_path, last_modified FROM tracks")?;
+
+        let keys = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(keys)
+    }
+
+    pub fn track_count(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM tracks", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Records a failed playout attempt for a track and quarantines it once
+    /// `max_failures` consecutive failures are reached.
+    ///
+    /// Returns `true` if the track is quarantined after this failure.
+    pub fn record_track_failure(
+        &self,
+        file_path: &str,
+        error: &str,
+        max_failures: u32,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        conn.execute(
+            "UPDATE tracks SET failure_count = failure_count + 1, last_error = ?1,
+                quarantined = CASE WHEN failure_count + 1 >= ?2 THEN 1 ELSE quarantined END
+             WHERE file_path = ?3",
+            params![error, max_failures, file_path],
+        )?;
+
+        let quarantined: Option<bool> = conn
+            .query_row(
+                "SELECT quarantined FROM tracks WHERE file_path = ?1",
+                params![file_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(quarantined.unwrap_or(false))
+    }
+
+    /// Resets the failure counter of a track after it was played successfully
+    pub fn record_track_success(
+        &self,
+        file_path: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE tracks SET failure_count = 0, last_error = NULL
+             WHERE file_path = ?1 AND failure_count > 0",
+            params![file_path],
+        )?;
+        Ok(())
+    }
+
+    /// Returns all tracks that failed at least once, most failures first
+    pub fn get_problem_tracks(&self) -> Result<Vec<ProblemTrack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, failure_count, last_error, quarantined
+             FROM tracks WHERE failure_count > 0 OR quarantined = 1
+             ORDER BY quarantined DESC, failure_count DESC, file_path",
+        )?;
+
+        let tracks = stmt
+            .query_map([], |row| {
+                Ok(ProblemTrack {
+                    id: row.get(0)?,
+                    file_path: row.get(1)?,
+                    title: row.get(2)?,
+                    artist: row.get(3)?,
+                    failure_count: row.get(4)?,
+                    last_error: row.get(5)?,
+                    quarantined: row.get(6)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// Tracks whose files failed the decode validation, they stay quarantined until the file
+    /// changes
+    pub fn get_broken_tracks(&self) -> Result<Vec<BrokenTrack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, title, artist, validation_error, updated_at
+             FROM tracks WHERE validation_error IS NOT NULL
+             ORDER BY file_path",
+        )?;
+
+        let tracks = stmt
+            .query_map([], |row| {
+                Ok(BrokenTrack {
+                    id: row.get(0)?,
+                    file_path: row.get(1)?,
+                    title: row.get(2)?,
+                    artist: row.get(3)?,
+                    error: row.get(4)?,
+                    scanned_at: row.get(5)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(tracks)
+    }
+
+    /// Number of applied schema migrations
+    pub fn schema_version(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        db_migrations::schema_version(&conn)
+    }
+
+    /// Runs the SQLite integrity check, returns `ok` or the problems found
+    pub fn integrity_check(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let findings = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(findings.join("; "))
+    }
+
+    pub fn get_metadata(&self, key: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let result = conn
+            .query_row(
+                "SELECT value FROM library_metadata WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(result)
+    }
+
+    /// Modification times of the directories seen by the last scan, in nanoseconds by path
+    pub fn get_directory_mtimes(
+        &self,
+    ) -> Result<HashMap<String, i64>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT path, modified_nanos FROM scanned_directories")?;
+        let mtimes = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqliteResult<HashMap<_, _>>>()?;
+        Ok(mtimes)
+    }
+
+    /// Replaces the directory modification times with the ones of a scan
+    pub fn replace_directory_mtimes(
+        &self,
+        mtimes: &HashMap<String, i64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM scanned_directories", [])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO scanned_directories (path, modified_nanos) VALUES (?1, ?2)",
+            )?;
+            for (path, modified) in mtimes {
+                stmt.execute(params![path, modified])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn set_metadata(&self, key: &str, value: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO library_metadata (key, value, updated_at)
+             VALUES (?1, ?2, ?3)",
+            params![key, value, now],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete_metadata(&self, key: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM library_metadata WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// Returns the ids that do not belong to a library track
+    pub fn get_missing_track_ids(
+        &self,
+        ids: &[i64],
+    ) -> Result<Vec<i64>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT 1 FROM tracks WHERE id = ?1")?;
+
+        let mut missing = Vec::new();
+        for id in ids {
+            if !stmt.exists(params![id])? && !missing.contains(id) {
+                missing.push(*id);
+            }
+        }
+
+        Ok(missing)
+    }
+
+    pub fn get_playlists(&self) -> Result<Vec<PlaylistSummary>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.name, COUNT(t.id), COALESCE(SUM(t.duration_seconds), 0),
+                p.source_path, p.unresolved_entries, p.created_at, p.updated_at
+             FROM playlists p
+             LEFT JOIN playlist_tracks pt ON pt.playlist_id = p.id
+             LEFT JOIN tracks t ON t.id = pt.track_id
+             GROUP BY p.id
+             ORDER BY p.name",
+        )?;
+
+        let playlists = stmt
+            .query_map([], |row| {
+                Ok(PlaylistSummary {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    track_count: row.get::<_, i64>(2)? as usize,
+                    duration_seconds: row.get(3)?,
+                    source_path: row.get(4)?,
+                    unresolved_entries: row.get::<_, i64>(5)? as usize,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(playlists)
+    }
+
+    pub fn get_playlist(&self, id: i64) -> Result<Option<Playlist>, Box<dyn Error + Send + Sync>> {
+        self.find_playlist("id = ?1", params![id])
+    }
+
+    pub fn get_playlist_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<Playlist>, Box<dyn Error + Send + Sync>> {
+        self.find_playlist("name = ?1", params![name])
+    }
+
+    /// Loads a playlist with its tracks, tracks removed from the library are left out
+    fn find_playlist(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Option<Playlist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+
+        let playlist = conn
+            .query_row(
+                &format!(
+                    "SELECT id, name, source_path, unresolved_entries, created_at, updated_at
+                     FROM playlists WHERE {}",
+                    condition
+                ),
+                params,
+                |row| {
+                    Ok(Playlist {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        tracks: Vec::new(),
+                        source_path: row.get(2)?,
+                        unresolved_entries: row.get::<_, i64>(3)? as usize,
+                        created_at: row.get(4)?,
+                        updated_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        let Some(mut playlist) = playlist else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.file_path, t.title, t.artist, t.album, t.duration_seconds,
+                t.file_size, t.last_modified, t.file_extension, t.created_at, t.updated_at, t.genre,
+                t.artwork_path, t.rating, t.year, t.content_hash, t.audio_fingerprint,
+                t.validation_error, t.loudness_lufs, t.bpm, t.energy,
+                t.start_seconds, t.end_seconds
+             FROM playlist_tracks pt
+             JOIN tracks t ON t.id = pt.track_id
+             WHERE pt.playlist_id = ?1
+             ORDER BY pt.position",
+        )?;
+        playlist.tracks = stmt
+            .query_map(params![playlist.id], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(Some(playlist))
+    }
+
+    pub fn create_playlist(
+        &self,
+        name: &str,
+        track_ids: &[i64],
+    ) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO playlists (name, created_at, updated_at) VALUES (?1, ?2, ?2)",
+            params![name, now],
+        )?;
+        let id = tx.last_insert_rowid();
+        Self::insert_playlist_tracks(&tx, id, track_ids)?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Renames a playlist and replaces its tracks, returns `false` if it does not exist
+    pub fn update_playlist(
+        &self,
+        id: i64,
+        name: &str,
+        track_ids: &[i64],
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let tx = conn.transaction()?;
+
+        let updated = tx.execute(
+            "UPDATE playlists SET name = ?1, updated_at = ?2 WHERE id = ?3",
+            params![name, now, id],
+        )?;
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        tx.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![id],
+        )?;
+        Self::insert_playlist_tracks(&tx, id, track_ids)?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Returns `false` if the playlist does not exist
+    pub fn delete_playlist(&self, id: i64) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![id],
+        )?;
+        let deleted = tx.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Creates or replaces the playlist indexed from the playlist file at `source_path`,
+    /// returns whether it was created or changed
+    pub fn sync_library_playlist(
+        &self,
+        source_path: &str,
+        name: &str,
+        track_ids: &[i64],
+        unresolved_entries: usize,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let tx = conn.transaction()?;
+
+        let name_taken = tx
+            .prepare(
+                "SELECT 1 FROM playlists
+                 WHERE name = ?1 AND (source_path IS NULL OR source_path != ?2)",
+            )?
+            .exists(params![name, source_path])?;
+        if name_taken {
+            return Err(format!("Playlist '{}' already exists", name).into());
+        }
+
+        let existing = tx
+            .query_row(
+                "SELECT id, name, unresolved_entries FROM playlists WHERE source_path = ?1",
+                params![source_path],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)? as usize,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let id = match existing {
+            Some((id, existing_name, existing_unresolved)) => {
+                let existing_ids = tx
+                    .prepare(
+                        "SELECT track_id FROM playlist_tracks
+                         WHERE playlist_id = ?1 ORDER BY position",
+                    )?
+                    .query_map(params![id], |row| row.get::<_, i64>(0))?
+                    .collect::<SqliteResult<Vec<_>>>()?;
+                if existing_name == name
+                    && existing_unresolved == unresolved_entries
+                    && existing_ids == track_ids
+                {
+                    return Ok(false);
+                }
+
+                tx.execute(
+                    "UPDATE playlists SET name = ?1, unresolved_entries = ?2, updated_at = ?3
+                     WHERE id = ?4",
+                    params![name, unresolved_entries as i64, now, id],
+                )?;
+                tx.execute(
+                    "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+                    params![id],
+                )?;
+                id
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO playlists
+                        (name, source_path, unresolved_entries, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?4)",
+                    params![name, source_path, unresolved_entries as i64, now],
+                )?;
+                tx.last_insert_rowid()
+            }
+        };
+        Self::insert_playlist_tracks(&tx, id, track_ids)?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Playlist files the indexed playlists come from
+    pub fn get_library_playlist_paths(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare("SELECT source_path FROM playlists WHERE source_path IS NOT NULL")?;
+        let paths = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(paths)
+    }
+
+    /// Deletes the indexed playlists whose playlist file is not in `source_paths`, returns how
+    /// many were deleted
+    pub fn delete_library_playlists_except(
+        &self,
+        source_paths: &HashSet<String>,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+
+        let stale: Vec<i64> = tx
+            .prepare("SELECT id, source_path FROM playlists WHERE source_path IS NOT NULL")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?
+            .into_iter()
+            .filter(|(_, source_path)| !source_paths.contains(source_path))
+            .map(|(id, _)| id)
+            .collect();
+        for id in &stale {
+            tx.execute(
+                "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+                params![id],
+            )?;
+            tx.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+        }
+
+        tx.commit()?;
+        Ok(stale.len())
+    }
+
+    /// Groups of identical files, followed by groups of files with identical audio that are
+    /// not all identical files
+    pub fn get_duplicate_groups(
+        &self,
+    ) -> Result<Vec<DuplicateGroup>, Box<dyn Error + Send + Sync>> {
+        let mut groups = self.duplicate_groups(DuplicateMatch::Content)?;
+        groups.extend(self.duplicate_groups(DuplicateMatch::Audio)?);
+        Ok(groups)
+    }
+
+    fn duplicate_groups(
+        &self,
+        matched_by: DuplicateMatch,
+    ) -> Result<Vec<DuplicateGroup>, Box<dyn Error + Send + Sync>> {
+        let (column, distinct) = match matched_by {
+            DuplicateMatch::Content => ("content_hash", "id"),
+            DuplicateMatch::Audio => ("audio_fingerprint", "COALESCE(content_hash, file_path)"),
+        };
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, file_path, title, artist, album, duration_seconds,
+                file_size, last_modified, file_extension, created_at, updated_at, genre,
+                artwork_path, rating, year, content_hash, audio_fingerprint, validation_error,
+                loudness_lufs, bpm, energy, start_seconds, end_seconds
+             FROM tracks
+             WHERE {0} IN (
+                SELECT {0} FROM tracks WHERE {0} IS NOT NULL
+                GROUP BY {0} HAVING COUNT(DISTINCT {1}) > 1
+             )
+             ORDER BY {0}, id",
+            column, distinct
+        ))?;
+        let tracks = stmt
+            .query_map([], Self::track_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        for track in tracks {
+            let hash = match matched_by {
+                DuplicateMatch::Content => track.content_hash.clone(),
+                DuplicateMatch::Audio => track.audio_fingerprint.clone(),
+            }
+            .unwrap_or_default();
+            match groups.last_mut() {
+                Some(group) if group.hash == hash => group.tracks.push(track),
+                _ => groups.push(DuplicateGroup {
+                    matched_by,
+                    hash,
+                    tracks: vec![track],
+                }),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Makes the next incremental scan read the tracks without an audio fingerprint, returns
+    /// their number. Tracks of single-file albums are never fingerprinted.
+    pub fn rescan_tracks_without_fingerprint(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let updated = conn.execute(
+            "UPDATE tracks SET last_modified = 0
+             WHERE audio_fingerprint IS NULL AND start_seconds IS NULL",
+            [],
+        )?;
+        Ok(updated)
+    }
+
+    /// Makes the next incremental scan read the tracks without a tempo analysis, returns their
+    /// number
+    pub fn rescan_tracks_without_tempo(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let updated = conn.execute(
+            "UPDATE tracks SET last_modified = 0 WHERE energy IS NULL",
+            [],
+        )?;
+        Ok(updated)
+    }
+
+    /// Smart playlists ordered by name
+    pub fn get_smart_playlists(&self) -> Result<Vec<SmartPlaylist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, query, created_at, updated_at FROM smart_playlists ORDER BY name",
+        )?;
+        let playlists = stmt
+            .query_map([], Self::smart_playlist_from_row)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(playlists)
+    }
+
+    pub fn get_smart_playlist(
+        &self,
+        id: i64,
+    ) -> Result<Option<SmartPlaylist>, Box<dyn Error + Send + Sync>> {
+        self.find_smart_playlist("id = ?1", params![id])
+    }
+
+    pub fn get_smart_playlist_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<SmartPlaylist>, Box<dyn Error + Send + Sync>> {
+        self.find_smart_playlist("name = ?1", params![name])
+    }
+
+    fn find_smart_playlist(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Option<SmartPlaylist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let playlist = conn
+            .query_row(
+                &format!(
+                    "SELECT id, name, query, created_at, updated_at FROM smart_playlists WHERE {}",
+                    condition
+                ),
+                params,
+                Self::smart_playlist_from_row,
+            )
+            .optional()?;
+        Ok(playlist)
+    }
+
+    fn smart_playlist_from_row(row: &rusqlite::Row) -> SqliteResult<SmartPlaylist> {
+        Ok(SmartPlaylist {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            query: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    }
+
+    pub fn create_smart_playlist(
+        &self,
+        name: &str,
+        query: &str,
+    ) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        conn.execute(
+            "INSERT INTO smart_playlists (name, query, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?3)",
+            params![name, query, now],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Renames a smart playlist and replaces its query, returns `false` if it does not exist
+    pub fn update_smart_playlist(
+        &self,
+        id: i64,
+        name: &str,
+        query: &str,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let updated = conn.execute(
+            "UPDATE smart_playlists SET name = ?1, query = ?2, updated_at = ?3 WHERE id = ?4",
+            params![name, query, now, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Returns `false` if the smart playlist does not exist
+    pub fn delete_smart_playlist(&self, id: i64) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let deleted = conn.execute("DELETE FROM smart_playlists WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Queues a failed webhook delivery for another attempt at `next_attempt_at`
+    pub fn enqueue_delivery(
+        &self,
+        webhook_id: &str,
+        url: &str,
+        event: &str,
+        body: &str,
+        next_attempt_at: i64,
+        last_error: &str,
+    ) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+
+        conn.execute(
+            "INSERT INTO pending_deliveries
+                (webhook_id, url, event, body, next_attempt_at, last_error, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                webhook_id,
+                url,
+                event,
+                body,
+                next_attempt_at,
+                last_error,
+                now
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Queued deliveries ordered by their next attempt, only those due at `due_at` if given
+    pub fn get_pending_deliveries(
+        &self,
+        due_at: Option<i64>,
+    ) -> Result<Vec<PendingDelivery>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, webhook_id, url, event, body, attempts, next_attempt_at, last_error,
+                    created_at
+             FROM pending_deliveries
+             WHERE ?1 IS NULL OR next_attempt_at <= ?1
+             ORDER BY next_attempt_at, id",
+        )?;
+
+        let deliveries = stmt
+            .query_map(params![due_at], |row| {
+                Ok(PendingDelivery {
+                    id: row.get(0)?,
+                    webhook_id: row.get(1)?,
+                    url: row.get(2)?,
+                    event: row.get(3)?,
+                    body: row.get(4)?,
+                    attempts: row.get(5)?,
+                    next_attempt_at: row.get(6)?,
+                    last_error: row.get(7)?,
+                    created_at: row.get(8)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(deliveries)
+    }
+
+    /// Counts a failed attempt of a queued delivery and schedules the next one
+    pub fn reschedule_delivery(
+        &self,
+        id: i64,
+        next_attempt_at: i64,
+        last_error: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE pending_deliveries
+             SET attempts = attempts + 1, next_attempt_at = ?1, last_error = ?2
+             WHERE id = ?3",
+            params![next_attempt_at, last_error, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_delivery(&self, id: i64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM pending_deliveries WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Stores the audience of a finished broadcast
+    pub fn insert_program_audience(
+        &self,
+        audience: &ProgramAudience,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
-
         conn.execute(
-            "UPDATE tracks SET title = ?1, artist = ?2, album = ?3, duration_seconds = ?4,
-                file_size = ?5, last_modified = ?6, file_extension = ?7, updated_at = ?8
-             WHERE file_path = ?9",
+            "INSERT INTO program_audiences
+                (program, started_at, ended_at, average_listeners, peak_listeners, joins, leaves)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
-                track.title,
-                track.artist,
-                track.album,
-                track.duration_seconds,
-                track.file_size,
-                track.last_modified,
-                track.file_extension,
-                track.updated_at,
-                track.file_path,
+                audience.program,
+                audience.started_at,
+                audience.ended_at.unwrap_or(audience.started_at),
+                audience.average_listeners,
+                audience.peak_listeners,
+                audience.joins,
+                audience.leaves
             ],
         )?;
-
         Ok(())
     }
 
-    pub fn update_tracks_batch(
+    /// Audiences of finished broadcasts, newest first, only those of `program` if given
+    pub fn get_program_audiences(
         &self,
-        tracks: &[TrackRecord],
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut conn = self.pool.get()?;
-        let tx = conn.transaction()?;
-
-        let mut stmt = tx.prepare(
-            "UPDATE tracks SET title = ?1, artist = ?2, album = ?3, duration_seconds = ?4,
-                file_size = ?5, last_modified = ?6, file_extension = ?7, updated_at = ?8
-             WHERE file_path = ?9",
+        program: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ProgramAudience>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT program, started_at, ended_at, average_listeners, peak_listeners, joins, leaves
+             FROM program_audiences
+             WHERE ?1 IS NULL OR program = ?1
+             ORDER BY started_at DESC, id DESC
+             LIMIT ?2",
         )?;
 
-        for track in tracks {
-            stmt.execute(params![
-                track.title,
-                track.artist,
-                track.album,
-                track.duration_seconds,
-                track.file_size,
-                track.last_modified,
-                track.file_extension,
-                track.updated_at,
-                track.file_path,
-            ])?;
-        }
-
-        drop(stmt);
-        tx.commit()?;
+        let audiences = stmt
+            .query_map(params![program, limit as i64], |row| {
+                Ok(ProgramAudience {
+                    program: row.get(0)?,
+                    started_at: row.get(1)?,
+                    ended_at: row.get(2)?,
+                    average_listeners: row.get(3)?,
+                    peak_listeners: row.get(4)?,
+                    joins: row.get(5)?,
+                    leaves: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(())
+        Ok(audiences)
     }
 
-    pub fn delete_track(&self, file_path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// Records that a track was played, replacing its previous play
+    pub fn record_recent_play(
+        &self,
+        track_id: i64,
+        played_at: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
         conn.execute(
-            "DELETE FROM tracks WHERE file_path = ?1",
-            params![file_path],
+            "INSERT INTO recent_plays (track_id, played_at) VALUES (?1, ?2)
+             ON CONFLICT(track_id) DO UPDATE SET played_at = excluded.played_at",
+            params![track_id, played_at],
         )?;
         Ok(())
     }
 
-    pub fn delete_tracks_batch(
+    /// Track ids with the time of their last play, for plays at or after `since`
+    pub fn get_recent_plays(
         &self,
-        file_paths: &[String],
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut conn = self.pool.get()?;
-        let tx = conn.transaction()?;
-
-        let mut stmt = tx.prepare("DELETE FROM tracks WHERE file_path = ?1")?;
-
-        for file_path in file_paths {
-            stmt.execute(params![file_path])?;
-        }
+        since: i64,
+    ) -> Result<Vec<(i64, i64)>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare("SELECT track_id, played_at FROM recent_plays WHERE played_at >= ?1")?;
+        let plays = stmt
+            .query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(plays)
+    }
 
-        drop(stmt);
-        tx.commit()?;
+    /// Forgets plays before `before`
+    pub fn prune_recent_plays(&self, before: i64) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let deleted = conn.execute(
+            "DELETE FROM recent_plays WHERE played_at < ?1",
+            params![before],
+        )?;
+        Ok(deleted)
+    }
 
+    /// Appends an aired item to the play history
+    pub fn insert_played_item(
+        &self,
+        item: &PlayedItem,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO play_history (played_at, source, program, streams, file_path, track_id,
+                title, artist, album, duration_seconds)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                item.played_at,
+                item.source.as_str(),
+                item.program,
+                item.streams.join(","),
+                item.file_path,
+                item.track_id,
+                item.title,
+                item.artist,
+                item.album,
+                item.duration_seconds
+            ],
+        )?;
         Ok(())
     }
 
-    pub fn get_all_tracks(&self) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+    /// Aired items played at or after `from` and before `until`, newest first
+    pub fn get_play_history(
+        &self,
+        from: Option<i64>,
+        until: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<PlayedItem>, Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
-
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, title, artist, album, duration_seconds,
-                file_size, last_modified, file_extension, created_at, updated_at
-             FROM tracks",
+            "SELECT played_at, source, program, streams, file_path, track_id, title, artist,
+                album, duration_seconds
+             FROM play_history
+             WHERE (?1 IS NULL OR played_at >= ?1) AND (?2 IS NULL OR played_at < ?2)
+             ORDER BY played_at DESC, id DESC
+             LIMIT ?3",
         )?;
 
-        let tracks = stmt
-            .query_map([], |row| {
-                Ok(TrackRecord {
-                    id: row.get(0)?,
-                    file_path: row.get(1)?,
-                    title: row.get(2)?,
-                    artist: row.get(3)?,
-                    album: row.get(4)?,
-                    duration_seconds: row.get(5)?,
-                    file_size: row.get(6)?,
-                    last_modified: row.get(7)?,
-                    file_extension: row.get(8)?,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
+        let items = stmt
+            .query_map(params![from, until, limit as i64], |row| {
+                let source: String = row.get(1)?;
+                let streams: String = row.get(3)?;
+                Ok(PlayedItem {
+                    played_at: row.get(0)?,
+                    source: PlaySource::from_column(&source),
+                    program: row.get(2)?,
+                    streams: streams
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(String::from)
+                        .collect(),
+                    file_path: row.get(4)?,
+                    track_id: row.get(5)?,
+                    title: row.get(6)?,
+                    artist: row.get(7)?,
+                    album: row.get(8)?,
+                    duration_seconds: row.get(9)?,
                 })
             })?
             .collect::<SqliteResult<Vec<_>>>()?;
 
-        Ok(tracks)
+        Ok(items)
     }
 
-    pub fn get_track_keys(&self) -> Result<Vec<TrackKey>, Box<dyn Error + Send + Sync>> {
+    /// Removes aired items played before `before`
+    pub fn prune_play_history(&self, before: i64) -> Result<usize, Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
-
-        let mut stmt = conn.prepare("SELECT id, file_path, last_modified FROM tracks")?;
-
-        let keys = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
-            .collect::<SqliteResult<Vec<_>>>()?;
-
-        Ok(keys)
+        let deleted = conn.execute(
+            "DELETE FROM play_history WHERE played_at < ?1",
+            params![before],
+        )?;
+        Ok(deleted)
     }
 
-    pub fn track_count(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    /// Stores the cue points of a track, removes them if neither is set
+    pub fn set_cue_points(
+        &self,
+        track_id: i64,
+        cue: &CuePoints,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
-        let count: i64 = conn.query_row("SELECT COUNT(*) FROM tracks", [], |row| row.get(0))?;
-        Ok(count as usize)
+        if cue.intro_seconds.is_none() && cue.outro_seconds.is_none() {
+            conn.execute(
+                "DELETE FROM cue_points WHERE track_id = ?1",
+                params![track_id],
+            )?;
+        } else {
+            conn.execute(
+                "INSERT INTO cue_points (track_id, intro_seconds, outro_seconds) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(track_id) DO UPDATE SET
+                    intro_seconds = excluded.intro_seconds,
+                    outro_seconds = excluded.outro_seconds",
+                params![track_id, cue.intro_seconds, cue.outro_seconds],
+            )?;
+        }
+        Ok(())
     }
 
-    pub fn get_metadata(&self, key: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    /// Cue points of the track at the path, `None` if it has none
+    pub fn get_cue_points(
+        &self,
+        file_path: &str,
+    ) -> Result<Option<CuePoints>, Box<dyn Error + Send + Sync>> {
         let conn = self.pool.get()?;
-        let result = conn
+        let cue = conn
             .query_row(
-                "SELECT value FROM library_metadata WHERE key = ?1",
-                params![key],
-                |row| row.get(0),
+                "SELECT c.intro_seconds, c.outro_seconds FROM cue_points c
+                 JOIN tracks t ON t.id = c.track_id
+                 WHERE t.file_path = ?1",
+                params![file_path],
+                |row| {
+                    Ok(CuePoints {
+                        intro_seconds: row.get(0)?,
+                        outro_seconds: row.get(1)?,
+                    })
+                },
             )
             .optional()?;
-        Ok(result)
+        Ok(cue)
     }
 
-    pub fn set_metadata(&self, key: &str, value: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let conn = self.pool.get()?;
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs() as i64;
+    /// Links the track to its artist, the artist is created with the first track naming it.
+    /// Tracks without a known artist are linked to none.
+    fn link_artist(
+        conn: &rusqlite::Connection,
+        file_path: &str,
+        artist: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let key = artist_key(artist);
+        if let Some(key) = &key {
+            conn.prepare_cached(
+                "INSERT OR IGNORE INTO artists (name, normalized_name) VALUES (?1, ?2)",
+            )?
+            .execute(params![main_artist(artist), key])?;
+        }
+        conn.prepare_cached(
+            "UPDATE tracks SET artist_id = (SELECT id FROM artists WHERE normalized_name = ?1)
+             WHERE file_path = ?2",
+        )?
+        .execute(params![key, file_path])?;
+        Ok(())
+    }
 
+    /// Deletes the artists left without tracks by track updates and deletions
+    fn prune_artists(conn: &rusqlite::Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
         conn.execute(
-            "INSERT OR REPLACE INTO library_metadata (key, value, updated_at)
-             VALUES (?1, ?2, ?3)",
-            params![key, value, now],
+            "DELETE FROM artists
+             WHERE id NOT IN (SELECT artist_id FROM tracks WHERE artist_id IS NOT NULL)",
+            [],
         )?;
+        Ok(())
+    }
 
+    fn insert_playlist_tracks(
+        tx: &rusqlite::Transaction,
+        playlist_id: i64,
+        track_ids: &[i64],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut stmt = tx.prepare(
+            "INSERT INTO playlist_tracks (playlist_id, position, track_id) VALUES (?1, ?2, ?3)",
+        )?;
+        for (position, track_id) in track_ids.iter().enumerate() {
+            stmt.execute(params![playlist_id, position as i64, track_id])?;
+        }
         Ok(())
     }
 }
@@ -342,6 +1961,18 @@ mod tests {
             file_extension: "mp3".to_string(),
             created_at: 1234567890,
             updated_at: 1234567890,
+            genre: None,
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+            year: None,
         }
     }
 
@@ -525,6 +2156,16 @@ mod tests {
         assert_eq!(value, Some("new_value".to_string()));
     }
 
+    #[test]
+    fn given_existing_metadata_when_deleted_then_returns_none() {
+        let (db, _temp) = create_test_db();
+        db.set_metadata("key", "value").unwrap();
+
+        db.delete_metadata("key").unwrap();
+
+        assert_eq!(db.get_metadata("key").unwrap(), None);
+    }
+
     #[test]
     fn given_duplicate_file_path_when_inserted_then_returns_error() {
         let (db, _temp) = create_test_db();
@@ -535,4 +2176,512 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn given_track_failing_below_threshold_when_failure_recorded_then_not_quarantined() {
+        let (db, _temp) = create_test_db();
+        db.insert_track(&create_test_track("/music/song1.mp3"))
+            .unwrap();
+
+        let quarantined = db
+            .record_track_failure("/music/song1.mp3", "decode error", 3)
+            .unwrap();
+
+        assert!(!quarantined);
+        let problems = db.get_problem_tracks().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].failure_count, 1);
+        assert_eq!(problems[0].last_error, Some("decode error".to_string()));
+        assert_eq!(db.get_playable_tracks().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn given_track_reaching_failure_threshold_when_failure_recorded_then_quarantined() {
+        let (db, _temp) = create_test_db();
+        db.insert_tracks_batch(&[
+            create_test_track("/music/song1.mp3"),
+            create_test_track("/music/song2.mp3"),
+        ])
+        .unwrap();
+
+        db.record_track_failure("/music/song1.mp3", "decode error", 2)
+            .unwrap();
+        let quarantined = db
+            .record_track_failure("/music/song1.mp3", "decode error", 2)
+            .unwrap();
+
+        assert!(quarantined);
+        assert!(db.get_problem_tracks().unwrap()[0].quarantined);
+        let playable = db.get_playable_tracks().unwrap();
+        assert_eq!(playable.len(), 1);
+        assert_eq!(playable[0].file_path, "/music/song2.mp3");
+    }
+
+    #[test]
+    fn given_failed_track_when_success_recorded_then_failure_count_reset() {
+        let (db, _temp) = create_test_db();
+        db.insert_track(&create_test_track("/music/song1.mp3"))
+            .unwrap();
+        db.record_track_failure("/music/song1.mp3", "timeout", 3)
+            .unwrap();
+
+        db.record_track_success("/music/song1.mp3").unwrap();
+
+        assert!(db.get_problem_tracks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn given_quarantined_track_when_file_updated_then_quarantine_lifted() {
+        let (db, _temp) = create_test_db();
+        let track = create_test_track("/music/song1.mp3");
+        db.insert_track(&track).unwrap();
+        db.record_track_failure("/music/song1.mp3", "decode error", 1)
+            .unwrap();
+
+        db.update_track(&track).unwrap();
+
+        assert!(db.get_problem_tracks().unwrap().is_empty());
+        assert_eq!(db.get_playable_tracks().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn given_unknown_path_when_failure_recorded_then_returns_not_quarantined() {
+        let (db, _temp) = create_test_db();
+
+        let quarantined = db
+            .record_track_failure("https://example.com/stream", "http error", 1)
+            .unwrap();
+
+        assert!(!quarantined);
+    }
+
+    #[test]
+    fn given_long_and_short_tracks_when_querying_long_ones_then_returns_them_with_genre() {
+        let (db, _temp) = create_test_db();
+        for (path, duration, genre) in [
+            ("/music/mix.mp3", Some(3600), Some("Techno; House")),
+            ("/music/song.mp3", Some(240), Some("Techno")),
+            ("/music/unknown.mp3", None, None),
+        ] {
+            let mut track = create_test_track(path);
+            track.duration_seconds = duration;
+            track.genre = genre.map(String::from);
+            db.insert_track(&track).unwrap();
+        }
+
+        let tracks = db.get_tracks_longer_than(1200).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].file_path, "/music/mix.mp3");
+        assert_eq!(tracks[0].genre.as_deref(), Some("Techno; House"));
+    }
+
+    #[test]
+    fn given_rated_track_when_rescanned_then_keeps_its_rating() {
+        let (db, _temp) = create_test_db();
+        let id = db
+            .insert_track(&create_test_track("/music/song.mp3"))
+            .unwrap();
+
+        assert!(db.set_track_rating(id, Some(5)).unwrap());
+        assert!(!db.set_track_rating(id + 1, Some(5)).unwrap());
+        db.update_track(&create_test_track("/music/song.mp3"))
+            .unwrap();
+        assert_eq!(db.get_track(id).unwrap().unwrap().rating, Some(5));
+
+        db.set_track_rating(id, None).unwrap();
+        assert_eq!(db.get_track(id).unwrap().unwrap().rating, None);
+    }
+
+    #[test]
+    fn given_duplicate_files_when_reporting_then_groups_them_and_plays_the_first_one() {
+        let (db, _temp) = create_test_db();
+        for (path, content_hash, audio_fingerprint) in [
+            ("/music/a.mp3", "aaa", Some("audio-a")),
+            ("/music/copy/a.mp3", "aaa", Some("audio-a")),
+            ("/music/a-retagged.mp3", "bbb", Some("audio-a")),
+            ("/music/c.mp3", "ccc", Some("audio-c")),
+            ("/music/d.mp3", "ddd", None),
+        ] {
+            let mut track = create_test_track(path);
+            track.content_hash = Some(content_hash.to_string());
+            track.audio_fingerprint = audio_fingerprint.map(String::from);
+            db.insert_track(&track).unwrap();
+        }
+        let paths = |tracks: &[TrackRecord]| -> Vec<String> {
+            tracks.iter().map(|t| t.file_path.clone()).collect()
+        };
+
+        let groups = db.get_duplicate_groups().unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].matched_by, DuplicateMatch::Content);
+        assert_eq!(groups[0].hash, "aaa");
+        assert_eq!(
+            paths(&groups[0].tracks),
+            ["/music/a.mp3", "/music/copy/a.mp3"]
+        );
+        assert_eq!(groups[1].matched_by, DuplicateMatch::Audio);
+        assert_eq!(groups[1].tracks.len(), 3);
+        assert_eq!(
+            paths(&db.get_playable_tracks().unwrap()),
+            ["/music/a.mp3", "/music/c.mp3", "/music/d.mp3"]
+        );
+
+        // A quarantined original leaves one of its copies in the rotation
+        db.record_track_failure("/music/a.mp3", "broken", 1)
+            .unwrap();
+        assert_eq!(
+            paths(&db.get_playable_tracks().unwrap()),
+            ["/music/copy/a.mp3", "/music/c.mp3", "/music/d.mp3"]
+        );
+    }
+
+    #[test]
+    fn given_library_when_searching_then_matches_word_prefixes_and_follows_changes() {
+        let (db, _temp) = create_test_db();
+        for (path, title, artist, album) in [
+            ("/music/a.mp3", "Help!", "The Beatles", "Live at the BBC"),
+            ("/music/b.mp3", "Café del Mar", "Energy 52", "Café del Mar"),
+            ("/music/c.mp3", "Paranoid", "Black Sabbath", "Paranoid"),
+        ] {
+            let mut track = create_test_track(path);
+            track.title = title.to_string();
+            track.artist = artist.to_string();
+            track.album = album.to_string();
+            db.insert_track(&track).unwrap();
+        }
+        let paths = |query: &str| -> Vec<String> {
+            db.search_tracks(query, 10)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.file_path)
+                .collect()
+        };
+
+        assert_eq!(paths("beat liv"), vec!["/music/a.mp3"]);
+        assert_eq!(paths("CAFE"), vec!["/music/b.mp3"]);
+        assert_eq!(paths("\"help\" OR NOT*"), Vec::<String>::new());
+        assert!(paths("  ").is_empty());
+
+        let mut renamed = db.get_track_by_path("/music/c.mp3").unwrap().unwrap();
+        renamed.title = "Iron Man".to_string();
+        db.update_track(&renamed).unwrap();
+        db.delete_track("/music/a.mp3").unwrap();
+
+        assert_eq!(paths("iron"), vec!["/music/c.mp3"]);
+        assert!(paths("beatles").is_empty());
+    }
+
+    #[test]
+    fn given_spellings_of_an_artist_when_tracks_stored_then_they_share_one_artist() {
+        let (db, _temp) = create_test_db();
+        let mut tracks = Vec::new();
+        for (path, artist) in [
+            ("/music/a.mp3", "Daft Punk"),
+            ("/music/b.mp3", "daft punk feat. Pharrell Williams"),
+            ("/music/c.mp3", "Air"),
+        ] {
+            let mut track = create_test_track(path);
+            track.artist = artist.to_string();
+            tracks.push(track);
+        }
+        db.insert_tracks_batch(&tracks[..2]).unwrap();
+        db.insert_track(&tracks[2]).unwrap();
+
+        let artists = db.get_artists().unwrap();
+        let names: Vec<(&str, usize)> = artists
+            .iter()
+            .map(|a| (a.name.as_str(), a.track_count))
+            .collect();
+        assert_eq!(names, [("Air", 1), ("Daft Punk", 2)]);
+        let daft_punk = db.get_artist_tracks(artists[1].id).unwrap().unwrap();
+        assert_eq!(daft_punk.len(), 2);
+
+        // Re-tagged and deleted tracks leave no artist without tracks behind
+        tracks[2].artist = "AIR".to_string();
+        db.update_track(&tracks[2]).unwrap();
+        db.delete_track("/music/a.mp3").unwrap();
+        tracks[1].artist = "Justice".to_string();
+        db.update_tracks_batch(&tracks[1..2]).unwrap();
+
+        let names: Vec<String> = db
+            .get_artists()
+            .unwrap()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        assert_eq!(names, ["Air", "Justice"]);
+        assert!(db.get_artist_tracks(artists[1].id).unwrap().is_none());
+    }
+
+    #[test]
+    fn given_library_without_new_columns_when_initialized_then_adds_them_and_rescans_tracks() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let conn = rusqlite::Connection::open(temp_file.path()).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tracks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_path TEXT NOT NULL UNIQUE,
+                title TEXT NOT NULL,
+                artist TEXT NOT NULL,
+                album TEXT NOT NULL,
+                duration_seconds INTEGER,
+                file_size INTEGER NOT NULL,
+                last_modified INTEGER NOT NULL,
+                file_extension TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                failure_count INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                quarantined INTEGER NOT NULL DEFAULT 0
+            );
+            INSERT INTO tracks (file_path, title, artist, album, file_size, last_modified,
+                file_extension, created_at, updated_at)
+            VALUES ('/music/old.mp3', 'Old', 'Artist', 'Album', 1000, 1234567890, 'mp3', 0, 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        db.initialize_schema().unwrap();
+
+        let track = db.get_track_by_path("/music/old.mp3").unwrap().unwrap();
+        assert_eq!(track.genre, None);
+        assert_eq!(track.artwork_path, None);
+        assert_eq!(track.rating, None);
+        assert_eq!(track.year, None);
+        assert_eq!(track.last_modified, 0);
+        assert_eq!(db.search_tracks("old", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn given_inserted_track_when_fetched_by_id_then_returns_track() {
+        let (db, _temp) = create_test_db();
+        let id = db
+            .insert_track(&create_test_track("/music/song.mp3"))
+            .unwrap();
+
+        let track = db.get_track(id).unwrap().unwrap();
+
+        assert_eq!(track.id, Some(id));
+        assert_eq!(track.file_path, "/music/song.mp3");
+        assert!(db.get_track(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn given_playlist_when_created_then_tracks_keep_their_order() {
+        let (db, _temp) = create_test_db();
+        let first = db.insert_track(&create_test_track("/music/a.mp3")).unwrap();
+        let second = db.insert_track(&create_test_track("/music/b.mp3")).unwrap();
+
+        let id = db
+            .create_playlist("Friday Warmup", &[second, first, second])
+            .unwrap();
+
+        let playlist = db.get_playlist_by_name("Friday Warmup").unwrap().unwrap();
+        let paths: Vec<&str> = playlist
+            .tracks
+            .iter()
+            .map(|t| t.file_path.as_str())
+            .collect();
+        assert_eq!(playlist.id, id);
+        assert_eq!(paths, vec!["/music/b.mp3", "/music/a.mp3", "/music/b.mp3"]);
+
+        let summaries = db.get_playlists().unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].track_count, 3);
+        assert_eq!(summaries[0].duration_seconds, 540);
+    }
+
+    #[test]
+    fn given_smart_playlist_when_created_updated_and_deleted_then_stores_its_query() {
+        let (db, _temp) = create_test_db();
+        let id = db
+            .create_smart_playlist("Jazz Classics", "genre = jazz")
+            .unwrap();
+
+        assert!(db
+            .update_smart_playlist(id, "Jazz Favorites", "genre = jazz AND rating >= 4")
+            .unwrap());
+        assert!(!db
+            .update_smart_playlist(id + 1, "Other", "rating = 5")
+            .unwrap());
+
+        let playlist = db
+            .get_smart_playlist_by_name("Jazz Favorites")
+            .unwrap()
+            .unwrap();
+        assert_eq!(playlist.id, id);
+        assert_eq!(playlist.query, "genre = jazz AND rating >= 4");
+        assert_eq!(db.get_smart_playlists().unwrap(), vec![playlist]);
+
+        assert!(db.delete_smart_playlist(id).unwrap());
+        assert!(db.get_smart_playlist(id).unwrap().is_none());
+    }
+
+    #[test]
+    fn given_playlist_when_updated_then_name_and_tracks_are_replaced() {
+        let (db, _temp) = create_test_db();
+        let first = db.insert_track(&create_test_track("/music/a.mp3")).unwrap();
+        let second = db.insert_track(&create_test_track("/music/b.mp3")).unwrap();
+        let id = db.create_playlist("Warmup", &[first]).unwrap();
+
+        assert!(db.update_playlist(id, "Cooldown", &[second]).unwrap());
+        assert!(!db.update_playlist(id + 1, "Other", &[first]).unwrap());
+
+        let playlist = db.get_playlist(id).unwrap().unwrap();
+        assert_eq!(playlist.name, "Cooldown");
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks[0].id, Some(second));
+        assert!(db.get_playlist_by_name("Warmup").unwrap().is_none());
+    }
+
+    #[test]
+    fn given_playlist_when_tracks_or_playlist_deleted_then_entries_are_gone() {
+        let (db, _temp) = create_test_db();
+        let first = db.insert_track(&create_test_track("/music/a.mp3")).unwrap();
+        let second = db.insert_track(&create_test_track("/music/b.mp3")).unwrap();
+        let id = db.create_playlist("Warmup", &[first, second]).unwrap();
+
+        db.delete_track("/music/a.mp3").unwrap();
+        assert_eq!(db.get_playlist(id).unwrap().unwrap().tracks.len(), 1);
+        assert_eq!(
+            db.get_missing_track_ids(&[first, second]).unwrap(),
+            vec![first]
+        );
+
+        assert!(db.delete_playlist(id).unwrap());
+        assert!(!db.delete_playlist(id).unwrap());
+        assert!(db.get_playlist(id).unwrap().is_none());
+        assert!(db.create_playlist("Warmup", &[]).is_ok());
+    }
+
+    #[test]
+    fn given_failed_delivery_when_queued_then_due_once_next_attempt_reached() {
+        let (db, _temp) = create_test_db();
+
+        let id = db
+            .enqueue_delivery(
+                "hook",
+                "https://example.com",
+                "track_change",
+                "{}",
+                100,
+                "timeout",
+            )
+            .unwrap();
+        assert!(db.get_pending_deliveries(Some(99)).unwrap().is_empty());
+        assert_eq!(db.get_pending_deliveries(Some(100)).unwrap().len(), 1);
+
+        db.reschedule_delivery(id, 200, "503 Service Unavailable")
+            .unwrap();
+        let pending = db.get_pending_deliveries(None).unwrap();
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].next_attempt_at, 200);
+        assert_eq!(
+            pending[0].last_error.as_deref(),
+            Some("503 Service Unavailable")
+        );
+
+        db.delete_delivery(id).unwrap();
+        assert!(db.get_pending_deliveries(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn given_program_audiences_when_listed_then_newest_first_and_filtered_by_program() {
+        let (db, _temp) = create_test_db();
+        let audience = |program: &str, started_at: i64| ProgramAudience {
+            program: program.to_string(),
+            started_at,
+            ended_at: Some(started_at + 3600),
+            average_listeners: 4.5,
+            peak_listeners: 9,
+            joins: 12,
+            leaves: 7,
+        };
+
+        db.insert_program_audience(&audience("Morning Show", 100))
+            .unwrap();
+        db.insert_program_audience(&audience("Night Shift", 200))
+            .unwrap();
+        db.insert_program_audience(&audience("Morning Show", 300))
+            .unwrap();
+
+        let all = db.get_program_audiences(None, 10).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0], audience("Morning Show", 300));
+
+        let morning = db.get_program_audiences(Some("Morning Show"), 1).unwrap();
+        assert_eq!(morning, vec![audience("Morning Show", 300)]);
+    }
+
+    #[test]
+    fn given_recent_plays_when_replayed_and_pruned_then_keeps_last_play_in_window() {
+        let (db, _temp) = create_test_db();
+
+        db.record_recent_play(1, 1000).unwrap();
+        db.record_recent_play(2, 2000).unwrap();
+        db.record_recent_play(1, 3000).unwrap();
+
+        let mut plays = db.get_recent_plays(1500).unwrap();
+        plays.sort();
+        assert_eq!(plays, vec![(1, 3000), (2, 2000)]);
+
+        assert_eq!(db.prune_recent_plays(2500).unwrap(), 1);
+        assert_eq!(db.get_recent_plays(0).unwrap(), vec![(1, 3000)]);
+    }
+
+    #[test]
+    fn given_cue_points_when_set_and_cleared_then_read_by_path_until_track_deleted() {
+        let (db, _temp) = create_test_db();
+        let id = db
+            .insert_track(&create_test_track("/music/song1.mp3"))
+            .unwrap();
+        let cue = CuePoints {
+            intro_seconds: Some(8.5),
+            outro_seconds: Some(170.0),
+        };
+
+        db.set_cue_points(id, &cue).unwrap();
+        assert_eq!(db.get_cue_points("/music/song1.mp3").unwrap(), Some(cue));
+
+        db.set_cue_points(id, &CuePoints::default()).unwrap();
+        assert_eq!(db.get_cue_points("/music/song1.mp3").unwrap(), None);
+
+        db.set_cue_points(id, &cue).unwrap();
+        db.delete_track("/music/song1.mp3").unwrap();
+        assert_eq!(db.get_cue_points("/music/song1.mp3").unwrap(), None);
+    }
+
+    #[test]
+    fn given_locked_database_when_checking_health_then_unavailable_until_released() {
+        let (_, temp) = create_test_db();
+        let locker = rusqlite::Connection::open(temp.path()).unwrap();
+        // Without busy timeout, so the check fails right away instead of retrying
+        let db = LibraryDatabase {
+            pool: Pool::builder()
+                .max_size(1)
+                .build(
+                    SqliteConnectionManager::file(temp.path())
+                        .with_init(|conn| conn.busy_timeout(Duration::ZERO)),
+                )
+                .unwrap(),
+            health: Arc::default(),
+        };
+
+        // Like a backup tool holding the database, WAL mode would let readers pass a transaction
+        locker
+            .execute_batch("PRAGMA locking_mode = EXCLUSIVE; BEGIN EXCLUSIVE;")
+            .unwrap();
+        let health = db.check_health();
+        assert!(!health.available);
+        assert!(health.unavailable_since.is_some());
+        assert!(health.last_error.unwrap().contains("locked"));
+
+        drop(locker);
+        assert!(db.check_health().available);
+        assert!(db.health().last_error.is_none());
+    }
 }
@@ -1,11 +1,27 @@
+use crate::artist_names::UNKNOWN_ARTIST;
+use crate::artwork_cache::ArtworkCache;
+use crate::cue_sheet::{self, CueSheet, TrackSection};
+use crate::file_validator::FileValidator;
 use crate::library_db::{LibraryDatabase, TrackRecord};
+use crate::m3u_parser::M3uParser;
+use crate::notifier::{Notifier, WebhookEvent};
+use crate::playout_control::PlayoutControl;
+use crate::tempo_analysis::{TempoAnalyzer, TrackTempo};
+use crate::track_cache::TrackCache;
+use crate::track_hashes::{content_hash, AudioFingerprinter};
 use audiotags::Tag;
 use log::{debug, info, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata key of the time of the last incremental scan that looked at every file
+const LAST_RECONCILIATION: &str = "last_scan_reconciliation";
+
+/// Hours between scans looking at every file when unchanged directories are skipped
+pub const DEFAULT_RECONCILE_HOURS: u32 = 24;
 
 #[derive(Debug)]
 pub struct ScanResult {
@@ -16,9 +32,26 @@ pub struct ScanResult {
     pub errors: Vec<String>,
 }
 
+/// Album ripped to a single audio file, with a cue sheet listing its tracks
+struct CueAlbum {
+    sheet_path: PathBuf,
+    sheet: CueSheet,
+    audio_file: PathBuf,
+}
+
+#[derive(Clone)]
 pub struct LibraryScanner {
     music_directory: PathBuf,
     db: LibraryDatabase,
+    notifier: Option<Notifier>,
+    track_cache: Option<TrackCache>,
+    playout_control: Option<PlayoutControl>,
+    artwork_cache: Option<ArtworkCache>,
+    fingerprinter: Option<AudioFingerprinter>,
+    validator: Option<FileValidator>,
+    tempo_analyzer: Option<TempoAnalyzer>,
+    /// Interval of the incremental scans looking at every file, `None` to always look at all
+    reconcile_interval: Option<Duration>,
 }
 
 impl LibraryScanner {
@@ -26,6 +59,86 @@ impl LibraryScanner {
         Self {
             music_directory,
             db,
+            notifier: None,
+            track_cache: None,
+            playout_control: None,
+            artwork_cache: None,
+            fingerprinter: None,
+            validator: None,
+            tempo_analyzer: None,
+            reconcile_interval: None,
+        }
+    }
+
+    /// Sends a `scan_complete` webhook event after every scan
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Keeps the track cache in sync with scans, imports and deletions
+    pub fn with_track_cache(mut self, track_cache: TrackCache) -> Self {
+        self.track_cache = Some(track_cache);
+        self
+    }
+
+    /// Lets tracks removed through the API play again once a scan or import adds them back
+    pub fn with_playout_control(mut self, playout_control: PlayoutControl) -> Self {
+        self.playout_control = Some(playout_control);
+        self
+    }
+
+    /// Writes thumbnails of the embedded cover art of scanned tracks
+    pub fn with_artwork_cache(mut self, artwork_cache: ArtworkCache) -> Self {
+        self.artwork_cache = Some(artwork_cache);
+        self
+    }
+
+    /// Stores a hash of the decoded audio of scanned tracks to find re-tagged duplicates
+    pub fn with_audio_fingerprints(mut self, fingerprinter: AudioFingerprinter) -> Self {
+        self.fingerprinter = Some(fingerprinter);
+        self
+    }
+
+    /// Decodes new and changed files, quarantining the ones that cannot be played
+    pub fn with_validator(mut self, validator: FileValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Incremental scans only look at the files of directories whose modification time changed,
+    /// files changed in place are found by a scan looking at every file once per `interval`
+    pub fn with_unchanged_directories_skipped(mut self, interval: Duration) -> Self {
+        self.reconcile_interval = Some(interval);
+        self
+    }
+
+    /// Estimates tempo and energy of scanned tracks
+    pub fn with_tempo_analysis(mut self, tempo_analyzer: TempoAnalyzer) -> Self {
+        self.tempo_analyzer = Some(tempo_analyzer);
+        self
+    }
+
+    /// Tracks skipped when they were deleted are part of the library again
+    fn tracks_added<'a>(&self, file_paths: impl IntoIterator<Item = &'a str>) {
+        if let Some(playout_control) = &self.playout_control {
+            for file_path in file_paths {
+                playout_control.allow(Path::new(file_path));
+            }
+        }
+    }
+
+    fn scan_complete(&self, result: &ScanResult) {
+        if let Some(track_cache) = &self.track_cache {
+            track_cache.reload();
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(WebhookEvent::ScanComplete {
+                added: result.added,
+                updated: result.updated,
+                deleted: result.deleted,
+                errors: result.errors.len(),
+            });
         }
     }
 
@@ -41,9 +154,18 @@ impl LibraryScanner {
         };
 
         let mut files = Vec::new();
-        self.scan_directory_recursive(&self.music_directory, &mut files)?;
+        let mut directories = HashMap::new();
+        self.scan_directory_recursive(&self.music_directory, &mut files, &mut directories)?;
+        let (playlists, files): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .into_iter()
+            .partition(|path| M3uParser::is_playlist_file(path));
+        let (files, albums) = self.split_albums(files, &mut result);
 
-        info!("Found {} audio files", files.len());
+        info!(
+            "Found {} audio files and {} single-file albums",
+            files.len(),
+            albums.len()
+        );
 
         let mut tracks = Vec::new();
 
@@ -58,11 +180,21 @@ impl LibraryScanner {
                 }
             }
         }
+        for album in &albums {
+            match self.process_album(album) {
+                Ok(album_tracks) => tracks.extend(album_tracks),
+                Err(e) => {
+                    warn!("Failed to process album {:?}: {}", album.sheet_path, e);
+                    result.errors.push(format!("{:?}: {}", album.sheet_path, e));
+                }
+            }
+        }
 
         match self.db.insert_tracks_batch(&tracks) {
             Ok(_) => {
                 result.added = tracks.len();
                 info!("Inserted {} tracks in batch", tracks.len());
+                self.tracks_added(tracks.iter().map(|t| t.file_path.as_str()));
             }
             Err(e) => {
                 warn!(
@@ -74,6 +206,7 @@ impl LibraryScanner {
                         Ok(_) => {
                             debug!("Added track: {}", track.file_path);
                             result.added += 1;
+                            self.tracks_added([track.file_path.as_str()]);
                         }
                         Err(e) => {
                             warn!("Failed to insert track {}: {}", track.file_path, e);
@@ -83,12 +216,15 @@ impl LibraryScanner {
                 }
             }
         }
+        self.index_playlists(&playlists, &mut result)?;
 
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)?
             .as_secs()
             .to_string();
         self.db.set_metadata("last_full_scan", &now)?;
+        self.db.set_metadata(LAST_RECONCILIATION, &now)?;
+        self.db.replace_directory_mtimes(&directories)?;
 
         info!(
             "Full scan complete: +{} added, {} errors",
@@ -96,6 +232,7 @@ impl LibraryScanner {
             result.errors.len()
         );
 
+        self.scan_complete(&result);
         Ok(result)
     }
 
@@ -117,7 +254,52 @@ impl LibraryScanner {
             .collect();
 
         let mut files = Vec::new();
-        self.scan_directory_recursive(&self.music_directory, &mut files)?;
+        let mut directories = HashMap::new();
+        let mut unchanged = HashSet::new();
+        let reconcile = self.needs_reconciliation(&existing_map)?;
+        if reconcile {
+            self.scan_directory_recursive(&self.music_directory, &mut files, &mut directories)?;
+        } else {
+            let stored = self.db.get_directory_mtimes()?;
+            self.scan_changed_directories(
+                &self.music_directory,
+                &subdirectories(&stored),
+                &stored,
+                &mut files,
+                &mut directories,
+                &mut unchanged,
+            )?;
+            // Files of unchanged directories were neither added, removed nor renamed
+            existing_map.retain(|file_path, _| {
+                let in_unchanged = Path::new(file_path)
+                    .parent()
+                    .is_some_and(|parent| unchanged.contains(parent));
+                if in_unchanged {
+                    result.unchanged += 1;
+                }
+                !in_unchanged
+            });
+            debug!(
+                "Skipped {} of {} directories, their modification time is unchanged",
+                unchanged.len(),
+                directories.len()
+            );
+        }
+
+        let (mut playlists, files): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .into_iter()
+            .partition(|path| M3uParser::is_playlist_file(path));
+        let (files, albums) = self.split_albums(files, &mut result);
+        if !unchanged.is_empty() {
+            // Playlist files of unchanged directories still exist, their tracks may have changed
+            playlists.extend(
+                self.db
+                    .get_library_playlist_paths()?
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .filter(|path| path.parent().is_some_and(|dir| unchanged.contains(dir))),
+            );
+        }
 
         let mut tracks_to_add = Vec::new();
         let mut tracks_to_update = Vec::new();
@@ -160,11 +342,55 @@ impl LibraryScanner {
             }
         }
 
+        for album in &albums {
+            let modified = match self.album_mtime(album) {
+                Ok(modified) => modified,
+                Err(e) => {
+                    warn!("Failed to get mtime for {:?}: {}", album.audio_file, e);
+                    result.errors.push(format!("{:?}: {}", album.audio_file, e));
+                    continue;
+                }
+            };
+            // The tracks of an album are read together, all again if any of them changed
+            let stored: Vec<Option<(i64, i64)>> = album
+                .sheet
+                .tracks
+                .iter()
+                .map(|track| {
+                    let path = cue_sheet::track_path(&album.audio_file, track.number);
+                    existing_map.remove(path.to_string_lossy().as_ref())
+                })
+                .collect();
+            if stored
+                .iter()
+                .all(|track| track.is_some_and(|(db_mtime, _)| db_mtime == modified))
+            {
+                result.unchanged += stored.len();
+                continue;
+            }
+
+            match self.process_album(album) {
+                Ok(album_tracks) => {
+                    for (track, stored) in album_tracks.into_iter().zip(stored) {
+                        match stored {
+                            Some(_) => tracks_to_update.push(track),
+                            None => tracks_to_add.push(track),
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to process album {:?}: {}", album.sheet_path, e);
+                    result.errors.push(format!("{:?}: {}", album.sheet_path, e));
+                }
+            }
+        }
+
         if !tracks_to_add.is_empty() {
             match self.db.insert_tracks_batch(&tracks_to_add) {
                 Ok(_) => {
                     result.added = tracks_to_add.len();
                     info!("Added {} tracks in batch", tracks_to_add.len());
+                    self.tracks_added(tracks_to_add.iter().map(|t| t.file_path.as_str()));
                 }
                 Err(e) => {
                     warn!(
@@ -176,6 +402,7 @@ impl LibraryScanner {
                             Ok(_) => {
                                 debug!("Added new track: {}", track.file_path);
                                 result.added += 1;
+                                self.tracks_added([track.file_path.as_str()]);
                             }
                             Err(e) => {
                                 warn!("Failed to insert track {}: {}", track.file_path, e);
@@ -242,12 +469,17 @@ impl LibraryScanner {
                 }
             }
         }
+        self.index_playlists(&playlists, &mut result)?;
 
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)?
             .as_secs()
             .to_string();
         self.db.set_metadata("last_incremental_scan", &now)?;
+        if reconcile {
+            self.db.set_metadata(LAST_RECONCILIATION, &now)?;
+        }
+        self.db.replace_directory_mtimes(&directories)?;
 
         if result.added > 0 || result.updated > 0 || result.deleted > 0 {
             info!(
@@ -258,14 +490,44 @@ impl LibraryScanner {
             info!("No library changes detected");
         }
 
+        self.scan_complete(&result);
         Ok(result)
     }
 
+    /// Whether the incremental scan has to look at every file: unchanged directories are not
+    /// skipped, the reconciliation is due or tracks are marked to be read again
+    fn needs_reconciliation(
+        &self,
+        existing: &HashMap<String, (i64, i64)>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let Some(interval) = self.reconcile_interval else {
+            return Ok(true);
+        };
+        if existing
+            .values()
+            .any(|(last_modified, _)| *last_modified == 0)
+        {
+            return Ok(true);
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let last = self
+            .db
+            .get_metadata(LAST_RECONCILIATION)?
+            .and_then(|value| value.parse::<u64>().ok());
+        Ok(last.is_none_or(|last| now.saturating_sub(last) >= interval.as_secs()))
+    }
+
+    /// Collects the audio, playlist and cue files below `dir` and the modification times of its
+    /// directories
     fn scan_directory_recursive(
         &self,
         dir: &Path,
         files: &mut Vec<PathBuf>,
+        directories: &mut HashMap<String, i64>,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Taken before reading the entries, so changes during the scan are seen by the next one
+        directories.insert(dir.to_string_lossy().to_string(), directory_mtime(dir)?);
         let entries = fs::read_dir(dir)?;
 
         for entry in entries {
@@ -273,8 +535,11 @@ impl LibraryScanner {
             let path = entry.path();
 
             if path.is_dir() {
-                self.scan_directory_recursive(&path, files)?;
-            } else if self.is_audio_file(&path) {
+                self.scan_directory_recursive(&path, files, directories)?;
+            } else if Self::is_audio_file(&path)
+                || M3uParser::is_playlist_file(&path)
+                || cue_sheet::is_cue_file(&path)
+            {
                 files.push(path);
             }
         }
@@ -282,7 +547,167 @@ impl LibraryScanner {
         Ok(())
     }
 
-    fn is_audio_file(&self, path: &Path) -> bool {
+    /// Like `scan_directory_recursive`, but directories whose modification time matches
+    /// `stored` are not read: their entries are unchanged, so only their subdirectories known
+    /// from the last scan are visited. The unchanged directories are added to `unchanged`.
+    fn scan_changed_directories(
+        &self,
+        dir: &Path,
+        children: &HashMap<PathBuf, Vec<PathBuf>>,
+        stored: &HashMap<String, i64>,
+        files: &mut Vec<PathBuf>,
+        directories: &mut HashMap<String, i64>,
+        unchanged: &mut HashSet<PathBuf>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = dir.to_string_lossy().to_string();
+        let modified = directory_mtime(dir)?;
+        let is_unchanged = stored.get(&path) == Some(&modified);
+        directories.insert(path, modified);
+
+        if is_unchanged {
+            unchanged.insert(dir.to_path_buf());
+            for child in children.get(dir).into_iter().flatten() {
+                self.scan_changed_directories(
+                    child,
+                    children,
+                    stored,
+                    files,
+                    directories,
+                    unchanged,
+                )?;
+            }
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                self.scan_changed_directories(
+                    &path,
+                    children,
+                    stored,
+                    files,
+                    directories,
+                    unchanged,
+                )?;
+            } else if Self::is_audio_file(&path)
+                || M3uParser::is_playlist_file(&path)
+                || cue_sheet::is_cue_file(&path)
+            {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Separates the audio files of single-file albums from the other files by the cue sheets
+    /// among them. The album file replaces the audio file, which is not a track of its own.
+    fn split_albums(
+        &self,
+        files: Vec<PathBuf>,
+        result: &mut ScanResult,
+    ) -> (Vec<PathBuf>, Vec<CueAlbum>) {
+        let (sheets, mut files): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .into_iter()
+            .partition(|path| cue_sheet::is_cue_file(path));
+
+        let found: HashSet<PathBuf> = files.iter().cloned().collect();
+        let mut albums = Vec::new();
+        for sheet_path in sheets {
+            let sheet = match CueSheet::read(&sheet_path) {
+                Ok(sheet) => sheet,
+                Err(e) => {
+                    warn!("Failed to read cue sheet {:?}: {}", sheet_path, e);
+                    result.errors.push(format!("{:?}: {}", sheet_path, e));
+                    continue;
+                }
+            };
+            let audio_file = sheet.audio_file(&sheet_path);
+            if !found.contains(&audio_file) {
+                let error = format!("No audio file {:?} next to the cue sheet", sheet.file);
+                warn!("Skipping cue sheet {:?}: {}", sheet_path, error);
+                result.errors.push(format!("{:?}: {}", sheet_path, error));
+                continue;
+            }
+            albums.push(CueAlbum {
+                sheet_path,
+                sheet,
+                audio_file,
+            });
+        }
+
+        let album_files: HashSet<&PathBuf> = albums.iter().map(|a| &a.audio_file).collect();
+        files.retain(|path| !album_files.contains(path));
+        (files, albums)
+    }
+
+    /// Stores the playlist files of the music directory as playlists of the library tracks they
+    /// list, and deletes the playlists of files that are gone. Runs after the tracks are stored,
+    /// so entries of new tracks resolve.
+    fn index_playlists(
+        &self,
+        playlists: &[PathBuf],
+        result: &mut ScanResult,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let track_ids: HashMap<String, i64> = self
+            .db
+            .get_track_keys()?
+            .into_iter()
+            .map(|(id, file_path, _)| (file_path, id))
+            .collect();
+
+        let mut indexed = HashSet::new();
+        let mut changed = 0;
+        for path in playlists {
+            let source_path = path.to_string_lossy().to_string();
+            match self.index_playlist(path, &source_path, &track_ids) {
+                Ok(true) => changed += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Failed to index playlist {:?}: {}", path, e);
+                    result.errors.push(format!("{:?}: {}", path, e));
+                }
+            }
+            // A playlist indexed before is kept when its file cannot be read this time
+            indexed.insert(source_path);
+        }
+
+        let deleted = self.db.delete_library_playlists_except(&indexed)?;
+        if changed > 0 || deleted > 0 {
+            info!(
+                "Indexed playlist files: {} added or changed, {} removed",
+                changed, deleted
+            );
+        }
+        Ok(())
+    }
+
+    fn index_playlist(
+        &self,
+        path: &Path,
+        source_path: &str,
+        track_ids: &HashMap<String, i64>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let entries = M3uParser::read_entries(path)?;
+        let ids: Vec<i64> = entries
+            .iter()
+            .filter_map(|entry| track_ids.get(entry.to_string_lossy().as_ref()).copied())
+            .collect();
+        // Named after the file without extension, relative to the music directory, e.g.
+        // `Rock/Party Mix`, which keeps the names of different files apart
+        let name = path
+            .strip_prefix(&self.music_directory)
+            .unwrap_or(path)
+            .with_extension("")
+            .to_string_lossy()
+            .to_string();
+
+        self.db
+            .sync_library_playlist(source_path, &name, &ids, entries.len() - ids.len())
+    }
+
+    pub fn is_audio_file(path: &Path) -> bool {
         if let Some(extension) = path.extension() {
             if let Some(ext_str) = extension.to_str() {
                 matches!(
@@ -297,6 +722,44 @@ impl LibraryScanner {
         }
     }
 
+    /// Reads the tags of a single file and adds it to the library immediately
+    pub fn import_file(&self, path: &Path) -> Result<TrackRecord, Box<dyn Error + Send + Sync>> {
+        let mut track = self.process_file(path)?;
+        track.id = Some(self.db.insert_track(&track)?);
+        info!("Imported track into library: {}", track.file_path);
+        self.tracks_added([track.file_path.as_str()]);
+        if let Some(track_cache) = &self.track_cache {
+            track_cache.track_changed(track.clone());
+            track_cache.set_quarantined(&track.file_path, track.validation_error.is_some());
+        }
+        Ok(track)
+    }
+
+    /// Sets or clears the rating of a track, `None` if there is no track with the id
+    pub fn rate_track(
+        &self,
+        id: i64,
+        rating: Option<u8>,
+    ) -> Result<Option<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        if !self.db.set_track_rating(id, rating)? {
+            return Ok(None);
+        }
+        let track = self.db.get_track(id)?;
+        if let (Some(track_cache), Some(track)) = (&self.track_cache, &track) {
+            track_cache.track_changed(track.clone());
+        }
+        Ok(track)
+    }
+
+    /// Removes a track from the library, the file is kept
+    pub fn delete_track(&self, file_path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.db.delete_track(file_path)?;
+        if let Some(track_cache) = &self.track_cache {
+            track_cache.track_removed(file_path);
+        }
+        Ok(())
+    }
+
     fn process_file(&self, path: &Path) -> Result<TrackRecord, Box<dyn Error + Send + Sync>> {
         let file_path = path.to_string_lossy().to_string();
         let metadata = fs::metadata(path)?;
@@ -309,37 +772,56 @@ impl LibraryScanner {
             .unwrap_or("")
             .to_lowercase();
 
-        let (title, artist, album) = match Tag::new().read_from_path(path) {
-            Ok(tag) => {
-                let title = tag.title().map(|s| s.to_string()).unwrap_or_else(|| {
-                    path.file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("Unknown")
-                        .to_string()
-                });
-                let artist = tag
-                    .artist()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "Unknown Artist".to_string());
-                let album = tag
-                    .album()
-                    .map(|a| a.title.to_string())
-                    .unwrap_or_else(|| "Unknown Album".to_string());
-                (title, artist, album)
-            }
-            Err(e) => {
-                debug!("Failed to read tags from {:?}: {}", path, e);
-                let title = path
-                    .file_stem()
+        let tag = Tag::new()
+            .read_from_path(path)
+            .map_err(|e| debug!("Failed to read tags from {:?}: {}", path, e))
+            .ok();
+        let title = tag
+            .as_ref()
+            .and_then(|tag| tag.title())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| {
+                path.file_stem()
                     .and_then(|s| s.to_str())
                     .unwrap_or("Unknown")
-                    .to_string();
-                (
-                    title,
-                    "Unknown Artist".to_string(),
-                    "Unknown Album".to_string(),
-                )
-            }
+                    .to_string()
+            });
+        let artist = tag
+            .as_ref()
+            .and_then(|tag| tag.artist())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| UNKNOWN_ARTIST.to_string());
+        let album = tag
+            .as_ref()
+            .and_then(|tag| tag.album())
+            .map(|a| a.title.to_string())
+            .unwrap_or_else(|| "Unknown Album".to_string());
+        let duration_seconds = tag
+            .as_ref()
+            .and_then(|tag| tag.duration())
+            .filter(|d| *d > 0.0)
+            .map(|d| d.round() as i64);
+        let genre = tag
+            .as_ref()
+            .and_then(|tag| tag.genre())
+            .map(|g| g.to_string());
+        let year = tag.as_ref().and_then(|tag| tag.year());
+        let artwork_path = tag
+            .as_ref()
+            .and_then(|tag| tag.album_cover())
+            .and_then(|cover| self.cache_artwork(path, cover.data));
+        let content_hash = content_hash(path)?;
+        let audio_fingerprint = self.audio_fingerprint(path);
+        let validation_error = self
+            .validator
+            .as_ref()
+            .and_then(|validator| validator.validate(path));
+        if let Some(error) = &validation_error {
+            warn!("Quarantined broken file {:?}: {}", path, error);
+        }
+        let tempo = match validation_error {
+            Some(_) => TrackTempo::default(),
+            None => self.track_tempo(path, None),
         };
 
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
@@ -350,15 +832,189 @@ impl LibraryScanner {
             title,
             artist,
             album,
-            duration_seconds: None,
+            duration_seconds,
             file_size,
             last_modified,
             file_extension: extension,
             created_at: now,
             updated_at: now,
+            genre,
+            year,
+            artwork_path,
+            rating: None,
+            content_hash: Some(content_hash),
+            audio_fingerprint,
+            validation_error,
+            loudness_lufs: None,
+            bpm: tempo.bpm,
+            energy: tempo.energy,
+            start_seconds: None,
+            end_seconds: None,
         })
     }
 
+    /// Reads the tracks of a single-file album, one library track per audio track of its cue
+    /// sheet in the order of the sheet. The sheet names the tracks, the tags of the album file
+    /// fill in what it leaves out.
+    fn process_album(
+        &self,
+        album: &CueAlbum,
+    ) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let path = album.audio_file.as_path();
+        let file_size = fs::metadata(path)?.len() as i64;
+        let last_modified = self.album_mtime(album)?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let tag = Tag::new()
+            .read_from_path(path)
+            .map_err(|e| debug!("Failed to read tags from {:?}: {}", path, e))
+            .ok();
+        let sheet = &album.sheet;
+        let album_artist = sheet
+            .performer
+            .clone()
+            .or_else(|| tag.as_ref().and_then(|tag| tag.artist()).map(String::from))
+            .unwrap_or_else(|| UNKNOWN_ARTIST.to_string());
+        let album_title = sheet
+            .title
+            .clone()
+            .or_else(|| {
+                tag.as_ref()
+                    .and_then(|tag| tag.album())
+                    .map(|a| a.title.to_string())
+            })
+            .unwrap_or_else(|| "Unknown Album".to_string());
+        let genre = sheet
+            .genre
+            .clone()
+            .or_else(|| tag.as_ref().and_then(|tag| tag.genre()).map(String::from));
+        let year = sheet
+            .year
+            .or_else(|| tag.as_ref().and_then(|tag| tag.year()));
+        let file_duration = tag
+            .as_ref()
+            .and_then(|tag| tag.duration())
+            .filter(|d| *d > 0.0);
+        let artwork_path = tag
+            .as_ref()
+            .and_then(|tag| tag.album_cover())
+            .and_then(|cover| self.cache_artwork(path, cover.data));
+        let validation_error = self
+            .validator
+            .as_ref()
+            .and_then(|validator| validator.validate(path));
+        if let Some(error) = &validation_error {
+            warn!("Quarantined broken file {:?}: {}", path, error);
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let tracks = sheet
+            .tracks
+            .iter()
+            .zip(sheet.track_ends())
+            .map(|(track, end_seconds)| {
+                let section = TrackSection {
+                    file: path.to_path_buf(),
+                    start_seconds: track.start_seconds,
+                    end_seconds,
+                };
+                let tempo = match validation_error {
+                    Some(_) => TrackTempo::default(),
+                    None => self.track_tempo(path, Some(&section)),
+                };
+                let duration_seconds = end_seconds
+                    .or(file_duration)
+                    .map(|end| (end - track.start_seconds).round() as i64)
+                    .filter(|d| *d > 0);
+
+                TrackRecord {
+                    id: None,
+                    file_path: cue_sheet::track_path(path, track.number)
+                        .to_string_lossy()
+                        .to_string(),
+                    title: track
+                        .title
+                        .clone()
+                        .unwrap_or_else(|| format!("Track {:02}", track.number)),
+                    artist: track
+                        .performer
+                        .clone()
+                        .unwrap_or_else(|| album_artist.clone()),
+                    album: album_title.clone(),
+                    duration_seconds,
+                    file_size,
+                    last_modified,
+                    file_extension: extension.clone(),
+                    created_at: now,
+                    updated_at: now,
+                    genre: genre.clone(),
+                    year,
+                    artwork_path: artwork_path.clone(),
+                    rating: None,
+                    // Tracks of the same file are no duplicates of each other
+                    content_hash: None,
+                    audio_fingerprint: None,
+                    validation_error: validation_error.clone(),
+                    loudness_lufs: None,
+                    bpm: tempo.bpm,
+                    energy: tempo.energy,
+                    start_seconds: Some(section.start_seconds),
+                    end_seconds,
+                }
+            })
+            .collect();
+        Ok(tracks)
+    }
+
+    /// Modification time of the tracks of a single-file album, they change with the album file
+    /// and with the cue sheet
+    fn album_mtime(&self, album: &CueAlbum) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .get_file_mtime(&album.audio_file)?
+            .max(self.get_file_mtime(&album.sheet_path)?))
+    }
+
+    /// Path of the thumbnail of an embedded cover, `None` without an artwork cache
+    fn cache_artwork(&self, path: &Path, image: &[u8]) -> Option<String> {
+        match self.artwork_cache.as_ref()?.thumbnail(image) {
+            Ok(thumbnail) => Some(thumbnail.to_string_lossy().to_string()),
+            Err(e) => {
+                warn!("Failed to cache the cover art of {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Hash of the decoded audio, `None` without a fingerprinter or if decoding failed
+    fn audio_fingerprint(&self, path: &Path) -> Option<String> {
+        match self.fingerprinter.as_ref()?.fingerprint(path) {
+            Ok(fingerprint) => Some(fingerprint),
+            Err(e) => {
+                warn!("Failed to fingerprint the audio of {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Tempo and energy of the track, unknown without a tempo analyzer or if decoding failed
+    fn track_tempo(&self, path: &Path, section: Option<&TrackSection>) -> TrackTempo {
+        let Some(analyzer) = self.tempo_analyzer.as_ref() else {
+            return TrackTempo::default();
+        };
+        match analyzer.analyze(path, section) {
+            Ok(tempo) => tempo,
+            Err(e) => {
+                warn!("Failed to analyze the tempo of {:?}: {}", path, e);
+                TrackTempo::default()
+            }
+        }
+    }
+
     fn get_file_mtime(&self, path: &Path) -> Result<i64, Box<dyn Error + Send + Sync>> {
         let metadata = fs::metadata(path)?;
         let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
@@ -366,6 +1022,25 @@ impl LibraryScanner {
     }
 }
 
+/// Modification time of a directory in nanoseconds, it changes when entries are added,
+/// removed or renamed but not when a file in it is rewritten
+fn directory_mtime(dir: &Path) -> Result<i64, Box<dyn Error + Send + Sync>> {
+    let modified = fs::metadata(dir)?.modified()?.duration_since(UNIX_EPOCH)?;
+    Ok(modified.as_nanos() as i64)
+}
+
+/// Subdirectories of each stored directory
+fn subdirectories(stored: &HashMap<String, i64>) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut children: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for path in stored.keys() {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent() {
+            children.entry(parent.to_path_buf()).or_default().push(path);
+        }
+    }
+    children
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -508,6 +1183,137 @@ mod tests {
         assert_eq!(result.deleted, 0);
     }
 
+    #[test]
+    fn given_unchanged_directory_when_incremental_scan_then_skips_its_files_until_reconciled() {
+        let (db, _temp_db) = create_test_db();
+        let temp_dir = TempDir::new().unwrap();
+        let (rock, jazz) = (temp_dir.path().join("rock"), temp_dir.path().join("jazz"));
+        fs::create_dir(&rock).unwrap();
+        fs::create_dir(&jazz).unwrap();
+        let rewritten = create_test_audio_file(&rock, "song1.mp3");
+        create_test_audio_file(&jazz, "song2.mp3");
+
+        let scanner = LibraryScanner::new(temp_dir.path().to_path_buf(), db.clone())
+            .with_unchanged_directories_skipped(Duration::from_secs(3600));
+        scanner.full_scan().unwrap();
+
+        // Rewriting a file keeps the modification time of its directory
+        File::options()
+            .write(true)
+            .open(&rewritten)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        create_test_audio_file(&jazz, "song3.mp3");
+
+        let result = scanner.incremental_scan().unwrap();
+        assert_eq!(result.added, 1);
+        assert_eq!(result.updated, 0);
+        assert_eq!(result.deleted, 0);
+        assert_eq!(result.unchanged, 2);
+
+        db.set_metadata(LAST_RECONCILIATION, "0").unwrap();
+        let result = scanner.incremental_scan().unwrap();
+        assert_eq!(result.updated, 1);
+        assert_eq!(result.unchanged, 2);
+    }
+
+    #[test]
+    fn given_album_with_cue_sheet_when_scanning_then_indexes_each_of_its_tracks() {
+        let (db, _temp_db) = create_test_db();
+        let temp_dir = TempDir::new().unwrap();
+        let album = create_test_audio_file(temp_dir.path(), "Discovery.flac");
+        create_test_audio_file(temp_dir.path(), "song1.mp3");
+        let sheet = temp_dir.path().join("Discovery.cue");
+        fs::write(
+            &sheet,
+            "PERFORMER \"Daft Punk\"\nTITLE \"Discovery\"\nFILE \"Discovery.flac\" WAVE\n\
+             TRACK 01 AUDIO\nTITLE \"One More Time\"\nINDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\nTITLE \"Aerodynamic\"\nINDEX 01 05:20:00\n",
+        )
+        .unwrap();
+
+        let scanner = LibraryScanner::new(temp_dir.path().to_path_buf(), db.clone());
+        let result = scanner.full_scan().unwrap();
+        assert_eq!(result.added, 3);
+        assert!(result.errors.is_empty());
+
+        let first = cue_sheet::track_path(&album, 1);
+        let track = db
+            .get_track_by_path(&first.to_string_lossy())
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.title, "One More Time");
+        assert_eq!(track.artist, "Daft Punk");
+        assert_eq!(track.album, "Discovery");
+        assert_eq!(track.duration_seconds, Some(320));
+        assert_eq!(
+            (track.start_seconds, track.end_seconds),
+            (Some(0.0), Some(320.0))
+        );
+        let last = cue_sheet::track_path(&album, 2);
+        let track = db
+            .get_track_by_path(&last.to_string_lossy())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (track.start_seconds, track.end_seconds),
+            (Some(320.0), None)
+        );
+        assert!(db
+            .get_track_by_path(&album.to_string_lossy())
+            .unwrap()
+            .is_none());
+
+        let result = scanner.incremental_scan().unwrap();
+        assert_eq!((result.added, result.unchanged), (0, 3));
+
+        // Without its sheet the album is a single track again
+        fs::remove_file(&sheet).unwrap();
+        let result = scanner.incremental_scan().unwrap();
+        assert_eq!((result.added, result.deleted), (1, 2));
+    }
+
+    #[test]
+    fn given_playlist_files_when_scanning_then_indexes_them_as_playlists_of_library_tracks() {
+        let (db, _temp_db) = create_test_db();
+        let temp_dir = TempDir::new().unwrap();
+        let (rock, jazz) = (temp_dir.path().join("rock"), temp_dir.path().join("jazz"));
+        fs::create_dir(&rock).unwrap();
+        fs::create_dir(&jazz).unwrap();
+        create_test_audio_file(&rock, "song1.mp3");
+        create_test_audio_file(temp_dir.path(), "song2.mp3");
+        let playlist = rock.join("Party Mix.m3u");
+        fs::write(&playlist, "#EXTM3U\nsong1.mp3\n../song2.mp3\nmissing.mp3\n").unwrap();
+
+        let scanner = LibraryScanner::new(temp_dir.path().to_path_buf(), db.clone())
+            .with_unchanged_directories_skipped(Duration::from_secs(3600));
+        let result = scanner.full_scan().unwrap();
+        assert_eq!(result.added, 2);
+
+        let indexed = db.get_playlist_by_name("rock/Party Mix").unwrap().unwrap();
+        let titles: Vec<&str> = indexed.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["song1", "song2"]);
+        assert_eq!(indexed.unresolved_entries, 1);
+        assert_eq!(
+            indexed.source_path.as_deref(),
+            Some(playlist.to_str().unwrap())
+        );
+
+        // Playlist files of unchanged directories are read again, new tracks resolve
+        create_test_audio_file(&jazz, "song3.mp3");
+        fs::write(&playlist, "../jazz/song3.mp3\nsong1.mp3\n").unwrap();
+        scanner.incremental_scan().unwrap();
+        let indexed = db.get_playlist_by_name("rock/Party Mix").unwrap().unwrap();
+        let titles: Vec<&str> = indexed.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["song3", "song1"]);
+        assert_eq!(indexed.unresolved_entries, 0);
+
+        fs::remove_file(&playlist).unwrap();
+        scanner.incremental_scan().unwrap();
+        assert!(db.get_playlists().unwrap().is_empty());
+    }
+
     #[test]
     fn given_deleted_files_when_incremental_scan_then_files_removed_from_database() {
         let (db, _temp_db) = create_test_db();
@@ -570,4 +1376,52 @@ mod tests {
 
         assert_eq!(result.added, 8);
     }
+
+    #[test]
+    fn given_new_file_when_imported_then_track_available_immediately() {
+        let (db, _temp_db) = create_test_db();
+        let temp_dir = TempDir::new().unwrap();
+        let scanner = LibraryScanner::new(temp_dir.path().to_path_buf(), db.clone());
+        let file_path = create_test_audio_file(temp_dir.path(), "uploaded.mp3");
+
+        let track = scanner.import_file(&file_path).unwrap();
+
+        assert!(track.id.is_some());
+        assert_eq!(track.title, "uploaded");
+        assert_eq!(db.track_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn given_validation_when_scanning_broken_file_then_quarantines_it() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (db, _temp_db) = create_test_db();
+        let temp_dir = TempDir::new().unwrap();
+        let music = temp_dir.path().join("music");
+        fs::create_dir(&music).unwrap();
+        create_test_audio_file(&music, "fine.mp3");
+        create_test_audio_file(&music, "broken.mp3");
+        // Stands in for ffmpeg and fails to decode files named broken
+        let ffmpeg = temp_dir.path().join("ffmpeg");
+        fs::write(
+            &ffmpeg,
+            "#!/bin/sh\ncase \"$*\" in *broken*) echo 'Invalid data' >&2; exit 1;; esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&ffmpeg, fs::Permissions::from_mode(0o755)).unwrap();
+        let scanner = LibraryScanner::new(music, db.clone()).with_validator(FileValidator::new(
+            Some(ffmpeg.to_string_lossy().to_string()),
+        ));
+
+        scanner.full_scan().unwrap();
+
+        let broken = db.get_broken_tracks().unwrap();
+        assert_eq!(broken.len(), 1);
+        assert!(broken[0].file_path.ends_with("broken.mp3"));
+        assert_eq!(broken[0].error, "Cannot decode the file: Invalid data");
+        let playable = db.get_playable_tracks().unwrap();
+        assert_eq!(playable.len(), 1);
+        assert!(playable[0].file_path.ends_with("fine.mp3"));
+        assert!(db.get_problem_tracks().unwrap()[0].quarantined);
+    }
 }
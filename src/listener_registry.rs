@@ -0,0 +1,334 @@
+use crate::access_log::{AccessLog, AccessLogEntry};
+use crate::notifier::{Notifier, WebhookEvent};
+use crate::program_stats::ProgramStats;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Name of the cookie carrying the session token of a stream connection
+pub const SESSION_COOKIE: &str = "funkstrom_session";
+
+/// Request details of a new stream connection
+pub struct ListenerInfo {
+    pub mount: String,
+    pub bitrate: u32,
+    pub path: String,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+}
+
+struct ListenerEntry {
+    info: ListenerInfo,
+    connected_at: chrono::DateTime<chrono::Local>,
+    started: Instant,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+/// Statistics of a single connected listener
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListenerSession {
+    #[schema(example = "3f2a9c0d5e7b4a1c8d6e0f1a2b3c4d5e")]
+    pub session_id: String,
+    /// Name of the stream the listener is connected to
+    #[schema(example = "high")]
+    pub mount: String,
+    /// Stream bitrate in kbps
+    #[schema(example = 320)]
+    pub bitrate: u32,
+    pub connected_at: String,
+    pub connected_seconds: u64,
+    pub bytes_received: u64,
+}
+
+/// Details of a connected listener, as listed per stream
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListenerDetail {
+    /// Client IP address, or a hash of it when anonymization is enabled
+    #[schema(example = "192.168.1.10")]
+    pub client: String,
+    pub user_agent: Option<String>,
+    pub connected_at: String,
+    pub connected_seconds: u64,
+    pub bytes_sent: u64,
+}
+
+/// Keeps track of all connected stream listeners
+#[derive(Clone, Default)]
+pub struct ListenerRegistry {
+    listeners: Arc<Mutex<HashMap<String, ListenerEntry>>>,
+    access_log: Option<AccessLog>,
+    anonymizer: Option<RandomState>,
+    /// Most listeners connected at once since the server started
+    peak: Arc<AtomicUsize>,
+    notifier: Option<Notifier>,
+    program_stats: Option<ProgramStats>,
+}
+
+impl ListenerRegistry {
+    /// Finished sessions are written to the access log, if one is given.
+    /// With `anonymize_ips` listener listings only contain a hash of the client IP.
+    pub fn new(access_log: Option<AccessLog>, anonymize_ips: bool) -> Self {
+        Self {
+            listeners: Arc::default(),
+            access_log,
+            anonymizer: anonymize_ips.then(RandomState::new),
+            peak: Arc::default(),
+            notifier: None,
+            program_stats: None,
+        }
+    }
+
+    /// Sends a `listener_peak` webhook event whenever a new listener peak is reached
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Counts listeners joining and leaving for the audience of the running program
+    pub fn with_program_stats(mut self, program_stats: ProgramStats) -> Self {
+        self.program_stats = Some(program_stats);
+        self
+    }
+
+    /// Registers a new listener; the session ends when the returned handle is dropped
+    pub fn connect(&self, info: ListenerInfo) -> ListenerHandle {
+        let session_id = session_token();
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+
+        let listeners = {
+            let mut listeners = self.listeners.lock().unwrap();
+            listeners.insert(
+                session_id.clone(),
+                ListenerEntry {
+                    info,
+                    connected_at: chrono::Local::now(),
+                    started: Instant::now(),
+                    bytes_sent: Arc::clone(&bytes_sent),
+                },
+            );
+            if let Some(program_stats) = &self.program_stats {
+                program_stats.listener_joined(listeners.len());
+            }
+            listeners.len()
+        };
+
+        if self.peak.fetch_max(listeners, Ordering::Relaxed) < listeners {
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(WebhookEvent::ListenerPeak { listeners });
+            }
+        }
+
+        ListenerHandle {
+            registry: self.clone(),
+            session_id,
+            bytes_sent,
+        }
+    }
+
+    pub fn session(&self, session_id: &str) -> Option<ListenerSession> {
+        self.listeners
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|entry| Self::to_session(session_id, entry))
+    }
+
+    /// Lists the listeners of a stream, longest connected first
+    pub fn listeners_of(&self, mount: &str) -> Vec<ListenerDetail> {
+        let listeners = self.listeners.lock().unwrap();
+        let mut entries: Vec<&ListenerEntry> = listeners
+            .values()
+            .filter(|entry| entry.info.mount == mount)
+            .collect();
+        entries.sort_by_key(|entry| entry.started);
+
+        entries
+            .into_iter()
+            .map(|entry| ListenerDetail {
+                client: self.client_id(entry.info.remote_addr.as_deref()),
+                user_agent: entry.info.user_agent.clone(),
+                connected_at: entry.connected_at.to_rfc3339(),
+                connected_seconds: entry.started.elapsed().as_secs(),
+                bytes_sent: entry.bytes_sent.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    fn client_id(&self, remote_addr: Option<&str>) -> String {
+        let remote_addr = remote_addr.unwrap_or("-");
+        match &self.anonymizer {
+            // Keyed per process, so hashes are stable while running but not reversible via lookup tables
+            Some(state) => format!("{:016x}", state.hash_one(remote_addr)),
+            None => remote_addr.to_string(),
+        }
+    }
+
+    fn to_session(session_id: &str, entry: &ListenerEntry) -> ListenerSession {
+        ListenerSession {
+            session_id: session_id.to_string(),
+            mount: entry.info.mount.clone(),
+            bitrate: entry.info.bitrate,
+            connected_at: entry.connected_at.to_rfc3339(),
+            connected_seconds: entry.started.elapsed().as_secs(),
+            bytes_received: entry.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+
+    fn disconnect(&self, session_id: &str) {
+        let entry = {
+            let mut listeners = self.listeners.lock().unwrap();
+            let entry = listeners.remove(session_id);
+            if let (Some(_), Some(program_stats)) = (&entry, &self.program_stats) {
+                program_stats.listener_left(listeners.len());
+            }
+            entry
+        };
+
+        if let (Some(entry), Some(access_log)) = (entry, &self.access_log) {
+            access_log.write(&AccessLogEntry {
+                remote_addr: entry.info.remote_addr.unwrap_or_else(|| "-".to_string()),
+                connected_at: entry.connected_at,
+                method: "GET".to_string(),
+                path: entry.info.path,
+                status: 200,
+                bytes: entry.bytes_sent.load(Ordering::Relaxed),
+                referer: entry.info.referer,
+                user_agent: entry.info.user_agent,
+                duration_seconds: entry.started.elapsed().as_secs(),
+            });
+        }
+    }
+}
+
+/// Handle of a connected listener, used by the stream task to account sent data
+pub struct ListenerHandle {
+    registry: ListenerRegistry,
+    session_id: String,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl ListenerHandle {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ListenerHandle {
+    fn drop(&mut self) {
+        self.registry.disconnect(&self.session_id);
+    }
+}
+
+/// Session token of a stream connection, 32 random bytes of the system CSPRNG, hex encoded
+fn session_token() -> String {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("System random number generator should be available");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_log::AccessLogFormat;
+    use tempfile::TempDir;
+
+    fn listener_info(mount: &str) -> ListenerInfo {
+        ListenerInfo {
+            mount: mount.to_string(),
+            bitrate: 320,
+            path: format!("/{}", mount),
+            remote_addr: Some("192.168.1.10".to_string()),
+            user_agent: Some("mpv".to_string()),
+            referer: None,
+        }
+    }
+
+    #[test]
+    fn given_connected_listener_when_bytes_sent_then_session_reports_them() {
+        let registry = ListenerRegistry::new(None, false);
+        let handle = registry.connect(listener_info("high"));
+
+        handle.add_bytes(1000);
+        handle.add_bytes(500);
+
+        let session = registry.session(handle.session_id()).unwrap();
+        assert_eq!(session.mount, "high");
+        assert_eq!(session.bitrate, 320);
+        assert_eq!(session.bytes_received, 1500);
+    }
+
+    #[test]
+    fn given_listener_handle_when_dropped_then_session_ends() {
+        let registry = ListenerRegistry::new(None, false);
+        let handle = registry.connect(listener_info("high"));
+        let session_id = handle.session_id().to_string();
+
+        drop(handle);
+
+        assert!(registry.session(&session_id).is_none());
+    }
+
+    #[test]
+    fn given_two_listeners_when_connected_then_session_ids_differ() {
+        let registry = ListenerRegistry::new(None, false);
+        let first = registry.connect(listener_info("high"));
+        let second = registry.connect(listener_info("high"));
+
+        assert_ne!(first.session_id(), second.session_id());
+        assert_eq!(first.session_id().len(), 64);
+    }
+
+    #[test]
+    fn given_access_log_when_session_ends_then_writes_entry() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("access.log");
+        let access_log = AccessLog::open(&path, AccessLogFormat::Combined).unwrap();
+        let registry = ListenerRegistry::new(Some(access_log), false);
+
+        let handle = registry.connect(listener_info("high"));
+        handle.add_bytes(4096);
+        drop(handle);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("192.168.1.10 - - ["));
+        assert!(content.contains("\"GET /high HTTP/1.1\" 200 4096 \"-\" \"mpv\""));
+    }
+
+    #[test]
+    fn given_listeners_on_two_streams_when_listed_then_returns_only_stream_listeners() {
+        let registry = ListenerRegistry::new(None, false);
+        let _high = registry.connect(listener_info("high"));
+        let _low = registry.connect(listener_info("low"));
+
+        let listeners = registry.listeners_of("high");
+
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].client, "192.168.1.10");
+        assert_eq!(listeners[0].user_agent, Some("mpv".to_string()));
+    }
+
+    #[test]
+    fn given_anonymization_when_listed_then_client_ip_is_hashed_consistently() {
+        let registry = ListenerRegistry::new(None, true);
+        let _first = registry.connect(listener_info("high"));
+        let _second = registry.connect(listener_info("high"));
+
+        let listeners = registry.listeners_of("high");
+
+        assert_ne!(listeners[0].client, "192.168.1.10");
+        assert_eq!(listeners[0].client, listeners[1].client);
+    }
+}
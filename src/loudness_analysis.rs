@@ -0,0 +1,254 @@
+use crate::cue_sheet::{self, TrackSection};
+use crate::library_db::LibraryDatabase;
+use crate::track_cache::TrackCache;
+use log::{debug, info, warn};
+use std::error::Error;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Tracks measured before the analysis looks for new ones
+const BATCH_SIZE: usize = 20;
+
+/// Pause after all tracks are measured, new tracks of scans and uploads are picked up after it
+const IDLE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Largest boost applied to quiet tracks, louder boosts would clip without a limiter
+const MAX_BOOST_DB: f64 = 12.0;
+
+/// Largest cut applied to loud tracks
+const MAX_CUT_DB: f64 = 30.0;
+
+/// Loudness ffmpeg reports for silence, such tracks are not corrected
+const SILENCE_LUFS: f64 = -70.0;
+
+/// Measures the integrated loudness of tracks with ffmpeg's EBU R128 filter
+#[derive(Clone)]
+pub struct LoudnessAnalyzer {
+    ffmpeg_path: String,
+}
+
+impl LoudnessAnalyzer {
+    pub fn new(ffmpeg_path: Option<String>) -> Self {
+        Self {
+            ffmpeg_path: ffmpeg_path.unwrap_or_else(|| "ffmpeg".to_string()),
+        }
+    }
+
+    /// Integrated loudness of the first audio stream in LUFS, decodes the whole file or only
+    /// the `section` of a track of a single-file album
+    pub fn measure(
+        &self,
+        path: &Path,
+        section: Option<&TrackSection>,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-hide_banner", "-nostats"])
+            .args(section.map(TrackSection::input_args).unwrap_or_default())
+            .arg("-i")
+            .arg(path)
+            .args(["-map", "0:a:0", "-af", "ebur128", "-f", "null", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+            .map_err(|e| format!("Cannot run {}: {}", self.ffmpeg_path, e))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            let message = stderr.lines().map(str::trim).rfind(|l| !l.is_empty());
+            return Err(format!(
+                "ffmpeg failed to measure the loudness with {}: {}",
+                output.status,
+                message.unwrap_or_default()
+            )
+            .into());
+        }
+
+        parse_integrated_loudness(&stderr)
+            .ok_or_else(|| "No integrated loudness in the ffmpeg output".into())
+    }
+}
+
+/// Reads `I: -14.2 LUFS` of the summary printed by the ebur128 filter when it ends
+fn parse_integrated_loudness(output: &str) -> Option<f64> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("I:"))
+        .filter_map(|value| value.trim().strip_suffix("LUFS"))
+        .filter_map(|value| value.trim().parse().ok())
+        .next_back()
+}
+
+/// Gain in dB bringing a track measured at `loudness_lufs` to `target_lufs`, `None` for
+/// silent tracks. Boosts are limited so quiet recordings are not driven into clipping.
+pub fn normalization_gain(loudness_lufs: f64, target_lufs: f64) -> Option<f64> {
+    if loudness_lufs <= SILENCE_LUFS {
+        return None;
+    }
+    Some((target_lufs - loudness_lufs).clamp(-MAX_CUT_DB, MAX_BOOST_DB))
+}
+
+/// Background job measuring the loudness of library tracks once, so playout corrects their
+/// level with a single gain instead of analyzing the audio while it plays
+#[derive(Clone)]
+pub struct LoudnessAnalysis {
+    db: LibraryDatabase,
+    track_cache: TrackCache,
+    analyzer: LoudnessAnalyzer,
+}
+
+impl LoudnessAnalysis {
+    pub fn new(db: LibraryDatabase, track_cache: TrackCache, analyzer: LoudnessAnalyzer) -> Self {
+        Self {
+            db,
+            track_cache,
+            analyzer,
+        }
+    }
+
+    pub fn start(&self) {
+        let analysis = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let batch = analysis.clone();
+                match tokio::task::spawn_blocking(move || batch.analyze_batch()).await {
+                    Ok(Ok(0)) => tokio::time::sleep(IDLE_INTERVAL).await,
+                    Ok(Ok(measured)) => debug!("Measured the loudness of {} tracks", measured),
+                    Ok(Err(e)) => {
+                        warn!("Loudness analysis failed: {}", e);
+                        tokio::time::sleep(IDLE_INTERVAL).await;
+                    }
+                    Err(e) => {
+                        warn!("Loudness analysis task failed: {}", e);
+                        tokio::time::sleep(IDLE_INTERVAL).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Measures the next tracks without loudness, returns how many were analyzed
+    pub fn analyze_batch(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let tracks = self.db.get_tracks_without_loudness(BATCH_SIZE)?;
+        for track in &tracks {
+            let Some(id) = track.id else {
+                continue;
+            };
+            let path = cue_sheet::audio_file(Path::new(&track.file_path));
+            let loudness = match self
+                .analyzer
+                .measure(&path, TrackSection::of(track).as_ref())
+            {
+                Ok(loudness) => {
+                    debug!("Loudness of {}: {:.1} LUFS", track.file_path, loudness);
+                    Some(loudness)
+                }
+                Err(e) => {
+                    warn!("Cannot measure the loudness of {}: {}", track.file_path, e);
+                    None
+                }
+            };
+            self.db.set_track_loudness(id, loudness)?;
+            self.track_cache.set_loudness(&track.file_path, loudness);
+        }
+
+        if !tracks.is_empty() && tracks.len() < BATCH_SIZE {
+            info!("Loudness analysis caught up with the library");
+        }
+        Ok(tracks.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library_db::TrackRecord;
+    use tempfile::{NamedTempFile, TempDir};
+
+    const EBUR128_SUMMARY: &str = "[Parsed_ebur128_0 @ 0x1] Summary:
+
+  Integrated loudness:
+    I:         -11.3 LUFS
+    Threshold: -21.5 LUFS
+
+  Loudness range:
+    LRA:         6.1 LU
+";
+
+    fn track(file_path: &str) -> TrackRecord {
+        TrackRecord {
+            id: None,
+            file_path: file_path.to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration_seconds: Some(180),
+            file_size: 1000,
+            last_modified: 1234567890,
+            file_extension: "mp3".to_string(),
+            created_at: 1234567890,
+            updated_at: 1234567890,
+            genre: None,
+            year: None,
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+        }
+    }
+
+    #[test]
+    fn given_measured_loudness_when_normalizing_then_gain_reaches_the_target() {
+        assert_eq!(parse_integrated_loudness(EBUR128_SUMMARY), Some(-11.3));
+        assert_eq!(parse_integrated_loudness("Invalid data"), None);
+
+        assert_eq!(normalization_gain(-11.0, -16.0), Some(-5.0));
+        assert_eq!(normalization_gain(-20.0, -16.0), Some(4.0));
+        assert_eq!(normalization_gain(-40.0, -16.0), Some(MAX_BOOST_DB));
+        assert_eq!(normalization_gain(-70.0, -16.0), None);
+    }
+
+    #[test]
+    fn given_unmeasured_tracks_when_analyzing_then_stores_their_loudness_once() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new().unwrap();
+        // Stands in for ffmpeg, prints the summary of the ebur128 filter and fails for broken files
+        let ffmpeg = directory.path().join("ffmpeg");
+        std::fs::write(
+            &ffmpeg,
+            format!(
+                "#!/bin/sh\ncase \"$*\" in *broken*) echo 'Invalid data' >&2; exit 1;; esac\ncat >&2 <<'EOF'\n{}EOF\n",
+                EBUR128_SUMMARY
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        db.insert_track(&track("/music/a.mp3")).unwrap();
+        db.insert_track(&track("/music/broken.mp3")).unwrap();
+        let track_cache = TrackCache::load(db.clone()).unwrap();
+        let analysis = LoudnessAnalysis::new(
+            db.clone(),
+            track_cache.clone(),
+            LoudnessAnalyzer::new(Some(ffmpeg.to_string_lossy().to_string())),
+        );
+
+        assert_eq!(analysis.analyze_batch().unwrap(), 2);
+        assert_eq!(analysis.analyze_batch().unwrap(), 0);
+        assert_eq!(track_cache.loudness(Path::new("/music/a.mp3")), Some(-11.3));
+        assert_eq!(track_cache.loudness(Path::new("/music/broken.mp3")), None);
+
+        // A changed file is measured again
+        db.update_track(&track("/music/a.mp3")).unwrap();
+        assert_eq!(db.get_tracks_without_loudness(10).unwrap().len(), 1);
+    }
+}
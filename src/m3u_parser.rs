@@ -1,6 +1,6 @@
 use log::{debug, warn};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 pub struct M3uParser;
 
@@ -12,25 +12,9 @@ impl M3uParser {
             return Err(format!("M3U playlist not found: {:?}", playlist_path).into());
         }
 
-        let content = fs::read_to_string(playlist_path)?;
         let mut tracks = Vec::new();
-        let playlist_dir = playlist_path
-            .parent()
-            .ok_or("Failed to get playlist directory")?;
-
-        for line in content.lines() {
-            let line = line.trim();
-
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-
-            let track_path = if Path::new(line).is_absolute() {
-                PathBuf::from(line)
-            } else {
-                playlist_dir.join(line)
-            };
 
+        for track_path in Self::read_entries(playlist_path)? {
             if track_path.exists() {
                 debug!("Found track in M3U: {:?}", track_path);
                 tracks.push(track_path);
@@ -48,6 +32,41 @@ impl M3uParser {
         Ok(tracks)
     }
 
+    /// Paths of the entries of an M3U or PLS playlist in playout order, whether the files exist
+    /// or not. Relative paths are resolved from the directory of the playlist, stream URLs are
+    /// left out.
+    pub fn read_entries(
+        playlist_path: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        // Plain .m3u files are often Latin-1, their paths are still matched where possible
+        let content = String::from_utf8_lossy(&fs::read(playlist_path)?).into_owned();
+        let playlist_dir = playlist_path
+            .parent()
+            .ok_or("Failed to get playlist directory")?;
+        let is_pls = playlist_path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pls"));
+        let locations = if is_pls {
+            pls_locations(&content)
+        } else {
+            m3u_locations(&content)
+        };
+
+        Ok(locations
+            .into_iter()
+            .filter(|location| !location.contains("://"))
+            .map(|location| normalize(&playlist_dir.join(location)))
+            .collect())
+    }
+
+    pub fn is_playlist_file(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                matches!(extension.to_lowercase().as_str(), "m3u" | "m3u8" | "pls")
+            })
+    }
+
     pub fn validate_playlist(
         playlist_path: &Path,
     ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
@@ -56,6 +75,46 @@ impl M3uParser {
     }
 }
 
+/// Non-empty lines of an M3U playlist that are not comments or `#EXT` tags
+fn m3u_locations(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// `FileN=` entries of a PLS playlist ordered by their number
+fn pls_locations(content: &str) -> Vec<&str> {
+    let mut entries: Vec<(u32, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let number = key.trim().strip_prefix("File")?.parse().ok()?;
+            Some((number, value.trim()))
+        })
+        .filter(|(_, location)| !location.is_empty())
+        .collect();
+    entries.sort_by_key(|(number, _)| *number);
+    entries.into_iter().map(|(_, location)| location).collect()
+}
+
+/// Drops `.` and `..` components without touching the file system, so entries like
+/// `../music/a.mp3` match the paths of library tracks
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -216,4 +275,28 @@ mod tests {
 
         assert_eq!(count, 3);
     }
+
+    #[test]
+    fn given_pls_playlist_when_reading_entries_then_resolves_them_in_order_without_urls() {
+        let temp_dir = TempDir::new().unwrap();
+        let playlist_path = temp_dir.path().join("sets").join("test.PLS");
+        fs::create_dir(temp_dir.path().join("sets")).unwrap();
+        fs::write(
+            &playlist_path,
+            "[playlist]\nFile2=../b.mp3\nTitle2=B\nFile1=a.mp3\nFile3=http://radio.example/stream\nNumberOfEntries=3\nVersion=2\n",
+        )
+        .unwrap();
+
+        let entries = M3uParser::read_entries(&playlist_path).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                temp_dir.path().join("sets").join("a.mp3"),
+                temp_dir.path().join("b.mp3"),
+            ]
+        );
+        assert!(M3uParser::is_playlist_file(&playlist_path));
+        assert!(!M3uParser::is_playlist_file(Path::new("a.mp3")));
+    }
 }
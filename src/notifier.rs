@@ -0,0 +1,639 @@
+//! Webhook notifications.
+//!
+//! Registered webhooks receive a JSON payload for every subscribed station event. The body is
+//! signed with HMAC-SHA256 keyed by the webhook secret and sent as
+//! `X-Funkstrom-Signature: sha256=<hex>`, so receivers can verify it came from this station.
+//! Failed deliveries are retried with exponential backoff. Deliveries that still fail are
+//! persisted in the retry queue of the database and retried for a day, so network outages and
+//! restarts do not lose events.
+
+use crate::http_client::HttpClientFactory;
+use crate::library_db::{LibraryDatabase, PendingDelivery};
+use log::{debug, info, warn};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Location of the registered webhooks
+pub const WEBHOOK_STORE_PATH: &str = "./data/webhooks.json";
+
+pub const SIGNATURE_HEADER: &str = "X-Funkstrom-Signature";
+pub const EVENT_HEADER: &str = "X-Funkstrom-Event";
+
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+
+/// How often the retry queue is checked for due deliveries
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const QUEUE_INITIAL_DELAY: Duration = Duration::from_secs(60);
+const QUEUE_MAX_DELAY: Duration = Duration::from_secs(3600);
+/// Queued deliveries older than this are dropped
+const QUEUE_MAX_AGE: Duration = Duration::from_secs(24 * 3600);
+
+/// Kinds of events a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    TrackChange,
+    ProgramStart,
+    ProgramEnd,
+    ListenerPeak,
+    ScanComplete,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::TrackChange => "track_change",
+            WebhookEventKind::ProgramStart => "program_start",
+            WebhookEventKind::ProgramEnd => "program_end",
+            WebhookEventKind::ListenerPeak => "listener_peak",
+            WebhookEventKind::ScanComplete => "scan_complete",
+        }
+    }
+}
+
+/// Station event delivered to the webhooks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    TrackChange {
+        title: String,
+        artist: String,
+        album: String,
+        duration_seconds: Option<u64>,
+    },
+    ProgramStart {
+        name: String,
+    },
+    ProgramEnd {
+        name: String,
+    },
+    /// More listeners than ever since the server started
+    ListenerPeak {
+        listeners: usize,
+    },
+    ScanComplete {
+        added: usize,
+        updated: usize,
+        deleted: usize,
+        errors: usize,
+    },
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> WebhookEventKind {
+        match self {
+            WebhookEvent::TrackChange { .. } => WebhookEventKind::TrackChange,
+            WebhookEvent::ProgramStart { .. } => WebhookEventKind::ProgramStart,
+            WebhookEvent::ProgramEnd { .. } => WebhookEventKind::ProgramEnd,
+            WebhookEvent::ListenerPeak { .. } => WebhookEventKind::ListenerPeak,
+            WebhookEvent::ScanComplete { .. } => WebhookEventKind::ScanComplete,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    timestamp: String,
+}
+
+/// A registered webhook
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct Webhook {
+    #[schema(example = "3f2a9c0d5e7b4a1c8d6e0f1a2b3c4d5e")]
+    pub id: String,
+    #[schema(example = "https://example.com/hooks/radio")]
+    pub url: String,
+    /// Subscribed events, all events if empty
+    pub events: Vec<WebhookEventKind>,
+    pub created_at: String,
+    /// Key of the payload signature, only returned on registration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    fn subscribes(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Registration request of a webhook
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewWebhook {
+    #[schema(example = "https://example.com/hooks/radio")]
+    pub url: String,
+    /// Events to subscribe to, all events if empty or omitted
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+    /// Key of the payload signature, generated if omitted
+    pub secret: Option<String>,
+}
+
+/// Holds the registered webhooks and delivers events to them
+#[derive(Clone)]
+pub struct Notifier {
+    path: PathBuf,
+    webhooks: Arc<Mutex<Vec<Webhook>>>,
+    client: reqwest::Client,
+    queue: Option<LibraryDatabase>,
+}
+
+impl Notifier {
+    pub fn load(
+        path: &Path,
+        http: &HttpClientFactory,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let webhooks: Vec<Webhook> = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid webhook file {:?}: {}", path, e))?
+        } else {
+            Vec::new()
+        };
+
+        if !webhooks.is_empty() {
+            info!("Loaded {} webhook(s) from {:?}", webhooks.len(), path);
+        }
+
+        let client = http.client(Duration::from_secs(DELIVERY_TIMEOUT_SECONDS))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            webhooks: Arc::new(Mutex::new(webhooks)),
+            client,
+            queue: None,
+        })
+    }
+
+    /// Persists deliveries that fail all attempts in the database for later retries
+    pub fn with_retry_queue(mut self, db: LibraryDatabase) -> Self {
+        self.queue = Some(db);
+        self
+    }
+
+    /// Registers and persists a webhook, returns it including its secret
+    pub fn register(
+        &self,
+        new: NewWebhook,
+    ) -> Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let url = reqwest::Url::parse(&new.url).map_err(|e| format!("Invalid URL: {}", e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err("Webhook URL must use http or https".into());
+        }
+        if new.secret.as_ref().is_some_and(|secret| secret.is_empty()) {
+            return Err("Webhook secret must not be empty".into());
+        }
+
+        let secret = match new.secret {
+            Some(secret) => secret,
+            None => random_secret()?,
+        };
+        let webhook = Webhook {
+            id: random_secret()?,
+            url: new.url,
+            events: new.events,
+            created_at: chrono::Local::now().to_rfc3339(),
+            secret: Some(secret),
+        };
+
+        let mut webhooks = self.webhooks.lock().unwrap();
+        webhooks.push(webhook.clone());
+        self.save(&webhooks)?;
+
+        info!("Registered webhook {} for {}", webhook.id, webhook.url);
+        Ok(webhook)
+    }
+
+    /// Registered webhooks without their secrets
+    pub fn list(&self) -> Vec<Webhook> {
+        self.webhooks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|webhook| Webhook {
+                secret: None,
+                ..webhook.clone()
+            })
+            .collect()
+    }
+
+    /// Removes a webhook, returns it without its secret if it existed
+    pub fn remove(
+        &self,
+        id: &str,
+    ) -> Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut webhooks = self.webhooks.lock().unwrap();
+        let Some(index) = webhooks.iter().position(|webhook| webhook.id == id) else {
+            return Ok(None);
+        };
+
+        let removed = webhooks.remove(index);
+        self.save(&webhooks)?;
+        info!("Removed webhook {} for {}", removed.id, removed.url);
+        Ok(Some(Webhook {
+            secret: None,
+            ..removed
+        }))
+    }
+
+    fn save(&self, webhooks: &[Webhook]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(webhooks)?)?;
+        Ok(())
+    }
+
+    /// Delivers an event to all subscribed webhooks in the background
+    pub fn notify(&self, event: WebhookEvent) {
+        let kind = event.kind();
+        let targets: Vec<Webhook> = self
+            .webhooks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|webhook| webhook.subscribes(kind))
+            .cloned()
+            .collect();
+
+        if targets.is_empty() {
+            return;
+        }
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!("No async runtime, dropping {} webhook event", kind.as_str());
+            return;
+        };
+
+        let payload = WebhookPayload {
+            event: &event,
+            timestamp: chrono::Local::now().to_rfc3339(),
+        };
+        let body = match serde_json::to_string(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize {} webhook event: {}", kind.as_str(), e);
+                return;
+            }
+        };
+
+        for webhook in targets {
+            let client = self.client.clone();
+            let queue = self.queue.clone();
+            let body = body.clone();
+            runtime.spawn(async move { deliver(client, queue, webhook, kind, body).await });
+        }
+    }
+
+    /// Deliveries waiting in the retry queue, ordered by their next attempt
+    pub fn pending_deliveries(
+        &self,
+    ) -> Result<Vec<PendingDelivery>, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.queue {
+            Some(queue) => queue.get_pending_deliveries(None),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Retries the due deliveries of the retry queue in the background
+    pub fn start_retry_queue(&self) {
+        let Some(queue) = self.queue.clone() else {
+            return;
+        };
+
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(QUEUE_POLL_INTERVAL).await;
+                if let Err(e) = notifier.retry_due(&queue).await {
+                    warn!("Failed to process the webhook retry queue: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn retry_due(
+        &self,
+        queue: &LibraryDatabase,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let now = chrono::Utc::now().timestamp();
+
+        for delivery in queue.get_pending_deliveries(Some(now))? {
+            let secret = self
+                .webhooks
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|webhook| webhook.id == delivery.webhook_id)
+                .map(|webhook| webhook.secret.clone().unwrap_or_default());
+            let Some(secret) = secret else {
+                debug!(
+                    "Dropping queued delivery {} of removed webhook {}",
+                    delivery.id, delivery.webhook_id
+                );
+                queue.delete_delivery(delivery.id)?;
+                continue;
+            };
+
+            let result = send(
+                &self.client,
+                &delivery.url,
+                &delivery.event,
+                &delivery.body,
+                &sign(&secret, delivery.body.as_bytes()),
+            )
+            .await;
+
+            match result {
+                Ok(_) => {
+                    info!(
+                        "Delivered queued {} webhook to {}",
+                        delivery.event, delivery.url
+                    );
+                    queue.delete_delivery(delivery.id)?;
+                }
+                Err(e) if now - delivery.created_at >= QUEUE_MAX_AGE.as_secs() as i64 => {
+                    warn!(
+                        "Dropping {} webhook delivery to {}, undelivered since {:?}: {}",
+                        delivery.event, delivery.url, QUEUE_MAX_AGE, e
+                    );
+                    queue.delete_delivery(delivery.id)?;
+                }
+                Err(e) => {
+                    let delay = queue_retry_delay(delivery.attempts + 1);
+                    debug!(
+                        "Queued webhook delivery to {} failed, retrying in {:?}: {}",
+                        delivery.url, delay, e
+                    );
+                    queue.reschedule_delivery(
+                        delivery.id,
+                        now + delay.as_secs() as i64,
+                        &e.to_string(),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn deliver(
+    client: reqwest::Client,
+    queue: Option<LibraryDatabase>,
+    webhook: Webhook,
+    kind: WebhookEventKind,
+    body: String,
+) {
+    let url = &webhook.url;
+    let signature = sign(
+        webhook.secret.as_deref().unwrap_or_default(),
+        body.as_bytes(),
+    );
+
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        match send(&client, url, kind.as_str(), &body, &signature).await {
+            Ok(_) => {
+                debug!("Delivered {} webhook to {}", kind.as_str(), url);
+                return;
+            }
+            Err(e) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                let delay = retry_delay(attempt);
+                debug!(
+                    "Webhook delivery to {} failed (attempt {}), retrying in {:?}: {}",
+                    url, attempt, delay, e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                let Some(queue) = &queue else {
+                    warn!(
+                        "Giving up {} webhook delivery to {} after {} attempts: {}",
+                        kind.as_str(),
+                        url,
+                        attempt,
+                        e
+                    );
+                    return;
+                };
+
+                warn!(
+                    "{} webhook delivery to {} failed {} times, queued for retry: {}",
+                    kind.as_str(),
+                    url,
+                    attempt,
+                    e
+                );
+                let next_attempt_at =
+                    chrono::Utc::now().timestamp() + QUEUE_INITIAL_DELAY.as_secs() as i64;
+                if let Err(queue_error) = queue.enqueue_delivery(
+                    &webhook.id,
+                    url,
+                    kind.as_str(),
+                    &body,
+                    next_attempt_at,
+                    &e.to_string(),
+                ) {
+                    warn!(
+                        "Failed to queue {} webhook delivery: {}",
+                        kind.as_str(),
+                        queue_error
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Posts a signed payload once, non-2xx responses are errors
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    event: &str,
+    body: &str,
+    signature: &str,
+) -> Result<reqwest::Response, reqwest::Error> {
+    client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, event)
+        .header(SIGNATURE_HEADER, signature)
+        .body(body.to_string())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+}
+
+/// Delay before the next attempt after the given failed attempt
+fn retry_delay(attempt: u32) -> Duration {
+    INITIAL_RETRY_DELAY * 2u32.pow(attempt.saturating_sub(1))
+}
+
+/// Delay of a queued delivery after the given failed attempt from the queue
+fn queue_retry_delay(attempt: i64) -> Duration {
+    let exponent = attempt.saturating_sub(1).clamp(0, 16) as u32;
+    (QUEUE_INITIAL_DELAY * 2u32.pow(exponent)).min(QUEUE_MAX_DELAY)
+}
+
+/// 32 random bytes of the system CSPRNG, hex encoded, for webhook ids and secrets
+fn random_secret() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "Failed to generate a random webhook secret")?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Signature header value of a payload
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body);
+    let hex: String = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn new_webhook(url: &str, events: Vec<WebhookEventKind>) -> NewWebhook {
+        NewWebhook {
+            url: url.to_string(),
+            events,
+            secret: None,
+        }
+    }
+
+    #[test]
+    fn given_payload_when_signed_then_matches_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn given_event_when_serialized_then_payload_has_event_name_and_data() {
+        let event = WebhookEvent::ProgramStart {
+            name: "Morning Show".to_string(),
+        };
+        let payload = WebhookPayload {
+            event: &event,
+            timestamp: "2025-01-15T08:00:00+01:00".to_string(),
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&payload).unwrap();
+
+        assert_eq!(json["event"], "program_start");
+        assert_eq!(json["data"]["name"], "Morning Show");
+        assert_eq!(json["timestamp"], "2025-01-15T08:00:00+01:00");
+    }
+
+    #[test]
+    fn given_registered_webhooks_when_reloaded_then_persisted_without_exposing_secrets() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("webhooks.json");
+        let notifier = Notifier::load(&path, &HttpClientFactory::default()).unwrap();
+
+        let registered = notifier
+            .register(new_webhook(
+                "https://example.com/hook",
+                vec![WebhookEventKind::TrackChange],
+            ))
+            .unwrap();
+        let reloaded = Notifier::load(&path, &HttpClientFactory::default()).unwrap();
+
+        assert_eq!(registered.secret.as_ref().unwrap().len(), 64);
+        assert_eq!(registered.id.len(), 64);
+        assert_ne!(registered.secret.as_ref(), Some(&registered.id));
+        let listed = reloaded.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, registered.id);
+        assert!(listed[0].secret.is_none());
+        assert!(listed[0].subscribes(WebhookEventKind::TrackChange));
+        assert!(!listed[0].subscribes(WebhookEventKind::ScanComplete));
+
+        assert!(reloaded.remove(&registered.id).unwrap().is_some());
+        assert!(reloaded.remove(&registered.id).unwrap().is_none());
+        assert!(Notifier::load(&path, &HttpClientFactory::default())
+            .unwrap()
+            .list()
+            .is_empty());
+    }
+
+    #[test]
+    fn given_invalid_url_when_registering_then_rejected() {
+        let dir = TempDir::new().unwrap();
+        let notifier = Notifier::load(
+            &dir.path().join("webhooks.json"),
+            &HttpClientFactory::default(),
+        )
+        .unwrap();
+
+        assert!(notifier
+            .register(new_webhook("ftp://example.com/hook", Vec::new()))
+            .is_err());
+        assert!(notifier
+            .register(new_webhook("not a url", Vec::new()))
+            .is_err());
+        assert!(notifier.list().is_empty());
+    }
+
+    #[test]
+    fn given_failed_attempts_when_retrying_then_delay_doubles() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(2), Duration::from_secs(2));
+        assert_eq!(retry_delay(4), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn given_failed_queued_attempts_when_retrying_then_delay_doubles_up_to_an_hour() {
+        assert_eq!(queue_retry_delay(1), Duration::from_secs(60));
+        assert_eq!(queue_retry_delay(3), Duration::from_secs(240));
+        assert_eq!(queue_retry_delay(10), QUEUE_MAX_DELAY);
+        assert_eq!(queue_retry_delay(1000), QUEUE_MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn given_queued_delivery_of_removed_webhook_when_retrying_then_dropped() {
+        let dir = TempDir::new().unwrap();
+        let db = LibraryDatabase::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let notifier = Notifier::load(
+            &dir.path().join("webhooks.json"),
+            &HttpClientFactory::default(),
+        )
+        .unwrap()
+        .with_retry_queue(db.clone());
+        db.enqueue_delivery(
+            "removed",
+            "https://example.com",
+            "track_change",
+            "{}",
+            0,
+            "",
+        )
+        .unwrap();
+        assert_eq!(notifier.pending_deliveries().unwrap().len(), 1);
+
+        notifier.retry_due(&db).await.unwrap();
+
+        assert!(notifier.pending_deliveries().unwrap().is_empty());
+    }
+}
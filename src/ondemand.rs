@@ -0,0 +1,437 @@
+use crate::audio_metadata::TrackMetadata;
+use crate::audio_processor::{self, FFmpegProcessor};
+use crate::config::{OnDemandConfig, StreamConfig};
+use crate::library_db::LibraryDatabase;
+use crate::library_scanner::LibraryScanner;
+use log::debug;
+use ring::digest;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+/// Directory holding the transcoded on-demand files
+pub const ONDEMAND_CACHE_PATH: &str = "./data/ondemand";
+
+/// URL prefix of the on-demand files
+pub const ONDEMAND_URL_PREFIX: &str = "/ondemand";
+
+/// A file available for on-demand playback
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OnDemandFile {
+    /// Path relative to the on-demand directory
+    #[schema(example = "shows/Night Shift 2025-01-10.flac")]
+    pub path: String,
+    /// URL of the transcoded, seekable audio
+    #[schema(example = "/ondemand/shows/Night%20Shift%202025-01-10.flac")]
+    pub url: String,
+    #[schema(example = "Night Shift")]
+    pub title: String,
+    #[schema(example = "DJ Example")]
+    pub artist: String,
+    #[schema(example = "Unknown Album")]
+    pub album: String,
+    #[schema(example = 7200)]
+    pub duration_seconds: Option<u64>,
+}
+
+/// Part of a file requested with a `Range` header
+#[derive(Debug, PartialEq)]
+pub enum ByteRange {
+    /// No or an unsupported range, the whole file is sent
+    Full,
+    /// First and last byte, both inclusive
+    Partial(u64, u64),
+    /// The range starts beyond the end of the file
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parses a single `bytes=` range of the `Range` header for a file of `len` bytes
+    pub fn parse(header: Option<&str>, len: u64) -> Self {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return Self::Full;
+        };
+        // Multiple ranges may be answered with the whole file
+        let Some((start, end)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+            return Self::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        let (first, last) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(first), Ok(last)) if first <= last => (first, last),
+            (Ok(first), Err(_)) if end.is_empty() => (first, u64::MAX),
+            (Err(_), Ok(0)) if start.is_empty() => return Self::Unsatisfiable,
+            (Err(_), Ok(suffix)) if start.is_empty() => (len.saturating_sub(suffix), u64::MAX),
+            _ => return Self::Full,
+        };
+
+        if first >= len {
+            return Self::Unsatisfiable;
+        }
+        Self::Partial(first, last.min(len - 1))
+    }
+}
+
+/// Serves the audio files of a directory transcoded with the settings of the `[ondemand]`
+/// section. Files are transcoded on their first request and cached, so they can be served
+/// with a known length and seeked in.
+#[derive(Clone)]
+pub struct OnDemandLibrary {
+    directory: PathBuf,
+    cache_directory: PathBuf,
+    settings: StreamConfig,
+    processor: FFmpegProcessor,
+    db: LibraryDatabase,
+    /// Lock per cache file, held while it is transcoded, so concurrent requests of a file
+    /// transcode it once and requests of other files are not held up
+    transcoding: Arc<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>>,
+}
+
+impl OnDemandLibrary {
+    pub fn new(
+        config: &OnDemandConfig,
+        ffmpeg_path: Option<String>,
+        db: LibraryDatabase,
+        cache_directory: PathBuf,
+    ) -> Self {
+        let settings = config.stream_settings();
+        let processor = FFmpegProcessor::new(
+            ffmpeg_path,
+            settings.sample_rate,
+            settings.bitrate,
+            settings.channels,
+            settings.format.clone(),
+        );
+
+        Self {
+            directory: PathBuf::from(&config.directory),
+            cache_directory,
+            settings,
+            processor,
+            db,
+            transcoding: Arc::default(),
+        }
+    }
+
+    /// Audio files of the directory ordered by path, with library metadata where available
+    pub fn list(&self) -> Result<Vec<OnDemandFile>, Box<dyn Error + Send + Sync>> {
+        let mut files = Vec::new();
+        collect_audio_files(&self.directory, &mut files)?;
+        files.sort();
+
+        files.iter().map(|path| self.describe(path)).collect()
+    }
+
+    fn describe(&self, path: &Path) -> Result<OnDemandFile, Box<dyn Error + Send + Sync>> {
+        let segments: Vec<String> = path
+            .strip_prefix(&self.directory)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let url = segments
+            .iter()
+            .map(|segment| percent_encode(segment))
+            .fold(ONDEMAND_URL_PREFIX.to_string(), |url, segment| {
+                url + "/" + &segment
+            });
+
+        let file = match self.db.get_track_by_path(&path.to_string_lossy())? {
+            Some(track) => OnDemandFile {
+                path: segments.join("/"),
+                url,
+                title: track.title,
+                artist: track.artist,
+                album: track.album,
+                duration_seconds: track.duration_seconds.map(|d| d as u64),
+            },
+            None => {
+                let metadata = TrackMetadata::from_file(path);
+                OnDemandFile {
+                    path: segments.join("/"),
+                    url,
+                    title: metadata.title,
+                    artist: metadata.artist,
+                    album: metadata.album,
+                    duration_seconds: metadata.duration_seconds,
+                }
+            }
+        };
+
+        Ok(file)
+    }
+
+    /// Resolves the percent-encoded path of a request to an audio file inside the directory
+    pub fn resolve(&self, request_path: &str) -> Option<PathBuf> {
+        let relative = percent_decode(request_path)?;
+        let relative = Path::new(&relative);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        // Canonicalized, so symlinks cannot lead out of the directory
+        let root = self.directory.canonicalize().ok()?;
+        let path = root.join(relative).canonicalize().ok()?;
+        (path.starts_with(&root) && path.is_file() && LibraryScanner::is_audio_file(&path))
+            .then_some(path)
+    }
+
+    /// Transcoded copy of `source`, created on the first request and after the source changed
+    pub fn transcoded(&self, source: &Path) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+        let target = self.cache_directory.join(format!(
+            "{}.{}",
+            self.cache_key(source),
+            self.settings.format
+        ));
+
+        if is_newer(&target, source) {
+            debug!("Serving cached transcode of {:?}", source);
+            return Ok(target);
+        }
+
+        let lock = self.file_lock(&target);
+        let result = {
+            let _transcoding = lock.lock().unwrap();
+            self.transcode(source, &target)
+        };
+        self.release_file_lock(&target, lock);
+        result.map(|()| target)
+    }
+
+    /// Transcodes `source` into `target`, unless a concurrent request already did
+    fn transcode(&self, source: &Path, target: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if is_newer(target, source) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.cache_directory)?;
+        let partial = target.with_extension("part");
+        if let Err(e) = self.processor.transcode_file(source, &partial) {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, target)?;
+        Ok(())
+    }
+
+    fn file_lock(&self, target: &Path) -> Arc<Mutex<()>> {
+        self.transcoding
+            .lock()
+            .unwrap()
+            .entry(target.to_path_buf())
+            .or_default()
+            .clone()
+    }
+
+    /// Forgets the lock of a cache file once no other request waits for it
+    fn release_file_lock(&self, target: &Path, lock: Arc<Mutex<()>>) {
+        let mut locks = self.transcoding.lock().unwrap();
+        // One reference is held by the map, one by this request
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(target);
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        audio_processor::content_type(&self.settings.format)
+    }
+
+    /// Cache file name of a source, changes with the output settings
+    fn cache_key(&self, source: &Path) -> String {
+        let key = format!(
+            "{}|{}|{}",
+            source.display(),
+            self.settings.format,
+            self.settings.bitrate
+        );
+        digest::digest(&digest::SHA256, key.as_bytes()).as_ref()[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+fn collect_audio_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_audio_files(&path, files)?;
+        } else if LibraryScanner::is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `target` exists and was modified after `source`
+fn is_newer(target: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(target), modified(source)) {
+        (Some(target), Some(source)) => target >= source,
+        _ => false,
+    }
+}
+
+fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes, `None` for malformed escapes or invalid UTF-8
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut input = path.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn library(directory: &Path) -> OnDemandLibrary {
+        let db = LibraryDatabase::new(directory.join("library.db").to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let config = OnDemandConfig {
+            directory: directory.to_string_lossy().to_string(),
+            format: None,
+            bitrate: None,
+        };
+        OnDemandLibrary::new(&config, None, db, directory.join("cache"))
+    }
+
+    #[test]
+    fn given_range_headers_when_parsing_then_resolves_inclusive_byte_range() {
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Full);
+        assert_eq!(
+            ByteRange::parse(Some("bytes=10-19"), 100),
+            ByteRange::Partial(10, 19)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=90-"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-30"), 100),
+            ByteRange::Partial(70, 99)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=50-500"), 100),
+            ByteRange::Partial(50, 99)
+        );
+    }
+
+    #[test]
+    fn given_invalid_or_unsatisfiable_range_when_parsing_then_falls_back() {
+        assert_eq!(
+            ByteRange::parse(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-0"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(ByteRange::parse(Some("bytes=20-10"), 100), ByteRange::Full);
+        assert_eq!(
+            ByteRange::parse(Some("bytes=0-1,5-6"), 100),
+            ByteRange::Full
+        );
+        assert_eq!(ByteRange::parse(Some("items=0-1"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn given_request_paths_when_resolving_then_only_audio_files_inside_directory_match() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("shows")).unwrap();
+        fs::write(dir.path().join("shows/Night Shift.mp3"), b"").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        let ondemand = library(dir.path());
+
+        assert!(ondemand.resolve("shows/Night%20Shift.mp3").is_some());
+        assert!(ondemand.resolve("shows/Missing.mp3").is_none());
+        assert!(ondemand.resolve("notes.txt").is_none());
+        assert!(ondemand.resolve("shows/..%2F..%2Fetc%2Fpasswd").is_none());
+        assert!(ondemand.resolve("%2Fetc%2Fpasswd").is_none());
+    }
+
+    #[test]
+    fn given_directory_when_listing_then_returns_encoded_urls() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("shows")).unwrap();
+        fs::write(dir.path().join("shows/Night Shift.mp3"), b"").unwrap();
+        fs::write(dir.path().join("jingle.mp3"), b"").unwrap();
+
+        let files = library(dir.path()).list().unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].url, "/ondemand/jingle.mp3");
+        assert_eq!(files[1].path, "shows/Night Shift.mp3");
+        assert_eq!(files[1].url, "/ondemand/shows/Night%20Shift.mp3");
+        assert_eq!(files[1].title, "Night Shift");
+    }
+
+    #[test]
+    fn given_cached_transcode_when_another_file_is_transcoding_then_served_without_waiting() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("show.mp3");
+        fs::write(&source, b"").unwrap();
+        let ondemand = library(dir.path());
+        let target = ondemand.cache_directory.join(format!(
+            "{}.{}",
+            ondemand.cache_key(&source),
+            ondemand.settings.format
+        ));
+        fs::create_dir_all(&ondemand.cache_directory).unwrap();
+        fs::write(&target, b"transcoded").unwrap();
+
+        let other = ondemand.file_lock(&dir.path().join("cache/other.mp3"));
+        let _transcoding = other.lock().unwrap();
+        let cached = ondemand.file_lock(&target);
+        let _held = cached.lock().unwrap();
+
+        // A cached transcode is served without taking any lock
+        assert_eq!(ondemand.transcoded(&source).unwrap(), target);
+        assert!(!Arc::ptr_eq(&other, &cached));
+    }
+
+    #[test]
+    fn given_released_file_lock_when_no_request_waits_then_it_is_forgotten() {
+        let dir = TempDir::new().unwrap();
+        let ondemand = library(dir.path());
+        let target = dir.path().join("cache/show.mp3");
+
+        let first = ondemand.file_lock(&target);
+        let second = ondemand.file_lock(&target);
+        assert!(Arc::ptr_eq(&first, &second));
+
+        ondemand.release_file_lock(&target, first);
+        assert!(ondemand.transcoding.lock().unwrap().contains_key(&target));
+        ondemand.release_file_lock(&target, second);
+        assert!(ondemand.transcoding.lock().unwrap().is_empty());
+    }
+}
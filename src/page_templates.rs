@@ -0,0 +1,113 @@
+use minijinja::Environment;
+use serde::Serialize;
+use std::path::PathBuf;
+use warp::filters::BoxedFilter;
+use warp::Filter;
+
+const INFO_TEMPLATE: &str = "info.html";
+const DEFAULT_INFO_TEMPLATE: &str = include_str!("../templates/info.html");
+
+/// Renders the HTML pages, preferring operator templates from the configured templates directory.
+///
+/// Templates are read on every request, so changes apply without a restart. A missing or
+/// broken template falls back to the embedded default.
+#[derive(Clone, Default)]
+pub struct PageTemplates {
+    templates_dir: Option<PathBuf>,
+}
+
+impl PageTemplates {
+    pub fn new(templates_dir: Option<PathBuf>) -> Self {
+        Self { templates_dir }
+    }
+
+    pub fn render_info<S: Serialize>(&self, context: &S) -> Result<String, minijinja::Error> {
+        if let Some(custom) = self.load(INFO_TEMPLATE) {
+            match Self::render(&custom, context) {
+                Ok(rendered) => return Ok(rendered),
+                Err(e) => log::error!(
+                    "Custom template {} failed, using default: {:#}",
+                    INFO_TEMPLATE,
+                    e
+                ),
+            }
+        }
+
+        Self::render(DEFAULT_INFO_TEMPLATE, context)
+    }
+
+    /// Serve static files of custom templates (stylesheets, logos) from `<templates_dir>/assets`
+    pub fn assets_route(&self) -> BoxedFilter<(warp::fs::File,)> {
+        match &self.templates_dir {
+            Some(templates_dir) => warp::path("assets")
+                .and(warp::get())
+                .and(warp::fs::dir(templates_dir.join("assets")))
+                .boxed(),
+            None => warp::path("assets")
+                .and_then(|| async { Err(warp::reject::not_found()) })
+                .boxed(),
+        }
+    }
+
+    fn load(&self, name: &str) -> Option<String> {
+        let path = self.templates_dir.as_ref()?.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(template) => Some(template),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Failed to read template {:?}, using default: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn render<S: Serialize>(template: &str, context: &S) -> Result<String, minijinja::Error> {
+        let mut env = Environment::new();
+        env.add_template("page", template)?;
+        env.get_template("page")?.render(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[derive(Serialize)]
+    struct Context {
+        station_name: String,
+    }
+
+    fn context() -> Context {
+        Context {
+            station_name: "Deep Sea Radio".to_string(),
+        }
+    }
+
+    #[test]
+    fn given_custom_template_when_rendered_then_uses_custom_template() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("info.html"), "<h1>{{ station_name }}</h1>").unwrap();
+        let templates = PageTemplates::new(Some(dir.path().to_path_buf()));
+
+        let rendered = templates.render_info(&context()).unwrap();
+
+        assert_eq!(rendered, "<h1>Deep Sea Radio</h1>");
+    }
+
+    #[test]
+    fn given_missing_or_broken_template_when_rendered_then_falls_back_to_default() {
+        let dir = TempDir::new().unwrap();
+        let missing = PageTemplates::new(Some(dir.path().to_path_buf()));
+        std::fs::write(dir.path().join("info.html"), "{% if %}").unwrap();
+        let broken = PageTemplates::new(Some(dir.path().to_path_buf()));
+
+        let default = PageTemplates::new(None).render_info(&context()).unwrap();
+
+        assert!(default.contains("Deep Sea Radio"));
+        assert!(default.contains("<html"));
+        assert_eq!(broken.render_info(&context()).unwrap(), default);
+        std::fs::remove_file(dir.path().join("info.html")).unwrap();
+        assert_eq!(missing.render_info(&context()).unwrap(), default);
+    }
+}
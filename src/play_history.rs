@@ -0,0 +1,157 @@
+use crate::audio_metadata::TrackMetadata;
+use crate::library_db::{LibraryDatabase, PlaySource, PlayedItem};
+use crate::track_cache::TrackCache;
+use log::error;
+use std::error::Error;
+use std::path::Path;
+
+/// Days the play history is kept unless configured otherwise
+pub const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+/// Log of every item that went on air, stored in the database for reports and exports.
+/// Items older than the retention are removed whenever a new one is stored.
+#[derive(Clone)]
+pub struct PlayHistory {
+    db: LibraryDatabase,
+    track_cache: TrackCache,
+    /// Enabled streams, all of them air the same items
+    streams: Vec<String>,
+    retention_seconds: i64,
+}
+
+impl PlayHistory {
+    pub fn new(
+        db: LibraryDatabase,
+        track_cache: TrackCache,
+        mut streams: Vec<String>,
+        retention_days: u32,
+    ) -> Self {
+        streams.sort();
+        Self {
+            db,
+            track_cache,
+            streams,
+            retention_seconds: i64::from(retention_days) * 86400,
+        }
+    }
+
+    /// Stores that the item went on air now, errors are logged
+    pub fn item_played(&self, source: PlaySource, program: Option<String>, item: &TrackMetadata) {
+        self.played_at(source, program, item, chrono::Local::now().timestamp());
+    }
+
+    /// Aired items played at or after `from` and before `until`, newest first
+    pub fn items(
+        &self,
+        from: Option<i64>,
+        until: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<PlayedItem>, Box<dyn Error + Send + Sync>> {
+        self.db.get_play_history(from, until, limit)
+    }
+
+    fn played_at(
+        &self,
+        source: PlaySource,
+        program: Option<String>,
+        item: &TrackMetadata,
+        now: i64,
+    ) {
+        let played = PlayedItem {
+            played_at: now,
+            source,
+            program,
+            streams: self.streams.clone(),
+            file_path: item.file_path.clone(),
+            track_id: self.track_cache.track_id(Path::new(&item.file_path)),
+            title: item.title.clone(),
+            artist: item.artist.clone(),
+            album: item.album.clone(),
+            duration_seconds: item.duration_seconds.and_then(|d| i64::try_from(d).ok()),
+        };
+
+        if let Err(e) = self
+            .db
+            .insert_played_item(&played)
+            .and_then(|_| self.db.prune_play_history(now - self.retention_seconds))
+        {
+            error!("Failed to store play of {:?}: {}", item.file_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library_db::TrackRecord;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn given_aired_items_when_recorded_then_history_keeps_them_within_retention() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let track_id = db
+            .insert_track(&TrackRecord {
+                id: None,
+                file_path: "/music/a.mp3".to_string(),
+                title: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                duration_seconds: Some(180),
+                file_size: 1000,
+                last_modified: 0,
+                file_extension: "mp3".to_string(),
+                created_at: 0,
+                updated_at: 0,
+                genre: None,
+                artwork_path: None,
+                rating: None,
+                content_hash: None,
+                audio_fingerprint: None,
+                validation_error: None,
+                loudness_lufs: None,
+                bpm: None,
+                energy: None,
+                start_seconds: None,
+                end_seconds: None,
+                year: None,
+            })
+            .unwrap();
+        let track_cache = TrackCache::load(db.clone()).unwrap();
+        let history = PlayHistory::new(
+            db,
+            track_cache,
+            vec!["standard".to_string(), "high".to_string()],
+            1,
+        );
+        let item = |file_path: &str| TrackMetadata {
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            file_path: file_path.to_string(),
+            duration_seconds: Some(180),
+            ..TrackMetadata::default()
+        };
+
+        history.played_at(PlaySource::Library, None, &item("/music/a.mp3"), 1000);
+        history.played_at(
+            PlaySource::Jingle,
+            Some("Morning Show".to_string()),
+            &item("/jingles/id.mp3"),
+            2000,
+        );
+        history.played_at(PlaySource::Program, None, &item("/music/a.mp3"), 88000);
+
+        let items = history.items(None, None, 10).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].played_at, 88000);
+        assert_eq!(items[0].track_id, Some(track_id));
+        assert_eq!(items[0].streams, vec!["high", "standard"]);
+        assert_eq!(items[1].source, PlaySource::Jingle);
+        assert_eq!(items[1].program.as_deref(), Some("Morning Show"));
+        assert_eq!(items[1].track_id, None);
+
+        assert_eq!(history.items(Some(0), Some(3000), 10).unwrap().len(), 1);
+    }
+}
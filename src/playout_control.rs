@@ -0,0 +1,133 @@
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct PlayoutState {
+    /// Tracks currently being encoded, with the number of streams playing them
+    playing: HashMap<PathBuf, usize>,
+    /// Tracks that must not be played anymore
+    skipped: HashSet<PathBuf>,
+    /// Programs whose tracks are cut off, with the length of the fade out
+    ended_programs: HashMap<String, Duration>,
+}
+
+/// Shared playout state between the audio pipeline and the API, used to
+/// interrupt tracks that are being removed from the library or whose program ended.
+#[derive(Clone, Default)]
+pub struct PlayoutControl {
+    state: Arc<Mutex<PlayoutState>>,
+}
+
+impl PlayoutControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track_started(&self, track: &Path) {
+        let mut state = self.state.lock().unwrap();
+        *state.playing.entry(track.to_path_buf()).or_insert(0) += 1;
+    }
+
+    pub fn track_finished(&self, track: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(count) = state.playing.get_mut(track) {
+            *count -= 1;
+            if *count == 0 {
+                state.playing.remove(track);
+            }
+        }
+    }
+
+    pub fn is_playing(&self, track: &Path) -> bool {
+        self.state.lock().unwrap().playing.contains_key(track)
+    }
+
+    /// Stops the track on all streams and prevents it from being played again
+    pub fn skip(&self, track: &Path) {
+        info!("Skipping track {:?}", track);
+        self.state
+            .lock()
+            .unwrap()
+            .skipped
+            .insert(track.to_path_buf());
+    }
+
+    /// Allows a previously skipped track to be played again, e.g. after a re-upload
+    pub fn allow(&self, track: &Path) {
+        self.state.lock().unwrap().skipped.remove(track);
+    }
+
+    pub fn is_skipped(&self, track: &Path) -> bool {
+        self.state.lock().unwrap().skipped.contains(track)
+    }
+
+    /// Fades out the tracks of the program on all streams, or stops them if `fade` is zero,
+    /// and drops its tracks still queued
+    pub fn end_program(&self, name: &str, fade: Duration) {
+        info!("Ending tracks of program '{}'", name);
+        self.state
+            .lock()
+            .unwrap()
+            .ended_programs
+            .insert(name.to_string(), fade);
+    }
+
+    /// Lets the tracks of a program play again when it starts anew
+    pub fn program_started(&self, name: &str) {
+        self.state.lock().unwrap().ended_programs.remove(name);
+    }
+
+    /// Fade out of the tracks of the program, `None` while it is on air
+    pub fn program_end(&self, name: &str) -> Option<Duration> {
+        self.state.lock().unwrap().ended_programs.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_track_playing_on_two_streams_when_one_finishes_then_still_playing() {
+        let control = PlayoutControl::new();
+        let track = Path::new("/music/song.mp3");
+
+        control.track_started(track);
+        control.track_started(track);
+        control.track_finished(track);
+        assert!(control.is_playing(track));
+
+        control.track_finished(track);
+        assert!(!control.is_playing(track));
+    }
+
+    #[test]
+    fn given_skipped_track_when_allowed_again_then_no_longer_skipped() {
+        let control = PlayoutControl::new();
+        let track = Path::new("/music/song.mp3");
+
+        control.skip(track);
+        assert!(control.is_skipped(track));
+
+        control.allow(track);
+        assert!(!control.is_skipped(track));
+    }
+
+    #[test]
+    fn given_ended_program_when_started_again_then_its_tracks_play() {
+        let control = PlayoutControl::new();
+
+        control.end_program("Techno Night", Duration::from_secs(3));
+        assert_eq!(
+            control.program_end("Techno Night"),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(control.program_end("Morning Show"), None);
+
+        control.program_started("Techno Night");
+        assert_eq!(control.program_end("Techno Night"), None);
+    }
+}
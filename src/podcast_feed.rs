@@ -0,0 +1,171 @@
+//! Podcast feeds as program source.
+//!
+//! The RSS feed of a podcast program is requested when the program starts. Of its `<item>`
+//! elements with an audio `<enclosure>`, the newest by `<pubDate>` or a random one is picked,
+//! and its enclosure URL is streamed like a liveset.
+
+use crate::config::PodcastEpisodeSelection;
+use crate::http_client::HttpClientFactory;
+use crate::xml_scan::{attribute_value, element_contents, start_tags, text_value};
+use chrono::{DateTime, FixedOffset};
+use log::{debug, info};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const FEED_TIMEOUT_SECONDS: u64 = 30;
+
+/// An episode of a podcast feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodcastEpisode {
+    pub title: String,
+    /// URL of the audio enclosure
+    pub url: String,
+    pub published: Option<DateTime<FixedOffset>>,
+}
+
+pub struct PodcastClient {
+    client: reqwest::Client,
+}
+
+impl PodcastClient {
+    pub fn new(http: &HttpClientFactory) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let client = http.client(std::time::Duration::from_secs(FEED_TIMEOUT_SECONDS))?;
+
+        Ok(Self { client })
+    }
+
+    /// Requests the feed and picks an episode of it
+    pub async fn fetch_episode(
+        &self,
+        feed_url: &str,
+        selection: PodcastEpisodeSelection,
+    ) -> Result<PodcastEpisode, Box<dyn std::error::Error + Send + Sync>> {
+        debug!("Fetching podcast feed {}", feed_url);
+        let feed = self
+            .client
+            .get(feed_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let episodes = parse_feed(&feed);
+        info!(
+            "Podcast feed {} has {} episode(s) with audio",
+            feed_url,
+            episodes.len()
+        );
+        select_episode(episodes, selection)
+            .ok_or_else(|| format!("No episode with audio in feed {}", feed_url).into())
+    }
+}
+
+/// Extracts the episodes of an RSS feed, items without an audio enclosure are ignored
+pub fn parse_feed(xml: &str) -> Vec<PodcastEpisode> {
+    element_contents(xml, "item")
+        .into_iter()
+        .filter_map(|item| {
+            let url = start_tags(item, "enclosure")
+                .into_iter()
+                .find_map(|enclosure| {
+                    let audio = attribute_value(enclosure, "type")
+                        .is_none_or(|mime| mime.starts_with("audio/"));
+                    attribute_value(enclosure, "url").filter(|url| audio && !url.is_empty())
+                })?;
+            let title = element_contents(item, "title")
+                .into_iter()
+                .map(text_value)
+                .next()
+                .unwrap_or_default();
+            let published = element_contents(item, "pubDate")
+                .into_iter()
+                .find_map(|date| DateTime::parse_from_rfc2822(&text_value(date)).ok());
+
+            Some(PodcastEpisode {
+                title,
+                url,
+                published,
+            })
+        })
+        .collect()
+}
+
+/// The newest episode, the first of the feed if none has a publication date, or a random one
+fn select_episode(
+    episodes: Vec<PodcastEpisode>,
+    selection: PodcastEpisodeSelection,
+) -> Option<PodcastEpisode> {
+    match selection {
+        PodcastEpisodeSelection::Newest => {
+            let newest = episodes
+                .iter()
+                .enumerate()
+                .max_by_key(|(index, episode)| (episode.published, std::cmp::Reverse(*index)))
+                .map(|(index, _)| index)?;
+            episodes.into_iter().nth(newest)
+        }
+        PodcastEpisodeSelection::Random => {
+            if episodes.is_empty() {
+                return None;
+            }
+            let mut hasher = DefaultHasher::new();
+            std::time::SystemTime::now().hash(&mut hasher);
+            let index = hasher.finish() as usize % episodes.len();
+            episodes.into_iter().nth(index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Jazz Talk</title>
+    <item>
+      <title>Episode 1</title>
+      <itunes:title>Ep. 1</itunes:title>
+      <pubDate>Mon, 05 Oct 2026 06:00:00 +0000</pubDate>
+      <enclosure url="https://cdn.example.com/ep1.mp3" length="1000" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title><![CDATA[Episode 2 & more]]></title>
+      <pubDate>Mon, 12 Oct 2026 06:00:00 +0000</pubDate>
+      <enclosure url="https://cdn.example.com/ep2.m4a?src=rss&amp;v=2" type="audio/x-m4a" />
+    </item>
+    <item>
+      <title>Video special</title>
+      <pubDate>Wed, 14 Oct 2026 06:00:00 +0000</pubDate>
+      <enclosure url="https://cdn.example.com/special.mp4" type="video/mp4"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn given_rss_feed_when_parsed_then_returns_episodes_with_audio() {
+        let episodes = parse_feed(FEED);
+
+        assert_eq!(episodes.len(), 2);
+        assert_eq!(episodes[0].title, "Episode 1");
+        assert_eq!(episodes[1].title, "Episode 2 & more");
+        assert_eq!(
+            episodes[1].url,
+            "https://cdn.example.com/ep2.m4a?src=rss&v=2"
+        );
+    }
+
+    #[test]
+    fn given_episodes_when_selecting_newest_then_latest_publication_wins() {
+        let newest = select_episode(parse_feed(FEED), PodcastEpisodeSelection::Newest).unwrap();
+        assert_eq!(newest.url, "https://cdn.example.com/ep2.m4a?src=rss&v=2");
+
+        let undated = parse_feed(&FEED.replace("pubDate", "date"));
+        let first = select_episode(undated, PodcastEpisodeSelection::Newest).unwrap();
+        assert_eq!(first.title, "Episode 1");
+
+        assert!(select_episode(Vec::new(), PodcastEpisodeSelection::Random).is_none());
+    }
+}
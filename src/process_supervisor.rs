@@ -0,0 +1,308 @@
+use log::{debug, error, warn};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often the watchdog checks a process
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often `wait` polls for the exit of a process
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Stderr lines kept for error messages
+const STDERR_TAIL_LINES: usize = 5;
+
+/// How long a process may take to produce output while output is expected
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessTimeouts {
+    /// Until the first output, e.g. while connecting to a stream URL
+    pub startup: Duration,
+    /// Between two outputs
+    pub read: Duration,
+}
+
+#[derive(Default)]
+struct SupervisorState {
+    /// Since when output is expected, `None` while the process is idle
+    waiting_since: Mutex<Option<Instant>>,
+    produced_output: AtomicBool,
+    timed_out: AtomicBool,
+    finished: AtomicBool,
+    stderr_tail: Mutex<VecDeque<String>>,
+}
+
+/// A child process watched by a supervisor.
+///
+/// Its stderr is logged line by line, and the last lines are kept for error messages. A
+/// watchdog thread kills the process once it produces no output within the timeouts while
+/// output is expected, so a hung process cannot stall the caller forever. Processes are
+/// reaped when they exit, when stopped and when dropped.
+pub struct SupervisedProcess {
+    label: String,
+    child: Arc<Mutex<Child>>,
+    state: Arc<SupervisorState>,
+    timeouts: ProcessTimeouts,
+    stderr_logger: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Reports output of a supervised process from another thread
+#[derive(Clone)]
+pub struct OutputMonitor {
+    state: Arc<SupervisorState>,
+}
+
+impl OutputMonitor {
+    /// The process produced the expected output
+    pub fn output_received(&self) {
+        self.state.produced_output.store(true, Ordering::Relaxed);
+        *self.state.waiting_since.lock().unwrap() = None;
+    }
+}
+
+impl SupervisedProcess {
+    /// Spawns the command with piped stdout and stderr, `label` names it in logs
+    pub fn spawn(
+        command: &mut Command,
+        label: &str,
+        timeouts: ProcessTimeouts,
+    ) -> std::io::Result<Self> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stderr = child.stderr.take();
+        let state: Arc<SupervisorState> = Arc::default();
+
+        let stderr_logger = stderr.map(|stderr| {
+            let label = label.to_string();
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || log_stderr(&label, stderr, &state))
+        });
+
+        let process = Self {
+            label: label.to_string(),
+            child: Arc::new(Mutex::new(child)),
+            state,
+            timeouts,
+            stderr_logger: Mutex::new(stderr_logger),
+        };
+
+        let label = process.label.clone();
+        let child = Arc::clone(&process.child);
+        let state = Arc::clone(&process.state);
+        std::thread::spawn(move || watch(&label, &child, &state, timeouts));
+
+        Ok(process)
+    }
+
+    pub fn take_stdin(&self) -> Option<ChildStdin> {
+        self.child.lock().unwrap().stdin.take()
+    }
+
+    pub fn take_stdout(&self) -> Option<ChildStdout> {
+        self.child.lock().unwrap().stdout.take()
+    }
+
+    /// Output is expected from now on, keeps an earlier expectation
+    pub fn expect_output(&self) {
+        self.state
+            .waiting_since
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+    }
+
+    /// The process produced the expected output
+    pub fn output_received(&self) {
+        self.monitor().output_received();
+    }
+
+    /// Handle reporting output from the thread reading stdout
+    pub fn monitor(&self) -> OutputMonitor {
+        OutputMonitor {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Whether the watchdog killed the process
+    pub fn timed_out(&self) -> bool {
+        self.state.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Last stderr lines, joined by `; `
+    pub fn stderr_tail(&self) -> String {
+        let tail = self.state.stderr_tail.lock().unwrap();
+        tail.iter().cloned().collect::<Vec<_>>().join("; ")
+    }
+
+    /// Waits for the process to exit, it is killed if it does not exit within the read timeout
+    pub fn wait(&self) -> std::io::Result<ExitStatus> {
+        let deadline = Instant::now() + self.timeouts.read;
+        let mut killed = false;
+        loop {
+            if let Some(status) = self.child.lock().unwrap().try_wait()? {
+                self.state.finished.store(true, Ordering::Relaxed);
+                // Stderr closes with the exit, wait for its last lines to reach the tail
+                if let Some(logger) = self.stderr_logger.lock().unwrap().take() {
+                    let _ = logger.join();
+                }
+                return Ok(status);
+            }
+            if !killed && Instant::now() >= deadline {
+                warn!("FFmpeg {} did not exit, killing it", self.label);
+                self.kill();
+                killed = true;
+            }
+            std::thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+
+    /// Kills the process and reaps it
+    pub fn stop(&self) {
+        self.state.finished.store(true, Ordering::Relaxed);
+        let mut child = self.child.lock().unwrap();
+        if matches!(child.try_wait(), Ok(Some(_))) {
+            return;
+        }
+        if let Err(e) = child.kill() {
+            warn!("Failed to kill FFmpeg {}: {}", self.label, e);
+        }
+        let _ = child.wait();
+    }
+
+    fn kill(&self) {
+        if let Err(e) = self.child.lock().unwrap().kill() {
+            warn!("Failed to kill FFmpeg {}: {}", self.label, e);
+        }
+    }
+}
+
+impl Drop for SupervisedProcess {
+    fn drop(&mut self) {
+        if !self.state.finished.load(Ordering::Relaxed) {
+            self.stop();
+        }
+    }
+}
+
+fn log_stderr(label: &str, stderr: std::process::ChildStderr, state: &SupervisorState) {
+    for line in BufReader::new(stderr).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        warn!("FFmpeg {}: {}", label, line);
+        let mut tail = state.stderr_tail.lock().unwrap();
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+    }
+}
+
+/// Kills the process once it misses its timeout, reaps it once it exits
+fn watch(label: &str, child: &Mutex<Child>, state: &SupervisorState, timeouts: ProcessTimeouts) {
+    loop {
+        std::thread::sleep(WATCHDOG_INTERVAL);
+        if state.finished.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let mut child = child.lock().unwrap();
+        if let Ok(Some(status)) = child.try_wait() {
+            debug!("FFmpeg {} exited with {}", label, status);
+            break;
+        }
+
+        let timeout = if state.produced_output.load(Ordering::Relaxed) {
+            timeouts.read
+        } else {
+            timeouts.startup
+        };
+        let waiting_since = *state.waiting_since.lock().unwrap();
+        if waiting_since.is_some_and(|since| since.elapsed() >= timeout) {
+            error!(
+                "FFmpeg {} produced no output for {}s, killing it",
+                label,
+                timeout.as_secs()
+            );
+            state.timed_out.store(true, Ordering::Relaxed);
+            if let Err(e) = child.kill() {
+                warn!("Failed to kill FFmpeg {}: {}", label, e);
+            }
+            let _ = child.wait();
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn timeouts(millis: u64) -> ProcessTimeouts {
+        ProcessTimeouts {
+            startup: Duration::from_millis(millis),
+            read: Duration::from_millis(millis),
+        }
+    }
+
+    fn shell(script: &str, millis: u64) -> SupervisedProcess {
+        SupervisedProcess::spawn(
+            Command::new("sh").args(["-c", script]),
+            "test",
+            timeouts(millis),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn given_hung_process_when_output_expected_then_killed_after_timeout() {
+        let process = shell("echo 'Connection timed out' >&2; exec sleep 30", 300);
+        let mut stdout = process.take_stdout().unwrap();
+
+        process.expect_output();
+        let started = Instant::now();
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(process.timed_out());
+        assert!(!process.wait().unwrap().success());
+        assert_eq!(process.stderr_tail(), "Connection timed out");
+    }
+
+    #[test]
+    fn given_idle_process_when_no_output_expected_then_keeps_running() {
+        let process = shell("exec sleep 30", 300);
+
+        std::thread::sleep(Duration::from_millis(800));
+
+        assert!(!process.timed_out());
+        process.stop();
+    }
+
+    #[test]
+    fn given_finishing_process_when_read_then_returns_output_and_status() {
+        let process = shell("printf audio", 5000);
+        let mut stdout = process.take_stdout().unwrap();
+
+        process.expect_output();
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+        process.output_received();
+
+        assert_eq!(output, "audio");
+        assert!(process.wait().unwrap().success());
+        assert!(!process.timed_out());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::audio_processor::FFmpegProcessor;
+use crate::config::{AccessConfig, ScheduleProgram, StreamConfig};
+use crate::program_schedule::ProgramSchedule;
+use crate::schedule_engine::ScheduleEngine;
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Local};
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// Location of the fallback loops transcoded for each stream
+pub const ACCESS_CACHE_PATH: &str = "./data/access";
+
+/// Query parameter carrying the access token of a listener
+pub const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+/// How often the schedule is checked for a running restricted program
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Time slot of a restricted program
+struct RestrictedSlot {
+    name: String,
+    schedule: ProgramSchedule,
+    duration: Duration,
+}
+
+/// Restricts programs to listeners with an access token.
+///
+/// While a restricted program runs, the stream sends listeners without a valid token the
+/// fallback loop, encoded with the settings of their stream, instead of the program audio.
+/// The restricted slots follow the schedule, so reloaded or imported programs apply right away.
+#[derive(Clone)]
+pub struct ProgramAccess {
+    slots: Arc<RwLock<Vec<RestrictedSlot>>>,
+    programs: watch::Receiver<Vec<ScheduleProgram>>,
+    tokens: Arc<HashSet<String>>,
+    fallback: PathBuf,
+    fallbacks: Arc<RwLock<HashMap<String, Bytes>>>,
+    restricted: Arc<AtomicBool>,
+}
+
+impl ProgramAccess {
+    pub fn new(
+        config: &AccessConfig,
+        mut programs: watch::Receiver<Vec<ScheduleProgram>>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let slots = restricted_slots(&programs.borrow_and_update())?;
+
+        Ok(Self {
+            slots: Arc::new(RwLock::new(slots)),
+            programs,
+            tokens: Arc::new(config.tokens.iter().cloned().collect()),
+            fallback: PathBuf::from(&config.fallback),
+            fallbacks: Arc::new(RwLock::new(HashMap::new())),
+            restricted: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Transcodes the fallback loop for a stream, so it can replace the stream audio byte
+    /// for byte
+    pub fn prepare_fallback(
+        &self,
+        mount: &str,
+        settings: &StreamConfig,
+        ffmpeg_path: Option<String>,
+        cache_directory: &Path,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let processor = FFmpegProcessor::new(
+            ffmpeg_path,
+            settings.sample_rate,
+            settings.bitrate,
+            settings.channels,
+            settings.format.clone(),
+        );
+
+        std::fs::create_dir_all(cache_directory)?;
+        let target = cache_directory.join(format!("{}.{}", mount, settings.format));
+        processor
+            .transcode_file(&self.fallback, &target)
+            .map_err(|e| format!("Failed to transcode access fallback for '{}': {}", mount, e))?;
+
+        let data = std::fs::read(&target)?;
+        if data.is_empty() {
+            return Err(format!("Access fallback {:?} contains no audio", self.fallback).into());
+        }
+
+        self.fallbacks
+            .write()
+            .unwrap()
+            .insert(mount.to_string(), Bytes::from(data));
+        Ok(())
+    }
+
+    /// Whether the fallback loop of a stream was prepared
+    pub fn has_fallback(&self, mount: &str) -> bool {
+        self.fallbacks.read().unwrap().contains_key(mount)
+    }
+
+    /// Number of active restricted programs
+    pub fn restricted_programs(&self) -> usize {
+        self.slots.read().unwrap().len()
+    }
+
+    /// Tracks whether a restricted program is on air in the background
+    pub fn start(&self) {
+        let mut access = self.clone();
+        tokio::spawn(async move {
+            loop {
+                access.follow_schedule();
+                let program = access.restricted_program(Local::now());
+                let restricted = program.is_some();
+                if access.restricted.swap(restricted, Ordering::Relaxed) != restricted {
+                    match program {
+                        Some(name) => info!("Restricted program '{}' started", name),
+                        None => info!("Restricted program ended, stream is open to all"),
+                    }
+                }
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Whether a restricted program is on air
+    pub fn is_restricted(&self) -> bool {
+        self.restricted.load(Ordering::Relaxed)
+    }
+
+    pub fn is_authorized(&self, token: Option<&str>) -> bool {
+        token.is_some_and(|token| self.tokens.contains(token))
+    }
+
+    /// Fallback loop of a stream, `None` if it was not prepared
+    pub fn fallback(&self, mount: &str) -> Option<FallbackLoop> {
+        match self.fallbacks.read().unwrap().get(mount) {
+            Some(data) => Some(FallbackLoop::new(data.clone())),
+            None => {
+                warn!("No access fallback for stream '{}'", mount);
+                None
+            }
+        }
+    }
+
+    /// Rebuilds the restricted slots once the schedule changed, an invalid schedule keeps the
+    /// current slots
+    fn follow_schedule(&mut self) {
+        if !self.programs.has_changed().unwrap_or(false) {
+            return;
+        }
+
+        match restricted_slots(&self.programs.borrow_and_update()) {
+            Ok(slots) => {
+                info!(
+                    "Schedule changed, {} restricted program(s) require an access token",
+                    slots.len()
+                );
+                *self.slots.write().unwrap() = slots;
+            }
+            Err(e) => error!(
+                "Failed to apply restricted programs, keeping current: {}",
+                e
+            ),
+        }
+    }
+
+    /// Name of the restricted program running at `now`, if any
+    fn restricted_program(&self, now: DateTime<Local>) -> Option<String> {
+        self.slots
+            .read()
+            .unwrap()
+            .iter()
+            .find(|slot| {
+                slot.schedule
+                    .after(&(now - slot.duration))
+                    .next()
+                    .is_some_and(|start| start <= now)
+            })
+            .map(|slot| slot.name.clone())
+    }
+}
+
+/// Time slots of the active restricted programs
+fn restricted_slots(
+    programs: &[ScheduleProgram],
+) -> Result<Vec<RestrictedSlot>, Box<dyn std::error::Error + Send + Sync>> {
+    programs
+        .iter()
+        .filter(|p| p.active && p.is_restricted())
+        .map(|p| {
+            let schedule = ProgramSchedule::from_program(p)?.ok_or_else(|| {
+                format!("Restricted program '{}' needs a cron expression", p.name)
+            })?;
+            Ok(RestrictedSlot {
+                name: p.name.clone(),
+                schedule,
+                duration: ScheduleEngine::parse_duration(&p.duration)?,
+            })
+        })
+        .collect()
+}
+
+/// Encoded fallback audio played endlessly to a single listener
+pub struct FallbackLoop {
+    data: Bytes,
+    position: usize,
+}
+
+impl FallbackLoop {
+    fn new(data: Bytes) -> Self {
+        Self { data, position: 0 }
+    }
+
+    /// Next `len` bytes of the loop, wrapping around at its end
+    pub fn next_chunk(&mut self, len: usize) -> Bytes {
+        let mut chunk = Vec::with_capacity(len);
+        while chunk.len() < len {
+            let end = (self.position + len - chunk.len()).min(self.data.len());
+            chunk.extend_from_slice(&self.data[self.position..end]);
+            self.position = end % self.data.len();
+        }
+        Bytes::from(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn program(name: &str, cron: &str, restricted: bool) -> ScheduleProgram {
+        ScheduleProgram {
+            name: name.to_string(),
+            active: true,
+            cron: cron.to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "2h".to_string(),
+            program_type: None,
+            playlist: Some("/playlists/members.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: Some(restricted),
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        }
+    }
+
+    fn access(programs: &[ScheduleProgram]) -> ProgramAccess {
+        let config = AccessConfig {
+            tokens: vec!["member-token".to_string()],
+            fallback: "/sounds/members-only.mp3".to_string(),
+        };
+        let (_tx, programs) = watch::channel(programs.to_vec());
+        ProgramAccess::new(&config, programs).unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 1, 15, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn given_restricted_program_when_checking_time_then_restricted_only_during_its_slot() {
+        let access = access(&[
+            program("Members Hour", "0 0 20 * * *", true),
+            program("Open Show", "0 0 8 * * *", false),
+        ]);
+
+        assert_eq!(
+            access.restricted_program(at(20, 0)).as_deref(),
+            Some("Members Hour")
+        );
+        assert_eq!(
+            access.restricted_program(at(21, 59)).as_deref(),
+            Some("Members Hour")
+        );
+        assert_eq!(access.restricted_program(at(22, 0)), None);
+        assert_eq!(access.restricted_program(at(19, 59)), None);
+        assert_eq!(access.restricted_program(at(9, 0)), None);
+    }
+
+    #[test]
+    fn given_reloaded_schedule_when_following_it_then_restricted_slots_are_replaced() {
+        let config = AccessConfig {
+            tokens: vec!["member-token".to_string()],
+            fallback: "/sounds/members-only.mp3".to_string(),
+        };
+        let (tx, programs) = watch::channel(vec![program("Members Hour", "0 0 20 * * *", true)]);
+        let mut access = ProgramAccess::new(&config, programs).unwrap();
+
+        tx.send(vec![
+            program("Members Hour", "0 0 20 * * *", false),
+            program("Late Members", "0 0 23 * * *", true),
+        ])
+        .unwrap();
+        access.follow_schedule();
+
+        assert_eq!(access.restricted_program(at(20, 30)), None);
+        assert_eq!(
+            access.restricted_program(at(23, 30)).as_deref(),
+            Some("Late Members")
+        );
+
+        tx.send(Vec::new()).unwrap();
+        access.follow_schedule();
+        assert_eq!(access.restricted_programs(), 0);
+    }
+
+    #[test]
+    fn given_tokens_when_authorizing_then_only_configured_tokens_pass() {
+        let access = access(&[]);
+
+        assert!(access.is_authorized(Some("member-token")));
+        assert!(!access.is_authorized(Some("guess")));
+        assert!(!access.is_authorized(None));
+        assert_eq!(access.restricted_programs(), 0);
+    }
+
+    #[test]
+    fn given_fallback_loop_when_reading_chunks_then_wraps_around() {
+        let mut fallback = FallbackLoop::new(Bytes::from_static(b"abcde"));
+
+        assert_eq!(fallback.next_chunk(3), Bytes::from_static(b"abc"));
+        assert_eq!(fallback.next_chunk(4), Bytes::from_static(b"deab"));
+        assert_eq!(fallback.next_chunk(11), Bytes::from_static(b"cdeabcdeabc"));
+    }
+}
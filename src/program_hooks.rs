@@ -0,0 +1,135 @@
+use chrono::{DateTime, Local};
+use log::{debug, error, info};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+
+/// Program start or end a hook command runs for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramEvent {
+    Start,
+    End,
+}
+
+impl ProgramEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProgramEvent::Start => "program_start",
+            ProgramEvent::End => "program_end",
+        }
+    }
+}
+
+/// Commands run when a program starts and ends, e.g. to switch the studio lights or start a
+/// recorder
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgramHooks {
+    pub on_start: Option<Vec<String>>,
+    pub on_end: Option<Vec<String>>,
+}
+
+impl ProgramHooks {
+    /// Runs the command of the event in the background, playout does not wait for it.
+    ///
+    /// `end` is the scheduled end of a starting program and the actual end of an ending
+    /// one, `duration` the configured program duration.
+    pub fn run(
+        &self,
+        event: ProgramEvent,
+        program: &str,
+        duration: chrono::Duration,
+        end: DateTime<Local>,
+    ) -> Option<JoinHandle<()>> {
+        let command = match event {
+            ProgramEvent::Start => self.on_start.as_ref(),
+            ProgramEvent::End => self.on_end.as_ref(),
+        }?;
+        let (program_path, args) = command.split_first()?;
+
+        let mut process = Command::new(program_path);
+        process
+            .args(args)
+            .env("FUNKSTROM_EVENT", event.as_str())
+            .env("FUNKSTROM_PROGRAM", program)
+            .env(
+                "FUNKSTROM_PROGRAM_DURATION",
+                duration.num_seconds().to_string(),
+            )
+            .env("FUNKSTROM_PROGRAM_END", end.to_rfc3339())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let label = format!("{} hook of program '{}'", event.as_str(), program);
+        debug!("Running {}: {:?}", label, command);
+        Some(std::thread::spawn(move || {
+            match process.spawn().and_then(|child| child.wait_with_output()) {
+                Ok(output) if output.status.success() => info!("Finished {}", label),
+                Ok(output) => error!(
+                    "{} failed with {}: {}",
+                    label,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => error!("Cannot run {}: {}", label, e),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn given_hooks_when_program_starts_and_ends_then_commands_get_program_environment() {
+        let directory = TempDir::new().unwrap();
+        let output = directory.path().join("events.txt");
+        let command = |event: &str| {
+            Some(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!(
+                    "echo \"{} $FUNKSTROM_EVENT $FUNKSTROM_PROGRAM $FUNKSTROM_PROGRAM_DURATION $FUNKSTROM_PROGRAM_END\" >> {}",
+                    event,
+                    output.display()
+                ),
+            ])
+        };
+        let hooks = ProgramHooks {
+            on_start: command("on_start"),
+            on_end: command("on_end"),
+        };
+        let end = Local.with_ymd_and_hms(2026, 3, 2, 21, 0, 0).unwrap();
+
+        for event in [ProgramEvent::Start, ProgramEvent::End] {
+            hooks
+                .run(event, "Jazz Hour", chrono::Duration::hours(1), end)
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+
+        let lines: Vec<String> = std::fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                format!("on_start program_start Jazz Hour 3600 {}", end.to_rfc3339()),
+                format!("on_end program_end Jazz Hour 3600 {}", end.to_rfc3339()),
+            ]
+        );
+        assert!(ProgramHooks::default()
+            .run(
+                ProgramEvent::Start,
+                "Jazz Hour",
+                chrono::Duration::hours(1),
+                end
+            )
+            .is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::announcements::{synthesize, DEFAULT_EXTENSION};
+use crate::config::{AnnouncementsConfig, ProgramIntro, ScheduleProgram};
+use log::{debug, error, warn};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use tokio::sync::watch;
+
+/// Files the spoken intros are written to in turn, so an intro still playing is never overwritten
+const INTRO_SLOTS: usize = 2;
+
+/// Intros played right before the first track of a program, e.g. "Up next: The Jazz Hour".
+///
+/// Spoken intros are synthesized by the TTS command of the announcements once the program is
+/// announced, up to an hour before it starts. An intro that is not ready when the program
+/// starts is skipped instead of delaying the program.
+pub struct ProgramIntros {
+    programs: watch::Receiver<Vec<ScheduleProgram>>,
+    /// TTS command of the announcements, spoken intros are skipped without one
+    command: Option<Vec<String>>,
+    station_name: String,
+    directory: PathBuf,
+    extension: String,
+    slot: usize,
+    /// Program and synthesis of its spoken intro
+    synthesis: Option<(String, JoinHandle<Result<PathBuf, String>>)>,
+}
+
+impl ProgramIntros {
+    /// `programs` follows the schedule, so reloaded intros apply at the next program start
+    pub fn new(
+        announcements: Option<&AnnouncementsConfig>,
+        station_name: &str,
+        directory: PathBuf,
+        programs: watch::Receiver<Vec<ScheduleProgram>>,
+    ) -> Self {
+        Self {
+            programs,
+            command: announcements.map(|a| a.command.clone()),
+            station_name: station_name.to_string(),
+            directory,
+            extension: announcements
+                .and_then(|a| a.extension.clone())
+                .unwrap_or_else(|| DEFAULT_EXTENSION.to_string()),
+            slot: 0,
+            synthesis: None,
+        }
+    }
+
+    fn intro(&self, name: &str) -> Option<ProgramIntro> {
+        self.programs
+            .borrow()
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.intro.clone())
+    }
+
+    /// Starts synthesizing the spoken intro of a program announced to start soon
+    pub fn program_announced(&mut self, name: &str) {
+        let Some(text) = self.intro(name).and_then(|intro| intro.text) else {
+            return;
+        };
+        if self
+            .synthesis
+            .as_ref()
+            .is_some_and(|(program, _)| program == name)
+        {
+            return;
+        }
+        let Some(command) = self.command.clone() else {
+            warn!(
+                "Program '{}' has a spoken intro, but no [announcements] command synthesizes it",
+                name
+            );
+            return;
+        };
+
+        let text = text
+            .replace("{program}", name)
+            .replace("{station}", &self.station_name);
+        self.slot = (self.slot + 1) % INTRO_SLOTS;
+        let output = self
+            .directory
+            .join(format!("intro-{}.{}", self.slot, self.extension));
+        debug!("Synthesizing intro of program '{}': {}", name, text);
+        self.synthesis = Some((
+            name.to_string(),
+            std::thread::spawn(move || {
+                synthesize(&command, &text, &output)
+                    .map(|()| output)
+                    .map_err(|e| e.to_string())
+            }),
+        ));
+    }
+
+    /// Intro to play before the first track of a starting program, `None` if it has none or
+    /// its spoken intro is not ready
+    pub fn program_started(&mut self, name: &str) -> Option<PathBuf> {
+        let intro = self.intro(name)?;
+        if let Some(file) = intro.file {
+            return Some(PathBuf::from(file));
+        }
+
+        let synthesis = match self.synthesis.take() {
+            Some((program, synthesis)) if program == name => synthesis,
+            other => {
+                self.synthesis = other;
+                warn!(
+                    "Intro of program '{}' was not synthesized ahead, skipping it",
+                    name
+                );
+                return None;
+            }
+        };
+        if !synthesis.is_finished() {
+            warn!(
+                "Intro of program '{}' is not synthesized in time, skipping it",
+                name
+            );
+            return None;
+        }
+
+        match synthesis.join() {
+            Ok(Ok(clip)) => Some(clip),
+            Ok(Err(e)) => {
+                error!("Failed to synthesize intro of program '{}': {}", name, e);
+                None
+            }
+            Err(_) => {
+                error!("Intro synthesis of program '{}' panicked", name);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn program(name: &str, intro: &str) -> ScheduleProgram {
+        toml::from_str(&format!(
+            "name = \"{}\"\nactive = true\ncron = \"0 0 21 * * *\"\nduration = \"1h\"\nplaylist = \"show.m3u\"\nintro = {}",
+            name, intro
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn given_spoken_intro_when_program_announced_then_plays_it_at_the_start() {
+        let directory = TempDir::new().unwrap();
+        let (_tx, programs) = watch::channel(vec![
+            program(
+                "Jazz Hour",
+                "{ text = \"Up next on {station}: {program}\" }",
+            ),
+            program("News", "{ file = \"/shows/intros/news.mp3\" }"),
+        ]);
+        let mut intros = ProgramIntros::new(
+            Some(&AnnouncementsConfig {
+                command: ["sh", "-c", "cat > {output}"]
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect(),
+                every_n_tracks: 1,
+                template: None,
+                extension: Some("txt".to_string()),
+            }),
+            "Funkstrom FM",
+            directory.path().to_path_buf(),
+            programs,
+        );
+
+        assert_eq!(intros.program_started("Jazz Hour"), None);
+
+        intros.program_announced("Jazz Hour");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while intros
+            .synthesis
+            .as_ref()
+            .is_some_and(|(_, s)| !s.is_finished())
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let clip = intros.program_started("Jazz Hour").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(clip).unwrap(),
+            "Up next on Funkstrom FM: Jazz Hour"
+        );
+        assert_eq!(
+            intros.program_started("News"),
+            Some(PathBuf::from("/shows/intros/news.mp3"))
+        );
+    }
+}
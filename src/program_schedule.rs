@@ -0,0 +1,88 @@
+use crate::config::ScheduleProgram;
+use chrono::{DateTime, Local, NaiveDate};
+use cron::Schedule;
+use std::str::FromStr;
+
+/// Start times of a program: the times of its cron expression, except on its `except_dates`
+/// and only on its `only_dates` if it has any
+#[derive(Debug)]
+pub struct ProgramSchedule {
+    cron: Schedule,
+    except_dates: Vec<NaiveDate>,
+    only_dates: Vec<NaiveDate>,
+}
+
+impl ProgramSchedule {
+    /// Schedule of the program, `None` for programs that only start after another one
+    pub fn from_program(
+        program: &ScheduleProgram,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(cron) = program.cron_expression()? else {
+            return Ok(None);
+        };
+        let (except_dates, only_dates) = program.calendar_dates()?;
+
+        Ok(Some(Self {
+            cron: Schedule::from_str(&cron)
+                .map_err(|e| format!("Invalid cron expression '{}': {}", cron, e))?,
+            except_dates,
+            only_dates,
+        }))
+    }
+
+    /// Start times after `from`, ending after the last of the `only_dates`
+    pub fn after<'a>(
+        &'a self,
+        from: &DateTime<Local>,
+    ) -> impl Iterator<Item = DateTime<Local>> + 'a {
+        let last_date = self.only_dates.iter().max().copied();
+        self.cron
+            .after(from)
+            .take_while(move |start| last_date.is_none_or(|last| start.date_naive() <= last))
+            .filter(|start| self.runs_on(start.date_naive()))
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        !self.except_dates.contains(&date)
+            && (self.only_dates.is_empty() || self.only_dates.contains(&date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn weekly_show(except_dates: &str, only_dates: &str) -> ProgramSchedule {
+        let program: ScheduleProgram = toml::from_str(&format!(
+            "name = \"Weekly\"\nactive = true\ncron = \"0 0 20 * * Tue\"\nduration = \"1h\"\nplaylist = \"weekly.m3u\"\nexcept_dates = [{}]\nonly_dates = [{}]",
+            except_dates, only_dates
+        ))
+        .unwrap();
+        ProgramSchedule::from_program(&program).unwrap().unwrap()
+    }
+
+    fn starts(schedule: &ProgramSchedule) -> Vec<String> {
+        let from = Local.with_ymd_and_hms(2025, 12, 15, 0, 0, 0).unwrap();
+        schedule
+            .after(&from)
+            .take(3)
+            .map(|start| start.format("%Y-%m-%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn given_calendar_dates_when_iterating_starts_then_skips_exceptions_and_keeps_only_dates() {
+        assert_eq!(
+            starts(&weekly_show("\"2025-12-23\", \"2025-12-30\"", "")),
+            ["2025-12-16", "2026-01-06", "2026-01-13"]
+        );
+        assert_eq!(
+            starts(&weekly_show(
+                "\"2026-01-06\"",
+                "\"2026-01-06\", \"2025-12-16\""
+            )),
+            ["2025-12-16"]
+        );
+    }
+}
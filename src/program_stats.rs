@@ -0,0 +1,233 @@
+use crate::library_db::{LibraryDatabase, ProgramAudience};
+use log::{error, info};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Audience of the program on air, accumulated on every listener change
+struct RunningProgram {
+    name: String,
+    started_at: i64,
+    started: Instant,
+    last_change: Instant,
+    /// Sum of connected listeners times the seconds they were connected
+    listener_seconds: f64,
+    peak: usize,
+    joins: i64,
+    leaves: i64,
+}
+
+impl RunningProgram {
+    fn new(name: String, listeners: usize, now: Instant) -> Self {
+        Self {
+            name,
+            started_at: chrono::Local::now().timestamp(),
+            started: now,
+            last_change: now,
+            listener_seconds: 0.0,
+            peak: listeners,
+            joins: 0,
+            leaves: 0,
+        }
+    }
+
+    /// Adds the listener time since the last change
+    fn accumulate(&mut self, listeners: usize, now: Instant) {
+        let seconds = now.duration_since(self.last_change).as_secs_f64();
+        self.listener_seconds += listeners as f64 * seconds;
+        self.last_change = now;
+    }
+
+    fn audience(&self, listeners: usize, now: Instant, ended: bool) -> ProgramAudience {
+        let elapsed = now.duration_since(self.started).as_secs_f64();
+        let pending = now.duration_since(self.last_change).as_secs_f64();
+        let average_listeners = if elapsed > 0.0 {
+            (self.listener_seconds + listeners as f64 * pending) / elapsed
+        } else {
+            listeners as f64
+        };
+
+        ProgramAudience {
+            program: self.name.clone(),
+            started_at: self.started_at,
+            ended_at: ended.then(|| self.started_at + elapsed as i64),
+            average_listeners: (average_listeners * 100.0).round() / 100.0,
+            peak_listeners: self.peak as i64,
+            joins: self.joins,
+            leaves: self.leaves,
+        }
+    }
+}
+
+#[derive(Default)]
+struct AudienceState {
+    listeners: usize,
+    running: Option<RunningProgram>,
+}
+
+/// Collects the audience of each scheduled program broadcast: average and peak listeners and
+/// the listeners joining and leaving during its slot. Finished broadcasts are stored in the
+/// database so hosts can pull reports after their show.
+#[derive(Clone)]
+pub struct ProgramStats {
+    db: LibraryDatabase,
+    state: Arc<Mutex<AudienceState>>,
+}
+
+impl ProgramStats {
+    pub fn new(db: LibraryDatabase) -> Self {
+        Self {
+            db,
+            state: Arc::default(),
+        }
+    }
+
+    /// Starts collecting the audience of a program, ending the running one
+    pub fn program_started(&self, name: &str) {
+        self.start_at(name, Instant::now());
+    }
+
+    /// Stores the audience of the running program
+    pub fn program_ended(&self) {
+        self.end_at(Instant::now());
+    }
+
+    /// Counts a listener connect, `listeners` includes the new listener
+    pub fn listener_joined(&self, listeners: usize) {
+        self.listeners_changed(listeners, true, Instant::now());
+    }
+
+    /// Counts a listener disconnect, `listeners` no longer includes it
+    pub fn listener_left(&self, listeners: usize) {
+        self.listeners_changed(listeners, false, Instant::now());
+    }
+
+    /// Audience of the running program, if any, followed by the finished broadcasts, newest
+    /// first, only those of `program` if given
+    pub fn broadcasts(
+        &self,
+        program: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ProgramAudience>, Box<dyn std::error::Error + Send + Sync>> {
+        let running = {
+            let state = self.state.lock().unwrap();
+            state
+                .running
+                .as_ref()
+                .filter(|running| program.is_none_or(|p| p == running.name))
+                .map(|running| running.audience(state.listeners, Instant::now(), false))
+        };
+
+        let mut broadcasts: Vec<ProgramAudience> = running.into_iter().collect();
+        let finished = limit.saturating_sub(broadcasts.len());
+        broadcasts.extend(self.db.get_program_audiences(program, finished)?);
+        broadcasts.truncate(limit);
+        Ok(broadcasts)
+    }
+
+    fn start_at(&self, name: &str, now: Instant) {
+        self.end_at(now);
+        let mut state = self.state.lock().unwrap();
+        state.running = Some(RunningProgram::new(name.to_string(), state.listeners, now));
+    }
+
+    fn end_at(&self, now: Instant) {
+        let audience = {
+            let mut state = self.state.lock().unwrap();
+            let listeners = state.listeners;
+            match state.running.take() {
+                Some(running) => running.audience(listeners, now, true),
+                None => return,
+            }
+        };
+
+        info!(
+            "Program '{}' audience: {:.1} average, {} peak listener(s), {} joined, {} left",
+            audience.program,
+            audience.average_listeners,
+            audience.peak_listeners,
+            audience.joins,
+            audience.leaves
+        );
+        if let Err(e) = self.db.insert_program_audience(&audience) {
+            error!(
+                "Failed to store audience of program '{}': {}",
+                audience.program, e
+            );
+        }
+    }
+
+    fn listeners_changed(&self, listeners: usize, joined: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.listeners;
+        state.listeners = listeners;
+
+        if let Some(running) = state.running.as_mut() {
+            running.accumulate(previous, now);
+            running.peak = running.peak.max(listeners);
+            if joined {
+                running.joins += 1;
+            } else {
+                running.leaves += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+
+    fn program_stats() -> (ProgramStats, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        (ProgramStats::new(db), temp_file)
+    }
+
+    #[test]
+    fn given_listener_changes_during_program_when_ended_then_stores_time_weighted_audience() {
+        let (stats, _temp) = program_stats();
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        stats.listeners_changed(2, true, start);
+        stats.start_at("Morning Show", start);
+        // 2 listeners for 60s, 4 for 30s, 1 for 30s
+        stats.listeners_changed(3, true, at(60));
+        stats.listeners_changed(4, true, at(60));
+        stats.listeners_changed(1, false, at(90));
+        stats.end_at(at(120));
+
+        let broadcasts = stats.broadcasts(None, 10).unwrap();
+        assert_eq!(broadcasts.len(), 1);
+        let audience = &broadcasts[0];
+        assert_eq!(audience.program, "Morning Show");
+        assert_eq!(audience.average_listeners, 2.25);
+        assert_eq!(audience.peak_listeners, 4);
+        assert_eq!(audience.joins, 2);
+        assert_eq!(audience.leaves, 1);
+        assert_eq!(audience.ended_at, Some(audience.started_at + 120));
+    }
+
+    #[test]
+    fn given_running_program_when_listing_then_it_comes_first_without_end() {
+        let (stats, _temp) = program_stats();
+        let start = Instant::now();
+
+        stats.start_at("Morning Show", start);
+        stats.start_at("Night Shift", start + Duration::from_secs(10));
+        stats.listeners_changed(1, false, start + Duration::from_secs(20));
+
+        let broadcasts = stats.broadcasts(None, 10).unwrap();
+        assert_eq!(broadcasts.len(), 2);
+        assert_eq!(broadcasts[0].program, "Night Shift");
+        assert_eq!(broadcasts[0].ended_at, None);
+        assert_eq!(broadcasts[1].program, "Morning Show");
+
+        let morning = stats.broadcasts(Some("Morning Show"), 10).unwrap();
+        assert_eq!(morning.len(), 1);
+        assert!(stats.broadcasts(None, 1).unwrap()[0].ended_at.is_none());
+    }
+}
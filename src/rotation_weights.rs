@@ -0,0 +1,120 @@
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+/// How often a track plays per pass of the shuffled library rotation, by its star rating.
+/// Unrated tracks play once like 3 star tracks.
+pub fn rating_weight(rating: Option<u8>) -> f64 {
+    match rating {
+        Some(1) => 0.25,
+        Some(2) => 0.5,
+        Some(4) => 1.5,
+        Some(5) => 2.0,
+        _ => 1.0,
+    }
+}
+
+/// One shuffled pass of the library rotation, weighted by the track ratings.
+///
+/// Each track plays `rating_weight` times per pass, a fraction being the chance of one more
+/// play: 5 star tracks play twice, 1 star tracks in one of four passes. Plays of the same
+/// track are spread over the pass. Falls back to an unweighted shuffle if every track missed
+/// its chance.
+pub fn weighted_rotation(
+    tracks: &[PathBuf],
+    rating: impl Fn(&Path) -> Option<u8>,
+) -> VecDeque<PathBuf> {
+    weighted_rotation_with(tracks, rating, random_unit)
+}
+
+fn weighted_rotation_with(
+    tracks: &[PathBuf],
+    rating: impl Fn(&Path) -> Option<u8>,
+    mut random: impl FnMut() -> f64,
+) -> VecDeque<PathBuf> {
+    let mut plays: Vec<(f64, &PathBuf)> = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        let weight = rating_weight(rating(track));
+        let count = weight.floor() as usize + usize::from(random() < weight.fract());
+        // Play `k` falls into the k-th of `count` equal slices of the pass
+        for k in 0..count {
+            plays.push(((k as f64 + random()) / count as f64, track));
+        }
+    }
+
+    if plays.is_empty() {
+        plays = tracks.iter().map(|track| (random(), track)).collect();
+    }
+    plays.sort_by(|a, b| a.0.total_cmp(&b.0));
+    plays.into_iter().map(|(_, track)| track.clone()).collect()
+}
+
+/// Random number in `[0, 1)`, from the randomly seeded std hasher
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_rated_tracks_when_building_rotation_then_favorites_play_more_often() {
+        let tracks: Vec<PathBuf> = ["/music/best.mp3", "/music/plain.mp3", "/music/weak.mp3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let rating = |track: &Path| match track.to_str() {
+            Some("/music/best.mp3") => Some(5),
+            Some("/music/weak.mp3") => Some(1),
+            _ => None,
+        };
+
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..400 {
+            for track in weighted_rotation(&tracks, rating) {
+                *counts.entry(track).or_insert(0) += 1;
+            }
+        }
+
+        assert_eq!(counts[&tracks[0]], 800);
+        assert_eq!(counts[&tracks[1]], 400);
+        assert!((50..150).contains(&counts[&tracks[2]]));
+    }
+
+    #[test]
+    fn given_twice_played_track_when_building_rotation_then_spreads_its_plays() {
+        let tracks: Vec<PathBuf> = (0..10)
+            .map(|i| PathBuf::from(format!("/music/{}.mp3", i)))
+            .collect();
+        let rating = |track: &Path| (track == Path::new("/music/0.mp3")).then_some(5);
+
+        let rotation = weighted_rotation_with(&tracks, rating, || 0.5);
+
+        assert_eq!(rotation.len(), 11);
+        let plays: Vec<usize> = rotation
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| *track == &tracks[0])
+            .map(|(position, _)| position)
+            .collect();
+        assert_eq!(plays, vec![0, 10]);
+    }
+
+    #[test]
+    fn given_only_unlucky_weak_tracks_when_building_rotation_then_plays_them_anyway() {
+        let tracks = vec![PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")];
+
+        let rotation = weighted_rotation_with(&tracks, |_| Some(1), || 0.9);
+
+        assert_eq!(rotation.len(), 2);
+    }
+}
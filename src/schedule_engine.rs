@@ -1,11 +1,41 @@
-use crate::config::{ProgramType, ScheduleProgram};
+use crate::audio_reader::shuffle_playlist;
+use crate::config::{
+    OverlapPolicy, PodcastEpisodeSelection, ProgramEndBehavior, ProgramType, ProgramVoiceBreak,
+    ScheduleProgram,
+};
+use crate::cue_sheet;
+use crate::dayparting::{split_all, split_genres};
+use crate::library_db::LibraryDatabase;
+use crate::library_scanner::LibraryScanner;
 use crate::m3u_parser::M3uParser;
+use crate::playout_control::PlayoutControl;
+use crate::program_hooks::{ProgramEvent, ProgramHooks};
+use crate::program_schedule::ProgramSchedule;
+use crate::smart_playlist::SmartQuery;
+use crate::transitions::{Ducking, ProgramTransitions, Transition};
 use chrono::{DateTime, Duration, Local};
-use cron::Schedule;
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use log::{debug, error, info};
+use crossbeam_channel::Sender;
+use log::{debug, error, info, warn};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
 use std::path::PathBuf;
-use std::str::FromStr;
+use tokio::sync::watch;
+
+/// How long before a program start it is announced for clock alignment
+const ANNOUNCE_HORIZON_MINUTES: i64 = 60;
+const DEFAULT_END_FADE_SECONDS: f64 = 3.0;
+const DEFAULT_RETRY_DELAY_SECONDS: u64 = 30;
+const DEFAULT_MIN_TRACK_DURATION: &str = "20m";
+/// How far ahead overlapping programs are reported when the schedule is loaded
+const OVERLAP_CHECK_DAYS: i64 = 7;
+/// Occurrences of a program looked at for overlaps, bounds programs scheduled every minute
+const MAX_CHECKED_OCCURRENCES: usize = 10_000;
+/// Overlapping occurrences listed per pair of programs
+const LISTED_OVERLAPS: usize = 3;
+/// Longest time slot of a program running until the next one starts
+const MAX_UNTIL_NEXT_HOURS: i64 = 24;
+/// Metadata key of the running program, resumed after a restart
+const CURRENT_PROGRAM_KEY: &str = "current_program";
 
 #[derive(Debug, Clone)]
 pub enum PlaylistCommand {
@@ -13,38 +43,284 @@ pub enum PlaylistCommand {
         name: String,
         tracks: Vec<PathBuf>,
         duration: Duration,
+        voice_breaks: Vec<VoiceBreak>,
+        transitions: ProgramTransitions,
     },
     SwitchToLiveset {
         name: String,
         genres: Vec<String>,
         duration: Duration,
+        transitions: ProgramTransitions,
+        recovery: SourceRecovery,
+    },
+    /// Plays an episode of a podcast feed, fetched by the reader
+    SwitchToPodcast {
+        name: String,
+        feed_url: String,
+        episode: PodcastEpisodeSelection,
+        duration: Duration,
+        transitions: ProgramTransitions,
+        recovery: SourceRecovery,
+    },
+    /// Plays library tracks of the genres in place of a program whose source is unavailable
+    SwitchToGenres {
+        name: String,
+        genres: Vec<String>,
+        duration: Duration,
+        transitions: ProgramTransitions,
     },
     ReturnToLibrary,
+    /// Announces the next program start, so library rotation can align to it
+    UpcomingProgram {
+        name: String,
+        start_time: DateTime<Local>,
+    },
+    /// Plays the given items next, then continues with the current playlist
+    InsertBreak {
+        name: String,
+        items: Vec<BreakItem>,
+    },
+}
+
+/// A track played during a break, e.g. an ad creative
+#[derive(Debug, Clone)]
+pub struct BreakItem {
+    pub path: PathBuf,
+    /// URLs requested once the item is handed to playout
+    pub impression_urls: Vec<String>,
+}
+
+/// A voice break within a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceBreak {
+    pub path: PathBuf,
+    pub trigger: VoiceBreakTrigger,
+    /// Played over the next track with the music ducked, between tracks if not set
+    pub ducking: Option<Ducking>,
+}
+
+/// How a program gets on air if its source is unavailable when it starts, e.g. a missing
+/// playlist or an unreachable liveset API
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceRecovery {
+    /// Further attempts to load the source
+    pub retry_attempts: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub retry_delay: std::time::Duration,
+    /// Genres of the library tracks filling the time slot once all attempts failed, the
+    /// library rotation continues if not set
+    pub fallback_genres: Option<Vec<String>>,
+}
+
+impl SourceRecovery {
+    /// Wait before the given retry, counted from 1
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        self.retry_delay
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceBreakTrigger {
+    /// After the given number of program tracks
+    AfterTrack(u32),
+    /// At the first track change once the offset from the program start has passed
+    Offset(Duration),
 }
 
 pub struct ScheduleEngine {
     programs: Vec<ValidatedProgram>,
+    db: LibraryDatabase,
     command_tx: Sender<PlaylistCommand>,
-    command_rx: Receiver<PlaylistCommand>,
+    playout_control: PlayoutControl,
+}
+
+/// Track source of a playlist program
+#[derive(Debug)]
+enum ProgramPlaylist {
+    /// M3U file, validated when the schedule is loaded
+    File(PathBuf),
+    /// Server-managed playlist, looked up by name when the program starts
+    Stored(String),
+    /// Smart playlist, looked up by name and its query evaluated when the program starts
+    Smart { name: String, shuffle: bool },
+    /// Audio files of a directory, read when the program starts
+    Directory { path: PathBuf, shuffle: bool },
+    /// Library tracks of at least `min_duration` tagged with one of the lowercase `genres`,
+    /// any genre if empty, shuffled when the program starts
+    LongTracks {
+        genres: Vec<String>,
+        min_duration: Duration,
+    },
 }
 
 #[derive(Debug)]
 struct ValidatedProgram {
     name: String,
-    schedule: Schedule,
+    /// `None` for programs that only start after another one
+    schedule: Option<ProgramSchedule>,
+    /// Longest time slot for programs running until the next one
     duration: Duration,
+    /// Ends when the next program starts, at the latest after `duration`
+    until_next: bool,
     program_type: ProgramType,
-    playlist_path: Option<PathBuf>,
+    playlist: Option<ProgramPlaylist>,
     genres: Option<Vec<String>>,
+    /// Feed of a podcast program and the episode it plays
+    podcast: Option<(String, PodcastEpisodeSelection)>,
+    voice_breaks: Vec<VoiceBreak>,
+    transitions: ProgramTransitions,
+    /// Fade out of the playing track when the program ends, zero to cut it off and
+    /// `None` to let it finish
+    end_fade: Option<std::time::Duration>,
+    /// Priority over overlapping programs, higher wins
+    priority: i32,
+    /// Plays the program playlist to its last track after the program end instead of
+    /// returning to the library
+    finish_playlist: bool,
+    /// Program started when this program ends
+    followed_by: Option<String>,
+    hooks: ProgramHooks,
+    recovery: SourceRecovery,
+}
+
+/// A playlist program whose tracks failed to load, tried again at `at` if its time slot has
+/// not ended by then
+#[derive(Debug, PartialEq)]
+struct SourceRetry {
+    name: String,
+    end_time: DateTime<Local>,
+    /// Retries so far, counted from 1
+    attempt: u32,
+    at: DateTime<Local>,
+}
+
+/// How a program due to start gets on air
+#[derive(Debug, PartialEq)]
+enum StartDecision {
+    /// No program is running
+    Start,
+    /// The running program has a lower priority and ends
+    Preempt,
+    /// The running program has the same or a higher priority, the program starts at its end
+    Queue,
+    /// An occurrence overlapping the previous one of the same program, which keeps running
+    AlreadyRunning,
+}
+
+/// Occurrences of two different programs overlapping in time
+#[derive(Debug, PartialEq)]
+struct ProgramOverlap {
+    /// Program starting first, or defined first if both start at once
+    first: usize,
+    second: usize,
+    /// Start of the second program, within the first one
+    at: DateTime<Local>,
+}
+
+/// Checks the `followed_by` links of programs given as name and follow-up: the follow-up
+/// must be one of the programs and the chain must not lead back to the program
+pub fn follow_up_errors(links: &[(&str, Option<&str>)]) -> Vec<(String, String)> {
+    let follow_up = |name: &str| links.iter().find(|(n, _)| *n == name).map(|(_, f)| *f);
+
+    links
+        .iter()
+        .filter_map(|&(name, followed_by)| {
+            let followed_by = followed_by?;
+            if follow_up(followed_by).is_none() {
+                return Some((
+                    name.to_string(),
+                    format!("followed_by program '{}' is not scheduled", followed_by),
+                ));
+            }
+
+            let mut next = Some(followed_by);
+            for _ in 0..links.len() {
+                match next {
+                    Some(next_name) if next_name == name => {
+                        return Some((
+                            name.to_string(),
+                            "followed_by chain leads back to the program".to_string(),
+                        ));
+                    }
+                    Some(next_name) => next = follow_up(next_name).flatten(),
+                    None => break,
+                }
+            }
+            None
+        })
+        .collect()
+}
+
+/// Computed occurrences of a schedule and the programs that will not run
+#[derive(Debug, Default)]
+pub struct SchedulePreview {
+    pub occurrences: Vec<PreviewOccurrence>,
+    pub inactive: Vec<String>,
+    /// Programs failing validation with the reason
+    pub invalid: Vec<(String, String)>,
+    /// Programs that never start with the reason
+    pub unreachable: Vec<(String, String)>,
+    /// Programs without an occurrence in the previewed time range, with their next start
+    /// if they have a cron expression
+    pub not_in_range: Vec<(String, Option<DateTime<Local>>)>,
+}
+
+/// Occurrence of a program in a schedule preview
+#[derive(Debug, PartialEq)]
+pub struct PreviewOccurrence {
+    pub program: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub priority: i32,
+    /// Program this occurrence follows via `followed_by`, `None` for starts by cron
+    pub after: Option<String>,
+    /// Programs running at the start of this occurrence, and whether this occurrence
+    /// preempts them instead of waiting for them to end
+    pub overlaps: Vec<(String, bool)>,
 }
 
 impl ScheduleEngine {
+    /// Fails with `OverlapPolicy::Error` if programs overlap in the coming days
     pub fn new(
         programs: Vec<ScheduleProgram>,
+        on_overlap: OverlapPolicy,
+        db: LibraryDatabase,
+        command_tx: Sender<PlaylistCommand>,
+        playout_control: PlayoutControl,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let (command_tx, command_rx) = unbounded();
+        let programs = Self::validate_programs(programs);
+        let overlaps = Self::describe_overlaps(&programs, Local::now());
+        if on_overlap == OverlapPolicy::Error && !overlaps.is_empty() {
+            return Err(
+                format!("Schedule has overlapping programs: {}", overlaps.join("; ")).into(),
+            );
+        }
+
+        if programs.is_empty() {
+            info!("No active programs found, running in library-only mode");
+        } else {
+            info!(
+                "Schedule engine initialized with {} active program(s)",
+                programs.len()
+            );
+        }
+
+        for overlap in overlaps {
+            warn!("{}", overlap);
+        }
+
+        Ok(Self {
+            programs,
+            db,
+            command_tx,
+            playout_control,
+        })
+    }
 
-        let validated_programs = programs
+    /// The active programs, invalid programs are skipped
+    fn validate_programs(programs: Vec<ScheduleProgram>) -> Vec<ValidatedProgram> {
+        let mut programs: Vec<_> = programs
             .into_iter()
             .filter(|p| p.active)
             .filter_map(|program| match Self::validate_and_convert(&program) {
@@ -54,22 +330,310 @@ impl ScheduleEngine {
                     None
                 }
             })
-            .collect::<Vec<_>>();
+            .collect();
 
-        if validated_programs.is_empty() {
-            return Err("No active and valid programs found for scheduling".into());
+        for (name, e) in Self::link_follow_ups(&mut programs) {
+            warn!(
+                "Program '{}' is not followed by another program: {}",
+                name, e
+            );
         }
+        for program in &programs {
+            if program.schedule.is_none() && !Self::is_follow_up(&programs, &program.name) {
+                warn!(
+                    "Program '{}' has no cron expression and no program is followed by it, it never starts",
+                    program.name
+                );
+            }
+        }
+        programs
+    }
+
+    /// Drops the follow-ups that are not among the programs or lead back to the program,
+    /// returns the affected programs with the reason
+    fn link_follow_ups(programs: &mut [ValidatedProgram]) -> Vec<(String, String)> {
+        let links: Vec<(&str, Option<&str>)> = programs
+            .iter()
+            .map(|p| (p.name.as_str(), p.followed_by.as_deref()))
+            .collect();
+        let errors = follow_up_errors(&links);
+
+        for program in programs.iter_mut() {
+            if errors.iter().any(|(name, _)| *name == program.name) {
+                program.followed_by = None;
+            }
+        }
+        errors
+    }
 
+    fn is_follow_up(programs: &[ValidatedProgram], name: &str) -> bool {
+        programs
+            .iter()
+            .any(|p| p.followed_by.as_deref() == Some(name))
+    }
+
+    /// Replaces the programs with those of a reloaded schedule. New occurrences follow the
+    /// new programs; a running program that is no longer scheduled ends, otherwise it keeps
+    /// its current time slot.
+    fn replace_programs(
+        &mut self,
+        programs: Vec<ScheduleProgram>,
+        current_program: &mut Option<(String, DateTime<Local>)>,
+        queued: &mut Vec<(String, DateTime<Local>)>,
+    ) {
+        let programs = Self::validate_programs(programs);
         info!(
-            "Schedule engine initialized with {} active program(s)",
-            validated_programs.len()
+            "Schedule reloaded with {} active program(s)",
+            programs.len()
         );
 
-        Ok(Self {
-            programs: validated_programs,
-            command_tx,
-            command_rx,
-        })
+        if let Some((name, _)) = current_program.clone() {
+            if !programs.iter().any(|p| p.name == name) {
+                info!(
+                    "Program '{}' removed from the schedule, returning to library",
+                    name
+                );
+                self.program_ended(&name);
+                if let Err(e) = self.command_tx.send(PlaylistCommand::ReturnToLibrary) {
+                    error!("Failed to send return to library command: {}", e);
+                }
+                *current_program = None;
+            }
+        }
+        queued.retain(|(name, _)| programs.iter().any(|p| p.name == *name));
+
+        for overlap in Self::describe_overlaps(&programs, Local::now()) {
+            warn!("{}", overlap);
+        }
+        self.programs = programs;
+    }
+
+    /// Describes the overlaps of the active, valid programs in the coming days, for checks
+    /// of a schedule before it is applied
+    pub fn overlaps(programs: &[ScheduleProgram], from: DateTime<Local>) -> Vec<String> {
+        let validated: Vec<_> = programs
+            .iter()
+            .filter(|p| p.active)
+            .filter_map(|p| Self::validate_and_convert(p).ok())
+            .collect();
+        Self::describe_overlaps(&validated, from)
+    }
+
+    /// Lists the programs overlapping in the coming days and which of them wins
+    fn describe_overlaps(programs: &[ValidatedProgram], from: DateTime<Local>) -> Vec<String> {
+        let until = from + Duration::days(OVERLAP_CHECK_DAYS);
+        let mut pairs: BTreeMap<(usize, usize), Vec<DateTime<Local>>> = BTreeMap::new();
+        for overlap in Self::find_overlaps(programs, from, until) {
+            pairs
+                .entry((overlap.first, overlap.second))
+                .or_default()
+                .push(overlap.at);
+        }
+
+        let mut described = Vec::new();
+        for ((first, second), times) in pairs {
+            let (first, second) = (&programs[first], &programs[second]);
+            let listed: Vec<String> = times
+                .iter()
+                .take(LISTED_OVERLAPS)
+                .map(|t| t.format("%a %Y-%m-%d %H:%M").to_string())
+                .collect();
+            let more = if times.len() > LISTED_OVERLAPS {
+                ", ..."
+            } else {
+                ""
+            };
+            let resolution = match first.priority.cmp(&second.priority) {
+                std::cmp::Ordering::Equal => format!(
+                    "equal priority {}, the later one waits for the earlier one to end",
+                    first.priority
+                ),
+                _ => {
+                    let (high, low) = if first.priority > second.priority {
+                        (first, second)
+                    } else {
+                        (second, first)
+                    };
+                    format!(
+                        "'{}' (priority {}) takes precedence over '{}' (priority {})",
+                        high.name, high.priority, low.name, low.priority
+                    )
+                }
+            };
+            described.push(format!(
+                "Programs '{}' and '{}' overlap {} time(s) in the next {} days, at {}{}: {}",
+                first.name,
+                second.name,
+                times.len(),
+                OVERLAP_CHECK_DAYS,
+                listed.join(", "),
+                more,
+                resolution
+            ));
+        }
+        described
+    }
+
+    /// Occurrences between `from` and `until` starting while another program runs
+    fn find_overlaps(
+        programs: &[ValidatedProgram],
+        from: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Vec<ProgramOverlap> {
+        let mut occurrences: Vec<(DateTime<Local>, DateTime<Local>, usize)> = programs
+            .iter()
+            .enumerate()
+            .flat_map(|(index, program)| {
+                program
+                    .schedule
+                    .iter()
+                    .flat_map(|schedule| schedule.after(&from))
+                    .take_while(|start| *start < until)
+                    .take(MAX_CHECKED_OCCURRENCES)
+                    .map(move |start| {
+                        (start, Self::occurrence_end(programs, program, start), index)
+                    })
+            })
+            .collect();
+        occurrences.sort();
+
+        let mut overlaps = Vec::new();
+        let mut running: Vec<(DateTime<Local>, usize)> = Vec::new();
+        for (start, end, index) in occurrences {
+            running.retain(|(running_end, _)| *running_end > start);
+            overlaps.extend(running.iter().filter(|(_, other)| *other != index).map(
+                |&(_, other)| ProgramOverlap {
+                    first: other,
+                    second: index,
+                    at: start,
+                },
+            ));
+            running.push((end, index));
+        }
+        overlaps
+    }
+
+    /// Validates the programs and computes their occurrences between `from` and `until`
+    /// without running them
+    pub fn preview(
+        programs: &[ScheduleProgram],
+        from: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> SchedulePreview {
+        let mut preview = SchedulePreview::default();
+        let mut validated = Vec::new();
+        for program in programs {
+            if !program.active {
+                preview.inactive.push(program.name.clone());
+                continue;
+            }
+            match Self::validate_and_convert(program) {
+                Ok(program) => validated.push(program),
+                Err(e) => preview.invalid.push((program.name.clone(), e.to_string())),
+            }
+        }
+        preview
+            .invalid
+            .extend(Self::link_follow_ups(&mut validated));
+
+        for program in &validated {
+            preview.occurrences.extend(
+                program
+                    .schedule
+                    .iter()
+                    .flat_map(|schedule| schedule.after(&from))
+                    .take_while(|start| *start < until)
+                    .take(MAX_CHECKED_OCCURRENCES)
+                    .map(|start| PreviewOccurrence {
+                        program: program.name.clone(),
+                        start,
+                        end: Self::occurrence_end(&validated, program, start),
+                        priority: program.priority,
+                        after: None,
+                        overlaps: Vec::new(),
+                    }),
+            );
+        }
+        let mut follow_ups = Vec::new();
+        for occurrence in &preview.occurrences {
+            let mut previous = occurrence;
+            for _ in 0..validated.len() {
+                let Some(next) = validated
+                    .iter()
+                    .find(|p| p.name == previous.program)
+                    .and_then(|p| p.followed_by.as_deref())
+                    .and_then(|name| validated.iter().find(|p| p.name == name))
+                else {
+                    break;
+                };
+                if previous.end >= until {
+                    break;
+                }
+                follow_ups.push(PreviewOccurrence {
+                    program: next.name.clone(),
+                    start: previous.end,
+                    end: Self::occurrence_end(&validated, next, previous.end),
+                    priority: next.priority,
+                    after: Some(previous.program.clone()),
+                    overlaps: Vec::new(),
+                });
+                previous = follow_ups.last().expect("just pushed");
+            }
+        }
+        preview.occurrences.extend(follow_ups);
+        preview
+            .occurrences
+            .sort_by_key(|o| (o.start, Reverse(o.priority)));
+
+        for program in &validated {
+            if preview
+                .occurrences
+                .iter()
+                .any(|o| o.program == program.name)
+            {
+                continue;
+            }
+            let next = program
+                .schedule
+                .as_ref()
+                .and_then(|schedule| schedule.after(&from).next());
+            if next.is_some() || Self::is_follow_up(&validated, &program.name) {
+                preview.not_in_range.push((program.name.clone(), next));
+            } else if program.schedule.is_some() {
+                preview.unreachable.push((
+                    program.name.clone(),
+                    "cron expression never matches".to_string(),
+                ));
+            } else {
+                preview.unreachable.push((
+                    program.name.clone(),
+                    "no cron expression and no program is followed by it".to_string(),
+                ));
+            }
+        }
+
+        for overlap in Self::find_overlaps(&validated, from, until) {
+            let (first, second) = (&validated[overlap.first], &validated[overlap.second]);
+            let decision = Self::decide_start(second, Some(first));
+            if let Some(occurrence) = preview
+                .occurrences
+                .iter_mut()
+                .find(|o| o.program == second.name && o.start == overlap.at)
+            {
+                occurrence
+                    .overlaps
+                    .push((first.name.clone(), decision == StartDecision::Preempt));
+            }
+        }
+
+        preview
+    }
+
+    /// Validates a program the same way the engine does when scheduling it
+    pub fn validate_program(
+        program: &ScheduleProgram,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Self::validate_and_convert(program).map(|_| ())
     }
 
     fn validate_and_convert(
@@ -80,15 +644,41 @@ impl ScheduleEngine {
             .validate()
             .map_err(|e| format!("Program '{}': {}", program.name, e))?;
 
-        let schedule = Schedule::from_str(&program.cron)
-            .map_err(|e| format!("Invalid cron expression '{}': {}", program.cron, e))?;
+        let schedule = ProgramSchedule::from_program(program)?;
 
-        let duration = Self::parse_duration(&program.duration)?;
+        let until_next = program.runs_until_next();
+        let duration = if until_next {
+            Duration::hours(MAX_UNTIL_NEXT_HOURS)
+        } else {
+            Self::parse_duration(&program.duration)?
+        };
 
         let program_type = program.get_type();
 
-        let playlist_path = match program_type {
-            ProgramType::Playlist => {
+        let playlist = match (
+            &program_type,
+            &program.stored_playlist,
+            &program.smart_playlist,
+            &program.source_dir,
+        ) {
+            (ProgramType::Playlist, Some(name), _, _) => {
+                Some(ProgramPlaylist::Stored(name.clone()))
+            }
+            (ProgramType::Playlist, None, Some(name), _) => Some(ProgramPlaylist::Smart {
+                name: name.clone(),
+                shuffle: program.shuffle.unwrap_or(false),
+            }),
+            (ProgramType::Playlist, None, None, Some(directory)) => {
+                let path = PathBuf::from(directory);
+                if !path.is_dir() {
+                    return Err(format!("Source directory not found: {}", directory).into());
+                }
+                Some(ProgramPlaylist::Directory {
+                    path,
+                    shuffle: program.shuffle.unwrap_or(false),
+                })
+            }
+            (ProgramType::Playlist, None, None, None) => {
                 let path = PathBuf::from(
                     program
                         .playlist
@@ -96,9 +686,28 @@ impl ScheduleEngine {
                         .expect("Playlist path should exist after validation"),
                 );
                 M3uParser::validate_playlist(&path)?;
-                Some(path)
+                Some(ProgramPlaylist::File(path))
             }
-            ProgramType::Liveset => None,
+            (ProgramType::LocalLiveset, _, _, _) => {
+                let min_duration = program
+                    .min_track_duration
+                    .as_deref()
+                    .unwrap_or(DEFAULT_MIN_TRACK_DURATION);
+                let min_duration = Self::parse_duration(min_duration)?;
+                if min_duration <= Duration::zero() {
+                    return Err("min_track_duration must be positive".into());
+                }
+                Some(ProgramPlaylist::LongTracks {
+                    genres: split_all(
+                        program
+                            .genres
+                            .as_ref()
+                            .expect("Genres should exist after validation"),
+                    ),
+                    min_duration,
+                })
+            }
+            (ProgramType::Liveset | ProgramType::Podcast, _, _, _) => None,
         };
 
         let genres = match program_type {
@@ -108,20 +717,101 @@ impl ScheduleEngine {
                     .clone()
                     .expect("Genres should exist after validation"),
             ),
-            ProgramType::Playlist => None,
+            ProgramType::Playlist | ProgramType::LocalLiveset | ProgramType::Podcast => None,
         };
 
+        let podcast = program.feed_url.clone().map(|feed_url| {
+            (
+                feed_url,
+                program.episode.unwrap_or(PodcastEpisodeSelection::Newest),
+            )
+        });
+
+        let voice_breaks = program
+            .voice_breaks
+            .iter()
+            .flatten()
+            .map(Self::validate_voice_break)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(file) = program.intro.as_ref().and_then(|i| i.file.as_ref()) {
+            if !std::path::Path::new(file).is_file() {
+                return Err(format!("Intro file not found: {}", file).into());
+            }
+        }
+
         Ok(ValidatedProgram {
             name: program.name.clone(),
             schedule,
             duration,
+            until_next,
             program_type,
-            playlist_path,
+            playlist,
             genres,
+            podcast,
+            voice_breaks,
+            transitions: ProgramTransitions {
+                tracks: program.transition.as_ref().map(Transition::from_config),
+                fade_in: program.fade_in_seconds.map(Self::program_fade),
+                fade_out: program.fade_out_seconds.map(Self::program_fade),
+            },
+            end_fade: match program.end_behavior {
+                Some(ProgramEndBehavior::Fade) => Some(std::time::Duration::from_secs_f64(
+                    program.end_fade_seconds.unwrap_or(DEFAULT_END_FADE_SECONDS),
+                )),
+                Some(ProgramEndBehavior::Cut) => Some(std::time::Duration::ZERO),
+                Some(ProgramEndBehavior::FinishTrack | ProgramEndBehavior::FinishPlaylist)
+                | None => None,
+            },
+            priority: program.priority.unwrap_or(0),
+            finish_playlist: program.end_behavior == Some(ProgramEndBehavior::FinishPlaylist),
+            followed_by: program.followed_by.clone(),
+            hooks: ProgramHooks {
+                on_start: program.on_start_cmd.clone(),
+                on_end: program.on_end_cmd.clone(),
+            },
+            recovery: SourceRecovery {
+                retry_attempts: program.retry_attempts.unwrap_or(0),
+                retry_delay: std::time::Duration::from_secs(
+                    program
+                        .retry_delay_seconds
+                        .unwrap_or(DEFAULT_RETRY_DELAY_SECONDS),
+                ),
+                fallback_genres: program.fallback_genres(),
+            },
         })
     }
 
-    fn parse_duration(
+    fn program_fade(seconds: f64) -> Transition {
+        Transition::crossfade(std::time::Duration::from_secs_f64(seconds))
+    }
+
+    fn validate_voice_break(
+        voice_break: &ProgramVoiceBreak,
+    ) -> Result<VoiceBreak, Box<dyn std::error::Error + Send + Sync>> {
+        let path = PathBuf::from(&voice_break.file);
+        if !path.is_file() {
+            return Err(format!("Voice break file not found: {}", voice_break.file).into());
+        }
+
+        let trigger = match (voice_break.after_track, &voice_break.offset) {
+            (Some(tracks), None) => VoiceBreakTrigger::AfterTrack(tracks),
+            (None, Some(offset)) => VoiceBreakTrigger::Offset(Self::parse_duration(offset)?),
+            _ => unreachable!("Exactly one trigger should exist after validation"),
+        };
+
+        let ducking = voice_break
+            .duck_db
+            .map(|duck_db| Ducking::new(duck_db, voice_break.duck_fade_seconds));
+
+        Ok(VoiceBreak {
+            path,
+            trigger,
+            ducking,
+        })
+    }
+
+    pub fn parse_duration(
         duration_str: &str,
     ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
         let duration_str = duration_str.trim();
@@ -147,63 +837,390 @@ impl ScheduleEngine {
         .into())
     }
 
-    pub fn get_command_receiver(&self) -> Receiver<PlaylistCommand> {
-        self.command_rx.clone()
-    }
-
-    pub fn start(self) {
+    /// Runs the schedule, programs are replaced whenever `updates` receives a new schedule
+    pub fn start(mut self, mut updates: watch::Receiver<Vec<ScheduleProgram>>) {
         tokio::spawn(async move {
             info!("Schedule engine started");
             let mut current_program: Option<(String, DateTime<Local>)> = None;
+            let mut announced_program: Option<(String, DateTime<Local>)> = None;
+            // Programs waiting for a running program of the same or a higher priority to end
+            let mut queued: Vec<(String, DateTime<Local>)> = Vec::new();
+            // Playlist programs tried again after their tracks failed to load
+            let mut retries: Vec<SourceRetry> = Vec::new();
+            // Program starts up to this time are handled, with a tolerance for a start
+            // exactly at engine start
+            let mut checked_until = Local::now() - Duration::seconds(2);
+
+            if let Some((program, end_time)) = self.stored_program(&checked_until) {
+                info!(
+                    "Resuming program '{}' running before the restart until {}",
+                    program.name,
+                    end_time.format("%H:%M:%S")
+                );
+                self.handle_due_program(
+                    program,
+                    end_time,
+                    &mut current_program,
+                    &mut queued,
+                    &mut retries,
+                );
+            }
+            // Programs whose time slot began before the engine started, e.g. after a restart
+            for (program, start_time) in self.in_progress_programs(&checked_until) {
+                info!(
+                    "Program '{}' started at {} and is still in progress, catching up",
+                    program.name,
+                    start_time.format("%H:%M:%S")
+                );
+                self.handle_due_program(
+                    program,
+                    self.end_time(program, start_time),
+                    &mut current_program,
+                    &mut queued,
+                    &mut retries,
+                );
+            }
+            // Running program as stored in the database
+            self.store_current_program(current_program.as_ref());
+            let mut stored_program = current_program.clone();
 
             loop {
                 let now = Local::now();
                 debug!("Schedule check at {}", now.format("%H:%M:%S"));
 
-                // Calculate how long to sleep
-                let sleep_duration = if let Some((ref program_name, end_time)) = current_program {
-                    // A program is running, check if it should end
+                if let Some((program_name, end_time)) = current_program.clone() {
                     if now >= end_time {
-                        info!("Program '{}' ended, returning to library", program_name);
-                        if let Err(e) = self.command_tx.send(PlaylistCommand::ReturnToLibrary) {
-                            error!("Failed to send return to library command: {}", e);
+                        queued.retain(|(_, queued_end)| *queued_end > now);
+                        if let Some(next) = self
+                            .program(&program_name)
+                            .and_then(|p| p.followed_by.as_deref())
+                            .and_then(|name| self.program(name))
+                        {
+                            info!("Program '{}' is followed by '{}'", program_name, next.name);
+                            // Starts before waiting programs of the same priority
+                            queued.insert(0, (next.name.clone(), self.end_time(next, end_time)));
                         }
-                        current_program = None;
-                        std::time::Duration::from_secs(1) // Check again soon
-                    } else {
-                        // Sleep until the program ends (or check every 5 seconds, whichever is sooner)
-                        let time_until_end = (end_time - now).num_seconds().max(0) as u64;
-                        std::time::Duration::from_secs(time_until_end.min(5))
-                    }
-                } else {
-                    // No program running, check for next scheduled program
-                    if let Some((program, start_time)) = self.find_next_program(&now) {
-                        // Allow a tolerance window: start if scheduled time is in the past but within last 2 seconds
-                        let tolerance = Duration::seconds(2);
-                        let earliest_start = now - tolerance;
-
-                        if start_time >= earliest_start && start_time <= now {
-                            // Start this program now
-                            self.start_program(program, &now, &mut current_program);
-                            std::time::Duration::from_secs(1) // Check again soon
+                        let finish_playlist = self
+                            .program(&program_name)
+                            .is_some_and(|p| p.finish_playlist);
+                        if !queued.is_empty() {
+                            info!("Program '{}' ended", program_name);
+                        } else if finish_playlist {
+                            // The reader returns to the library after the last playlist track
+                            info!("Program '{}' ended, finishing its playlist", program_name);
                         } else {
-                            // Calculate time until next program (or check every 30 seconds, whichever is sooner)
-                            let time_until_start = (start_time - now).num_seconds().max(1) as u64; // Minimum 1 second
-                            debug!(
-                                "Next program '{}' starts in {} seconds",
-                                program.name, time_until_start
-                            );
-                            std::time::Duration::from_secs(time_until_start.min(30))
+                            info!("Program '{}' ended, returning to library", program_name);
+                            if let Err(e) = self.command_tx.send(PlaylistCommand::ReturnToLibrary) {
+                                error!("Failed to send return to library command: {}", e);
+                            }
                         }
-                    } else {
-                        // No programs scheduled, check again in 30 seconds
-                        std::time::Duration::from_secs(30)
+                        self.program_ended(&program_name);
+                        current_program = None;
                     }
+                }
+
+                for (program, start_time) in self.due_programs(&checked_until, &now) {
+                    self.handle_due_program(
+                        program,
+                        self.end_time(program, start_time),
+                        &mut current_program,
+                        &mut queued,
+                        &mut retries,
+                    );
+                }
+                checked_until = now;
+                self.retry_due_programs(&now, &mut current_program, &mut queued, &mut retries);
+
+                if current_program.is_none() {
+                    self.start_queued_program(
+                        &now,
+                        &mut current_program,
+                        &mut queued,
+                        &mut retries,
+                    );
+                }
+
+                if current_program != stored_program {
+                    self.store_current_program(current_program.as_ref());
+                    stored_program = current_program.clone();
+                }
+
+                // Sleep until the next program starts or the running one ends, but check
+                // every 5 seconds while a program runs and every 30 seconds otherwise
+                let next_program = self.find_next_program(&now);
+                let mut sleep_seconds = match &current_program {
+                    Some((_, end_time)) => (*end_time - now).num_seconds().clamp(0, 5) as u64,
+                    None => 30,
                 };
+                if let Some(retry_at) = retries.iter().map(|retry| retry.at).min() {
+                    sleep_seconds = sleep_seconds.min((retry_at - now).num_seconds().max(0) as u64);
+                }
+                if let Some((program, start_time)) = next_program {
+                    if current_program.is_none() {
+                        self.announce_program(program, start_time, &now, &mut announced_program);
+                    }
+                    // Minimum 1 second
+                    let time_until_start = (start_time - now).num_seconds().max(1) as u64;
+                    debug!(
+                        "Next program '{}' starts in {} seconds",
+                        program.name, time_until_start
+                    );
+                    sleep_seconds = sleep_seconds.min(time_until_start);
+                }
+
+                let sleep =
+                    tokio::time::sleep(std::time::Duration::from_secs(sleep_seconds.max(1)));
+                tokio::select! {
+                    _ = sleep => {}
+                    Ok(()) = updates.changed() => {
+                        let programs = updates.borrow_and_update().clone();
+                        self.replace_programs(programs, &mut current_program, &mut queued);
+                        retries.retain(|retry| self.program(&retry.name).is_some());
+                    }
+                }
+            }
+        });
+    }
+
+    /// Programs with a start after `since` up to `now`, the highest priority first and
+    /// programs of equal priority in the order of the configuration
+    fn due_programs(
+        &self,
+        since: &DateTime<Local>,
+        now: &DateTime<Local>,
+    ) -> Vec<(&ValidatedProgram, DateTime<Local>)> {
+        let mut due: Vec<_> = self
+            .programs
+            .iter()
+            .filter_map(|program| {
+                let start = program.schedule.as_ref()?.after(since).next()?;
+                (start <= *now).then_some((program, start))
+            })
+            .collect();
+        due.sort_by_key(|(program, _)| Reverse(program.priority));
+        due
+    }
+
+    /// Programs whose latest start up to `now` is less than their duration ago, ordered like
+    /// [`Self::due_programs`]. Programs only started by `followed_by` are not included.
+    fn in_progress_programs(
+        &self,
+        now: &DateTime<Local>,
+    ) -> Vec<(&ValidatedProgram, DateTime<Local>)> {
+        let mut in_progress: Vec<_> = self
+            .programs
+            .iter()
+            .filter_map(|program| {
+                let start = program
+                    .schedule
+                    .as_ref()?
+                    .after(&(*now - program.duration))
+                    .take_while(|start| start <= now)
+                    .last()?;
+                (self.end_time(program, start) > *now).then_some((program, start))
+            })
+            .collect();
+        in_progress.sort_by_key(|(program, _)| Reverse(program.priority));
+        in_progress
+    }
+
+    /// Program stored as running before a restart whose time slot has not ended, with its end
+    fn stored_program(
+        &self,
+        now: &DateTime<Local>,
+    ) -> Option<(&ValidatedProgram, DateTime<Local>)> {
+        let value = match self.db.get_metadata(CURRENT_PROGRAM_KEY) {
+            Ok(value) => value?,
+            Err(e) => {
+                warn!(
+                    "Failed to load the program running before the restart: {}",
+                    e
+                );
+                return None;
+            }
+        };
+        let (end_time, name) = value.split_once(' ')?;
+        let end_time = DateTime::parse_from_rfc3339(end_time)
+            .ok()?
+            .with_timezone(&Local);
+        if end_time <= *now {
+            return None;
+        }
+        let program = self.program(name);
+        if program.is_none() {
+            info!(
+                "Program '{}' running before the restart is no longer scheduled",
+                name
+            );
+        }
+        Some((program?, end_time))
+    }
+
+    /// Stores the running program, so it is resumed after a restart
+    fn store_current_program(&self, current_program: Option<&(String, DateTime<Local>)>) {
+        let result = match current_program {
+            Some((name, end_time)) => self.db.set_metadata(
+                CURRENT_PROGRAM_KEY,
+                &format!("{} {}", end_time.to_rfc3339(), name),
+            ),
+            None => self.db.delete_metadata(CURRENT_PROGRAM_KEY),
+        };
+        if let Err(e) = result {
+            warn!("Failed to store the running program: {}", e);
+        }
+    }
+
+    fn decide_start(
+        program: &ValidatedProgram,
+        running: Option<&ValidatedProgram>,
+    ) -> StartDecision {
+        match running {
+            None => StartDecision::Start,
+            Some(running) if running.name == program.name => StartDecision::AlreadyRunning,
+            Some(running) if program.priority > running.priority => StartDecision::Preempt,
+            Some(_) => StartDecision::Queue,
+        }
+    }
+
+    fn handle_due_program(
+        &self,
+        program: &ValidatedProgram,
+        end_time: DateTime<Local>,
+        current_program: &mut Option<(String, DateTime<Local>)>,
+        queued: &mut Vec<(String, DateTime<Local>)>,
+        retries: &mut Vec<SourceRetry>,
+    ) {
+        let running = current_program
+            .as_ref()
+            .and_then(|(name, _)| self.program(name));
+
+        match Self::decide_start(program, running) {
+            StartDecision::Start => self.start_program(program, end_time, current_program, retries),
+            StartDecision::Preempt => {
+                let preempted = running.expect("a program runs").name.clone();
+                info!(
+                    "Program '{}' (priority {}) preempts '{}'",
+                    program.name, program.priority, preempted
+                );
+                self.start_program(program, end_time, current_program, retries);
+                if current_program.as_ref().map(|(name, _)| name) == Some(&program.name) {
+                    self.program_ended(&preempted);
+                }
+            }
+            StartDecision::Queue => {
+                let running = running.expect("a program runs");
+                info!(
+                    "Program '{}' (priority {}) waits for '{}' (priority {}) to end",
+                    program.name, program.priority, running.name, running.priority
+                );
+                queued.retain(|(name, _)| name != &program.name);
+                queued.push((program.name.clone(), end_time));
+            }
+            StartDecision::AlreadyRunning => {
+                debug!("Program '{}' is already running", program.name)
+            }
+        }
+    }
 
-                tokio::time::sleep(sleep_duration).await;
+    /// Starts the waiting program with the highest priority whose time slot has not ended
+    fn start_queued_program(
+        &self,
+        now: &DateTime<Local>,
+        current_program: &mut Option<(String, DateTime<Local>)>,
+        queued: &mut Vec<(String, DateTime<Local>)>,
+        retries: &mut Vec<SourceRetry>,
+    ) {
+        queued.retain(|(name, end_time)| {
+            let open = end_time > now;
+            if !open {
+                info!("Waiting program '{}' skipped, its time slot ended", name);
             }
+            open
         });
+
+        // The earliest queued wins among equal priorities
+        let next = queued
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (name, _))| Some((index, self.program(name)?)))
+            .max_by_key(|(index, program)| (program.priority, Reverse(*index)))
+            .map(|(index, _)| index);
+        if let Some(index) = next {
+            let (name, end_time) = queued.remove(index);
+            if let Some(program) = self.program(&name) {
+                self.start_program(program, end_time, current_program, retries);
+            }
+        }
+    }
+
+    /// Starts the playlist programs whose retry is due like a program due to start, unless
+    /// their time slot ended
+    fn retry_due_programs(
+        &self,
+        now: &DateTime<Local>,
+        current_program: &mut Option<(String, DateTime<Local>)>,
+        queued: &mut Vec<(String, DateTime<Local>)>,
+        retries: &mut Vec<SourceRetry>,
+    ) {
+        let due: Vec<(String, DateTime<Local>)> = retries
+            .iter()
+            .filter(|retry| retry.at <= *now)
+            .map(|retry| (retry.name.clone(), retry.end_time))
+            .collect();
+
+        for (name, end_time) in due {
+            match self.program(&name) {
+                Some(program) if end_time > *now => {
+                    self.handle_due_program(program, end_time, current_program, queued, retries)
+                }
+                _ => info!("Retry of program '{}' skipped, its time slot ended", name),
+            }
+        }
+        // Failed retries are due again later, the others started or wait for another program
+        retries.retain(|retry| retry.at > *now);
+    }
+
+    /// End of the program starting at `start`
+    fn end_time(&self, program: &ValidatedProgram, start: DateTime<Local>) -> DateTime<Local> {
+        Self::occurrence_end(&self.programs, program, start)
+    }
+
+    /// End of a program starting at `start`, for programs running until the next one the
+    /// next start of any of the programs
+    fn occurrence_end(
+        programs: &[ValidatedProgram],
+        program: &ValidatedProgram,
+        start: DateTime<Local>,
+    ) -> DateTime<Local> {
+        let latest = start + program.duration;
+        if !program.until_next {
+            return latest;
+        }
+        programs
+            .iter()
+            .filter_map(|p| p.schedule.as_ref()?.after(&start).next())
+            .filter(|next| *next < latest)
+            .min()
+            .unwrap_or(latest)
+    }
+
+    fn program(&self, name: &str) -> Option<&ValidatedProgram> {
+        self.programs.iter().find(|p| p.name == name)
+    }
+
+    /// Ends the playing track of a program as configured by its end behavior and runs its
+    /// end hook. The reader switches at the next track change, the track finishes if it has
+    /// no end fade.
+    fn program_ended(&self, name: &str) {
+        let Some(program) = self.program(name) else {
+            return;
+        };
+        if let Some(fade) = program.end_fade {
+            self.playout_control.end_program(name, fade);
+        }
+        program
+            .hooks
+            .run(ProgramEvent::End, name, program.duration, Local::now());
     }
 
     fn find_next_program(
@@ -223,36 +1240,71 @@ impl ScheduleEngine {
             .filter_map(|program| {
                 // Get the next occurrence after (now - tolerance)
                 // This way, if we're at 20:00:01, we check from 19:59:59 and get 20:00:00
-                let mut after_iter = program.schedule.after(&check_from);
+                let mut after_iter = program.schedule.as_ref()?.after(&check_from);
                 let next_time = after_iter.next()?;
 
                 Some((program, next_time))
             })
-            .min_by_key(|(_, next_time)| *next_time)
+            // The higher priority among programs starting at once
+            .min_by_key(|(program, next_time)| (*next_time, Reverse(program.priority)))
     }
 
-    fn start_program(
+    fn announce_program(
         &self,
         program: &ValidatedProgram,
+        start_time: DateTime<Local>,
         now: &DateTime<Local>,
+        announced_program: &mut Option<(String, DateTime<Local>)>,
+    ) {
+        let upcoming = (program.name.clone(), start_time);
+        if start_time - *now > Duration::minutes(ANNOUNCE_HORIZON_MINUTES)
+            || announced_program.as_ref() == Some(&upcoming)
+        {
+            return;
+        }
+
+        debug!(
+            "Announcing program '{}' starting at {}",
+            program.name,
+            start_time.format("%H:%M:%S")
+        );
+        if self
+            .command_tx
+            .send(PlaylistCommand::UpcomingProgram {
+                name: program.name.clone(),
+                start_time,
+            })
+            .is_ok()
+        {
+            *announced_program = Some(upcoming);
+        }
+    }
+
+    fn start_program(
+        &self,
+        program: &ValidatedProgram,
+        end_time: DateTime<Local>,
         current_program: &mut Option<(String, DateTime<Local>)>,
+        retries: &mut Vec<SourceRetry>,
     ) {
-        let end_time = *now + program.duration;
+        // Shorter than the program if it waited for another one to end
+        let duration = end_time - Local::now();
 
         match program.program_type {
-            ProgramType::Playlist => {
-                let playlist_path = program
-                    .playlist_path
+            ProgramType::Playlist | ProgramType::LocalLiveset => {
+                let playlist = program
+                    .playlist
                     .as_ref()
-                    .expect("Playlist path should exist for playlist programs");
+                    .expect("Playlist should exist for playlist programs");
 
-                match M3uParser::parse(playlist_path) {
+                match self.load_playlist(playlist) {
                     Ok(tracks) => {
+                        retries.retain(|retry| retry.name != program.name);
                         info!(
                             "Starting playlist program '{}' with {} tracks (duration: {})",
                             program.name,
                             tracks.len(),
-                            Self::format_duration(&program.duration)
+                            Self::format_duration(&duration)
                         );
 
                         if self
@@ -260,7 +1312,9 @@ impl ScheduleEngine {
                             .send(PlaylistCommand::SwitchToPlaylist {
                                 name: program.name.clone(),
                                 tracks,
-                                duration: program.duration,
+                                duration,
+                                voice_breaks: program.voice_breaks.clone(),
+                                transitions: program.transitions,
                             })
                             .is_ok()
                         {
@@ -270,45 +1324,286 @@ impl ScheduleEngine {
                         }
                     }
                     Err(e) => {
-                        error!(
-                            "Failed to load playlist for program '{}': {}",
-                            program.name, e
-                        );
+                        self.playlist_unavailable(program, end_time, &*e, current_program, retries)
                     }
                 }
             }
-            ProgramType::Liveset => {
-                let genres = program
-                    .genres
-                    .as_ref()
-                    .expect("Genres should exist for liveset programs");
+            ProgramType::Liveset => {
+                let genres = program
+                    .genres
+                    .as_ref()
+                    .expect("Genres should exist for liveset programs");
+
+                info!(
+                    "Starting liveset program '{}' (genres: {:?}, duration: {})",
+                    program.name,
+                    if genres.is_empty() {
+                        "all".to_string()
+                    } else {
+                        genres.join(", ")
+                    },
+                    Self::format_duration(&duration)
+                );
+
+                if self
+                    .command_tx
+                    .send(PlaylistCommand::SwitchToLiveset {
+                        name: program.name.clone(),
+                        genres: genres.clone(),
+                        duration,
+                        transitions: program.transitions,
+                        recovery: program.recovery.clone(),
+                    })
+                    .is_ok()
+                {
+                    *current_program = Some((program.name.clone(), end_time));
+                } else {
+                    error!("Failed to send liveset switch command");
+                }
+            }
+            ProgramType::Podcast => {
+                let (feed_url, episode) = program
+                    .podcast
+                    .clone()
+                    .expect("Feed should exist for podcast programs");
 
                 info!(
-                    "Starting liveset program '{}' (genres: {:?}, duration: {})",
+                    "Starting podcast program '{}' ({:?} episode of {}, duration: {})",
                     program.name,
-                    if genres.is_empty() {
-                        "all".to_string()
-                    } else {
-                        genres.join(", ")
-                    },
-                    Self::format_duration(&program.duration)
+                    episode,
+                    feed_url,
+                    Self::format_duration(&duration)
                 );
 
                 if self
                     .command_tx
-                    .send(PlaylistCommand::SwitchToLiveset {
+                    .send(PlaylistCommand::SwitchToPodcast {
                         name: program.name.clone(),
-                        genres: genres.clone(),
-                        duration: program.duration,
+                        feed_url,
+                        episode,
+                        duration,
+                        transitions: program.transitions,
+                        recovery: program.recovery.clone(),
                     })
                     .is_ok()
                 {
                     *current_program = Some((program.name.clone(), end_time));
                 } else {
-                    error!("Failed to send liveset switch command");
+                    error!("Failed to send podcast switch command");
                 }
             }
         }
+
+        if current_program
+            .as_ref()
+            .is_some_and(|(name, _)| *name == program.name)
+        {
+            program.hooks.run(
+                ProgramEvent::Start,
+                &program.name,
+                program.duration,
+                end_time,
+            );
+        }
+    }
+
+    /// Tries a playlist program whose tracks failed to load again later, or plays its
+    /// fallback once all attempts failed
+    fn playlist_unavailable(
+        &self,
+        program: &ValidatedProgram,
+        end_time: DateTime<Local>,
+        error: &(dyn std::error::Error + Send + Sync),
+        current_program: &mut Option<(String, DateTime<Local>)>,
+        retries: &mut Vec<SourceRetry>,
+    ) {
+        let attempt = retries
+            .iter()
+            .position(|retry| retry.name == program.name)
+            .map_or(0, |index| retries.remove(index).attempt)
+            + 1;
+
+        if attempt <= program.recovery.retry_attempts {
+            let delay = program.recovery.delay(attempt);
+            warn!(
+                "Failed to load playlist for program '{}': {}. Retry {} of {} in {}s",
+                program.name,
+                error,
+                attempt,
+                program.recovery.retry_attempts,
+                delay.as_secs()
+            );
+            retries.push(SourceRetry {
+                name: program.name.clone(),
+                end_time,
+                attempt,
+                at: Local::now() + Duration::from_std(delay).unwrap_or(Duration::MAX),
+            });
+            return;
+        }
+
+        let Some(genres) = program.recovery.fallback_genres.clone() else {
+            error!(
+                "Failed to load playlist for program '{}': {}. Continuing with library.",
+                program.name, error
+            );
+            return;
+        };
+
+        error!(
+            "Failed to load playlist for program '{}': {}. Playing library tracks of {} instead.",
+            program.name,
+            error,
+            genres.join(", ")
+        );
+        if self
+            .command_tx
+            .send(PlaylistCommand::SwitchToGenres {
+                name: program.name.clone(),
+                genres,
+                duration: end_time - Local::now(),
+                transitions: program.transitions,
+            })
+            .is_ok()
+        {
+            *current_program = Some((program.name.clone(), end_time));
+        } else {
+            error!("Failed to send genre fallback command");
+        }
+    }
+
+    fn load_playlist(
+        &self,
+        playlist: &ProgramPlaylist,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let name = match playlist {
+            ProgramPlaylist::File(path) => return M3uParser::parse(path),
+            ProgramPlaylist::Directory { path, shuffle } => {
+                return Self::read_source_dir(path, *shuffle)
+            }
+            ProgramPlaylist::Stored(name) => name,
+            ProgramPlaylist::Smart { name, shuffle } => return self.smart_tracks(name, *shuffle),
+            ProgramPlaylist::LongTracks {
+                genres,
+                min_duration,
+            } => return self.long_tracks(genres, *min_duration),
+        };
+
+        let playlist = self
+            .db
+            .get_playlist_by_name(name)?
+            .ok_or_else(|| format!("Stored playlist '{}' not found", name))?;
+
+        let tracks: Vec<PathBuf> = playlist
+            .tracks
+            .into_iter()
+            .map(|track| PathBuf::from(track.file_path))
+            .filter(|path| {
+                let exists = cue_sheet::audio_file(path).exists();
+                if !exists {
+                    warn!("Track file not found: {:?}", path);
+                }
+                exists
+            })
+            .collect();
+
+        if tracks.is_empty() {
+            return Err(format!("No valid tracks found in stored playlist '{}'", name).into());
+        }
+
+        Ok(tracks)
+    }
+
+    /// Library tracks matching the query of a smart playlist, sorted or shuffled
+    fn smart_tracks(
+        &self,
+        name: &str,
+        shuffle: bool,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let playlist = self
+            .db
+            .get_smart_playlist_by_name(name)?
+            .ok_or_else(|| format!("Smart playlist '{}' not found", name))?;
+        let query = SmartQuery::parse(&playlist.query)
+            .map_err(|e| format!("Invalid query of smart playlist '{}': {}", name, e))?;
+
+        let mut tracks: VecDeque<PathBuf> = query
+            .tracks(&self.db)?
+            .into_iter()
+            .map(|track| PathBuf::from(track.file_path))
+            .filter(|path| cue_sheet::audio_file(path).exists())
+            .collect();
+
+        if tracks.is_empty() {
+            return Err(format!("No library tracks match smart playlist '{}'", name).into());
+        }
+
+        if shuffle {
+            shuffle_playlist(&mut tracks);
+        }
+        Ok(tracks.into())
+    }
+
+    /// Shuffled library tracks of a local liveset program
+    fn long_tracks(
+        &self,
+        genres: &[String],
+        min_duration: Duration,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tracks: VecDeque<PathBuf> = self
+            .db
+            .get_tracks_longer_than(min_duration.num_seconds())?
+            .into_iter()
+            .filter(|track| {
+                genres.is_empty()
+                    || track
+                        .genre
+                        .as_deref()
+                        .is_some_and(|tag| split_genres(tag).iter().any(|g| genres.contains(g)))
+            })
+            .map(|track| PathBuf::from(track.file_path))
+            .filter(|path| cue_sheet::audio_file(path).exists())
+            .collect();
+
+        if tracks.is_empty() {
+            return Err(format!(
+                "No library tracks of at least {} found (genres: {})",
+                Self::format_duration(&min_duration),
+                if genres.is_empty() {
+                    "all".to_string()
+                } else {
+                    genres.join(", ")
+                }
+            )
+            .into());
+        }
+
+        shuffle_playlist(&mut tracks);
+        Ok(tracks.into())
+    }
+
+    /// Audio files of a program directory, sorted by name or shuffled
+    fn read_source_dir(
+        directory: &std::path::Path,
+        shuffle: bool,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tracks: Vec<PathBuf> = std::fs::read_dir(directory)
+            .map_err(|e| format!("Cannot read source directory {:?}: {}", directory, e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && LibraryScanner::is_audio_file(path))
+            .collect();
+
+        if tracks.is_empty() {
+            return Err(format!("No audio files found in source directory {:?}", directory).into());
+        }
+
+        tracks.sort();
+        if shuffle {
+            let mut shuffled = VecDeque::from(tracks);
+            shuffle_playlist(&mut shuffled);
+            tracks = shuffled.into();
+        }
+        Ok(tracks)
     }
 
     fn format_duration(duration: &Duration) -> String {
@@ -332,6 +1627,10 @@ mod tests {
     use super::*;
     use chrono::Timelike;
 
+    fn test_db() -> LibraryDatabase {
+        LibraryDatabase::new(":memory:").unwrap()
+    }
+
     #[test]
     fn given_duration_string_with_minutes_when_parsed_then_returns_correct_duration() {
         let result = ScheduleEngine::parse_duration("30m").unwrap();
@@ -407,10 +1706,37 @@ mod tests {
             name: "test".to_string(),
             active: true,
             cron: "invalid cron".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         let result = ScheduleEngine::validate_and_convert(&program);
@@ -419,16 +1745,77 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("Invalid cron"));
     }
 
+    #[test]
+    fn given_program_fades_when_validated_then_resolve_to_crossfades() {
+        let program: ScheduleProgram = toml::from_str(
+            r#"
+name = "Jazz Hour"
+active = true
+cron = "0 0 20 * * *"
+duration = "1h"
+stored_playlist = "Jazz"
+fade_in_seconds = 4.0
+fade_out_seconds = 0.0
+"#,
+        )
+        .unwrap();
+
+        let transitions = ScheduleEngine::validate_and_convert(&program)
+            .unwrap()
+            .transitions;
+
+        assert_eq!(transitions.tracks, None);
+        assert_eq!(
+            transitions.fade_in,
+            Some(Transition::crossfade(std::time::Duration::from_secs(4)))
+        );
+        let fade_out = transitions.fade_out.unwrap();
+        assert_eq!(
+            fade_out.head_bytes(crate::transitions::PcmFormat {
+                sample_rate: 44100,
+                channels: 2,
+            }),
+            0
+        );
+    }
+
     #[test]
     fn given_program_with_invalid_duration_when_validated_then_returns_error_about_duration() {
         let program = ScheduleProgram {
             name: "test".to_string(),
             active: true,
             cron: "0 0 * * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "invalid".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         let result = ScheduleEngine::validate_and_convert(&program);
@@ -440,6 +1827,43 @@ mod tests {
             .contains("Invalid duration format"));
     }
 
+    #[test]
+    fn given_voice_breaks_when_validated_then_triggers_are_resolved() {
+        let voice_file = tempfile::NamedTempFile::new().unwrap();
+        let file = voice_file.path().to_string_lossy().to_string();
+
+        let after_track = ScheduleEngine::validate_voice_break(&ProgramVoiceBreak {
+            file: file.clone(),
+            after_track: Some(3),
+            offset: None,
+            duck_db: None,
+            duck_fade_seconds: None,
+        })
+        .unwrap();
+        let offset = ScheduleEngine::validate_voice_break(&ProgramVoiceBreak {
+            file,
+            after_track: None,
+            offset: Some("15m".to_string()),
+            duck_db: None,
+            duck_fade_seconds: None,
+        })
+        .unwrap();
+        let missing = ScheduleEngine::validate_voice_break(&ProgramVoiceBreak {
+            file: "/nonexistent/voice.mp3".to_string(),
+            after_track: Some(1),
+            offset: None,
+            duck_db: None,
+            duck_fade_seconds: None,
+        });
+
+        assert_eq!(after_track.trigger, VoiceBreakTrigger::AfterTrack(3));
+        assert_eq!(
+            offset.trigger,
+            VoiceBreakTrigger::Offset(Duration::minutes(15))
+        );
+        assert!(missing.unwrap_err().to_string().contains("not found"));
+    }
+
     #[test]
     fn given_program_scheduled_at_exact_minute_when_queried_at_same_time_then_finds_program() {
         // Test that a program scheduled at exactly 20:00:00 is found when queried at 20:00:00
@@ -447,10 +1871,37 @@ mod tests {
             name: "exact_time".to_string(),
             active: true,
             cron: "0 0 20 * * *".to_string(), // Every day at 20:00:00
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "1h".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         // Create a minimal test file for validation
@@ -466,7 +1917,14 @@ mod tests {
         let mut program = program;
         program.playlist = Some(temp_file.path().to_string_lossy().to_string());
 
-        let engine = ScheduleEngine::new(vec![program]).unwrap();
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
 
         // Query at exactly 20:00:00
         let now = Local::now()
@@ -497,10 +1955,37 @@ mod tests {
             name: "tolerance_test".to_string(),
             active: true,
             cron: "0 0 20 * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         use tempfile::NamedTempFile;
@@ -515,7 +2000,14 @@ mod tests {
         let mut program = program;
         program.playlist = Some(temp_file.path().to_string_lossy().to_string());
 
-        let engine = ScheduleEngine::new(vec![program]).unwrap();
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
 
         // Query at 20:00:01 (1 second after scheduled time)
         let now = Local::now()
@@ -546,10 +2038,37 @@ mod tests {
             name: "outside_tolerance".to_string(),
             active: true,
             cron: "0 0 20 * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         use tempfile::NamedTempFile;
@@ -564,7 +2083,14 @@ mod tests {
         let mut program = program;
         program.playlist = Some(temp_file.path().to_string_lossy().to_string());
 
-        let engine = ScheduleEngine::new(vec![program]).unwrap();
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
 
         // Query at 20:00:03 (3 seconds after scheduled time, outside 2-second tolerance)
         let now = Local::now()
@@ -592,20 +2118,74 @@ mod tests {
             name: "program1".to_string(),
             active: true,
             cron: "0 0 21 * * *".to_string(), // 21:00:00
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "1h".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test1.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         let program2 = ScheduleProgram {
             name: "program2".to_string(),
             active: true,
             cron: "0 30 20 * * *".to_string(), // 20:30:00
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "30m".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test2.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         use tempfile::NamedTempFile;
@@ -630,7 +2210,14 @@ mod tests {
         program1.playlist = Some(temp_file1.path().to_string_lossy().to_string());
         program2.playlist = Some(temp_file2.path().to_string_lossy().to_string());
 
-        let engine = ScheduleEngine::new(vec![program1, program2]).unwrap();
+        let engine = ScheduleEngine::new(
+            vec![program1, program2],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
 
         // Query at 20:00:00
         let now = Local::now()
@@ -660,10 +2247,37 @@ mod tests {
             active: true,
             // Scheduled for a very specific time that's unlikely to match
             cron: "0 37 3 1 1 *".to_string(), // Jan 1st at 03:37:00
+            every: None,
+            except_dates: None,
+            only_dates: None,
             duration: "1h".to_string(),
             program_type: Some("playlist".to_string()),
             playlist: Some("test.m3u".to_string()),
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
             genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
         };
 
         use tempfile::NamedTempFile;
@@ -678,7 +2292,14 @@ mod tests {
         let mut program = program;
         program.playlist = Some(temp_file.path().to_string_lossy().to_string());
 
-        let engine = ScheduleEngine::new(vec![program]).unwrap();
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
 
         // Query at a time that doesn't match
         let now = Local::now();
@@ -693,4 +2314,761 @@ mod tests {
         assert!(scheduled_time > now);
         // Files automatically cleaned up when temp_track and temp_file drop
     }
+
+    fn liveset(name: &str, cron: &str, duration: &str, priority: Option<i32>) -> ScheduleProgram {
+        ScheduleProgram {
+            name: name.to_string(),
+            active: true,
+            cron: cron.to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: duration.to_string(),
+            program_type: Some("liveset".to_string()),
+            playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: Some(Vec::new()),
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local::now()
+            .date_naive()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+    }
+
+    #[test]
+    fn given_running_program_when_another_is_due_then_priority_decides() {
+        let program = |priority| {
+            ScheduleEngine::validate_and_convert(&liveset("news", "0 0 * * * *", "1h", priority))
+                .unwrap()
+        };
+        let running =
+            ScheduleEngine::validate_and_convert(&liveset("show", "0 0 20 * * *", "2h", Some(5)))
+                .unwrap();
+
+        assert_eq!(
+            ScheduleEngine::decide_start(&program(None), None),
+            StartDecision::Start
+        );
+        assert_eq!(
+            ScheduleEngine::decide_start(&program(Some(10)), Some(&running)),
+            StartDecision::Preempt
+        );
+        assert_eq!(
+            ScheduleEngine::decide_start(&program(Some(5)), Some(&running)),
+            StartDecision::Queue
+        );
+        assert_eq!(
+            ScheduleEngine::decide_start(&running, Some(&running)),
+            StartDecision::AlreadyRunning
+        );
+    }
+
+    #[test]
+    fn given_programs_due_at_once_when_collected_then_highest_priority_comes_first() {
+        let engine = ScheduleEngine::new(
+            vec![
+                liveset("first", "0 0 20 * * *", "1h", None),
+                liveset("urgent", "0 0 20 * * *", "1h", Some(10)),
+                liveset("second", "0 0 20 * * *", "1h", None),
+                liveset("later", "0 30 20 * * *", "1h", Some(20)),
+            ],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let due: Vec<&str> = engine
+            .due_programs(&at(19, 59), &at(20, 0))
+            .iter()
+            .map(|(program, _)| program.name.as_str())
+            .collect();
+
+        assert_eq!(due, ["urgent", "first", "second"]);
+        assert_eq!(
+            engine.find_next_program(&at(19, 0)).unwrap().0.name,
+            "urgent"
+        );
+    }
+
+    #[test]
+    fn given_engine_start_within_time_slots_when_checked_then_finds_programs_in_progress() {
+        let engine = ScheduleEngine::new(
+            vec![
+                liveset("evening", "0 0 20 * * *", "2h", None),
+                liveset("hourly", "0 0 * * * *", "1h", Some(5)),
+                liveset("ended", "0 0 18 * * *", "1h", None),
+                liveset("later", "0 30 21 * * *", "1h", None),
+            ],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let in_progress: Vec<(&str, DateTime<Local>)> = engine
+            .in_progress_programs(&at(21, 15))
+            .iter()
+            .map(|(program, start)| (program.name.as_str(), *start))
+            .collect();
+
+        assert_eq!(in_progress, [("hourly", at(21, 0)), ("evening", at(20, 0))]);
+    }
+
+    #[test]
+    fn given_until_next_program_when_started_then_ends_at_the_next_program_start() {
+        let engine = ScheduleEngine::new(
+            vec![
+                liveset("warmup", "0 0 20 * * *", "until_next", None),
+                liveset("main act", "0 30 21 * * *", "2h", None),
+                liveset("night", "0 0 0 * * *", "until_next", None),
+            ],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let warmup = engine.program("warmup").unwrap();
+        assert_eq!(engine.end_time(warmup, at(20, 0)), at(21, 30));
+        let night = engine.program("night").unwrap();
+        assert_eq!(engine.end_time(night, at(0, 0)), at(20, 0));
+        assert!(ScheduleEngine::overlaps(
+            &[
+                liveset("warmup", "0 0 20 * * *", "until_next", None),
+                liveset("main act", "0 30 21 * * *", "2h", None),
+            ],
+            at(0, 0)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn given_stored_running_program_when_restarted_then_resumes_it_until_its_end() {
+        let db_file = tempfile::NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let engine = ScheduleEngine::new(
+            vec![liveset("Night Shift", "0 0 22 * * *", "3h", None)],
+            OverlapPolicy::Warn,
+            db,
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+        let stored = |name: &str, end_time: DateTime<Local>| {
+            engine.store_current_program(Some(&(name.to_string(), end_time)));
+            engine
+                .stored_program(&at(23, 0))
+                .map(|(program, end_time)| (program.name.clone(), end_time))
+        };
+
+        assert_eq!(
+            stored("Night Shift", at(23, 30)),
+            Some(("Night Shift".to_string(), at(23, 30)))
+        );
+        assert_eq!(stored("Night Shift", at(22, 30)), None);
+        assert_eq!(stored("Removed", at(23, 30)), None);
+
+        engine.store_current_program(None);
+        assert!(engine.stored_program(&at(23, 0)).is_none());
+    }
+
+    #[test]
+    fn given_running_program_removed_when_reloaded_then_returns_to_library() {
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let mut engine = ScheduleEngine::new(
+            vec![
+                liveset("evening", "0 0 20 * * *", "2h", None),
+                liveset("news", "0 0 21 * * *", "30m", None),
+            ],
+            OverlapPolicy::Warn,
+            test_db(),
+            command_tx,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+        let mut current_program = Some(("evening".to_string(), at(22, 0)));
+        let mut queued = vec![("news".to_string(), at(21, 30))];
+
+        engine.replace_programs(
+            vec![liveset("evening", "0 0 19 * * *", "2h", None)],
+            &mut current_program,
+            &mut queued,
+        );
+        assert_eq!(current_program, Some(("evening".to_string(), at(22, 0))));
+        assert!(queued.is_empty());
+        assert!(command_rx.try_recv().is_err());
+
+        engine.replace_programs(
+            vec![liveset("news", "0 0 21 * * *", "30m", None)],
+            &mut current_program,
+            &mut queued,
+        );
+        assert_eq!(current_program, None);
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(PlaylistCommand::ReturnToLibrary)
+        ));
+        assert_eq!(engine.programs.len(), 1);
+    }
+
+    #[test]
+    fn given_schedule_when_previewed_then_lists_occurrences_overlaps_and_problems() {
+        use chrono::TimeZone;
+
+        let mut inactive = liveset("old", "0 0 8 * * *", "1h", None);
+        inactive.active = false;
+        let programs = vec![
+            liveset("evening", "0 0 20 * * *", "2h", None),
+            liveset("news", "0 0 21 * * *", "30m", Some(10)),
+            liveset("late", "0 0 21 * * *", "1h", None),
+            liveset("broken", "not a cron", "1h", None),
+            liveset("leap", "0 0 12 30 2 *", "1h", None),
+            liveset("yearly", "0 0 12 1 1 *", "1h", None),
+            inactive,
+        ];
+        let day = |hour| Local.with_ymd_and_hms(2026, 3, 2, hour, 0, 0).unwrap();
+
+        let preview = ScheduleEngine::preview(&programs, day(0), day(0) + Duration::days(1));
+
+        let timetable: Vec<_> = preview
+            .occurrences
+            .iter()
+            .map(|o| (o.program.as_str(), o.start, o.overlaps.as_slice()))
+            .collect();
+        assert_eq!(
+            timetable,
+            [
+                ("evening", day(20), &[][..]),
+                ("news", day(21), &[("evening".to_string(), true)][..]),
+                (
+                    "late",
+                    day(21),
+                    &[("evening".to_string(), false), ("news".to_string(), false)][..]
+                ),
+            ]
+        );
+        assert_eq!(preview.inactive, ["old"]);
+        assert_eq!(preview.invalid.len(), 1);
+        assert!(preview.invalid[0].1.contains("Invalid cron"));
+        assert_eq!(
+            preview.unreachable,
+            [(
+                "leap".to_string(),
+                "cron expression never matches".to_string()
+            )]
+        );
+        assert_eq!(
+            preview.not_in_range,
+            [(
+                "yearly".to_string(),
+                Some(Local.with_ymd_and_hms(2027, 1, 1, 12, 0, 0).unwrap())
+            )]
+        );
+    }
+
+    #[test]
+    fn given_program_chain_when_previewed_then_follow_ups_start_at_the_end() {
+        use chrono::TimeZone;
+
+        let followed = |program: ScheduleProgram, next: &str| ScheduleProgram {
+            followed_by: Some(next.to_string()),
+            ..program
+        };
+        let programs = vec![
+            followed(liveset("talk", "0 0 18 * * *", "1h", None), "special"),
+            followed(liveset("special", "", "90m", None), "late"),
+            liveset("late", "", "30m", None),
+            liveset("orphan", "", "30m", None),
+            followed(liveset("loop", "0 0 6 * * *", "1h", None), "loop_back"),
+            followed(liveset("loop_back", "", "1h", None), "loop"),
+        ];
+        let day = |hour, minute| Local.with_ymd_and_hms(2026, 3, 2, hour, minute, 0).unwrap();
+
+        let preview = ScheduleEngine::preview(&programs, day(0, 0), day(0, 0) + Duration::days(1));
+
+        let timetable: Vec<_> = preview
+            .occurrences
+            .iter()
+            .map(|o| (o.program.as_str(), o.start, o.after.as_deref()))
+            .collect();
+        assert_eq!(
+            timetable,
+            [
+                ("loop", day(6, 0), None),
+                ("talk", day(18, 0), None),
+                ("special", day(19, 0), Some("talk")),
+                ("late", day(20, 30), Some("special")),
+            ]
+        );
+        assert_eq!(preview.invalid.len(), 2);
+        assert!(preview.invalid[0].1.contains("leads back"));
+        assert_eq!(
+            preview.unreachable,
+            [
+                (
+                    "orphan".to_string(),
+                    "no cron expression and no program is followed by it".to_string()
+                ),
+                (
+                    "loop_back".to_string(),
+                    "no cron expression and no program is followed by it".to_string()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn given_overlapping_programs_when_checked_then_lists_overlapping_occurrences() {
+        let engine = ScheduleEngine::new(
+            vec![
+                liveset("evening", "0 0 20 * * *", "2h", None),
+                liveset("news", "0 0 21 * * *", "30m", Some(10)),
+                liveset("late", "0 0 22 * * *", "1h", None),
+                liveset("night", "0 0 3 * * *", "2h", None),
+            ],
+            OverlapPolicy::Warn,
+            test_db(),
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let overlaps =
+            ScheduleEngine::find_overlaps(&engine.programs, at(0, 0), at(0, 0) + Duration::days(1));
+
+        assert_eq!(
+            overlaps,
+            vec![ProgramOverlap {
+                first: 0,
+                second: 1,
+                at: at(21, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn given_error_policy_when_programs_overlap_then_engine_refuses_to_start() {
+        let engine = |on_overlap| {
+            ScheduleEngine::new(
+                vec![
+                    liveset("evening", "0 0 20 * * *", "2h", None),
+                    liveset("news", "0 0 21 * * *", "30m", Some(10)),
+                ],
+                on_overlap,
+                test_db(),
+                crossbeam_channel::unbounded().0,
+                PlayoutControl::new(),
+            )
+        };
+
+        let error = engine(OverlapPolicy::Error).err().unwrap().to_string();
+
+        assert!(error.contains("Programs 'evening' and 'news' overlap "));
+        assert!(
+            error.ends_with("'news' (priority 10) takes precedence over 'evening' (priority 0)")
+        );
+        assert!(engine(OverlapPolicy::Warn).is_ok());
+    }
+
+    #[test]
+    fn given_local_liveset_program_when_started_then_plays_long_library_tracks_of_its_genres() {
+        use crate::library_db::TrackRecord;
+        use tempfile::{NamedTempFile, TempDir};
+
+        let db_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let music = TempDir::new().unwrap();
+        let mix = music.path().join("mix.mp3");
+        for (file, duration_seconds, genre) in [
+            ("mix.mp3", 3600, "House; Techno"),
+            ("ambient.mp3", 3600, "Ambient"),
+            ("single.mp3", 240, "House"),
+        ] {
+            let path = music.path().join(file);
+            std::fs::write(&path, b"").unwrap();
+            db.insert_track(&TrackRecord {
+                id: None,
+                file_path: path.to_string_lossy().to_string(),
+                title: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                duration_seconds: Some(duration_seconds),
+                file_size: 0,
+                last_modified: 0,
+                file_extension: "mp3".to_string(),
+                created_at: 0,
+                updated_at: 0,
+                genre: Some(genre.to_string()),
+                artwork_path: None,
+                rating: None,
+                content_hash: None,
+                audio_fingerprint: None,
+                validation_error: None,
+                loudness_lufs: None,
+                bpm: None,
+                energy: None,
+                start_seconds: None,
+                end_seconds: None,
+                year: None,
+            })
+            .unwrap();
+        }
+        let program = |min_track_duration: &str| -> ScheduleProgram {
+            toml::from_str(&format!(
+                r#"
+name = "mixes"
+active = true
+cron = "0 0 20 * * *"
+duration = "2h"
+type = "local_liveset"
+genres = ["house"]
+min_track_duration = "{}"
+"#,
+                min_track_duration
+            ))
+            .unwrap()
+        };
+        assert!(ScheduleEngine::validate_program(&program("0m")).is_err());
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let engine = ScheduleEngine::new(
+            vec![program("30m")],
+            OverlapPolicy::Warn,
+            db,
+            command_tx,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+        let end_time = Local::now() + Duration::hours(2);
+        let mut current_program = None;
+
+        engine.start_program(
+            engine.program("mixes").unwrap(),
+            end_time,
+            &mut current_program,
+            &mut Vec::new(),
+        );
+
+        assert_eq!(current_program, Some(("mixes".to_string(), end_time)));
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(PlaylistCommand::SwitchToPlaylist { tracks, .. }) if tracks == [mix]
+        ));
+    }
+
+    #[test]
+    fn given_unavailable_playlist_when_started_then_retries_before_falling_back_to_genres() {
+        let empty = tempfile::TempDir::new().unwrap();
+        let program: ScheduleProgram = toml::from_str(&format!(
+            r#"
+name = "warmup"
+active = true
+cron = "0 0 20 * * *"
+duration = "1h"
+source_dir = "{}"
+retry_attempts = 2
+retry_delay_seconds = 10
+fallback = "genres"
+fallback_genres = ["house"]
+"#,
+            empty.path().display()
+        ))
+        .unwrap();
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            test_db(),
+            command_tx,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+        let program = engine.program("warmup").unwrap();
+        let end_time = Local::now() + Duration::hours(1);
+        let mut current_program = None;
+        let mut retries = Vec::new();
+
+        for attempt in 1..=2 {
+            engine.start_program(program, end_time, &mut current_program, &mut retries);
+            assert_eq!(current_program, None);
+            assert_eq!(retries.len(), 1);
+            assert_eq!(retries[0].attempt, attempt);
+        }
+        // The delay doubles for the second retry
+        let delay = retries[0].at - Local::now();
+        assert!(delay > Duration::seconds(15) && delay <= Duration::seconds(20));
+
+        engine.start_program(program, end_time, &mut current_program, &mut retries);
+        assert!(retries.is_empty());
+        assert_eq!(current_program, Some(("warmup".to_string(), end_time)));
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(PlaylistCommand::SwitchToGenres { genres, .. }) if genres == ["house"]
+        ));
+    }
+
+    #[test]
+    fn given_stored_playlist_program_when_loading_then_resolves_tracks_from_database() {
+        use crate::library_db::TrackRecord;
+        use tempfile::{NamedTempFile, TempDir};
+
+        let db_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let music = TempDir::new().unwrap();
+        let existing = music.path().join("a.mp3");
+        std::fs::write(&existing, b"").unwrap();
+
+        let track = |path: &std::path::Path| TrackRecord {
+            id: None,
+            file_path: path.to_string_lossy().to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration_seconds: Some(180),
+            file_size: 0,
+            last_modified: 0,
+            file_extension: "mp3".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            genre: None,
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+            year: None,
+        };
+        let first = db.insert_track(&track(&existing)).unwrap();
+        let gone = db
+            .insert_track(&track(&music.path().join("gone.mp3")))
+            .unwrap();
+        db.create_playlist("Warmup", &[gone, first]).unwrap();
+
+        let program = ScheduleProgram {
+            name: "warmup".to_string(),
+            active: true,
+            cron: "0 0 20 * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "1h".to_string(),
+            program_type: None,
+            playlist: None,
+            stored_playlist: Some("Warmup".to_string()),
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        };
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            db,
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let tracks = engine
+            .load_playlist(&ProgramPlaylist::Stored("Warmup".to_string()))
+            .unwrap();
+
+        assert_eq!(tracks, vec![existing]);
+        assert!(engine
+            .load_playlist(&ProgramPlaylist::Stored("Missing".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn given_smart_playlist_program_when_loading_then_evaluates_its_query() {
+        use crate::library_db::TrackRecord;
+        use tempfile::{NamedTempFile, TempDir};
+
+        let db_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let music = TempDir::new().unwrap();
+        let existing = music.path().join("a.mp3");
+        std::fs::write(&existing, b"").unwrap();
+
+        let track = |path: &std::path::Path, year: i32| TrackRecord {
+            id: None,
+            file_path: path.to_string_lossy().to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration_seconds: Some(180),
+            file_size: 0,
+            last_modified: 0,
+            file_extension: "mp3".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            genre: Some("Jazz".to_string()),
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+            year: Some(year),
+        };
+        db.insert_track(&track(&existing, 1995)).unwrap();
+        db.insert_track(&track(&music.path().join("old.mp3"), 1960))
+            .unwrap();
+        db.insert_track(&track(&music.path().join("gone.mp3"), 2001))
+            .unwrap();
+        db.create_smart_playlist("Modern Jazz", "genre = 'jazz' AND year >= 1990")
+            .unwrap();
+
+        let program = ScheduleProgram {
+            name: "warmup".to_string(),
+            active: true,
+            cron: "0 0 20 * * *".to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "1h".to_string(),
+            program_type: None,
+            playlist: None,
+            stored_playlist: None,
+            smart_playlist: Some("Modern Jazz".to_string()),
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: None,
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        };
+        let engine = ScheduleEngine::new(
+            vec![program],
+            OverlapPolicy::Warn,
+            db,
+            crossbeam_channel::unbounded().0,
+            PlayoutControl::new(),
+        )
+        .unwrap();
+
+        let tracks = engine
+            .load_playlist(&ProgramPlaylist::Smart {
+                name: "Modern Jazz".to_string(),
+                shuffle: false,
+            })
+            .unwrap();
+
+        assert_eq!(tracks, vec![existing]);
+        assert!(engine
+            .load_playlist(&ProgramPlaylist::Smart {
+                name: "Missing".to_string(),
+                shuffle: false,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn given_source_dir_when_read_then_plays_audio_files_sorted_by_name() {
+        use tempfile::TempDir;
+
+        let show = TempDir::new().unwrap();
+        for file in [
+            "02 second.mp3",
+            "01 first.flac",
+            "cover.jpg",
+            "03 third.ogg",
+        ] {
+            std::fs::write(show.path().join(file), b"").unwrap();
+        }
+        std::fs::create_dir(show.path().join("extras.mp3")).unwrap();
+
+        let sorted = ScheduleEngine::read_source_dir(show.path(), false).unwrap();
+        let shuffled = ScheduleEngine::read_source_dir(show.path(), true).unwrap();
+
+        let names: Vec<_> = sorted
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["01 first.flac", "02 second.mp3", "03 third.ogg"]);
+        assert_eq!(shuffled.len(), 3);
+        assert!(shuffled.iter().all(|track| sorted.contains(track)));
+        assert!(ScheduleEngine::read_source_dir(&show.path().join("missing"), false).is_err());
+    }
 }
@@ -0,0 +1,549 @@
+use crate::config::{OverlapPolicy, ScheduleProgram};
+use crate::schedule_engine::{follow_up_errors, ScheduleEngine};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+/// Location of the imported schedule, which takes precedence over `[schedule]` in the config
+pub const SCHEDULE_STORE_PATH: &str = "./data/schedule.json";
+
+const SCHEDULE_EXPORT_VERSION: u32 = 1;
+
+/// Portable JSON representation of the full schedule
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct ScheduleExport {
+    #[schema(example = 1)]
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: Option<String>,
+    #[serde(default)]
+    pub station_name: Option<String>,
+    pub programs: Vec<ScheduleProgram>,
+}
+
+/// Differences between the current schedule and an imported one, keyed by program name
+#[derive(Debug, Serialize, Default, PartialEq, ToSchema)]
+pub struct ScheduleDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+/// Holds the effective schedule programs and persists imported schedules. Changes are
+/// published to the subscribers, e.g. the schedule engine.
+#[derive(Clone)]
+pub struct ScheduleStore {
+    path: PathBuf,
+    station_name: String,
+    programs: Arc<watch::Sender<Vec<ScheduleProgram>>>,
+    on_overlap: OverlapPolicy,
+}
+
+impl ScheduleStore {
+    /// Loads the stored schedule if present, otherwise uses the programs from the config file
+    pub fn load(
+        path: &Path,
+        station_name: String,
+        config_programs: Vec<ScheduleProgram>,
+        on_overlap: OverlapPolicy,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let programs = Self::read_programs(path, config_programs)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            station_name,
+            programs: Arc::new(watch::channel(programs).0),
+            on_overlap,
+        })
+    }
+
+    /// Reads the stored schedule again, or takes the given config programs if there is none,
+    /// and publishes the programs if they changed
+    pub fn reload(
+        &self,
+        config_programs: Vec<ScheduleProgram>,
+    ) -> Result<ScheduleDiff, Box<dyn std::error::Error + Send + Sync>> {
+        let programs = Self::read_programs(&self.path, config_programs)?;
+        let diff = Self::diff(&self.programs(), &programs);
+
+        if diff.added.is_empty() && diff.changed.is_empty() && diff.removed.is_empty() {
+            info!("Schedule unchanged");
+        } else {
+            let overlaps = self.refused_overlaps(&programs);
+            if !overlaps.is_empty() {
+                return Err(format!(
+                    "Schedule not reloaded, programs overlap: {}",
+                    overlaps.join("; ")
+                )
+                .into());
+            }
+
+            info!(
+                "Reloaded schedule: +{} ~{} -{} program(s)",
+                diff.added.len(),
+                diff.changed.len(),
+                diff.removed.len()
+            );
+            self.programs.send_replace(programs);
+        }
+
+        Ok(diff)
+    }
+
+    /// How overlapping programs are handled
+    pub fn on_overlap(&self) -> OverlapPolicy {
+        self.on_overlap
+    }
+
+    /// Overlaps of the programs if they are refused by the overlap policy
+    fn refused_overlaps(&self, programs: &[ScheduleProgram]) -> Vec<String> {
+        match self.on_overlap {
+            OverlapPolicy::Warn => Vec::new(),
+            OverlapPolicy::Error => ScheduleEngine::overlaps(programs, chrono::Local::now()),
+        }
+    }
+
+    /// Receives the programs whenever the schedule changes
+    pub fn subscribe(&self) -> watch::Receiver<Vec<ScheduleProgram>> {
+        self.programs.subscribe()
+    }
+
+    fn read_programs(
+        path: &Path,
+        config_programs: Vec<ScheduleProgram>,
+    ) -> Result<Vec<ScheduleProgram>, Box<dyn std::error::Error + Send + Sync>> {
+        let programs = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let mut export: ScheduleExport = serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid schedule file {:?}: {}", path, e))?;
+            info!(
+                "Loaded {} program(s) from imported schedule {:?}",
+                export.programs.len(),
+                path
+            );
+
+            // Command hooks run on the host and are only accepted from the config file
+            for program in export.programs.iter_mut().filter(|p| has_command_hooks(p)) {
+                warn!(
+                    "Ignoring on_start_cmd/on_end_cmd of program '{}' in {:?}, command hooks can only be set in the config file",
+                    program.name, path
+                );
+                program.on_start_cmd = None;
+                program.on_end_cmd = None;
+            }
+            export.programs
+        } else {
+            config_programs
+        };
+
+        Ok(programs)
+    }
+
+    pub fn programs(&self) -> Vec<ScheduleProgram> {
+        self.programs.borrow().clone()
+    }
+
+    pub fn export(&self) -> ScheduleExport {
+        ScheduleExport {
+            version: SCHEDULE_EXPORT_VERSION,
+            exported_at: Some(chrono::Local::now().to_rfc3339()),
+            station_name: Some(self.station_name.clone()),
+            programs: self.programs(),
+        }
+    }
+
+    /// Validates an imported schedule and returns the changes it would apply
+    pub fn preview_import(&self, import: &ScheduleExport) -> Result<ScheduleDiff, Vec<String>> {
+        self.validate(import)?;
+        Ok(Self::diff(&self.programs(), &import.programs))
+    }
+
+    /// Validates, persists and activates an imported schedule
+    pub fn import(
+        &self,
+        import: &ScheduleExport,
+    ) -> Result<ScheduleDiff, Box<dyn std::error::Error + Send + Sync>> {
+        let diff = self.preview_import(import).map_err(|e| e.join("; "))?;
+
+        let mut stored = import.clone();
+        stored.exported_at = Some(chrono::Local::now().to_rfc3339());
+        fs::write(&self.path, serde_json::to_string_pretty(&stored)?)?;
+
+        self.programs.send_replace(import.programs.clone());
+
+        info!(
+            "Imported schedule: +{} ~{} -{} program(s)",
+            diff.added.len(),
+            diff.changed.len(),
+            diff.removed.len()
+        );
+
+        Ok(diff)
+    }
+
+    fn validate(&self, import: &ScheduleExport) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if import.version != SCHEDULE_EXPORT_VERSION {
+            errors.push(format!(
+                "Unsupported schedule version {}, expected {}",
+                import.version, SCHEDULE_EXPORT_VERSION
+            ));
+        }
+
+        let mut names = HashSet::new();
+        for program in &import.programs {
+            if !names.insert(program.name.as_str()) {
+                errors.push(format!("Duplicate program name '{}'", program.name));
+            }
+            if has_command_hooks(program) {
+                errors.push(format!(
+                    "Program '{}': on_start_cmd and on_end_cmd can only be set in the config file",
+                    program.name
+                ));
+            }
+
+            // Inactive programs may reference playlists that do not exist yet
+            let result = if program.active {
+                ScheduleEngine::validate_program(program).map_err(|e| e.to_string())
+            } else {
+                program.validate()
+            };
+
+            if let Err(e) = result {
+                errors.push(format!("Program '{}': {}", program.name, e));
+            }
+        }
+
+        let links: Vec<(&str, Option<&str>)> = import
+            .programs
+            .iter()
+            .map(|p| (p.name.as_str(), p.followed_by.as_deref()))
+            .collect();
+        for (name, e) in follow_up_errors(&links) {
+            errors.push(format!("Program '{}': {}", name, e));
+        }
+        errors.extend(self.refused_overlaps(&import.programs));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn diff(current: &[ScheduleProgram], incoming: &[ScheduleProgram]) -> ScheduleDiff {
+        let mut diff = ScheduleDiff::default();
+
+        for program in incoming {
+            match current.iter().find(|p| p.name == program.name) {
+                None => diff.added.push(program.name.clone()),
+                Some(existing) if existing != program => diff.changed.push(program.name.clone()),
+                Some(_) => diff.unchanged.push(program.name.clone()),
+            }
+        }
+
+        diff.removed = current
+            .iter()
+            .filter(|p| !incoming.iter().any(|i| i.name == p.name))
+            .map(|p| p.name.clone())
+            .collect();
+
+        diff
+    }
+}
+
+fn has_command_hooks(program: &ScheduleProgram) -> bool {
+    program.on_start_cmd.is_some() || program.on_end_cmd.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn liveset_program(name: &str, cron: &str) -> ScheduleProgram {
+        ScheduleProgram {
+            name: name.to_string(),
+            active: true,
+            cron: cron.to_string(),
+            every: None,
+            except_dates: None,
+            only_dates: None,
+            duration: "1h".to_string(),
+            program_type: Some("liveset".to_string()),
+            playlist: None,
+            stored_playlist: None,
+            smart_playlist: None,
+            source_dir: None,
+            shuffle: None,
+            restricted: None,
+            end_behavior: None,
+            end_fade_seconds: None,
+            fade_in_seconds: None,
+            fade_out_seconds: None,
+            priority: None,
+            followed_by: None,
+            metadata: None,
+            intro: None,
+            on_start_cmd: None,
+            on_end_cmd: None,
+            retry_attempts: None,
+            retry_delay_seconds: None,
+            fallback: None,
+            fallback_genres: None,
+            genres: Some(vec!["techno".to_string()]),
+            min_track_duration: None,
+            feed_url: None,
+            episode: None,
+            voice_breaks: None,
+            transition: None,
+        }
+    }
+
+    fn create_store(dir: &TempDir, programs: Vec<ScheduleProgram>) -> ScheduleStore {
+        ScheduleStore::load(
+            &dir.path().join("schedule.json"),
+            "Test Radio".to_string(),
+            programs,
+            OverlapPolicy::Warn,
+        )
+        .unwrap()
+    }
+
+    fn import_of(programs: Vec<ScheduleProgram>) -> ScheduleExport {
+        ScheduleExport {
+            version: SCHEDULE_EXPORT_VERSION,
+            exported_at: None,
+            station_name: None,
+            programs,
+        }
+    }
+
+    #[test]
+    fn given_config_programs_when_exported_then_contains_programs_and_metadata() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+
+        let export = store.export();
+
+        assert_eq!(export.version, SCHEDULE_EXPORT_VERSION);
+        assert_eq!(export.station_name, Some("Test Radio".to_string()));
+        assert!(export.exported_at.is_some());
+        assert_eq!(export.programs.len(), 1);
+    }
+
+    #[test]
+    fn given_modified_schedule_when_previewed_then_reports_diff_without_applying() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(
+            &dir,
+            vec![
+                liveset_program("Techno", "0 0 22 * * *"),
+                liveset_program("House", "0 0 20 * * *"),
+                liveset_program("Ambient", "0 0 8 * * *"),
+            ],
+        );
+        let import = import_of(vec![
+            liveset_program("Techno", "0 0 23 * * *"),
+            liveset_program("House", "0 0 20 * * *"),
+            liveset_program("Jungle", "0 0 18 * * *"),
+        ]);
+
+        let diff = store.preview_import(&import).unwrap();
+
+        assert_eq!(diff.added, vec!["Jungle"]);
+        assert_eq!(diff.removed, vec!["Ambient"]);
+        assert_eq!(diff.changed, vec!["Techno"]);
+        assert_eq!(diff.unchanged, vec!["House"]);
+        assert_eq!(store.programs().len(), 3);
+        assert!(!dir.path().join("schedule.json").exists());
+    }
+
+    #[test]
+    fn given_invalid_program_when_previewed_then_returns_validation_errors() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![]);
+        let import = import_of(vec![
+            liveset_program("Broken", "not a cron"),
+            liveset_program("Twice", "0 0 20 * * *"),
+            liveset_program("Twice", "0 0 21 * * *"),
+        ]);
+
+        let errors = store.preview_import(&import).unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("Invalid cron"));
+        assert!(errors[1].contains("Duplicate program name 'Twice'"));
+    }
+
+    #[test]
+    fn given_error_policy_when_overlapping_schedule_imported_or_reloaded_then_refused() {
+        let dir = TempDir::new().unwrap();
+        let store = ScheduleStore::load(
+            &dir.path().join("schedule.json"),
+            "Test Radio".to_string(),
+            vec![liveset_program("Techno", "0 0 22 * * *")],
+            OverlapPolicy::Error,
+        )
+        .unwrap();
+        let overlapping = vec![
+            liveset_program("Techno", "0 0 22 * * *"),
+            liveset_program("House", "0 30 22 * * *"),
+        ];
+
+        let errors = store
+            .preview_import(&import_of(overlapping.clone()))
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Programs 'Techno' and 'House' overlap "));
+
+        let error = store.reload(overlapping).unwrap_err().to_string();
+        assert!(error.starts_with("Schedule not reloaded, programs overlap"));
+        assert_eq!(store.programs().len(), 1);
+
+        let separate = vec![
+            liveset_program("Techno", "0 0 22 * * *"),
+            liveset_program("House", "0 0 23 * * *"),
+        ];
+        assert!(store.import(&import_of(separate)).is_ok());
+    }
+
+    #[test]
+    fn given_unknown_or_looping_follow_up_when_previewed_then_returns_validation_errors() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![]);
+        let followed = |name: &str, next: &str| ScheduleProgram {
+            followed_by: Some(next.to_string()),
+            ..liveset_program(name, "0 0 20 * * *")
+        };
+        let import = import_of(vec![
+            followed("Talk", "Special"),
+            followed("Special", "Talk"),
+            followed("News", "Weather"),
+        ]);
+
+        let errors = store.preview_import(&import).unwrap_err();
+
+        assert_eq!(
+            errors,
+            [
+                "Program 'Talk': followed_by chain leads back to the program",
+                "Program 'Special': followed_by chain leads back to the program",
+                "Program 'News': followed_by program 'Weather' is not scheduled",
+            ]
+        );
+    }
+
+    #[test]
+    fn given_valid_schedule_when_imported_then_persisted_and_loaded_on_next_start() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+        let import = import_of(vec![liveset_program("House", "0 0 20 * * *")]);
+
+        store.import(&import).unwrap();
+
+        assert_eq!(store.programs()[0].name, "House");
+        let reloaded = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+        assert_eq!(reloaded.programs().len(), 1);
+        assert_eq!(reloaded.programs()[0].name, "House");
+    }
+
+    #[test]
+    fn given_changed_config_programs_when_reloaded_then_subscribers_receive_them() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+        let mut updates = store.subscribe();
+
+        let diff = store
+            .reload(vec![liveset_program("Techno", "0 0 22 * * *")])
+            .unwrap();
+        assert_eq!(diff.unchanged, vec!["Techno"]);
+        assert!(!updates.has_changed().unwrap());
+
+        let diff = store
+            .reload(vec![
+                liveset_program("Techno", "0 0 23 * * *"),
+                liveset_program("House", "0 0 20 * * *"),
+            ])
+            .unwrap();
+        assert_eq!(diff.changed, vec!["Techno"]);
+        assert_eq!(diff.added, vec!["House"]);
+        assert!(updates.has_changed().unwrap());
+        assert_eq!(updates.borrow_and_update().len(), 2);
+    }
+
+    #[test]
+    fn given_imported_schedule_when_reloaded_then_config_programs_are_ignored() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+        store
+            .import(&import_of(vec![liveset_program("House", "0 0 20 * * *")]))
+            .unwrap();
+
+        store
+            .reload(vec![liveset_program("Jungle", "0 0 18 * * *")])
+            .unwrap();
+
+        assert_eq!(store.programs()[0].name, "House");
+    }
+
+    #[test]
+    fn given_program_with_command_hooks_when_imported_then_rejected() {
+        let dir = TempDir::new().unwrap();
+        let store = create_store(&dir, vec![liveset_program("Techno", "0 0 22 * * *")]);
+        let hooked = ScheduleProgram {
+            on_start_cmd: Some(vec!["touch".to_string(), "/tmp/pwned".to_string()]),
+            ..liveset_program("House", "0 0 20 * * *")
+        };
+
+        let errors = store
+            .preview_import(&import_of(vec![hooked.clone()]))
+            .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec!["Program 'House': on_start_cmd and on_end_cmd can only be set in the config file"]
+        );
+        assert!(store.import(&import_of(vec![hooked])).is_err());
+        assert_eq!(store.programs()[0].name, "Techno");
+    }
+
+    #[test]
+    fn given_config_program_with_command_hooks_when_exported_then_hooks_are_omitted() {
+        let dir = TempDir::new().unwrap();
+        let hooked = ScheduleProgram {
+            on_end_cmd: Some(vec!["studio-lights".to_string(), "off".to_string()]),
+            ..liveset_program("Techno", "0 0 22 * * *")
+        };
+        let store = create_store(&dir, vec![hooked]);
+
+        let json = serde_json::to_string(&store.export()).unwrap();
+
+        assert!(!json.contains("on_end_cmd"));
+        assert!(!json.contains("studio-lights"));
+    }
+
+    #[test]
+    fn given_stored_schedule_with_command_hooks_when_loaded_then_hooks_are_dropped() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("schedule.json"),
+            r#"{"version": 1, "programs": [{"name": "House", "active": true,
+                "cron": "0 0 20 * * *", "duration": "1h", "type": "liveset",
+                "genres": ["house"], "on_start_cmd": ["touch", "/tmp/pwned"]}]}"#,
+        )
+        .unwrap();
+
+        let store = create_store(&dir, Vec::new());
+
+        assert_eq!(store.programs()[0].name, "House");
+        assert_eq!(store.programs()[0].on_start_cmd, None);
+    }
+}
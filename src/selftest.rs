@@ -0,0 +1,270 @@
+use crate::audio_processor::{select_codec, FFmpegProcessor};
+use crate::config::{Config, ProgramType, ScheduleProgram};
+use crate::hearthis_client::HEARTHIS_API_BASE;
+use crate::http_client::HttpClientFactory;
+use crate::library_db::LibraryDatabase;
+use log::{error, info};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Timeout of the outbound connectivity checks
+const CONNECTIVITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a single self-test check
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SelfTestCheck {
+    /// Checked component, e.g. `database` or `codec:opus`
+    #[schema(example = "codec:mp3")]
+    pub name: String,
+    pub passed: bool,
+    #[schema(example = "libmp3lame available")]
+    pub message: String,
+}
+
+/// Result of the self-test run at startup
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SelfTestReport {
+    /// Whether all checks passed
+    pub passed: bool,
+    /// RFC 3339 time the self-test finished
+    #[schema(example = "2025-01-10T08:00:03+01:00")]
+    pub finished_at: String,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+/// Checks the configuration, FFmpeg codecs, database, music directory and the outbound
+/// connectivity of enabled integrations at startup. Failures are reported, not fatal.
+#[derive(Clone)]
+pub struct SelfTest {
+    config: Config,
+    liveset_programs: usize,
+    db: LibraryDatabase,
+    http: HttpClientFactory,
+    report: Arc<Mutex<Option<SelfTestReport>>>,
+}
+
+impl SelfTest {
+    pub fn new(
+        config: &Config,
+        programs: &[ScheduleProgram],
+        db: LibraryDatabase,
+        http: HttpClientFactory,
+    ) -> Self {
+        let liveset_programs = programs
+            .iter()
+            .filter(|p| p.active && p.get_type() == ProgramType::Liveset)
+            .count();
+
+        Self {
+            config: config.clone(),
+            liveset_programs,
+            db,
+            http,
+            report: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Runs all checks, logs a pass/fail summary and keeps the report
+    pub async fn run(&self) -> SelfTestReport {
+        let local = self.clone();
+        let mut checks = match tokio::task::spawn_blocking(move || local.local_checks()).await {
+            Ok(checks) => checks,
+            Err(e) => vec![check("self-test", Err(e.to_string()))],
+        };
+        checks.extend(self.connectivity_checks().await);
+
+        let report = SelfTestReport {
+            passed: checks.iter().all(|c| c.passed),
+            finished_at: chrono::Local::now().to_rfc3339(),
+            checks,
+        };
+        log_report(&report);
+
+        *self.report.lock().unwrap() = Some(report.clone());
+        report
+    }
+
+    /// Report of the finished self-test, `None` while it is running
+    pub fn report(&self) -> Option<SelfTestReport> {
+        self.report.lock().unwrap().clone()
+    }
+
+    fn local_checks(&self) -> Vec<SelfTestCheck> {
+        let mut checks = vec![check("config", self.check_config())];
+        checks.extend(self.check_ffmpeg());
+        checks.push(check("database", self.check_database()));
+        checks.push(check("music_directory", self.check_music_directory()));
+        checks
+    }
+
+    fn check_config(&self) -> Result<String, String> {
+        let enabled = self.config.stream.values().filter(|s| s.enabled).count();
+        Ok(format!(
+            "{} of {} stream(s) enabled, {} active liveset program(s)",
+            enabled,
+            self.config.stream.len(),
+            self.liveset_programs
+        ))
+    }
+
+    /// FFmpeg version and one check per distinct output format
+    fn check_ffmpeg(&self) -> Vec<SelfTestCheck> {
+        let ffmpeg_path = self.config.server.ffmpeg_path.clone();
+        let processor = FFmpegProcessor::new(ffmpeg_path, 44100, 128, 2, "mp3".to_string());
+
+        let version = processor.ffmpeg_version().map_err(|e| e.to_string());
+        let encoders = processor.available_encoders();
+
+        let mut checks = vec![check("ffmpeg", version)];
+        for format in self.output_formats() {
+            let result = match &encoders {
+                Ok(encoders) => {
+                    select_codec(&format, encoders).map(|codec| format!("{} available", codec))
+                }
+                Err(e) => Err(format!("Cannot list FFmpeg encoders: {}", e)),
+            };
+            checks.push(check(&format!("codec:{}", format), result));
+        }
+        checks
+    }
+
+    /// Formats of the enabled streams and the on-demand mode
+    fn output_formats(&self) -> BTreeSet<String> {
+        self.config
+            .stream
+            .values()
+            .filter(|s| s.enabled)
+            .map(|s| s.format.clone())
+            .chain(
+                self.config
+                    .ondemand
+                    .as_ref()
+                    .map(|o| o.stream_settings().format),
+            )
+            .collect()
+    }
+
+    fn check_database(&self) -> Result<String, String> {
+        let integrity = self.db.integrity_check().map_err(|e| e.to_string())?;
+        if integrity != "ok" {
+            return Err(format!("Integrity check failed: {}", integrity));
+        }
+
+        let version = self.db.schema_version().map_err(|e| e.to_string())?;
+        let tracks = self.db.track_count().map_err(|e| e.to_string())?;
+        Ok(format!(
+            "Integrity ok, schema version {}, {} track(s)",
+            version, tracks
+        ))
+    }
+
+    fn check_music_directory(&self) -> Result<String, String> {
+        let directory = &self.config.library.music_directory;
+        std::fs::read_dir(directory)
+            .map(|_| format!("{} is readable", directory))
+            .map_err(|e| format!("Cannot read {}: {}", directory, e))
+    }
+
+    /// Outbound connectivity of the integrations in use, any HTTP response counts as reachable
+    async fn connectivity_checks(&self) -> Vec<SelfTestCheck> {
+        let mut targets = Vec::new();
+        if self.liveset_programs > 0 {
+            targets.push(("hearthis", HEARTHIS_API_BASE.to_string()));
+        }
+        if let Some(ads) = &self.config.ads {
+            targets.push(("ads", ads.decision_url.clone()));
+        }
+
+        let client = match self.http.client(CONNECTIVITY_TIMEOUT) {
+            Ok(client) => client,
+            Err(e) => {
+                return targets
+                    .into_iter()
+                    .map(|(name, _)| check(name, Err(e.to_string())))
+                    .collect();
+            }
+        };
+
+        let mut checks = Vec::new();
+        for (name, url) in targets {
+            let result = match client.head(&url).send().await {
+                Ok(response) => Ok(format!("{} reachable ({})", url, response.status())),
+                Err(e) => Err(format!("{} unreachable: {}", url, e)),
+            };
+            checks.push(check(name, result));
+        }
+        checks
+    }
+}
+
+fn check(name: &str, result: Result<String, String>) -> SelfTestCheck {
+    let (passed, message) = match result {
+        Ok(message) => (true, message),
+        Err(message) => (false, message),
+    };
+    SelfTestCheck {
+        name: name.to_string(),
+        passed,
+        message,
+    }
+}
+
+fn log_report(report: &SelfTestReport) {
+    for check in &report.checks {
+        if check.passed {
+            info!("Self-test [PASS] {}: {}", check.name, check.message);
+        } else {
+            error!("Self-test [FAIL] {}: {}", check.name, check.message);
+        }
+    }
+
+    let failed = report.checks.iter().filter(|c| !c.passed).count();
+    if failed == 0 {
+        info!("Self-test passed: {} check(s)", report.checks.len());
+    } else {
+        error!(
+            "Self-test failed: {} of {} check(s) failed",
+            failed,
+            report.checks.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn given_missing_ffmpeg_and_music_dir_when_running_then_reports_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = LibraryDatabase::new(temp_dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+
+        let mut config = Config::default();
+        config.server.ffmpeg_path = Some("/nonexistent/ffmpeg".to_string());
+        config.library.music_directory = "/nonexistent/music".to_string();
+        let selftest = SelfTest::new(&config, &[], db, HttpClientFactory::default());
+
+        assert!(selftest.report().is_none());
+        let report = selftest.run().await;
+
+        let passed = |name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .passed
+        };
+        assert!(!report.passed);
+        assert!(passed("config"));
+        assert!(passed("database"));
+        assert!(!passed("ffmpeg"));
+        assert!(!passed("music_directory"));
+        assert!(selftest.report().is_some());
+    }
+}
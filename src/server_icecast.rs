@@ -1,33 +1,110 @@
+use crate::admin_auth::{self, AdminAuth};
+use crate::api_error::{error_reply, ApiError};
 use crate::audio_buffer::StreamBuffer;
-use crate::audio_metadata::TrackMetadata;
+use crate::audio_metadata::{read_artwork, NowPlaying, TrackMetadata};
+use crate::audio_processor::AudioChunk;
+use crate::library_db::DatabaseHealth;
+use crate::listener_registry::{ListenerInfo, ListenerRegistry, SESSION_COOKIE};
+use crate::notifier::Notifier;
+use crate::ondemand::OnDemandLibrary;
+use crate::page_templates::PageTemplates;
+use crate::play_history::PlayHistory;
+use crate::program_access::{ProgramAccess, ACCESS_TOKEN_PARAM};
+use crate::program_stats::ProgramStats;
+use crate::schedule_store::ScheduleStore;
+use crate::selftest::SelfTest;
+use crate::server_library::LibraryApi;
+use crate::server_listeners;
+use crate::server_ondemand;
+use crate::server_schedule;
+use crate::server_selftest;
+use crate::server_stats;
 use crate::server_swagger;
-use minijinja::Environment;
+use crate::server_webhooks;
+use crate::station_metadata::StationMetadata;
+use crate::stream_encoders::LiveSettings;
+use crate::stream_rejection::{RejectReason, StreamRejection};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request};
 use serde::Serialize;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use warp::{http::HeaderMap, Filter, Reply};
+use utoipa::ToSchema;
+use warp::http::{HeaderMap, Version};
+use warp::{Filter, Reply};
+
+/// How long an HTTP/2 keep-alive ping may stay unanswered before the connection is closed
+const HTTP2_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
 
 // JSON response structures for serialization
-#[derive(Serialize)]
+/// Server status and metrics
+#[derive(Serialize, ToSchema)]
+#[schema(as = ServerStatus)]
 struct StatusResponse {
+    #[schema(example = "My Radio Station")]
     station_name: String,
+    #[schema(example = "Great music 24/7")]
     station_description: String,
+    #[schema(example = "Various")]
     station_genre: String,
     streams: Vec<StreamStatus>,
+    /// Server start time (RFC 3339)
+    #[schema(example = "2025-01-15T08:00:00+01:00")]
+    started_at: String,
+    /// Seconds since server start
+    #[schema(example = 97506)]
+    uptime_seconds: u64,
+    /// Human readable uptime
+    #[schema(example = "1d 03:05:06")]
     uptime: String,
+    /// Whether the server runs in standby mode without any stream
+    standby: bool,
+    /// Availability of the library database, playout continues while it is unavailable
+    database: DatabaseHealth,
 }
 
-#[derive(Serialize)]
+/// Status of a single stream
+#[derive(Serialize, ToSchema)]
 struct StreamStatus {
+    #[schema(example = "high")]
     name: String,
+    /// Streaming bitrate in kbps
+    #[schema(example = 320)]
     bitrate: u32,
+    /// `online` or `offline`
+    #[schema(example = "online")]
     status: String,
+    /// Number of audio chunks in circular buffer
+    #[schema(example = 1000)]
     buffer_chunks: usize,
+    /// Total bytes of audio data in buffer
+    #[schema(example = 8176452)]
     buffer_bytes: usize,
+    /// Playback time of the buffered audio, how far new listeners start behind live
+    #[schema(example = 42.5)]
+    buffered_seconds: f64,
+    /// Time the first audio of this stream was encoded (RFC 3339)
+    #[schema(example = "2025-01-15T08:00:02+01:00")]
+    on_air_since: Option<String>,
 }
 
+/// Binary audio data of a stream, only used for the API documentation
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+#[allow(dead_code)]
+pub(crate) struct AudioData(Vec<u8>);
+
+/// Binary image data, only used for the API documentation
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+#[allow(dead_code)]
+struct ImageData(Vec<u8>);
+
 // Template context structures
 #[derive(Serialize)]
 struct InfoPageContext {
@@ -41,6 +118,7 @@ struct InfoPageContext {
     port: u16,
     streams: Vec<StreamLink>,
     first_stream: String,
+    off_air: bool,
 }
 
 #[derive(Serialize)]
@@ -53,59 +131,167 @@ struct StreamLink {
 // Context for handling stream requests
 #[derive(Clone)]
 struct StreamContext {
+    mount: String,
     buffer: StreamBuffer,
+    listeners: ListenerRegistry,
     bitrate: u32,
+    content_type: &'static str,
     station_name: String,
     station_description: String,
     station_genre: String,
+    access: Option<ProgramAccess>,
+    access_token: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct IcecastServer {
-    streams: Arc<Vec<StreamEndpoint>>,
-    station_name: String,
-    station_description: String,
-    station_genre: String,
+    /// Grows when streams are brought up at runtime
+    streams: Arc<RwLock<Vec<StreamEndpoint>>>,
+    station: StationMetadata,
     current_metadata: Arc<Mutex<TrackMetadata>>,
+    library_api: LibraryApi,
+    schedule_store: ScheduleStore,
+    listeners: ListenerRegistry,
+    notifier: Notifier,
+    ondemand: Option<OnDemandLibrary>,
+    selftest: Option<SelfTest>,
+    access: Option<ProgramAccess>,
+    program_stats: Option<ProgramStats>,
+    play_history: Option<PlayHistory>,
+    admin: AdminAuth,
+    templates: PageTemplates,
+    started_at: chrono::DateTime<chrono::Local>,
+    started: Instant,
     bind_address: Arc<Mutex<String>>,
     port: Arc<Mutex<u16>>,
+    http2: Option<Http2Settings>,
+}
+
+/// HTTP/2 support for API and UI requests, streams stay on HTTP/1.1
+#[derive(Clone, Copy)]
+struct Http2Settings {
+    keep_alive: Option<Duration>,
+}
+
+/// Connection of a request, added to its extensions by the server
+#[derive(Clone, Copy)]
+struct ClientConnection {
+    remote_addr: std::net::SocketAddr,
+    version: Version,
 }
 
 #[derive(Clone)]
 struct StreamEndpoint {
     name: String,
     buffer: StreamBuffer,
-    bitrate: u32,
+    /// Updated when the stream encoder is rebuilt with new settings
+    live: Arc<LiveSettings>,
+}
+
+impl StreamEndpoint {
+    fn bitrate(&self) -> u32 {
+        self.live.bitrate()
+    }
 }
 
 impl IcecastServer {
     pub fn new(
-        stream_buffers: Vec<(String, StreamBuffer, u32)>,
-        station_name: String,
-        station_description: String,
-        station_genre: String,
+        station: StationMetadata,
         current_metadata: Arc<Mutex<TrackMetadata>>,
+        library_api: LibraryApi,
+        schedule_store: ScheduleStore,
+        listeners: ListenerRegistry,
+        notifier: Notifier,
+        templates: PageTemplates,
     ) -> Self {
-        let streams = stream_buffers
-            .into_iter()
-            .map(|(name, buffer, bitrate)| StreamEndpoint {
-                name,
-                buffer,
-                bitrate,
-            })
-            .collect();
-
         Self {
-            streams: Arc::new(streams),
-            station_name,
-            station_description,
-            station_genre,
+            streams: Arc::new(RwLock::new(Vec::new())),
+            station,
             current_metadata,
+            library_api,
+            schedule_store,
+            listeners,
+            notifier,
+            ondemand: None,
+            selftest: None,
+            access: None,
+            program_stats: None,
+            play_history: None,
+            admin: AdminAuth::new(None),
+            templates,
+            started_at: chrono::Local::now(),
+            started: Instant::now(),
             bind_address: Arc::new(Mutex::new(String::new())),
             port: Arc::new(Mutex::new(0)),
+            http2: None,
         }
     }
 
+    /// Serves a stream, also one brought up while the server is running, e.g. enabled by a reload
+    pub fn add_stream(&self, name: String, buffer: StreamBuffer, live: Arc<LiveSettings>) {
+        log::info!("Serving stream '{}'", name);
+        self.streams
+            .write()
+            .unwrap()
+            .push(StreamEndpoint { name, buffer, live });
+    }
+
+    fn streams(&self) -> Vec<StreamEndpoint> {
+        self.streams.read().unwrap().clone()
+    }
+
+    fn stream(&self, name: &str) -> Option<StreamEndpoint> {
+        self.streams
+            .read()
+            .unwrap()
+            .iter()
+            .find(|s| s.name == name)
+            .cloned()
+    }
+
+    /// Serves the files of the on-demand directory at `/ondemand`
+    pub fn with_ondemand(mut self, ondemand: OnDemandLibrary) -> Self {
+        self.ondemand = Some(ondemand);
+        self
+    }
+
+    /// Serves the startup self-test report at `/api/admin/selftest`
+    pub fn with_selftest(mut self, selftest: SelfTest) -> Self {
+        self.selftest = Some(selftest);
+        self
+    }
+
+    /// Sends listeners without an access token the fallback loop during restricted programs
+    pub fn with_access(mut self, access: ProgramAccess) -> Self {
+        self.access = Some(access);
+        self
+    }
+
+    /// Serves the listener statistics per program at `/api/stats/programs`
+    pub fn with_program_stats(mut self, program_stats: ProgramStats) -> Self {
+        self.program_stats = Some(program_stats);
+        self
+    }
+
+    /// Serves the play history at `/api/stats/history`
+    pub fn with_play_history(mut self, play_history: PlayHistory) -> Self {
+        self.play_history = Some(play_history);
+        self
+    }
+
+    /// Opens the admin API to requests carrying the token as `Authorization: Bearer` header
+    pub fn with_admin_token(mut self, token: String) -> Self {
+        self.admin = AdminAuth::new(Some(token));
+        self
+    }
+
+    /// Accepts HTTP/2 for API and UI requests, `keep_alive` is the interval of the pings
+    /// detecting dead connections
+    pub fn with_http2(mut self, keep_alive: Option<Duration>) -> Self {
+        self.http2 = Some(Http2Settings { keep_alive });
+        self
+    }
+
     pub async fn start_server(&self, bind_address: &str, port: u16) {
         // Store bind_address and port for use in info page
         *self.bind_address.lock().unwrap() = bind_address.to_string();
@@ -113,73 +299,77 @@ impl IcecastServer {
 
         let server = Arc::new(self.clone());
 
-        // Dynamic stream route handler
-        let streams_map = self.streams.clone();
-        let station_name = self.station_name.clone();
-        let station_description = self.station_description.clone();
-        let station_genre = self.station_genre.clone();
-
-        let stream_route = warp::path::param::<String>()
-            .and(warp::get())
-            .and(warp::header::headers_cloned())
-            .and_then(move |stream_name: String, headers: HeaderMap| {
-                let streams = streams_map.clone();
-                let station_name = station_name.clone();
-                let station_description = station_description.clone();
-                let station_genre = station_genre.clone();
+        let stream_route = stream_route(Arc::clone(&server));
+        let status_route = status_route(Arc::clone(&server));
+        let info_route = info_route(Arc::clone(&server));
+        let current_route = current_route(Arc::clone(&server));
+        let artwork_route = artwork_route(Arc::clone(&server));
+        let unknown_mount_route = unknown_mount_route(Arc::clone(&server));
 
-                async move {
-                    // Find the stream by name and create context
-                    for stream in streams.iter() {
-                        if stream.name == stream_name {
-                            let context = StreamContext {
-                                buffer: stream.buffer.clone(),
-                                bitrate: stream.bitrate,
-                                station_name: station_name.clone(),
-                                station_description: station_description.clone(),
-                                station_genre: station_genre.clone(),
-                            };
-                            return Self::handle_stream_request(headers, context).await;
-                        }
-                    }
-                    Err(warp::reject::not_found())
-                }
-            });
+        // Library API routes
+        let library_routes = self.library_api.routes();
 
-        let status_route = warp::path("status").and(warp::get()).and_then({
-            let server = Arc::clone(&server);
-            move || {
+        // Listener API routes
+        let session_route = server_listeners::session_route(self.listeners.clone());
+        let stream_listeners_route =
+            server_listeners::stream_listeners_route(self.listeners.clone(), {
                 let server = Arc::clone(&server);
-                async move { server.handle_status_request().await }
-            }
-        });
+                move |name: &str| server.stream(name).is_some()
+            });
 
-        let info_route = warp::path::end().and(warp::get()).and_then({
-            let server = Arc::clone(&server);
-            move || {
-                let server = Arc::clone(&server);
-                async move { server.handle_info_request().await }
-            }
-        });
+        // Schedule API routes
+        let schedule_export_route = server_schedule::export_route(self.schedule_store.clone());
+        let schedule_import_route = server_schedule::import_route(self.schedule_store.clone());
 
-        let current_route = warp::path("current").and(warp::get()).and_then({
-            let server = Arc::clone(&server);
-            move || {
-                let server = Arc::clone(&server);
-                async move { server.handle_current_request().await }
-            }
-        });
+        // Webhook admin routes
+        let webhook_register_route = server_webhooks::register_route(self.notifier.clone());
+        let webhook_list_route = server_webhooks::list_route(self.notifier.clone());
+        let webhook_delete_route = server_webhooks::delete_route(self.notifier.clone());
+        let webhook_deliveries_route = server_webhooks::deliveries_route(self.notifier.clone());
+
+        // Self-test report route
+        let selftest_route = server_selftest::selftest_route(self.selftest.clone());
+
+        // Statistics routes
+        let program_stats_route = server_stats::programs_route(self.program_stats.clone());
+        let history_route = server_stats::history_route(self.play_history.clone());
+
+        // On-demand routes
+        let ondemand_routes = server_ondemand::routes(self.ondemand.clone());
+
+        // Static files of custom templates
+        let assets_route = self.templates.assets_route();
 
         // Swagger API documentation routes
         let swagger_ui_route = server_swagger::swagger_ui();
         let openapi_spec_route = server_swagger::openapi_spec();
 
+        // Requests changing the station and the admin paths need the admin token
+        let admin_guard = self.admin.filter();
+
         let routes = stream_route
+            .or(stream_listeners_route)
             .or(status_route)
+            .or(artwork_route)
             .or(current_route)
+            .or(library_routes)
+            .or(session_route)
+            .or(schedule_export_route)
+            .or(schedule_import_route)
+            .or(webhook_register_route)
+            .or(webhook_list_route)
+            .or(webhook_delete_route)
+            .or(webhook_deliveries_route)
+            .or(selftest_route)
+            .or(program_stats_route)
+            .or(history_route)
+            .or(ondemand_routes)
             .or(swagger_ui_route)
             .or(openapi_spec_route)
-            .or(info_route);
+            .or(assets_route)
+            .or(info_route)
+            .or(unknown_mount_route);
+        let routes = admin_guard.and(routes).recover(admin_auth::recover);
 
         log::info!("Starting Funkstrom server on {}:{}", bind_address, port);
         log::info!("API Docs: http://{}:{}/api-docs", bind_address, port);
@@ -187,11 +377,45 @@ impl IcecastServer {
         let addr: std::net::SocketAddr = format!("{}:{}", bind_address, port)
             .parse()
             .expect("Invalid bind address");
-        warp::serve(routes).run(addr).await;
+
+        // Filters read the client address and protocol from the request extensions
+        let service = warp::service(routes);
+        let make_service = make_service_fn(move |conn: &AddrStream| {
+            let service = service.clone();
+            let remote_addr = conn.remote_addr();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |mut request: Request<Body>| {
+                    let connection = ClientConnection {
+                        remote_addr,
+                        version: request.version(),
+                    };
+                    request.extensions_mut().insert(connection);
+                    service.clone().call(request)
+                }))
+            }
+        });
+
+        let builder = match hyper::Server::try_bind(&addr) {
+            Ok(builder) => builder.tcp_nodelay(true),
+            Err(e) => panic!("error binding to {}: {}", addr, e),
+        };
+        let builder = match self.http2 {
+            Some(http2) => {
+                log::info!("HTTP/2 enabled for API and UI requests");
+                builder
+                    .http2_keep_alive_interval(http2.keep_alive)
+                    .http2_keep_alive_timeout(HTTP2_KEEP_ALIVE_TIMEOUT)
+            }
+            None => builder.http1_only(true),
+        };
+        if let Err(e) = builder.serve(make_service).await {
+            log::error!("Server error: {}", e);
+        }
     }
 
     async fn handle_stream_request(
         headers: HeaderMap,
+        remote_addr: Option<std::net::SocketAddr>,
         context: StreamContext,
     ) -> Result<impl Reply, warp::Rejection> {
         log::info!("New client connected for streaming");
@@ -210,17 +434,55 @@ impl IcecastServer {
 
         let (tx, rx) = mpsc::unbounded_channel();
         let buffer = context.buffer.clone();
+        let listener = context.listeners.connect(ListenerInfo {
+            mount: context.mount.clone(),
+            bitrate: context.bitrate,
+            path: format!("/{}", context.mount),
+            remote_addr: remote_addr.map(|addr| addr.ip().to_string()),
+            user_agent: Self::header_value(&headers, "user-agent"),
+            referer: Self::header_value(&headers, "referer"),
+        });
+        let session_id = listener.session_id().to_string();
+
+        // Listeners without a valid token get the fallback loop during restricted programs
+        let access = context
+            .access
+            .filter(|access| !access.is_authorized(context.access_token.as_deref()));
+        let mut fallback = access
+            .as_ref()
+            .and_then(|access| access.fallback(&context.mount));
 
         tokio::spawn(async move {
             let mut last_data_time = Instant::now();
             let timeout_duration = Duration::from_secs(30);
 
+            // Decoders need the codec header before they can join the running stream
+            if let Some(header) = buffer.stream_header() {
+                listener.add_bytes(header.len());
+                if tx.send(Ok::<_, warp::Error>(header)).is_err() {
+                    return;
+                }
+            }
+
             loop {
-                if let Some(chunk) = buffer.read_chunk(8192) {
+                if let Some(AudioChunk { data: chunk, .. }) = buffer.read_chunk(8192) {
+                    let chunk = match (&access, &mut fallback) {
+                        (Some(access), Some(fallback)) if access.is_restricted() => {
+                            fallback.next_chunk(chunk.len())
+                        }
+                        // Without a fallback loop the restricted program is withheld
+                        (Some(access), None) if access.is_restricted() => {
+                            log::info!("No access fallback, disconnecting unauthorized listener");
+                            break;
+                        }
+                        _ => chunk,
+                    };
+                    let chunk_len = chunk.len();
                     if tx.send(Ok::<_, warp::Error>(chunk)).is_err() {
                         log::info!("Client disconnected");
                         break;
                     }
+                    listener.add_bytes(chunk_len);
                     last_data_time = Instant::now();
                 } else {
                     if last_data_time.elapsed() > timeout_duration {
@@ -237,7 +499,7 @@ impl IcecastServer {
         let server_version = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
         let response = warp::http::Response::builder()
-            .header("Content-Type", "audio/mpeg")
+            .header("Content-Type", context.content_type)
             .header("Cache-Control", "no-cache, no-store")
             .header("Connection", "close")
             .header("Pragma", "no-cache")
@@ -251,22 +513,37 @@ impl IcecastServer {
             .header("icy-br", context.bitrate.to_string())
             .header("icy-metaint", "16000")
             .header("Server", &server_version)
+            .header(
+                "Set-Cookie",
+                format!(
+                    "{}={}; Path=/; HttpOnly; SameSite=Lax",
+                    SESSION_COOKIE, session_id
+                ),
+            )
+            .header("X-Session-Token", &session_id)
             .body(hyper::Body::wrap_stream(stream))
             .unwrap();
 
         Ok(response)
     }
 
+    fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    }
+
     async fn handle_status_request(&self) -> Result<impl Reply, warp::Rejection> {
-        let streams = self
-            .streams
+        let streams: Vec<StreamStatus> = self
+            .streams()
             .iter()
             .map(|stream| {
                 let (chunks, bytes) = stream.buffer.buffer_info();
                 let is_running = stream.buffer.is_running();
                 StreamStatus {
                     name: stream.name.clone(),
-                    bitrate: stream.bitrate,
+                    bitrate: stream.bitrate(),
                     status: if is_running {
                         "online".to_string()
                     } else {
@@ -274,16 +551,24 @@ impl IcecastServer {
                     },
                     buffer_chunks: chunks,
                     buffer_bytes: bytes,
+                    buffered_seconds: stream.buffer.buffered_duration().as_secs_f64(),
+                    on_air_since: stream.buffer.on_air_since().map(|t| t.to_rfc3339()),
                 }
             })
             .collect();
 
+        let uptime_seconds = self.started.elapsed().as_secs();
+        let station = self.station.current();
         let response = StatusResponse {
-            station_name: self.station_name.clone(),
-            station_description: self.station_description.clone(),
-            station_genre: self.station_genre.clone(),
+            station_name: station.name,
+            station_description: station.description,
+            station_genre: station.genre,
+            standby: streams.is_empty(),
             streams,
-            uptime: "unknown".to_string(),
+            started_at: self.started_at.to_rfc3339(),
+            uptime_seconds,
+            uptime: format_uptime(uptime_seconds),
+            database: self.library_api.database_health(),
         };
 
         let json = serde_json::to_string(&response).unwrap();
@@ -306,6 +591,40 @@ impl IcecastServer {
         ))
     }
 
+    async fn handle_artwork_request(&self) -> Result<warp::reply::Response, warp::Rejection> {
+        let file_path = self.current_metadata.lock().unwrap().file_path.clone();
+
+        let artwork = if file_path.is_empty() {
+            None
+        } else {
+            let library_api = self.library_api.clone();
+            tokio::task::spawn_blocking(move || {
+                // The thumbnail of library tracks, the embedded image of programs
+                library_api
+                    .cached_artwork(&file_path)
+                    .and_then(|thumbnail| Some((std::fs::read(thumbnail).ok()?, "image/jpeg")))
+                    .or_else(|| read_artwork(std::path::Path::new(&file_path)))
+            })
+            .await
+            .ok()
+            .flatten()
+        };
+
+        Ok(match artwork {
+            Some((data, mime_type)) => warp::reply::with_header(
+                warp::reply::with_header(data, "Content-Type", mime_type),
+                "Cache-Control",
+                "no-cache",
+            )
+            .into_response(),
+            None => error_reply(
+                warp::http::StatusCode::NOT_FOUND,
+                "Current track has no cover art",
+            )
+            .into_response(),
+        })
+    }
+
     async fn handle_info_request(&self) -> Result<impl Reply, warp::Rejection> {
         let metadata = self.current_metadata.lock().unwrap();
         let current_track = metadata.to_icy_metadata();
@@ -315,51 +634,39 @@ impl IcecastServer {
         let port = *self.port.lock().unwrap();
 
         // Build streams list for template context
-        let streams: Vec<StreamLink> = self
-            .streams
+        let endpoints = self.streams();
+        let streams: Vec<StreamLink> = endpoints
             .iter()
             .map(|stream| StreamLink {
                 name: stream.name.clone(),
-                bitrate: stream.bitrate,
+                bitrate: stream.bitrate(),
                 url: format!("http://{}:{}/{}", bind_address, port, stream.name),
             })
             .collect();
 
         // Use the first stream for the audio player
-        let first_stream = self
-            .streams
+        let first_stream = endpoints
             .first()
             .map(|s| s.name.clone())
             .unwrap_or_else(|| "stream".to_string());
-        let first_bitrate = self.streams.first().map(|s| s.bitrate).unwrap_or(128);
+        let first_bitrate = endpoints.first().map(|s| s.bitrate()).unwrap_or(128);
 
+        let station = self.station.current();
         let context = InfoPageContext {
-            station_name: self.station_name.clone(),
+            station_name: station.name,
             current_track,
             album: album.clone(),
-            station_description: self.station_description.clone(),
-            station_genre: self.station_genre.clone(),
+            station_description: station.description,
+            station_genre: station.genre,
             bitrate: first_bitrate,
             bind_address: bind_address.clone(),
             port,
             streams,
             first_stream,
+            off_air: endpoints.is_empty(),
         };
 
-        const TEMPLATE_STR: &str = include_str!("../templates/info.html");
-
-        let mut env = Environment::new();
-        env.add_template("info", TEMPLATE_STR).map_err(|e| {
-            log::error!("Template error: {}", e);
-            warp::reject::reject()
-        })?;
-
-        let tmpl = env.get_template("info").map_err(|e| {
-            log::error!("Template get error: {}", e);
-            warp::reject::reject()
-        })?;
-
-        let rendered = tmpl.render(&context).map_err(|e| {
+        let rendered = self.templates.render_info(&context).map_err(|e| {
             log::error!("Render error: {}", e);
             warp::reject::reject()
         })?;
@@ -371,3 +678,243 @@ impl IcecastServer {
         ))
     }
 }
+
+/// Audio stream
+///
+/// Continuous audio stream of the mount, compatible with the Icecast protocol and
+/// players like VLC, iTunes and Winamp. Clients are disconnected after 30 seconds
+/// without audio data.
+#[utoipa::path(
+    get,
+    path = "/{stream}",
+    tag = "streaming",
+    operation_id = "getStream",
+    params(
+        ("stream" = String, Path, description = "Stream name as configured, e.g. `high`"),
+        ("access_token" = Option<String>, Query, description = "Token unlocking restricted programs, alternatively sent as `Authorization: Bearer` header"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Continuous audio stream",
+            content_type = "audio/mpeg",
+            body = AudioData,
+            headers(
+                ("icy-name" = String, description = "Station name"),
+                ("icy-description" = String, description = "Station description"),
+                ("icy-genre" = String, description = "Station genre"),
+                ("icy-br" = u32, description = "Bitrate in kbps"),
+                ("icy-metaint" = u32, description = "Metadata interval"),
+                ("Set-Cookie" = String, description = "Session cookie identifying this connection for `/api/session`"),
+                ("X-Session-Token" = String, description = "Session token identifying this connection for `/api/session`"),
+            )
+        ),
+        (status = 404, description = "No stream of this name, `icy-notice` explains why, HTML for browsers", body = StreamRejection),
+        (status = 505, description = "Stream requested over HTTP/2, streams are served over HTTP/1.1 only", body = StreamRejection),
+        (status = 503, description = "Stream or station off the air, `icy-notice` explains why, HTML for browsers", body = StreamRejection),
+    )
+)]
+fn stream_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path::param::<String>()
+        .and(warp::get())
+        .and(warp::header::headers_cloned())
+        .and(warp::ext::optional::<ClientConnection>())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(
+            move |stream_name: String,
+                  headers: HeaderMap,
+                  connection: Option<ClientConnection>,
+                  query: HashMap<String, String>| {
+                let server = Arc::clone(&server);
+
+                async move {
+                    // Unknown mounts fall through to the other routes
+                    let Some(stream) = server.stream(&stream_name) else {
+                        return Err(warp::reject::not_found());
+                    };
+                    if !stream.buffer.is_running() {
+                        return Ok(StreamRejection::new(RejectReason::OffAir, &stream.name)
+                            .reply(&headers));
+                    }
+                    // Players expect chunked HTTP/1.1, HTTP/2 is only served to API clients
+                    if connection.is_some_and(|c| c.version >= Version::HTTP_2) {
+                        return Ok(
+                            StreamRejection::new(RejectReason::Http1Required, &stream.name)
+                                .reply(&headers),
+                        );
+                    }
+
+                    let station = server.station.current();
+                    let context = StreamContext {
+                        mount: stream.name.clone(),
+                        buffer: stream.buffer.clone(),
+                        listeners: server.listeners.clone(),
+                        bitrate: stream.bitrate(),
+                        content_type: stream.live.content_type(),
+                        station_name: station.name,
+                        station_description: station.description,
+                        station_genre: station.genre,
+                        access: server.access.clone(),
+                        access_token: access_token(&headers, &query),
+                    };
+                    let remote_addr = connection.map(|c| c.remote_addr);
+                    IcecastServer::handle_stream_request(headers, remote_addr, context)
+                        .await
+                        .map(Reply::into_response)
+                }
+            },
+        )
+}
+
+/// Rejects requests for mounts that no other route served, so players and users can tell a
+/// wrong URL from a station off the air
+fn unknown_mount_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::header::headers_cloned())
+        .map(move |mount: String, headers: HeaderMap| {
+            let reason = if server.streams.read().unwrap().is_empty() {
+                RejectReason::Standby
+            } else {
+                RejectReason::UnknownMount
+            };
+            StreamRejection::new(reason, &mount).reply(&headers)
+        })
+}
+
+/// Access token of a listener from the query or a bearer `Authorization` header
+fn access_token(headers: &HeaderMap, query: &HashMap<String, String>) -> Option<String> {
+    query.get(ACCESS_TOKEN_PARAM).cloned().or_else(|| {
+        headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string())
+    })
+}
+
+/// Server status and health check
+///
+/// Returns server health, buffer metrics per stream, station configuration, the server
+/// uptime and start time and the on-air time of each stream.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "monitoring",
+    operation_id = "getStatus",
+    responses((status = 200, description = "Server status information", body = StatusResponse))
+)]
+fn status_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path("status").and(warp::get()).and_then(move || {
+        let server = Arc::clone(&server);
+        async move { server.handle_status_request().await }
+    })
+}
+
+/// Current track metadata
+///
+/// Metadata of the currently playing track, read from the audio file tags or derived
+/// from the filename when tags are unavailable. Includes the duration and elapsed time
+/// for progress bars and a link to the embedded cover art.
+#[utoipa::path(
+    get,
+    path = "/current",
+    tag = "metadata",
+    operation_id = "getCurrentTrack",
+    responses((status = 200, description = "Current track metadata", body = NowPlaying))
+)]
+fn current_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path("current").and(warp::get()).and_then(move || {
+        let server = Arc::clone(&server);
+        async move { server.handle_current_request().await }
+    })
+}
+
+/// Cover art of the current track
+///
+/// Serves the cover image embedded in the current track, as linked by `artwork_url` of `/current`.
+/// Library tracks are served from the thumbnails written by the library scan.
+#[utoipa::path(
+    get,
+    path = "/current/artwork",
+    tag = "metadata",
+    operation_id = "getCurrentArtwork",
+    responses(
+        (status = 200, description = "Embedded cover image", content_type = "image/*", body = ImageData),
+        (status = 404, description = "Current track has no cover art", body = ApiError),
+    )
+)]
+fn artwork_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("current" / "artwork")
+        .and(warp::get())
+        .and_then(move || {
+            let server = Arc::clone(&server);
+            async move { server.handle_artwork_request().await }
+        })
+}
+
+/// Server information page
+///
+/// HTML landing page with the current track, station information and stream links.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "info",
+    operation_id = "getInfoPage",
+    responses((status = 200, description = "HTML information page", content_type = "text/html", body = String))
+)]
+fn info_route(
+    server: Arc<IcecastServer>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path::end().and(warp::get()).and_then(move || {
+        let server = Arc::clone(&server);
+        async move { server.handle_info_request().await }
+    })
+}
+
+/// Formats an uptime as "3d 04:05:06", omitting the days when zero
+fn format_uptime(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        (seconds % 86_400) / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    );
+
+    if days > 0 {
+        format!("{}d {}", days, time)
+    } else {
+        time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_uptime_below_one_day_when_formatted_then_omits_days() {
+        assert_eq!(format_uptime(0), "00:00:00");
+        assert_eq!(format_uptime(3723), "01:02:03");
+    }
+
+    #[test]
+    fn given_uptime_of_several_days_when_formatted_then_includes_days() {
+        assert_eq!(
+            format_uptime(3 * 86_400 + 4 * 3600 + 5 * 60 + 6),
+            "3d 04:05:06"
+        );
+    }
+}
@@ -0,0 +1,916 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::library_db::{
+    Artist, BrokenTrack, CuePoints, DatabaseHealth, DuplicateGroup, LibraryDatabase, ProblemTrack,
+    TrackRecord,
+};
+use crate::library_scanner::LibraryScanner;
+use crate::playout_control::PlayoutControl;
+use crate::server_playlists;
+use crate::server_smart_playlists;
+use bytes::Buf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+use utoipa::{IntoParams, ToSchema};
+use warp::http::StatusCode;
+use warp::multipart::{FormData, Part};
+use warp::reply::{Json, WithStatus};
+use warp::{Filter, Reply};
+
+/// Largest accepted upload, big enough for long DJ mixes
+const MAX_UPLOAD_BYTES: u64 = 512 * 1024 * 1024;
+
+/// How long a delete waits for a playing track to be stopped on all streams
+const SKIP_TIMEOUT: Duration = Duration::from_secs(5);
+const SKIP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Search results returned without a `limit`, and the most returned with one
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+const MAX_SEARCH_LIMIT: usize = 500;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    /// Words to find in title, artist or album, each matching the start of a word
+    #[serde(default)]
+    q: String,
+    /// Maximum number of results, at most 500
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeleteQuery {
+    /// Also remove the audio file from disk
+    #[serde(default)]
+    delete_file: bool,
+}
+
+/// Multipart form of an upload, only used for the API documentation
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct UploadForm {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// Cover art thumbnail, only used for the API documentation
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+#[allow(dead_code)]
+struct Thumbnail(Vec<u8>);
+
+/// Star rating of a track
+#[derive(Deserialize, ToSchema)]
+struct TrackRating {
+    /// 1 to 5 stars, `null` removes the rating
+    #[schema(example = 5)]
+    rating: Option<u8>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeleteResult {
+    deleted: TrackRecord,
+    file_deleted: bool,
+    /// Whether the track was playing and had to be skipped
+    skipped: bool,
+}
+
+/// Shared state for the library API routes
+#[derive(Clone)]
+pub struct LibraryApi {
+    db: LibraryDatabase,
+    scanner: LibraryScanner,
+    inbox_directory: Option<PathBuf>,
+    playout_control: PlayoutControl,
+}
+
+impl LibraryApi {
+    /// Uploads are disabled when no inbox directory is configured
+    pub fn new(
+        db: LibraryDatabase,
+        scanner: LibraryScanner,
+        inbox_directory: Option<PathBuf>,
+        playout_control: PlayoutControl,
+    ) -> Self {
+        Self {
+            db,
+            scanner,
+            inbox_directory,
+            playout_control,
+        }
+    }
+
+    /// Availability of the library database
+    pub fn database_health(&self) -> DatabaseHealth {
+        self.db.health()
+    }
+
+    /// Cover art thumbnail the library scan wrote for the track at `file_path`
+    pub fn cached_artwork(&self, file_path: &str) -> Option<PathBuf> {
+        self.db
+            .get_track_by_path(file_path)
+            .ok()??
+            .artwork_path
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+    }
+
+    pub fn routes(&self) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+        problems_route(self.db.clone())
+            .or(broken_route(self.db.clone()))
+            .or(duplicates_route(self.db.clone()))
+            .or(search_route(self.db.clone()))
+            .or(artists_route(self.db.clone()))
+            .or(artist_tracks_route(self.db.clone()))
+            .or(upload_route(
+                self.scanner.clone(),
+                self.inbox_directory.clone(),
+            ))
+            .or(delete_route(
+                self.db.clone(),
+                self.scanner.clone(),
+                self.playout_control.clone(),
+            ))
+            .or(cue_route(self.db.clone()))
+            .or(rating_route(self.scanner.clone()))
+            .or(artwork_route(self.db.clone()))
+            .or(server_playlists::routes(self.db.clone()))
+            .or(server_smart_playlists::routes(self.db.clone()))
+    }
+}
+
+/// Tracks that failed to play
+///
+/// Lists tracks that failed to play at least once. Tracks reaching the configured
+/// `max_track_failures` are quarantined and skipped during track selection.
+#[utoipa::path(
+    get,
+    path = "/api/library/problems",
+    tag = "library",
+    operation_id = "getLibraryProblems",
+    responses(
+        (status = 200, description = "Problem tracks, quarantined tracks first", body = Vec<ProblemTrack>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn problems_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "problems")
+        .and(warp::get())
+        .and_then(move || {
+            let db = db.clone();
+            async move {
+                match db.get_problem_tracks() {
+                    Ok(tracks) => Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&tracks),
+                        StatusCode::OK,
+                    )),
+                    Err(e) => {
+                        log::error!("Failed to load problem tracks: {}", e);
+                        Ok(error_reply(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            &e.to_string(),
+                        ))
+                    }
+                }
+            }
+        })
+}
+
+/// Broken files
+///
+/// Lists tracks whose files could not be decoded by the scan with `validate_files` enabled.
+/// They are quarantined until the file changes and is scanned again.
+#[utoipa::path(
+    get,
+    path = "/api/library/broken",
+    tag = "library",
+    operation_id = "getBrokenTracks",
+    responses(
+        (status = 200, description = "Broken files ordered by path", body = Vec<BrokenTrack>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn broken_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "broken")
+        .and(warp::get())
+        .and_then(move || {
+            let db = db.clone();
+            async move {
+                match db.get_broken_tracks() {
+                    Ok(tracks) => Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&tracks),
+                        StatusCode::OK,
+                    )),
+                    Err(e) => {
+                        log::error!("Failed to load broken tracks: {}", e);
+                        Ok(error_reply(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            &e.to_string(),
+                        ))
+                    }
+                }
+            }
+        })
+}
+
+/// Duplicate tracks
+///
+/// Lists groups of library tracks that are copies of the same song: identical files, then
+/// files with identical audio but different tags, found with `audio_fingerprints` enabled.
+/// Only the first track of a group plays in the library rotation.
+#[utoipa::path(
+    get,
+    path = "/api/library/duplicates",
+    tag = "library",
+    operation_id = "getLibraryDuplicates",
+    responses(
+        (status = 200, description = "Duplicate groups", body = Vec<DuplicateGroup>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn duplicates_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "duplicates")
+        .and(warp::get())
+        .and_then(move || {
+            let db = db.clone();
+            async move {
+                match tokio::task::spawn_blocking(move || db.get_duplicate_groups()).await {
+                    Ok(Ok(groups)) => Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&groups),
+                        StatusCode::OK,
+                    )),
+                    Ok(Err(e)) => {
+                        log::error!("Failed to load duplicate tracks: {}", e);
+                        Ok(error_reply(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            &e.to_string(),
+                        ))
+                    }
+                    Err(e) => Ok(error_reply(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &e.to_string(),
+                    )),
+                }
+            }
+        })
+}
+
+/// Search the library
+///
+/// Finds tracks by title, artist and album through a full-text index, best matches first.
+/// Every word of `q` has to match the start of a word, ignoring case and diacritics, so
+/// `beat liv` finds "The Beatles - Live at the BBC".
+#[utoipa::path(
+    get,
+    path = "/api/library/search",
+    tag = "library",
+    operation_id = "searchLibrary",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching tracks, best matches first", body = Vec<TrackRecord>),
+        (status = 400, description = "Missing search words", body = ApiError),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn search_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "search")
+        .and(warp::get())
+        .and(warp::query::<SearchQuery>())
+        .map(move |query: SearchQuery| {
+            if query.q.trim().is_empty() {
+                return error_reply(StatusCode::BAD_REQUEST, "Query parameter 'q' is required");
+            }
+            let limit = query
+                .limit
+                .unwrap_or(DEFAULT_SEARCH_LIMIT)
+                .min(MAX_SEARCH_LIMIT);
+
+            match db.search_tracks(&query.q, limit) {
+                Ok(tracks) => warp::reply::with_status(warp::reply::json(&tracks), StatusCode::OK),
+                Err(e) => {
+                    log::error!("Failed to search the library for '{}': {}", query.q, e);
+                    error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                }
+            }
+        })
+}
+
+/// Artists
+///
+/// Lists the artists of the library tracks. Spellings differing only in case and tags naming
+/// featured artists, like "daft punk feat. Pharrell Williams", count as the same artist.
+#[utoipa::path(
+    get,
+    path = "/api/library/artists",
+    tag = "library",
+    operation_id = "getLibraryArtists",
+    responses(
+        (status = 200, description = "Artists ordered by name", body = Vec<Artist>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn artists_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "artists")
+        .and(warp::get())
+        .map(move || match db.get_artists() {
+            Ok(artists) => warp::reply::with_status(warp::reply::json(&artists), StatusCode::OK),
+            Err(e) => {
+                log::error!("Failed to load artists: {}", e);
+                error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }
+        })
+}
+
+/// Tracks of an artist
+#[utoipa::path(
+    get,
+    path = "/api/library/artists/{id}/tracks",
+    tag = "library",
+    operation_id = "getLibraryArtistTracks",
+    params(("id" = i64, Path, description = "Artist id")),
+    responses(
+        (status = 200, description = "Tracks ordered by album and title", body = Vec<TrackRecord>),
+        (status = 404, description = "Artist not found", body = ApiError),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn artist_tracks_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "artists" / i64 / "tracks")
+        .and(warp::get())
+        .map(move |id: i64| match db.get_artist_tracks(id) {
+            Ok(Some(tracks)) => {
+                warp::reply::with_status(warp::reply::json(&tracks), StatusCode::OK)
+            }
+            Ok(None) => error_reply(StatusCode::NOT_FOUND, &format!("Artist {} not found", id)),
+            Err(e) => {
+                log::error!("Failed to load the tracks of artist {}: {}", id, e);
+                error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }
+        })
+}
+
+/// Upload a track
+///
+/// Stores the uploaded audio file in the configured `inbox_directory`, reads its tags and
+/// adds it to the library immediately. Disabled unless `inbox_directory` is configured.
+#[utoipa::path(
+    post,
+    path = "/api/library/upload",
+    tag = "library",
+    operation_id = "uploadLibraryTrack",
+    security(("admin_token" = [])),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Track added to the library", body = TrackRecord),
+        (status = 400, description = "Missing or invalid file field", body = ApiError),
+        (status = 403, description = "Uploads are disabled", body = ApiError),
+        (status = 409, description = "A file with the same name already exists in the inbox", body = ApiError),
+        (status = 415, description = "Not a supported audio file", body = ApiError),
+        (status = 422, description = "File could not be read as audio", body = ApiError),
+    )
+)]
+fn upload_route(
+    scanner: LibraryScanner,
+    inbox_directory: Option<PathBuf>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "upload")
+        .and(warp::post())
+        .and(warp::multipart::form().max_length(MAX_UPLOAD_BYTES))
+        .and_then(move |form: FormData| {
+            let scanner = scanner.clone();
+            let inbox_directory = inbox_directory.clone();
+            async move {
+                Ok::<_, warp::Rejection>(handle_upload(scanner, inbox_directory, form).await)
+            }
+        })
+}
+
+async fn handle_upload(
+    scanner: LibraryScanner,
+    inbox_directory: Option<PathBuf>,
+    mut form: FormData,
+) -> WithStatus<Json> {
+    let Some(inbox_directory) = inbox_directory else {
+        return error_reply(
+            StatusCode::FORBIDDEN,
+            "Uploads are disabled, set library.inbox_directory to enable them",
+        );
+    };
+
+    while let Some(part) = form.next().await {
+        let mut part = match part {
+            Ok(part) => part,
+            Err(e) => return error_reply(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+
+        if part.name() != "file" {
+            continue;
+        }
+
+        let Some(file_name) = part.filename().and_then(sanitize_file_name) else {
+            return error_reply(StatusCode::BAD_REQUEST, "Missing or invalid file name");
+        };
+
+        let target = inbox_directory.join(&file_name);
+        if !LibraryScanner::is_audio_file(&target) {
+            return error_reply(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                &format!("'{}' is not a supported audio file", file_name),
+            );
+        }
+        if target.exists() {
+            return error_reply(
+                StatusCode::CONFLICT,
+                &format!("'{}' already exists in the inbox", file_name),
+            );
+        }
+
+        if let Err(reply) = write_upload(&inbox_directory, &target, &mut part).await {
+            return reply;
+        }
+
+        let import_path = target.clone();
+        let result = tokio::task::spawn_blocking(move || scanner.import_file(&import_path)).await;
+        return match result {
+            // A track previously deleted under the same name plays again, the scanner allows it
+            Ok(Ok(track)) => {
+                warp::reply::with_status(warp::reply::json(&track), StatusCode::CREATED)
+            }
+            Ok(Err(e)) => {
+                log::warn!("Rejected upload {:?}: {}", target, e);
+                let _ = tokio::fs::remove_file(&target).await;
+                error_reply(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string())
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&target).await;
+                error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }
+        };
+    }
+
+    error_reply(StatusCode::BAD_REQUEST, "Missing multipart field 'file'")
+}
+
+/// Delete a track
+///
+/// Removes the track from the library. A track that is currently playing is skipped on all
+/// streams first. With `delete_file=true` the audio file is removed from disk as well, except
+/// for tracks of single-file albums whose file holds the whole album.
+#[utoipa::path(
+    delete,
+    path = "/api/library/tracks/{id}",
+    tag = "library",
+    operation_id = "deleteLibraryTrack",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Track id"), DeleteQuery),
+    responses(
+        (status = 200, description = "Track deleted", body = DeleteResult),
+        (status = 400, description = "The file of the track holds a whole album", body = ApiError),
+        (status = 404, description = "Track not found", body = ApiError),
+        (status = 409, description = "Track is still playing and could not be skipped in time", body = ApiError),
+    )
+)]
+fn delete_route(
+    db: LibraryDatabase,
+    scanner: LibraryScanner,
+    playout_control: PlayoutControl,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "tracks" / i64)
+        .and(warp::delete())
+        .and(warp::query::<DeleteQuery>())
+        .and_then(move |id: i64, query: DeleteQuery| {
+            let db = db.clone();
+            let scanner = scanner.clone();
+            let playout_control = playout_control.clone();
+            async move {
+                Ok::<_, warp::Rejection>(
+                    handle_delete(db, scanner, playout_control, id, query.delete_file).await,
+                )
+            }
+        })
+}
+
+async fn handle_delete(
+    db: LibraryDatabase,
+    scanner: LibraryScanner,
+    playout_control: PlayoutControl,
+    id: i64,
+    delete_file: bool,
+) -> WithStatus<Json> {
+    let track = match db.get_track(id) {
+        Ok(Some(track)) => track,
+        Ok(None) => {
+            return error_reply(StatusCode::NOT_FOUND, &format!("Track {} not found", id));
+        }
+        Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    };
+    if delete_file && track.start_seconds.is_some() {
+        return error_reply(
+            StatusCode::BAD_REQUEST,
+            "The track is part of a single-file album, its file cannot be deleted",
+        );
+    }
+
+    // Skip the track first, so it is neither playing nor queued once it is gone
+    let path = PathBuf::from(&track.file_path);
+    let was_playing = playout_control.is_playing(&path);
+    playout_control.skip(&path);
+
+    if was_playing && !wait_until_stopped(&playout_control, &path).await {
+        return error_reply(
+            StatusCode::CONFLICT,
+            "Track is still playing, it could not be skipped in time",
+        );
+    }
+
+    if delete_file {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::error!("Failed to delete file {:?}: {}", path, e);
+                return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+            }
+        }
+    }
+
+    if let Err(e) = scanner.delete_track(&track.file_path) {
+        log::error!("Failed to delete track {}: {}", id, e);
+        return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+    }
+
+    log::info!(
+        "Deleted track {} ({}){}",
+        id,
+        track.file_path,
+        if delete_file { " including file" } else { "" }
+    );
+
+    warp::reply::with_status(
+        warp::reply::json(&DeleteResult {
+            deleted: track,
+            file_deleted: delete_file,
+            skipped: was_playing,
+        }),
+        StatusCode::OK,
+    )
+}
+
+/// Set the cue points of a track
+///
+/// Marks the end of the intro and the start of the outro of a track, in seconds from its
+/// start. The next track starts at the outro and plays over it until its own intro ended.
+/// Setting both to `null` removes the cue points.
+#[utoipa::path(
+    put,
+    path = "/api/library/tracks/{id}/cue",
+    tag = "library",
+    operation_id = "setTrackCuePoints",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Track id")),
+    request_body = CuePoints,
+    responses(
+        (status = 200, description = "Cue points stored", body = CuePoints),
+        (status = 400, description = "Cue points outside the track or outro before intro", body = ApiError),
+        (status = 404, description = "Track not found", body = ApiError),
+    )
+)]
+fn cue_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "tracks" / i64 / "cue")
+        .and(warp::put())
+        .and(warp::body::json::<CuePoints>())
+        .map(move |id: i64, cue: CuePoints| {
+            let track = match db.get_track(id) {
+                Ok(Some(track)) => track,
+                Ok(None) => {
+                    return error_reply(StatusCode::NOT_FOUND, &format!("Track {} not found", id))
+                }
+                Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            };
+            if let Err(e) = validate_cue_points(&cue, track.duration_seconds) {
+                return error_reply(StatusCode::BAD_REQUEST, &e);
+            }
+
+            match db.set_cue_points(id, &cue) {
+                Ok(()) => warp::reply::with_status(warp::reply::json(&cue), StatusCode::OK),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Rate a track
+///
+/// Gives a track 1 to 5 stars. In the shuffled library rotation 5 star tracks play twice per
+/// pass and 1 star tracks only in one of four passes. Setting `null` removes the rating,
+/// unrated tracks play like 3 star tracks.
+#[utoipa::path(
+    put,
+    path = "/api/library/tracks/{id}/rating",
+    tag = "library",
+    operation_id = "setTrackRating",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Track id")),
+    request_body = TrackRating,
+    responses(
+        (status = 200, description = "Rating stored", body = TrackRecord),
+        (status = 400, description = "Rating outside of 1 to 5", body = ApiError),
+        (status = 404, description = "Track not found", body = ApiError),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn rating_route(
+    scanner: LibraryScanner,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "tracks" / i64 / "rating")
+        .and(warp::put())
+        .and(warp::body::json::<TrackRating>())
+        .map(move |id: i64, body: TrackRating| {
+            if body.rating.is_some_and(|rating| !(1..=5).contains(&rating)) {
+                return error_reply(StatusCode::BAD_REQUEST, "The rating must be 1 to 5 stars");
+            }
+
+            match scanner.rate_track(id, body.rating) {
+                Ok(Some(track)) => {
+                    warp::reply::with_status(warp::reply::json(&track), StatusCode::OK)
+                }
+                Ok(None) => error_reply(StatusCode::NOT_FOUND, &format!("Track {} not found", id)),
+                Err(e) => {
+                    log::error!("Failed to rate track {}: {}", id, e);
+                    error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                }
+            }
+        })
+}
+
+/// Cover art of a library track
+///
+/// Serves the thumbnail of the cover art embedded in the track, written to the artwork
+/// cache by the library scan.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/{id}/artwork",
+    tag = "library",
+    operation_id = "getTrackArtwork",
+    params(("id" = i64, Path, description = "Track id")),
+    responses(
+        (status = 200, description = "Cover art thumbnail", content_type = "image/jpeg", body = Thumbnail),
+        (status = 404, description = "Track not found or without cover art", body = ApiError),
+    )
+)]
+fn artwork_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "library" / "tracks" / i64 / "artwork")
+        .and(warp::get())
+        .and_then(move |id: i64| {
+            let db = db.clone();
+            async move { Ok::<_, warp::Rejection>(handle_artwork(db, id).await) }
+        })
+}
+
+async fn handle_artwork(db: LibraryDatabase, id: i64) -> warp::reply::Response {
+    let thumbnail = match db.get_track(id) {
+        Ok(Some(track)) => track.artwork_path,
+        Ok(None) => {
+            return error_reply(StatusCode::NOT_FOUND, &format!("Track {} not found", id))
+                .into_response()
+        }
+        Err(e) => {
+            return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()).into_response()
+        }
+    };
+
+    match thumbnail {
+        Some(path) => match tokio::fs::read(path).await {
+            Ok(data) => warp::reply::with_header(
+                warp::reply::with_header(data, "Content-Type", "image/jpeg"),
+                "Cache-Control",
+                "max-age=3600",
+            )
+            .into_response(),
+            Err(_) => error_reply(StatusCode::NOT_FOUND, "Track has no cover art").into_response(),
+        },
+        None => error_reply(StatusCode::NOT_FOUND, "Track has no cover art").into_response(),
+    }
+}
+
+fn validate_cue_points(cue: &CuePoints, duration_seconds: Option<i64>) -> Result<(), String> {
+    for (name, seconds) in [("intro", cue.intro_seconds), ("outro", cue.outro_seconds)] {
+        match (seconds, duration_seconds) {
+            (Some(seconds), _) if seconds.is_nan() || seconds < 0.0 => {
+                return Err(format!("The {} cue point must not be negative", name));
+            }
+            (Some(seconds), Some(duration)) if seconds > duration as f64 => {
+                return Err(format!(
+                    "The {} cue point {}s is beyond the end of the {}s track",
+                    name, seconds, duration
+                ));
+            }
+            _ => {}
+        }
+    }
+    match (cue.intro_seconds, cue.outro_seconds) {
+        (Some(intro), Some(outro)) if outro <= intro => {
+            Err("The outro must start after the intro".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn wait_until_stopped(playout_control: &PlayoutControl, path: &Path) -> bool {
+    let deadline = tokio::time::Instant::now() + SKIP_TIMEOUT;
+    while playout_control.is_playing(path) {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(SKIP_POLL_INTERVAL).await;
+    }
+    true
+}
+
+/// Streams the uploaded file chunk by chunk into a hidden partial file next to `target` and
+/// renames it once complete, so uploads are not held in memory and scans never see a part
+async fn write_upload(
+    inbox_directory: &Path,
+    target: &Path,
+    part: &mut Part,
+) -> Result<(), WithStatus<Json>> {
+    let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+    let partial = inbox_directory.join(format!(".{}.part", file_name));
+    let stored = async {
+        tokio::fs::create_dir_all(inbox_directory).await?;
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial)
+            .await
+    };
+    let mut file = match stored.await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(error_reply(
+                StatusCode::CONFLICT,
+                &format!("'{}' is already being uploaded", file_name),
+            ));
+        }
+        Err(e) => {
+            log::error!("Failed to store upload {:?}: {}", target, e);
+            return Err(error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &e.to_string(),
+            ));
+        }
+    };
+
+    let written = async {
+        while let Some(chunk) = part.data().await {
+            let chunk = chunk.map_err(|e| error_reply(StatusCode::BAD_REQUEST, &e.to_string()))?;
+            file.write_all(chunk.chunk()).await.map_err(|e| {
+                log::error!("Failed to store upload {:?}: {}", target, e);
+                error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            })?;
+        }
+        file.flush().await.map_err(|e| {
+            log::error!("Failed to store upload {:?}: {}", target, e);
+            error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        })?;
+        tokio::fs::rename(&partial, target).await.map_err(|e| {
+            log::error!("Failed to store upload {:?}: {}", target, e);
+            error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        })
+    }
+    .await;
+
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+    }
+    written
+}
+
+/// Strips any directory components so uploads cannot escape the inbox
+fn sanitize_file_name(file_name: &str) -> Option<String> {
+    let name = Path::new(file_name).file_name()?.to_str()?;
+    if name.starts_with('.') {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_reader::AudioReader;
+    use crate::http_client::HttpClientFactory;
+    use crate::track_cache::TrackCache;
+
+    #[test]
+    fn given_path_in_file_name_when_sanitized_then_only_file_name_remains() {
+        assert_eq!(
+            sanitize_file_name("../../etc/track.mp3"),
+            Some("track.mp3".to_string())
+        );
+        assert_eq!(
+            sanitize_file_name("song.flac"),
+            Some("song.flac".to_string())
+        );
+    }
+
+    #[test]
+    fn given_cue_points_when_validated_then_must_lie_within_the_track_in_order() {
+        let cue = |intro_seconds, outro_seconds| CuePoints {
+            intro_seconds,
+            outro_seconds,
+        };
+
+        assert!(validate_cue_points(&cue(Some(8.5), Some(170.0)), Some(180)).is_ok());
+        assert!(validate_cue_points(&cue(None, Some(170.0)), None).is_ok());
+        assert!(validate_cue_points(&cue(Some(-1.0), None), Some(180)).is_err());
+        assert!(validate_cue_points(&cue(None, Some(190.0)), Some(180)).is_err());
+        assert!(validate_cue_points(&cue(Some(20.0), Some(10.0)), Some(180)).is_err());
+    }
+
+    #[test]
+    fn given_hidden_or_empty_file_name_when_sanitized_then_rejected() {
+        assert_eq!(sanitize_file_name(".hidden.mp3"), None);
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name(""), None);
+    }
+
+    #[tokio::test]
+    async fn given_upload_when_stored_then_file_is_moved_out_of_its_partial_file() {
+        let music = tempfile::TempDir::new().unwrap();
+        let inbox = music.path().join("inbox");
+        let db_file = tempfile::NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let scanner = LibraryScanner::new(music.path().to_path_buf(), db);
+        let body = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"song.mp3\"\r\n\
+            Content-Type: audio/mpeg\r\n\r\n\
+            not really audio\r\n\
+            --boundary--\r\n";
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/library/upload")
+            .header("content-type", "multipart/form-data; boundary=boundary")
+            .body(body)
+            .reply(&upload_route(scanner, Some(inbox.clone())))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!inbox.join(".song.mp3.part").exists());
+        assert_eq!(
+            std::fs::read(inbox.join("song.mp3")).unwrap(),
+            b"not really audio"
+        );
+    }
+
+    #[tokio::test]
+    async fn given_track_deleted_without_its_file_when_rescanned_then_it_is_selected_again() {
+        let music = tempfile::TempDir::new().unwrap();
+        let db_file = tempfile::NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(db_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        let track_path = music.path().join("song.mp3");
+        std::fs::File::create(&track_path).unwrap();
+        let track_cache = TrackCache::load(db.clone()).unwrap();
+        let playout_control = PlayoutControl::new();
+        let scanner = LibraryScanner::new(music.path().to_path_buf(), db.clone())
+            .with_track_cache(track_cache.clone())
+            .with_playout_control(playout_control.clone());
+        scanner.full_scan().unwrap();
+        let mut reader = AudioReader::new(
+            music.path().to_path_buf(),
+            false,
+            true,
+            db.clone(),
+            track_cache,
+            playout_control.clone(),
+            HttpClientFactory::default(),
+        )
+        .unwrap();
+        let id = db.get_all_tracks().unwrap()[0].id.unwrap();
+
+        handle_delete(db.clone(), scanner.clone(), playout_control, id, false).await;
+        assert_eq!(reader.next_track(), None);
+
+        scanner.incremental_scan().unwrap();
+        assert_eq!(reader.next_track(), Some(track_path));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::csv_export::{csv_reply, CsvRecord, ExportFormat};
+use crate::listener_registry::{ListenerDetail, ListenerRegistry, ListenerSession, SESSION_COOKIE};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SessionQuery {
+    /// Session token, alternatively sent as `X-Session-Token` header or session cookie
+    token: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListenersQuery {
+    /// `csv` downloads the listeners as spreadsheet, default `json`
+    #[param(inline)]
+    format: Option<ExportFormat>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StreamListeners {
+    #[schema(example = "high")]
+    stream: String,
+    listener_count: usize,
+    listeners: Vec<ListenerDetail>,
+}
+
+/// Statistics of the caller's stream connection
+///
+/// Returns how long the stream connection has been open, which mount it is listening to,
+/// its bitrate and the bytes received so far. The session is identified by the
+/// `funkstrom_session` cookie set on connect, the `X-Session-Token` header or the `token`
+/// query parameter.
+#[utoipa::path(
+    get,
+    path = "/api/session",
+    tag = "listeners",
+    operation_id = "getSession",
+    params(SessionQuery),
+    responses(
+        (status = 200, description = "Active session", body = ListenerSession),
+        (status = 404, description = "No active stream session for the given token", body = ApiError),
+    )
+)]
+pub fn session_route(
+    registry: ListenerRegistry,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "session")
+        .and(warp::get())
+        .and(warp::cookie::optional::<String>(SESSION_COOKIE))
+        .and(warp::header::optional::<String>("x-session-token"))
+        .and(warp::query::<SessionQuery>())
+        .map(
+            move |cookie: Option<String>, header: Option<String>, query: SessionQuery| {
+                let session = query
+                    .token
+                    .or(header)
+                    .or(cookie)
+                    .and_then(|token| registry.session(&token));
+
+                match session {
+                    Some(session) => {
+                        warp::reply::with_status(warp::reply::json(&session), StatusCode::OK)
+                    }
+                    None => error_reply(StatusCode::NOT_FOUND, "No active stream session"),
+                }
+            },
+        )
+}
+
+/// Connected clients of a stream
+///
+/// Lists every client connected to the stream with its IP (or a hash of it when
+/// `anonymize_listener_ips` is enabled), user agent, connect time and bytes sent.
+/// With `format=csv` the listeners are returned as CSV download. Requires the admin token.
+#[utoipa::path(
+    get,
+    path = "/status/streams/{name}/listeners",
+    tag = "listeners",
+    operation_id = "getStreamListeners",
+    security(("admin_token" = [])),
+    params(("name" = String, Path, description = "Stream name"), ListenersQuery),
+    responses(
+        (status = 200, description = "Connected listeners, CSV with `format=csv`", content(
+            (StreamListeners = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 404, description = "Stream not found", body = ApiError),
+    )
+)]
+pub fn stream_listeners_route(
+    registry: ListenerRegistry,
+    is_stream: impl Fn(&str) -> bool + Clone + Send + Sync + 'static,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("status" / "streams" / String / "listeners")
+        .and(warp::get())
+        .and(warp::query::<ListenersQuery>())
+        .map(move |name: String, query: ListenersQuery| {
+            if !is_stream(&name) {
+                return error_reply(
+                    StatusCode::NOT_FOUND,
+                    &format!("Stream '{}' not found", name),
+                )
+                .into_response();
+            }
+
+            let listeners = registry.listeners_of(&name);
+            if query.format == Some(ExportFormat::Csv) {
+                return csv_reply(&listeners, &format!("listeners-{}.csv", name));
+            }
+
+            warp::reply::with_status(
+                warp::reply::json(&StreamListeners {
+                    stream: name,
+                    listener_count: listeners.len(),
+                    listeners,
+                }),
+                StatusCode::OK,
+            )
+            .into_response()
+        })
+}
+
+impl CsvRecord for ListenerDetail {
+    const HEADER: &'static [&'static str] = &[
+        "client",
+        "user_agent",
+        "connected_at",
+        "connected_seconds",
+        "bytes_sent",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.client.clone(),
+            self.user_agent.clone().unwrap_or_default(),
+            self.connected_at.clone(),
+            self.connected_seconds.to_string(),
+            self.bytes_sent.to_string(),
+        ]
+    }
+}
@@ -0,0 +1,195 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::ondemand::{ByteRange, OnDemandFile, OnDemandLibrary};
+use crate::server_icecast::AudioData;
+use bytes::Bytes;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_stream::wrappers::ReceiverStream;
+use warp::http::StatusCode;
+use warp::path::Tail;
+use warp::{Filter, Reply};
+
+/// Size of the chunks the transcoded files are sent in
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Routes of the on-demand mode, all requests are rejected as not found when it is disabled
+pub fn routes(
+    ondemand: Option<OnDemandLibrary>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    list_route(ondemand.clone()).or(file_route(ondemand))
+}
+
+/// List on-demand files
+///
+/// Lists the audio files of the on-demand directory with the metadata of the library, or of
+/// their tags if they are not part of the library.
+#[utoipa::path(
+    get,
+    path = "/ondemand",
+    tag = "ondemand",
+    operation_id = "listOnDemandFiles",
+    responses(
+        (status = 200, description = "Files ordered by path", body = Vec<OnDemandFile>),
+        (status = 404, description = "On-demand mode is not configured"),
+        (status = 500, description = "On-demand directory cannot be read", body = ApiError),
+    )
+)]
+fn list_route(
+    ondemand: Option<OnDemandLibrary>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("ondemand").and(warp::get()).and_then(move || {
+        let ondemand = ondemand.clone();
+        async move {
+            let Some(ondemand) = ondemand else {
+                return Err(warp::reject::not_found());
+            };
+
+            Ok(
+                match tokio::task::spawn_blocking(move || ondemand.list()).await {
+                    Ok(Ok(files)) => {
+                        warp::reply::with_status(warp::reply::json(&files), StatusCode::OK)
+                    }
+                    Ok(Err(e)) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+                    Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+                },
+            )
+        }
+    })
+}
+
+/// Play an on-demand file
+///
+/// Serves a file of the on-demand directory transcoded with the `[ondemand]` settings.
+/// The file is transcoded on its first request, which responds once transcoding finished;
+/// later requests are served from the cache. Supports a single `Range` for seeking.
+#[utoipa::path(
+    get,
+    path = "/ondemand/{path}",
+    tag = "ondemand",
+    operation_id = "getOnDemandFile",
+    params(
+        ("path" = String, Path, description = "Path relative to the on-demand directory, e.g. `shows/night.flac`"),
+        ("Range" = Option<String>, Header, description = "Byte range to send, e.g. `bytes=1048576-`"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Whole transcoded file",
+            content_type = "audio/mpeg",
+            body = AudioData,
+            headers(("Accept-Ranges" = String, description = "Always `bytes`"))
+        ),
+        (
+            status = 206,
+            description = "Requested range of the transcoded file",
+            content_type = "audio/mpeg",
+            body = AudioData,
+            headers(("Content-Range" = String, description = "Sent range, e.g. `bytes 0-1023/4096`"))
+        ),
+        (status = 404, description = "File not found or on-demand mode not configured"),
+        (status = 416, description = "Range starts beyond the end of the file"),
+        (status = 500, description = "Transcoding failed", body = ApiError),
+    )
+)]
+fn file_route(
+    ondemand: Option<OnDemandLibrary>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path("ondemand")
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("range"))
+        .and_then(move |tail: Tail, range: Option<String>| {
+            let ondemand = ondemand.clone();
+            async move {
+                let Some((ondemand, source)) =
+                    ondemand.and_then(|o| o.resolve(tail.as_str()).map(|source| (o, source)))
+                else {
+                    return Err(warp::reject::not_found());
+                };
+
+                let content_type = ondemand.content_type();
+                let transcoded =
+                    tokio::task::spawn_blocking(move || ondemand.transcoded(&source)).await;
+                Ok(match transcoded {
+                    Ok(Ok(path)) => file_response(path, content_type, range.as_deref()).await,
+                    Ok(Err(e)) => {
+                        log::error!("On-demand transcoding failed: {}", e);
+                        error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                            .into_response()
+                    }
+                    Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                        .into_response(),
+                })
+            }
+        })
+}
+
+/// Streams the requested range of a transcoded file
+async fn file_response(
+    path: PathBuf,
+    content_type: &str,
+    range: Option<&str>,
+) -> warp::reply::Response {
+    let opened = async {
+        let file = tokio::fs::File::open(&path).await?;
+        let len = file.metadata().await?.len();
+        Ok::<_, std::io::Error>((file, len))
+    };
+    let (mut file, len) = match opened.await {
+        Ok(opened) => opened,
+        Err(e) => {
+            return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()).into_response()
+        }
+    };
+
+    let response = warp::http::Response::builder()
+        .header("Accept-Ranges", "bytes")
+        .header("Access-Control-Allow-Origin", "*");
+    let (response, start, length) = match ByteRange::parse(range, len) {
+        ByteRange::Full => (response.status(StatusCode::OK), 0, len),
+        ByteRange::Partial(first, last) => (
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header("Content-Range", format!("bytes {}-{}/{}", first, last, len)),
+            first,
+            last - first + 1,
+        ),
+        ByteRange::Unsatisfiable => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("Content-Range", format!("bytes */{}", len))
+                .body(hyper::Body::empty())
+                .unwrap();
+        }
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(async move {
+        if let Err(e) = file.seek(std::io::SeekFrom::Start(start)).await {
+            let _ = tx.send(Err(e)).await;
+            return;
+        }
+
+        let mut remaining = length;
+        let mut buffer = vec![0u8; FILE_CHUNK_SIZE];
+        while remaining > 0 {
+            let wanted = remaining.min(FILE_CHUNK_SIZE as u64) as usize;
+            let chunk = match file.read(&mut buffer[..wanted]).await {
+                Ok(0) => break,
+                Ok(read) => Ok(Bytes::copy_from_slice(&buffer[..read])),
+                Err(e) => Err(e),
+            };
+            remaining -= chunk.as_ref().map_or(0, |c| c.len() as u64);
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    response
+        .header("Content-Type", content_type)
+        .header("Content-Length", length)
+        .body(hyper::Body::wrap_stream(ReceiverStream::new(rx)))
+        .unwrap()
+}
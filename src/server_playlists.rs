@@ -0,0 +1,273 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::library_db::{LibraryDatabase, Playlist, PlaylistSummary};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+use warp::{Filter, Reply};
+
+/// Name and tracks of a playlist to create or replace
+#[derive(Debug, Deserialize, ToSchema)]
+struct PlaylistInput {
+    /// Unique name, referenced by `stored_playlist` of scheduled programs
+    #[schema(example = "Friday Warmup")]
+    name: String,
+    /// Library track ids in playout order, a track may appear more than once
+    #[schema(example = json!([12, 7, 31]))]
+    track_ids: Vec<i64>,
+}
+
+pub fn routes(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    list_route(db.clone())
+        .or(create_route(db.clone()))
+        .or(get_route(db.clone()))
+        .or(update_route(db.clone()))
+        .or(delete_route(db))
+}
+
+/// List playlists
+///
+/// Lists the server-managed playlists and the playlist files indexed from the music directory
+/// with their track count and total duration.
+#[utoipa::path(
+    get,
+    path = "/api/playlists",
+    tag = "playlists",
+    operation_id = "listPlaylists",
+    responses(
+        (status = 200, description = "Playlists ordered by name", body = Vec<PlaylistSummary>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn list_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "playlists")
+        .and(warp::get())
+        .map(move || match db.get_playlists() {
+            Ok(playlists) => {
+                warp::reply::with_status(warp::reply::json(&playlists), StatusCode::OK)
+            }
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        })
+}
+
+/// Create a playlist
+///
+/// Stores a named playlist of library tracks. Scheduled programs play it by setting
+/// `stored_playlist` to its name.
+#[utoipa::path(
+    post,
+    path = "/api/playlists",
+    tag = "playlists",
+    operation_id = "createPlaylist",
+    security(("admin_token" = [])),
+    request_body = PlaylistInput,
+    responses(
+        (status = 201, description = "Playlist created", body = Playlist),
+        (status = 400, description = "Empty name or unknown track ids", body = ApiError),
+        (status = 409, description = "A playlist with this name exists", body = ApiError),
+    )
+)]
+fn create_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "playlists")
+        .and(warp::post())
+        .and(warp::body::json::<PlaylistInput>())
+        .map(move |input: PlaylistInput| {
+            if let Err(reply) = validate(&db, &input, None) {
+                return reply;
+            }
+
+            match db
+                .create_playlist(input.name.trim(), &input.track_ids)
+                .and_then(|id| db.get_playlist(id))
+            {
+                Ok(Some(playlist)) => {
+                    log::info!(
+                        "Created playlist '{}' with {} track(s)",
+                        playlist.name,
+                        playlist.tracks.len()
+                    );
+                    warp::reply::with_status(warp::reply::json(&playlist), StatusCode::CREATED)
+                }
+                Ok(None) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Playlist vanished"),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Get a playlist
+#[utoipa::path(
+    get,
+    path = "/api/playlists/{id}",
+    tag = "playlists",
+    operation_id = "getPlaylist",
+    params(("id" = i64, Path, description = "Playlist id")),
+    responses(
+        (status = 200, description = "Playlist with its tracks", body = Playlist),
+        (status = 404, description = "Playlist not found", body = ApiError),
+    )
+)]
+fn get_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "playlists" / i64)
+        .and(warp::get())
+        .map(move |id: i64| playlist_reply(&db, id, StatusCode::OK))
+}
+
+/// Replace a playlist
+///
+/// Renames the playlist and replaces its tracks. Programs pick up the change at their next
+/// start.
+#[utoipa::path(
+    put,
+    path = "/api/playlists/{id}",
+    tag = "playlists",
+    operation_id = "updatePlaylist",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Playlist id")),
+    request_body = PlaylistInput,
+    responses(
+        (status = 200, description = "Playlist updated", body = Playlist),
+        (status = 400, description = "Empty name or unknown track ids", body = ApiError),
+        (status = 404, description = "Playlist not found", body = ApiError),
+        (status = 409, description = "Name taken by another playlist or playlist indexed from a file", body = ApiError),
+    )
+)]
+fn update_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "playlists" / i64)
+        .and(warp::put())
+        .and(warp::body::json::<PlaylistInput>())
+        .map(move |id: i64, input: PlaylistInput| {
+            match db.get_playlist(id) {
+                Ok(Some(playlist)) => {
+                    if let Err(reply) = editable(&playlist) {
+                        return reply;
+                    }
+                }
+                Ok(None) => return playlist_not_found(id),
+                Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+            if let Err(reply) = validate(&db, &input, Some(id)) {
+                return reply;
+            }
+
+            match db.update_playlist(id, input.name.trim(), &input.track_ids) {
+                Ok(true) => playlist_reply(&db, id, StatusCode::OK),
+                Ok(false) => playlist_not_found(id),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Delete a playlist
+///
+/// Programs still referencing the playlist fall back to the library when they start.
+#[utoipa::path(
+    delete,
+    path = "/api/playlists/{id}",
+    tag = "playlists",
+    operation_id = "deletePlaylist",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Playlist id")),
+    responses(
+        (status = 200, description = "Playlist deleted", body = Playlist),
+        (status = 404, description = "Playlist not found", body = ApiError),
+        (status = 409, description = "The playlist is indexed from a file", body = ApiError),
+    )
+)]
+fn delete_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "playlists" / i64)
+        .and(warp::delete())
+        .map(move |id: i64| {
+            let playlist = match db.get_playlist(id) {
+                Ok(Some(playlist)) => playlist,
+                Ok(None) => return playlist_not_found(id),
+                Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            };
+            if let Err(reply) = editable(&playlist) {
+                return reply;
+            }
+
+            match db.delete_playlist(id) {
+                Ok(_) => {
+                    log::info!("Deleted playlist '{}'", playlist.name);
+                    warp::reply::with_status(warp::reply::json(&playlist), StatusCode::OK)
+                }
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Playlists indexed from a playlist file of the music directory are changed by editing the
+/// file, the next scan would undo changes made here
+fn editable(playlist: &Playlist) -> Result<(), WithStatus<Json>> {
+    match &playlist.source_path {
+        Some(source_path) => Err(error_reply(
+            StatusCode::CONFLICT,
+            &format!(
+                "Playlist '{}' is indexed from {}, edit the file instead",
+                playlist.name, source_path
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks the name and tracks of a playlist, `id` is the playlist being replaced
+fn validate(
+    db: &LibraryDatabase,
+    input: &PlaylistInput,
+    id: Option<i64>,
+) -> Result<(), WithStatus<Json>> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(error_reply(
+            StatusCode::BAD_REQUEST,
+            "Playlist name must not be empty",
+        ));
+    }
+
+    let internal_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+        error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+    };
+
+    let missing = db
+        .get_missing_track_ids(&input.track_ids)
+        .map_err(internal_error)?;
+    if !missing.is_empty() {
+        return Err(error_reply(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown track ids: {:?}", missing),
+        ));
+    }
+
+    match db.get_playlist_by_name(name).map_err(internal_error)? {
+        Some(existing) if Some(existing.id) != id => Err(error_reply(
+            StatusCode::CONFLICT,
+            &format!("Playlist '{}' already exists", name),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn playlist_reply(db: &LibraryDatabase, id: i64, status: StatusCode) -> WithStatus<Json> {
+    match db.get_playlist(id) {
+        Ok(Some(playlist)) => warp::reply::with_status(warp::reply::json(&playlist), status),
+        Ok(None) => playlist_not_found(id),
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn playlist_not_found(id: i64) -> WithStatus<Json> {
+    error_reply(StatusCode::NOT_FOUND, &format!("Playlist {} not found", id))
+}
@@ -0,0 +1,94 @@
+use crate::schedule_store::{ScheduleDiff, ScheduleExport, ScheduleStore};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportQuery {
+    /// Only validate and report the changes
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ImportResult {
+    dry_run: bool,
+    applied: bool,
+    /// Always `false`, applied schedules take effect right away
+    restart_required: bool,
+    diff: ScheduleDiff,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ImportErrors {
+    errors: Vec<String>,
+}
+
+/// Export the schedule
+///
+/// Returns the effective schedule (imported schedule or `[schedule]` config section) as JSON.
+#[utoipa::path(
+    get,
+    path = "/api/schedule/export",
+    tag = "schedule",
+    operation_id = "exportSchedule",
+    responses((status = 200, description = "Schedule export", body = ScheduleExport))
+)]
+pub fn export_route(
+    store: ScheduleStore,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "schedule" / "export")
+        .and(warp::get())
+        .map(move || warp::reply::json(&store.export()))
+}
+
+/// Import a schedule
+///
+/// Validates the given schedule and returns the changes compared to the current schedule.
+/// Unless `dry_run` is set, the schedule is stored in `./data/schedule.json` and applied right away.
+#[utoipa::path(
+    post,
+    path = "/api/schedule/import",
+    tag = "schedule",
+    operation_id = "importSchedule",
+    security(("admin_token" = [])),
+    params(ImportQuery),
+    request_body = ScheduleExport,
+    responses(
+        (status = 200, description = "Schedule validated (and applied unless dry run)", body = ImportResult),
+        (status = 400, description = "Validation failed", body = ImportErrors),
+    )
+)]
+pub fn import_route(
+    store: ScheduleStore,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "schedule" / "import")
+        .and(warp::post())
+        .and(warp::query::<ImportQuery>())
+        .and(warp::body::json::<ScheduleExport>())
+        .map(move |query: ImportQuery, import: ScheduleExport| {
+            let result = if query.dry_run {
+                store.preview_import(&import)
+            } else {
+                store.import(&import).map_err(|e| vec![e.to_string()])
+            };
+
+            match result {
+                Ok(diff) => warp::reply::with_status(
+                    warp::reply::json(&ImportResult {
+                        dry_run: query.dry_run,
+                        applied: !query.dry_run,
+                        restart_required: false,
+                        diff,
+                    }),
+                    StatusCode::OK,
+                ),
+                Err(errors) => warp::reply::with_status(
+                    warp::reply::json(&ImportErrors { errors }),
+                    StatusCode::BAD_REQUEST,
+                ),
+            }
+        })
+}
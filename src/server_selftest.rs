@@ -0,0 +1,45 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::selftest::{SelfTest, SelfTestReport};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+/// Get the startup self-test report
+///
+/// Results of the checks run at startup: configuration, FFmpeg codecs of the configured
+/// formats, database integrity, music directory and connectivity of enabled integrations.
+#[utoipa::path(
+    get,
+    path = "/api/admin/selftest",
+    tag = "admin",
+    operation_id = "getSelfTest",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Self-test report, check `passed` for the outcome", body = SelfTestReport),
+        (status = 404, description = "Self-test is not available"),
+        (status = 503, description = "Self-test is still running", body = ApiError),
+    )
+)]
+pub fn selftest_route(
+    selftest: Option<SelfTest>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "admin" / "selftest")
+        .and(warp::get())
+        .and_then(move || {
+            let selftest = selftest.clone();
+            async move {
+                let Some(selftest) = selftest else {
+                    return Err(warp::reject::not_found());
+                };
+
+                Ok(match selftest.report() {
+                    Some(report) => {
+                        warp::reply::with_status(warp::reply::json(&report), StatusCode::OK)
+                    }
+                    None => error_reply(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Self-test is still running",
+                    ),
+                })
+            }
+        })
+}
@@ -0,0 +1,283 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::library_db::{LibraryDatabase, SmartPlaylist, TrackRecord};
+use crate::smart_playlist::SmartQuery;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+use warp::{Filter, Reply};
+
+/// Name and query of a smart playlist to create or replace
+#[derive(Debug, Deserialize, ToSchema)]
+struct SmartPlaylistInput {
+    /// Unique name, referenced by `smart_playlist` of scheduled programs
+    #[schema(example = "Jazz Classics")]
+    name: String,
+    /// Track query, see the configuration docs for the syntax
+    #[schema(example = "genre = \"jazz\" AND year >= 1990 AND rating >= 3")]
+    query: String,
+}
+
+pub fn routes(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    list_route(db.clone())
+        .or(create_route(db.clone()))
+        .or(get_route(db.clone()))
+        .or(update_route(db.clone()))
+        .or(delete_route(db.clone()))
+        .or(tracks_route(db))
+}
+
+/// List smart playlists
+#[utoipa::path(
+    get,
+    path = "/api/smart-playlists",
+    tag = "playlists",
+    operation_id = "listSmartPlaylists",
+    responses(
+        (status = 200, description = "Smart playlists ordered by name", body = Vec<SmartPlaylist>),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+fn list_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists")
+        .and(warp::get())
+        .map(move || match db.get_smart_playlists() {
+            Ok(playlists) => {
+                warp::reply::with_status(warp::reply::json(&playlists), StatusCode::OK)
+            }
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        })
+}
+
+/// Create a smart playlist
+///
+/// Stores a named track query. Scheduled programs play the matching tracks by setting
+/// `smart_playlist` to its name, the query is evaluated each time the program starts.
+#[utoipa::path(
+    post,
+    path = "/api/smart-playlists",
+    tag = "playlists",
+    operation_id = "createSmartPlaylist",
+    security(("admin_token" = [])),
+    request_body = SmartPlaylistInput,
+    responses(
+        (status = 201, description = "Smart playlist created", body = SmartPlaylist),
+        (status = 400, description = "Empty name or invalid query", body = ApiError),
+        (status = 409, description = "A smart playlist with this name exists", body = ApiError),
+    )
+)]
+fn create_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists")
+        .and(warp::post())
+        .and(warp::body::json::<SmartPlaylistInput>())
+        .map(move |input: SmartPlaylistInput| {
+            if let Err(reply) = validate(&db, &input, None) {
+                return reply;
+            }
+
+            match db
+                .create_smart_playlist(input.name.trim(), input.query.trim())
+                .and_then(|id| db.get_smart_playlist(id))
+            {
+                Ok(Some(playlist)) => {
+                    log::info!(
+                        "Created smart playlist '{}': {}",
+                        playlist.name,
+                        playlist.query
+                    );
+                    warp::reply::with_status(warp::reply::json(&playlist), StatusCode::CREATED)
+                }
+                Ok(None) => {
+                    error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Smart playlist vanished")
+                }
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Get a smart playlist
+#[utoipa::path(
+    get,
+    path = "/api/smart-playlists/{id}",
+    tag = "playlists",
+    operation_id = "getSmartPlaylist",
+    params(("id" = i64, Path, description = "Smart playlist id")),
+    responses(
+        (status = 200, description = "Smart playlist with its query", body = SmartPlaylist),
+        (status = 404, description = "Smart playlist not found", body = ApiError),
+    )
+)]
+fn get_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists" / i64)
+        .and(warp::get())
+        .map(move |id: i64| smart_playlist_reply(&db, id, StatusCode::OK))
+}
+
+/// Replace a smart playlist
+///
+/// Renames the smart playlist and replaces its query. Programs pick up the change at their
+/// next start.
+#[utoipa::path(
+    put,
+    path = "/api/smart-playlists/{id}",
+    tag = "playlists",
+    operation_id = "updateSmartPlaylist",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Smart playlist id")),
+    request_body = SmartPlaylistInput,
+    responses(
+        (status = 200, description = "Smart playlist updated", body = SmartPlaylist),
+        (status = 400, description = "Empty name or invalid query", body = ApiError),
+        (status = 404, description = "Smart playlist not found", body = ApiError),
+        (status = 409, description = "Another smart playlist with this name exists", body = ApiError),
+    )
+)]
+fn update_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists" / i64)
+        .and(warp::put())
+        .and(warp::body::json::<SmartPlaylistInput>())
+        .map(move |id: i64, input: SmartPlaylistInput| {
+            if let Err(reply) = validate(&db, &input, Some(id)) {
+                return reply;
+            }
+
+            match db.update_smart_playlist(id, input.name.trim(), input.query.trim()) {
+                Ok(true) => smart_playlist_reply(&db, id, StatusCode::OK),
+                Ok(false) => smart_playlist_not_found(id),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Delete a smart playlist
+///
+/// Programs still referencing the smart playlist fall back to the library when they start.
+#[utoipa::path(
+    delete,
+    path = "/api/smart-playlists/{id}",
+    tag = "playlists",
+    operation_id = "deleteSmartPlaylist",
+    security(("admin_token" = [])),
+    params(("id" = i64, Path, description = "Smart playlist id")),
+    responses(
+        (status = 200, description = "Smart playlist deleted", body = SmartPlaylist),
+        (status = 404, description = "Smart playlist not found", body = ApiError),
+    )
+)]
+fn delete_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists" / i64)
+        .and(warp::delete())
+        .map(move |id: i64| {
+            let playlist = match db.get_smart_playlist(id) {
+                Ok(Some(playlist)) => playlist,
+                Ok(None) => return smart_playlist_not_found(id),
+                Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            };
+
+            match db.delete_smart_playlist(id) {
+                Ok(_) => {
+                    log::info!("Deleted smart playlist '{}'", playlist.name);
+                    warp::reply::with_status(warp::reply::json(&playlist), StatusCode::OK)
+                }
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Preview the tracks of a smart playlist
+///
+/// Evaluates the query against the library now, sorted by artist, album and title.
+#[utoipa::path(
+    get,
+    path = "/api/smart-playlists/{id}/tracks",
+    tag = "playlists",
+    operation_id = "getSmartPlaylistTracks",
+    params(("id" = i64, Path, description = "Smart playlist id")),
+    responses(
+        (status = 200, description = "Library tracks matching the query", body = Vec<TrackRecord>),
+        (status = 404, description = "Smart playlist not found", body = ApiError),
+        (status = 500, description = "Stored query is invalid or library database error", body = ApiError),
+    )
+)]
+fn tracks_route(
+    db: LibraryDatabase,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "smart-playlists" / i64 / "tracks")
+        .and(warp::get())
+        .map(move |id: i64| {
+            let playlist = match db.get_smart_playlist(id) {
+                Ok(Some(playlist)) => playlist,
+                Ok(None) => return smart_playlist_not_found(id),
+                Err(e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            };
+
+            match SmartQuery::parse(&playlist.query)
+                .map_err(Into::into)
+                .and_then(|query| query.tracks(&db))
+            {
+                Ok(tracks) => warp::reply::with_status(warp::reply::json(&tracks), StatusCode::OK),
+                Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            }
+        })
+}
+
+/// Checks the name and query of a smart playlist, `id` is the smart playlist being replaced
+fn validate(
+    db: &LibraryDatabase,
+    input: &SmartPlaylistInput,
+    id: Option<i64>,
+) -> Result<(), WithStatus<Json>> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(error_reply(
+            StatusCode::BAD_REQUEST,
+            "Smart playlist name must not be empty",
+        ));
+    }
+
+    if let Err(e) = SmartQuery::parse(&input.query) {
+        return Err(error_reply(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid query: {}", e),
+        ));
+    }
+
+    match db.get_smart_playlist_by_name(name) {
+        Ok(Some(existing)) if Some(existing.id) != id => Err(error_reply(
+            StatusCode::CONFLICT,
+            &format!("Smart playlist '{}' already exists", name),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &e.to_string(),
+        )),
+    }
+}
+
+fn smart_playlist_reply(db: &LibraryDatabase, id: i64, status: StatusCode) -> WithStatus<Json> {
+    match db.get_smart_playlist(id) {
+        Ok(Some(playlist)) => warp::reply::with_status(warp::reply::json(&playlist), status),
+        Ok(None) => smart_playlist_not_found(id),
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn smart_playlist_not_found(id: i64) -> WithStatus<Json> {
+    error_reply(
+        StatusCode::NOT_FOUND,
+        &format!("Smart playlist {} not found", id),
+    )
+}
@@ -0,0 +1,219 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::csv_export::{csv_reply, CsvRecord, ExportFormat};
+use crate::library_db::{PlayedItem, ProgramAudience};
+use crate::play_history::PlayHistory;
+use crate::program_stats::ProgramStats;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+/// Broadcasts returned unless a `limit` is given
+const DEFAULT_BROADCAST_LIMIT: usize = 50;
+
+/// Most broadcasts returned by a single request
+const MAX_BROADCAST_LIMIT: usize = 1000;
+
+/// Aired items returned unless a `limit` is given
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Most aired items returned by a single request
+const MAX_HISTORY_LIMIT: usize = 10000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ProgramStatsQuery {
+    /// Only broadcasts of this program
+    program: Option<String>,
+    /// Number of broadcasts to return, default 50, at most 1000
+    limit: Option<usize>,
+    /// `csv` downloads the broadcasts as spreadsheet, default `json`
+    #[param(inline)]
+    format: Option<ExportFormat>,
+}
+
+/// Listener statistics per program broadcast
+///
+/// Average and peak listeners and the listeners joining and leaving during each broadcast of
+/// a scheduled program. The program on air comes first with `ended_at` unset, followed by
+/// finished broadcasts, newest first. With `format=csv` they are returned as CSV download
+/// with local times instead of unix seconds.
+#[utoipa::path(
+    get,
+    path = "/api/stats/programs",
+    tag = "stats",
+    operation_id = "getProgramStats",
+    params(ProgramStatsQuery),
+    responses(
+        (status = 200, description = "Program broadcasts, newest first, CSV with `format=csv`", content(
+            ([ProgramAudience] = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 404, description = "Program statistics are not available"),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+pub fn programs_route(
+    program_stats: Option<ProgramStats>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "stats" / "programs")
+        .and(warp::get())
+        .and(warp::query::<ProgramStatsQuery>())
+        .and_then(move |query: ProgramStatsQuery| {
+            let program_stats = program_stats.clone();
+            async move {
+                let Some(program_stats) = program_stats else {
+                    return Err(warp::reject::not_found());
+                };
+
+                let limit = query
+                    .limit
+                    .unwrap_or(DEFAULT_BROADCAST_LIMIT)
+                    .min(MAX_BROADCAST_LIMIT);
+                Ok(
+                    match program_stats.broadcasts(query.program.as_deref(), limit) {
+                        Ok(broadcasts) if query.format == Some(ExportFormat::Csv) => {
+                            csv_reply(&broadcasts, "program-stats.csv")
+                        }
+                        Ok(broadcasts) => {
+                            warp::reply::with_status(warp::reply::json(&broadcasts), StatusCode::OK)
+                                .into_response()
+                        }
+                        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                            .into_response(),
+                    },
+                )
+            }
+        })
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryQuery {
+    /// Only items played at or after this time (unix seconds)
+    from: Option<i64>,
+    /// Only items played before this time (unix seconds)
+    until: Option<i64>,
+    /// Number of items to return, default 100, at most 10000
+    limit: Option<usize>,
+    /// `csv` downloads the items as spreadsheet, default `json`
+    #[param(inline)]
+    format: Option<ExportFormat>,
+}
+
+/// Play history
+///
+/// Every track, jingle, announcement and break that went on air, newest first. Items are
+/// kept for `history_retention_days` of the `[library]` section. With `format=csv` they are
+/// returned as CSV download with local times instead of unix seconds.
+#[utoipa::path(
+    get,
+    path = "/api/stats/history",
+    tag = "stats",
+    operation_id = "getPlayHistory",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Aired items, newest first, CSV with `format=csv`", content(
+            ([PlayedItem] = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 404, description = "The play history is not available"),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+pub fn history_route(
+    play_history: Option<PlayHistory>,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "stats" / "history")
+        .and(warp::get())
+        .and(warp::query::<HistoryQuery>())
+        .and_then(move |query: HistoryQuery| {
+            let play_history = play_history.clone();
+            async move {
+                let Some(play_history) = play_history else {
+                    return Err(warp::reject::not_found());
+                };
+
+                let limit = query
+                    .limit
+                    .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                    .min(MAX_HISTORY_LIMIT);
+                Ok(match play_history.items(query.from, query.until, limit) {
+                    Ok(items) if query.format == Some(ExportFormat::Csv) => {
+                        csv_reply(&items, "play-history.csv")
+                    }
+                    Ok(items) => {
+                        warp::reply::with_status(warp::reply::json(&items), StatusCode::OK)
+                            .into_response()
+                    }
+                    Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                        .into_response(),
+                })
+            }
+        })
+}
+
+impl CsvRecord for PlayedItem {
+    const HEADER: &'static [&'static str] = &[
+        "played_at",
+        "source",
+        "program",
+        "streams",
+        "title",
+        "artist",
+        "album",
+        "duration_seconds",
+        "file_path",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            local_time(self.played_at),
+            self.source.as_str().to_string(),
+            self.program.clone().unwrap_or_default(),
+            self.streams.join(" "),
+            self.title.clone(),
+            self.artist.clone(),
+            self.album.clone(),
+            self.duration_seconds
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+            self.file_path.clone(),
+        ]
+    }
+}
+
+impl CsvRecord for ProgramAudience {
+    const HEADER: &'static [&'static str] = &[
+        "program",
+        "started_at",
+        "ended_at",
+        "average_listeners",
+        "peak_listeners",
+        "joins",
+        "leaves",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.program.clone(),
+            local_time(self.started_at),
+            self.ended_at.map(local_time).unwrap_or_default(),
+            self.average_listeners.to_string(),
+            self.peak_listeners.to_string(),
+            self.joins.to_string(),
+            self.leaves.to_string(),
+        ]
+    }
+}
+
+/// Unix seconds as local time spreadsheets recognize, e.g. `2025-01-15 08:00:00`
+fn local_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
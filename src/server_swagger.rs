@@ -1,13 +1,101 @@
+use crate::{
+    server_icecast, server_library, server_listeners, server_ondemand, server_playlists,
+    server_schedule, server_selftest, server_smart_playlists, server_stats, server_webhooks,
+};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use warp::{Filter, Reply};
 
+/// OpenAPI spec generated from the route handlers and their response types
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Funkstrom API",
+        description = "**Funkstrom** is an Icecast-compatible internet radio server written in Rust.",
+        contact(name = "Funkstrom Project")
+    ),
+    modifiers(&AdminTokenScheme),
+    tags(
+        (name = "streaming", description = "Audio streaming endpoints"),
+        (name = "monitoring", description = "Server monitoring and status"),
+        (name = "metadata", description = "Track metadata information"),
+        (name = "info", description = "Server information pages"),
+        (name = "listeners", description = "Listener sessions and statistics"),
+        (name = "stats", description = "Audience reports"),
+        (name = "library", description = "Music library management"),
+        (name = "playlists", description = "Server-managed playlists"),
+        (name = "ondemand", description = "Seekable playback of single files"),
+        (name = "schedule", description = "Program schedule management"),
+        (name = "admin", description = "Station administration"),
+    ),
+    paths(
+        server_icecast::stream_route,
+        server_icecast::status_route,
+        server_icecast::current_route,
+        server_icecast::artwork_route,
+        server_icecast::info_route,
+        server_listeners::stream_listeners_route,
+        server_listeners::session_route,
+        server_library::problems_route,
+        server_library::broken_route,
+        server_library::duplicates_route,
+        server_library::search_route,
+        server_library::artists_route,
+        server_library::artist_tracks_route,
+        server_library::upload_route,
+        server_library::delete_route,
+        server_library::cue_route,
+        server_library::rating_route,
+        server_library::artwork_route,
+        server_playlists::list_route,
+        server_playlists::create_route,
+        server_playlists::get_route,
+        server_playlists::update_route,
+        server_playlists::delete_route,
+        server_smart_playlists::list_route,
+        server_smart_playlists::create_route,
+        server_smart_playlists::get_route,
+        server_smart_playlists::update_route,
+        server_smart_playlists::delete_route,
+        server_smart_playlists::tracks_route,
+        server_ondemand::list_route,
+        server_ondemand::file_route,
+        server_schedule::export_route,
+        server_schedule::import_route,
+        server_webhooks::register_route,
+        server_webhooks::list_route,
+        server_webhooks::delete_route,
+        server_webhooks::deliveries_route,
+        server_selftest::selftest_route,
+        server_stats::programs_route,
+        server_stats::history_route,
+    )
+)]
+struct ApiDoc;
+
+/// Documents the bearer token of the admin API (`[server] admin_token`)
+struct AdminTokenScheme;
+
+impl Modify for AdminTokenScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
 /// Serve the OpenAPI spec at /api-docs/openapi.yaml
 pub fn openapi_spec() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    let spec = ApiDoc::openapi()
+        .to_yaml()
+        .expect("OpenAPI spec must be serializable");
+
     warp::path!("api-docs" / "openapi.yaml")
         .and(warp::get())
-        .map(|| {
-            let spec = include_str!("../openapi.yaml");
-            warp::reply::with_header(spec, "Content-Type", "application/x-yaml")
-        })
+        .map(move || warp::reply::with_header(spec.clone(), "Content-Type", "application/x-yaml"))
 }
 
 /// Serve the Swagger UI HTML page at /api-docs
@@ -71,3 +159,85 @@ pub fn swagger_ui() -> impl Filter<Extract = impl Reply, Error = warp::Rejection
             warp::reply::html(html)
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_api_doc_when_generated_then_documents_all_api_routes() {
+        let spec = ApiDoc::openapi();
+
+        let paths: Vec<&String> = spec.paths.paths.keys().collect();
+        for path in [
+            "/{stream}",
+            "/status",
+            "/current",
+            "/current/artwork",
+            "/status/streams/{name}/listeners",
+            "/api/session",
+            "/api/library/broken",
+            "/api/library/duplicates",
+            "/api/library/search",
+            "/api/library/artists",
+            "/api/library/artists/{id}/tracks",
+            "/api/library/upload",
+            "/api/library/tracks/{id}",
+            "/api/library/tracks/{id}/cue",
+            "/api/library/tracks/{id}/rating",
+            "/api/library/tracks/{id}/artwork",
+            "/api/playlists",
+            "/api/playlists/{id}",
+            "/api/smart-playlists",
+            "/api/smart-playlists/{id}",
+            "/api/smart-playlists/{id}/tracks",
+            "/api/schedule/import",
+            "/ondemand",
+            "/ondemand/{path}",
+            "/admin/webhooks",
+            "/admin/webhooks/{id}",
+            "/admin/webhooks/deliveries",
+            "/api/admin/selftest",
+            "/api/stats/programs",
+            "/api/stats/history",
+        ] {
+            assert!(
+                paths.iter().any(|p| *p == path),
+                "{} is not documented",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn given_api_doc_when_generated_then_contains_response_schemas() {
+        let schemas = ApiDoc::openapi().components.unwrap().schemas;
+
+        for schema in [
+            "ServerStatus",
+            "StreamStatus",
+            "Track",
+            "ApiError",
+            "ScheduleExport",
+            "Webhook",
+            "Playlist",
+            "SmartPlaylist",
+            "OnDemandFile",
+            "SelfTestReport",
+            "ProgramAudience",
+            "DatabaseHealth",
+            "ProgramAudience",
+            "StreamRejection",
+            "RejectReason",
+            "CuePoints",
+            "PlayedItem",
+            "PlaySource",
+            "BrokenTrack",
+            "DuplicateGroup",
+            "DuplicateMatch",
+            "Artist",
+        ] {
+            assert!(schemas.contains_key(schema), "{} schema is missing", schema);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::api_error::{error_reply, ApiError};
+use crate::library_db::PendingDelivery;
+use crate::notifier::{NewWebhook, Notifier, Webhook};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+/// Register a webhook
+///
+/// The URL receives a signed JSON `POST` for every subscribed event (`track_change`,
+/// `program_start`, `program_end`, `listener_peak`, `scan_complete`). The response contains
+/// the secret used for the `X-Funkstrom-Signature` header; it is not returned again.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    operation_id = "registerWebhook",
+    security(("admin_token" = [])),
+    request_body = NewWebhook,
+    responses(
+        (status = 201, description = "Webhook registered", body = Webhook),
+        (status = 400, description = "Invalid webhook", body = ApiError),
+    )
+)]
+pub fn register_route(
+    notifier: Notifier,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "webhooks")
+        .and(warp::post())
+        .and(warp::body::json::<NewWebhook>())
+        .map(move |new: NewWebhook| match notifier.register(new) {
+            Ok(webhook) => {
+                warp::reply::with_status(warp::reply::json(&webhook), StatusCode::CREATED)
+            }
+            Err(e) => error_reply(StatusCode::BAD_REQUEST, &e.to_string()),
+        })
+}
+
+/// List the registered webhooks
+///
+/// Secrets are omitted.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    operation_id = "listWebhooks",
+    security(("admin_token" = [])),
+    responses((status = 200, description = "Registered webhooks", body = [Webhook]))
+)]
+pub fn list_route(
+    notifier: Notifier,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "webhooks")
+        .and(warp::get())
+        .map(move || warp::reply::json(&notifier.list()))
+}
+
+/// Remove a webhook
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{id}",
+    tag = "admin",
+    operation_id = "deleteWebhook",
+    security(("admin_token" = [])),
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "Webhook removed", body = Webhook),
+        (status = 404, description = "Webhook not found", body = ApiError),
+    )
+)]
+pub fn delete_route(
+    notifier: Notifier,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "webhooks" / String)
+        .and(warp::delete())
+        .map(move |id: String| match notifier.remove(&id) {
+            Ok(Some(webhook)) => {
+                warp::reply::with_status(warp::reply::json(&webhook), StatusCode::OK)
+            }
+            Ok(None) => error_reply(StatusCode::NOT_FOUND, "Webhook not found"),
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        })
+}
+
+/// List the webhook retry queue
+///
+/// Deliveries that failed all immediate attempts. They are retried with a growing delay of up
+/// to an hour and dropped a day after the event.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/deliveries",
+    tag = "admin",
+    operation_id = "listPendingDeliveries",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Pending deliveries ordered by their next attempt", body = [PendingDelivery]),
+        (status = 500, description = "Library database error", body = ApiError),
+    )
+)]
+pub fn deliveries_route(
+    notifier: Notifier,
+) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "webhooks" / "deliveries")
+        .and(warp::get())
+        .map(move || match notifier.pending_deliveries() {
+            Ok(deliveries) => {
+                warp::reply::with_status(warp::reply::json(&deliveries), StatusCode::OK)
+            }
+            Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        })
+}
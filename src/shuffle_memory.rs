@@ -0,0 +1,150 @@
+use crate::library_db::LibraryDatabase;
+use crate::track_cache::TrackCache;
+use log::{error, info};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Remembers when library tracks were played, so a track is not repeated within a window of
+/// hours. The plays are stored in the database and survive restarts; library mode and
+/// scheduled programs share them.
+pub struct ShuffleMemory {
+    db: LibraryDatabase,
+    track_cache: TrackCache,
+    window_seconds: i64,
+    /// Last play per track id (unix seconds)
+    played: HashMap<i64, i64>,
+}
+
+impl ShuffleMemory {
+    pub fn load(db: LibraryDatabase, track_cache: TrackCache, hours: u32) -> Self {
+        Self::load_at(db, track_cache, hours, chrono::Local::now().timestamp())
+    }
+
+    /// Records that the track was played, files outside the library are ignored
+    pub fn track_played(&mut self, track: &Path) {
+        self.played_at(track, chrono::Local::now().timestamp());
+    }
+
+    /// Whether the track was played within the window
+    pub fn is_recent(&self, track: &Path) -> bool {
+        self.is_recent_at(track, chrono::Local::now().timestamp())
+    }
+
+    fn load_at(db: LibraryDatabase, track_cache: TrackCache, hours: u32, now: i64) -> Self {
+        let window_seconds = i64::from(hours) * 3600;
+        let played: HashMap<i64, i64> = match db.get_recent_plays(now - window_seconds) {
+            Ok(plays) => plays.into_iter().collect(),
+            Err(e) => {
+                error!("Failed to load recently played tracks: {}", e);
+                HashMap::new()
+            }
+        };
+        info!(
+            "No repeat within {}h, {} track(s) played recently",
+            hours,
+            played.len()
+        );
+
+        Self {
+            db,
+            track_cache,
+            window_seconds,
+            played,
+        }
+    }
+
+    fn played_at(&mut self, track: &Path, now: i64) {
+        let Some(track_id) = self.track_cache.track_id(track) else {
+            return;
+        };
+
+        let cutoff = now - self.window_seconds;
+        self.played.retain(|_, played_at| *played_at >= cutoff);
+        self.played.insert(track_id, now);
+
+        if let Err(e) = self
+            .db
+            .record_recent_play(track_id, now)
+            .and_then(|_| self.db.prune_recent_plays(cutoff))
+        {
+            error!("Failed to store play of {:?}: {}", track, e);
+        }
+    }
+
+    fn is_recent_at(&self, track: &Path, now: i64) -> bool {
+        self.track_cache
+            .track_id(track)
+            .and_then(|track_id| self.played.get(&track_id))
+            .is_some_and(|played_at| now - played_at < self.window_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library_db::TrackRecord;
+    use tempfile::NamedTempFile;
+
+    fn create_test_db() -> (LibraryDatabase, TrackCache, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        for file_path in ["/music/a.mp3", "/music/b.mp3"] {
+            db.insert_track(&TrackRecord {
+                id: None,
+                file_path: file_path.to_string(),
+                title: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                duration_seconds: Some(180),
+                file_size: 1000,
+                last_modified: 1234567890,
+                file_extension: "mp3".to_string(),
+                created_at: 1234567890,
+                updated_at: 1234567890,
+                genre: None,
+                artwork_path: None,
+                rating: None,
+                content_hash: None,
+                audio_fingerprint: None,
+                validation_error: None,
+                loudness_lufs: None,
+                bpm: None,
+                energy: None,
+                start_seconds: None,
+                end_seconds: None,
+                year: None,
+            })
+            .unwrap();
+        }
+        let track_cache = TrackCache::load(db.clone()).unwrap();
+        (db, track_cache, temp_file)
+    }
+
+    #[test]
+    fn given_played_track_when_checking_then_recent_until_window_passed() {
+        let (db, track_cache, _temp) = create_test_db();
+        let mut memory = ShuffleMemory::load_at(db, track_cache, 2, 10_000);
+        let track = Path::new("/music/a.mp3");
+
+        memory.played_at(track, 10_000);
+
+        assert!(memory.is_recent_at(track, 10_000 + 7199));
+        assert!(!memory.is_recent_at(track, 10_000 + 7200));
+        assert!(!memory.is_recent_at(Path::new("/music/b.mp3"), 10_000));
+        assert!(!memory.is_recent_at(Path::new("https://example.com/live"), 10_000));
+    }
+
+    #[test]
+    fn given_plays_before_restart_when_loaded_then_still_recent() {
+        let (db, track_cache, _temp) = create_test_db();
+        let mut memory = ShuffleMemory::load_at(db.clone(), track_cache.clone(), 2, 10_000);
+        memory.played_at(Path::new("/music/a.mp3"), 10_000);
+        memory.played_at(Path::new("/music/b.mp3"), 2_000);
+
+        let restarted = ShuffleMemory::load_at(db, track_cache, 2, 11_000);
+
+        assert!(restarted.is_recent_at(Path::new("/music/a.mp3"), 11_000));
+        assert!(!restarted.is_recent_at(Path::new("/music/b.mp3"), 11_000));
+    }
+}
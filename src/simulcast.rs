@@ -0,0 +1,199 @@
+use crate::audio_reader::QueuedTrack;
+use crate::transitions::PcmFormat;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::{debug, info};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far a stream may encode ahead of the station clock
+const PACING_LEAD: Duration = Duration::from_secs(2);
+
+/// Shared time base of all streams, so simulcast mounts encode the same audio at the same time
+#[derive(Clone)]
+pub struct StationClock {
+    epoch: Instant,
+}
+
+impl Default for StationClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StationClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+/// Paces the PCM audio of a stream to real time on the station clock
+pub struct Pacer {
+    clock: StationClock,
+    format: PcmFormat,
+    /// Station time at which the next written audio plays
+    position: Duration,
+}
+
+impl Pacer {
+    pub fn new(clock: StationClock, format: PcmFormat) -> Self {
+        let position = clock.now();
+        Self {
+            clock,
+            format,
+            position,
+        }
+    }
+
+    /// Waits until `bytes` more audio may be written without running ahead of the clock,
+    /// returns the station time at which that audio starts playing
+    pub async fn pace(&mut self, bytes: usize) -> Duration {
+        let now = self.clock.now();
+        let wait = self.advance(bytes, now);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        self.position - self.format.duration_of(bytes)
+    }
+
+    /// Accounts `bytes` of audio written at station time `now`, returns how long to wait first.
+    ///
+    /// A stream that fell behind, e.g. while waiting for a track, continues at the current
+    /// station time instead of rushing to catch up.
+    fn advance(&mut self, bytes: usize, now: Duration) -> Duration {
+        if self.position < now {
+            if now - self.position > PACING_LEAD {
+                debug!(
+                    "Stream fell {:?} behind the station clock, resyncing",
+                    now - self.position
+                );
+            }
+            self.position = now;
+        }
+
+        let wait = self.position.saturating_sub(now + PACING_LEAD);
+        self.position += self.format.duration_of(bytes);
+        wait
+    }
+}
+
+/// Hands every queued track to all streams, so simulcast mounts play the same program
+#[derive(Clone)]
+pub struct TrackFanout {
+    subscribers: Arc<Mutex<Vec<Sender<QueuedTrack>>>>,
+}
+
+impl TrackFanout {
+    pub fn new(track_rx: Receiver<QueuedTrack>) -> Self {
+        let subscribers: Arc<Mutex<Vec<Sender<QueuedTrack>>>> = Arc::default();
+        let fanout = Self {
+            subscribers: Arc::clone(&subscribers),
+        };
+
+        std::thread::spawn(move || loop {
+            // Keep tracks queued until the first stream subscribed
+            if subscribers.lock().unwrap().is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            let Ok(track) = track_rx.recv() else {
+                info!("Track queue closed, stopping fan-out");
+                break;
+            };
+
+            let senders = subscribers.lock().unwrap().clone();
+            for sender in senders {
+                // Blocks until the stream took the previous track, keeping the mounts in step
+                if sender.send(track.clone()).is_err() {
+                    debug!("Stream track queue closed, unsubscribing");
+                    subscribers
+                        .lock()
+                        .unwrap()
+                        .retain(|s| !s.same_channel(&sender));
+                }
+            }
+        });
+
+        fanout
+    }
+
+    /// Track queue of a single stream
+    pub fn subscribe(&self) -> Receiver<QueuedTrack> {
+        let (track_tx, track_rx) = bounded(1);
+        self.subscribers.lock().unwrap().push(track_tx);
+        track_rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const FORMAT: PcmFormat = PcmFormat {
+        sample_rate: 1000,
+        channels: 1,
+    };
+
+    #[test]
+    fn given_stream_ahead_of_clock_when_advancing_then_waits_beyond_lead() {
+        let mut pacer = Pacer::new(StationClock::new(), FORMAT);
+        pacer.position = Duration::ZERO;
+
+        // 5 seconds of audio written at once
+        assert_eq!(pacer.advance(10_000, Duration::ZERO), Duration::ZERO);
+        assert_eq!(
+            pacer.advance(2_000, Duration::from_secs(1)),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn given_stream_behind_clock_when_advancing_then_resyncs_without_waiting() {
+        let mut pacer = Pacer::new(StationClock::new(), FORMAT);
+        pacer.position = Duration::from_secs(1);
+
+        assert_eq!(
+            pacer.advance(2_000, Duration::from_secs(10)),
+            Duration::ZERO
+        );
+        assert_eq!(pacer.position, Duration::from_secs(11));
+    }
+
+    #[test]
+    fn given_two_subscribers_when_track_queued_then_both_receive_it() {
+        let (track_tx, track_rx) = bounded(2);
+        let fanout = TrackFanout::new(track_rx);
+        let high = fanout.subscribe();
+        let low = fanout.subscribe();
+
+        track_tx
+            .send(QueuedTrack {
+                path: PathBuf::from("/music/song.mp3"),
+                playout: 1,
+                transition: None,
+                voice_over: None,
+                program: None,
+                cue: None,
+                gain_db: None,
+                section: None,
+            })
+            .unwrap();
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            high.recv_timeout(timeout).unwrap().path,
+            PathBuf::from("/music/song.mp3")
+        );
+        assert_eq!(
+            low.recv_timeout(timeout).unwrap().path,
+            PathBuf::from("/music/song.mp3")
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use crate::dayparting::split_genres;
+use crate::library_db::{LibraryDatabase, TrackRecord};
+use std::error::Error;
+
+/// Saved query selecting library tracks, e.g. `genre = "jazz" AND year >= 1990 AND rating >= 3`.
+///
+/// Comparisons `field op value` are combined with `AND`, `OR`, `NOT` and parentheses, keywords
+/// ignore case. Text fields (`title`, `artist`, `album`, `genre`) support `=`, `!=` and `~`
+/// (contains), ignoring case; `genre = "jazz"` matches any of the genres of a track.
+/// Number fields (`year`, `rating`, `duration` in seconds) support `=`, `!=`, `<`, `<=`, `>`
+/// and `>=`. Comparisons with a missing tag or an unrated track are false.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartQuery {
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Text(TextField, Operator, String),
+    Number(NumberField, Operator, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NumberField {
+    Year,
+    Rating,
+    Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Contains,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+impl SmartQuery {
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Err("The query must not be empty".to_string());
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Self { expression }),
+            Some(token) => Err(format!("Unexpected {} after the query", describe(token))),
+        }
+    }
+
+    pub fn matches(&self, track: &TrackRecord) -> bool {
+        self.expression.matches(track)
+    }
+
+    /// Playable library tracks matching the query, sorted by artist, album and title
+    pub fn tracks(
+        &self,
+        db: &LibraryDatabase,
+    ) -> Result<Vec<TrackRecord>, Box<dyn Error + Send + Sync>> {
+        let mut tracks: Vec<TrackRecord> = db
+            .get_playable_tracks()?
+            .into_iter()
+            .filter(|track| self.matches(track))
+            .collect();
+        tracks.sort_by(|a, b| {
+            (&a.artist, &a.album, &a.title, &a.file_path).cmp(&(
+                &b.artist,
+                &b.album,
+                &b.title,
+                &b.file_path,
+            ))
+        });
+        Ok(tracks)
+    }
+}
+
+impl Expression {
+    fn matches(&self, track: &TrackRecord) -> bool {
+        match self {
+            Expression::And(left, right) => left.matches(track) && right.matches(track),
+            Expression::Or(left, right) => left.matches(track) || right.matches(track),
+            Expression::Not(inner) => !inner.matches(track),
+            Expression::Text(field, operator, value) => {
+                let values: Vec<String> = match field {
+                    TextField::Title => vec![track.title.to_lowercase()],
+                    TextField::Artist => vec![track.artist.to_lowercase()],
+                    TextField::Album => vec![track.album.to_lowercase()],
+                    TextField::Genre => match (&track.genre, operator) {
+                        (None, _) => return false,
+                        (Some(genre), Operator::Contains) => vec![genre.to_lowercase()],
+                        (Some(genre), _) => split_genres(genre),
+                    },
+                };
+                match operator {
+                    Operator::Equal => values.iter().any(|v| v == value),
+                    Operator::NotEqual => values.iter().all(|v| v != value),
+                    Operator::Contains => values.iter().any(|v| v.contains(value.as_str())),
+                    _ => false,
+                }
+            }
+            Expression::Number(field, operator, value) => {
+                let actual = match field {
+                    NumberField::Year => track.year.map(f64::from),
+                    NumberField::Rating => track.rating.map(f64::from),
+                    NumberField::Duration => track.duration_seconds.map(|d| d as f64),
+                };
+                let Some(actual) = actual else {
+                    return false;
+                };
+                match operator {
+                    Operator::Equal => actual == *value,
+                    Operator::NotEqual => actual != *value,
+                    Operator::Less => actual < *value,
+                    Operator::LessOrEqual => actual <= *value,
+                    Operator::Greater => actual > *value,
+                    Operator::GreaterOrEqual => actual >= *value,
+                    Operator::Contains => false,
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.keyword("OR") {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while self.keyword("AND") {
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.keyword("NOT") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expression = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expression),
+                    _ => Err("Missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Word(field)) => self.comparison(&field),
+            Some(token) => Err(format!("Expected a field, found {}", describe(&token))),
+            None => Err("Unexpected end of the query".to_string()),
+        }
+    }
+
+    fn comparison(&mut self, field: &str) -> Result<Expression, String> {
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            _ => return Err(format!("Expected an operator after '{}'", field)),
+        };
+        let value = self.next();
+
+        let text_field = match field.to_lowercase().as_str() {
+            "title" => Some(TextField::Title),
+            "artist" => Some(TextField::Artist),
+            "album" => Some(TextField::Album),
+            "genre" => Some(TextField::Genre),
+            _ => None,
+        };
+        if let Some(text_field) = text_field {
+            if !matches!(
+                operator,
+                Operator::Equal | Operator::NotEqual | Operator::Contains
+            ) {
+                return Err(format!("'{}' only supports =, != and ~", field));
+            }
+            return match value {
+                Some(Token::Text(text) | Token::Word(text)) => {
+                    Ok(Expression::Text(text_field, operator, text.to_lowercase()))
+                }
+                _ => Err(format!("Expected a text after '{}'", field)),
+            };
+        }
+
+        let number_field = match field.to_lowercase().as_str() {
+            "year" => NumberField::Year,
+            "rating" => NumberField::Rating,
+            "duration" => NumberField::Duration,
+            _ => {
+                return Err(format!(
+                    "Unknown field '{}', expected title, artist, album, genre, year, rating or duration",
+                    field
+                ))
+            }
+        };
+        if operator == Operator::Contains {
+            return Err(format!("'{}' does not support ~", field));
+        }
+        match value {
+            Some(Token::Word(number)) => number
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(|number| Expression::Number(number_field, operator, number))
+                .ok_or_else(|| format!("Expected a number after '{}', found '{}'", field, number)),
+            _ => Err(format!("Expected a number after '{}'", field)),
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err("Unterminated quoted text".to_string()),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            '=' | '~' => {
+                chars.next();
+                tokens.push(Token::Operator(if c == '=' {
+                    Operator::Equal
+                } else {
+                    Operator::Contains
+                }));
+            }
+            '!' | '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                let operator = match (c, or_equal) {
+                    ('!', true) => Operator::NotEqual,
+                    ('<', false) => Operator::Less,
+                    ('<', true) => Operator::LessOrEqual,
+                    ('>', false) => Operator::Greater,
+                    ('>', true) => Operator::GreaterOrEqual,
+                    _ => return Err("Expected '=' after '!'".to_string()),
+                };
+                tokens.push(Token::Operator(operator));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "()\"'=~!<>".contains(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("'{}'", word),
+        Token::Text(text) => format!("\"{}\"", text),
+        Token::Operator(_) => "an operator".to_string(),
+        Token::Open => "'('".to_string(),
+        Token::Close => "')'".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(genre: Option<&str>, year: Option<i32>, rating: Option<u8>) -> TrackRecord {
+        TrackRecord {
+            id: None,
+            file_path: "/music/track.mp3".to_string(),
+            title: "So What".to_string(),
+            artist: "Miles Davis".to_string(),
+            album: "Kind of Blue".to_string(),
+            duration_seconds: Some(562),
+            file_size: 1000,
+            last_modified: 0,
+            file_extension: "mp3".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            genre: genre.map(String::from),
+            year,
+            artwork_path: None,
+            rating,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+        }
+    }
+
+    #[test]
+    fn given_query_when_matching_tracks_then_combines_comparisons() {
+        let query =
+            SmartQuery::parse(r#"genre = "jazz" AND year >= 1990 AND rating >= 3"#).unwrap();
+
+        assert!(query.matches(&track(Some("Jazz; Chillout"), Some(1995), Some(4))));
+        assert!(!query.matches(&track(Some("Acid Jazz"), Some(1995), Some(4))));
+        assert!(!query.matches(&track(Some("Jazz"), Some(1959), Some(4))));
+        assert!(!query.matches(&track(Some("Jazz"), Some(1995), None)));
+    }
+
+    #[test]
+    fn given_query_with_or_not_and_parentheses_when_matching_then_follows_precedence() {
+        let query = SmartQuery::parse(
+            "artist ~ miles and not (genre = 'fusion' or duration > 600) OR rating = 5",
+        )
+        .unwrap();
+
+        assert!(query.matches(&track(Some("Jazz"), None, None)));
+        assert!(!query.matches(&track(Some("Fusion"), None, None)));
+        assert!(query.matches(&track(Some("Fusion"), None, Some(5))));
+        // Without a genre tag the track is no fusion track
+        assert!(query.matches(&track(None, None, None)));
+    }
+
+    #[test]
+    fn given_invalid_query_when_parsing_then_explains_the_error() {
+        for (query, error) in [
+            ("", "must not be empty"),
+            ("bpm > 120", "Unknown field 'bpm'"),
+            ("year >= nineteen", "Expected a number"),
+            ("title > 'a'", "only supports"),
+            ("genre = \"jazz", "Unterminated"),
+            ("(rating > 3", "Missing closing parenthesis"),
+            ("rating > 3 year < 2000", "Unexpected 'year'"),
+            ("rating ! 3", "Expected '='"),
+        ] {
+            let result = SmartQuery::parse(query).unwrap_err();
+            assert!(result.contains(error), "{}: {}", query, result);
+        }
+    }
+}
@@ -0,0 +1,1076 @@
+use crate::access_log::{AccessLog, AccessLogFormat};
+use crate::ad_breaks::AdBreakScheduler;
+use crate::announcements::{Announcements, ANNOUNCEMENT_CACHE_PATH};
+use crate::artwork_cache::{ArtworkCache, ARTWORK_CACHE_PATH};
+use crate::audio_buffer::StreamBuffer;
+use crate::audio_metadata::TrackMetadata;
+use crate::audio_processor::AudioChunk;
+use crate::audio_reader::AudioReader;
+use crate::audio_stage::AudioStages;
+use crate::cli::{CliArgs, CliCommand};
+use crate::clock_alignment::DEFAULT_ALIGN_TOLERANCE_SECONDS;
+use crate::config::Config;
+use crate::dayparting::Dayparting;
+use crate::file_validator::FileValidator;
+use crate::http_client::HttpClientFactory;
+use crate::jingles::Jingles;
+use crate::library_db::{DuplicateMatch, LibraryDatabase};
+use crate::library_scanner::{LibraryScanner, DEFAULT_RECONCILE_HOURS};
+use crate::listener_registry::ListenerRegistry;
+use crate::loudness_analysis::{LoudnessAnalysis, LoudnessAnalyzer};
+use crate::notifier::{Notifier, WEBHOOK_STORE_PATH};
+use crate::ondemand::{OnDemandLibrary, ONDEMAND_CACHE_PATH};
+use crate::page_templates::PageTemplates;
+use crate::play_history::{PlayHistory, DEFAULT_RETENTION_DAYS};
+use crate::playout_control::PlayoutControl;
+use crate::program_access::{ProgramAccess, ACCESS_CACHE_PATH};
+use crate::program_intros::ProgramIntros;
+use crate::program_stats::ProgramStats;
+use crate::schedule_engine::{PlaylistCommand, ScheduleEngine};
+use crate::schedule_store::{ScheduleExport, ScheduleStore, SCHEDULE_STORE_PATH};
+use crate::selftest::SelfTest;
+use crate::server_icecast::IcecastServer;
+use crate::server_library::LibraryApi;
+use crate::shuffle_memory::ShuffleMemory;
+use crate::station_id::StationId;
+use crate::station_metadata::StationMetadata;
+use crate::stream_encoders::{MountOutput, StreamEncoders};
+use crate::tempo_analysis::TempoAnalyzer;
+use crate::track_cache::TrackCache;
+use crate::track_hashes::AudioFingerprinter;
+use crate::track_quarantine::TrackQuarantine;
+use crossbeam_channel::{Receiver, Sender};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// Components the audio pipeline is built from, kept by the config reload so a station in
+/// standby can bring its streams up once one is enabled
+#[derive(Clone)]
+struct AudioComponents {
+    db: LibraryDatabase,
+    track_cache: TrackCache,
+    playout_control: PlayoutControl,
+    notifier: Notifier,
+    program_stats: ProgramStats,
+    play_history: PlayHistory,
+    station_metadata: StationMetadata,
+    schedule_store: ScheduleStore,
+    http: HttpClientFactory,
+    current_metadata: Arc<Mutex<TrackMetadata>>,
+    stages: AudioStages,
+}
+
+/// Runs the command given on the command line, serving the station until it stops for
+/// `CliCommand::Serve`. Data is kept in `./data` of the working directory.
+pub async fn run(args: CliArgs) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    run_with_stages(args, AudioStages::registered()).await
+}
+
+/// Like [`run`], with the audio stages the streams can pick with their `stages` option, so
+/// a binary depending on this crate can add its own stages without changing it.
+///
+/// ```no_run
+/// use funkstrom::audio_stage::{AudioStage, AudioStages};
+/// use funkstrom::transitions::PcmFormat;
+///
+/// /// Halves the volume
+/// struct Attenuate;
+///
+/// impl AudioStage for Attenuate {
+///     fn process(&mut self, samples: &mut [i16], _format: PcmFormat) {
+///         samples.iter_mut().for_each(|sample| *sample /= 2);
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+///     let mut stages = AudioStages::registered();
+///     stages.register("attenuate", |_| Box::new(Attenuate));
+///     funkstrom::station::run_with_stages(funkstrom::cli::parse_args(), stages).await
+/// }
+/// ```
+pub async fn run_with_stages(
+    args: CliArgs,
+    stages: AudioStages,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    std::fs::create_dir_all("./data")?;
+
+    // Load config
+    let config = Config::from_file(&args.config_path)?;
+    let schedule_store = load_schedule_store(&config)?;
+
+    match args.command {
+        CliCommand::Serve => {}
+        CliCommand::ScheduleExport { output } => {
+            return export_schedule(&schedule_store, output.as_deref());
+        }
+        CliCommand::ScheduleImport { file, dry_run } => {
+            return import_schedule(&schedule_store, &file, dry_run);
+        }
+        CliCommand::SchedulePreview { days } => {
+            return preview_schedule(&schedule_store, days);
+        }
+        CliCommand::LibraryDuplicates => {
+            return report_duplicates(&open_database()?);
+        }
+    }
+
+    log_startup_info(&config);
+
+    // Initialize components
+    let http = HttpClientFactory::new(config.http.as_ref(), &config.station)?;
+    let db = open_database()?;
+    db.start_health_check();
+    let notifier =
+        Notifier::load(Path::new(WEBHOOK_STORE_PATH), &http)?.with_retry_queue(db.clone());
+    notifier.start_retry_queue();
+    let program_stats = ProgramStats::new(db.clone());
+    let track_cache = TrackCache::load(db.clone())?;
+    let play_history = PlayHistory::new(
+        db.clone(),
+        track_cache.clone(),
+        config
+            .stream
+            .iter()
+            .filter(|(_, stream)| stream.enabled)
+            .map(|(name, _)| name.clone())
+            .collect(),
+        config
+            .library
+            .history_retention_days
+            .unwrap_or(DEFAULT_RETENTION_DAYS),
+    );
+    let playout_control = PlayoutControl::new();
+    let scanner = initialize_library(
+        &config,
+        db.clone(),
+        notifier.clone(),
+        track_cache.clone(),
+        playout_control.clone(),
+    )?;
+    if let Some(target) = config.library.loudness_target_lufs {
+        log::info!("Loudness normalization to {} LUFS enabled", target);
+        LoudnessAnalysis::new(
+            db.clone(),
+            track_cache.clone(),
+            LoudnessAnalyzer::new(config.server.ffmpeg_path.clone()),
+        )
+        .start();
+    }
+    let selftest = SelfTest::new(
+        &config,
+        &schedule_store.programs(),
+        db.clone(),
+        http.clone(),
+    );
+    selftest.run().await;
+    let audio = AudioComponents {
+        db: db.clone(),
+        track_cache,
+        playout_control: playout_control.clone(),
+        notifier: notifier.clone(),
+        program_stats: program_stats.clone(),
+        play_history: play_history.clone(),
+        station_metadata: StationMetadata::new(&config.station, schedule_store.subscribe()),
+        schedule_store: schedule_store.clone(),
+        http,
+        current_metadata: Arc::new(Mutex::new(TrackMetadata::default())),
+        stages,
+    };
+    let access = setup_access(&config, &schedule_store)?;
+    let (stream_encoders, mounts) = if config.has_enabled_streams() {
+        let (stream_encoders, mounts) = start_audio(&audio, &config)?;
+        (Some(stream_encoders), mounts)
+    } else {
+        log::warn!(
+            "No enabled streams, starting in standby mode (info page and API only) until a reload enables one"
+        );
+        (None, Vec::new())
+    };
+
+    // Start server
+    let ondemand = setup_ondemand(&config, db.clone());
+    let library_api = LibraryApi::new(
+        db,
+        scanner.clone(),
+        inbox_directory(&config),
+        playout_control,
+    );
+    let listeners = ListenerRegistry::new(
+        open_access_log(&config)?,
+        config.server.anonymize_listener_ips.unwrap_or(false),
+    )
+    .with_notifier(notifier.clone())
+    .with_program_stats(program_stats.clone());
+    let server = build_server(
+        &config,
+        &audio,
+        library_api,
+        listeners,
+        ondemand,
+        selftest,
+        access.clone(),
+    );
+    let buffer_writer_handles = serve_streams(&server, mounts);
+    let server_handle = start_server(&config, server.clone());
+
+    log_server_urls(&config);
+
+    // Start nightly rescan task
+    let nightly_rescan_handle = start_nightly_rescan(scanner);
+
+    start_config_reload(
+        args.config_path.clone(),
+        stream_encoders,
+        audio,
+        server,
+        access,
+    )?;
+
+    // Wait for all tasks to complete
+    tokio::select! {
+        _ = server_handle => log::error!("Icecast server stopped"),
+        _ = async {
+            for handle in buffer_writer_handles {
+                let _ = handle.await;
+            }
+        }, if !buffer_writer_handles.is_empty() => log::error!("All buffer writers stopped"),
+        _ = nightly_rescan_handle => log::error!("Nightly rescan stopped"),
+    }
+
+    Ok(())
+}
+
+fn open_database() -> Result<LibraryDatabase, Box<dyn std::error::Error + Send + Sync>> {
+    let db = LibraryDatabase::new("./data/database.db")?;
+    db.initialize_schema()?;
+    Ok(db)
+}
+
+fn initialize_library(
+    config: &Config,
+    db: LibraryDatabase,
+    notifier: Notifier,
+    track_cache: TrackCache,
+    playout_control: PlayoutControl,
+) -> Result<LibraryScanner, Box<dyn std::error::Error + Send + Sync>> {
+    let music_dir = PathBuf::from(&config.library.music_directory);
+    let scanner = LibraryScanner::new(music_dir.clone(), db.clone())
+        .with_notifier(notifier)
+        .with_track_cache(track_cache)
+        .with_playout_control(playout_control)
+        .with_artwork_cache(ArtworkCache::new(
+            PathBuf::from(ARTWORK_CACHE_PATH),
+            config.server.ffmpeg_path.clone(),
+        ));
+    let scanner = if config.library.validate_files.unwrap_or(false) {
+        scanner.with_validator(FileValidator::new(config.server.ffmpeg_path.clone()))
+    } else {
+        scanner
+    };
+    let scanner = if config.library.audio_fingerprints.unwrap_or(false) {
+        let pending = db.rescan_tracks_without_fingerprint()?;
+        if pending > 0 {
+            log::info!("Fingerprinting the audio of {} tracks", pending);
+        }
+        scanner.with_audio_fingerprints(AudioFingerprinter::new(config.server.ffmpeg_path.clone()))
+    } else {
+        scanner
+    };
+    let scanner = if config.library.skip_unchanged_directories.unwrap_or(false) {
+        let hours = config
+            .library
+            .reconcile_hours
+            .unwrap_or(DEFAULT_RECONCILE_HOURS);
+        scanner.with_unchanged_directories_skipped(std::time::Duration::from_secs(
+            u64::from(hours) * 3600,
+        ))
+    } else {
+        scanner
+    };
+    let scanner = if config.library.tempo_analysis.unwrap_or(false) {
+        let pending = db.rescan_tracks_without_tempo()?;
+        if pending > 0 {
+            log::info!("Analyzing the tempo of {} tracks", pending);
+        }
+        scanner.with_tempo_analysis(TempoAnalyzer::new(config.server.ffmpeg_path.clone()))
+    } else {
+        scanner
+    };
+
+    let track_count = db.track_count()?;
+    if track_count == 0 {
+        log::info!("Empty library, performing initial full scan...");
+        let result = scanner.full_scan()?;
+        log::info!("Initial scan complete: {} tracks added", result.added);
+        if !result.errors.is_empty() {
+            log::warn!("Scan encountered {} errors", result.errors.len());
+        }
+    } else {
+        log_last_scan_times(&db);
+
+        log::info!("Performing incremental library scan...");
+        let result = scanner.incremental_scan()?;
+        if result.added > 0 || result.updated > 0 || result.deleted > 0 {
+            log::info!(
+                "Library changes: +{} ~{} -{} tracks",
+                result.added,
+                result.updated,
+                result.deleted
+            );
+        }
+    }
+
+    Ok(scanner)
+}
+
+fn open_access_log(
+    config: &Config,
+) -> Result<Option<AccessLog>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(path) = &config.server.access_log else {
+        return Ok(None);
+    };
+
+    let format = match &config.server.access_log_format {
+        Some(format) => AccessLogFormat::parse(format)?,
+        None => AccessLogFormat::Combined,
+    };
+
+    Ok(Some(AccessLog::open(Path::new(path), format)?))
+}
+
+/// Upload inbox inside the music directory, so uploads survive incremental scans
+fn inbox_directory(config: &Config) -> Option<PathBuf> {
+    config
+        .library
+        .inbox_directory
+        .as_ref()
+        .map(|dir| PathBuf::from(&config.library.music_directory).join(dir))
+}
+
+fn log_last_scan_times(db: &LibraryDatabase) {
+    if let Ok(Some(last_full)) = db.get_metadata("last_full_scan") {
+        if let Ok(timestamp) = last_full.parse::<i64>() {
+            let datetime = chrono::DateTime::from_timestamp(timestamp, 0)
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            log::info!("Last full scan: {}", datetime);
+        }
+    }
+
+    if let Ok(Some(last_incr)) = db.get_metadata("last_incremental_scan") {
+        if let Ok(timestamp) = last_incr.parse::<i64>() {
+            let datetime = chrono::DateTime::from_timestamp(timestamp, 0)
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            log::info!("Last incremental scan: {}", datetime);
+        }
+    }
+}
+
+fn load_schedule_store(
+    config: &Config,
+) -> Result<ScheduleStore, Box<dyn std::error::Error + Send + Sync>> {
+    let config_programs = config
+        .schedule
+        .as_ref()
+        .map(|s| s.programs.clone())
+        .unwrap_or_default();
+    let on_overlap = config
+        .schedule
+        .as_ref()
+        .and_then(|s| s.on_overlap)
+        .unwrap_or_default();
+
+    ScheduleStore::load(
+        Path::new(SCHEDULE_STORE_PATH),
+        config.station.station_name.clone(),
+        config_programs,
+        on_overlap,
+    )
+}
+
+fn export_schedule(
+    store: &ScheduleStore,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let json = serde_json::to_string_pretty(&store.export())?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, json)?;
+            println!("Schedule exported to {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+fn import_schedule(
+    store: &ScheduleStore,
+    file: &Path,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let content = std::fs::read_to_string(file)?;
+    let import: ScheduleExport = serde_json::from_str(&content)?;
+
+    let diff = match store.preview_import(&import) {
+        Ok(diff) => diff,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("  ✗ {}", error);
+            }
+            return Err(format!("Schedule import rejected: {} error(s)", errors.len()).into());
+        }
+    };
+
+    for name in &diff.added {
+        println!("  + {}", name);
+    }
+    for name in &diff.changed {
+        println!("  ~ {}", name);
+    }
+    for name in &diff.removed {
+        println!("  - {}", name);
+    }
+    println!(
+        "{} added, {} changed, {} removed, {} unchanged",
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len(),
+        diff.unchanged.len()
+    );
+
+    if dry_run {
+        println!("Dry run, no changes applied");
+    } else {
+        store.import(&import)?;
+        println!(
+            "Schedule saved to {}, send SIGHUP to a running server to apply it",
+            SCHEDULE_STORE_PATH
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the occurrences of the programs in the coming days, fails if a program would not run
+fn report_duplicates(db: &LibraryDatabase) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let groups = db.get_duplicate_groups()?;
+    if groups.is_empty() {
+        println!("No duplicate tracks found");
+        return Ok(());
+    }
+
+    for group in &groups {
+        let kind = match group.matched_by {
+            DuplicateMatch::Content => "Identical files",
+            DuplicateMatch::Audio => "Identical audio",
+        };
+        println!("{} ({}):", kind, &group.hash[..group.hash.len().min(12)]);
+        for track in &group.tracks {
+            println!("  {}", track.file_path);
+        }
+    }
+    println!(
+        "{} duplicate groups, the first file of each group plays in the library rotation",
+        groups.len()
+    );
+
+    Ok(())
+}
+
+fn preview_schedule(
+    store: &ScheduleStore,
+    days: u32,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let from = chrono::Local::now();
+    let preview = ScheduleEngine::preview(
+        &store.programs(),
+        from,
+        from + chrono::Duration::days(days.into()),
+    );
+
+    let mut day = None;
+    for occurrence in &preview.occurrences {
+        if day != Some(occurrence.start.date_naive()) {
+            day = Some(occurrence.start.date_naive());
+            println!("{}", occurrence.start.format("%a %Y-%m-%d"));
+        }
+        let overlaps: Vec<String> = occurrence
+            .overlaps
+            .iter()
+            .map(|(other, preempts)| {
+                let resolution = if *preempts { "preempts it" } else { "waits" };
+                format!("overlaps '{}', {}", other, resolution)
+            })
+            .collect();
+        println!(
+            "  {} - {}  {}{}{}",
+            occurrence.start.format("%H:%M"),
+            occurrence.end.format("%H:%M"),
+            occurrence.program,
+            match &occurrence.after {
+                Some(previous) => format!(" (after '{}')", previous),
+                None => String::new(),
+            },
+            if overlaps.is_empty() {
+                String::new()
+            } else {
+                format!("  ! {}", overlaps.join("; "))
+            }
+        );
+    }
+
+    let not_running = preview.inactive.len()
+        + preview.not_in_range.len()
+        + preview.invalid.len()
+        + preview.unreachable.len();
+    if not_running > 0 {
+        println!("Not running as configured:");
+    }
+    for name in &preview.inactive {
+        println!("  - {}: inactive", name);
+    }
+    for (name, next) in &preview.not_in_range {
+        match next {
+            Some(next) => println!(
+                "  - {}: no occurrence in the next {} days, next at {}",
+                name,
+                days,
+                next.format("%a %Y-%m-%d %H:%M")
+            ),
+            None => println!("  - {}: no occurrence in the next {} days", name, days),
+        }
+    }
+    for (name, error) in &preview.invalid {
+        eprintln!("  ✗ {}: {}", name, error);
+    }
+    for (name, reason) in &preview.unreachable {
+        eprintln!("  ✗ {}: never starts, {}", name, reason);
+    }
+
+    let overlaps: usize = preview.occurrences.iter().map(|o| o.overlaps.len()).sum();
+    let problems = preview.invalid.len() + preview.unreachable.len();
+    println!(
+        "{} occurrence(s) in the next {} days, {} overlap(s), {} program(s) with problems",
+        preview.occurrences.len(),
+        days,
+        overlaps,
+        problems
+    );
+
+    if problems > 0 {
+        return Err(format!("Schedule preview found {} problem(s)", problems).into());
+    }
+    Ok(())
+}
+
+/// Runs the schedule engine, which follows changes of the schedule store
+fn setup_schedule_engine(
+    schedule_store: &ScheduleStore,
+    db: LibraryDatabase,
+    command_tx: Sender<PlaylistCommand>,
+    playout_control: PlayoutControl,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ScheduleEngine::new(
+        schedule_store.programs(),
+        schedule_store.on_overlap(),
+        db,
+        command_tx,
+        playout_control,
+    )?
+    .start(schedule_store.subscribe());
+    Ok(())
+}
+
+fn setup_ad_breaks(
+    config: &Config,
+    command_tx: Sender<PlaylistCommand>,
+    http: &HttpClientFactory,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(ads) = &config.ads {
+        log::info!("Ad breaks enabled ({})", ads.break_cron);
+        AdBreakScheduler::new(ads, command_tx, http)?.start();
+    }
+    Ok(())
+}
+
+/// Starts the schedule engine, the ad breaks and the audio pipeline with an encoder for each
+/// enabled stream, returns the encoders and the encoded audio of each mount
+fn start_audio(
+    audio: &AudioComponents,
+    config: &Config,
+) -> Result<(StreamEncoders, Vec<MountOutput>), Box<dyn std::error::Error + Send + Sync>> {
+    // The reader fails on an empty library, check it before anything is started
+    let audio_reader = setup_audio_reader(config, audio)?;
+    let (command_tx, command_rx) = crossbeam_channel::unbounded();
+    setup_schedule_engine(
+        &audio.schedule_store,
+        audio.db.clone(),
+        command_tx.clone(),
+        audio.playout_control.clone(),
+    )?;
+    setup_ad_breaks(config, command_tx, &audio.http)?;
+    setup_audio_pipeline(config, audio, audio_reader, command_rx)
+}
+
+fn setup_audio_reader(
+    config: &Config,
+    audio: &AudioComponents,
+) -> Result<AudioReader, Box<dyn std::error::Error + Send + Sync>> {
+    let music_dir = PathBuf::from(&config.library.music_directory);
+    let mut audio_reader = AudioReader::new(
+        music_dir,
+        config.library.shuffle,
+        config.library.repeat,
+        audio.db.clone(),
+        audio.track_cache.clone(),
+        audio.playout_control.clone(),
+        audio.http.clone(),
+    )?;
+    audio_reader.publish_metadata_to(audio.current_metadata.clone());
+    audio_reader.enable_webhooks(audio.notifier.clone());
+    audio_reader.enable_program_stats(audio.program_stats.clone());
+    audio_reader.enable_play_history(audio.play_history.clone());
+    audio_reader.enable_station_metadata(audio.station_metadata.clone());
+    audio_reader.enable_program_intros(ProgramIntros::new(
+        config.announcements.as_ref(),
+        &config.station.station_name,
+        PathBuf::from(ANNOUNCEMENT_CACHE_PATH),
+        audio.schedule_store.subscribe(),
+    ));
+
+    if let Some(hours) = config.library.no_repeat_hours {
+        audio_reader.enable_shuffle_memory(ShuffleMemory::load(
+            audio.db.clone(),
+            audio.track_cache.clone(),
+            hours,
+        ));
+    }
+    Ok(audio_reader)
+}
+
+fn setup_audio_pipeline(
+    config: &Config,
+    audio: &AudioComponents,
+    mut audio_reader: AudioReader,
+    command_rx: Receiver<PlaylistCommand>,
+) -> Result<(StreamEncoders, Vec<MountOutput>), Box<dyn std::error::Error + Send + Sync>> {
+    let quarantine = TrackQuarantine::new(audio.db.clone(), config.library.max_track_failures)
+        .with_track_cache(audio.track_cache.clone());
+
+    if let Some(tracks) = config.library.artist_separation {
+        log::info!("Artists do not repeat within {} library tracks", tracks);
+        audio_reader.enable_artist_separation(tracks);
+    }
+
+    if let Some(dayparts) = config.dayparts.as_ref().filter(|d| !d.is_empty()) {
+        log::info!("Dayparting enabled with {} daypart(s)", dayparts.len());
+        audio_reader.enable_dayparting(Dayparting::new(dayparts));
+    }
+
+    if let Some(target) = config.library.loudness_target_lufs {
+        audio_reader.enable_loudness_normalization(target);
+    }
+
+    if config.library.tempo_matching.unwrap_or(false) {
+        log::info!("Tempo matching of library tracks enabled");
+        audio_reader.enable_tempo_matching();
+    }
+
+    if let Some(jingles) = &config.jingles {
+        log::info!("Jingles enabled from {}", jingles.directory);
+        audio_reader.enable_jingles(Jingles::new(jingles));
+    }
+
+    if let Some(station_id) = &config.station_id {
+        log::info!("Station ID at the top of each hour: {}", station_id.file);
+        audio_reader.enable_station_id(StationId::new(station_id, chrono::Local::now()));
+    }
+
+    if let Some(announcements) = &config.announcements {
+        log::info!(
+            "Announcements after every {} library tracks",
+            announcements.every_n_tracks
+        );
+        audio_reader.enable_announcements(Announcements::new(
+            announcements,
+            &config.station.station_name,
+            PathBuf::from(ANNOUNCEMENT_CACHE_PATH),
+        ));
+    }
+
+    if let Some(schedule) = config
+        .schedule
+        .as_ref()
+        .filter(|s| s.align_to_programs.unwrap_or(false))
+    {
+        let tolerance = schedule
+            .align_tolerance_seconds
+            .unwrap_or(DEFAULT_ALIGN_TOLERANCE_SECONDS);
+        log::info!(
+            "Clock alignment to programs enabled (tolerance: {}s)",
+            tolerance
+        );
+        audio_reader.enable_clock_alignment(tolerance);
+    }
+
+    let track_rx = audio_reader.start_playlist_service(command_rx);
+    let mut stream_encoders = StreamEncoders::new(
+        config.server.ffmpeg_path.clone(),
+        track_rx,
+        quarantine,
+        audio.playout_control.clone(),
+        audio.stages.clone(),
+    );
+
+    // Create an encoder for each enabled stream
+    let mut mounts = Vec::new();
+
+    for (name, stream_config) in &config.stream {
+        if !stream_config.enabled {
+            log::info!("Stream '{}' is disabled, skipping", name);
+            continue;
+        }
+
+        log::info!(
+            "Setting up stream '{}': {} @ {:?}kbps, {}Hz",
+            name,
+            stream_config.format,
+            stream_config.ladder(),
+            stream_config.sample_rate
+        );
+
+        mounts.extend(stream_encoders.start(name, stream_config)?);
+    }
+
+    if mounts.is_empty() {
+        return Err("No enabled streams found in configuration".into());
+    }
+
+    log::info!("Initialized {} stream(s)", mounts.len());
+
+    Ok((stream_encoders, mounts))
+}
+
+/// Buffers the encoded audio of the mounts and serves them, returns the buffer writers
+fn serve_streams(server: &IcecastServer, mounts: Vec<MountOutput>) -> Vec<JoinHandle<()>> {
+    mounts
+        .into_iter()
+        .map(|(name, receiver, live)| {
+            let stream_buffer = StreamBuffer::new(1000, 50 * 1024 * 1024);
+            stream_buffer.start();
+            let handle = start_buffer_writer(&stream_buffer, receiver);
+            server.add_stream(name, stream_buffer, live);
+            handle
+        })
+        .collect()
+}
+
+/// Rebuilds the encoders of streams with changed settings, starts added streams and reloads
+/// the schedule when SIGHUP is received. A station in standby starts its audio pipeline once
+/// a stream is enabled.
+fn start_config_reload(
+    config_path: PathBuf,
+    mut stream_encoders: Option<StreamEncoders>,
+    audio: AudioComponents,
+    server: IcecastServer,
+    access: Option<ProgramAccess>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            log::info!(
+                "SIGHUP received, reloading stream settings and schedule from {:?}",
+                config_path
+            );
+            let config = match Config::from_file(&config_path) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Failed to reload config, keeping current settings: {}", e);
+                    continue;
+                }
+            };
+
+            let config_programs = config
+                .schedule
+                .clone()
+                .map(|s| s.programs)
+                .unwrap_or_default();
+            if let Err(e) = audio.schedule_store.reload(config_programs) {
+                log::error!("Failed to reload schedule, keeping current programs: {}", e);
+            }
+
+            // Listeners without an access token must not hear restricted programs on new mounts
+            if let Some(Err(e)) = access
+                .as_ref()
+                .map(|access| prepare_access_fallbacks(access, &config))
+            {
+                log::error!(
+                    "Failed to prepare access fallbacks, no streams started: {}",
+                    e
+                );
+                continue;
+            }
+
+            let mounts = match stream_encoders.as_mut() {
+                Some(stream_encoders) => stream_encoders.reload(&config),
+                None if config.has_enabled_streams() => {
+                    log::info!("Stream enabled, leaving standby mode");
+                    match start_audio(&audio, &config) {
+                        Ok((started, mounts)) => {
+                            stream_encoders = Some(started);
+                            mounts
+                        }
+                        Err(e) => {
+                            log::error!("Failed to start streams, staying in standby: {}", e);
+                            Vec::new()
+                        }
+                    }
+                }
+                None => Vec::new(),
+            };
+            serve_streams(&server, mounts);
+        }
+    });
+
+    Ok(())
+}
+
+fn start_buffer_writer(
+    stream_buffer: &StreamBuffer,
+    audio_rx: Receiver<AudioChunk>,
+) -> JoinHandle<()> {
+    let buffer_input_tx = stream_buffer.get_input_sender();
+
+    tokio::spawn(async move {
+        loop {
+            match tokio::task::spawn_blocking({
+                let audio_rx = audio_rx.clone();
+                move || audio_rx.recv()
+            })
+            .await
+            {
+                Ok(Ok(audio_data)) => {
+                    if let Err(e) = buffer_input_tx.send(audio_data) {
+                        log::error!("Failed to send audio data to buffer: {}", e);
+                        break;
+                    }
+                }
+                Ok(Err(e)) => {
+                    log::error!("Failed to receive audio data: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    log::error!("Task join error: {}", e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Access control of restricted programs with the fallback loop of each enabled stream
+fn setup_access(
+    config: &Config,
+    schedule_store: &ScheduleStore,
+) -> Result<Option<ProgramAccess>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(access_config) = &config.access else {
+        if schedule_store
+            .programs()
+            .iter()
+            .any(|p| p.active && p.is_restricted())
+        {
+            log::warn!("Restricted programs require an [access] section, they are open to all");
+        }
+        return Ok(None);
+    };
+
+    // Enabled without restricted programs as well, a reloaded schedule may add some
+    let access = ProgramAccess::new(access_config, schedule_store.subscribe())?;
+    prepare_access_fallbacks(&access, config)?;
+
+    log::info!(
+        "Access control enabled for {} restricted program(s), listeners without a token hear {}",
+        access.restricted_programs(),
+        access_config.fallback
+    );
+    access.start();
+    Ok(Some(access))
+}
+
+/// Renders the access fallback of every enabled mount that has none yet
+fn prepare_access_fallbacks(
+    access: &ProgramAccess,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for (name, stream_config) in config.stream.iter().filter(|(_, s)| s.enabled) {
+        for (mount, mount_config) in stream_config.mounts(name) {
+            if access.has_fallback(&mount) {
+                continue;
+            }
+            access.prepare_fallback(
+                &mount,
+                &mount_config,
+                config.server.ffmpeg_path.clone(),
+                Path::new(ACCESS_CACHE_PATH),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// On-demand mode serving the files of the configured directory, if any
+fn setup_ondemand(config: &Config, db: LibraryDatabase) -> Option<OnDemandLibrary> {
+    let ondemand = config.ondemand.as_ref()?;
+    if !Path::new(&ondemand.directory).is_dir() {
+        log::warn!(
+            "On-demand directory {} does not exist, on-demand mode disabled",
+            ondemand.directory
+        );
+        return None;
+    }
+
+    log::info!("Serving on-demand files from {}", ondemand.directory);
+    Some(OnDemandLibrary::new(
+        ondemand,
+        config.server.ffmpeg_path.clone(),
+        db,
+        PathBuf::from(ONDEMAND_CACHE_PATH),
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_server(
+    config: &Config,
+    audio: &AudioComponents,
+    library_api: LibraryApi,
+    listeners: ListenerRegistry,
+    ondemand: Option<OnDemandLibrary>,
+    selftest: SelfTest,
+    access: Option<ProgramAccess>,
+) -> IcecastServer {
+    let mut server = IcecastServer::new(
+        audio.station_metadata.clone(),
+        audio.current_metadata.clone(),
+        library_api,
+        audio.schedule_store.clone(),
+        listeners,
+        audio.notifier.clone(),
+        PageTemplates::new(config.server.templates_dir.as_ref().map(PathBuf::from)),
+    )
+    .with_selftest(selftest)
+    .with_program_stats(audio.program_stats.clone())
+    .with_play_history(audio.play_history.clone());
+    if let Some(ondemand) = ondemand {
+        server = server.with_ondemand(ondemand);
+    }
+    if let Some(access) = access {
+        server = server.with_access(access);
+    }
+    if let Some(admin_token) = &config.server.admin_token {
+        server = server.with_admin_token(admin_token.clone());
+    }
+    if config.server.http2.unwrap_or(false) {
+        server = server.with_http2(
+            config
+                .server
+                .http2_keep_alive_seconds
+                .map(std::time::Duration::from_secs),
+        );
+    }
+    server
+}
+
+fn start_server(config: &Config, server: IcecastServer) -> JoinHandle<()> {
+    let bind_address = config.server.bind_address.clone();
+    let port = config.server.port;
+    tokio::spawn(async move {
+        server.start_server(&bind_address, port).await;
+    })
+}
+
+fn start_nightly_rescan(scanner: LibraryScanner) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let now = chrono::Local::now();
+            let next_scan = now
+                .date_naive()
+                .succ_opt()
+                .unwrap()
+                .and_hms_opt(3, 0, 0)
+                .unwrap()
+                .and_local_timezone(chrono::Local)
+                .unwrap();
+            let duration = (next_scan - now).to_std().unwrap();
+
+            log::info!(
+                "Next library scan scheduled at {}",
+                next_scan.format("%Y-%m-%d %H:%M:%S")
+            );
+
+            tokio::time::sleep(duration).await;
+
+            log::info!("Performing nightly library scan...");
+            match scanner.incremental_scan() {
+                Ok(result) => {
+                    if result.added > 0 || result.updated > 0 || result.deleted > 0 {
+                        log::info!(
+                            "Nightly scan complete: +{} added, ~{} updated, -{} deleted",
+                            result.added,
+                            result.updated,
+                            result.deleted
+                        );
+                    } else {
+                        log::info!("Nightly scan complete: no changes detected");
+                    }
+                }
+                Err(e) => log::error!("Nightly scan failed: {}", e),
+            }
+        }
+    })
+}
+
+fn log_startup_info(config: &Config) {
+    log::info!(
+        "Starting Funkstrom server on {}:{}",
+        config.server.bind_address,
+        config.server.port
+    );
+    log::info!("Music directory: {}", config.library.music_directory);
+    log::info!("Station: {}", config.station.station_name);
+}
+
+fn log_server_urls(config: &Config) {
+    log::info!("Funkstrom server started successfully!");
+
+    // Log all enabled stream URLs
+    for (name, stream_config) in config.stream.iter().filter(|(_, s)| s.enabled) {
+        for (mount, mount_config) in stream_config.mounts(name) {
+            log::info!(
+                "  Stream '{}': http://{}:{}/{} ({}kbps)",
+                mount,
+                config.server.bind_address,
+                config.server.port,
+                mount,
+                mount_config.bitrate
+            );
+        }
+    }
+
+    log::info!(
+        "Status URL: http://{}:{}/status",
+        config.server.bind_address,
+        config.server.port
+    );
+    log::info!(
+        "Info URL: http://{}:{}/",
+        config.server.bind_address,
+        config.server.port
+    );
+}
@@ -0,0 +1,137 @@
+use crate::config::StationIdConfig;
+use chrono::{DateTime, Duration, Local, Timelike};
+use log::{info, warn};
+use std::path::PathBuf;
+
+/// Default deviation from the full hour at which a track boundary is used for the ID
+pub const DEFAULT_STATION_ID_TOLERANCE_SECONDS: u64 = 120;
+
+/// Plays the station identification at the track boundary closest to the top of each hour.
+///
+/// The ID is due at the first track boundary from `tolerance` before the full hour on. When
+/// no boundary falls within the tolerance, e.g. during a long liveset, it plays late at the
+/// next boundary within that hour.
+pub struct StationId {
+    file: PathBuf,
+    tolerance: Duration,
+    /// Estimated end of all audio already handed to playout
+    queued_until: Option<DateTime<Local>>,
+    /// Full hour the ID was last played for
+    last_hour: DateTime<Local>,
+}
+
+impl StationId {
+    pub fn new(config: &StationIdConfig, now: DateTime<Local>) -> Self {
+        let tolerance = Duration::seconds(
+            config
+                .tolerance_seconds
+                .unwrap_or(DEFAULT_STATION_ID_TOLERANCE_SECONDS) as i64,
+        );
+
+        Self {
+            file: PathBuf::from(&config.file),
+            tolerance,
+            queued_until: None,
+            // Not played on startup, the first ID airs at the next full hour
+            last_hour: top_of_hour(now + tolerance),
+        }
+    }
+
+    /// Records a track handed to playout, tracks of unknown duration reset the estimate
+    pub fn track_queued(&mut self, duration_seconds: Option<u64>, now: DateTime<Local>) {
+        self.queued_until = duration_seconds.map(|duration| {
+            let start = self
+                .queued_until
+                .filter(|until| *until > now)
+                .unwrap_or(now);
+            start + Duration::seconds(duration as i64)
+        });
+    }
+
+    /// Station ID to play at the next track boundary, `None` if it is not due
+    pub fn next_due(&mut self, now: DateTime<Local>) -> Option<PathBuf> {
+        let boundary = self
+            .queued_until
+            .filter(|until| *until > now)
+            .unwrap_or(now);
+        let hour = top_of_hour(boundary + self.tolerance);
+        if hour <= self.last_hour {
+            return None;
+        }
+        self.last_hour = hour;
+
+        let offset = (boundary - hour).num_seconds();
+        if offset > self.tolerance.num_seconds() {
+            warn!(
+                "Station ID for {} is {}s late, no track boundary within the tolerance",
+                hour.format("%H:%M"),
+                offset
+            );
+        } else {
+            info!("Station ID for {} at {:+}s", hour.format("%H:%M"), offset);
+        }
+        Some(self.file.clone())
+    }
+}
+
+fn top_of_hour(time: DateTime<Local>) -> DateTime<Local> {
+    time.with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 1, 15, hour, minute, second)
+            .unwrap()
+    }
+
+    fn station_id(now: DateTime<Local>) -> StationId {
+        StationId::new(
+            &StationIdConfig {
+                file: "/sounds/station-id.mp3".to_string(),
+                tolerance_seconds: Some(60),
+            },
+            now,
+        )
+    }
+
+    #[test]
+    fn given_boundary_within_tolerance_when_checking_then_plays_once_per_hour() {
+        let mut station_id = station_id(at(12, 30, 0));
+
+        assert!(station_id.next_due(at(12, 58, 0)).is_none());
+        assert_eq!(
+            station_id.next_due(at(12, 59, 10)),
+            Some(PathBuf::from("/sounds/station-id.mp3"))
+        );
+        assert!(station_id.next_due(at(13, 0, 30)).is_none());
+        assert!(station_id.next_due(at(13, 40, 0)).is_none());
+        assert!(station_id.next_due(at(14, 0, 20)).is_some());
+    }
+
+    #[test]
+    fn given_queued_audio_when_checking_then_uses_estimated_boundary() {
+        let mut station_id = station_id(at(12, 30, 0));
+
+        // A 4 minute track queued at 12:55 ends at 12:59, within the tolerance
+        station_id.track_queued(Some(240), at(12, 55, 0));
+        assert!(station_id.next_due(at(12, 55, 0)).is_some());
+    }
+
+    #[test]
+    fn given_no_boundary_within_tolerance_when_checking_then_plays_late_within_hour() {
+        let mut station_id = station_id(at(12, 59, 30));
+
+        // Started within the tolerance of 13:00, the ID is first due for 14:00
+        assert!(station_id.next_due(at(13, 5, 0)).is_none());
+        assert!(station_id.next_due(at(14, 25, 0)).is_some());
+        assert!(station_id.next_due(at(14, 50, 0)).is_none());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::config::{ProgramMetadata, ScheduleProgram, StationConfig};
+use log::info;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Station name, description and genre as announced to listeners
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationInfo {
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+}
+
+/// Station metadata sent in the ICY headers, the status and the info page. A running program
+/// may override it with its `metadata`, the station defaults apply again when it ends.
+#[derive(Clone)]
+pub struct StationMetadata {
+    defaults: StationInfo,
+    current: Arc<Mutex<StationInfo>>,
+    programs: watch::Receiver<Vec<ScheduleProgram>>,
+}
+
+impl StationMetadata {
+    /// `programs` follows the schedule, so reloaded overrides apply at the next program start
+    pub fn new(station: &StationConfig, programs: watch::Receiver<Vec<ScheduleProgram>>) -> Self {
+        let defaults = StationInfo {
+            name: station.station_name.clone(),
+            description: station.description.clone(),
+            genre: station.genre.clone(),
+        };
+
+        Self {
+            current: Arc::new(Mutex::new(defaults.clone())),
+            defaults,
+            programs,
+        }
+    }
+
+    pub fn current(&self) -> StationInfo {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Applies the overrides of a program starting to play
+    pub fn program_started(&self, name: &str) {
+        let metadata = self
+            .programs
+            .borrow()
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.metadata.clone());
+
+        let info = match metadata {
+            Some(metadata) => {
+                info!("Program '{}' overrides the station metadata", name);
+                self.defaults.with_overrides(&metadata)
+            }
+            None => self.defaults.clone(),
+        };
+        *self.current.lock().unwrap() = info;
+    }
+
+    /// Restores the station defaults
+    pub fn program_ended(&self) {
+        *self.current.lock().unwrap() = self.defaults.clone();
+    }
+}
+
+impl StationInfo {
+    fn with_overrides(&self, metadata: &ProgramMetadata) -> Self {
+        Self {
+            name: metadata
+                .station_name
+                .clone()
+                .unwrap_or_else(|| self.name.clone()),
+            description: metadata
+                .description
+                .clone()
+                .unwrap_or_else(|| self.description.clone()),
+            genre: metadata.genre.clone().unwrap_or_else(|| self.genre.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station() -> StationConfig {
+        StationConfig {
+            station_name: "Deep Sea Radio".to_string(),
+            description: "Music from the deep".to_string(),
+            genre: "Various".to_string(),
+            url: "https://radio.example.com".to_string(),
+            contact: None,
+        }
+    }
+
+    fn program(name: &str, metadata: Option<ProgramMetadata>) -> ScheduleProgram {
+        let mut program: ScheduleProgram = toml::from_str(&format!(
+            "name = \"{}\"\nactive = true\ncron = \"0 0 20 * * 2\"\nduration = \"2h\"\nplaylist = \"techno.m3u\"",
+            name
+        ))
+        .unwrap();
+        program.metadata = metadata;
+        program
+    }
+
+    #[test]
+    fn given_program_with_overrides_when_started_then_applies_them_until_it_ends() {
+        let (_tx, programs) = watch::channel(vec![
+            program(
+                "Techno Tuesdays",
+                Some(ProgramMetadata {
+                    station_name: Some("Techno Tuesdays w/ DJ X".to_string()),
+                    description: None,
+                    genre: Some("Techno".to_string()),
+                }),
+            ),
+            program("Jazz Hour", None),
+        ]);
+        let metadata = StationMetadata::new(&station(), programs);
+
+        metadata.program_started("Techno Tuesdays");
+        let during = metadata.current();
+        assert_eq!(during.name, "Techno Tuesdays w/ DJ X");
+        assert_eq!(during.description, "Music from the deep");
+        assert_eq!(during.genre, "Techno");
+
+        metadata.program_started("Jazz Hour");
+        assert_eq!(metadata.current().name, "Deep Sea Radio");
+
+        metadata.program_started("Techno Tuesdays");
+        metadata.program_ended();
+        assert_eq!(metadata.current().genre, "Various");
+    }
+}
@@ -0,0 +1,511 @@
+use crate::audio_processor::{self, AudioChunk, EncoderHandle, EncoderOutput, FFmpegProcessor};
+use crate::audio_reader::QueuedTrack;
+use crate::audio_stage::AudioStages;
+use crate::config::{Config, StreamConfig};
+use crate::playout_control::PlayoutControl;
+use crate::simulcast::{StationClock, TrackFanout};
+use crate::track_quarantine::TrackQuarantine;
+use crate::transitions::Transition;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct RunningEncoder {
+    settings: StreamConfig,
+    track_rx: Receiver<QueuedTrack>,
+    /// One mount per bitrate of the stream
+    mounts: Vec<RunningMount>,
+    handle: EncoderHandle,
+}
+
+struct RunningMount {
+    audio_tx: Sender<AudioChunk>,
+    live: Arc<LiveSettings>,
+}
+
+/// Encoded audio of a mount, with the settings announced to its listeners
+pub type MountOutput = (String, Receiver<AudioChunk>, Arc<LiveSettings>);
+
+/// Settings of a running stream announced to listeners, updated when its encoder is rebuilt
+pub struct LiveSettings {
+    bitrate: AtomicU32,
+    format: Mutex<String>,
+}
+
+impl LiveSettings {
+    fn new(settings: &StreamConfig) -> Self {
+        Self {
+            bitrate: AtomicU32::new(settings.bitrate),
+            format: Mutex::new(settings.format.to_lowercase()),
+        }
+    }
+
+    fn update(&self, settings: &StreamConfig) {
+        self.bitrate.store(settings.bitrate, Ordering::Relaxed);
+        *self.format.lock().unwrap() = settings.format.to_lowercase();
+    }
+
+    /// Bitrate in kbps
+    pub fn bitrate(&self) -> u32 {
+        self.bitrate.load(Ordering::Relaxed)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        audio_processor::content_type(&self.format.lock().unwrap())
+    }
+}
+
+/// Runs the FFmpeg encoder of each stream.
+///
+/// All streams play the same tracks paced on a shared station clock, so simulcast mounts stay
+/// in step and listeners switching between them hear the same position.
+///
+/// A stream with a bitrate ladder decodes and mixes its tracks once and runs one encoder per
+/// bitrate, each feeding its own mount.
+///
+/// On reload only the encoders of streams with changed settings are rebuilt. The new encoder
+/// writes into the same output channel, so the stream buffer and its listeners stay connected
+/// and only miss the rest of the current track. Added or enabled streams are started and join
+/// the running tracks.
+pub struct StreamEncoders {
+    ffmpeg_path: Option<String>,
+    tracks: TrackFanout,
+    clock: StationClock,
+    quarantine: TrackQuarantine,
+    playout_control: PlayoutControl,
+    /// Custom audio stages the streams can use
+    stages: AudioStages,
+    encoders: HashMap<String, RunningEncoder>,
+}
+
+impl StreamEncoders {
+    pub fn new(
+        ffmpeg_path: Option<String>,
+        track_rx: Receiver<QueuedTrack>,
+        quarantine: TrackQuarantine,
+        playout_control: PlayoutControl,
+        stages: AudioStages,
+    ) -> Self {
+        Self {
+            ffmpeg_path,
+            tracks: TrackFanout::new(track_rx),
+            clock: StationClock::new(),
+            quarantine,
+            playout_control,
+            stages,
+            encoders: HashMap::new(),
+        }
+    }
+
+    /// Starts the encoders of a stream, returns the encoded audio and the live settings of
+    /// each of its mounts
+    pub fn start(
+        &mut self,
+        name: &str,
+        settings: &StreamConfig,
+    ) -> Result<Vec<MountOutput>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut mounts = Vec::new();
+        let mut outputs = Vec::new();
+        for (mount, mount_settings) in settings.mounts(name) {
+            let (audio_tx, audio_rx) = unbounded::<AudioChunk>();
+            let live = Arc::new(LiveSettings::new(&mount_settings));
+            mounts.push(RunningMount {
+                audio_tx,
+                live: Arc::clone(&live),
+            });
+            outputs.push((mount, audio_rx, live));
+        }
+
+        let track_rx = self.tracks.subscribe();
+        let handle = self
+            .start_encoder(settings, track_rx.clone(), &mounts)
+            .map_err(|e| format!("Stream '{}': {}", name, e))?;
+
+        self.encoders.insert(
+            name.to_string(),
+            RunningEncoder {
+                settings: settings.clone(),
+                track_rx,
+                mounts,
+                handle,
+            },
+        );
+
+        Ok(outputs)
+    }
+
+    /// Rebuilds the encoders of streams whose settings differ in the given configuration and
+    /// starts the added or enabled streams, returns the mounts of the started streams.
+    ///
+    /// Removed or disabled streams, changed bitrate ladders and changes of the format, sample
+    /// rate or channels are only reported, they take effect after a restart.
+    pub fn reload(&mut self, config: &Config) -> Vec<MountOutput> {
+        let running: HashMap<String, StreamConfig> = self
+            .encoders
+            .iter()
+            .map(|(name, encoder)| (name.clone(), encoder.settings.clone()))
+            .collect();
+        let changes = changed_streams(&running, &config.stream);
+
+        for name in &changes.restart_required {
+            warn!(
+                "Stream '{}' was removed, disabled or changed its bitrates or codec, restart to apply",
+                name
+            );
+        }
+
+        if changes.rebuild.is_empty() && changes.added.is_empty() {
+            info!("Config reloaded, no stream encoder settings changed");
+            return Vec::new();
+        }
+
+        self.ffmpeg_path = config.server.ffmpeg_path.clone();
+        for name in changes.rebuild {
+            let settings = &config.stream[&name];
+            if let Err(e) = self.rebuild(&name, settings) {
+                error!("Failed to rebuild encoder of stream '{}': {}", name, e);
+            }
+        }
+
+        let mut outputs = Vec::new();
+        for name in changes.added {
+            let settings = &config.stream[&name];
+            match self.start(&name, settings) {
+                Ok(mounts) => {
+                    info!(
+                        "Started stream '{}': {} @ {:?}kbps, {}Hz",
+                        name,
+                        settings.format,
+                        settings.ladder(),
+                        settings.sample_rate
+                    );
+                    outputs.extend(mounts);
+                }
+                Err(e) => error!("Failed to start stream '{}': {}", name, e),
+            }
+        }
+        outputs
+    }
+
+    fn rebuild(
+        &mut self,
+        name: &str,
+        settings: &StreamConfig,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(encoder) = self.encoders.get(name) else {
+            return Err(format!("Stream '{}' is not running", name).into());
+        };
+
+        // Start the replacement first, so a failing one keeps the old encoder on air
+        let handle = self.start_encoder(settings, encoder.track_rx.clone(), &encoder.mounts)?;
+
+        let encoder = self.encoders.get_mut(name).expect("encoder exists");
+        encoder.handle.stop();
+        encoder.handle = handle;
+        for (mount, (_, mount_settings)) in encoder.mounts.iter().zip(settings.mounts(name)) {
+            mount.live.update(&mount_settings);
+        }
+        encoder.settings = settings.clone();
+
+        info!(
+            "Rebuilt encoder of stream '{}': {} @ {:?}kbps, {}Hz",
+            name,
+            settings.format,
+            settings.ladder(),
+            settings.sample_rate
+        );
+        Ok(())
+    }
+
+    fn start_encoder(
+        &self,
+        settings: &StreamConfig,
+        track_rx: Receiver<QueuedTrack>,
+        mounts: &[RunningMount],
+    ) -> Result<EncoderHandle, Box<dyn std::error::Error + Send + Sync>> {
+        let mut processor = FFmpegProcessor::new(
+            self.ffmpeg_path.clone(),
+            settings.sample_rate,
+            settings.bitrate,
+            settings.channels,
+            settings.format.clone(),
+        )
+        .with_transition(
+            settings
+                .transition
+                .as_ref()
+                .map(Transition::from_config)
+                .unwrap_or_default(),
+        )
+        .with_filters(settings.filters.clone())
+        .with_processing(settings.processing)
+        .with_downmix(settings.downmix.clone())
+        .with_resampler(settings.resampler, settings.resampler_precision)
+        .with_gain(settings.gain_db)
+        .with_stages(
+            self.stages
+                .select(settings.stages.as_deref().unwrap_or_default())?,
+        )
+        .with_encoder(settings.encoder.clone(), settings.threads)
+        .with_encoding_mode(settings.mode, settings.quality)
+        .with_aac_profile(settings.aac_profile);
+        processor.check_ffmpeg_available()?;
+
+        let outputs = mounts
+            .iter()
+            .zip(settings.ladder())
+            .map(|(mount, bitrate)| EncoderOutput {
+                bitrate,
+                audio_tx: mount.audio_tx.clone(),
+            })
+            .collect();
+
+        Ok(processor.start_streaming_service(
+            outputs,
+            track_rx,
+            self.clock.clone(),
+            self.quarantine.clone(),
+            self.playout_control.clone(),
+        ))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct StreamChanges {
+    /// Running streams with changed encoder settings
+    rebuild: Vec<String>,
+    /// Enabled streams that are not running yet
+    added: Vec<String>,
+    /// Streams that were removed or disabled, or whose bitrate ladder or codec changed
+    restart_required: Vec<String>,
+}
+
+fn changed_streams(
+    running: &HashMap<String, StreamConfig>,
+    configured: &HashMap<String, StreamConfig>,
+) -> StreamChanges {
+    let mut changes = StreamChanges::default();
+
+    for (name, settings) in running {
+        match configured.get(name) {
+            Some(new) if new.enabled && mount_names(name, new) != mount_names(name, settings) => {
+                changes.restart_required.push(name.clone())
+            }
+            Some(new) if new.enabled && codec_changed(settings, new) => {
+                changes.restart_required.push(name.clone())
+            }
+            Some(new) if new.enabled => {
+                if new != settings {
+                    changes.rebuild.push(name.clone());
+                }
+            }
+            _ => changes.restart_required.push(name.clone()),
+        }
+    }
+
+    // A running stream keeps its mounts until a restart, even if it was removed
+    let running_mounts: Vec<String> = running
+        .iter()
+        .flat_map(|(name, settings)| mount_names(name, settings))
+        .collect();
+    for (name, settings) in configured {
+        if settings.enabled && !running.contains_key(name) {
+            if mount_names(name, settings)
+                .iter()
+                .any(|mount| running_mounts.contains(mount))
+            {
+                changes.restart_required.push(name.clone());
+            } else {
+                changes.added.push(name.clone());
+            }
+        }
+    }
+
+    changes.rebuild.sort();
+    changes.added.sort();
+    changes.restart_required.sort();
+    changes
+}
+
+/// Whether the encoded audio changes its codec or PCM layout. Connected listeners, the
+/// buffered audio, the stream header and the access fallback are all in the running codec,
+/// so such a stream cannot be rebuilt in place.
+fn codec_changed(running: &StreamConfig, configured: &StreamConfig) -> bool {
+    !running.format.eq_ignore_ascii_case(&configured.format)
+        || running.sample_rate != configured.sample_rate
+        || running.channels != configured.channels
+}
+
+fn mount_names(name: &str, settings: &StreamConfig) -> Vec<String> {
+    settings
+        .mounts(name)
+        .into_iter()
+        .map(|(mount, _)| mount)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(bitrate: u32, enabled: bool) -> StreamConfig {
+        StreamConfig {
+            bitrate,
+            format: "mp3".to_string(),
+            sample_rate: 44100,
+            channels: 2,
+            enabled,
+            transition: None,
+            filters: None,
+            mode: None,
+            quality: None,
+            aac_profile: None,
+            processing: None,
+            downmix: None,
+            resampler: None,
+            resampler_precision: None,
+            gain_db: None,
+            stages: None,
+            encoder: None,
+            threads: None,
+            bitrates: None,
+        }
+    }
+
+    #[test]
+    fn given_changed_bitrate_when_comparing_then_only_that_stream_is_rebuilt() {
+        let running = HashMap::from([
+            ("high".to_string(), stream(320, true)),
+            ("low".to_string(), stream(64, true)),
+        ]);
+        let configured = HashMap::from([
+            ("high".to_string(), stream(256, true)),
+            ("low".to_string(), stream(64, true)),
+        ]);
+
+        let changes = changed_streams(&running, &configured);
+
+        assert_eq!(changes.rebuild, vec!["high".to_string()]);
+        assert!(changes.restart_required.is_empty());
+    }
+
+    #[test]
+    fn given_changed_bitrate_ladder_when_comparing_then_restart_is_required() {
+        let ladder = |bitrates: Vec<u32>| StreamConfig {
+            bitrate: 0,
+            bitrates: Some(bitrates),
+            ..stream(0, true)
+        };
+        let running = HashMap::from([
+            ("main".to_string(), ladder(vec![64, 128])),
+            ("mobile".to_string(), ladder(vec![32, 64])),
+        ]);
+        let mut mobile = ladder(vec![32, 64]);
+        mobile.gain_db = Some(-3.0);
+        let configured = HashMap::from([
+            ("main".to_string(), ladder(vec![64, 192])),
+            ("mobile".to_string(), mobile),
+        ]);
+
+        let changes = changed_streams(&running, &configured);
+
+        assert_eq!(changes.rebuild, vec!["mobile".to_string()]);
+        assert_eq!(changes.restart_required, vec!["main".to_string()]);
+    }
+
+    #[test]
+    fn given_changed_codec_when_comparing_then_restart_is_required() {
+        let running = HashMap::from([
+            ("high".to_string(), stream(320, true)),
+            ("low".to_string(), stream(64, true)),
+            ("mono".to_string(), stream(64, true)),
+        ]);
+        let configured = HashMap::from([
+            (
+                "high".to_string(),
+                StreamConfig {
+                    format: "ogg".to_string(),
+                    ..stream(320, true)
+                },
+            ),
+            (
+                "low".to_string(),
+                StreamConfig {
+                    sample_rate: 48000,
+                    ..stream(64, true)
+                },
+            ),
+            (
+                "mono".to_string(),
+                StreamConfig {
+                    channels: 1,
+                    ..stream(64, true)
+                },
+            ),
+        ]);
+
+        let changes = changed_streams(&running, &configured);
+
+        assert!(changes.rebuild.is_empty());
+        assert_eq!(
+            changes.restart_required,
+            vec!["high".to_string(), "low".to_string(), "mono".to_string()]
+        );
+    }
+
+    #[test]
+    fn given_removed_or_disabled_streams_when_comparing_then_restart_is_required() {
+        let running = HashMap::from([
+            ("high".to_string(), stream(320, true)),
+            ("low".to_string(), stream(64, true)),
+        ]);
+        let configured = HashMap::from([
+            ("high".to_string(), stream(320, false)),
+            ("mobile".to_string(), stream(96, true)),
+            ("spare".to_string(), stream(128, false)),
+        ]);
+
+        let changes = changed_streams(&running, &configured);
+
+        assert!(changes.rebuild.is_empty());
+        assert_eq!(changes.added, vec!["mobile".to_string()]);
+        assert_eq!(
+            changes.restart_required,
+            vec!["high".to_string(), "low".to_string()]
+        );
+    }
+
+    #[test]
+    fn given_added_stream_taking_a_running_mount_when_comparing_then_restart_is_required() {
+        let ladder = StreamConfig {
+            bitrate: 0,
+            bitrates: Some(vec![64, 128]),
+            ..stream(0, true)
+        };
+        let running = HashMap::from([("main_64".to_string(), stream(64, true))]);
+        let configured = HashMap::from([("main".to_string(), ladder)]);
+
+        let changes = changed_streams(&running, &configured);
+
+        assert!(changes.added.is_empty());
+        assert_eq!(
+            changes.restart_required,
+            vec!["main".to_string(), "main_64".to_string()]
+        );
+    }
+
+    #[test]
+    fn given_standby_without_running_streams_when_comparing_then_enabled_streams_are_added() {
+        let configured = HashMap::from([
+            ("high".to_string(), stream(320, true)),
+            ("spare".to_string(), stream(128, false)),
+        ]);
+
+        let changes = changed_streams(&HashMap::new(), &configured);
+
+        assert_eq!(changes.added, vec!["high".to_string()]);
+        assert!(changes.restart_required.is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use warp::http::header::ACCEPT;
+use warp::http::{HeaderMap, HeaderValue, StatusCode};
+use warp::reply::Response;
+use warp::Reply;
+
+/// Header explaining a rejection, shown by ICY players instead of a bare status
+const ICY_NOTICE: &str = "icy-notice";
+
+/// Why a mount turned a client away
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// No stream of this name is configured, e.g. a wrong URL
+    UnknownMount,
+    /// The stream is configured, but its encoder stopped delivering audio
+    OffAir,
+    /// The server runs in standby mode without any stream
+    Standby,
+    /// The stream was requested over HTTP/2, streams are served over HTTP/1.1 only
+    Http1Required,
+}
+
+/// Error body of a rejected stream request
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StreamRejection {
+    pub reason: RejectReason,
+    #[schema(example = "Stream 'high' is off the air, try again later")]
+    pub error: String,
+}
+
+impl StreamRejection {
+    pub fn new(reason: RejectReason, mount: &str) -> Self {
+        let error = match reason {
+            RejectReason::UnknownMount => format!("No stream named '{}' on this station", mount),
+            RejectReason::OffAir => format!("Stream '{}' is off the air, try again later", mount),
+            RejectReason::Standby => "Station is off the air, no streams are running".to_string(),
+            RejectReason::Http1Required => {
+                format!("Stream '{}' is only served over HTTP/1.1", mount)
+            }
+        };
+        Self { reason, error }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self.reason {
+            RejectReason::UnknownMount => StatusCode::NOT_FOUND,
+            RejectReason::OffAir | RejectReason::Standby => StatusCode::SERVICE_UNAVAILABLE,
+            RejectReason::Http1Required => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+        }
+    }
+
+    /// HTML page for browsers, JSON for players and other clients, both with `icy-notice`
+    pub fn reply(&self, headers: &HeaderMap) -> Response {
+        let wants_html = headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+
+        let mut response = if wants_html {
+            warp::reply::html(self.html()).into_response()
+        } else {
+            warp::reply::json(self).into_response()
+        };
+        *response.status_mut() = self.status();
+        if let Ok(notice) = HeaderValue::from_str(&self.error) {
+            response.headers_mut().insert(ICY_NOTICE, notice);
+        }
+        response
+    }
+
+    fn html(&self) -> String {
+        let status = self.status();
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"UTF-8\"><title>{} {}</title></head>\n\
+             <body><h1>{}</h1><p>{}</p><p><a href=\"/\">Station page</a></p></body>\n</html>\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default(),
+            status.canonical_reason().unwrap_or_default(),
+            escape_html(&self.error)
+        )
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::header::CONTENT_TYPE;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn given_player_when_mount_is_off_air_then_replies_json_with_notice() {
+        let response = StreamRejection::new(RejectReason::OffAir, "high").reply(&HeaderMap::new());
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            response.headers()[ICY_NOTICE],
+            "Stream 'high' is off the air, try again later"
+        );
+    }
+
+    #[test]
+    fn given_browser_when_mount_is_unknown_then_replies_escaped_html() {
+        let response = StreamRejection::new(RejectReason::UnknownMount, "<b>")
+            .reply(&accept("text/html,application/xhtml+xml"));
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+
+        let rejection = StreamRejection::new(RejectReason::UnknownMount, "<b>");
+        assert!(rejection.html().contains("No stream named '&lt;b&gt;'"));
+    }
+}
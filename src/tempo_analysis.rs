@@ -0,0 +1,273 @@
+use crate::cue_sheet::TrackSection;
+use std::error::Error;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Sample rate the audio is decoded at for the analysis, enough for beats and loudness
+const ANALYSIS_SAMPLE_RATE: u32 = 11025;
+
+/// Seconds of audio analyzed from the start of a track
+const ANALYSIS_SECONDS: &str = "120";
+
+/// Samples per frame of the energy envelope, about 23 ms
+const FRAME_SAMPLES: usize = 256;
+
+/// Tempo range searched for the beat, slower and faster tracks are found at a multiple
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+
+/// Tempo most music is close to, breaks the tie between a tempo and its half or double
+const TYPICAL_BPM: f64 = 120.0;
+
+/// Loudness mapped to the lowest and highest energy, in dBFS
+const QUIET_DBFS: f64 = -30.0;
+const LOUD_DBFS: f64 = -6.0;
+
+/// Library tracks of the rotation looked at for the one closest in tempo to the current one
+pub const TEMPO_CANDIDATES: usize = 5;
+
+/// Tempo and energy of a track, either unknown if it was not analyzed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrackTempo {
+    /// Beats per minute, `None` for tracks without a steady beat
+    pub bpm: Option<f64>,
+    /// From 0 (calm) to 1 (energetic)
+    pub energy: Option<f64>,
+}
+
+impl TrackTempo {
+    /// How far apart two tracks are in tempo and energy, 0 for a perfect match. A tempo and
+    /// its half or double match, they mix well. `None` if nothing of both is known.
+    pub fn distance(&self, other: &TrackTempo) -> Option<f64> {
+        let tempo = match (self.bpm, other.bpm) {
+            (Some(a), Some(b)) => {
+                let octaves = (a / b).log2().abs().fract();
+                Some(octaves.min(1.0 - octaves))
+            }
+            _ => None,
+        };
+        let energy = match (self.energy, other.energy) {
+            (Some(a), Some(b)) => Some((a - b).abs()),
+            _ => None,
+        };
+        match (tempo, energy) {
+            (Some(tempo), Some(energy)) => Some(tempo + energy),
+            (tempo, energy) => tempo.or(energy),
+        }
+    }
+}
+
+/// Index of the candidate closest in tempo and energy to `current`, unknown candidates are
+/// never picked
+pub fn closest_tempo(current: &TrackTempo, candidates: &[TrackTempo]) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| Some((index, current.distance(candidate)?)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+/// Estimates tempo and energy of tracks from their first two minutes, decoded with ffmpeg
+#[derive(Clone)]
+pub struct TempoAnalyzer {
+    ffmpeg_path: String,
+}
+
+impl TempoAnalyzer {
+    pub fn new(ffmpeg_path: Option<String>) -> Self {
+        Self {
+            ffmpeg_path: ffmpeg_path.unwrap_or_else(|| "ffmpeg".to_string()),
+        }
+    }
+
+    /// Analyzes the file at `path`, or only its `section` for a track of a single-file album
+    pub fn analyze(
+        &self,
+        path: &Path,
+        section: Option<&TrackSection>,
+    ) -> Result<TrackTempo, Box<dyn Error + Send + Sync>> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-v", "error"])
+            .args(section.map(TrackSection::input_args).unwrap_or_default())
+            .arg("-i")
+            .arg(path)
+            .args(["-map", "0:a:0", "-t", ANALYSIS_SECONDS, "-ac", "1", "-ar"])
+            .arg(ANALYSIS_SAMPLE_RATE.to_string())
+            .args(["-f", "s16le", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("Cannot run {}: {}", self.ffmpeg_path, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "ffmpeg failed to decode the audio with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        let samples: Vec<i16> = output
+            .stdout
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        if samples.len() < FRAME_SAMPLES * 2 {
+            return Err("Too little audio to analyze".into());
+        }
+        Ok(analyze_samples(&samples, ANALYSIS_SAMPLE_RATE))
+    }
+}
+
+/// Tempo from the periodicity of the onsets, energy from the loudness and the density of the
+/// onsets of mono audio
+fn analyze_samples(samples: &[i16], sample_rate: u32) -> TrackTempo {
+    let levels: Vec<f64> = samples
+        .chunks_exact(FRAME_SAMPLES)
+        .map(|frame| {
+            let power = frame
+                .iter()
+                .map(|&s| (s as f64 / 32768.0).powi(2))
+                .sum::<f64>()
+                / frame.len() as f64;
+            power.sqrt()
+        })
+        .collect();
+    // Rises of the level, where notes and beats start
+    let onsets: Vec<f64> = levels
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).max(0.0))
+        .collect();
+
+    let frames_per_second = sample_rate as f64 / FRAME_SAMPLES as f64;
+    let bpm = estimate_bpm(&onsets, frames_per_second);
+
+    let mean_level = levels.iter().sum::<f64>() / levels.len() as f64;
+    let rms = (levels.iter().map(|l| l * l).sum::<f64>() / levels.len() as f64).sqrt();
+    let loudness = if rms > 0.0 {
+        ((20.0 * rms.log10() - QUIET_DBFS) / (LOUD_DBFS - QUIET_DBFS)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let activity = if mean_level > 0.0 {
+        (onsets.iter().sum::<f64>() / onsets.len() as f64 / mean_level * 4.0).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let energy = 0.6 * loudness + 0.4 * activity;
+
+    TrackTempo {
+        bpm: bpm.map(|bpm| (bpm * 10.0).round() / 10.0),
+        energy: Some((energy * 100.0).round() / 100.0),
+    }
+}
+
+/// Beat period with the strongest autocorrelation of the onsets, weighted towards the typical
+/// tempo so a tempo is not mistaken for its half
+fn estimate_bpm(onsets: &[f64], frames_per_second: f64) -> Option<f64> {
+    let mean = onsets.iter().sum::<f64>() / onsets.len() as f64;
+    let centered: Vec<f64> = onsets.iter().map(|o| o - mean).collect();
+    let min_lag = (60.0 * frames_per_second / MAX_BPM).floor() as usize;
+    let max_lag = (60.0 * frames_per_second / MIN_BPM).ceil() as usize;
+    if centered.len() < max_lag * 4 {
+        return None;
+    }
+
+    let correlation = |lag: usize| {
+        centered
+            .iter()
+            .zip(&centered[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f64>()
+            / (centered.len() - lag) as f64
+    };
+    let correlations: Vec<f64> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let weight = |lag: f64| {
+        let octaves = (60.0 * frames_per_second / lag / TYPICAL_BPM).log2();
+        (-0.5 * octaves * octaves).exp()
+    };
+
+    let (best, strength) = (1..correlations.len() - 1)
+        .map(|i| (i, correlations[i] * weight((min_lag + i - 1) as f64)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    if strength <= 0.0 {
+        return None;
+    }
+
+    // The beat falls between two frames, the peak of a parabola through the neighbors finds it
+    let (before, peak, after) = (
+        correlations[best - 1],
+        correlations[best],
+        correlations[best + 1],
+    );
+    let curvature = before - 2.0 * peak + after;
+    let shift = if curvature < 0.0 {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let lag = (min_lag + best - 1) as f64 + shift;
+    Some(60.0 * frames_per_second / lag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clicks of 10 ms at the tempo over a quiet noise floor
+    fn click_track(bpm: f64, seconds: usize, level: f64) -> Vec<i16> {
+        let rate = ANALYSIS_SAMPLE_RATE as usize;
+        let period = 60.0 / bpm * rate as f64;
+        (0..rate * seconds)
+            .map(|i| {
+                let in_beat = i as f64 % period;
+                let click = if in_beat < rate as f64 / 100.0 {
+                    level
+                } else {
+                    0.0
+                };
+                let noise = ((i * 7919) % 200) as f64 - 100.0;
+                (click * 32767.0 * if i % 2 == 0 { 1.0 } else { -1.0 } + noise) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn given_click_track_when_analyzing_then_finds_its_tempo() {
+        for bpm in [95.0, 128.0, 174.0] {
+            let tempo = analyze_samples(&click_track(bpm, 30, 0.9), ANALYSIS_SAMPLE_RATE);
+            let found = tempo.bpm.unwrap();
+            assert!((found - bpm).abs() < 2.0, "found {} for {}", found, bpm);
+        }
+
+        let silence = analyze_samples(&[0; 44100], ANALYSIS_SAMPLE_RATE);
+        assert_eq!(silence.bpm, None);
+        assert_eq!(silence.energy, Some(0.0));
+    }
+
+    #[test]
+    fn given_loud_and_quiet_tracks_when_analyzing_then_loud_one_has_more_energy() {
+        let loud = analyze_samples(&click_track(128.0, 20, 0.9), ANALYSIS_SAMPLE_RATE);
+        let quiet = analyze_samples(&click_track(128.0, 20, 0.05), ANALYSIS_SAMPLE_RATE);
+
+        assert!(loud.energy.unwrap() > quiet.energy.unwrap());
+        assert!((0.0..=1.0).contains(&loud.energy.unwrap()));
+    }
+
+    #[test]
+    fn given_candidates_when_matching_tempo_then_picks_the_closest_one() {
+        let tempo = |bpm: Option<f64>, energy: Option<f64>| TrackTempo { bpm, energy };
+        let current = tempo(Some(124.0), Some(0.7));
+        let candidates = [
+            tempo(Some(90.0), Some(0.7)),
+            tempo(None, None),
+            // Half the tempo mixes as well as the same tempo
+            tempo(Some(62.5), Some(0.65)),
+            tempo(Some(128.0), Some(0.2)),
+        ];
+
+        assert_eq!(closest_tempo(&current, &candidates), Some(2));
+        assert_eq!(closest_tempo(&current, &[tempo(None, None)]), None);
+        assert_eq!(current.distance(&current), Some(0.0));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::library_db::{LibraryDatabase, TrackRecord};
+use crate::tempo_analysis::TrackTempo;
+use log::{debug, error};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+#[derive(Default)]
+struct TrackIndex {
+    /// Library tracks by file path
+    tracks: HashMap<String, TrackRecord>,
+    quarantined: HashSet<String>,
+}
+
+/// In-memory index of the track table, so track selection on the playout path does not query
+/// SQLite for every decision.
+///
+/// The index is loaded once and kept in sync by the components changing the library: the
+/// scanner after scans, imports and deletions, the quarantine when a track is quarantined.
+#[derive(Clone)]
+pub struct TrackCache {
+    db: LibraryDatabase,
+    index: Arc<RwLock<TrackIndex>>,
+}
+
+impl TrackCache {
+    pub fn load(db: LibraryDatabase) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let cache = Self {
+            index: Arc::new(RwLock::new(Self::read_index(&db)?)),
+            db,
+        };
+        Ok(cache)
+    }
+
+    /// Reloads the index after a scan, the previous index is kept if the database fails
+    pub fn reload(&self) {
+        match Self::read_index(&self.db) {
+            Ok(index) => {
+                debug!("Track cache reloaded with {} tracks", index.tracks.len());
+                *self.index.write().unwrap() = index;
+            }
+            Err(e) => error!("Failed to reload track cache: {}", e),
+        }
+    }
+
+    /// A track was added or its tags changed
+    pub fn track_changed(&self, track: TrackRecord) {
+        let mut index = self.index.write().unwrap();
+        index.tracks.insert(track.file_path.clone(), track);
+    }
+
+    /// A track was removed from the library
+    pub fn track_removed(&self, file_path: &str) {
+        let mut index = self.index.write().unwrap();
+        index.tracks.remove(file_path);
+        index.quarantined.remove(file_path);
+    }
+
+    pub fn set_quarantined(&self, file_path: &str, quarantined: bool) {
+        let mut index = self.index.write().unwrap();
+        if quarantined {
+            index.quarantined.insert(file_path.to_string());
+        } else {
+            index.quarantined.remove(file_path);
+        }
+    }
+
+    pub fn is_quarantined(&self, track: &Path) -> bool {
+        let index = self.index.read().unwrap();
+        index.quarantined.contains(track.to_string_lossy().as_ref())
+    }
+
+    /// Library id of a track, `None` for files outside the library
+    pub fn track_id(&self, track: &Path) -> Option<i64> {
+        let index = self.index.read().unwrap();
+        index.tracks.get(track.to_string_lossy().as_ref())?.id
+    }
+
+    /// Library record of a track, `None` for files outside the library
+    pub fn track(&self, track: &Path) -> Option<TrackRecord> {
+        let index = self.index.read().unwrap();
+        index.tracks.get(track.to_string_lossy().as_ref()).cloned()
+    }
+
+    /// Artist tag of a track, `None` for files outside the library
+    pub fn artist(&self, track: &Path) -> Option<String> {
+        let index = self.index.read().unwrap();
+        Some(
+            index
+                .tracks
+                .get(track.to_string_lossy().as_ref())?
+                .artist
+                .clone(),
+        )
+    }
+
+    /// Star rating of a track, `None` if unrated or outside the library
+    pub fn rating(&self, track: &Path) -> Option<u8> {
+        let index = self.index.read().unwrap();
+        index.tracks.get(track.to_string_lossy().as_ref())?.rating
+    }
+
+    /// Tempo and energy of a track, unknown if not analyzed or outside the library
+    pub fn tempo(&self, track: &Path) -> TrackTempo {
+        let index = self.index.read().unwrap();
+        index
+            .tracks
+            .get(track.to_string_lossy().as_ref())
+            .map(|t| TrackTempo {
+                bpm: t.bpm,
+                energy: t.energy,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Integrated loudness of a track in LUFS, `None` until the loudness analysis measured it
+    pub fn loudness(&self, track: &Path) -> Option<f64> {
+        let index = self.index.read().unwrap();
+        index
+            .tracks
+            .get(track.to_string_lossy().as_ref())?
+            .loudness_lufs
+    }
+
+    /// The loudness analysis measured a track
+    pub fn set_loudness(&self, file_path: &str, loudness_lufs: Option<f64>) {
+        let mut index = self.index.write().unwrap();
+        if let Some(track) = index.tracks.get_mut(file_path) {
+            track.loudness_lufs = loudness_lufs;
+        }
+    }
+
+    /// Playable tracks with a known duration of at most `max_seconds`, longest first, i.e.
+    /// ordered by how well they fill a time window of that length
+    pub fn tracks_fitting(&self, max_seconds: i64, limit: usize) -> Vec<TrackRecord> {
+        let index = self.index.read().unwrap();
+        let mut tracks: Vec<&TrackRecord> = index
+            .tracks
+            .values()
+            .filter(|t| !index.quarantined.contains(&t.file_path))
+            .filter(|t| t.duration_seconds.is_some_and(|d| d <= max_seconds))
+            .collect();
+        tracks.sort_by(|a, b| {
+            b.duration_seconds
+                .cmp(&a.duration_seconds)
+                .then_with(|| a.file_path.cmp(&b.file_path))
+        });
+        tracks.into_iter().take(limit).cloned().collect()
+    }
+
+    fn read_index(
+        db: &LibraryDatabase,
+    ) -> Result<TrackIndex, Box<dyn std::error::Error + Send + Sync>> {
+        let tracks = db
+            .get_all_tracks()?
+            .into_iter()
+            .map(|t| (t.file_path.clone(), t))
+            .collect();
+        let quarantined = db
+            .get_problem_tracks()?
+            .into_iter()
+            .filter(|t| t.quarantined)
+            .map(|t| t.file_path)
+            .collect();
+        Ok(TrackIndex {
+            tracks,
+            quarantined,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn track(file_path: &str, duration_seconds: Option<i64>) -> TrackRecord {
+        TrackRecord {
+            id: None,
+            file_path: file_path.to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration_seconds,
+            file_size: 1000,
+            last_modified: 1234567890,
+            file_extension: "mp3".to_string(),
+            created_at: 1234567890,
+            updated_at: 1234567890,
+            genre: None,
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+            year: None,
+        }
+    }
+
+    fn create_test_db() -> (LibraryDatabase, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        (db, temp_file)
+    }
+
+    #[test]
+    fn given_library_when_selecting_fitting_tracks_then_matches_database() {
+        let (db, _temp) = create_test_db();
+        for t in [
+            track("/music/a.mp3", Some(180)),
+            track("/music/b.mp3", Some(240)),
+            track("/music/c.mp3", Some(400)),
+            track("/music/d.mp3", None),
+            track("/music/e.mp3", Some(200)),
+        ] {
+            db.insert_track(&t).unwrap();
+        }
+        db.record_track_failure("/music/e.mp3", "invalid data", 1)
+            .unwrap();
+
+        let cache = TrackCache::load(db.clone()).unwrap();
+        let paths = |tracks: Vec<TrackRecord>| -> Vec<String> {
+            tracks.into_iter().map(|t| t.file_path).collect()
+        };
+
+        assert_eq!(
+            paths(cache.tracks_fitting(300, 10)),
+            vec!["/music/b.mp3", "/music/a.mp3"]
+        );
+        assert_eq!(paths(cache.tracks_fitting(300, 1)), vec!["/music/b.mp3"]);
+        assert!(cache.is_quarantined(Path::new("/music/e.mp3")));
+    }
+
+    #[test]
+    fn given_library_changes_when_reported_then_cache_follows() {
+        let (db, _temp) = create_test_db();
+        let cache = TrackCache::load(db.clone()).unwrap();
+
+        cache.track_changed(track("/music/a.mp3", Some(180)));
+        assert_eq!(cache.tracks_fitting(200, 10).len(), 1);
+
+        cache.set_quarantined("/music/a.mp3", true);
+        assert!(cache.is_quarantined(Path::new("/music/a.mp3")));
+        assert!(cache.tracks_fitting(200, 10).is_empty());
+
+        cache.track_removed("/music/a.mp3");
+        assert!(!cache.is_quarantined(Path::new("/music/a.mp3")));
+
+        // A scan replaces the index with the database content
+        db.insert_track(&track("/music/b.mp3", Some(120))).unwrap();
+        cache.reload();
+        assert_eq!(cache.tracks_fitting(200, 10)[0].file_path, "/music/b.mp3");
+    }
+}
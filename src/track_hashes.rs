@@ -0,0 +1,110 @@
+use ring::digest;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// SHA-256 of the file content as hex, identical copies of a file share it
+pub fn content_hash(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut file = File::open(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(to_hex(context.finish().as_ref()))
+}
+
+/// Hashes the decoded audio of tracks with ffmpeg, so copies of a file that differ only in
+/// their tags or cover art are found as duplicates. Re-encoded copies are not matched.
+#[derive(Clone)]
+pub struct AudioFingerprinter {
+    ffmpeg_path: String,
+}
+
+impl AudioFingerprinter {
+    pub fn new(ffmpeg_path: Option<String>) -> Self {
+        Self {
+            ffmpeg_path: ffmpeg_path.unwrap_or_else(|| "ffmpeg".to_string()),
+        }
+    }
+
+    /// SHA-256 of the decoded first audio stream as hex, reads and decodes the whole file
+    pub fn fingerprint(&self, path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-v", "error", "-i"])
+            .arg(path)
+            .args(["-map", "0:a:0", "-f", "hash", "-hash", "sha256", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("Cannot run {}: {}", self.ffmpeg_path, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "ffmpeg failed to decode the audio with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("SHA256="))
+            .map(|hash| hash.to_lowercase())
+            .ok_or_else(|| format!("Unexpected ffmpeg hash output: {}", stdout.trim()).into())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn given_copied_file_when_hashing_then_hash_matches_the_original() {
+        let directory = TempDir::new().unwrap();
+        let original = directory.path().join("a.mp3");
+        let copy = directory.path().join("copy.mp3");
+        let other = directory.path().join("b.mp3");
+        std::fs::write(&original, vec![7u8; 100_000]).unwrap();
+        std::fs::copy(&original, &copy).unwrap();
+        std::fs::write(&other, b"other").unwrap();
+
+        let hash = content_hash(&original).unwrap();
+
+        assert_eq!(hash.len(), 64);
+        assert_eq!(content_hash(&copy).unwrap(), hash);
+        assert_ne!(content_hash(&other).unwrap(), hash);
+        assert!(content_hash(&directory.path().join("missing.mp3")).is_err());
+    }
+
+    #[test]
+    fn given_ffmpeg_hash_output_when_fingerprinting_then_reads_the_hash() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new().unwrap();
+        // Stands in for ffmpeg and prints the output of its hash muxer
+        let ffmpeg = directory.path().join("ffmpeg");
+        std::fs::write(&ffmpeg, "#!/bin/sh\necho SHA256=ABC123\n").unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let fingerprinter = AudioFingerprinter::new(Some(ffmpeg.to_string_lossy().to_string()));
+
+        assert_eq!(
+            fingerprinter
+                .fingerprint(Path::new("/music/a.mp3"))
+                .unwrap(),
+            "abc123"
+        );
+    }
+}
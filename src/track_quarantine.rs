@@ -0,0 +1,177 @@
+use crate::library_db::LibraryDatabase;
+use crate::track_cache::TrackCache;
+use log::{error, warn};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Default number of consecutive failures before a track is quarantined
+pub const DEFAULT_MAX_TRACK_FAILURES: u32 = 3;
+
+/// Playouts whose outcome is remembered, more than the streams can be apart
+const RECENT_PLAYOUTS: usize = 64;
+
+/// Records playout failures of library tracks and quarantines tracks that
+/// fail repeatedly, so they are no longer selected for playback.
+///
+/// Every stream decodes the same queued track, only the first outcome reported for a
+/// playout is recorded, so a failed playout counts once however many streams run.
+#[derive(Clone)]
+pub struct TrackQuarantine {
+    db: LibraryDatabase,
+    max_failures: u32,
+    track_cache: Option<TrackCache>,
+    /// Playouts whose outcome was recorded, shared by the streams
+    recorded: Arc<Mutex<VecDeque<u64>>>,
+}
+
+impl TrackQuarantine {
+    pub fn new(db: LibraryDatabase, max_failures: Option<u32>) -> Self {
+        Self {
+            db,
+            max_failures: max_failures.unwrap_or(DEFAULT_MAX_TRACK_FAILURES).max(1),
+            track_cache: None,
+            recorded: Arc::default(),
+        }
+    }
+
+    /// Marks quarantined tracks in the track cache as well
+    pub fn with_track_cache(mut self, track_cache: TrackCache) -> Self {
+        self.track_cache = Some(track_cache);
+        self
+    }
+
+    pub fn report_failure(&self, playout: u64, track: &Path, reason: &str) {
+        if !self.first_outcome(playout) {
+            return;
+        }
+        let file_path = track.to_string_lossy();
+
+        match self
+            .db
+            .record_track_failure(&file_path, reason, self.max_failures)
+        {
+            Ok(true) => {
+                warn!(
+                    "Track {:?} quarantined after {} failed attempts: {}",
+                    track, self.max_failures, reason
+                );
+                if let Some(track_cache) = &self.track_cache {
+                    track_cache.set_quarantined(&file_path, true);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => error!("Failed to record failure for track {:?}: {}", track, e),
+        }
+    }
+
+    pub fn report_success(&self, playout: u64, track: &Path) {
+        if !self.first_outcome(playout) {
+            return;
+        }
+        if let Err(e) = self.db.record_track_success(&track.to_string_lossy()) {
+            error!("Failed to reset failure count for track {:?}: {}", track, e);
+        }
+    }
+
+    /// Whether no other stream reported the outcome of the playout yet
+    fn first_outcome(&self, playout: u64) -> bool {
+        let mut recorded = self.recorded.lock().unwrap();
+        if recorded.contains(&playout) {
+            return false;
+        }
+        if recorded.len() == RECENT_PLAYOUTS {
+            recorded.pop_front();
+        }
+        recorded.push_back(playout);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library_db::TrackRecord;
+    use tempfile::NamedTempFile;
+
+    fn create_test_db_with_track(file_path: &str) -> (LibraryDatabase, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = LibraryDatabase::new(temp_file.path().to_str().unwrap()).unwrap();
+        db.initialize_schema().unwrap();
+        db.insert_track(&TrackRecord {
+            id: None,
+            file_path: file_path.to_string(),
+            title: "Broken Song".to_string(),
+            artist: "Test Artist".to_string(),
+            album: "Test Album".to_string(),
+            duration_seconds: Some(180),
+            file_size: 1000,
+            last_modified: 1234567890,
+            file_extension: "mp3".to_string(),
+            created_at: 1234567890,
+            updated_at: 1234567890,
+            genre: None,
+            artwork_path: None,
+            rating: None,
+            content_hash: None,
+            audio_fingerprint: None,
+            validation_error: None,
+            loudness_lufs: None,
+            bpm: None,
+            energy: None,
+            start_seconds: None,
+            end_seconds: None,
+            year: None,
+        })
+        .unwrap();
+        (db, temp_file)
+    }
+
+    #[test]
+    fn given_track_failing_repeatedly_when_reported_then_quarantined_at_threshold() {
+        let (db, _temp) = create_test_db_with_track("/music/broken.mp3");
+        let quarantine = TrackQuarantine::new(db.clone(), Some(2));
+
+        quarantine.report_failure(1, Path::new("/music/broken.mp3"), "invalid data");
+        assert!(!db.get_problem_tracks().unwrap()[0].quarantined);
+
+        quarantine.report_failure(2, Path::new("/music/broken.mp3"), "invalid data");
+        assert!(db.get_problem_tracks().unwrap()[0].quarantined);
+    }
+
+    #[test]
+    fn given_several_streams_failing_one_playout_when_reported_then_counts_as_one_failure() {
+        let (db, _temp) = create_test_db_with_track("/music/broken.mp3");
+        let quarantine = TrackQuarantine::new(db.clone(), Some(3));
+        let streams: Vec<TrackQuarantine> = (0..5).map(|_| quarantine.clone()).collect();
+
+        for stream in &streams {
+            stream.report_failure(1, Path::new("/music/broken.mp3"), "invalid data");
+        }
+
+        let problems = db.get_problem_tracks().unwrap();
+        assert_eq!(problems[0].failure_count, 1);
+        assert!(!problems[0].quarantined);
+    }
+
+    #[test]
+    fn given_failed_playout_when_another_stream_succeeds_then_failure_is_kept() {
+        let (db, _temp) = create_test_db_with_track("/music/broken.mp3");
+        let quarantine = TrackQuarantine::new(db.clone(), Some(3));
+
+        quarantine.report_failure(1, Path::new("/music/broken.mp3"), "invalid data");
+        quarantine
+            .clone()
+            .report_success(1, Path::new("/music/broken.mp3"));
+
+        assert_eq!(db.get_problem_tracks().unwrap()[0].failure_count, 1);
+    }
+
+    #[test]
+    fn given_no_configured_threshold_when_created_then_uses_default() {
+        let (db, _temp) = create_test_db_with_track("/music/broken.mp3");
+        let quarantine = TrackQuarantine::new(db, None);
+
+        assert_eq!(quarantine.max_failures, DEFAULT_MAX_TRACK_FAILURES);
+    }
+}
@@ -0,0 +1,544 @@
+use crate::config::{TransitionConfig, TransitionStyle};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DEFAULT_FADE_SECONDS: f64 = 3.0;
+const DEFAULT_DUCK_DB: f64 = -12.0;
+const DEFAULT_DUCK_FADE_SECONDS: f64 = 0.5;
+
+/// Audio held back beyond the expected outro, the known track lengths are rounded to seconds
+const OUTRO_HOLD_MARGIN: Duration = Duration::from_secs(2);
+
+/// Bytes of a single signed 16-bit PCM sample
+pub const PCM_SAMPLE_BYTES: usize = 2;
+
+/// Layout of the raw PCM audio mixed between decoder and encoder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+impl PcmFormat {
+    pub fn frame_bytes(&self) -> usize {
+        PCM_SAMPLE_BYTES * self.channels as usize
+    }
+
+    /// Number of bytes holding the given duration of audio
+    pub fn bytes_for(&self, duration: Duration) -> usize {
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64) as usize;
+        frames * self.frame_bytes()
+    }
+
+    /// Playback time of the given number of bytes
+    pub fn duration_of(&self, bytes: usize) -> Duration {
+        let frames = bytes / self.frame_bytes();
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
+
+/// Resolved transition between two consecutive tracks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub style: TransitionStyle,
+    pub fade_out: Duration,
+    pub fade_in: Duration,
+    /// Linear gain the ending track is ducked to
+    pub duck_gain: f32,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            style: TransitionStyle::Cut,
+            fade_out: Duration::ZERO,
+            fade_in: Duration::ZERO,
+            duck_gain: 1.0,
+        }
+    }
+}
+
+impl Transition {
+    pub fn from_config(config: &TransitionConfig) -> Self {
+        let seconds = |value: Option<f64>| {
+            Duration::from_secs_f64(value.unwrap_or(DEFAULT_FADE_SECONDS).max(0.0))
+        };
+
+        Self {
+            style: config.style,
+            fade_out: seconds(config.fade_out_seconds),
+            fade_in: seconds(config.fade_in_seconds),
+            duck_gain: 10f64.powf(config.duck_db.unwrap_or(DEFAULT_DUCK_DB) / 20.0) as f32,
+        }
+    }
+
+    /// Crossfade with both tracks fading over the same length, a cut if it is zero
+    pub fn crossfade(length: Duration) -> Self {
+        Self {
+            style: TransitionStyle::Crossfade,
+            fade_out: length,
+            fade_in: length,
+            duck_gain: 1.0,
+        }
+    }
+
+    /// Audio at the end of a track that is held back to be mixed into the transition
+    pub fn tail_bytes(&self, format: PcmFormat) -> usize {
+        match self.style {
+            TransitionStyle::Cut => 0,
+            _ => format.bytes_for(self.fade_out),
+        }
+    }
+
+    /// Audio at the start of the next track needed to mix the transition
+    pub fn head_bytes(&self, format: PcmFormat) -> usize {
+        match self.style {
+            TransitionStyle::Cut => 0,
+            TransitionStyle::Fade => format.bytes_for(self.fade_in),
+            TransitionStyle::Crossfade | TransitionStyle::Duck => {
+                format.bytes_for(self.fade_in.max(self.fade_out))
+            }
+        }
+    }
+
+    /// Mixes the held back end of the previous track with the start of the next one.
+    ///
+    /// Only the last `fade_out` of `tail` is faded, audio before it passes unchanged.
+    /// Overlapping styles shorten the output by the overlap.
+    pub fn mix(&self, tail: &[u8], head: &[u8], format: PcmFormat) -> Vec<u8> {
+        let frame_bytes = format.frame_bytes();
+        let channels = format.channels as usize;
+        let tail = to_samples(&tail[..tail.len() - tail.len() % frame_bytes]);
+        let head_aligned = head.len() - head.len() % frame_bytes;
+        let (head, head_rest) = (to_samples(&head[..head_aligned]), &head[head_aligned..]);
+
+        let out_frames = (self.fade_out.as_secs_f64() * format.sample_rate as f64) as usize;
+        let out_frames = out_frames.min(tail.len() / channels);
+        let in_frames = (self.fade_in.as_secs_f64() * format.sample_rate as f64) as usize;
+        let (kept, fading) = tail.split_at(tail.len() - out_frames * channels);
+
+        let fade_out = |frame: usize| 1.0 - frame as f32 / out_frames.max(1) as f32;
+        let fade_in = |frame: usize| (frame as f32 / in_frames.max(1) as f32).min(1.0);
+
+        let mut mixed: Vec<i16> = kept.to_vec();
+        match self.style {
+            TransitionStyle::Cut => {
+                mixed.extend_from_slice(fading);
+                mixed.extend_from_slice(&head);
+            }
+            TransitionStyle::Fade => {
+                mixed.extend(scale(fading, channels, fade_out));
+                mixed.extend(scale(&head, channels, fade_in));
+            }
+            TransitionStyle::Crossfade | TransitionStyle::Duck => {
+                let duck = |frame: usize| match self.style {
+                    TransitionStyle::Duck => 1.0 + (self.duck_gain - 1.0) * fade_in(frame),
+                    _ => 1.0,
+                };
+                let overlap = fading.len().max(head.len());
+                mixed.extend((0..overlap).map(|i| {
+                    let frame = i / channels;
+                    let outgoing = fading
+                        .get(i)
+                        .map_or(0.0, |&s| s as f32 * fade_out(frame) * duck(frame));
+                    let incoming = head.get(i).map_or(0.0, |&s| s as f32 * fade_in(frame));
+                    clip(outgoing + incoming)
+                }));
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(mixed.len() * PCM_SAMPLE_BYTES + head_rest.len());
+        for sample in mixed {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        bytes.extend_from_slice(head_rest);
+        bytes
+    }
+}
+
+/// Transitions of a scheduled program
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProgramTransitions {
+    /// Into the tracks of the program, the stream transition is used if not set
+    pub tracks: Option<Transition>,
+    /// From the library into the first program track
+    pub fade_in: Option<Transition>,
+    /// From the program into the first library track after it
+    pub fade_out: Option<Transition>,
+}
+
+/// Intro and outro cue points of a queued track, as offsets from its start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackCue {
+    /// End of the intro, the outro of the previous track may play over the intro
+    pub intro: Option<Duration>,
+    /// Start of the outro, the next track starts here
+    pub outro: Option<Duration>,
+    /// Length of the track, if known
+    pub length: Option<Duration>,
+}
+
+impl TrackCue {
+    /// Audio to hold back at the end of the track so its outro can be mixed with the next
+    /// track, `None` without an outro cue point
+    pub fn outro_hold(&self) -> Option<Duration> {
+        let outro = self.outro?;
+        let length = self.length?;
+        Some(length.saturating_sub(outro) + OUTRO_HOLD_MARGIN)
+    }
+
+    /// Transition starting the next track at the outro of this one, which has `played` of
+    /// audio decoded in total. The overlap ends with the intro of the next track.
+    pub fn segue(&self, played: Duration, next: Option<&TrackCue>) -> Option<Transition> {
+        let mut overlap = played.checked_sub(self.outro?)?;
+        if let Some(intro) = next.and_then(|cue| cue.intro) {
+            overlap = overlap.min(intro);
+        }
+        (!overlap.is_zero()).then_some(Transition {
+            style: TransitionStyle::Crossfade,
+            fade_out: overlap,
+            fade_in: Duration::ZERO,
+            duck_gain: 1.0,
+        })
+    }
+}
+
+/// Voice played over the start of a track, e.g. a voice track or announcement
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceOver {
+    pub path: PathBuf,
+    pub ducking: Ducking,
+}
+
+/// How far and how fast the music is lowered under a voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ducking {
+    /// Linear gain of the music while the voice plays
+    pub gain: f32,
+    /// Length of the fade down when the voice starts and back up when it ended
+    pub fade: Duration,
+}
+
+impl Ducking {
+    pub fn new(duck_db: f64, fade_seconds: Option<f64>) -> Self {
+        Self {
+            gain: 10f64.powf(duck_db / 20.0) as f32,
+            fade: Duration::from_secs_f64(
+                fade_seconds.unwrap_or(DEFAULT_DUCK_FADE_SECONDS).max(0.0),
+            ),
+        }
+    }
+}
+
+/// Mixes a voice into the music bed, which fades down to the ducked level as the voice starts
+/// and back up once it ended.
+///
+/// The audio may be passed in pieces of any length, samples split between two pieces are
+/// carried over to the next one.
+pub struct VoiceOverMixer {
+    ducking: Ducking,
+    format: PcmFormat,
+    /// Music frames mixed so far
+    position: usize,
+    /// Frame at which the voice ended
+    voice_end: Option<usize>,
+    /// Start of a frame split off the end of the last piece
+    carry: Vec<u8>,
+}
+
+impl VoiceOverMixer {
+    pub fn new(ducking: Ducking, format: PcmFormat) -> Self {
+        Self {
+            ducking,
+            format,
+            position: 0,
+            voice_end: None,
+            carry: Vec::new(),
+        }
+    }
+
+    /// Bytes of voice needed to mix the next piece of music of `music_bytes`
+    pub fn voice_bytes_for(&self, music_bytes: usize) -> usize {
+        let bytes = self.carry.len() + music_bytes;
+        bytes - bytes % self.format.frame_bytes()
+    }
+
+    /// Mixes `voice` into `music`, a voice shorter than `voice_bytes_for` the music has ended
+    pub fn mix(&mut self, music: &[u8], voice: &[u8]) -> Vec<u8> {
+        let channels = self.format.channels as usize;
+        let mut pending = std::mem::take(&mut self.carry);
+        pending.extend_from_slice(music);
+        let aligned = pending.len() - pending.len() % self.format.frame_bytes();
+        self.carry = pending.split_off(aligned);
+
+        let music = to_samples(&pending);
+        let voice = to_samples(voice);
+        if self.voice_end.is_none() && voice.len() < music.len() {
+            self.voice_end = Some(self.position + voice.len() / channels);
+        }
+
+        let mut bytes = Vec::with_capacity(pending.len());
+        for (i, &sample) in music.iter().enumerate() {
+            let gain = 1.0 + (self.ducking.gain - 1.0) * self.depth(self.position + i / channels);
+            let voice = voice.get(i).map_or(0.0, |&v| v as f32);
+            bytes.extend_from_slice(&clip(sample as f32 * gain + voice).to_le_bytes());
+        }
+        self.position += music.len() / channels;
+        bytes
+    }
+
+    /// Whether the voice ended and the music is back at full level
+    pub fn is_finished(&self) -> bool {
+        self.voice_end
+            .is_some_and(|end| self.position >= end + self.fade_frames())
+    }
+
+    /// Audio held back for a split frame, played unmixed once the mixer is finished
+    pub fn into_rest(self) -> Vec<u8> {
+        self.carry
+    }
+
+    /// How far the music is lowered at the frame, from 0 (full level) to 1 (ducked)
+    fn depth(&self, frame: usize) -> f32 {
+        let fade = self.fade_frames() as f32;
+        let down = frame as f32 / fade;
+        let up = match self.voice_end {
+            Some(end) => 1.0 - frame.saturating_sub(end) as f32 / fade,
+            None => 1.0,
+        };
+        down.min(up).clamp(0.0, 1.0)
+    }
+
+    fn fade_frames(&self) -> usize {
+        ((self.ducking.fade.as_secs_f64() * self.format.sample_rate as f64) as usize).max(1)
+    }
+}
+
+/// Volume ramp from full level down to silence, e.g. when a program ends mid-track.
+///
+/// Like the voice over mixer it takes the audio in pieces of any length, the audio after
+/// the end of the ramp is dropped.
+pub struct FadeOut {
+    format: PcmFormat,
+    /// Frames the ramp lasts
+    length: usize,
+    /// Frames faded so far
+    position: usize,
+    /// Start of a frame split off the end of the last piece
+    carry: Vec<u8>,
+}
+
+impl FadeOut {
+    pub fn new(duration: Duration, format: PcmFormat) -> Self {
+        Self {
+            format,
+            length: (format.bytes_for(duration) / format.frame_bytes()).max(1),
+            position: 0,
+            carry: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, pcm: &[u8]) -> Vec<u8> {
+        let channels = self.format.channels as usize;
+        let mut pending = std::mem::take(&mut self.carry);
+        pending.extend_from_slice(pcm);
+        let aligned = pending.len() - pending.len() % self.format.frame_bytes();
+        self.carry = pending.split_off(aligned);
+
+        let remaining = (self.length - self.position) * channels;
+        let samples = to_samples(&pending);
+        let samples = &samples[..samples.len().min(remaining)];
+        let start = self.position;
+        let length = self.length as f32;
+        let bytes = scale(samples, channels, |frame| {
+            1.0 - (start + frame) as f32 / length
+        })
+        .flat_map(i16::to_le_bytes)
+        .collect();
+        self.position += samples.len() / channels;
+        bytes
+    }
+
+    /// Whether the ramp reached silence
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.length
+    }
+}
+
+fn to_samples(bytes: &[u8]) -> Vec<i16> {
+    bytes
+        .chunks_exact(PCM_SAMPLE_BYTES)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+fn scale<'a>(
+    samples: &'a [i16],
+    channels: usize,
+    gain: impl Fn(usize) -> f32 + 'a,
+) -> impl Iterator<Item = i16> + 'a {
+    samples
+        .iter()
+        .enumerate()
+        .map(move |(i, &s)| clip(s as f32 * gain(i / channels)))
+}
+
+fn clip(sample: f32) -> i16 {
+    sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: PcmFormat = PcmFormat {
+        sample_rate: 10,
+        channels: 1,
+    };
+
+    fn transition(style: TransitionStyle) -> Transition {
+        Transition::from_config(&TransitionConfig {
+            style,
+            fade_out_seconds: Some(1.0),
+            fade_in_seconds: Some(1.0),
+            duck_db: Some(-6.0),
+        })
+    }
+
+    fn pcm(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn given_cut_when_mixing_then_tracks_are_concatenated() {
+        let mixed = Transition::default().mix(&pcm(&[1, 2]), &pcm(&[3, 4]), FORMAT);
+
+        assert_eq!(to_samples(&mixed), vec![1, 2, 3, 4]);
+        assert_eq!(Transition::default().tail_bytes(FORMAT), 0);
+    }
+
+    #[test]
+    fn given_fade_when_mixing_then_fades_out_to_silence_and_in_from_silence() {
+        let fade = transition(TransitionStyle::Fade);
+        let tail = pcm(&[1000; 15]);
+        let head = pcm(&[1000; 10]);
+
+        let mixed = to_samples(&fade.mix(&tail, &head, FORMAT));
+
+        assert_eq!(mixed.len(), 25);
+        assert_eq!(&mixed[..5], &[1000; 5]);
+        assert_eq!(mixed[5], 1000);
+        assert_eq!(mixed[14], 100);
+        assert_eq!(mixed[15], 0);
+        assert_eq!(mixed[24], 900);
+    }
+
+    #[test]
+    fn given_crossfade_when_mixing_then_tracks_overlap() {
+        let crossfade = transition(TransitionStyle::Crossfade);
+        let tail = pcm(&[1000; 10]);
+        let head = pcm(&[1000; 12]);
+
+        let mixed = to_samples(&crossfade.mix(&tail, &head, FORMAT));
+
+        assert_eq!(mixed.len(), 12);
+        assert!(mixed[..10].iter().all(|&s| s == 1000));
+        assert_eq!(crossfade.head_bytes(FORMAT), 20);
+    }
+
+    #[test]
+    fn given_voice_over_when_mixing_then_music_ducks_under_voice_and_recovers() {
+        let mut mixer = VoiceOverMixer::new(Ducking::new(-6.0, Some(0.5)), FORMAT);
+
+        // Odd piece lengths, the split sample is carried over
+        assert_eq!(mixer.voice_bytes_for(15), 14);
+        let first = to_samples(&mixer.mix(&pcm(&[1000; 8])[..15], &pcm(&[100; 7])));
+        assert!(!mixer.is_finished());
+        let second = to_samples(&mixer.mix(&pcm(&[1000; 8])[1..], &pcm(&[100; 3])));
+
+        // Fades down to -6 dB within 5 frames, the voice ends after 10 frames and the music
+        // fades back up within another 5
+        assert_eq!(first.len(), 7);
+        assert_eq!(first[0], 1100);
+        assert_eq!(first[5], 601);
+        assert_eq!(second.len(), 8);
+        assert_eq!(second[2], 601);
+        assert_eq!(second[3], 501);
+        assert_eq!(second[7], 900);
+        assert!(mixer.is_finished());
+        assert!(mixer.into_rest().is_empty());
+    }
+
+    #[test]
+    fn given_duck_when_mixing_then_ending_track_is_lowered_under_next() {
+        let duck = transition(TransitionStyle::Duck);
+        let tail = pcm(&[1000; 10]);
+        let head = pcm(&[0; 10]);
+
+        let mixed = to_samples(&duck.mix(&tail, &head, FORMAT));
+
+        // Ducked towards -6 dB while fading out, below a plain crossfade (500 at the midpoint)
+        assert_eq!(mixed[0], 1000);
+        assert_eq!(mixed[5], 375);
+        assert_eq!(mixed[9], 55);
+    }
+
+    #[test]
+    fn given_fade_out_when_applied_in_pieces_then_ramps_to_silence_and_drops_the_rest() {
+        let mut fade = FadeOut::new(Duration::from_secs(1), FORMAT);
+
+        let first = fade.apply(&pcm(&[100; 5]));
+        let second = fade.apply(&pcm(&[100; 8]));
+
+        assert_eq!(first, pcm(&[100, 90, 80, 70, 60]));
+        assert_eq!(second, pcm(&[50, 40, 30, 20, 10]));
+        assert!(fade.is_finished());
+        assert!(fade.apply(&pcm(&[100; 4])).is_empty());
+    }
+
+    #[test]
+    fn given_outro_cue_when_segue_then_next_track_starts_over_the_outro_until_its_intro_ends() {
+        let previous = TrackCue {
+            intro: None,
+            outro: Some(Duration::from_secs(170)),
+            length: Some(Duration::from_secs(180)),
+        };
+        let next = TrackCue {
+            intro: Some(Duration::from_secs(4)),
+            outro: None,
+            length: None,
+        };
+
+        assert_eq!(previous.outro_hold(), Some(Duration::from_secs(12)));
+        let segue = previous
+            .segue(Duration::from_secs_f64(179.5), Some(&next))
+            .unwrap();
+        assert_eq!(segue.style, TransitionStyle::Crossfade);
+        assert_eq!(segue.fade_out, Duration::from_secs(4));
+        assert_eq!(segue.fade_in, Duration::ZERO);
+
+        let segue = previous
+            .segue(Duration::from_secs_f64(179.5), None)
+            .unwrap();
+        assert_eq!(segue.fade_out, Duration::from_secs_f64(9.5));
+        assert!(previous.segue(Duration::from_secs(150), None).is_none());
+    }
+
+    #[test]
+    fn given_segue_when_mixing_then_next_track_plays_at_full_level_over_fading_outro() {
+        let segue = TrackCue {
+            intro: None,
+            outro: Some(Duration::from_secs(1)),
+            length: None,
+        }
+        .segue(Duration::from_millis(1500), None)
+        .unwrap();
+
+        let mixed = segue.mix(&pcm(&[100; 10]), &pcm(&[10; 8]), FORMAT);
+
+        assert_eq!(
+            mixed,
+            pcm(&[100, 100, 100, 100, 100, 100, 90, 70, 50, 30, 10, 10, 10])
+        );
+    }
+}
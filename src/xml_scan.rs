@@ -0,0 +1,129 @@
+//! Minimal scanning of the XML documents of external services (VAST ads, podcast feeds).
+//!
+//! Only what those documents need is supported: elements are found by tag name regardless
+//! of nesting, text may be wrapped in CDATA, and of the entities only `&amp;` is decoded.
+
+/// Returns the inner content of all elements with the given tag name
+pub fn element_contents<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let close = format!("</{}>", tag);
+    let mut contents = Vec::new();
+    let mut rest = xml;
+
+    while let Some((_, body)) = next_start_tag(rest, tag) {
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+
+        contents.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+
+    contents
+}
+
+/// Returns the attributes of all start tags with the given tag name, including self-closing
+/// tags such as `<enclosure url="..." />`
+pub fn start_tags<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let mut tags = Vec::new();
+    let mut rest = xml;
+
+    while let Some((attributes, body)) = next_start_tag(rest, tag) {
+        tags.push(attributes);
+        rest = body;
+    }
+
+    tags
+}
+
+/// Value of an attribute in the attributes of a start tag
+pub fn attribute_value(attributes: &str, name: &str) -> Option<String> {
+    let mut offset = 0;
+    while let Some(found) = attributes[offset..].find(name) {
+        let start = offset + found;
+        offset = start + name.len();
+
+        // Skip names that only end with the name, e.g. `data-url` for `url`
+        let at_boundary = attributes[..start]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        let Some(value) = attributes[offset..].trim_start().strip_prefix('=') else {
+            continue;
+        };
+        if !at_boundary {
+            continue;
+        }
+
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let end = value.find(quote)?;
+        return Some(value[..end].replace("&amp;", "&"));
+    }
+    None
+}
+
+pub fn text_value(content: &str) -> String {
+    let content = content.trim();
+    match content
+        .strip_prefix("<![CDATA[")
+        .and_then(|c| c.strip_suffix("]]>"))
+    {
+        Some(cdata) => cdata.trim().to_string(),
+        None => content.replace("&amp;", "&"),
+    }
+}
+
+/// Finds the next start tag with the given name, returns its attributes and the document
+/// after it
+fn next_start_tag<'a>(xml: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let mut rest = xml;
+
+    loop {
+        let start = rest.find(&open)?;
+        let after_name = &rest[start + open.len()..];
+
+        // Skip elements that only share the prefix, e.g. <MediaFiles> for <MediaFile>
+        if !after_name.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            rest = after_name;
+            continue;
+        }
+
+        let tag_end = after_name.find('>')?;
+        let attributes = after_name[..tag_end]
+            .trim()
+            .trim_end_matches('/')
+            .trim_end();
+        return Some((attributes, &after_name[tag_end + 1..]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_self_closing_tags_when_scanned_then_returns_their_attributes() {
+        let xml = r#"<item><enclosure url="https://cdn/ep1.mp3?a=1&amp;b=2" type='audio/mpeg'/>
+            <enclosures/><enclosure length="1" url="https://cdn/ep2.mp3" /></item>"#;
+
+        let urls: Vec<_> = start_tags(xml, "enclosure")
+            .into_iter()
+            .map(|attributes| attribute_value(attributes, "url"))
+            .collect();
+
+        assert_eq!(
+            urls,
+            [
+                Some("https://cdn/ep1.mp3?a=1&b=2".to_string()),
+                Some("https://cdn/ep2.mp3".to_string())
+            ]
+        );
+        assert_eq!(
+            attribute_value(r#"type='audio/mpeg' url="x""#, "type").as_deref(),
+            Some("audio/mpeg")
+        );
+        assert_eq!(attribute_value(r#"data-url="x""#, "url"), None);
+    }
+}
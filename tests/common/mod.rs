@@ -0,0 +1,297 @@
+//! Harness booting a full station from the built binary in a temporary directory, with
+//! generated sine-wave tracks as music library.
+
+use serde_json::Value;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// FFmpeg used for the fixtures and by the station, overrides `ffmpeg` from the PATH
+const FFMPEG_ENV: &str = "FUNKSTROM_TEST_FFMPEG";
+
+/// Token of the admin API of the station
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// Length of the generated tracks, short so track changes happen within a test
+pub const TRACK_SECONDS: u64 = 3;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Generated track of the station
+pub struct Tone {
+    pub file: &'static str,
+    pub title: &'static str,
+    pub artist: &'static str,
+    pub frequency: u32,
+}
+
+pub const LIBRARY_TONES: [Tone; 2] = [
+    Tone {
+        file: "music/a4.mp3",
+        title: "Library A4",
+        artist: "Test Oscillator",
+        frequency: 440,
+    },
+    Tone {
+        file: "music/e5.mp3",
+        title: "Library E5",
+        artist: "Test Oscillator",
+        frequency: 659,
+    },
+];
+
+pub const PROGRAM_TONE: Tone = Tone {
+    file: "program/c5.mp3",
+    title: "Program C5",
+    artist: "Scheduled Oscillator",
+    frequency: 523,
+};
+
+/// Audio received by a fake listener
+pub struct Reception {
+    pub content_type: String,
+    pub chunks: usize,
+    pub bytes: usize,
+}
+
+/// Running station, killed when dropped
+pub struct TestStation {
+    process: Child,
+    dir: TempDir,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl TestStation {
+    /// FFmpeg to run the station with, panics if there is none, so an ignored test run
+    /// without FFmpeg fails instead of passing
+    pub fn ffmpeg() -> String {
+        let ffmpeg = std::env::var(FFMPEG_ENV).unwrap_or_else(|_| "ffmpeg".to_string());
+        let runs = Command::new(&ffmpeg)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        assert!(
+            runs,
+            "FFmpeg not found at {:?}, set {} to its path",
+            ffmpeg, FFMPEG_ENV
+        );
+        ffmpeg
+    }
+
+    /// Boots a station with the generated library and `schedule` as `[schedule]` section,
+    /// returns once its stream is online
+    pub async fn start(ffmpeg: &str, schedule: &str) -> Self {
+        let dir = tempfile::tempdir().expect("Failed to create station directory");
+        for dir_name in ["music", "program", "playlists"] {
+            std::fs::create_dir(dir.path().join(dir_name)).unwrap();
+        }
+        for tone in LIBRARY_TONES.iter().chain([&PROGRAM_TONE]) {
+            generate_tone(ffmpeg, &dir.path().join(tone.file), tone);
+        }
+        std::fs::write(
+            dir.path().join("playlists/program.m3u"),
+            format!(
+                "#EXTM3U\n#EXTINF:{},{} - {}\n{}\n",
+                TRACK_SECONDS,
+                PROGRAM_TONE.artist,
+                PROGRAM_TONE.title,
+                dir.path().join(PROGRAM_TONE.file).display()
+            ),
+        )
+        .unwrap();
+
+        let port = free_port();
+        let config = config(dir.path(), ffmpeg, port, schedule);
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, config).unwrap();
+
+        // The station keeps its state in ./data, next to the config
+        let process = Command::new(env!("CARGO_BIN_EXE_funkstrom"))
+            .arg("--config")
+            .arg(&config_path)
+            .current_dir(dir.path())
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start the station");
+
+        let station = Self {
+            process,
+            dir,
+            base_url: format!("http://127.0.0.1:{}", port),
+            client: admin_client(),
+        };
+        station
+            .wait_for("/status", STARTUP_TIMEOUT, |status| {
+                status["streams"][0]["status"] == "online"
+                    && status["streams"][0]["buffer_chunks"].as_u64() > Some(0)
+            })
+            .await;
+        station
+    }
+
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.dir.path().join(file)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Client authorized for the admin API
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub async fn get_json(&self, path: &str) -> Value {
+        self.client
+            .get(self.url(path))
+            .send()
+            .await
+            .unwrap_or_else(|e| panic!("GET {} failed: {}", path, e))
+            .json()
+            .await
+            .unwrap_or_else(|e| panic!("GET {} returned no JSON: {}", path, e))
+    }
+
+    /// Polls the JSON endpoint until `condition` holds, panics with the last response on timeout
+    pub async fn wait_for(
+        &self,
+        path: &str,
+        timeout: Duration,
+        condition: impl Fn(&Value) -> bool,
+    ) -> Value {
+        let deadline = Instant::now() + timeout;
+        let mut last = Value::Null;
+        while Instant::now() < deadline {
+            if let Ok(response) = self.client.get(self.url(path)).send().await {
+                if let Ok(json) = response.json::<Value>().await {
+                    if condition(&json) {
+                        return json;
+                    }
+                    last = json;
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        panic!("Timed out waiting for {}, last response: {}", path, last);
+    }
+
+    /// Connects a fake listener to the mount and counts the audio received for `duration`
+    pub async fn listen(&self, mount: &str, duration: Duration) -> Reception {
+        let mut response = self
+            .client
+            .get(self.url(&format!("/{}", mount)))
+            .header("User-Agent", "funkstrom-e2e")
+            .send()
+            .await
+            .unwrap_or_else(|e| panic!("Listener of /{} failed to connect: {}", mount, e));
+        assert_eq!(
+            response.status(),
+            200,
+            "Listener of /{} was rejected",
+            mount
+        );
+
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let mut reception = Reception {
+            content_type,
+            chunks: 0,
+            bytes: 0,
+        };
+        let deadline = tokio::time::Instant::now() + duration;
+        while let Ok(Ok(Some(chunk))) = tokio::time::timeout_at(deadline, response.chunk()).await {
+            reception.chunks += 1;
+            reception.bytes += chunk.len();
+        }
+        reception
+    }
+}
+
+impl Drop for TestStation {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn generate_tone(ffmpeg: &str, path: &Path, tone: &Tone) {
+    let status = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-f", "lavfi", "-i"])
+        .arg(format!(
+            "sine=frequency={}:duration={}",
+            tone.frequency, TRACK_SECONDS
+        ))
+        .args(["-metadata", &format!("title={}", tone.title)])
+        .args(["-metadata", &format!("artist={}", tone.artist)])
+        .args(["-codec:a", "libmp3lame", "-b:a", "64k", "-y"])
+        .arg(path)
+        .status()
+        .expect("Failed to run FFmpeg");
+    assert!(status.success(), "FFmpeg failed to generate {:?}", path);
+}
+
+/// Client sending the admin token with every request
+fn admin_client() -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        format!("Bearer {}", ADMIN_TOKEN).parse().unwrap(),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .expect("Failed to build the HTTP client")
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("No free port")
+}
+
+fn config(dir: &Path, ffmpeg: &str, port: u16, schedule: &str) -> String {
+    format!(
+        r#"[server]
+port = {port}
+bind_address = "127.0.0.1"
+ffmpeg_path = "{ffmpeg}"
+admin_token = "{admin_token}"
+
+[library]
+music_directory = "{music}"
+shuffle = false
+repeat = true
+
+[station]
+station_name = "Test Station"
+description = "End-to-end test station"
+genre = "Test"
+url = "http://127.0.0.1:{port}"
+
+[stream.high]
+bitrate = 128
+format = "mp3"
+sample_rate = 44100
+channels = 2
+enabled = true
+
+[schedule]
+{schedule}
+"#,
+        music = dir.join("music").display(),
+        admin_token = ADMIN_TOKEN,
+    )
+}
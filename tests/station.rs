@@ -0,0 +1,154 @@
+//! End-to-end tests running a full station: library playout, listeners, schedule switches
+//! and the admin API. Ignored by default as they need FFmpeg, run them with
+//! `cargo test --test station -- --ignored`.
+
+mod common;
+
+use common::{TestStation, Tone, LIBRARY_TONES, PROGRAM_TONE, TRACK_SECONDS};
+use std::time::Duration;
+
+/// Program starting within seconds of the station, playing the program tone for a minute
+const PROGRAM_NOW: &str = r#"
+[[schedule.programs]]
+name = "Test Program"
+active = true
+cron = "*/5 * * * * *"
+duration = "1m"
+playlist = "playlists/program.m3u"
+"#;
+
+/// Program that never starts during a test
+const PROGRAM_LATER: &str = r#"
+[[schedule.programs]]
+name = "Test Program"
+active = true
+cron = "0 0 0 1 1 * 2099"
+duration = "1h"
+playlist = "playlists/program.m3u"
+"#;
+
+fn is_playing(current: &serde_json::Value, tone: &Tone) -> bool {
+    current["title"] == tone.title && current["artist"] == tone.artist
+}
+
+#[tokio::test]
+#[ignore = "requires FFmpeg, run with --ignored"]
+async fn given_library_station_when_listeners_connect_then_both_receive_audio() {
+    let ffmpeg = TestStation::ffmpeg();
+    let station = TestStation::start(&ffmpeg, PROGRAM_LATER).await;
+
+    let listen = Duration::from_secs(3);
+    let (first, second, listeners) = tokio::join!(
+        station.listen("high", listen),
+        station.listen("high", listen),
+        async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            station.get_json("/status/streams/high/listeners").await
+        }
+    );
+
+    assert_eq!(listeners["listener_count"], 2);
+    for reception in [&first, &second] {
+        assert_eq!(reception.content_type, "audio/mpeg");
+        assert!(reception.chunks > 0, "Listener received no audio chunks");
+        // At least a second of 128 kbps audio
+        assert!(reception.bytes >= 16_000, "Only {} bytes", reception.bytes);
+    }
+
+    let status = station.get_json("/status").await;
+    assert_eq!(status["standby"], false);
+    assert!(status["streams"][0]["buffer_chunks"].as_u64() > Some(0));
+
+    let current = station.get_json("/current").await;
+    assert!(
+        LIBRARY_TONES.iter().any(|tone| is_playing(&current, tone)),
+        "Not a library track: {}",
+        current
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires FFmpeg, run with --ignored"]
+async fn given_library_station_when_track_ends_then_next_library_track_plays() {
+    let ffmpeg = TestStation::ffmpeg();
+    let station = TestStation::start(&ffmpeg, PROGRAM_LATER).await;
+
+    let first = station.get_json("/current").await;
+    let timeout = Duration::from_secs(TRACK_SECONDS * 4);
+    let next = station
+        .wait_for("/current", timeout, |current| {
+            current["title"] != first["title"]
+                && LIBRARY_TONES.iter().any(|tone| is_playing(current, tone))
+        })
+        .await;
+
+    assert!(next["file_path"].as_str().unwrap().ends_with(".mp3"));
+}
+
+#[tokio::test]
+#[ignore = "requires FFmpeg, run with --ignored"]
+async fn given_scheduled_program_when_it_starts_then_program_tracks_replace_the_library() {
+    let ffmpeg = TestStation::ffmpeg();
+    let station = TestStation::start(&ffmpeg, PROGRAM_NOW).await;
+
+    // The program starts within 5 seconds and takes over at the next track change
+    let timeout = Duration::from_secs(5 + TRACK_SECONDS * 4);
+    let current = station
+        .wait_for("/current", timeout, |current| {
+            is_playing(current, &PROGRAM_TONE)
+        })
+        .await;
+
+    assert_eq!(
+        current["file_path"],
+        station.path(PROGRAM_TONE.file).display().to_string()
+    );
+    let reception = station.listen("high", Duration::from_secs(1)).await;
+    assert!(reception.chunks > 0, "Stream stopped during the program");
+}
+
+#[tokio::test]
+#[ignore = "requires FFmpeg, run with --ignored"]
+async fn given_running_station_when_using_admin_api_then_schedule_and_selftest_are_reported() {
+    let ffmpeg = TestStation::ffmpeg();
+    let station = TestStation::start(&ffmpeg, PROGRAM_LATER).await;
+
+    let export = station.get_json("/api/schedule/export").await;
+    assert_eq!(export["programs"][0]["name"], "Test Program");
+
+    let mut import = export.clone();
+    import["programs"][0]["name"] = "Renamed Program".into();
+    let unauthorized = reqwest::Client::new()
+        .post(station.url("/api/schedule/import"))
+        .json(&import)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unauthorized.status(), 401);
+    let response = station
+        .client()
+        .post(station.url("/api/schedule/import?dry_run=true"))
+        .json(&import)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let result: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(result["dry_run"], true);
+    assert_eq!(result["applied"], false);
+
+    let report = station
+        .wait_for("/api/admin/selftest", Duration::from_secs(10), |report| {
+            report.get("checks").is_some()
+        })
+        .await;
+    assert!(!report["checks"].as_array().unwrap().is_empty());
+
+    let rejected = station
+        .client()
+        .get(station.url("/unknown"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejected.status(), 404);
+}